@@ -1,83 +1,385 @@
-use ndarray::{Array2, Axis, s};
-use ndarray::Zip;
+use ndarray::{Array1, Array2, Axis, ErrorKind, ShapeError, s};
+
+use crate::backend::tensor_backend::{CpuBackend, TensorBackend};
+use crate::numerics::Float;
+
+/// Functional: `masked_attention_weights`
+/// Computes the attention weights with masked keys excluded from the softmax.
+///
+/// Parameters:
+///   - `query`: The Q matrix (`Array2<A>`) representing the query vectors.
+///   - `key`: The K matrix (`Array2<A>`) representing the key vectors.
+///   - `key_mask`: 1 for real tokens and 0 for PAD positions (shape: [num_keys]). When every
+///     key is masked, the mask is ignored so the weights stay finite.
+///
+/// Return:
+///   A matrix (`Array2<A>`) of shape [num_queries, num_keys] whose rows sum to 1 and
+///   that is 0 in the columns of masked keys.
+pub fn masked_attention_weights<A: Float>(query: &Array2<A>, key: &Array2<A>, key_mask: Option<&Array1<A>>) -> Array2<A> {
+	attention_weights_with(query, key, key_mask, false)
+}
+
+/// Functional: `attention_weights_with`
+/// Computes the attention weights with masked keys and, optionally, future keys excluded
+/// from the softmax.
+///
+/// Parameters:
+///   - `query`, `key`, `key_mask`: As in `masked_attention_weights`.
+///   - `causal`: When set, query `i` only attends to keys `0..=i` (look-ahead mask), as in a
+///     decoder or an autoregressive objective. A query whose allowed keys are all PAD
+///     (e.g. left padding) ignores the key mask, so its weights stay finite.
+///
+/// Return:
+///   A matrix (`Array2<A>`) of shape [num_queries, num_keys] whose rows sum to 1 and
+///   that is 0 for every excluded key.
+pub fn attention_weights_with<A: Float>(query: &Array2<A>, key: &Array2<A>, key_mask: Option<&Array1<A>>, causal: bool) -> Array2<A> {
+	assert_eq!(query.shape()[1], key.shape()[1], "Query and Key dimensions must match.");
+	if let Some(mask) = key_mask {
+			assert_eq!(mask.len(), key.nrows(), "Key mask length must match the number of keys.");
+	}
+	attention_weights_on(&CpuBackend, query, key, key_mask, causal).expect("shapes are checked above")
+}
+
+/// Functional: `attention_weights_on`
+/// `attention_weights_with` computed with the operations of `backend`.
+///
+/// Parameters:
+///   - `backend`: The tensor backend that runs the matrix products and the softmax.
+///   - `query`, `key`, `key_mask`, `causal`: As in `attention_weights_with`.
+///
+/// Return:
+///   The attention weights, or an `IncompatibleShape` error when the query and key widths
+///   or the key mask length do not match.
+pub fn attention_weights_on<A: Float>(
+	backend: &dyn TensorBackend<A>,
+	query: &Array2<A>,
+	key: &Array2<A>,
+	key_mask: Option<&Array1<A>>,
+	causal: bool,
+) -> Result<Array2<A>, ShapeError> {
+	if key_mask.is_some_and(|mask| mask.len() != key.nrows()) {
+			return Err(ShapeError::from_kind(ErrorKind::IncompatibleShape));
+	}
+	let key_mask = key_mask.filter(|mask| mask.iter().any(|m| !m.is_zero()));
+
+	let d_k = A::cast(key.shape()[1] as f64);
+
+	let mut qk_transpose = backend.scale(&backend.matmul_transposed(query, key)?, A::one() / d_k.sqrt());
+	for (i, mut row) in qk_transpose.outer_iter_mut().enumerate() {
+			let visible = if causal { (i + 1).min(row.len()) } else { row.len() };
+			row.slice_mut(s![visible..]).fill(A::neg_infinity());
+			if let Some(mask) = key_mask {
+					if mask.iter().take(visible).any(|m| !m.is_zero()) {
+							row.zip_mut_with(mask, |score, &m| if m.is_zero() { *score = A::neg_infinity() });
+					}
+			}
+	}
+
+	Ok(backend.softmax_rows(&qk_transpose))
+}
 
 /// Functional: `scaled_dot_product_attention`
 /// Computes the scaled dot-product attention for a set of queries, keys, and values.
 ///
 /// Parameters:
-///   - `query`: The Q matrix (`Array2<f64>`) representing the query vectors.
-///   - `key`: The K matrix (`Array2<f64>`) representing the key vectors.
-///   - `value`: The V matrix (`Array2<f64>`) representing the value vectors.
+///   - `query`: The Q matrix (`Array2<A>`) representing the query vectors.
+///   - `key`: The K matrix (`Array2<A>`) representing the key vectors.
+///   - `value`: The V matrix (`Array2<A>`) representing the value vectors.
 ///
 /// Return:
-///   A matrix (`Array2<f64>`) representing the attention-weighted output.
-pub fn scaled_dot_product_attention(
-	query: &Array2<f64>,
-	key: &Array2<f64>,
-	value: &Array2<f64>,
-) -> Array2<f64> {
+///   A matrix (`Array2<A>`) representing the attention-weighted output.
+pub fn scaled_dot_product_attention<A: Float>(
+	query: &Array2<A>,
+	key: &Array2<A>,
+	value: &Array2<A>,
+) -> Array2<A> {
+	masked_scaled_dot_product_attention(query, key, value, None)
+}
+
+/// Functional: `masked_scaled_dot_product_attention`
+/// Same as `scaled_dot_product_attention`, with no query attending to masked keys (e.g. PAD).
+///
+/// Parameters:
+///   - `query`, `key`, `value`: As in `scaled_dot_product_attention`.
+///   - `key_mask`: 1 for real tokens and 0 for PAD positions (shape: [num_keys]).
+///
+/// Return:
+///   A matrix (`Array2<A>`) representing the attention-weighted output.
+pub fn masked_scaled_dot_product_attention<A: Float>(
+	query: &Array2<A>,
+	key: &Array2<A>,
+	value: &Array2<A>,
+	key_mask: Option<&Array1<A>>,
+) -> Array2<A> {
 	assert_eq!(query.shape()[1], key.shape()[1], "Query and Key dimensions must match.");
 	assert_eq!(key.shape()[0], value.shape()[0], "Key and Value must have the same number of tokens.");
+	if let Some(mask) = key_mask {
+			assert_eq!(mask.len(), key.nrows(), "Key mask length must match the number of keys.");
+	}
+
+	scaled_dot_product_attention_on(&CpuBackend, query, key, value, key_mask).expect("shapes are checked above")
+}
+
+/// Functional: `scaled_dot_product_attention_on`
+/// `masked_scaled_dot_product_attention` computed with the operations of `backend`.
+///
+/// Parameters:
+///   - `backend`: The tensor backend that runs the attention.
+///   - `query`, `key`, `value`, `key_mask`: As in `masked_scaled_dot_product_attention`.
+///
+/// Return:
+///   The attention-weighted output, or an `IncompatibleShape` error when the inputs do not fit.
+pub fn scaled_dot_product_attention_on<A: Float>(
+	backend: &dyn TensorBackend<A>,
+	query: &Array2<A>,
+	key: &Array2<A>,
+	value: &Array2<A>,
+	key_mask: Option<&Array1<A>>,
+) -> Result<Array2<A>, ShapeError> {
+	backend.matmul(&attention_weights_on(backend, query, key, key_mask, false)?, value)
+}
+
+/// Functional: `scaled_dot_product_attention_backward`
+/// Computes the gradients of the scaled dot-product attention with respect to its inputs.
+///
+/// Parameters:
+///   - `query`, `key`, `value`: The inputs used in the forward pass.
+///   - `grad_output`: Gradient of the loss with respect to the attention output.
+///
+/// Return:
+///   A tuple `(grad_query, grad_key, grad_value)` with the same shapes as the inputs.
+pub fn scaled_dot_product_attention_backward<A: Float>(
+	query: &Array2<A>,
+	key: &Array2<A>,
+	value: &Array2<A>,
+	grad_output: &Array2<A>,
+) -> (Array2<A>, Array2<A>, Array2<A>) {
+	masked_scaled_dot_product_attention_backward(query, key, value, None, grad_output)
+}
+
+/// Functional: `masked_scaled_dot_product_attention_backward`
+/// Gradients of `masked_scaled_dot_product_attention`. Masked keys and values receive
+/// no gradient through the attention weights.
+///
+/// Parameters:
+///   - `query`, `key`, `value`, `key_mask`: The inputs used in the forward pass.
+///   - `grad_output`: Gradient of the loss with respect to the attention output.
+///
+/// Return:
+///   A tuple `(grad_query, grad_key, grad_value)` with the same shapes as the inputs.
+pub fn masked_scaled_dot_product_attention_backward<A: Float>(
+	query: &Array2<A>,
+	key: &Array2<A>,
+	value: &Array2<A>,
+	key_mask: Option<&Array1<A>>,
+	grad_output: &Array2<A>,
+) -> (Array2<A>, Array2<A>, Array2<A>) {
+	attention_backward(&masked_attention_weights(query, key, key_mask), query, key, value, grad_output)
+}
 
-	let d_k = key.shape()[1] as f64;
+/// Gradients of `weights.dot(value)` with respect to the query, key and value, where
+/// `weights` are the attention weights of the forward pass.
+fn attention_backward<A: Float>(
+	weights: &Array2<A>,
+	query: &Array2<A>,
+	key: &Array2<A>,
+	value: &Array2<A>,
+	grad_output: &Array2<A>,
+) -> (Array2<A>, Array2<A>, Array2<A>) {
+	let d_k = A::cast(key.shape()[1] as f64);
 
-	let mut qk_transpose = query.dot(&key.t());
-	qk_transpose.mapv_inplace(|x| x / d_k.sqrt());
+	let grad_value = weights.t().dot(grad_output);
+	let grad_weights = grad_output.dot(&value.t());
 
-	// Apply softmax
-	for mut row in qk_transpose.outer_iter_mut() {
-			let max = row.iter().cloned().fold(f64::MIN, f64::max);
-			let exp_sum: f64 = row.iter().map(|&x| (x - max).exp()).sum();
-			row.mapv_inplace(|x| (x - max).exp() / exp_sum);
+	// Softmax backward: dS = P ⊙ (dP - rowsum(dP ⊙ P))
+	let mut grad_scores = weights * &grad_weights;
+	let row_sums = grad_scores.sum_axis(Axis(1));
+	for ((mut row, weight_row), &row_sum) in grad_scores.outer_iter_mut().zip(weights.outer_iter()).zip(row_sums.iter()) {
+			row.zip_mut_with(&weight_row, |g, &p| *g -= p * row_sum);
 	}
+	grad_scores.mapv_inplace(|x| x / d_k.sqrt());
+
+	let grad_query = grad_scores.dot(key);
+	let grad_key = grad_scores.t().dot(query);
 
-	qk_transpose.dot(value)
+	(grad_query, grad_key, grad_value)
 }
 
 /// Functional: `multi_head_attention`
 /// Implements multi-head attention by splitting inputs into multiple heads, computing scaled dot-product attention for each, and concatenating the results.
 ///
 /// Parameters:
-///   - `query`: The Q matrix (`Array2<f64>`) representing the query vectors.
-///   - `key`: The K matrix (`Array2<f64>`) representing the key vectors.
-///   - `value`: The V matrix (`Array2<f64>`) representing the value vectors.
+///   - `query`: The Q matrix (`Array2<A>`) representing the query vectors.
+///   - `key`: The K matrix (`Array2<A>`) representing the key vectors.
+///   - `value`: The V matrix (`Array2<A>`) representing the value vectors.
 ///   - `num_heads`: The number of attention heads (`usize`) for the computation.
+///   - `key_mask`: 1 for real tokens and 0 for PAD positions (shape: [num_keys]); every head
+///     ignores the masked keys, as in `masked_scaled_dot_product_attention`.
+///   - `causal`: When set, every head attends only to the current and earlier positions
+///     (see `attention_weights_with`).
 ///
 /// Return:
-///   A matrix (`Array2<f64>`) representing the concatenated and projected multi-head attention output.
-pub fn multi_head_attention(
-    query: &Array2<f64>,
-    key: &Array2<f64>,
-    value: &Array2<f64>,
+///   A matrix (`Array2<A>`) representing the concatenated and projected multi-head attention output.
+pub fn multi_head_attention<A: Float>(
+    query: &Array2<A>,
+    key: &Array2<A>,
+    value: &Array2<A>,
     num_heads: usize,
-) -> Array2<f64> {
+    key_mask: Option<&Array1<A>>,
+    causal: bool,
+) -> Array2<A> {
     assert_eq!(query.ncols() % num_heads, 0, "d_model must be divisible by num_heads");
-    let head_dim = query.ncols() / num_heads;
 
-    let split_heads = |matrix: &Array2<f64>| -> Vec<Array2<f64>> {
-        (0..num_heads)
-            .map(|h| matrix.slice(s![.., h * head_dim..(h + 1) * head_dim]).to_owned())
-            .collect()
-    };
-
-    let query_heads = split_heads(query);
-    let key_heads = split_heads(key);
-    let value_heads = split_heads(value);
-
-    let head_outputs: Vec<Array2<f64>> = query_heads
+    let head_outputs: Vec<Array2<A>> = split_heads(query, num_heads)
         .iter()
-        .zip(&key_heads)
-        .zip(&value_heads)
-        .map(|((q, k), v)| scaled_dot_product_attention(q, k, v))
+        .zip(&split_heads(key, num_heads))
+        .zip(&split_heads(value, num_heads))
+        .map(|((q, k), v)| attention_weights_with(q, k, key_mask, causal).dot(v))
         .collect();
 
-    // Concatenate head outputs
-    let mut concatenated = Array2::zeros((query.nrows(), query.ncols()));
-    for (h, head_output) in head_outputs.iter().enumerate() {
+    concat_heads(&head_outputs, query.nrows())
+}
+
+/// Functional: `multi_head_attention_backward`
+/// Gradients of `multi_head_attention`, computed head by head.
+///
+/// Parameters:
+///   - `query`, `key`, `value`, `num_heads`, `key_mask`, `causal`: The inputs used in the forward pass.
+///   - `grad_output`: Gradient of the loss with respect to the concatenated head outputs.
+///
+/// Return:
+///   A tuple `(grad_query, grad_key, grad_value)` with the same shapes as the inputs.
+pub fn multi_head_attention_backward<A: Float>(
+    query: &Array2<A>,
+    key: &Array2<A>,
+    value: &Array2<A>,
+    num_heads: usize,
+    key_mask: Option<&Array1<A>>,
+    causal: bool,
+    grad_output: &Array2<A>,
+) -> (Array2<A>, Array2<A>, Array2<A>) {
+    assert_eq!(query.ncols() % num_heads, 0, "d_model must be divisible by num_heads");
+
+    let key_heads = split_heads(key, num_heads);
+    let value_heads = split_heads(value, num_heads);
+    let grad_output_heads = split_heads(grad_output, num_heads);
+    let (mut grad_query, mut grad_key, mut grad_value) = (Vec::new(), Vec::new(), Vec::new());
+    for (h, q) in split_heads(query, num_heads).iter().enumerate() {
+        let weights = attention_weights_with(q, &key_heads[h], key_mask, causal);
+        let (gq, gk, gv) = attention_backward(&weights, q, &key_heads[h], &value_heads[h], &grad_output_heads[h]);
+        grad_query.push(gq);
+        grad_key.push(gk);
+        grad_value.push(gv);
+    }
+
+    (concat_heads(&grad_query, query.nrows()), concat_heads(&grad_key, key.nrows()), concat_heads(&grad_value, value.nrows()))
+}
+
+/// Splits the columns of `matrix` into `num_heads` equal blocks.
+pub(crate) fn split_heads<A: Float>(matrix: &Array2<A>, num_heads: usize) -> Vec<Array2<A>> {
+    let head_dim = matrix.ncols() / num_heads;
+    (0..num_heads)
+        .map(|h| matrix.slice(s![.., h * head_dim..(h + 1) * head_dim]).to_owned())
+        .collect()
+}
+
+/// Concatenates per-head blocks of `rows` rows along the columns.
+pub(crate) fn concat_heads<A: Float>(heads: &[Array2<A>], rows: usize) -> Array2<A> {
+    let head_dim = heads.first().map_or(0, |head| head.ncols());
+    let mut concatenated = Array2::zeros((rows, head_dim * heads.len()));
+    for (h, head) in heads.iter().enumerate() {
         concatenated
             .slice_mut(s![.., h * head_dim..(h + 1) * head_dim])
-            .assign(&head_output);
+            .assign(head);
     }
-
     concatenated
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_multi_head_attention_ignores_padding() {
+        let x: Array2<f64> = array![[0.2, -0.1, 0.4, 0.3], [0.5, 0.2, -0.3, 0.1]];
+        let padded = array![[0.2, -0.1, 0.4, 0.3], [0.5, 0.2, -0.3, 0.1], [9.0, -9.0, 9.0, -9.0]];
+        let mask = array![1.0, 1.0, 0.0];
+
+        // The PAD row changes nothing for the real tokens once it is masked.
+        let expected = multi_head_attention(&x, &x, &x, 2, None, false);
+        let output = multi_head_attention(&padded, &padded, &padded, 2, Some(&mask), false);
+        assert!((output.slice(s![0..2, ..]).to_owned() - expected).iter().all(|d| d.abs() < 1e-12));
+        let unmasked = multi_head_attention(&padded, &padded, &padded, 2, None, false);
+        assert!((unmasked.slice(s![0..2, ..]).to_owned() - output.slice(s![0..2, ..])).iter().any(|d| d.abs() > 1e-3));
+    }
+
+    #[test]
+    fn test_causal_attention() {
+        let x: Array2<f64> = array![[0.2, -0.1, 0.4], [0.5, 0.2, -0.3], [-0.4, 0.3, 0.1]];
+
+        // Every position sees exactly its prefix, so appending tokens never changes it.
+        let output = multi_head_attention(&x, &x, &x, 1, None, true);
+        for i in 0..3 {
+            let prefix = x.slice(s![0..=i, ..]).to_owned();
+            let expected = scaled_dot_product_attention(&prefix, &prefix, &prefix);
+            assert!((&output.row(i) - &expected.row(i)).iter().all(|d| d.abs() < 1e-12));
+        }
+        let weights = attention_weights_with(&x, &x, None, true);
+        assert_eq!((weights[[0, 0]], weights[[0, 1]], weights[[1, 2]]), (1.0, 0.0, 0.0));
+
+        // A left-padded first position still gets finite weights.
+        let left_padded = attention_weights_with(&x, &x, Some(&array![0.0, 1.0, 1.0]), true);
+        assert!(left_padded.iter().all(|w| w.is_finite()));
+        assert_eq!(left_padded[[2, 0]], 0.0);
+    }
+
+    #[test]
+    fn test_single_precision_attention() {
+        let x = array![[0.2, -0.1, 0.4, 0.3], [0.5, 0.2, -0.3, 0.1], [-0.4, 0.3, 0.1, 0.2]];
+        let mask = array![1.0, 1.0, 0.0];
+        let single = x.mapv(|v| v as f32);
+
+        let expected = multi_head_attention(&x, &x, &x, 2, Some(&mask), true);
+        let output = multi_head_attention(&single, &single, &single, 2, Some(&mask.mapv(|v| v as f32)), true);
+        assert!((output.mapv(f64::from) - expected).iter().all(|d| d.abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_multi_head_attention_gradients() {
+        let x: Array2<f64> = array![[0.2, -0.3, 0.1, 0.4], [0.5, 0.1, -0.2, 0.3], [-0.4, 0.3, 0.2, -0.1]];
+        let mask = array![1.0, 1.0, 0.0];
+        let upstream = array![[1.0, -0.5, 0.2, 0.1], [0.3, 0.8, -0.6, 0.4], [-0.2, 0.4, 0.5, -0.3]];
+        let loss = |x: &Array2<f64>| (multi_head_attention(x, x, x, 2, Some(&mask), false) * &upstream).sum();
+
+        let (grad_query, grad_key, grad_value) = multi_head_attention_backward(&x, &x, &x, 2, Some(&mask), false, &upstream);
+        let grad_x = grad_query + grad_key + grad_value;
+        let h = 1e-6;
+        for ((i, j), &analytic) in grad_x.indexed_iter() {
+            let (mut plus, mut minus) = (x.clone(), x.clone());
+            plus[[i, j]] += h;
+            minus[[i, j]] -= h;
+            let numeric = (loss(&plus) - loss(&minus)) / (2.0 * h);
+            assert!((numeric - analytic).abs() < 1e-6, "gradient {:?}: {} vs {}", (i, j), numeric, analytic);
+        }
+    }
+
+    #[test]
+    fn test_causal_attention_gradients() {
+        let x = array![[0.2, -0.3], [0.5, 0.1], [-0.4, 0.3]];
+        let mask = array![1.0, 1.0, 0.0];
+        let upstream = array![[1.0, -0.5], [0.3, 0.8], [-0.2, 0.4]];
+        let loss = |x: &Array2<f64>| (multi_head_attention(x, x, x, 1, Some(&mask), true) * &upstream).sum();
+
+        let (grad_query, grad_key, grad_value) = multi_head_attention_backward(&x, &x, &x, 1, Some(&mask), true, &upstream);
+        let grad_x = grad_query + grad_key + grad_value;
+        let h = 1e-6;
+        for ((i, j), &analytic) in grad_x.indexed_iter() {
+            let (mut plus, mut minus) = (x.clone(), x.clone());
+            plus[[i, j]] += h;
+            minus[[i, j]] -= h;
+            let numeric = (loss(&plus) - loss(&minus)) / (2.0 * h);
+            assert!((numeric - analytic).abs() < 1e-6, "gradient {:?}: {} vs {}", (i, j), numeric, analytic);
+        }
+    }
+}
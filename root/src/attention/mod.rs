@@ -1,2 +1,6 @@
 pub mod attention_mechanism;
-pub use attention_mechanism::{scaled_dot_product_attention, multi_head_attention};
+pub mod projections;
+pub mod relative_position;
+pub use attention_mechanism::{scaled_dot_product_attention, scaled_dot_product_attention_backward, masked_scaled_dot_product_attention, masked_scaled_dot_product_attention_backward, multi_head_attention, multi_head_attention_backward};
+pub use projections::AttentionProjections;
+pub use relative_position::RelativePositions;
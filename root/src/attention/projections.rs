@@ -0,0 +1,109 @@
+use ndarray::Array2;
+use ndarray_rand::RandomExt;
+use ndarray_rand::rand_distr::Uniform;
+use serde::{Serialize, Deserialize};
+
+use crate::numerics::Float;
+
+/// Learned query, key, value and output projections of multi-head self-attention.
+///
+/// The projections are fused: each matrix is [d_model, d_model], and head `h` uses the
+/// columns `h * head_dim..(h + 1) * head_dim` of the projected query, key and value, as
+/// split by `multi_head_attention`. The concatenated head outputs are mixed by `W_O`:
+/// `MultiHead(X) = Concat(head_1, ..., head_h) W_O` with `head_i = Attention(X W_Q, X W_K, X W_V)_i`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AttentionProjections<A = f64> {
+    pub num_heads: usize,
+    /// `W_Q`. Shape: [d_model, d_model].
+    pub query: Array2<A>,
+    /// `W_K`. Shape: [d_model, d_model].
+    pub key: Array2<A>,
+    /// `W_V`. Shape: [d_model, d_model].
+    pub value: Array2<A>,
+    /// `W_O`. Shape: [d_model, d_model].
+    pub output: Array2<A>,
+}
+
+impl<A: Float> AttentionProjections<A> {
+    /// Randomly initialized projections for `num_heads` heads over `d_model`-sized vectors.
+    pub fn new(d_model: usize, num_heads: usize) -> Self {
+        assert_eq!(d_model % num_heads, 0, "d_model must be divisible by num_heads");
+        let random = || Array2::random((d_model, d_model), Uniform::new(A::cast(-0.1), A::cast(0.1)));
+        AttentionProjections { num_heads, query: random(), key: random(), value: random(), output: random() }
+    }
+
+    /// Projected `(query, key, value)` of the layer input `x` (shape: [seq_len, d_model]).
+    pub fn project(&self, x: &Array2<A>) -> (Array2<A>, Array2<A>, Array2<A>) {
+        (x.dot(&self.query), x.dot(&self.key), x.dot(&self.value))
+    }
+
+    /// Mixes the concatenated head outputs `heads` with `W_O`.
+    pub fn output(&self, heads: &Array2<A>) -> Array2<A> {
+        heads.dot(&self.output)
+    }
+
+    /// Gradients of `output`.
+    ///
+    /// # Returns
+    /// * `(grad_heads, grad_output_weights)`, the latter flattened row-major.
+    pub fn output_backward(&self, heads: &Array2<A>, grad_output: &Array2<A>) -> (Array2<A>, Vec<A>) {
+        let grad_weights = heads.t().dot(grad_output);
+        (grad_output.dot(&self.output.t()), grad_weights.into_iter().collect())
+    }
+
+    /// Gradients of `project`, given the gradients of the projected query, key and value.
+    ///
+    /// # Returns
+    /// * `(grad_x, grad_weights)`, where `grad_weights` holds `W_Q`, `W_K` and `W_V` in the
+    ///   order of `parameters_mut`.
+    pub fn project_backward(
+        &self,
+        x: &Array2<A>,
+        grad_query: &Array2<A>,
+        grad_key: &Array2<A>,
+        grad_value: &Array2<A>,
+    ) -> (Array2<A>, Vec<A>) {
+        let grad_x = grad_query.dot(&self.query.t()) + grad_key.dot(&self.key.t()) + grad_value.dot(&self.value.t());
+        let grad_weights = [grad_query, grad_key, grad_value].into_iter().flat_map(|grad| x.t().dot(grad)).collect();
+        (grad_x, grad_weights)
+    }
+
+    pub fn num_parameters(&self) -> usize {
+        self.query.len() + self.key.len() + self.value.len() + self.output.len()
+    }
+
+    /// `W_Q`, `W_K`, `W_V` then `W_O`, row-major.
+    pub fn parameters_mut(&mut self) -> Vec<&mut A> {
+        self.query
+            .iter_mut()
+            .chain(self.key.iter_mut())
+            .chain(self.value.iter_mut())
+            .chain(self.output.iter_mut())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attention::multi_head_attention;
+    use ndarray::array;
+
+    #[test]
+    fn test_identity_projections() {
+        // Identity projections reduce to attention over the raw input.
+        let identity = Array2::eye(4);
+        let projections = AttentionProjections {
+            num_heads: 2,
+            query: identity.clone(),
+            key: identity.clone(),
+            value: identity.clone(),
+            output: identity,
+        };
+        let x = array![[0.2, -0.1, 0.4, 0.3], [0.5, 0.2, -0.3, 0.1]];
+        let (query, key, value) = projections.project(&x);
+        let heads = multi_head_attention(&query, &key, &value, 2, None, false);
+        assert_eq!(projections.output(&heads), multi_head_attention(&x, &x, &x, 2, None, false));
+        assert_eq!(AttentionProjections::<f64>::new(4, 2).num_parameters(), 64);
+    }
+}
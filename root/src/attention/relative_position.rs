@@ -0,0 +1,284 @@
+use ndarray::{Array1, Array2, Axis};
+use ndarray_rand::RandomExt;
+use ndarray_rand::rand_distr::Uniform;
+use serde::{Serialize, Deserialize};
+
+use crate::attention::attention_mechanism::{concat_heads, split_heads};
+use crate::numerics::{softmax_inplace, Float};
+
+/// Learned relative-position representations (Shaw et al., 2018).
+///
+/// Every query-key pair gets the embedding of its clipped distance `j - i`, in
+/// `[-max_distance, max_distance]`: `a^K` is added to the key in the attention score and
+/// `a^V` to the value in the output, so attention sees how far apart two tokens are
+/// instead of where they are. Distances beyond `max_distance` share the outermost
+/// embedding, which lets the model generalize to sequences longer than seen in training.
+///
+/// In multi-head attention the tables are [2 * max_distance + 1, head_dim] and shared by
+/// every head, as in the paper (see `multi_head_attention`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RelativePositions<A = f64> {
+    pub max_distance: usize,
+    /// `a^K`, one row per clipped distance. Shape: [2 * max_distance + 1, dim].
+    pub key_embeddings: Array2<A>,
+    /// `a^V`, one row per clipped distance. Shape: [2 * max_distance + 1, dim].
+    pub value_embeddings: Array2<A>,
+}
+
+impl<A: Float> RelativePositions<A> {
+    /// Randomly initialized embeddings for distances up to `max_distance` of `dim`-sized vectors.
+    pub fn new(max_distance: usize, dim: usize) -> Self {
+        let rows = 2 * max_distance + 1;
+        RelativePositions {
+            max_distance,
+            key_embeddings: Array2::random((rows, dim), Uniform::new(A::cast(-0.1), A::cast(0.1))),
+            value_embeddings: Array2::random((rows, dim), Uniform::new(A::cast(-0.1), A::cast(0.1))),
+        }
+    }
+
+    /// Row of the embedding tables for query position `i` and key position `j`.
+    pub fn index(&self, i: usize, j: usize) -> usize {
+        let max_distance = self.max_distance as isize;
+        ((j as isize - i as isize).clamp(-max_distance, max_distance) + max_distance) as usize
+    }
+
+    pub fn num_parameters(&self) -> usize {
+        self.key_embeddings.len() + self.value_embeddings.len()
+    }
+
+    /// `a^K` then `a^V`, row-major.
+    pub fn parameters_mut(&mut self) -> Vec<&mut A> {
+        self.key_embeddings.iter_mut().chain(self.value_embeddings.iter_mut()).collect()
+    }
+
+    /// Attention weights `softmax((q_i · (k_j + a^K_ij)) / √d_k)`, with masked keys excluded
+    /// as in `masked_attention_weights`.
+    pub fn attention_weights(&self, query: &Array2<A>, key: &Array2<A>, key_mask: Option<&Array1<A>>) -> Array2<A> {
+        assert_eq!(query.ncols(), key.ncols(), "Query and Key dimensions must match.");
+        assert_eq!(query.ncols(), self.key_embeddings.ncols(), "Relative position embeddings must match the key dimension.");
+        let key_mask = key_mask.filter(|mask| mask.iter().any(|&m| m != A::zero()));
+        let scale = A::cast(key.ncols() as f64).sqrt();
+
+        // q_i · a^K_r for every query and distance, so each score costs one lookup.
+        let query_positions = query.dot(&self.key_embeddings.t());
+        let mut scores = query.dot(&key.t());
+        for ((i, j), score) in scores.indexed_iter_mut() {
+            *score = (*score + query_positions[[i, self.index(i, j)]]) / scale;
+            if key_mask.is_some_and(|mask| mask[j] == A::zero()) {
+                *score = A::neg_infinity();
+            }
+        }
+        for row in scores.outer_iter_mut() {
+            softmax_inplace(row);
+        }
+        scores
+    }
+
+    /// Self-attention with relative positions: `z_i = Σ_j α_ij (v_j + a^V_ij)`.
+    ///
+    /// # Arguments
+    /// * `query`, `key`, `value` - As in `masked_scaled_dot_product_attention`.
+    /// * `key_mask` - 1 for real tokens and 0 for PAD positions (shape: [num_keys]).
+    pub fn attention(&self, query: &Array2<A>, key: &Array2<A>, value: &Array2<A>, key_mask: Option<&Array1<A>>) -> Array2<A> {
+        let weights = self.attention_weights(query, key, key_mask);
+        let mut output = weights.dot(value);
+        // Σ_j α_ij a^V_ij, accumulated per distance.
+        let distance_weights = self.distance_sums(&weights);
+        output += &distance_weights.dot(&self.value_embeddings);
+        output
+    }
+
+    /// Gradients of `attention`.
+    ///
+    /// # Returns
+    /// * `(grad_query, grad_key, grad_value, grad_embeddings)`, where `grad_embeddings` is in
+    ///   the order of `parameters_mut`.
+    pub fn attention_backward(
+        &self,
+        query: &Array2<A>,
+        key: &Array2<A>,
+        value: &Array2<A>,
+        key_mask: Option<&Array1<A>>,
+        grad_output: &Array2<A>,
+    ) -> (Array2<A>, Array2<A>, Array2<A>, Vec<A>) {
+        let scale = A::cast(key.ncols() as f64).sqrt();
+        let weights = self.attention_weights(query, key, key_mask);
+
+        let grad_value = weights.t().dot(grad_output);
+        let grad_value_embeddings = self.distance_sums(&weights).t().dot(grad_output);
+
+        // dα_ij = g_i · (v_j + a^V_ij)
+        let grad_output_positions = grad_output.dot(&self.value_embeddings.t());
+        let mut grad_weights = grad_output.dot(&value.t());
+        for ((i, j), grad) in grad_weights.indexed_iter_mut() {
+            *grad += grad_output_positions[[i, self.index(i, j)]];
+        }
+
+        // Softmax backward: dS = P ⊙ (dP - rowsum(dP ⊙ P))
+        let mut grad_scores = &weights * &grad_weights;
+        let row_sums = grad_scores.sum_axis(Axis(1));
+        for ((mut row, weight_row), &row_sum) in grad_scores.outer_iter_mut().zip(weights.outer_iter()).zip(row_sums.iter()) {
+            row.zip_mut_with(&weight_row, |g, &p| *g -= p * row_sum);
+        }
+        grad_scores.mapv_inplace(|x| x / scale);
+
+        let distance_scores = self.distance_sums(&grad_scores);
+        let grad_query = grad_scores.dot(key) + distance_scores.dot(&self.key_embeddings);
+        let grad_key = grad_scores.t().dot(query);
+        let grad_key_embeddings = distance_scores.t().dot(query);
+
+        let grad_embeddings = grad_key_embeddings.iter().chain(grad_value_embeddings.iter()).copied().collect();
+        (grad_query, grad_key, grad_value, grad_embeddings)
+    }
+
+    /// Multi-head self-attention with relative positions: the columns are split into
+    /// `num_heads` heads as in `attention::multi_head_attention`, and every head runs
+    /// `attention` with the same embedding tables.
+    ///
+    /// # Arguments
+    /// * `num_heads` - Number of heads; `query.ncols() / num_heads` must equal the embedding width.
+    pub fn multi_head_attention(
+        &self,
+        query: &Array2<A>,
+        key: &Array2<A>,
+        value: &Array2<A>,
+        num_heads: usize,
+        key_mask: Option<&Array1<A>>,
+    ) -> Array2<A> {
+        assert_eq!(query.ncols() % num_heads, 0, "d_model must be divisible by num_heads");
+        let head_outputs: Vec<Array2<A>> = split_heads(query, num_heads)
+            .iter()
+            .zip(&split_heads(key, num_heads))
+            .zip(&split_heads(value, num_heads))
+            .map(|((q, k), v)| self.attention(q, k, v, key_mask))
+            .collect();
+        concat_heads(&head_outputs, query.nrows())
+    }
+
+    /// Gradients of `multi_head_attention`. The embedding gradients of the heads are summed.
+    ///
+    /// # Returns
+    /// * `(grad_query, grad_key, grad_value, grad_embeddings)`, as in `attention_backward`.
+    pub fn multi_head_attention_backward(
+        &self,
+        query: &Array2<A>,
+        key: &Array2<A>,
+        value: &Array2<A>,
+        num_heads: usize,
+        key_mask: Option<&Array1<A>>,
+        grad_output: &Array2<A>,
+    ) -> (Array2<A>, Array2<A>, Array2<A>, Vec<A>) {
+        assert_eq!(query.ncols() % num_heads, 0, "d_model must be divisible by num_heads");
+        let key_heads = split_heads(key, num_heads);
+        let value_heads = split_heads(value, num_heads);
+        let grad_output_heads = split_heads(grad_output, num_heads);
+        let (mut grad_query, mut grad_key, mut grad_value) = (Vec::new(), Vec::new(), Vec::new());
+        let mut grad_embeddings = vec![A::zero(); self.num_parameters()];
+        for (h, q) in split_heads(query, num_heads).iter().enumerate() {
+            let (gq, gk, gv, ge) = self.attention_backward(q, &key_heads[h], &value_heads[h], key_mask, &grad_output_heads[h]);
+            grad_query.push(gq);
+            grad_key.push(gk);
+            grad_value.push(gv);
+            for (total, g) in grad_embeddings.iter_mut().zip(ge) {
+                *total += g;
+            }
+        }
+        (concat_heads(&grad_query, query.nrows()), concat_heads(&grad_key, key.nrows()), concat_heads(&grad_value, value.nrows()), grad_embeddings)
+    }
+
+    /// Sums a [num_queries, num_keys] matrix per query and clipped distance.
+    /// Shape: [num_queries, 2 * max_distance + 1].
+    fn distance_sums(&self, matrix: &Array2<A>) -> Array2<A> {
+        let mut sums = Array2::zeros((matrix.nrows(), self.key_embeddings.nrows()));
+        for ((i, j), &value) in matrix.indexed_iter() {
+            sums[[i, self.index(i, j)]] += value;
+        }
+        sums
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attention::masked_scaled_dot_product_attention;
+    use ndarray::{array, s};
+
+    #[test]
+    fn test_relative_positions_shift_invariance() {
+        let positions: RelativePositions = RelativePositions::new(2, 3);
+        assert_eq!(positions.index(0, 0), 2);
+        assert_eq!(positions.index(0, 5), 4);
+        assert_eq!(positions.index(5, 0), 0);
+
+        // Zero embeddings reduce to plain attention.
+        let zero: RelativePositions = RelativePositions { max_distance: 1, key_embeddings: Array2::zeros((3, 3)), value_embeddings: Array2::zeros((3, 3)) };
+        let x = array![[0.1, 0.4, -0.2], [0.3, -0.1, 0.2], [0.0, 0.5, 0.1]];
+        let mask = array![1.0, 1.0, 0.0];
+        let expected = masked_scaled_dot_product_attention(&x, &x, &x, Some(&mask));
+        assert!((zero.attention(&x, &x, &x, Some(&mask)) - expected).iter().all(|d| d.abs() < 1e-12));
+
+        // The same tokens with the same spacing attend alike wherever they are.
+        let padded = array![[0.0, 0.0, 0.0], [0.1, 0.4, -0.2], [0.3, -0.1, 0.2]];
+        let head = x.slice(s![0..2, ..]).to_owned();
+        let first = positions.attention(&head, &head, &head, None);
+        let shifted = positions.attention(&padded, &padded, &padded, Some(&array![0.0, 1.0, 1.0]));
+        assert!((first - shifted.slice(s![1..3, ..])).iter().all(|d| d.abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_multi_head_relative_attention() {
+        let positions: RelativePositions = RelativePositions::new(1, 2);
+        let x = array![[0.2, -0.3, 0.1, 0.4], [0.5, 0.1, -0.2, 0.0], [-0.4, 0.3, 0.3, -0.1]];
+        let mask = array![1.0, 1.0, 0.0];
+
+        // Each head attends over its own columns with the shared tables.
+        let output = positions.multi_head_attention(&x, &x, &x, 2, Some(&mask));
+        for h in 0..2 {
+            let head = x.slice(s![.., 2 * h..2 * h + 2]).to_owned();
+            let expected = positions.attention(&head, &head, &head, Some(&mask));
+            assert!((output.slice(s![.., 2 * h..2 * h + 2]).to_owned() - expected).iter().all(|d| d.abs() < 1e-12));
+        }
+
+        // The embedding gradients are the sums over the heads.
+        let upstream = array![[1.0, -0.5, 0.2, 0.1], [0.3, 0.8, -0.6, 0.4], [-0.2, 0.4, 0.5, -0.3]];
+        let (_, _, _, grad_embeddings) = positions.multi_head_attention_backward(&x, &x, &x, 2, Some(&mask), &upstream);
+        let mut expected = vec![0.0; positions.num_parameters()];
+        for h in 0..2 {
+            let columns = s![.., 2 * h..2 * h + 2];
+            let head = x.slice(columns).to_owned();
+            let (_, _, _, head_grads) = positions.attention_backward(&head, &head, &head, Some(&mask), &upstream.slice(columns).to_owned());
+            expected.iter_mut().zip(head_grads).for_each(|(total, g)| *total += g);
+        }
+        assert_eq!(grad_embeddings, expected);
+    }
+
+    #[test]
+    fn test_relative_attention_gradients() {
+        let mut positions = RelativePositions::new(1, 2);
+        let x = array![[0.2, -0.3], [0.5, 0.1], [-0.4, 0.3]];
+        let mask = array![1.0, 1.0, 0.0];
+        let upstream = array![[1.0, -0.5], [0.3, 0.8], [-0.2, 0.4]];
+        let loss = |positions: &RelativePositions, x: &Array2<f64>| (positions.attention(x, x, x, Some(&mask)) * &upstream).sum();
+
+        let (grad_query, grad_key, grad_value, grad_embeddings) = positions.attention_backward(&x, &x, &x, Some(&mask), &upstream);
+        let grad_x = grad_query + grad_key + grad_value;
+        let h = 1e-6;
+        for ((i, j), &analytic) in grad_x.indexed_iter() {
+            let (mut plus, mut minus) = (x.clone(), x.clone());
+            plus[[i, j]] += h;
+            minus[[i, j]] -= h;
+            let numeric = (loss(&positions, &plus) - loss(&positions, &minus)) / (2.0 * h);
+            assert!((numeric - analytic).abs() < 1e-6, "input gradient {:?}: {} vs {}", (i, j), numeric, analytic);
+        }
+
+        for (p, &analytic) in grad_embeddings.iter().enumerate() {
+            let original = *positions.parameters_mut()[p];
+            *positions.parameters_mut()[p] = original + h;
+            let plus = loss(&positions, &x);
+            *positions.parameters_mut()[p] = original - h;
+            let minus = loss(&positions, &x);
+            *positions.parameters_mut()[p] = original;
+            assert!(((plus - minus) / (2.0 * h) - analytic).abs() < 1e-6, "embedding gradient {}", p);
+        }
+    }
+}
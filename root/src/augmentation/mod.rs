@@ -0,0 +1,11 @@
+pub mod noise;
+pub mod paraphrase;
+
+use std::error::Error;
+
+/// A stage of the augmentation pipeline: generates extra training examples from a dataset.
+pub trait Augmenter {
+    /// Returns the texts and labels to train on, every text followed by the copies generated
+    /// from it, each with the label of its original.
+    fn augment(&self, texts: &[String], labels: &[usize]) -> Result<(Vec<String>, Vec<usize>), Box<dyn Error>>;
+}
@@ -0,0 +1,202 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::error::Error;
+use crate::augmentation::Augmenter;
+
+/// A character-level edit that simulates messy input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseOperation {
+    /// Swaps a character with the next one (`teh`).
+    Swap,
+    /// Drops a character (`th`).
+    Delete,
+    /// Replaces a letter with a neighbouring key on a QWERTY keyboard (`rhe`).
+    KeyboardSubstitution,
+    /// Replaces characters OCR commonly confuses, e.g. `rn` -> `m` or `0` -> `o`.
+    OcrConfusion,
+}
+
+/// Every noise operation, in declaration order.
+pub const ALL_NOISE_OPERATIONS: &[NoiseOperation] = &[
+    NoiseOperation::Swap,
+    NoiseOperation::Delete,
+    NoiseOperation::KeyboardSubstitution,
+    NoiseOperation::OcrConfusion,
+];
+
+const KEYBOARD_ROWS: [&str; 3] = ["qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// Character sequences OCR engines commonly read as one another.
+const OCR_CONFUSIONS: &[(&str, &str)] = &[
+    ("rn", "m"),
+    ("m", "rn"),
+    ("cl", "d"),
+    ("d", "cl"),
+    ("vv", "w"),
+    ("w", "vv"),
+    ("0", "o"),
+    ("o", "0"),
+    ("1", "l"),
+    ("l", "1"),
+    ("i", "l"),
+    ("5", "s"),
+    ("s", "5"),
+    ("8", "b"),
+    ("e", "c"),
+    ("c", "e"),
+];
+
+/// Character-level noise: every letter or digit is corrupted with `char_probability` by one
+/// of `operations`, chosen uniformly. Whitespace and punctuation are never touched, so word
+/// boundaries survive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextNoise {
+    pub char_probability: f64,
+    pub operations: &'static [NoiseOperation],
+}
+
+impl TextNoise {
+    /// Returns a noisy copy of `text`. Operations that do not apply at a character (no
+    /// keyboard neighbour, no OCR confusion, nothing to swap with) leave it unchanged.
+    pub fn apply<R: Rng>(&self, text: &str, rng: &mut R) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut noisy = String::with_capacity(text.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if !c.is_alphanumeric() || self.operations.is_empty() || rng.gen::<f64>() >= self.char_probability {
+                noisy.push(c);
+                i += 1;
+                continue;
+            }
+            match self.operations[rng.gen_range(0..self.operations.len())] {
+                NoiseOperation::Swap => match chars.get(i + 1) {
+                    Some(&next) if next.is_alphanumeric() => {
+                        noisy.push(next);
+                        noisy.push(c);
+                        i += 1;
+                    }
+                    _ => noisy.push(c),
+                },
+                NoiseOperation::Delete => {}
+                NoiseOperation::KeyboardSubstitution => noisy.push(keyboard_neighbour(c, rng).unwrap_or(c)),
+                NoiseOperation::OcrConfusion => {
+                    let candidates: Vec<&(&str, &str)> =
+                        OCR_CONFUSIONS.iter().filter(|(source, _)| starts_with(&chars[i..], source)).collect();
+                    match candidates.choose(rng) {
+                        Some((source, target)) => {
+                            noisy.push_str(target);
+                            i += source.chars().count() - 1;
+                        }
+                        None => noisy.push(c),
+                    }
+                }
+            }
+            i += 1;
+        }
+        noisy
+    }
+}
+
+fn starts_with(chars: &[char], prefix: &str) -> bool {
+    prefix.chars().count() <= chars.len() && prefix.chars().zip(chars).all(|(a, &b)| a == b)
+}
+
+/// A random key next to `c` on a QWERTY keyboard, with the case of `c`.
+fn keyboard_neighbour<R: Rng>(c: char, rng: &mut R) -> Option<char> {
+    let lower = c.to_ascii_lowercase();
+    let (row, column) = KEYBOARD_ROWS
+        .iter()
+        .enumerate()
+        .find_map(|(row, keys)| keys.find(lower).map(|column| (row, column)))?;
+    let key = |row: usize, column: usize| KEYBOARD_ROWS.get(row).and_then(|keys| keys.as_bytes().get(column)).map(|&b| b as char);
+    // The keys left and right; rows are staggered, so the keys above are at `column` and
+    // `column + 1`, the keys below at `column - 1` and `column`.
+    let mut neighbours = Vec::new();
+    for (r, cols) in [
+        (Some(row), [column.checked_sub(1), Some(column + 1)]),
+        (row.checked_sub(1), [Some(column), Some(column + 1)]),
+        (Some(row + 1), [column.checked_sub(1), Some(column)]),
+    ] {
+        for col in cols {
+            if let (Some(r), Some(col)) = (r, col) {
+                neighbours.extend(key(r, col));
+            }
+        }
+    }
+    let neighbour = *neighbours.choose(rng)?;
+    Some(if c.is_ascii_uppercase() { neighbour.to_ascii_uppercase() } else { neighbour })
+}
+
+/// Noisy copies of the training examples, added next to the originals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseAugmentation {
+    pub noise: TextNoise,
+    /// Noisy copies per example.
+    pub copies: usize,
+    /// Seed of the noise, so a run's augmented dataset can be reproduced.
+    pub seed: u64,
+}
+
+impl Augmenter for NoiseAugmentation {
+    /// Adds `copies` noisy versions of every text, each with the label of its original.
+    ///
+    /// # Returns
+    /// * The texts and labels, every original followed by its copies.
+    fn augment(&self, texts: &[String], labels: &[usize]) -> Result<(Vec<String>, Vec<usize>), Box<dyn Error>> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut augmented_texts = Vec::with_capacity(texts.len() * (self.copies + 1));
+        let mut augmented_labels = Vec::with_capacity(texts.len() * (self.copies + 1));
+        for (text, &label) in texts.iter().zip(labels) {
+            augmented_texts.push(text.clone());
+            augmented_labels.push(label);
+            for _ in 0..self.copies {
+                augmented_texts.push(self.noise.apply(text, &mut rng));
+                augmented_labels.push(label);
+            }
+        }
+        Ok((augmented_texts, augmented_labels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(operations: &'static [NoiseOperation]) -> TextNoise {
+        TextNoise { char_probability: 1.0, operations }
+    }
+
+    #[test]
+    fn test_operations() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(noise(&[NoiseOperation::Swap]).apply("abcd ef!", &mut rng), "badc fe!");
+        assert_eq!(noise(&[NoiseOperation::Delete]).apply("ab, c", &mut rng), ", ");
+        assert_eq!(noise(&[NoiseOperation::OcrConfusion]).apply("rn 0 x", &mut rng), "m o x");
+
+        let typed = noise(&[NoiseOperation::KeyboardSubstitution]).apply("Sg7", &mut rng);
+        let chars: Vec<char> = typed.chars().collect();
+        assert!("ADWEZX".contains(chars[0]), "{}", typed);
+        assert!("fhtycv".contains(chars[1]), "{}", typed);
+        assert_eq!(chars[2], '7');
+        assert_eq!(TextNoise { char_probability: 0.0, operations: ALL_NOISE_OPERATIONS }.apply("unchanged", &mut rng), "unchanged");
+    }
+
+    #[test]
+    fn test_augment_is_seeded() {
+        let augmentation = NoiseAugmentation {
+            noise: TextNoise { char_probability: 0.3, operations: ALL_NOISE_OPERATIONS },
+            copies: 2,
+            seed: 1,
+        };
+        let texts = vec!["the delivery was late".to_string(), "great product".to_string()];
+        let (augmented, labels) = augmentation.augment(&texts, &[0, 1]).unwrap();
+
+        assert_eq!(labels, vec![0, 0, 0, 1, 1, 1]);
+        assert_eq!(augmented[0], texts[0]);
+        assert_eq!(augmented[3], texts[1]);
+        assert!(augmented[1] != texts[0] || augmented[2] != texts[0]);
+        assert_eq!(augmentation.augment(&texts, &[0, 1]).unwrap().0, augmented);
+    }
+}
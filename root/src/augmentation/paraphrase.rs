@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use crate::augmentation::Augmenter;
+use crate::logging::logger::LogEvent;
+
+/// Generates paraphrases of a text, e.g. by translating it to another language and back.
+///
+/// Any `Fn(&str, usize) -> Result<Vec<String>, Box<dyn Error>>` closure is a provider;
+/// `CommandParaphraser` runs an external program.
+pub trait ParaphraseProvider {
+    /// Up to `count` paraphrases of `text`. Returning fewer, or none, is allowed.
+    fn paraphrase(&self, text: &str, count: usize) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+impl<F> ParaphraseProvider for F
+where
+    F: Fn(&str, usize) -> Result<Vec<String>, Box<dyn Error>>,
+{
+    fn paraphrase(&self, text: &str, count: usize) -> Result<Vec<String>, Box<dyn Error>> {
+        self(text, count)
+    }
+}
+
+/// Paraphrases with an external program, started once per text. The program reads the text
+/// from stdin and the number of paraphrases wanted from the `PARAPHRASE_COUNT` environment
+/// variable, and writes one paraphrase per line to stdout.
+pub struct CommandParaphraser {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl CommandParaphraser {
+    /// # Arguments
+    /// * `command` - The program followed by its arguments, e.g. `["python3", "backtranslate.py", "--via", "de"]`.
+    ///
+    /// # Returns
+    /// * The paraphraser, or an error for an empty command.
+    pub fn new(command: &[&str]) -> Result<Self, Box<dyn Error>> {
+        let (program, args) = command.split_first().ok_or("Paraphrase command must name a program.")?;
+        Ok(CommandParaphraser {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        })
+    }
+}
+
+impl ParaphraseProvider for CommandParaphraser {
+    fn paraphrase(&self, text: &str, count: usize) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .env("PARAPHRASE_COUNT", count.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start paraphrase command '{}': {}", self.program, e))?;
+        // Written from another thread while `wait_with_output` drains stdout and stderr, so a
+        // program that answers before it has read the whole text cannot fill its output pipe
+        // and block both sides. Dropping stdin after the write closes it, so the program sees
+        // the end of the text.
+        let mut stdin = child.stdin.take().ok_or("Paraphrase command has no stdin")?;
+        let input = text.to_string();
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output()?;
+        // A program that exits without reading all of its input closes the pipe; its exit
+        // status below says whether that was a failure.
+        let written = writer.join().map_err(|_| "Paraphrase stdin writer panicked")?;
+        if let Err(e) = written {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(e.into());
+            }
+        }
+        if !output.status.success() {
+            return Err(format!(
+                "Paraphrase command '{}' failed ({}): {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .take(count)
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// Paraphrases generated for one text.
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedParaphrases {
+    /// The number of paraphrases asked for; providers may return fewer.
+    requested: usize,
+    paraphrases: Vec<String>,
+}
+
+/// Adds up to `copies` paraphrases of every training example, each with the label of its
+/// original. Generated paraphrases are cached by text, in memory and optionally in a JSON
+/// file, so a provider is only called once per text across `train` calls and runs.
+pub struct ParaphraseAugmentation<'a> {
+    provider: Box<dyn ParaphraseProvider + 'a>,
+    pub copies: usize,
+    cache: RefCell<HashMap<String, CachedParaphrases>>,
+    cache_path: Option<PathBuf>,
+}
+
+impl<'a> ParaphraseAugmentation<'a> {
+    pub fn new<P: ParaphraseProvider + 'a>(provider: P, copies: usize) -> Self {
+        ParaphraseAugmentation {
+            provider: Box::new(provider),
+            copies,
+            cache: RefCell::new(HashMap::new()),
+            cache_path: None,
+        }
+    }
+
+    /// Keeps the cache in a JSON file: entries in it are reused, and new paraphrases are
+    /// written back after every `augment`. The file is created when missing.
+    pub fn with_cache_file<T: AsRef<Path>>(mut self, path: T) -> Self {
+        self.cache_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    fn load_cache_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let saved: HashMap<String, CachedParaphrases> = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| format!("Failed to read paraphrase cache {}: {}", path.display(), e))?;
+        let mut cache = self.cache.borrow_mut();
+        for (text, paraphrases) in saved {
+            cache.entry(text).or_insert(paraphrases);
+        }
+        Ok(())
+    }
+
+    /// Paraphrases of `text`, from the cache unless fewer than `copies` were asked for.
+    fn paraphrases(&self, text: &str) -> Result<(Vec<String>, bool), Box<dyn Error>> {
+        if let Some(cached) = self.cache.borrow().get(text) {
+            if cached.requested >= self.copies {
+                return Ok((cached.paraphrases.clone(), false));
+            }
+        }
+        let paraphrases = self.provider.paraphrase(text, self.copies)?;
+        let cached = CachedParaphrases { requested: self.copies, paraphrases: paraphrases.clone() };
+        self.cache.borrow_mut().insert(text.to_string(), cached);
+        Ok((paraphrases, true))
+    }
+}
+
+impl Augmenter for ParaphraseAugmentation<'_> {
+    /// Adds up to `copies` paraphrases of every text. Paraphrases identical to the original
+    /// (back-translation often returns the input) are skipped.
+    ///
+    /// # Returns
+    /// * The texts and labels, every original followed by its paraphrases.
+    fn augment(&self, texts: &[String], labels: &[usize]) -> Result<(Vec<String>, Vec<usize>), Box<dyn Error>> {
+        if let Some(path) = &self.cache_path {
+            self.load_cache_file(path)?;
+        }
+        let mut augmented_texts = Vec::with_capacity(texts.len() * (self.copies + 1));
+        let mut augmented_labels = Vec::with_capacity(texts.len() * (self.copies + 1));
+        let mut generated = 0;
+        for (text, &label) in texts.iter().zip(labels) {
+            augmented_texts.push(text.clone());
+            augmented_labels.push(label);
+            let (paraphrases, new) = self.paraphrases(text)?;
+            generated += new as usize;
+            for paraphrase in paraphrases.into_iter().filter(|paraphrase| paraphrase != text).take(self.copies) {
+                augmented_texts.push(paraphrase);
+                augmented_labels.push(label);
+            }
+        }
+        LogEvent::info(
+            "augmentation",
+            format!(
+                "Paraphrase augmentation: {} texts -> {} examples ({} paraphrased, {} from cache)",
+                texts.len(),
+                augmented_texts.len(),
+                generated,
+                texts.len() - generated
+            ),
+        )
+        .metric("examples", augmented_texts.len())
+        .metric("paraphrased", generated)
+        .emit();
+        if let (Some(path), true) = (&self.cache_path, generated > 0) {
+            fs::write(path, serde_json::to_string_pretty(&*self.cache.borrow())?)?;
+        }
+        Ok((augmented_texts, augmented_labels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::temp_path;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_paraphrases_are_cached() {
+        let calls = Cell::new(0);
+        let provider = |text: &str, count: usize| -> Result<Vec<String>, Box<dyn Error>> {
+            calls.set(calls.get() + 1);
+            Ok(vec![text.to_string(), format!("{} indeed", text), format!("well, {}", text)].into_iter().take(count + 1).collect())
+        };
+        let cache_path = &temp_path("paraphrase_cache_test.json");
+        let _ = fs::remove_file(cache_path);
+        let texts = vec!["late delivery".to_string(), "great product".to_string(), "late delivery".to_string()];
+
+        let augmentation = ParaphraseAugmentation::new(provider, 2).with_cache_file(cache_path);
+        let (augmented, labels) = augmentation.augment(&texts, &[0, 1, 0]).unwrap();
+        assert_eq!(augmented[..3], ["late delivery", "late delivery indeed", "well, late delivery"]);
+        assert_eq!(labels, vec![0, 0, 0, 1, 1, 1, 0, 0, 0]);
+        assert_eq!(calls.get(), 2);
+        assert_eq!(augmentation.cache.borrow().len(), 2);
+
+        // A new augmentation over the same file does not call its provider again.
+        let fresh = ParaphraseAugmentation::new(|_: &str, _: usize| -> Result<Vec<String>, Box<dyn Error>> { Err("not cached".into()) }, 1)
+            .with_cache_file(cache_path);
+        let (cached, _) = fresh.augment(&texts[..2], &[0, 1]).unwrap();
+        fs::remove_file(cache_path).unwrap();
+        assert_eq!(cached, vec!["late delivery", "late delivery indeed", "great product", "great product indeed"]);
+    }
+
+    #[test]
+    fn test_command_paraphraser() {
+        let paraphraser = CommandParaphraser::new(&["sh", "-c", "tr a-z A-Z; echo; echo \"$PARAPHRASE_COUNT\""]).unwrap();
+        assert_eq!(paraphraser.paraphrase("slow reply", 3).unwrap(), vec!["SLOW REPLY", "3"]);
+
+        let failing = CommandParaphraser::new(&["sh", "-c", "echo broken >&2; exit 1"]).unwrap();
+        assert!(failing.paraphrase("text", 1).unwrap_err().to_string().contains("broken"));
+        assert!(CommandParaphraser::new(&[]).is_err());
+    }
+
+    #[test]
+    fn test_command_paraphraser_with_long_input_and_output() {
+        // Echoes every line back as it reads it, so its output fills the pipe long before
+        // its input is fully written.
+        let paraphraser = CommandParaphraser::new(&["cat"]).unwrap();
+        let text = "word ".repeat(20_000) + "\n";
+        let text = text.repeat(10);
+        assert_eq!(paraphraser.paraphrase(&text, 20).unwrap().len(), 10);
+    }
+}
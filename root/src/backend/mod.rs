@@ -0,0 +1 @@
+pub mod tensor_backend;
@@ -0,0 +1,122 @@
+use ndarray::{Array2, ErrorKind, ShapeError};
+
+use crate::layer_norm::apply_layer_norm_with;
+use crate::numerics::{softmax_inplace, Float};
+use crate::summation::Summation;
+
+/// Minimal set of tensor operations the model's forward and backward passes are built from.
+///
+/// A backend (BLAS, SIMD, GPU) implements these for one element type; code written against
+/// `&dyn TensorBackend<A>` then runs on any of them. Every operation takes and returns
+/// host `Array2`s, so a device backend copies in and out per call until the model keeps its
+/// tensors on the device. Operations on two matrices return an `IncompatibleShape` error
+/// when their shapes do not fit together.
+pub trait TensorBackend<A: Float> {
+    /// Matrix product `a b`. Shapes: [m, k] x [k, n] -> [m, n].
+    fn matmul(&self, a: &Array2<A>, b: &Array2<A>) -> Result<Array2<A>, ShapeError>;
+
+    /// Matrix product `a b^T`, as in attention scores. Shapes: [m, k] x [n, k] -> [m, n].
+    fn matmul_transposed(&self, a: &Array2<A>, b: &Array2<A>) -> Result<Array2<A>, ShapeError>;
+
+    /// Softmax of every row; entries of `-inf` get probability 0.
+    fn softmax_rows(&self, x: &Array2<A>) -> Array2<A>;
+
+    /// Layer normalization of every row, without a learned scale or shift, with the row
+    /// statistics accumulated as `summation` says.
+    fn layer_norm(&self, x: &Array2<A>, epsilon: A, summation: Summation) -> Array2<A>;
+
+    /// Element-wise sum; `b` may be a single row, broadcast over the rows of `a` (biases).
+    fn add(&self, a: &Array2<A>, b: &Array2<A>) -> Result<Array2<A>, ShapeError>;
+
+    /// Every element multiplied by `factor`.
+    fn scale(&self, x: &Array2<A>, factor: A) -> Array2<A>;
+
+    /// `max(x, 0)` element-wise.
+    fn relu(&self, x: &Array2<A>) -> Array2<A>;
+}
+
+/// The ndarray CPU path the model has always used. Matrix products go through ndarray's
+/// `dot`, which uses Accelerate when the crate is built with the `accelerate` feature.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuBackend;
+
+impl<A: Float> TensorBackend<A> for CpuBackend {
+    fn matmul(&self, a: &Array2<A>, b: &Array2<A>) -> Result<Array2<A>, ShapeError> {
+        if a.ncols() != b.nrows() {
+            return Err(incompatible_shape());
+        }
+        Ok(a.dot(b))
+    }
+
+    fn matmul_transposed(&self, a: &Array2<A>, b: &Array2<A>) -> Result<Array2<A>, ShapeError> {
+        if a.ncols() != b.ncols() {
+            return Err(incompatible_shape());
+        }
+        Ok(a.dot(&b.t()))
+    }
+
+    fn softmax_rows(&self, x: &Array2<A>) -> Array2<A> {
+        let mut probabilities = x.clone();
+        for row in probabilities.outer_iter_mut() {
+            softmax_inplace(row);
+        }
+        probabilities
+    }
+
+    fn layer_norm(&self, x: &Array2<A>, epsilon: A, summation: Summation) -> Array2<A> {
+        apply_layer_norm_with(x, epsilon, summation)
+    }
+
+    fn add(&self, a: &Array2<A>, b: &Array2<A>) -> Result<Array2<A>, ShapeError> {
+        Ok(a + &b.broadcast(a.dim()).ok_or_else(incompatible_shape)?)
+    }
+
+    fn scale(&self, x: &Array2<A>, factor: A) -> Array2<A> {
+        x * factor
+    }
+
+    fn relu(&self, x: &Array2<A>) -> Array2<A> {
+        x.mapv(|v| v.max(A::zero()))
+    }
+}
+
+fn incompatible_shape() -> ShapeError {
+    ShapeError::from_kind(ErrorKind::IncompatibleShape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attention::attention_mechanism::scaled_dot_product_attention_on;
+    use crate::attention::scaled_dot_product_attention;
+    use crate::layer_norm::apply_layer_norm;
+    use ndarray::array;
+
+    #[test]
+    fn test_cpu_backend_matches_kernels() {
+        let backend = CpuBackend;
+        let x: Array2<f64> = array![[0.2, -0.1, 0.4], [0.5, 0.2, -0.3]];
+        let expected = scaled_dot_product_attention(&x, &x, &x);
+        assert_eq!(scaled_dot_product_attention_on(&backend, &x, &x, &x, None).unwrap(), expected);
+
+        assert_eq!(TensorBackend::<f64>::relu(&backend, &x), array![[0.2, 0.0, 0.4], [0.5, 0.2, 0.0]]);
+        assert_eq!(backend.add(&x, &array![[1.0, 1.0, 1.0]]).unwrap(), x.mapv(|v| v + 1.0));
+        assert_eq!(backend.layer_norm(&x, 1e-6, Summation::Naive), apply_layer_norm(&x, 1e-6));
+
+        // The same interface serves single precision.
+        let single = x.mapv(|v| v as f32);
+        let output = scaled_dot_product_attention_on(&backend as &dyn TensorBackend<f32>, &single, &single, &single, None).unwrap();
+        assert!((output.mapv(f64::from) - expected).iter().all(|d| d.abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_mismatched_shapes_are_errors() {
+        let backend = CpuBackend;
+        let a: Array2<f64> = Array2::zeros((2, 3));
+        let b: Array2<f64> = Array2::zeros((2, 3));
+        assert_eq!(backend.matmul(&a, &b).unwrap_err().kind(), ErrorKind::IncompatibleShape);
+        assert!(backend.matmul_transposed(&a, &b).is_ok());
+        assert!(backend.add(&a, &Array2::zeros((1, 2))).is_err());
+        assert!(scaled_dot_product_attention_on(&backend, &a, &Array2::zeros((2, 4)), &b, None).is_err());
+    }
+}
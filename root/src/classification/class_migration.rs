@@ -0,0 +1,113 @@
+use super::ClassReduction;
+use crate::data_handler::label_map::LabelMap;
+use crate::transformer::Transformer;
+use std::error::Error;
+
+/// A change to the classes of a trained model, applied by `migrate_classes`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClassMigration {
+    /// Merges classes into `into`, which is created at the end of the map if missing.
+    Merge { names: Vec<String>, into: String },
+    /// Removes classes; their examples are no longer predicted.
+    Remove { names: Vec<String> },
+}
+
+/// Applies a taxonomy change to a trained model and its label map, so the model keeps
+/// predicting the remaining classes without a full retrain.
+///
+/// The classification head is rebuilt with `ClassificationHead::remap_classes`: the weight
+/// columns and biases of merged classes are combined with `reduction`, and those of removed
+/// classes are dropped. A class created by a merge into a new name gets the combined
+/// weights of the classes merged into it.
+///
+/// # Arguments
+/// * `model` - The trained model; `config.num_classes` is updated.
+/// * `label_map` - Names of the model's classes, updated in place.
+/// * `migration` - The classes to merge or remove.
+/// * `reduction` - How merged weight columns are combined.
+///
+/// # Returns
+/// * The new class id of every previous class (`None` for removed ones), for relabelling
+///   datasets and stored predictions.
+pub fn migrate_classes(
+    model: &mut Transformer,
+    label_map: &mut LabelMap,
+    migration: &ClassMigration,
+    reduction: ClassReduction,
+) -> Result<Vec<Option<usize>>, Box<dyn Error>> {
+    let num_classes = model.classification_head.num_classes();
+    if label_map.len() != num_classes {
+        return Err(format!("The label map names {} classes but the classification head has {}", label_map.len(), num_classes).into());
+    }
+
+    let mut migrated = label_map.clone();
+    let remap: Vec<Option<usize>> = match migration {
+        ClassMigration::Merge { names, into } => {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            migrated.merge(&names, into)?.into_iter().map(Some).collect()
+        }
+        ClassMigration::Remove { names } => {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            migrated.remove(&names)?
+        }
+    };
+    if migrated.is_empty() {
+        return Err("The migration would remove every class".into());
+    }
+
+    model.classification_head.remap_classes(&remap, reduction)?;
+    model.config.num_classes = migrated.len();
+    *label_map = migrated;
+    Ok(remap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{tiny_config, tiny_vocab};
+    use ndarray::array;
+
+    fn model(num_classes: usize) -> Transformer {
+        let vocab = tiny_vocab(&["late"]);
+        let config = tiny_config(num_classes);
+        Transformer::new(config, vocab)
+    }
+
+    #[test]
+    fn test_merge_keeps_predictions_of_untouched_classes() {
+        let mut model = model(4);
+        let mut label_map = LabelMap::from_names(&["billing", "refunds", "shipping", "returns"]).unwrap();
+        let inputs = array![[2.0, 2.0, 0.0]];
+        let before = model.forward(&inputs, None);
+
+        let migration = ClassMigration::Merge { names: vec!["refunds".into(), "returns".into()], into: "billing".into() };
+        let remap = migrate_classes(&mut model, &mut label_map, &migration, ClassReduction::Sum).unwrap();
+        assert_eq!(remap, vec![Some(0), Some(0), Some(1), Some(0)]);
+        assert_eq!(label_map.names(), ["billing", "shipping"]);
+        assert_eq!(model.config.num_classes, 2);
+
+        let after = model.forward(&inputs, None);
+        assert_eq!(after.ncols(), 2);
+        assert!((after[[0, 1]] - before[[0, 2]]).abs() < 1e-9);
+        // Summed weights sum the logits.
+        assert!((after[[0, 0]] - (before[[0, 0]] + before[[0, 1]] + before[[0, 3]])).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_remove_and_mismatched_map() {
+        let mut model = model(3);
+        let mut label_map = LabelMap::from_names(&["a", "b", "c"]).unwrap();
+        let migration = ClassMigration::Remove { names: vec!["b".into()] };
+        let remap = migrate_classes(&mut model, &mut label_map, &migration, ClassReduction::Mean).unwrap();
+        assert_eq!(remap, vec![Some(0), None, Some(1)]);
+        assert_eq!(label_map.names(), ["a", "c"]);
+        assert_eq!(model.classification_head.num_classes(), 2);
+
+        let everything = ClassMigration::Remove { names: vec!["a".into(), "c".into()] };
+        assert!(migrate_classes(&mut model, &mut label_map, &everything, ClassReduction::Mean).is_err());
+        assert_eq!(label_map.names(), ["a", "c"]);
+
+        let mut wrong_map = LabelMap::numeric(5);
+        assert!(migrate_classes(&mut model, &mut wrong_map, &migration, ClassReduction::Mean).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use ndarray::{Array2, ArrayView1, ShapeError};
+use serde::{Serialize, Deserialize};
+
+/// Class prototypes for nearest-centroid classification.
+///
+/// Each class is represented by the mean of the pooled encoder outputs of its
+/// training examples. Inputs are classified by cosine similarity to these
+/// centroids, so classes can be added without retraining the classification head.
+#[derive(Serialize, Deserialize)]
+pub struct ClassPrototypes {
+    centroids: Array2<f64>,
+}
+
+impl ClassPrototypes {
+    /// Computes one centroid per class from pooled encoder outputs.
+    ///
+    /// # Arguments
+    /// * `pooled` - Pooled encoder outputs. Shape: [num_examples, d_model].
+    /// * `labels` - Class label of every example. Shape: [num_examples].
+    /// * `num_classes` - Number of classes. Classes without examples get a zero centroid.
+    ///
+    /// # Returns
+    /// A new instance of `ClassPrototypes`.
+    pub fn fit(pooled: &Array2<f64>, labels: &[usize], num_classes: usize) -> Self {
+        assert_eq!(pooled.nrows(), labels.len(), "Pooled outputs and labels must have the same length.");
+
+        let mut centroids = Array2::zeros((num_classes, pooled.ncols()));
+        let mut counts = vec![0usize; num_classes];
+
+        for (row, &label) in pooled.outer_iter().zip(labels.iter()) {
+            assert!(label < num_classes, "Label index out of bounds for prototypes.");
+            let mut centroid = centroids.row_mut(label);
+            centroid += &row;
+            counts[label] += 1;
+        }
+
+        for (mut centroid, &count) in centroids.outer_iter_mut().zip(counts.iter()) {
+            if count > 0 {
+                centroid /= count as f64;
+            }
+        }
+
+        ClassPrototypes { centroids }
+    }
+
+    /// Appends a centroid for a new class and returns its index, or an error if the
+    /// centroid does not have d_model entries.
+    pub fn add_class(&mut self, centroid: ArrayView1<f64>) -> Result<usize, ShapeError> {
+        self.centroids.push_row(centroid)?;
+        Ok(self.centroids.nrows() - 1)
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.centroids.nrows()
+    }
+
+    /// Cosine similarity between a pooled output and every class centroid.
+    ///
+    /// # Arguments
+    /// * `pooled` - A single pooled encoder output. Shape: [d_model].
+    ///
+    /// # Returns
+    /// * One similarity in [-1, 1] per class. Shape: [num_classes].
+    pub fn similarities(&self, pooled: ArrayView1<f64>) -> Vec<f64> {
+        let pooled_norm = pooled.dot(&pooled).sqrt();
+
+        self.centroids
+            .outer_iter()
+            .map(|centroid| {
+                let norm = pooled_norm * centroid.dot(&centroid).sqrt();
+                if norm > 0.0 {
+                    pooled.dot(&centroid) / norm
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_fit_centroids() {
+        let pooled = array![
+            [1.0, 0.0],
+            [3.0, 0.0],
+            [0.0, 2.0],
+        ];
+        let labels = vec![0, 0, 1];
+
+        let prototypes = ClassPrototypes::fit(&pooled, &labels, 2);
+
+        assert_eq!(prototypes.num_classes(), 2);
+        assert_eq!(prototypes.centroids.row(0), array![2.0, 0.0]);
+        assert_eq!(prototypes.centroids.row(1), array![0.0, 2.0]);
+    }
+
+    #[test]
+    fn test_classify_by_cosine_similarity() {
+        let pooled = array![
+            [1.0, 0.0],
+            [0.0, 1.0],
+        ];
+        let prototypes = ClassPrototypes::fit(&pooled, &[0, 1], 2);
+
+        let query = array![0.2, 5.0];
+        let similarities = prototypes.similarities(query.view());
+
+        assert!((similarities[1] - 5.0 / (0.04f64 + 25.0).sqrt()).abs() < 1e-9);
+        assert!(similarities[1] > similarities[0]);
+    }
+}
@@ -1,15 +1,27 @@
-use ndarray::{Array2, Axis};
+use ndarray::{aview1, Array2, ArrayView1, Axis, ShapeError};
 use ndarray_rand::RandomExt;
 use ndarray_rand::rand_distr::Uniform;
 use serde::{Serialize, Deserialize};
 
+use crate::numerics::Float;
+
+/// How `ClassificationHead::remap_classes` combines the outputs of classes that are merged.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ClassReduction {
+    /// The merged logit is the sum of the merged logits.
+    Sum,
+    /// The merged logit is the mean of the merged logits, which keeps it on the scale of
+    /// the other classes.
+    Mean,
+}
+
 #[derive(Serialize, Deserialize)]
-pub struct ClassificationHead {
-    weights: Array2<f64>,
-    biases: Array2<f64>,
+pub struct ClassificationHead<A = f64> {
+    weights: Array2<A>,
+    biases: Array2<A>,
 }
 
-impl ClassificationHead {
+impl<A: Float> ClassificationHead<A> {
     /// Creates a new `ClassificationHead`.
     ///
     /// # Arguments
@@ -19,11 +31,18 @@ impl ClassificationHead {
     /// # Returns
     /// A new instance of `ClassificationHead`.
     pub fn new(d_model: usize, num_classes: usize) -> Self {
-        let weights = Array2::random((d_model, num_classes), Uniform::new(-0.1, 0.1));
+        let weights = Array2::random((d_model, num_classes), Uniform::new(A::cast(-0.1), A::cast(0.1)));
         let biases = Array2::zeros((1, num_classes));
         ClassificationHead { weights, biases }
     }
 
+    /// Creates a `ClassificationHead` from existing weights [d_model, num_classes]
+    /// and biases [1, num_classes].
+    pub fn from_parameters(weights: Array2<A>, biases: Array2<A>) -> Self {
+        assert_eq!(biases.dim(), (1, weights.ncols()), "Biases do not match the weights!");
+        ClassificationHead { weights, biases }
+    }
+
     /// Performs a forward pass through the classification head.
     ///
     /// # Arguments
@@ -31,11 +50,127 @@ impl ClassificationHead {
     ///
     /// # Returns
     /// * Logits. Shape: [batch_size, num_classes].
-    pub fn forward(&self, pooled_output: &Array2<f64>) -> Array2<f64> {
+    pub fn forward(&self, pooled_output: &Array2<A>) -> Array2<A> {
         pooled_output.dot(&self.weights) + &self.biases
     }
 
-    pub fn parameters_mut(&mut self) -> Vec<&mut f64> {
+    /// Performs a backward pass through the classification head.
+    ///
+    /// # Arguments
+    /// * `pooled_output` - The input used in the forward pass. Shape: [batch_size, d_model].
+    /// * `grad_logits` - Gradient of the loss with respect to the logits. Shape: [batch_size, num_classes].
+    ///
+    /// # Returns
+    /// * Gradient with respect to `pooled_output`, and the parameter gradients
+    ///   flattened in the same order as `parameters_mut` (weights, biases).
+    pub fn backward(&self, pooled_output: &Array2<A>, grad_logits: &Array2<A>) -> (Array2<A>, Vec<A>) {
+        let grad_weights = pooled_output.t().dot(grad_logits);
+        let grad_biases = grad_logits.sum_axis(Axis(0));
+        let grad_input = grad_logits.dot(&self.weights.t());
+
+        let mut param_grads = Vec::with_capacity(grad_weights.len() + grad_biases.len());
+        param_grads.extend(grad_weights.iter());
+        param_grads.extend(grad_biases.iter());
+
+        (grad_input, param_grads)
+    }
+
+    pub fn num_parameters(&self) -> usize {
+        self.weights.len() + self.biases.len()
+    }
+
+    /// The weights `[d_model, num_classes]` and biases `[1, num_classes]`.
+    pub fn parameters(&self) -> (&Array2<A>, &Array2<A>) {
+        (&self.weights, &self.biases)
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.weights.ncols()
+    }
+
+    /// Appends an output class to the head.
+    ///
+    /// # Arguments
+    /// * `weights` - Weight column of the new class. Shape: [d_model].
+    /// * `bias` - Bias of the new class.
+    ///
+    /// # Returns
+    /// * Index of the new class, or an error if `weights` does not have d_model entries.
+    pub fn add_class(&mut self, weights: ArrayView1<A>, bias: A) -> Result<usize, ShapeError> {
+        self.weights.push_column(weights)?;
+        self.biases.push_column(aview1(&[bias]))?;
+
+        Ok(self.weights.ncols() - 1)
+    }
+
+    /// Appends a class whose weight column is imprinted from a class centroid.
+    /// The centroid is normalized and scaled to the mean norm of the existing
+    /// weight columns, and the bias is set to the mean of the existing biases.
+    pub fn imprint_class(&mut self, centroid: ArrayView1<A>) -> Result<usize, ShapeError> {
+        let num_classes = self.num_classes();
+        let mean_norm = if num_classes > 0 {
+            self.weights
+                .axis_iter(Axis(1))
+                .map(|column| column.dot(&column).sqrt())
+                .sum::<A>()
+                / A::cast(num_classes as f64)
+        } else {
+            A::one()
+        };
+        let mean_bias = self.biases.mean().unwrap_or(A::zero());
+
+        let centroid_norm = centroid.dot(&centroid).sqrt();
+        let weights = if centroid_norm > A::zero() {
+            centroid.mapv(|x| x / centroid_norm * mean_norm)
+        } else {
+            centroid.to_owned()
+        };
+
+        self.add_class(weights.view(), mean_bias)
+    }
+
+    /// Rebuilds the head for a new set of classes without retraining, after classes of
+    /// the label map were merged or removed.
+    ///
+    /// # Arguments
+    /// * `remap` - The new class of every current class, indexed by current class; `None`
+    ///   removes the class. New classes must be `0..n` without gaps.
+    /// * `reduction` - How the weight columns and biases of classes mapped to the same new
+    ///   class are combined.
+    ///
+    /// # Returns
+    /// * An error if `remap` does not cover every class or leaves a new class without inputs.
+    pub fn remap_classes(&mut self, remap: &[Option<usize>], reduction: ClassReduction) -> Result<(), Box<dyn std::error::Error>> {
+        if remap.len() != self.num_classes() {
+            return Err(format!("The remapping covers {} classes but the head has {}", remap.len(), self.num_classes()).into());
+        }
+        let num_classes = remap.iter().flatten().map(|&class| class + 1).max().unwrap_or(0);
+        let mut weights = Array2::zeros((self.weights.nrows(), num_classes));
+        let mut biases = Array2::zeros((1, num_classes));
+        let mut counts = vec![0usize; num_classes];
+        for (class, new_class) in remap.iter().enumerate() {
+            let Some(new_class) = *new_class else { continue };
+            let mut column = weights.column_mut(new_class);
+            column += &self.weights.column(class);
+            biases[[0, new_class]] += self.biases[[0, class]];
+            counts[new_class] += 1;
+        }
+        if let Some(empty) = counts.iter().position(|&count| count == 0) {
+            return Err(format!("No class is mapped to new class {}", empty).into());
+        }
+        if reduction == ClassReduction::Mean {
+            for (new_class, &count) in counts.iter().enumerate() {
+                let mut column = weights.column_mut(new_class);
+                column /= A::cast(count as f64);
+                biases[[0, new_class]] /= A::cast(count as f64);
+            }
+        }
+        self.weights = weights;
+        self.biases = biases;
+        Ok(())
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut A> {
         let mut params = vec![];
 
   
@@ -62,7 +197,7 @@ mod tests {
         let d_model = 4;
         let num_classes = 2;
 
-        let mut head = ClassificationHead::new(d_model, num_classes);
+        let mut head: ClassificationHead = ClassificationHead::new(d_model, num_classes);
 
       
         head.weights = array![[0.1, 0.2], [0.3, 0.4], [0.5, 0.6], [0.7, 0.8]];
@@ -87,7 +222,7 @@ mod tests {
     fn test_parameters_mut() {
         let d_model = 4;
         let num_classes = 2;
-        let mut head = ClassificationHead::new(d_model, num_classes);
+        let mut head: ClassificationHead = ClassificationHead::new(d_model, num_classes);
 
         let params = head.parameters_mut();
         assert_eq!(params.len(), d_model * num_classes + num_classes);
@@ -97,7 +232,7 @@ mod tests {
     fn test_serialization() {
         let d_model = 4;
         let num_classes = 2;
-        let head = ClassificationHead::new(d_model, num_classes);
+        let head: ClassificationHead = ClassificationHead::new(d_model, num_classes);
 
         let serialized = serde_json::to_string(&head).expect("Failed to serialize");
         let deserialized: ClassificationHead = serde_json::from_str(&serialized).expect("Failed to deserialize");
@@ -106,4 +241,48 @@ mod tests {
         assert_eq!(head.weights.shape(), deserialized.weights.shape());
         assert_eq!(head.biases.shape(), deserialized.biases.shape());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_add_class() {
+        let mut head = ClassificationHead::new(2, 2);
+        head.weights = array![[1.0, 0.0], [0.0, 3.0]];
+        head.biases = array![[0.5, 1.5]];
+
+        let new_class = head.imprint_class(array![0.0, 4.0].view()).unwrap();
+
+        assert_eq!(new_class, 2);
+        assert_eq!(head.num_classes(), 3);
+        assert_eq!(head.weights.column(2), array![0.0, 2.0]);
+        assert_eq!(head.biases, array![[0.5, 1.5, 1.0]]);
+
+        let logits = head.forward(&array![[1.0, 1.0]]);
+        assert_eq!(logits.shape(), &[1, 3]);
+
+        assert!(head.imprint_class(array![1.0, 2.0, 3.0].view()).is_err());
+        assert_eq!(head.num_classes(), 3);
+    }
+
+    #[test]
+    fn test_remap_classes() {
+        let head = || ClassificationHead::from_parameters(array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]], array![[0.5, 1.5, 2.5]]);
+
+        // Classes 0 and 2 merge into new class 0, class 1 becomes class 1.
+        let mut summed = head();
+        summed.remap_classes(&[Some(0), Some(1), Some(0)], ClassReduction::Sum).unwrap();
+        assert_eq!(summed.weights, array![[4.0, 2.0], [10.0, 5.0]]);
+        assert_eq!(summed.biases, array![[3.0, 1.5]]);
+
+        let mut averaged = head();
+        averaged.remap_classes(&[Some(0), Some(1), Some(0)], ClassReduction::Mean).unwrap();
+        assert_eq!(averaged.weights, array![[2.0, 2.0], [5.0, 5.0]]);
+        assert_eq!(averaged.biases, array![[1.5, 1.5]]);
+
+        let mut removed = head();
+        removed.remap_classes(&[None, Some(0), Some(1)], ClassReduction::Mean).unwrap();
+        assert_eq!(removed.weights, array![[2.0, 3.0], [5.0, 6.0]]);
+        assert_eq!(removed.num_classes(), 2);
+
+        assert!(head().remap_classes(&[Some(0), Some(2), Some(0)], ClassReduction::Sum).is_err());
+        assert!(head().remap_classes(&[Some(0), Some(1)], ClassReduction::Sum).is_err());
+    }
+}
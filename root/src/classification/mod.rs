@@ -1,2 +1,8 @@
 mod classification_head;
-pub use classification_head::ClassificationHead;
\ No newline at end of file
+mod class_prototypes;
+mod tied_output_head;
+pub mod class_migration;
+pub mod word_pooling;
+pub use classification_head::{ClassificationHead, ClassReduction};
+pub use tied_output_head::TiedOutputHead;
+pub use class_prototypes::ClassPrototypes;
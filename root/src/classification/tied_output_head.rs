@@ -0,0 +1,91 @@
+use ndarray::{Array2, Axis};
+use crate::embedding::embeddings::Embeddings;
+
+/// Output projection over the vocabulary whose weights are the token embedding matrix
+/// (weight tying), for token-level objectives such as masked language modelling.
+///
+/// The head owns only its biases: the weights are borrowed from `Embeddings` on every call,
+/// so there is a single copy of the matrix and updates from either side are seen by both.
+pub struct TiedOutputHead {
+    biases: Array2<f64>,
+}
+
+impl TiedOutputHead {
+    /// Creates a head with zero biases over a vocabulary of `vocab_size` tokens.
+    pub fn new(vocab_size: usize) -> Self {
+        TiedOutputHead { biases: Array2::zeros((1, vocab_size)) }
+    }
+
+    /// Performs a forward pass through the head.
+    ///
+    /// # Arguments
+    /// * `hidden` - Encoder outputs. Shape: [batch_size, d_model].
+    /// * `embeddings` - The tied embeddings; their matrix is [vocab_size, d_model].
+    ///
+    /// # Returns
+    /// * Logits over the vocabulary. Shape: [batch_size, vocab_size].
+    pub fn forward(&self, hidden: &Array2<f64>, embeddings: &Embeddings) -> Array2<f64> {
+        hidden.dot(&embeddings.token_embedding_matrix().t()) + &self.biases
+    }
+
+    /// Performs a backward pass through the head.
+    ///
+    /// # Arguments
+    /// * `hidden` - The input used in the forward pass. Shape: [batch_size, d_model].
+    /// * `grad_logits` - Gradient of the loss with respect to the logits. Shape: [batch_size, vocab_size].
+    /// * `embeddings` - The tied embeddings.
+    ///
+    /// # Returns
+    /// * Gradient with respect to `hidden`, gradient of the shared embedding matrix
+    ///   [vocab_size, d_model], and the bias gradients in the order of `parameters_mut`.
+    pub fn backward(&self, hidden: &Array2<f64>, grad_logits: &Array2<f64>, embeddings: &Embeddings) -> (Array2<f64>, Array2<f64>, Vec<f64>) {
+        let grad_input = grad_logits.dot(embeddings.token_embedding_matrix());
+        let grad_embeddings = grad_logits.t().dot(hidden);
+        let grad_biases = grad_logits.sum_axis(Axis(0)).to_vec();
+        (grad_input, grad_embeddings, grad_biases)
+    }
+
+    /// Number of values the head owns (the biases); the tied weights count towards `Embeddings`.
+    pub fn num_parameters(&self) -> usize {
+        self.biases.len()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut f64> {
+        self.biases.iter_mut().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cross_entropy::loss::Loss;
+    use ndarray::array;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_tied_head_gradients() {
+        let vocab = HashMap::from([("a".to_string(), 0), ("b".to_string(), 1), ("c".to_string(), 2)]);
+        let matrix = array![[0.1, -0.2], [0.4, 0.3], [-0.5, 0.2]];
+        let embeddings = Embeddings::from_matrix(matrix.clone(), vocab.clone());
+        let head = TiedOutputHead::new(3);
+        let hidden = array![[1.0, 0.5], [-0.3, 2.0]];
+        let labels = [2, 0];
+
+        let logits = head.forward(&hidden, &embeddings);
+        assert!((logits[[0, 1]] - (0.4 + 0.15)).abs() < 1e-12);
+        let (grad_hidden, grad_embeddings, grad_biases) = head.backward(&hidden, &Loss::gradients(&logits, &labels), &embeddings);
+        assert_eq!(grad_hidden.dim(), (2, 2));
+        assert_eq!(grad_biases.len(), head.num_parameters());
+
+        // Finite differences on the shared matrix.
+        let loss_with = |matrix: Array2<f64>| Loss::cross_entropy_loss(&head.forward(&hidden, &Embeddings::from_matrix(matrix, vocab.clone())), &labels);
+        let epsilon = 1e-6;
+        for ((row, column), &analytic) in grad_embeddings.indexed_iter() {
+            let (mut plus, mut minus) = (matrix.clone(), matrix.clone());
+            plus[[row, column]] += epsilon;
+            minus[[row, column]] -= epsilon;
+            let numeric = (loss_with(plus) - loss_with(minus)) / (2.0 * epsilon);
+            assert!((numeric - analytic).abs() < 1e-6, "({}, {}): {} vs {}", row, column, numeric, analytic);
+        }
+    }
+}
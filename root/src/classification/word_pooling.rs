@@ -0,0 +1,78 @@
+use ndarray::{Array2, ArrayView2, Axis};
+use serde::{Deserialize, Serialize};
+
+/// How the vectors of a word's sub-word tokens are combined into one word vector.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WordPooling {
+    /// Element-wise mean of the word's tokens.
+    Mean,
+    /// The word's first token, as BERT-style token classification does.
+    First,
+    /// Element-wise maximum of the word's tokens.
+    Max,
+}
+
+/// Aggregates per-token vectors, e.g. encoder outputs or token importances, back to words.
+///
+/// # Arguments
+/// * `token_vectors` - One row per token position. Shape: [seq_len, dim].
+/// * `word_ids` - The word of every position (`Tokenizer::word_ids`); `None` for padding and
+///   special tokens, which are skipped.
+/// * `pooling` - How the rows of a word are combined.
+///
+/// # Returns
+/// * The word indices present, in order of their first token, and one pooled row per word.
+///   Shape: [num_words, dim]. Words dropped entirely by truncation are absent.
+pub fn pool_words(token_vectors: ArrayView2<f64>, word_ids: &[Option<usize>], pooling: WordPooling) -> (Vec<usize>, Array2<f64>) {
+    assert_eq!(token_vectors.nrows(), word_ids.len(), "Every token position needs a word id.");
+
+    let mut words: Vec<usize> = Vec::new();
+    let mut positions: Vec<Vec<usize>> = Vec::new();
+    for (position, word) in word_ids.iter().enumerate() {
+        let Some(word) = *word else { continue };
+        match words.iter().position(|&seen| seen == word) {
+            Some(index) => positions[index].push(position),
+            None => {
+                words.push(word);
+                positions.push(vec![position]);
+            }
+        }
+    }
+
+    let mut pooled = Array2::zeros((words.len(), token_vectors.ncols()));
+    for (mut row, positions) in pooled.outer_iter_mut().zip(&positions) {
+        let rows = token_vectors.select(Axis(0), positions);
+        match pooling {
+            WordPooling::Mean => row.assign(&rows.mean_axis(Axis(0)).unwrap()),
+            WordPooling::First => row.assign(&rows.row(0)),
+            WordPooling::Max => row.assign(&rows.fold_axis(Axis(0), f64::NEG_INFINITY, |&max, &value| max.max(value))),
+        }
+    }
+    (words, pooled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_pool_words() {
+        // [CLS] new yo ##rk [PAD]: "york" is two pieces.
+        let tokens = array![[0.0, 0.0], [3.0, 0.0], [5.0, 1.0], [1.0, 3.0], [7.0, 7.0]];
+        let word_ids = [None, Some(0), Some(1), Some(1), None];
+
+        let (words, mean) = pool_words(tokens.view(), &word_ids, WordPooling::Mean);
+        assert_eq!(words, vec![0, 1]);
+        assert_eq!(mean, array![[3.0, 0.0], [3.0, 2.0]]);
+        let (_, first) = pool_words(tokens.view(), &word_ids, WordPooling::First);
+        assert_eq!(first, array![[3.0, 0.0], [5.0, 1.0]]);
+        let (_, max) = pool_words(tokens.view(), &word_ids, WordPooling::Max);
+        assert_eq!(max, array![[3.0, 0.0], [5.0, 3.0]]);
+
+        // Only words with a token remain.
+        let (words, pooled) = pool_words(tokens.view(), &[None, Some(0), None, Some(3), Some(3)], WordPooling::Mean);
+        assert_eq!(words, vec![0, 3]);
+        assert_eq!(pooled.row(1), array![4.0, 5.0]);
+    }
+}
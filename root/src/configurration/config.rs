@@ -1,14 +1,243 @@
+use crate::tokenization::normalization::{TextNormalizer, UnicodeForm};
+use crate::tokenization::tokenizer::Truncation;
+use crate::tokenization::ngrams::NGramRange;
+use crate::tokenization::phrases::PhraseDetector;
+use crate::numerics::{Dtype, EpsilonPlacement};
+use crate::model_inference::inference::OverflowPolicy;
+use crate::classification::word_pooling::WordPooling;
+use crate::model_evaluator::promotion::PromotionGate;
+use crate::model_inference::request_limits::RequestLimits;
+use crate::model_evaluator::score_calibration::CalibrationMethod;
+use crate::logging::logger::LogFormat;
+use crate::data_handler::sliding_window::SlidingWindow;
+use crate::augmentation::noise::NoiseAugmentation;
+use crate::data_handler::masking::WordDropout;
+use crate::training::trainer::ContrastivePretraining;
+use crate::export::ann_index::HnswParams;
+use crate::classification::ClassReduction;
+use crate::positional_encoding::SinusoidalVariant;
+use crate::quantization::quantizer::Granularity;
+
+/// Datasets and model files of the default pipeline. They are resolved against the crate root
+/// at compile time, so the commands work from any working directory.
+pub const TRAIN_DATASET_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/train_dataset.json");
+pub const VALIDATION_DATASET_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/validation_dataset.json");
+pub const TEST_DATASET_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/test_dataset.json");
+/// Checkpoint `pretrain` adapts by default.
+pub const TRAINED_MODEL_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/trained_model.json");
 pub const MAX_SEQ_LENGTH: usize = 128; 
+/// Which tokens of longer texts are kept: `Head`, `Tail` or `HeadAndTail { head_tokens, separator }`.
+pub const TRUNCATION: Truncation = Truncation::Head;
+/// What inference does with texts longer than `MAX_SEQ_LENGTH`: `Truncate`, `ChunkAndAggregate` or `Error`.
+pub const INFERENCE_OVERFLOW_POLICY: OverflowPolicy = OverflowPolicy::Truncate;
+/// Dummy forward passes (at `BATCH_SIZE` x `MAX_SEQ_LENGTH`) run after loading a model for inference; 0 skips the warm-up.
+pub const INFERENCE_WARMUP_PASSES: usize = 0;
+/// Serves the encoder feed-forward networks in int8 with weight scales per `Tensor`, `Row` or
+/// `Column`, calibrated on `QUANTIZATION_CALIBRATION_DATASET`; `None` serves in full precision.
+pub const INFERENCE_QUANTIZATION: Option<Granularity> = None;
+/// Classifies run predictions by cosine similarity to class centroids fitted on the training set
+/// (nearest-centroid mode) instead of with the classification head.
+pub const PROTOTYPE_INFERENCE: bool = false;
+/// Dataset whose first `QUANTIZATION_CALIBRATION_SAMPLES` examples choose the activation ranges.
+pub const QUANTIZATION_CALIBRATION_DATASET: &str = VALIDATION_DATASET_PATH;
+pub const QUANTIZATION_CALIBRATION_SAMPLES: usize = 256;
+/// Renders multi-field training records into one model input, e.g. `Some("{title} [SEP] {body}")`;
+/// `None` reads the `text` field. Saved in the run config and reused at serve time.
+pub const INPUT_TEMPLATE: Option<&str> = None;
+/// JSON config file whose `data_schema` section names the dataset fields (see `DataSchema`);
+/// `None` reads the `text` and `label` fields. `INPUT_TEMPLATE` takes precedence over the file's template.
+pub const DATA_SCHEMA_PATH: Option<&str> = None;
+/// JSON cost matrix (see `CostMatrix::from_file`) with which `predict` and `serve` pick the class
+/// with the lowest expected cost, and `predict` abstains when that is cheaper; `None` takes the argmax.
+pub const COST_MATRIX_PATH: Option<&str> = None;
+/// Most probable classes listed in the `top_k` of every prediction.
+pub const PREDICTION_TOP_K: usize = 3;
+/// How sub-word pieces are combined into words for explanations and `word-embeddings`: `Mean`, `First` or `Max`.
+pub const WORD_POOLING: WordPooling = WordPooling::Mean;
+/// Held-out dataset `promote` evaluates a checkpoint on before it may replace the served model.
+pub const PROMOTION_GATE_DATASET: &str = TEST_DATASET_PATH;
+/// Directory `promote` installs `model.json` and `tokenizer.json` into.
+pub const SERVING_DIR: &str = "serving";
+/// Address `cargo run -- serve` listens on.
+pub const SERVER_ADDRESS: &str = "127.0.0.1:8080";
+/// Longest text and most texts per request the server accepts.
+pub const SERVER_REQUEST_LIMITS: RequestLimits = RequestLimits { max_input_chars: 10_000, max_batch_size: 32 };
+/// Requests per second and burst allowed per client address; `None` disables rate limiting.
+pub const SERVER_RATE_LIMIT: Option<(f64, usize)> = Some((10.0, 20));
+/// File of `<client> <key>` lines the server requires an API key from (`ApiKeyAuth::from_file`);
+/// `None` serves without authentication.
+pub const SERVER_API_KEYS_PATH: Option<&str> = None;
+/// PEM certificate chain and private key files the server terminates TLS with; `None` serves plain HTTP.
+pub const SERVER_TLS: Option<(&str, &str)> = None;
+/// Metadata fields (e.g. language or source) whose values the evaluation reports metrics for,
+/// one slice report per field; the loader reads them as metadata.
+pub const EVALUATION_SLICE_FIELDS: &[&str] = &[];
+/// Protected-group metadata field and positive label the evaluation reports group fairness for,
+/// e.g. `Some(("language", "spam"))`; the loader reads the field as metadata. `None` skips it.
+pub const FAIRNESS_GROUP: Option<(&str, &str)> = None;
+/// Character probability at which the evaluation re-scores the dataset under every noise
+/// operation (typos, OCR errors), e.g. `Some(0.05)`; `None` skips the noise report.
+pub const EVALUATION_NOISE_PROBABILITY: Option<f64> = None;
+/// Metrics a checkpoint needs on `PROMOTION_GATE_DATASET` to be promoted.
+pub const PROMOTION_GATE: PromotionGate = PromotionGate { min_accuracy: 0.7, min_f1_score: 0.7, max_f1_drop: Some(0.01) };
+/// Fits a score calibrator for a promoted model on `SCORE_CALIBRATION_DATASET` and installs it with
+/// the model, so served probabilities stay comparable across versions: `Some(Platt)`, `Some(Isotonic)` or `None`.
+pub const SCORE_CALIBRATION: Option<CalibrationMethod> = None;
+/// Held-out dataset the score calibrator is fitted on.
+pub const SCORE_CALIBRATION_DATASET: &str = VALIDATION_DATASET_PATH;
 pub const BATCH_SIZE: usize = 32;     
+/// Replaces `BATCH_SIZE` in new runs with the largest batch size within the budget below (see `batch_size_tuner.rs`).
+pub const AUTO_TUNE_BATCH_SIZE: bool = false;
+/// Longest acceptable forward and backward pass of one batch when tuning the batch size.
+pub const BATCH_SIZE_TUNER_MAX_STEP_MS: u64 = 2000;
+/// Largest acceptable memory growth of one step when tuning the batch size; `None` ignores memory.
+pub const BATCH_SIZE_TUNER_MAX_STEP_MB: Option<usize> = None;
+/// Largest batch size the tuner probes.
+pub const BATCH_SIZE_TUNER_MAX: usize = 256;
+/// Resets the process's peak memory (Linux `/proc/self/clear_refs`) before every probe, so a
+/// step's memory is its peak growth. When `false`, or where the reset is unsupported, memory
+/// is the resident growth measured after the step.
+pub const BATCH_SIZE_TUNER_RESET_PEAK_MEMORY: bool = true;
+/// `Text` prints the usual messages; `Json` writes pipeline and training events as one JSON object per
+/// line (timestamp, level, module, message, step, metrics). `--log-format json|text` overrides it.
+pub const LOG_FORMAT: LogFormat = LogFormat::Text;
+/// JSON array of hand-picked `{ "text", "label", "note" }` examples predicted after every epoch; `None` disables it.
+pub const PROBE_SET_PATH: Option<&str> = None;
+/// Threads that tokenize the dataset while loading; 1 disables the worker pool, 0 uses one per core.
+pub const DATA_LOADER_WORKERS: usize = 4;
+/// Cores the data loader workers are pinned to (Linux only), round-robin; empty leaves them to the OS.
+pub const DATA_LOADER_CORES: &[usize] = &[];
+/// Trains on overlapping windows of long documents instead of their truncation and evaluates on the
+/// weighted mean of the window predictions, e.g. `Some(SlidingWindow { stride: 64, weighting: WindowWeighting::NewTokens })`.
+pub const SLIDING_WINDOW: Option<SlidingWindow> = None;
+pub const MAX_VOCAB_SIZE: usize = 100;
+/// Words seen fewer times than this in the training set are left out of the vocabulary.
+pub const MIN_TOKEN_FREQUENCY: usize = 1;
+/// Distinct words counted at once by `build-vocab` on a streamed corpus; rarer words are pruned beyond this.
+pub const VOCAB_BUILDER_MAX_WORDS: usize = 1_000_000;
+/// Tokenizer file written by `build-vocab` or `import-tokenizer` that new runs use instead of a
+/// vocabulary built from the training set; `None` builds one with the settings below.
+pub const TOKENIZER_PATH: Option<&str> = None;
+/// Adds 256 byte tokens on top of `MAX_VOCAB_SIZE` so unknown words are spelled out in bytes instead of `[UNK]`.
+pub const BYTE_FALLBACK: bool = true;
+/// Word n-gram lengths added to the vocabulary and looked up while tokenizing, e.g. `NGramRange { min: 1, max: 3 }`.
+pub const NGRAMS: NGramRange = NGramRange::UNIGRAMS;
+/// Named regex patterns whose matches are kept as single tokens before word splitting, e.g.
+/// `&[("hashtag", r"#\w+"), ("sku", r"\b[A-Z]{2,}-\d+\b")]`; see `token_rules.rs` for presets.
+pub const TOKEN_RULES: &[(&str, &str)] = &[];
+/// Merges frequent word pairs into single vocabulary tokens (`new_york`), e.g.
+/// `Some(PhraseDetector { min_count: 5, scoring: PhraseScoring::Npmi(0.5) })`; `None` disables it.
+pub const PHRASES: Option<PhraseDetector> = None;
+/// Task names and their prefix tokens, e.g. `&[("sentiment", "[TASK1]"), ("topic", "[TASK2]")]`,
+/// registered with new run tokenizers so one encoder can condition on the task (`Tokenizer::for_task`).
+pub const TASK_PREFIXES: &[(&str, &str)] = &[];
+/// Task of `TASK_PREFIXES` whose prefix token training, evaluation and inference put in front
+/// of every text (`Tokenizer::for_task`); `None` adds no prefix.
+pub const ACTIVE_TASK: Option<&str> = None;
+/// Unicode normalization, accent folding, punctuation handling and CJK splitting of the word-level tokenizer.
+pub const TEXT_NORMALIZER: TextNormalizer = TextNormalizer {
+    unicode_form: UnicodeForm::None,
+    fold_accents: false,
+    keep_punctuation: false,
+    split_cjk: false,
+};
 
 
 pub const PAD_TOKEN: &str = "[PAD]";
 pub const UNK_TOKEN: &str = "[UNK]";
 pub const CLS_TOKEN: &str = "[CLS]";
 pub const SEP_TOKEN: &str = "[SEP]";
+pub const MASK_TOKEN: &str = "[MASK]";
+pub const MLM_MASK_PROBABILITY: f64 = 0.15;
+/// Ties the MLM output layer to the token embedding matrix, so it only adds `vocab_size`
+/// biases and its gradient trains the embeddings.
+pub const TIE_MLM_OUTPUT_WEIGHTS: bool = true;
+/// Sentence-order prediction epochs that `pretrain` runs on its corpus after MLM; consecutive
+/// sentences of every text are paired and half of the pairs swapped. `None` skips the stage.
+pub const SENTENCE_ORDER_PRETRAINING_EPOCHS: Option<usize> = None;
 
 
 pub const LEARNING_RATE: f64 = 0.001; 
 pub const BETA1: f64 = 0.9;           
 pub const BETA2: f64 = 0.999;        
-pub const EPSILON: f64 = 1e-8;       
+/// Format the stability constants are chosen for; lower precisions raise them above their smallest normal value.
+pub const NUMERIC_DTYPE: Dtype = Dtype::F64;
+pub const EPSILON: f64 = NUMERIC_DTYPE.adam_epsilon();
+/// `OutsideSqrt` divides by `sqrt(v) + EPSILON` (Adam paper), `InsideSqrt` by `sqrt(v + EPSILON)`.
+pub const ADAM_EPSILON_PLACEMENT: EpsilonPlacement = EpsilonPlacement::OutsideSqrt;
+/// Multiplies token embeddings of new models by `sqrt(d_model)` before adding positional encodings.
+pub const SCALE_EMBEDDINGS_BY_SQRT_D_MODEL: bool = true;
+/// Sinusoidal positional encodings of new models.
+pub const POSITIONAL_ENCODING_VARIANT: SinusoidalVariant = SinusoidalVariant::Standard;
+/// Epsilon added to the variance in layer normalization of new models.
+pub const LAYER_NORM_EPSILON: f64 = NUMERIC_DTYPE.layer_norm_epsilon();
+/// Threads for the per-sequence forward/backward loops of a batch; 0 uses one per core.
+pub const TRAINING_THREADS: usize = 1;
+/// Cores the training threads are pinned to (Linux only), round-robin; empty leaves them to the OS.
+pub const TRAINING_CORES: &[usize] = &[];
+/// Sum gradients in a fixed order so multi-threaded training is bit-reproducible.
+pub const DETERMINISTIC_REDUCTION: bool = true;       
+/// Use compensated (Kahan) summation for the loss, layer norm statistics and gradient sums.
+pub const COMPENSATED_SUMMATION: bool = false;
+/// Leaves the token embedding matrix unchanged during training, e.g. when fine-tuning on pretrained vectors.
+pub const FREEZE_EMBEDDINGS: bool = false;
+/// Gives new models the BERT-style embedding block: learned segment embeddings plus a layer
+/// norm over the summed embeddings (`TransformerConfig::bert_embeddings`).
+pub const BERT_EMBEDDINGS: bool = false;
+/// Gives the self-attention of new models learned relative-position representations for
+/// distances up to this value (`TransformerConfig::relative_positions`); `None` disables them.
+pub const RELATIVE_POSITIONS: Option<usize> = None;
+/// Gives the self-attention of new models learned query, key, value and output projections
+/// (`TransformerConfig::attention_projections`). Models saved without them keep attending
+/// over their raw layer input.
+pub const ATTENTION_PROJECTIONS: bool = true;
+/// Dropout rate on the embedding block output while training; 0 disables it.
+pub const EMBEDDING_DROPOUT: f64 = 0.0;
+/// Scales the initial token embeddings of new models by their frequency in the training set,
+/// down to this factor for the rarest tokens (see `Embeddings::scale_by_frequency`), e.g.
+/// `Some(0.1)`; `None` keeps the uniform initialization.
+pub const EMBEDDING_FREQUENCY_SCALING: Option<f64> = None;
+/// Adds noisy copies (typos, OCR errors) of every training example, e.g. `Some(NoiseAugmentation { noise: TextNoise {
+/// char_probability: 0.05, operations: ALL_NOISE_OPERATIONS }, copies: 1, seed: 0 })`; `None` trains on the data as is.
+pub const TEXT_NOISE_AUGMENTATION: Option<NoiseAugmentation> = None;
+/// External paraphrase (e.g. back-translation) program and its arguments, e.g.
+/// `Some(&["python3", "backtranslate.py"])`. It reads a text on stdin and writes up to
+/// `$PARAPHRASE_COUNT` paraphrases to stdout, one per line. `None` disables it.
+pub const PARAPHRASE_COMMAND: Option<&[&str]> = None;
+/// Paraphrases added per training example by `PARAPHRASE_COMMAND`.
+pub const PARAPHRASE_COPIES: usize = 1;
+/// JSON file caching the paraphrases of `PARAPHRASE_COMMAND` across runs.
+pub const PARAPHRASE_CACHE_PATH: &str = "paraphrase_cache.json";
+/// Replaces (`WordDropoutMode::Unk`) or removes (`WordDropoutMode::Drop`) random tokens of the
+/// training batches, e.g. `Some(WordDropout { probability: 0.1, mode: WordDropoutMode::Unk })`.
+/// Evaluation and inference are never affected; `None` disables it.
+pub const WORD_DROPOUT: Option<WordDropout> = None;
+/// Supervised contrastive stage run on the training set before fine-tuning a new model, e.g.
+/// `Some(ContrastivePretraining { epochs: 3, min_per_class: 2, temperature: 0.1 })`; `BATCH_SIZE`
+/// must hold `min_per_class` examples of every class. `None` skips it.
+pub const CONTRASTIVE_PRETRAINING: Option<ContrastivePretraining> = None;
+/// Metadata field naming the domain (e.g. source) of every training example; when set, a domain
+/// classifier is trained adversarially so the encoder learns domain-invariant features.
+pub const DOMAIN_FIELD: Option<&str> = None;
+/// Gradient reversal strength of the domain classifier at the end of training.
+pub const DOMAIN_ADVERSARIAL_WEIGHT: f64 = 0.1;
+/// Decay of the exponential moving average of the weights kept during training and saved as
+/// `model.ema.json`, e.g. `Some(0.999)`; `None` keeps no EMA.
+pub const EMA_DECAY: Option<f64> = None;
+/// First epoch (1-based) averaged into the stochastic weight average saved as `model.swa.json`;
+/// `None` disables SWA.
+pub const SWA_START_EPOCH: Option<usize> = None;
+/// Seed of the random draws of training (word dropout, embedding dropout masks), saved in
+/// the run config so resumed runs draw the same; `None` picks a random seed per new run.
+pub const TRAINING_SEED: Option<u64> = None;
+/// Neighbours listed per query by `neighbors`, and compared per example for the label-disagreement report.
+pub const NEIGHBOR_COUNT: usize = 5;
+/// Cosine similarity of pooled embeddings from which `neighbors` reports two examples as near-duplicates.
+pub const NEAR_DUPLICATE_SIMILARITY: f64 = 0.98;
+/// HNSW settings of the semantic search index written by `export-index`: links per node, candidates
+/// while building and per query, and the seed of the layer assignment.
+pub const ANN_INDEX_PARAMS: HnswParams = HnswParams { m: 16, ef_construction: 100, ef_search: 50, seed: 42 };
+/// Results returned by `search` when no count is given.
+pub const SEARCH_RESULTS: usize = 10;
+/// How `remap-classes merge` combines the classification head columns of merged classes: `Mean` or `Sum`.
+pub const CLASS_MERGE_REDUCTION: ClassReduction = ClassReduction::Mean;
@@ -0,0 +1,135 @@
+use crate::data_handler::input_template::InputTemplate;
+use serde::{Serialize, Deserialize};
+use std::error::Error;
+use std::fs;
+
+/// Describes how dataset records map to model inputs.
+///
+/// Loaded from the `data_schema` section of a JSON config file, e.g.:
+/// ```json
+/// {
+///   "data_schema": {
+///     "text_fields": ["title", "body"],
+///     "label_field": "category",
+///     "id_field": "id",
+///     "text_separator": " ",
+///     "metadata_fields": ["language", "source"],
+///     "csv_columns": "by_header"
+///   }
+/// }
+/// ```
+/// Missing keys fall back to the defaults (`text` / `label`, no id field, positional CSV
+/// columns). With an `input_template` such as `"{title} [SEP] {body}"`, the template's fields
+/// are read instead of `text_fields` and rendered with it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DataSchema {
+    /// Fields concatenated, in order, to form the input text.
+    pub text_fields: Vec<String>,
+    /// Field holding the numeric class label.
+    pub label_field: String,
+    /// Optional field holding a per-example identifier.
+    pub id_field: Option<String>,
+    /// Separator inserted between concatenated text fields.
+    pub text_separator: String,
+    /// Fields carried through as string metadata, e.g. for per-slice evaluation.
+    pub metadata_fields: Vec<String>,
+    /// Renders the text fields into the input text instead of joining them with `text_separator`.
+    pub input_template: Option<InputTemplate>,
+    /// How the text and label columns of a CSV file are found.
+    pub csv_columns: CsvColumns,
+}
+
+/// How `DataLoader` finds the text and label columns of a CSV file. The first row is always
+/// a header; id and metadata columns are found by their header name either way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvColumns {
+    /// The input fields are the first columns, in order, and the label is the next one;
+    /// the header's names are ignored.
+    #[default]
+    Positional,
+    /// Columns are matched by the header names of the input and label fields.
+    ByHeader,
+}
+
+impl Default for DataSchema {
+    fn default() -> Self {
+        DataSchema {
+            text_fields: vec!["text".to_string()],
+            label_field: "label".to_string(),
+            id_field: None,
+            text_separator: " ".to_string(),
+            metadata_fields: Vec::new(),
+            input_template: None,
+            csv_columns: CsvColumns::Positional,
+        }
+    }
+}
+
+impl DataSchema {
+    /// Fields that make up the input text: those of the template when there is one,
+    /// `text_fields` otherwise.
+    pub fn input_fields(&self) -> Vec<String> {
+        match &self.input_template {
+            Some(template) => template.fields().into_iter().map(str::to_string).collect(),
+            None => self.text_fields.clone(),
+        }
+    }
+
+    /// Builds the input text from the values of `input_fields`, in order.
+    pub fn render(&self, parts: &[&str]) -> Result<String, Box<dyn Error>> {
+        match &self.input_template {
+            Some(template) => {
+                let fields = template.fields();
+                template.render_with(|field| fields.iter().position(|&name| name == field).and_then(|i| parts.get(i).copied()))
+            }
+            None => Ok(parts.join(&self.text_separator)),
+        }
+    }
+
+    /// Reads the `data_schema` section of a JSON config file.
+    /// Returns the default schema when the section is absent.
+    pub fn from_config_file(config_path: &str) -> Result<Self, Box<dyn Error>> {
+        let config: serde_json::Value = serde_json::from_str(&fs::read_to_string(config_path)?)?;
+        match config.get("data_schema") {
+            Some(section) => {
+                let schema: DataSchema = serde_json::from_value(section.clone())?;
+                if schema.text_fields.is_empty() {
+                    return Err("data_schema.text_fields must name at least one field".into());
+                }
+                Ok(schema)
+            }
+            None => Ok(DataSchema::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_defaults_for_missing_keys() {
+        let schema: DataSchema = serde_json::from_str(r#"{ "text_fields": ["title", "body"] }"#).unwrap();
+
+        assert_eq!(schema.text_fields, vec!["title", "body"]);
+        assert_eq!(schema.label_field, "label");
+        assert_eq!(schema.id_field, None);
+        assert_eq!(schema.text_separator, " ");
+        assert!(schema.metadata_fields.is_empty());
+        assert_eq!(schema.input_template, None);
+        assert_eq!(schema.csv_columns, CsvColumns::Positional);
+
+        let schema: DataSchema = serde_json::from_str(r#"{ "csv_columns": "by_header" }"#).unwrap();
+        assert_eq!(schema.csv_columns, CsvColumns::ByHeader);
+    }
+
+    #[test]
+    fn test_schema_with_template() {
+        let schema: DataSchema = serde_json::from_str(r#"{ "input_template": "{title} [SEP] {body}" }"#).unwrap();
+        assert_eq!(schema.input_fields(), vec!["title", "body"]);
+        assert_eq!(schema.render(&["Late", "Still waiting"]).unwrap(), "Late [SEP] Still waiting");
+        assert_eq!(DataSchema::default().render(&["a", "b"]).unwrap(), "a b");
+    }
+}
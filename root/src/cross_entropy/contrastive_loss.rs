@@ -0,0 +1,162 @@
+use ndarray::{Array1, Array2, Axis};
+
+use crate::configurration::config::NUMERIC_DTYPE;
+use crate::numerics::softmax_inplace;
+
+/// Supervised contrastive (SupCon) loss over pooled sentence embeddings.
+///
+/// Purpose:
+/// - Pulls together embeddings of examples sharing a label and pushes apart the others.
+/// - Used as a pretraining stage before cross-entropy fine-tuning.
+///
+/// For L2-normalized embeddings `z` and temperature `τ`, with `P(i)` the other
+/// examples sharing the label of anchor `i`:
+/// `L_i = -1/|P(i)| Σ_{p∈P(i)} log( exp(z_i·z_p/τ) / Σ_{a≠i} exp(z_i·z_a/τ) )`.
+/// The loss is averaged over anchors that have at least one positive.
+pub struct ContrastiveLoss;
+
+impl ContrastiveLoss {
+    fn normalize(embeddings: &Array2<f64>) -> (Array2<f64>, Array1<f64>) {
+        let norms = embeddings.map_axis(Axis(1), |row| row.dot(&row).sqrt().max(NUMERIC_DTYPE.norm_floor()));
+        let normalized = embeddings / &norms.view().insert_axis(Axis(1));
+        (normalized, norms)
+    }
+
+    /// Per-anchor softmax over all other examples of the scaled similarities.
+    fn similarity_probabilities(normalized: &Array2<f64>, temperature: f64) -> Array2<f64> {
+        let mut probabilities = normalized.dot(&normalized.t()) / temperature;
+
+        for (i, mut row) in probabilities.outer_iter_mut().enumerate() {
+            row[i] = f64::NEG_INFINITY;
+            softmax_inplace(row);
+        }
+
+        probabilities
+    }
+
+    fn positives(labels: &[usize], anchor: usize) -> usize {
+        labels
+            .iter()
+            .enumerate()
+            .filter(|&(j, &label)| j != anchor && label == labels[anchor])
+            .count()
+    }
+
+    /// Computes the supervised contrastive loss.
+    ///
+    /// # Arguments
+    /// * `embeddings` - Pooled embeddings. Shape: [batch_size, d_model].
+    /// * `labels` - Class label of every embedding. Shape: [batch_size].
+    /// * `temperature` - Softmax temperature `τ`.
+    ///
+    /// # Returns
+    /// * The loss averaged over anchors with at least one positive (0 if there are none).
+    pub fn supervised_contrastive_loss(embeddings: &Array2<f64>, labels: &[usize], temperature: f64) -> f64 {
+        assert_eq!(embeddings.nrows(), labels.len(), "Embeddings and labels batch sizes must match.");
+
+        let (normalized, _) = Self::normalize(embeddings);
+        let probabilities = Self::similarity_probabilities(&normalized, temperature);
+
+        let mut total_loss = 0.0;
+        let mut anchors = 0;
+        for i in 0..labels.len() {
+            let num_positives = Self::positives(labels, i);
+            if num_positives == 0 {
+                continue;
+            }
+            let log_likelihood: f64 = (0..labels.len())
+                .filter(|&j| j != i && labels[j] == labels[i])
+                .map(|j| probabilities[(i, j)].ln())
+                .sum();
+            total_loss -= log_likelihood / num_positives as f64;
+            anchors += 1;
+        }
+
+        if anchors == 0 {
+            0.0
+        } else {
+            total_loss / anchors as f64
+        }
+    }
+
+    /// Computes gradients of the supervised contrastive loss with respect to the embeddings.
+    ///
+    /// # Returns
+    /// * A 2D array of gradients. Shape: [batch_size, d_model].
+    pub fn supervised_contrastive_gradients(embeddings: &Array2<f64>, labels: &[usize], temperature: f64) -> Array2<f64> {
+        let (normalized, norms) = Self::normalize(embeddings);
+        let probabilities = Self::similarity_probabilities(&normalized, temperature);
+
+        // Gradient with respect to the scaled similarities s_ia = z_i·z_a / τ.
+        let mut grad_similarities = Array2::zeros(probabilities.raw_dim());
+        let mut anchors = 0;
+        for i in 0..labels.len() {
+            let num_positives = Self::positives(labels, i);
+            if num_positives == 0 {
+                continue;
+            }
+            anchors += 1;
+            for a in 0..labels.len() {
+                if a == i {
+                    continue;
+                }
+                let positive = if labels[a] == labels[i] { 1.0 / num_positives as f64 } else { 0.0 };
+                grad_similarities[(i, a)] = probabilities[(i, a)] - positive;
+            }
+        }
+        if anchors == 0 {
+            return Array2::zeros(embeddings.raw_dim());
+        }
+        grad_similarities /= anchors as f64 * temperature;
+
+        let grad_normalized = grad_similarities.dot(&normalized) + grad_similarities.t().dot(&normalized);
+
+        // Backward through the L2 normalization z = u / ||u||.
+        let mut gradients = grad_normalized.clone();
+        for ((mut grad, z), &norm) in gradients.outer_iter_mut().zip(normalized.outer_iter()).zip(norms.iter()) {
+            let projection = grad.dot(&z);
+            grad.zip_mut_with(&z, |g, &zi| *g = (*g - zi * projection) / norm);
+        }
+
+        gradients
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grad_check::gradient_checker::{compare_gradients, numerical_input_gradient};
+    use ndarray::array;
+
+    #[test]
+    fn test_loss_prefers_clustered_embeddings() {
+        let labels = vec![0, 0, 1, 1];
+        let clustered = array![[1.0, 0.1], [1.0, -0.1], [-0.1, 1.0], [0.1, 1.0]];
+        let mixed = array![[1.0, 0.1], [-0.1, 1.0], [1.0, -0.1], [0.1, 1.0]];
+
+        let clustered_loss = ContrastiveLoss::supervised_contrastive_loss(&clustered, &labels, 0.5);
+        let mixed_loss = ContrastiveLoss::supervised_contrastive_loss(&mixed, &labels, 0.5);
+
+        assert!(clustered_loss < mixed_loss);
+    }
+
+    #[test]
+    fn test_gradients_match_finite_differences() {
+        let labels = vec![0, 1, 0, 1, 2];
+        let embeddings = array![
+            [0.3, -1.2, 0.5],
+            [1.1, 0.4, -0.7],
+            [-0.6, 0.9, 0.2],
+            [0.8, 0.1, 1.3],
+            [-0.2, -0.5, 0.9],
+        ];
+
+        let analytic = ContrastiveLoss::supervised_contrastive_gradients(&embeddings, &labels, 0.3);
+        let numerical = numerical_input_gradient(&embeddings, |e| {
+            ContrastiveLoss::supervised_contrastive_loss(e, &labels, 0.3)
+        });
+
+        let result = compare_gradients("supcon", analytic.as_slice().unwrap(), numerical.as_slice().unwrap());
+        assert!(result.passed, "relative error {}", result.max_relative_error);
+    }
+}
@@ -1,6 +1,7 @@
-use ndarray::{Array2, Axis};
-use ndarray::prelude::*;
+use ndarray::Array2;
 use std::f64;
+use crate::summation::Summation;
+use crate::numerics::{softmax_inplace, Float};
 
 /// Module for calculating loss functions, specifically Cross-Entropy Loss.
 ///
@@ -15,7 +16,6 @@ use std::f64;
 ///
 /// Output:
 /// - Scalar loss value (f64), averaged over the batch.
-
 pub struct Loss;
 
 impl Loss {
@@ -26,15 +26,11 @@ impl Loss {
     ///
     /// # Returns
     /// * A 2D array of probabilities. Shape: [batch_size, num_classes].
-    pub fn softmax(logits: &Array2<f64>) -> Array2<f64> {
+    pub fn softmax<A: Float>(logits: &Array2<A>) -> Array2<A> {
         let mut probabilities = logits.clone();
 
-        for mut row in probabilities.outer_iter_mut() {
-            let max_logit = row.iter().cloned().fold(f64::MIN, f64::max); // For numerical stability
-
-            let exp_sum: f64 = row.iter().map(|logit| (logit - max_logit).exp()).sum();
-
-            row.mapv_inplace(|logit| (logit - max_logit).exp() / exp_sum);
+        for row in probabilities.outer_iter_mut() {
+            softmax_inplace(row);
         }
 
         probabilities
@@ -47,23 +43,27 @@ impl Loss {
     /// * `labels` - A vector of ground truth labels. Shape: [batch_size].
     ///
     /// # Returns
-    /// * A scalar loss value averaged over the batch.
-    pub fn cross_entropy_loss(logits: &Array2<f64>, labels: &[usize]) -> f64 {
+    /// * A scalar loss value averaged over the batch, in f64 for any element type.
+    pub fn cross_entropy_loss<A: Float>(logits: &Array2<A>, labels: &[usize]) -> f64 {
+        Self::cross_entropy_loss_with(logits, labels, Summation::Naive)
+    }
+
+    /// Same as `cross_entropy_loss`, adding up the per-sample losses with `summation`.
+    pub fn cross_entropy_loss_with<A: Float>(logits: &Array2<A>, labels: &[usize], summation: Summation) -> f64 {
         assert_eq!(logits.nrows(), labels.len(), "Logits and labels batch sizes must match.");
 
         let probabilities = Self::softmax(logits);
 
-        let mut total_loss = 0.0;
-        for (i, &label) in labels.iter().enumerate() {
+        let sample_losses = labels.iter().enumerate().map(|(i, &label)| {
             assert!(
                 label < probabilities.ncols(),
                 "Label index out of bounds for logits."
             );
-         
-            total_loss -= probabilities[(i, label)].ln();
-        }
 
-        total_loss / labels.len() as f64 // Return average loss
+            -probabilities[(i, label)].ln().as_f64()
+        });
+
+        summation.mean(sample_losses) // Return average loss
     }
 
     /// Computes gradients of the cross-entropy loss with respect to logits.
@@ -74,16 +74,16 @@ impl Loss {
     ///
     /// # Returns
     /// * A 2D array of gradients. Shape: [batch_size, num_classes].
-    pub fn gradients(logits: &Array2<f64>, labels: &[usize]) -> Array2<f64> {
+    pub fn gradients<A: Float>(logits: &Array2<A>, labels: &[usize]) -> Array2<A> {
         let probabilities = Self::softmax(logits);
 
         let mut gradients = probabilities;
 
         for (i, &label) in labels.iter().enumerate() {
-            gradients[(i, label)] -= 1.0; 
+            gradients[(i, label)] -= A::one();
         }
 
-        gradients / labels.len() as f64
+        gradients / A::cast(labels.len() as f64)
     }
 }
 
@@ -94,7 +94,7 @@ mod tests {
 
     #[test]
     fn test_softmax() {
-        let logits = array![
+        let logits: Array2<f64> = array![
             [1.0, 2.0, 3.0],
             [1.0, 1.0, 1.0],
         ];
@@ -110,7 +110,7 @@ mod tests {
 
     #[test]
     fn test_cross_entropy_loss() {
-        let logits = array![
+        let logits: Array2<f64> = array![
             [1.0, 2.0, 3.0],
             [1.0, 1.0, 1.0],
         ];
@@ -122,7 +122,7 @@ mod tests {
 
     #[test]
     fn test_gradients() {
-        let logits = array![
+        let logits: Array2<f64> = array![
             [1.0, 2.0, 3.0],
             [1.0, 1.0, 1.0],
         ];
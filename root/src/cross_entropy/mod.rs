@@ -1 +1,2 @@
-pub mod loss;
\ No newline at end of file
+pub mod loss;
+pub mod contrastive_loss;
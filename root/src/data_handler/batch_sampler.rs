@@ -0,0 +1,134 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::error::Error;
+
+/// Batch sampler guaranteeing at least `min_per_class` examples of every class in each batch.
+///
+/// Every example appears at least once per pass. Classes with too few examples to
+/// fill their quota are oversampled, which also helps on very imbalanced data.
+pub struct StratifiedBatchSampler {
+    pub batch_size: usize,
+    pub min_per_class: usize,
+}
+
+impl StratifiedBatchSampler {
+    pub fn new(batch_size: usize, min_per_class: usize) -> Self {
+        StratifiedBatchSampler { batch_size, min_per_class }
+    }
+
+    /// Groups example indices into stratified batches.
+    ///
+    /// # Arguments
+    /// * `labels` - Class label of every example.
+    /// * `rng` - Random number generator used for shuffling.
+    ///
+    /// # Returns
+    /// * Batches of example indices, or an error if a batch cannot hold `min_per_class`
+    ///   examples of every class.
+    pub fn sample<R: Rng>(&self, labels: &[usize], rng: &mut R) -> Result<Vec<Vec<usize>>, Box<dyn Error>> {
+        if labels.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let num_classes = labels.iter().max().unwrap() + 1;
+        let mut class_indices: Vec<Vec<usize>> = vec![Vec::new(); num_classes];
+        for (index, &label) in labels.iter().enumerate() {
+            class_indices[label].push(index);
+        }
+        class_indices.retain(|indices| !indices.is_empty());
+
+        if self.batch_size < self.min_per_class * class_indices.len() {
+            return Err(format!(
+                "Batch size {} cannot hold {} examples for each of {} classes.",
+                self.batch_size,
+                self.min_per_class,
+                class_indices.len()
+            )
+            .into());
+        }
+
+        for indices in class_indices.iter_mut() {
+            indices.shuffle(rng);
+        }
+        let mut class_cursors = vec![0; class_indices.len()];
+
+        let mut pool: Vec<usize> = (0..labels.len()).collect();
+        pool.shuffle(rng);
+        let mut used = vec![false; labels.len()];
+        let mut remaining = labels.len();
+        let mut pool_cursor = 0;
+
+        let mut batches = Vec::new();
+        while remaining > 0 {
+            let mut batch = Vec::with_capacity(self.batch_size);
+
+            for (indices, cursor) in class_indices.iter_mut().zip(class_cursors.iter_mut()) {
+                for _ in 0..self.min_per_class {
+                    if *cursor == indices.len() {
+                        indices.shuffle(rng);
+                        *cursor = 0;
+                    }
+                    let index = indices[*cursor];
+                    *cursor += 1;
+
+                    if !used[index] {
+                        used[index] = true;
+                        remaining -= 1;
+                    }
+                    batch.push(index);
+                }
+            }
+
+            while batch.len() < self.batch_size && pool_cursor < pool.len() {
+                let index = pool[pool_cursor];
+                pool_cursor += 1;
+                if !used[index] {
+                    used[index] = true;
+                    remaining -= 1;
+                    batch.push(index);
+                }
+            }
+
+            batch.shuffle(rng);
+            batches.push(batch);
+        }
+
+        Ok(batches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_every_batch_has_min_per_class() {
+        let mut labels = vec![0; 40];
+        labels.extend(vec![1; 5]);
+        labels.extend(vec![2; 3]);
+
+        let sampler = StratifiedBatchSampler::new(8, 2);
+        let batches = sampler.sample(&labels, &mut StdRng::seed_from_u64(7)).unwrap();
+
+        let mut seen = vec![false; labels.len()];
+        for batch in &batches {
+            assert!(batch.len() <= 8);
+            for class in 0..3 {
+                let count = batch.iter().filter(|&&i| labels[i] == class).count();
+                assert!(count >= 2, "class {} has {} examples in a batch", class, count);
+            }
+            for &index in batch {
+                seen[index] = true;
+            }
+        }
+        assert!(seen.iter().all(|&s| s), "every example must be sampled");
+    }
+
+    #[test]
+    fn test_batch_too_small() {
+        let error = StratifiedBatchSampler::new(3, 2).sample(&[0, 1], &mut StdRng::seed_from_u64(0)).unwrap_err();
+        assert!(error.to_string().contains("cannot hold"));
+    }
+}
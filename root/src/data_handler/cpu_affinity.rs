@@ -0,0 +1,126 @@
+use std::io::{Error, ErrorKind};
+use std::thread;
+
+/// Turns a configured thread count into the number of threads to start.
+///
+/// # Arguments
+/// * `configured` - Thread count from `config.rs`; `0` means one thread per core the
+///   process may run on.
+///
+/// # Returns
+/// * At least 1.
+pub fn resolve_thread_count(configured: usize) -> usize {
+    if configured == 0 {
+        thread::available_parallelism().map_or(1, |count| count.get())
+    } else {
+        configured
+    }
+}
+
+/// Core that the `worker`-th thread of a pool is pinned to, or `None` when `cores` is empty.
+/// Workers beyond the number of cores wrap around.
+pub fn core_for_worker(cores: &[usize], worker: usize) -> Option<usize> {
+    if cores.is_empty() { None } else { Some(cores[worker % cores.len()]) }
+}
+
+/// Restricts the calling thread to one CPU core.
+///
+/// # Returns
+/// * `Ok(())`, or the OS error, e.g. when the core does not exist or is outside the
+///   process's allowed set. Other platforms than Linux return `ErrorKind::Unsupported`.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(core: usize) -> Result<(), Error> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Core {} is out of range.", core)));
+    }
+    // SAFETY: `set` is a plain bit mask owned by this frame, and `core` is below `CPU_SETSIZE`.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_core: usize) -> Result<(), Error> {
+    Err(Error::new(ErrorKind::Unsupported, "Pinning threads to cores is only supported on Linux."))
+}
+
+/// Cores the process may run on, in increasing order.
+#[cfg(target_os = "linux")]
+pub fn allowed_cores() -> Result<Vec<usize>, Error> {
+    // SAFETY: `set` is a plain bit mask owned by this frame and filled by the kernel.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok((0..libc::CPU_SETSIZE as usize).filter(|&core| libc::CPU_ISSET(core, &set)).collect())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn allowed_cores() -> Result<Vec<usize>, Error> {
+    Err(Error::new(ErrorKind::Unsupported, "Pinning threads to cores is only supported on Linux."))
+}
+
+/// Checks a core list from `config.rs` before workers are pinned to it, so a wrong setting
+/// fails once at startup instead of being ignored by every worker.
+pub fn check_cores(cores: &[usize]) -> Result<(), Error> {
+    if cores.is_empty() {
+        return Ok(());
+    }
+    let allowed = allowed_cores()?;
+    match cores.iter().find(|core| !allowed.contains(core)) {
+        Some(core) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Core {} is not available to this process (allowed: {:?}).", core, allowed),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Pins the `worker`-th thread of a pool according to `cores`. Does nothing for an empty list.
+/// Errors are ignored here; `check_cores` reports them at startup.
+pub fn pin_worker(cores: &[usize], worker: usize) {
+    if let Some(core) = core_for_worker(cores, worker) {
+        let _ = pin_current_thread(core);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_count_and_core_assignment() {
+        assert_eq!(resolve_thread_count(3), 3);
+        assert!(resolve_thread_count(0) >= 1);
+
+        assert_eq!(core_for_worker(&[], 0), None);
+        let cores = [2, 5];
+        let assigned: Vec<Option<usize>> = (0..3).map(|worker| core_for_worker(&cores, worker)).collect();
+        assert_eq!(assigned, vec![Some(2), Some(5), Some(2)]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pin_to_allowed_core() {
+        let allowed = allowed_cores().unwrap();
+        assert!(check_cores(&allowed[..1]).is_ok());
+        assert!(check_cores(&[libc::CPU_SETSIZE as usize + 1]).is_err());
+
+        // Pin a separate thread so the test harness thread keeps its affinity.
+        let core = allowed[allowed.len() - 1];
+        let pinned = thread::spawn(move || {
+            pin_current_thread(core).unwrap();
+            allowed_cores().unwrap()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(pinned, vec![core]);
+    }
+}
@@ -1,23 +1,354 @@
 use crate::configurration::config::BATCH_SIZE;
-use crate::tokenization::tokenizer::Tokenizer; 
+use crate::configurration::data_schema::{CsvColumns, DataSchema};
+use crate::data_handler::batch_sampler::StratifiedBatchSampler;
+use crate::data_handler::parallel_loader::{map_in_workers, try_process_in_workers, DEFAULT_QUEUE_CAPACITY};
+use crate::data_handler::label_map::LabelMap;
+use crate::data_handler::sentence_pairs::sentence_order_pairs;
+use rand::Rng;
+use crate::data_handler::sliding_window::{SlidingWindow, WindowedDataset};
+use crate::augmentation::Augmenter;
+use crate::logging::logger::LogEvent;
+use crate::tokenization::tokenizer::{EncodedBatch, Tokenizer, TruncationReport};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use std::error::Error;
+use std::ops::ControlFlow;
 use serde_json::Value;
+use ndarray::Array2;
+
+/// A dataset entry after applying the data schema.
+#[derive(Clone)]
+pub struct RawRecord {
+    /// Value of the schema's id field, or the record's position in the file when
+    /// no id field is configured.
+    pub id: String,
+    pub text: String,
+    /// Class id of the record's label, resolved with the loader's label map when it has one.
+    pub label: Option<usize>,
+    /// Values of the schema's metadata fields present in the record.
+    pub metadata: HashMap<String, String>,
+}
+
+/// Token ids and labels of a dataset.
+pub type Dataset = (Vec<Vec<usize>>, Vec<usize>);
+
+/// Token ids, labels and example ids of one batch.
+pub type IdentifiedBatch = (Vec<Vec<usize>>, Vec<usize>, Vec<String>);
+
+/// Records read from a file, with their label values as written there.
+type UnresolvedRecords = Vec<(RawRecord, Option<String>)>;
+
+/// Texts, labels and domains of a dataset.
+pub type DomainTexts = (Vec<String>, Vec<usize>, Vec<String>);
+
+/// Token, attention-mask and segment-id arrays of a batch.
+pub type ModelInputs = (Array2<f64>, Array2<f64>, Array2<f64>);
 
 pub struct DataLoader<'a> {
     pub tokenizer: &'a Tokenizer,
+    pub schema: DataSchema,
+    /// Worker threads used for tokenization; 1 tokenizes on the calling thread.
+    pub num_workers: usize,
+    /// Cores the tokenization workers are pinned to, round-robin; empty leaves them to the OS.
+    pub worker_cores: &'static [usize],
+    /// Examples per batch of `create_batches` and friends; `BATCH_SIZE` unless tuned.
+    pub batch_size: usize,
+    /// Expands long documents of `load_dataset` into overlapping windows instead of
+    /// truncating them; `None` truncates.
+    pub sliding_window: Option<SlidingWindow>,
+    /// Resolves label values to class ids by name; `None` requires numeric class ids.
+    pub label_map: Option<LabelMap>,
 }
 
 impl<'a> DataLoader<'a> {
     pub fn new(tokenizer: &'a Tokenizer) -> Self {
-        DataLoader { tokenizer }
+        DataLoader { tokenizer, schema: DataSchema::default(), num_workers: 1, worker_cores: &[], batch_size: BATCH_SIZE, sliding_window: None, label_map: None }
+    }
+
+    /// Reads labels as class names of `label_map` (see `LabelMap::resolve`), so datasets can
+    /// label examples `"billing"` or `"shipping"` instead of class ids.
+    pub fn with_label_map(mut self, label_map: LabelMap) -> Self {
+        self.label_map = Some(label_map);
+        self
+    }
+
+    /// Builds a label map from the distinct label values of a dataset (see `LabelMap::from_values`).
+    /// Records without a label are skipped.
+    pub fn collect_label_map(&self, file_path: &str) -> Result<LabelMap, Box<dyn Error>> {
+        let values: Vec<String> = self.read_records(file_path)?.into_iter().filter_map(|(_, value)| value).collect();
+        Ok(LabelMap::from_values(&values))
+    }
+
+    /// Class id of a label value as written in a dataset.
+    fn resolve_label(&self, value: &str) -> Result<usize, Box<dyn Error>> {
+        match &self.label_map {
+            Some(label_map) => label_map.resolve(value),
+            None => value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Label '{}' is not a class id; named labels need a label map", value).into()),
+        }
+    }
+
+    /// Uses `batch_size` examples per batch instead of `BATCH_SIZE`, e.g. a run's tuned size.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Tokenizes on `num_workers` threads (see `parallel_loader::process_in_workers`).
+    /// The output is identical to single-threaded loading.
+    pub fn with_workers(mut self, num_workers: usize) -> Self {
+        self.num_workers = num_workers.max(1);
+        self
+    }
+
+    /// Pins the tokenization workers to `cores` (see `cpu_affinity::pin_current_thread`).
+    pub fn with_worker_cores(mut self, cores: &'static [usize]) -> Self {
+        self.worker_cores = cores;
+        self
+    }
+
+    /// Tokenizes and pads texts in chunks of `BATCH_SIZE`, spread over the loader's workers.
+    /// Logs how many tokens were dropped when texts exceed `max_seq_length`.
+    pub fn tokenize_texts(&self, texts: &[String]) -> Vec<Vec<usize>> {
+        let (sequences, report) = self.tokenize_texts_with_report(texts);
+        self.log_truncation(&report);
+        sequences
+    }
+
+    fn log_truncation(&self, report: &TruncationReport) {
+        if report.dropped_tokens > 0 {
+            LogEvent::info(
+                "data_loader",
+                format!("Truncation ({:?}, max_seq_length {}): {}", self.tokenizer.truncation, self.tokenizer.max_seq_length, report.summary()),
+            )
+            .metric("truncated_sequences", report.truncated_sequences)
+            .metric("dropped_tokens", report.dropped_tokens)
+            .emit();
+        }
+    }
+
+    /// Tokenizes and pads labelled texts in batches of `batch_size` on the loader's workers
+    /// and hands every batch to `consume` in order while the workers tokenize the next ones,
+    /// so training can start before the whole dataset is tokenized. At most
+    /// `DEFAULT_QUEUE_CAPACITY` finished batches wait for `consume`; returning
+    /// `ControlFlow::Break` stops the workers. Sliding windows are not applied, and the
+    /// batches equal those of `create_batches`.
+    pub fn stream_batches<C>(&self, texts: &[String], labels: &[usize], mut consume: C)
+    where
+        C: FnMut(usize, (Vec<Vec<usize>>, Vec<usize>)) -> ControlFlow<()>,
+    {
+        let mut report = TruncationReport::default();
+        let batch_size = self.batch_size;
+        let _ = try_process_in_workers(texts, batch_size, self.num_workers, DEFAULT_QUEUE_CAPACITY, self.worker_cores, |chunk| {
+            self.tokenizer.tokenize_and_pad_batch_with_report(chunk)
+        }, |index, (inputs, chunk_report)| {
+            report.merge(&chunk_report);
+            let batch_labels = labels[index * batch_size..((index + 1) * batch_size).min(labels.len())].to_vec();
+            consume(index, (inputs, batch_labels))
+        });
+        self.log_truncation(&report);
+    }
+
+    /// Same as `tokenize_texts`, also returning the attention mask of every sequence.
+    pub fn encode_texts(&self, texts: &[String]) -> EncodedBatch {
+        self.tokenizer.mask_batch(self.tokenize_texts(texts))
+    }
+
+    /// Token, attention-mask and segment-id arrays of a batch of padded sequences, as expected
+    /// by `Transformer::forward_with_segments`. Shape of all three: [batch_size, seq_len].
+    pub fn model_inputs(&self, batch_inputs: &[Vec<usize>]) -> ModelInputs {
+        let batch = self.tokenizer.mask_batch(batch_inputs.to_vec());
+        let (tokens, mask) = batch.to_arrays().expect("Padded sequences of a batch must have the same length.");
+        (tokens, mask, batch.token_type_array().expect("Padded sequences of a batch must have the same length."))
+    }
+
+    /// Same as `tokenize_texts`, returning the truncation counts instead of logging them.
+    pub fn tokenize_texts_with_report(&self, texts: &[String]) -> (Vec<Vec<usize>>, TruncationReport) {
+        let mut report = TruncationReport::default();
+        let mut sequences = Vec::with_capacity(texts.len());
+        for (chunk, chunk_report) in map_in_workers(texts, BATCH_SIZE, self.num_workers, self.worker_cores, |chunk| {
+            self.tokenizer.tokenize_and_pad_batch_with_report(chunk)
+        }) {
+            sequences.extend(chunk);
+            report.merge(&chunk_report);
+        }
+        (sequences, report)
+    }
+
+    /// Trains on every part of long documents: `Trainer::train` gets one example per window
+    /// from `labelled_inputs`, all with the document's label, and `Evaluator::compute_report`
+    /// combines the window predictions of each document with `window.weighting`. Other
+    /// callers, including `load_dataset`, still get one truncated sequence per example.
+    pub fn with_sliding_window(mut self, window: SlidingWindow) -> Self {
+        self.sliding_window = Some(window);
+        self
+    }
+
+    /// Loads a labelled dataset and splits every document into padded windows of at most
+    /// `max_seq_length` tokens.
+    pub fn load_windowed_dataset(&self, file_path: &str, window: &SlidingWindow) -> Result<WindowedDataset, Box<dyn Error>> {
+        let (texts, labels) = self.load_labelled_texts(file_path)?;
+        Ok(self.windowed_dataset(&texts, &labels, window))
+    }
+
+    fn windowed_dataset(&self, texts: &[String], labels: &[usize], window: &SlidingWindow) -> WindowedDataset {
+        let documents: Vec<Vec<usize>> = map_in_workers(texts, BATCH_SIZE, self.num_workers, self.worker_cores, |chunk| {
+            chunk.iter().map(|text| self.tokenizer.tokenize(text)).collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect();
+        WindowedDataset::new(&documents, labels, window, self.tokenizer.max_seq_length, |tokens| {
+            self.tokenizer.pad_sequence(tokens)
+        })
+    }
+
+    /// Uses a custom schema to map dataset fields to text and labels.
+    pub fn with_schema(mut self, schema: DataSchema) -> Self {
+        self.schema = schema;
+        self
     }
 
     pub fn load_dataset(
         &self,
         file_path: &str,
-    ) -> Result<(Vec<Vec<usize>>, Vec<usize>), Box<dyn Error>> {
+    ) -> Result<Dataset, Box<dyn Error>> {
+        let (texts, labels) = self.load_labelled_texts(file_path)?;
+        Ok((self.tokenize_texts(&texts), labels))
+    }
+
+    /// Texts and labels of a labelled dataset, with the examples generated by `augmenters`
+    /// added, ready for `labelled_inputs` or `stream_batches`. The augmenters run in order,
+    /// each over the output of the previous one.
+    pub fn load_augmented_texts(
+        &self,
+        file_path: &str,
+        augmenters: &[Box<dyn Augmenter + '_>],
+    ) -> Result<(Vec<String>, Vec<usize>), Box<dyn Error>> {
+        let (mut texts, mut labels) = self.load_labelled_texts(file_path)?;
+        for augmenter in augmenters {
+            (texts, labels) = augmenter.augment(&texts, &labels)?;
+        }
+        Ok((texts, labels))
+    }
+
+    /// Texts and labels of a labelled dataset.
+    fn load_labelled_texts(&self, file_path: &str) -> Result<(Vec<String>, Vec<usize>), Box<dyn Error>> {
+        let mut texts = Vec::new();
+        let mut labels = Vec::new();
+        for record in self.load_records(file_path)? {
+            labels.push(record.label.ok_or_else(|| format!("Missing {} field", self.schema.label_field))?);
+            texts.push(record.text);
+        }
+        Ok((texts, labels))
+    }
+
+    /// Padded training sequences of labelled texts: one per window with a sliding window,
+    /// otherwise one truncated sequence per text.
+    pub fn labelled_inputs(&self, texts: &[String], labels: Vec<usize>) -> (Vec<Vec<usize>>, Vec<usize>) {
+        let Some(window) = &self.sliding_window else {
+            return (self.tokenize_texts(texts), labels);
+        };
+        let dataset = self.windowed_dataset(texts, &labels, window);
+        if dataset.inputs.len() > dataset.document_labels.len() {
+            LogEvent::info(
+                "data_loader",
+                format!(
+                    "Sliding windows (stride {}, max_seq_length {}): {} documents -> {} windows",
+                    window.stride,
+                    self.tokenizer.max_seq_length,
+                    dataset.document_labels.len(),
+                    dataset.inputs.len()
+                ),
+            )
+            .metric("documents", dataset.document_labels.len())
+            .metric("windows", dataset.inputs.len())
+            .emit();
+        }
+        (dataset.inputs, dataset.labels)
+    }
+
+    /// Same as `load_dataset`, but also returns the id of every example so
+    /// predictions can be joined back to the source records.
+    pub fn load_dataset_with_ids(&self, file_path: &str) -> Result<IdentifiedBatch, Box<dyn Error>> {
+        self.tokenize_records(&self.load_records(file_path)?)
+    }
+
+    /// Tokenizes records read with `load_records`, returning their token ids, labels and ids
+    /// like `load_dataset_with_ids`, so callers that also need the records read the file once.
+    ///
+    /// # Returns
+    /// * An error if a record has no label.
+    pub fn tokenize_records(&self, records: &[RawRecord]) -> Result<IdentifiedBatch, Box<dyn Error>> {
+        let mut texts = Vec::new();
+        let mut labels = Vec::new();
+        let mut ids = Vec::new();
+
+        for record in records {
+            let label = record.label.ok_or_else(|| format!("Missing {} field", self.schema.label_field))?;
+            texts.push(record.text.clone());
+            labels.push(label);
+            ids.push(record.id.clone());
+        }
+
+        Ok((self.tokenize_texts(&texts), labels, ids))
+    }
+
+    /// Texts and labels of a labelled dataset, with every example's value of the metadata
+    /// field `domain_field`, e.g. the source it was collected from, for domain-adversarial
+    /// training. Sliding windows are not applied.
+    ///
+    /// # Returns
+    /// * An error if the field is not one of the schema's `metadata_fields` or a record lacks it.
+    pub fn load_texts_with_domains(&self, file_path: &str, domain_field: &str) -> Result<DomainTexts, Box<dyn Error>> {
+        if !self.schema.metadata_fields.iter().any(|field| field == domain_field) {
+            return Err(format!("The domain field {} must be one of the data schema's metadata_fields", domain_field).into());
+        }
+        let mut texts = Vec::new();
+        let mut labels = Vec::new();
+        let mut domains = Vec::new();
+
+        for mut record in self.load_records(file_path)? {
+            labels.push(record.label.ok_or_else(|| format!("Missing {} field", self.schema.label_field))?);
+            domains.push(record.metadata.remove(domain_field).ok_or_else(|| format!("Missing {} field of record {}", domain_field, record.id))?);
+            texts.push(record.text);
+        }
+
+        Ok((texts, labels, domains))
+    }
+
+    /// Loads only the raw text of every example, without tokenization. Labels are not
+    /// resolved, so this works before a label map exists, e.g. to build a vocabulary; records
+    /// are otherwise rejected exactly as by `load_records`.
+    pub fn load_texts(&self, file_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self.read_records(file_path)?.into_iter().map(|(record, _)| record.text).collect())
+    }
+
+    /// Loads unlabeled texts as encoded sentence-order prediction examples
+    /// (see `sentence_pairs::sentence_order_pairs`), with the attention masks and segment
+    /// ids of every pair. Labels in the file are ignored; `rng` decides which pairs are swapped.
+    pub fn load_sentence_order_dataset<R: Rng>(&self, file_path: &str, rng: &mut R) -> Result<(EncodedBatch, Vec<usize>), Box<dyn Error>> {
+        let texts = self.load_texts(file_path)?;
+        let (pairs, labels) = sentence_order_pairs(&texts, rng);
+        Ok((self.tokenizer.encode_pair_batch(&pairs), labels))
+    }
+
+    /// Reads every record of a CSV or JSON dataset according to the data schema.
+    pub fn load_records(&self, file_path: &str) -> Result<Vec<RawRecord>, Box<dyn Error>> {
+        self.read_records(file_path)?
+            .into_iter()
+            .map(|(mut record, value)| {
+                record.label = value.map(|value| self.resolve_label(&value)).transpose()?;
+                Ok(record)
+            })
+            .collect()
+    }
+
+    /// Records with their label values as written in the file; `label` is left unresolved.
+    fn read_records(&self, file_path: &str) -> Result<UnresolvedRecords, Box<dyn Error>> {
         let path = Path::new(file_path);
         let extension = path.extension().and_then(|ext| ext.to_str());
 
@@ -28,54 +359,121 @@ impl<'a> DataLoader<'a> {
         }
     }
 
-    fn load_csv(
-        &self,
-        file_path: &str,
-    ) -> Result<(Vec<Vec<usize>>, Vec<usize>), Box<dyn Error>> {
+    fn load_csv(&self, file_path: &str) -> Result<UnresolvedRecords, Box<dyn Error>> {
         let mut reader = csv::Reader::from_path(file_path)?;
-        let mut inputs = Vec::new();
-        let mut labels = Vec::new();
+        let headers = reader.headers()?.clone();
+        let column = |field: &str| headers.iter().position(|header| header == field);
+
+        let input_fields = self.schema.input_fields();
+        let (text_columns, label_column) = match self.schema.csv_columns {
+            CsvColumns::Positional => ((0..input_fields.len()).collect(), Some(input_fields.len())),
+            CsvColumns::ByHeader => (
+                input_fields
+                    .iter()
+                    .map(|field| column(field).ok_or_else(|| format!("Missing {} column in CSV header", field)))
+                    .collect::<Result<Vec<usize>, String>>()?,
+                column(&self.schema.label_field),
+            ),
+        };
+        let id_column = match &self.schema.id_field {
+            Some(field) => Some(column(field).ok_or_else(|| format!("Missing {} column in CSV header", field))?),
+            None => None,
+        };
+        let metadata_columns: Vec<(&String, usize)> = self
+            .schema
+            .metadata_fields
+            .iter()
+            .filter_map(|field| column(field).map(|i| (field, i)))
+            .collect();
 
-        for result in reader.records() {
+        let mut records = Vec::new();
+        for (position, result) in reader.records().enumerate() {
             let record = result?;
-            let text = record.get(0).ok_or("Missing text field")?;
-            let label: usize = record.get(1)
-                .ok_or("Missing label field")?
-                .parse()?;
+            let text_parts = text_columns
+                .iter()
+                .map(|&i| record.get(i).ok_or("Missing text field"))
+                .collect::<Result<Vec<&str>, &str>>()?;
+            let label_value = label_column.and_then(|i| record.get(i)).map(str::to_string);
 
-            inputs.push(self.tokenizer.tokenize_and_pad_batch(&[text.to_string()])[0].clone());
-            labels.push(label);
+            let id = match id_column {
+                Some(i) => record.get(i).ok_or("Missing id field")?.to_string(),
+                None => position.to_string(),
+            };
+
+            let metadata = metadata_columns
+                .iter()
+                .filter_map(|&(field, i)| record.get(i).map(|value| (field.clone(), value.to_string())))
+                .collect();
+
+            let record = RawRecord {
+                id,
+                text: self.schema.render(&text_parts)?,
+                label: None,
+                metadata,
+            };
+            records.push((record, label_value));
         }
 
-        Ok((inputs, labels))
+        Ok(records)
     }
 
-    fn load_json(
-        &self,
-        file_path: &str,
-    ) -> Result<(Vec<Vec<usize>>, Vec<usize>), Box<dyn Error>> {
+    fn load_json(&self, file_path: &str) -> Result<UnresolvedRecords, Box<dyn Error>> {
         let file_content = fs::read_to_string(file_path)?;
         let data: Value = serde_json::from_str(&file_content)?;
 
-        let mut inputs = Vec::new();
-        let mut labels = Vec::new();
+        let mut records = Vec::new();
 
         if let Some(array) = data.as_array() {
-            for item in array {
-                let text = item.get("text")
-                    .and_then(|v| v.as_str())
-                    .ok_or("Missing text field in JSON entry")?;
-                let label = item.get("label")
-                    .ok_or("Missing label field in JSON entry")?
-                    .as_u64()
-                    .ok_or("Label must be a number")?;
-
-                inputs.push(self.tokenizer.tokenize_and_pad_batch(&[text.to_string()])[0].clone());
-                labels.push(label as usize);
+            for (position, item) in array.iter().enumerate() {
+                let input_fields = self.schema.input_fields();
+                let text_parts = input_fields
+                    .iter()
+                    .map(|field| {
+                        item.get(field)
+                            .and_then(|v| v.as_str())
+                            .ok_or_else(|| format!("Missing {} field in JSON entry", field))
+                    })
+                    .collect::<Result<Vec<&str>, String>>()?;
+                let label_value = match item.get(&self.schema.label_field) {
+                    Some(Value::String(name)) => Some(name.clone()),
+                    Some(value) => Some(value.as_u64().ok_or("Label must be a number or a class name")?.to_string()),
+                    None => None,
+                };
+
+                let id = match &self.schema.id_field {
+                    Some(field) => match item.get(field) {
+                        Some(Value::String(id)) => id.clone(),
+                        Some(Value::Number(id)) => id.to_string(),
+                        _ => return Err(format!("Missing {} field in JSON entry", field).into()),
+                    },
+                    None => position.to_string(),
+                };
+
+                let metadata = self
+                    .schema
+                    .metadata_fields
+                    .iter()
+                    .filter_map(|field| {
+                        let value = match item.get(field)? {
+                            Value::String(value) => value.clone(),
+                            Value::Null => return None,
+                            other => other.to_string(),
+                        };
+                        Some((field.clone(), value))
+                    })
+                    .collect();
+
+                let record = RawRecord {
+                    id,
+                    text: self.schema.render(&text_parts)?,
+                    label: None,
+                    metadata,
+                };
+                records.push((record, label_value));
             }
         }
 
-        Ok((inputs, labels))
+        Ok(records)
     }
 
     pub fn create_batches(
@@ -84,35 +482,227 @@ impl<'a> DataLoader<'a> {
         labels: Vec<usize>,
     ) -> Vec<(Vec<Vec<usize>>, Vec<usize>)> {
         inputs
-            .chunks(BATCH_SIZE)
-            .zip(labels.chunks(BATCH_SIZE))
+            .chunks(self.batch_size)
+            .zip(labels.chunks(self.batch_size))
             .map(|(input_chunk, label_chunk)| {
                 (input_chunk.to_vec(), label_chunk.to_vec())
             })
             .collect()
     }
+
+    /// Creates shuffled batches of `batch_size` that contain at least `min_per_class`
+    /// examples of every class (see `StratifiedBatchSampler`), shuffled with `rng`.
+    /// Fails if `batch_size` cannot hold `min_per_class` examples of every class.
+    pub fn create_stratified_batches<R: Rng>(
+        &self,
+        inputs: &[Vec<usize>],
+        labels: &[usize],
+        min_per_class: usize,
+        rng: &mut R,
+    ) -> Result<Vec<Dataset>, Box<dyn Error>> {
+        Ok(StratifiedBatchSampler::new(self.batch_size, min_per_class)
+            .sample(labels, rng)?
+            .into_iter()
+            .map(|batch| {
+                (
+                    batch.iter().map(|&i| inputs[i].clone()).collect(),
+                    batch.iter().map(|&i| labels[i]).collect(),
+                )
+            })
+            .collect())
+    }
+
+    /// Same as `create_batches` for encoded examples, so attention masks and segment ids
+    /// stay with their token ids.
+    pub fn create_encoded_batches(&self, encoded: &EncodedBatch, labels: &[usize]) -> Vec<(EncodedBatch, Vec<usize>)> {
+        (0..labels.len())
+            .step_by(self.batch_size)
+            .map(|start| {
+                let end = (start + self.batch_size).min(labels.len());
+                (encoded.slice(start..end), labels[start..end].to_vec())
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::fixtures::{synthetic_dataset_path, temp_path, tiny_vocab};
     use std::collections::HashMap;
-    use crate::configurration::config::{PAD_TOKEN, UNK_TOKEN};
+    use crate::configurration::config::{PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN};
+    use crate::data_handler::input_template::InputTemplate;
 
     #[test]
     fn test_data_loader() {
-        let vocab = HashMap::from([
-            (PAD_TOKEN.to_string(), 0),
-            (UNK_TOKEN.to_string(), 1),
-            ("hello".to_string(), 2),
-            ("world".to_string(), 3),
-        ]);
+        let vocab = tiny_vocab(&["hello", "world"]);
 
         let tokenizer = Tokenizer::new(vocab, 128);
         let data_loader = DataLoader::new(&tokenizer);
 
         // Test with JSON file
-        let result = data_loader.load_dataset("src/test_dataset.json");
+        let json_path = &synthetic_dataset_path("loader_test_dataset.json");
+        let result = data_loader.load_dataset(json_path);
+        fs::remove_file(json_path).unwrap();
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_schema_concatenates_fields() {
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab, 8);
+        let schema = DataSchema {
+            text_fields: vec!["title".to_string(), "body".to_string()],
+            label_field: "category".to_string(),
+            id_field: None,
+            text_separator: " | ".to_string(),
+            metadata_fields: vec!["source".to_string()],
+            input_template: None,
+            csv_columns: CsvColumns::ByHeader,
+        };
+        let data_loader = DataLoader::new(&tokenizer).with_schema(schema);
+
+        let json_path = &temp_path("schema_test_dataset.json");
+        fs::write(json_path, r#"[{ "title": "Big sale", "body": "Buy now", "category": 1, "source": "web" }]"#).unwrap();
+        let csv_path = &temp_path("schema_test_dataset.csv");
+        fs::write(csv_path, "category,body,title\n0,See you,Lunch\n").unwrap();
+
+        let json_records = data_loader.load_records(json_path).unwrap();
+        let csv_records = data_loader.load_records(csv_path).unwrap();
+        fs::remove_file(json_path).unwrap();
+        fs::remove_file(csv_path).unwrap();
+
+        assert_eq!(json_records[0].text, "Big sale | Buy now");
+        assert_eq!(json_records[0].label, Some(1));
+        assert_eq!(csv_records[0].text, "Lunch | See you");
+        assert_eq!(csv_records[0].label, Some(0));
+        assert_eq!(json_records[0].metadata["source"], "web");
+        assert!(csv_records[0].metadata.is_empty());
+    }
+
+    #[test]
+    fn test_csv_columns_are_positional_by_default() {
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab, 8);
+        let schema = DataSchema { id_field: Some("uid".to_string()), ..DataSchema::default() };
+        let csv_path = &temp_path("positional_test_dataset.csv");
+        fs::write(csv_path, "message,class,uid
+See you,1,a-1
+").unwrap();
+
+        let records = DataLoader::new(&tokenizer).with_schema(schema).load_records(csv_path);
+        fs::remove_file(csv_path).unwrap();
+
+        let records = records.unwrap();
+        assert_eq!(records[0].text, "See you");
+        assert_eq!(records[0].label, Some(1));
+        assert_eq!(records[0].id, "a-1");
+    }
+
+    #[test]
+    fn test_schema_renders_input_template() {
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab, 8);
+        let schema = DataSchema {
+            input_template: Some(InputTemplate::parse("{title} [SEP] {body}").unwrap()),
+            csv_columns: CsvColumns::ByHeader,
+            ..DataSchema::default()
+        };
+        let data_loader = DataLoader::new(&tokenizer).with_schema(schema);
+
+        let json_path = &temp_path("template_test_dataset.json");
+        fs::write(json_path, r#"[{ "title": "Big sale", "body": "Buy now", "label": 1 }, { "title": "No body", "label": 0 }]"#).unwrap();
+        let csv_path = &temp_path("template_test_dataset.csv");
+        fs::write(csv_path, "label,body,title\n0,See you,Lunch\n").unwrap();
+
+        let json_records = data_loader.load_records(json_path);
+        let json_texts = data_loader.load_texts(json_path);
+        let csv_records = data_loader.load_records(csv_path).unwrap();
+        let csv_texts = data_loader.load_texts(csv_path).unwrap();
+        fs::remove_file(json_path).unwrap();
+        fs::remove_file(csv_path).unwrap();
+
+        // Every template field is required, also for the texts a vocabulary is built from.
+        assert!(json_records.is_err() && json_texts.is_err());
+        assert_eq!(csv_records[0].text, "Lunch [SEP] See you");
+        assert_eq!(csv_texts, vec!["Lunch [SEP] See you"]);
+    }
+
+    #[test]
+    fn test_encoded_batches_keep_segment_ids() {
+        let vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, "a", "b"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        let tokenizer = Tokenizer::new(vocab, 6);
+        let pairs: Vec<(String, String)> =
+            [("a", "b"), ("a a", "b"), ("b", "a a")].iter().map(|&(first, second)| (first.to_string(), second.to_string())).collect();
+        let data_loader = DataLoader::new(&tokenizer).with_batch_size(2);
+        let (encoded, labels) = (tokenizer.encode_pair_batch(&pairs), vec![0, 1, 0]);
+
+        assert_eq!(encoded.token_type_ids[1], vec![0, 0, 0, 0, 1, 1]);
+        let batches = data_loader.create_encoded_batches(&encoded, &labels);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].0.input_ids, vec![vec![2, 5, 3, 4, 4, 3]]);
+        assert_eq!(batches[1].0.token_type_ids, vec![vec![0, 0, 0, 1, 1, 1]]);
+        assert_eq!(batches[1].1, vec![0]);
+    }
+
+    #[test]
+    fn test_ids_carried_through_batches() {
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab, 8);
+        let schema = DataSchema {
+            id_field: Some("uid".to_string()),
+            ..DataSchema::default()
+        };
+        let data_loader = DataLoader::new(&tokenizer).with_schema(schema);
+
+        let json_path = &temp_path("id_test_dataset.json");
+        fs::write(json_path, r#"[{ "uid": "a-1", "text": "x", "label": 0 }, { "uid": 7, "text": "y", "label": 1 }]"#).unwrap();
+        let result = data_loader.load_dataset_with_ids(json_path);
+        fs::remove_file(json_path).unwrap();
+
+        let (_, _, ids) = result.unwrap();
+        assert_eq!(ids, vec!["a-1", "7"]);
+
+        let positional_loader = DataLoader::new(&tokenizer);
+        let positional_path = &synthetic_dataset_path("positional_id_test_dataset.json");
+        let (_, _, positional_ids) = positional_loader.load_dataset_with_ids(positional_path).unwrap();
+        assert_eq!(positional_ids[0], "0");
+        let records = positional_loader.load_records(positional_path).unwrap();
+        assert_eq!(positional_loader.tokenize_records(&records).unwrap(), positional_loader.load_dataset_with_ids(positional_path).unwrap());
+        fs::remove_file(positional_path).unwrap();
+    }
+
+    #[test]
+    fn test_wordpiece_tokenizer_plugs_into_loader() {
+        let vocab_path = &temp_path("loader_test_vocab.txt");
+        let json_path = &temp_path("wordpiece_test_dataset.json");
+        fs::write(vocab_path, "[PAD]\n[UNK]\nplay\n##ing\n!\n").unwrap();
+        fs::write(json_path, r#"[{ "text": "Playing!", "label": 1 }]"#).unwrap();
+
+        let tokenizer = Tokenizer::from_wordpiece_vocab(vocab_path, 5).unwrap();
+        let result = DataLoader::new(&tokenizer).load_dataset(json_path);
+        fs::remove_file(vocab_path).unwrap();
+        fs::remove_file(json_path).unwrap();
+
+        let (inputs, labels) = result.unwrap();
+        assert_eq!(inputs[0], vec![2, 3, 4, 0, 0]);
+        assert_eq!(labels, vec![1]);
+    }
+
+    #[test]
+    fn test_workers_produce_identical_inputs() {
+        let vocab = tiny_vocab(&["hello"]);
+        let tokenizer = Tokenizer::new(vocab, 6);
+        let texts: Vec<String> = (0..3 * BATCH_SIZE + 5).map(|i| "hello ".repeat(i % 5)).collect();
+
+        let sequential = DataLoader::new(&tokenizer).tokenize_texts(&texts);
+        let parallel = DataLoader::new(&tokenizer).with_workers(4).tokenize_texts(&texts);
+
+        assert_eq!(sequential.len(), texts.len());
+        assert_eq!(sequential, parallel);
+    }
+}
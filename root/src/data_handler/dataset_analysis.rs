@@ -0,0 +1,203 @@
+use crate::configurration::config::{PAD_TOKEN, UNK_TOKEN};
+use crate::tokenization::tokenizer::Tokenizer;
+use crate::tokenization::phrases::{PhraseDetector, PhraseScoring};
+use crate::logging::logger::LogEvent;
+use std::collections::HashMap;
+
+/// Length percentile used to recommend `MAX_SEQ_LENGTH`.
+pub const DEFAULT_LENGTH_PERCENTILE: f64 = 0.95;
+/// Fraction of corpus token occurrences the recommended vocabulary must cover.
+pub const DEFAULT_VOCAB_COVERAGE: f64 = 0.95;
+/// Phrase detector the analysis recommends for `PHRASES` when it finds collocations.
+pub const RECOMMENDED_PHRASES: PhraseDetector = PhraseDetector { min_count: 5, scoring: PhraseScoring::Npmi(0.5) };
+
+/// Corpus statistics and the configuration recommended from them.
+pub struct DatasetAnalysis {
+    pub num_examples: usize,
+    pub mean_length: f64,
+    pub max_length: usize,
+    pub unique_tokens: usize,
+    pub total_tokens: usize,
+    /// Token length at `length_percentile`, used as the recommended `MAX_SEQ_LENGTH`.
+    pub recommended_max_seq_length: usize,
+    /// Number of most frequent tokens (plus special tokens) covering `vocab_coverage` of the corpus.
+    pub recommended_vocab_size: usize,
+    pub length_percentile: f64,
+    pub vocab_coverage: f64,
+    /// Adjacent word pairs seen at least `RECOMMENDED_PHRASES.min_count` times.
+    pub frequent_pairs: usize,
+    /// The frequent pairs `RECOMMENDED_PHRASES` would merge, sorted.
+    pub collocations: Vec<String>,
+}
+
+impl DatasetAnalysis {
+    /// Analyzes raw texts with the tokenizer's preprocessing.
+    ///
+    /// # Arguments
+    /// * `texts` - Raw dataset texts.
+    /// * `length_percentile` - Percentile of token lengths to cover, e.g. 0.95.
+    /// * `vocab_coverage` - Fraction of token occurrences the vocabulary should cover, e.g. 0.95.
+    pub fn analyze(texts: &[String], length_percentile: f64, vocab_coverage: f64) -> Self {
+        assert!((0.0..=1.0).contains(&length_percentile), "Length percentile must be in [0, 1].");
+        assert!((0.0..=1.0).contains(&vocab_coverage), "Vocabulary coverage must be in [0, 1].");
+
+        let mut lengths = Vec::with_capacity(texts.len());
+        let mut token_counts: HashMap<String, usize> = HashMap::new();
+        let mut words = Vec::with_capacity(texts.len());
+        for text in texts {
+            let tokens = Tokenizer::preprocess_text(text);
+            lengths.push(tokens.len());
+            for token in &tokens {
+                *token_counts.entry(token.clone()).or_insert(0) += 1;
+            }
+            words.push(tokens);
+        }
+        let frequent_pairs = PhraseDetector { scoring: PhraseScoring::Count, ..RECOMMENDED_PHRASES }.detect(&words).len();
+        let mut collocations: Vec<String> = RECOMMENDED_PHRASES.detect(&words).into_iter().collect();
+        collocations.sort_unstable();
+        lengths.sort_unstable();
+
+        let total_tokens: usize = lengths.iter().sum();
+        let percentile_length = if lengths.is_empty() {
+            0
+        } else {
+            // Nearest-rank percentile.
+            let rank = (length_percentile * lengths.len() as f64).ceil() as usize;
+            lengths[rank.clamp(1, lengths.len()) - 1]
+        };
+
+        let mut counts: Vec<usize> = token_counts.values().copied().collect();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        let target = vocab_coverage * total_tokens as f64;
+        let mut covered = 0;
+        let mut covering_tokens = 0;
+        for count in &counts {
+            if covered as f64 >= target {
+                break;
+            }
+            covered += count;
+            covering_tokens += 1;
+        }
+
+        DatasetAnalysis {
+            num_examples: texts.len(),
+            mean_length: if texts.is_empty() { 0.0 } else { total_tokens as f64 / texts.len() as f64 },
+            max_length: lengths.last().copied().unwrap_or(0),
+            unique_tokens: counts.len(),
+            total_tokens,
+            recommended_max_seq_length: percentile_length.max(1),
+            recommended_vocab_size: covering_tokens + [PAD_TOKEN, UNK_TOKEN].len(),
+            length_percentile,
+            vocab_coverage,
+            frequent_pairs,
+            collocations,
+        }
+    }
+
+    /// Renders the recommendation as constants ready to paste into `config.rs`. `PHRASES` is
+    /// only recommended when the corpus has collocations.
+    pub fn config_snippet(&self) -> String {
+        let mut snippet = format!(
+            "// {:.0}th percentile token length, {:.0}% token coverage\n\
+             pub const MAX_SEQ_LENGTH: usize = {};\n\
+             pub const MAX_VOCAB_SIZE: usize = {};\n",
+            self.length_percentile * 100.0,
+            self.vocab_coverage * 100.0,
+            self.recommended_max_seq_length,
+            self.recommended_vocab_size
+        );
+        if !self.collocations.is_empty() {
+            let scoring = match RECOMMENDED_PHRASES.scoring {
+                PhraseScoring::Count => "PhraseScoring::Count".to_string(),
+                PhraseScoring::Npmi(threshold) => format!("PhraseScoring::Npmi({:?})", threshold),
+            };
+            snippet.push_str(&format!(
+                "// {} of {} frequent word pairs are collocations, e.g. {}\n\
+                 pub const PHRASES: Option<PhraseDetector> = Some(PhraseDetector {{ min_count: {}, scoring: {} }});\n",
+                self.collocations.len(),
+                self.frequent_pairs,
+                self.collocations.iter().take(5).cloned().collect::<Vec<_>>().join(", "),
+                RECOMMENDED_PHRASES.min_count,
+                scoring
+            ));
+        }
+        snippet
+    }
+
+    pub fn print_report(&self) {
+        LogEvent::info(
+            "dataset_analysis",
+            format!(
+                "Examples: {}\nToken length: mean {:.1}, max {}, {:.0}th percentile {}\nUnique tokens: {} ({} total)\n\nRecommended configuration:\n{}",
+                self.num_examples,
+                self.mean_length,
+                self.max_length,
+                self.length_percentile * 100.0,
+                self.recommended_max_seq_length,
+                self.unique_tokens,
+                self.total_tokens,
+                self.config_snippet()
+            ),
+        )
+        .metric("num_examples", self.num_examples)
+        .metric("mean_length", self.mean_length)
+        .metric("max_length", self.max_length)
+        .metric("unique_tokens", self.unique_tokens)
+        .metric("recommended_max_seq_length", self.recommended_max_seq_length)
+        .metric("recommended_vocab_size", self.recommended_vocab_size)
+        .metric("collocations", self.collocations.len())
+        .emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_recommendations() {
+        let texts: Vec<String> = vec![
+            "a b",
+            "a b c",
+            "a a a a",
+            "a b c d e f g h i j",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        let analysis = DatasetAnalysis::analyze(&texts, 0.75, 0.5);
+
+        assert_eq!(analysis.num_examples, 4);
+        assert_eq!(analysis.max_length, 10);
+        assert_eq!(analysis.total_tokens, 19);
+        assert_eq!(analysis.recommended_max_seq_length, 4);
+        // "a" alone covers 7/19 tokens, "a" + "b" cover 10/19 >= 50%.
+        assert_eq!(analysis.recommended_vocab_size, 2 + 2);
+        assert!(analysis.config_snippet().contains("pub const MAX_SEQ_LENGTH: usize = 4;"));
+    }
+
+    #[test]
+    fn test_analyze_recommends_phrases_for_collocations() {
+        let mut texts = vec!["flights to new york".to_string(); 5];
+        texts.extend(vec!["the hotel of the year".to_string(); 5]);
+        texts.extend(["the museum", "the park", "of course", "of mine"].iter().map(|text| text.to_string()));
+
+        let analysis = DatasetAnalysis::analyze(&texts, DEFAULT_LENGTH_PERCENTILE, DEFAULT_VOCAB_COVERAGE);
+
+        assert!(analysis.collocations.contains(&"new_york".to_string()));
+        assert!(!analysis.collocations.contains(&"of_the".to_string()));
+        assert!(analysis.frequent_pairs > analysis.collocations.len());
+        assert!(analysis.config_snippet().contains("pub const PHRASES: Option<PhraseDetector> = Some(PhraseDetector { min_count: 5, scoring: PhraseScoring::Npmi(0.5) });"));
+    }
+
+    #[test]
+    fn test_analyze_empty_dataset() {
+        let analysis = DatasetAnalysis::analyze(&[], DEFAULT_LENGTH_PERCENTILE, DEFAULT_VOCAB_COVERAGE);
+
+        assert_eq!(analysis.num_examples, 0);
+        assert_eq!(analysis.recommended_max_seq_length, 1);
+        assert_eq!(analysis.recommended_vocab_size, 2);
+        assert!(!analysis.config_snippet().contains("PHRASES"));
+    }
+}
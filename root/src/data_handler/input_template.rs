@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+
+#[derive(Clone, Debug, PartialEq)]
+enum TemplateSegment {
+    Literal(String),
+    Field(String),
+}
+
+/// Renders the fields of a multi-field record into one model input, e.g.
+/// `"{title} [SEP] {body}"`.
+///
+/// `{name}` is replaced by the value of field `name`; everything else is copied as written
+/// and tokenized like the text around it. `{{` and `}}` stand for literal braces. Markers
+/// such as `[SEP]` stay one token only if they are registered with
+/// `Tokenizer::register_special_token`; `create_run` does so for the built-in special tokens
+/// of the configured template.
+///
+/// The same template is stored in the run config and used by `Inference::predict_fields`,
+/// so records are rendered identically at train and serve time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct InputTemplate {
+    template: String,
+    segments: Vec<TemplateSegment>,
+}
+
+impl InputTemplate {
+    /// Parses a template.
+    ///
+    /// # Returns
+    /// * An error for unbalanced braces, an empty `{}` or a template without fields.
+    pub fn parse(template: &str) -> Result<Self, Box<dyn Error>> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => return Err(format!("Unclosed field in input template {:?}", template).into()),
+                            Some(c) => field.push(c),
+                        }
+                    }
+                    let field = field.trim();
+                    if field.is_empty() {
+                        return Err(format!("Empty field name in input template {:?}", template).into());
+                    }
+                    if !literal.is_empty() {
+                        segments.push(TemplateSegment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(TemplateSegment::Field(field.to_string()));
+                }
+                '}' => return Err(format!("Unmatched '}}' in input template {:?}", template).into()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(TemplateSegment::Literal(literal));
+        }
+
+        let template = InputTemplate { template: template.to_string(), segments };
+        if template.fields().is_empty() {
+            return Err(format!("Input template {:?} names no fields", template.template).into());
+        }
+        Ok(template)
+    }
+
+    /// The template as written.
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// Names of the fields the template uses, in order of first use.
+    pub fn fields(&self) -> Vec<&str> {
+        let mut fields: Vec<&str> = Vec::new();
+        for segment in &self.segments {
+            if let TemplateSegment::Field(field) = segment {
+                if !fields.contains(&field.as_str()) {
+                    fields.push(field);
+                }
+            }
+        }
+        fields
+    }
+
+    /// Whitespace-separated words of the literal parts, e.g. `["[SEP]"]`.
+    pub fn literal_words(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                TemplateSegment::Literal(literal) => Some(literal.split_whitespace()),
+                TemplateSegment::Field(_) => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Renders a record, looking up every field with `value`.
+    ///
+    /// # Returns
+    /// * An error naming the first field `value` has no value for.
+    pub fn render_with<'a>(&self, value: impl Fn(&str) -> Option<&'a str>) -> Result<String, Box<dyn Error>> {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                TemplateSegment::Literal(literal) => rendered.push_str(literal),
+                TemplateSegment::Field(field) => {
+                    rendered.push_str(value(field).ok_or_else(|| format!("Missing field {} for the input template", field))?)
+                }
+            }
+        }
+        Ok(rendered)
+    }
+
+    /// Renders a record given as field names and values.
+    pub fn render(&self, fields: &HashMap<String, String>) -> Result<String, Box<dyn Error>> {
+        self.render_with(|field| fields.get(field).map(String::as_str))
+    }
+}
+
+impl TryFrom<String> for InputTemplate {
+    type Error = Box<dyn Error>;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        InputTemplate::parse(&template)
+    }
+}
+
+impl From<InputTemplate> for String {
+    fn from(template: InputTemplate) -> Self {
+        template.template
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let template = InputTemplate::parse("{title} [SEP] {body} {{{ lang }}}").unwrap();
+        assert_eq!(template.fields(), vec!["title", "body", "lang"]);
+        assert_eq!(template.literal_words(), vec!["[SEP]", "{", "}"]);
+
+        let fields = HashMap::from([
+            ("title".to_string(), "Late parcel".to_string()),
+            ("body".to_string(), "Still waiting".to_string()),
+            ("lang".to_string(), "en".to_string()),
+        ]);
+        assert_eq!(template.render(&fields).unwrap(), "Late parcel [SEP] Still waiting {en}");
+        assert!(template.render(&HashMap::new()).is_err());
+
+        let json = serde_json::to_string(&template).unwrap();
+        assert_eq!(serde_json::from_str::<InputTemplate>(&json).unwrap(), template);
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        for template in ["{title", "{title}}", "title }", "{} text", "no fields", "{a{b}}"] {
+            assert!(InputTemplate::parse(template).is_err(), "{:?} should be rejected", template);
+        }
+        assert!(serde_json::from_str::<InputTemplate>("\"{title\"").is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+/// Names of the classes a model predicts, indexed by class id.
+///
+/// Class ids are the positions of the classification head's outputs, so they are always
+/// dense and start at 0; the map is what lets datasets, reports and predictions use names
+/// (`"billing"`, `"spam"`, or sparse numbers such as `"3"` and `"7"`) instead. It is saved
+/// as a JSON array of names in id order, e.g. `["billing", "shipping", "other"]`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LabelMap {
+    names: Vec<String>,
+}
+
+impl LabelMap {
+    pub fn new() -> Self {
+        LabelMap::default()
+    }
+
+    /// A map whose names are the given names, in id order.
+    ///
+    /// # Returns
+    /// * An error if a name is repeated.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, Box<dyn Error>> {
+        let mut label_map = LabelMap::new();
+        for name in names {
+            let name = name.as_ref();
+            if label_map.id(name).is_some() {
+                return Err(format!("Duplicate label name '{}'", name).into());
+            }
+            label_map.add(name);
+        }
+        Ok(label_map)
+    }
+
+    /// The map of a dataset with numeric labels `0..num_classes`: every class is named by its id.
+    pub fn numeric(num_classes: usize) -> Self {
+        LabelMap { names: (0..num_classes).map(|id| id.to_string()).collect() }
+    }
+
+    /// A map of the label values found in a dataset. Numeric values come first, in numeric
+    /// order, then the other names alphabetically, so the same labels always get the same ids
+    /// and datasets labelled `0..n` keep their ids.
+    pub fn from_values<S: AsRef<str>>(values: &[S]) -> Self {
+        let mut numeric: Vec<(u64, &str)> = Vec::new();
+        let mut named: Vec<&str> = Vec::new();
+        for value in values {
+            let value = value.as_ref().trim();
+            match value.parse::<u64>() {
+                Ok(number) => numeric.push((number, value)),
+                Err(_) => named.push(value),
+            }
+        }
+        numeric.sort();
+        numeric.dedup_by_key(|(number, _)| *number);
+        named.sort_unstable();
+        named.dedup();
+
+        let names = numeric.into_iter().map(|(_, value)| value).chain(named).map(str::to_string).collect();
+        LabelMap { names }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Class names, in id order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|existing| existing == name)
+    }
+
+    pub fn name(&self, id: usize) -> Option<&str> {
+        self.names.get(id).map(String::as_str)
+    }
+
+    /// Name of class `id` for reports; the id itself for classes beyond the map.
+    pub fn display_name(&self, id: usize) -> String {
+        self.name(id).map_or_else(|| id.to_string(), str::to_string)
+    }
+
+    /// Class id of a dataset label value: the class with that name. Numbers are names too, so
+    /// in a map of `["3", "7"]` the label `"0"` is unknown rather than class 0.
+    ///
+    /// # Returns
+    /// * An error naming the known labels if no class has that name.
+    pub fn resolve(&self, value: &str) -> Result<usize, Box<dyn Error>> {
+        let value = value.trim();
+        self.id(value).ok_or_else(|| format!("Unknown label '{}'; known labels: {}", value, self.names.join(", ")).into())
+    }
+
+    /// Adds a class at the next id.
+    ///
+    /// # Returns
+    /// * The class id; existing names keep their id.
+    pub fn add(&mut self, name: &str) -> usize {
+        if let Some(id) = self.id(name) {
+            return id;
+        }
+        self.names.push(name.to_string());
+        self.names.len() - 1
+    }
+
+    /// Renames a class; its id is unchanged, so models trained with the map stay valid.
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), Box<dyn Error>> {
+        let id = self.id(name).ok_or_else(|| format!("Unknown label '{}'", name))?;
+        if name != new_name && self.id(new_name).is_some() {
+            return Err(format!("Label '{}' already exists; merge the classes instead", new_name).into());
+        }
+        self.names[id] = new_name.to_string();
+        Ok(())
+    }
+
+    /// Merges classes into `into`, which keeps its name. Merged classes are removed and the
+    /// remaining classes are renumbered densely in their previous order.
+    ///
+    /// # Arguments
+    /// * `names` - Classes to merge; `into` may be among them.
+    /// * `into` - The class that receives them; created at the end of the map if missing.
+    ///
+    /// # Returns
+    /// * The new id of every previous class, indexed by previous id, for remapping labels
+    ///   and classification heads.
+    pub fn merge(&mut self, names: &[&str], into: &str) -> Result<Vec<usize>, Box<dyn Error>> {
+        for name in names {
+            if self.id(name).is_none() {
+                return Err(format!("Unknown label '{}'", name).into());
+            }
+        }
+        let previous_len = self.len();
+        let target = self.add(into);
+        let merged = |id: usize| id != target && names.contains(&self.names[id].as_str());
+
+        let mut remap = vec![0; self.len()];
+        let mut names_kept = Vec::with_capacity(self.len());
+        for (id, new_id) in remap.iter_mut().enumerate() {
+            if !merged(id) {
+                *new_id = names_kept.len();
+                names_kept.push(self.names[id].clone());
+            }
+        }
+        let target_id = remap[target];
+        for (id, new_id) in remap.iter_mut().enumerate() {
+            if merged(id) {
+                *new_id = target_id;
+            }
+        }
+        self.names = names_kept;
+        remap.truncate(previous_len);
+        Ok(remap)
+    }
+
+    /// Removes classes; the remaining classes are renumbered densely in their previous order.
+    ///
+    /// # Returns
+    /// * The new id of every previous class, indexed by previous id; `None` for removed classes.
+    pub fn remove(&mut self, names: &[&str]) -> Result<Vec<Option<usize>>, Box<dyn Error>> {
+        for name in names {
+            if self.id(name).is_none() {
+                return Err(format!("Unknown label '{}'", name).into());
+            }
+        }
+        let mut remap = Vec::with_capacity(self.len());
+        let mut names_kept = Vec::with_capacity(self.len());
+        for name in &self.names {
+            if names.contains(&name.as_str()) {
+                remap.push(None);
+            } else {
+                remap.push(Some(names_kept.len()));
+                names_kept.push(name.clone());
+            }
+        }
+        self.names = names_kept;
+        Ok(remap)
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), std::io::Error> {
+        fs::write(file_path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Loads a map saved with `save`.
+    ///
+    /// # Returns
+    /// * An error if the file is not a JSON array of distinct names.
+    pub fn load(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        let names: Vec<String> = serde_json::from_str(&fs::read_to_string(file_path)?)
+            .map_err(|e| format!("Failed to read label map {}: {}", file_path, e))?;
+        LabelMap::from_names(&names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::temp_path;
+
+    #[test]
+    fn test_from_values_and_resolve() {
+        let label_map = LabelMap::from_values(&["spam", "7", "ham", "3", "spam", " 7"]);
+        assert_eq!(label_map.names(), ["3", "7", "ham", "spam"]);
+        assert_eq!(label_map.resolve("ham").unwrap(), 2);
+        assert_eq!(label_map.resolve("7").unwrap(), 1);
+        // Numbers that are not names are unknown, not class ids.
+        assert!(label_map.resolve("0").is_err());
+        assert!(label_map.resolve("eggs").unwrap_err().to_string().contains("known labels: 3, 7, ham, spam"));
+        assert!(label_map.resolve("9").is_err());
+
+        assert_eq!(LabelMap::from_values(&["1", "0", "1"]), LabelMap::numeric(2));
+    }
+
+    #[test]
+    fn test_add_rename_and_merge() {
+        let mut label_map = LabelMap::from_names(&["billing", "refunds", "shipping", "returns"]).unwrap();
+        assert_eq!(label_map.add("other"), 4);
+        assert_eq!(label_map.add("billing"), 0);
+        label_map.rename("shipping", "delivery").unwrap();
+        assert_eq!(label_map.id("delivery"), Some(2));
+        assert!(label_map.rename("delivery", "billing").is_err());
+        assert!(LabelMap::from_names(&["a", "a"]).is_err());
+
+        let remap = label_map.merge(&["refunds", "returns"], "billing").unwrap();
+        assert_eq!(label_map.names(), ["billing", "delivery", "other"]);
+        assert_eq!(remap, vec![0, 0, 1, 0, 2]);
+
+        // Merging into a new class appends it.
+        let remap = label_map.merge(&["billing", "other"], "account").unwrap();
+        assert_eq!(label_map.names(), ["delivery", "account"]);
+        assert_eq!(remap, vec![1, 0, 1]);
+        assert!(label_map.merge(&["missing"], "delivery").is_err());
+
+        let mut removed = label_map.clone();
+        assert_eq!(removed.remove(&["delivery"]).unwrap(), vec![None, Some(0)]);
+        assert_eq!(removed.names(), ["account"]);
+        assert!(removed.remove(&["delivery"]).is_err());
+
+        let path = &temp_path("label_map_test.json");
+        label_map.save(path).unwrap();
+        let loaded = LabelMap::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded, label_map);
+    }
+}
@@ -0,0 +1,130 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// BERT-style token corruption for masked language modelling.
+///
+/// Each non-PAD token is selected with `mask_probability`. A selected token is
+/// replaced by `[MASK]` 80% of the time, by a random vocabulary token 10% of the
+/// time, and kept unchanged otherwise; the model must recover the original id.
+pub struct TokenMasker {
+    pub mask_id: usize,
+    pub pad_id: usize,
+    pub vocab_size: usize,
+    pub mask_probability: f64,
+}
+
+impl TokenMasker {
+    pub fn new(mask_id: usize, pad_id: usize, vocab_size: usize, mask_probability: f64) -> Self {
+        assert!((0.0..=1.0).contains(&mask_probability), "Mask probability must be in [0, 1].");
+        TokenMasker { mask_id, pad_id, vocab_size, mask_probability }
+    }
+
+    /// Corrupts a padded sequence.
+    ///
+    /// # Arguments
+    /// * `sequence` - Token ids of one padded sequence.
+    /// * `rng` - Random number generator deciding which tokens are masked.
+    ///
+    /// # Returns
+    /// * The corrupted sequence and, for every position, the original token id if
+    ///   the position was selected for prediction.
+    pub fn mask<R: Rng>(&self, sequence: &[usize], rng: &mut R) -> (Vec<usize>, Vec<Option<usize>>) {
+        let mut masked = sequence.to_vec();
+        let mut targets = vec![None; sequence.len()];
+
+        for (position, &token) in sequence.iter().enumerate() {
+            if token == self.pad_id || !rng.gen_bool(self.mask_probability) {
+                continue;
+            }
+            targets[position] = Some(token);
+
+            let roll: f64 = rng.gen();
+            if roll < 0.8 {
+                masked[position] = self.mask_id;
+            } else if roll < 0.9 {
+                masked[position] = rng.gen_range(0..self.vocab_size);
+            }
+        }
+
+        (masked, targets)
+    }
+}
+
+/// What word dropout does with a selected token.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WordDropoutMode {
+    /// Replaces it with `[UNK]`, keeping the positions of the other tokens.
+    Unk,
+    /// Removes it; the following tokens move up and the sequence is padded at the end.
+    Drop,
+}
+
+/// Input-level regularizer for training: every non-PAD token is selected with `probability`
+/// and replaced or removed according to `mode`, so the classifier cannot rely on single
+/// words of small datasets.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WordDropout {
+    pub probability: f64,
+    pub mode: WordDropoutMode,
+}
+
+impl WordDropout {
+    /// Corrupts a padded sequence.
+    ///
+    /// # Arguments
+    /// * `sequence` - Token ids of one padded sequence.
+    /// * `unk_id` / `pad_id` - Ids of `[UNK]` and `[PAD]`.
+    /// * `protected` - Ids that are never selected, e.g. `[CLS]` and `[SEP]`.
+    ///
+    /// # Returns
+    /// * The corrupted sequence, as long as `sequence`.
+    pub fn apply<R: Rng>(&self, sequence: &[usize], unk_id: usize, pad_id: usize, protected: &[usize], rng: &mut R) -> Vec<usize> {
+        let mut corrupted = Vec::with_capacity(sequence.len());
+        for &token in sequence {
+            let selected = token != pad_id && !protected.contains(&token) && rng.gen::<f64>() < self.probability;
+            match (selected, self.mode) {
+                (false, _) => corrupted.push(token),
+                (true, WordDropoutMode::Unk) => corrupted.push(unk_id),
+                (true, WordDropoutMode::Drop) => {}
+            }
+        }
+        corrupted.resize(sequence.len(), pad_id);
+        corrupted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_mask_never_selects_padding() {
+        let masker = TokenMasker::new(9, 0, 10, 1.0);
+        let (masked, targets) = masker.mask(&[3, 4, 5, 0, 0], &mut StdRng::seed_from_u64(1));
+
+        assert_eq!(targets, vec![Some(3), Some(4), Some(5), None, None]);
+        assert_eq!(&masked[3..], &[0, 0]);
+    }
+
+    #[test]
+    fn test_mask_probability_zero_keeps_sequence() {
+        let masker = TokenMasker::new(9, 0, 10, 0.0);
+        let (masked, targets) = masker.mask(&[3, 4, 5], &mut StdRng::seed_from_u64(1));
+
+        assert_eq!(masked, vec![3, 4, 5]);
+        assert!(targets.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_word_dropout_modes() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let all = |mode| WordDropout { probability: 1.0, mode };
+        assert_eq!(all(WordDropoutMode::Unk).apply(&[7, 3, 4, 8, 0], 1, 0, &[7, 8], &mut rng), vec![7, 1, 1, 8, 0]);
+        assert_eq!(all(WordDropoutMode::Drop).apply(&[7, 3, 4, 8, 0], 1, 0, &[7, 8], &mut rng), vec![7, 8, 0, 0, 0]);
+
+        let none = WordDropout { probability: 0.0, mode: WordDropoutMode::Drop };
+        assert_eq!(none.apply(&[3, 4, 0], 1, 0, &[], &mut rng), vec![3, 4, 0]);
+    }
+}
@@ -1 +1,11 @@
 pub mod data_loader;
+pub mod label_map;
+pub mod dataset_analysis;
+pub mod batch_sampler;
+pub mod sentence_pairs;
+pub mod masking;
+pub mod synthetic;
+pub mod parallel_loader;
+pub mod cpu_affinity;
+pub mod sliding_window;
+pub mod input_template;
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+use crate::data_handler::cpu_affinity::pin_worker;
+
+/// Number of finished chunks that may wait in the queue before workers block.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 8;
+
+/// Processes `items` in chunks on worker threads and hands the results to `consume` in
+/// chunk order while the workers keep going.
+///
+/// Workers claim chunks through an atomic counter and send results through a bounded
+/// channel, which blocks a worker once `queue_capacity` finished chunks are waiting. Items,
+/// tokenizer and caches are shared by reference, so nothing is copied or serialized between
+/// workers. Results that arrive out of order are held back until their predecessors
+/// arrive, which keeps the output identical to a sequential run; those held-back results
+/// come on top of the queue.
+///
+/// # Arguments
+/// * `items` - Inputs, e.g. raw texts.
+/// * `chunk_size` - Number of items per chunk, e.g. the batch size.
+/// * `num_workers` - Number of worker threads. `0` and `1` process on the calling thread.
+/// * `queue_capacity` - Maximum number of finished chunks waiting in the channel.
+/// * `cores` - Cores the workers are pinned to, worker `i` to `cores[i % cores.len()]`
+///   (see `cpu_affinity`). Empty leaves the workers to the OS scheduler.
+/// * `work` - Turns a chunk into a result, e.g. tokenizes and pads it.
+/// * `consume` - Receives `(chunk_index, result)` in increasing chunk order.
+pub fn process_in_workers<T, R, W, C>(
+    items: &[T],
+    chunk_size: usize,
+    num_workers: usize,
+    queue_capacity: usize,
+    cores: &[usize],
+    work: W,
+    mut consume: C,
+) where
+    T: Sync,
+    R: Send,
+    W: Fn(&[T]) -> R + Sync,
+    C: FnMut(usize, R),
+{
+    let _ = try_process_in_workers(items, chunk_size, num_workers, queue_capacity, cores, work, |index, result| {
+        consume(index, result);
+        ControlFlow::Continue(())
+    });
+}
+
+/// Same as `process_in_workers`, stopping once `consume` returns `ControlFlow::Break`:
+/// workers finish the chunk they are on and claim no further ones.
+///
+/// # Returns
+/// * `ControlFlow::Break` if `consume` stopped early, `ControlFlow::Continue` otherwise.
+pub fn try_process_in_workers<T, R, W, C>(
+    items: &[T],
+    chunk_size: usize,
+    num_workers: usize,
+    queue_capacity: usize,
+    cores: &[usize],
+    work: W,
+    mut consume: C,
+) -> ControlFlow<()>
+where
+    T: Sync,
+    R: Send,
+    W: Fn(&[T]) -> R + Sync,
+    C: FnMut(usize, R) -> ControlFlow<()>,
+{
+    assert!(chunk_size > 0, "chunk_size must be positive.");
+    let chunks: Vec<&[T]> = items.chunks(chunk_size).collect();
+
+    if num_workers <= 1 {
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            consume(index, work(chunk))?;
+        }
+        return ControlFlow::Continue(());
+    }
+
+    let next_chunk = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+    let (sender, receiver) = sync_channel(queue_capacity.max(1));
+
+    thread::scope(|scope| {
+        for worker in 0..num_workers.min(chunks.len()) {
+            let sender = sender.clone();
+            let (chunks, next_chunk, stopped, work) = (&chunks, &next_chunk, &stopped, &work);
+            scope.spawn(move || {
+                pin_worker(cores, worker);
+                while !stopped.load(Ordering::Relaxed) {
+                    let index = next_chunk.fetch_add(1, Ordering::Relaxed);
+                    let Some(chunk) = chunks.get(index) else {
+                        break;
+                    };
+                    if sender.send((index, work(chunk))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // Only the workers' senders remain, so the receiver ends when they are done.
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        for (index, result) in receiver.iter() {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&expected) {
+                expected += 1;
+                if consume(expected - 1, result).is_break() {
+                    // Dropping the receiver fails the sends of workers blocked on a full queue.
+                    stopped.store(true, Ordering::Relaxed);
+                    drop(receiver);
+                    return ControlFlow::Break(());
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    })
+}
+
+/// Same as `process_in_workers`, collecting the results in chunk order.
+pub fn map_in_workers<T, R, W>(items: &[T], chunk_size: usize, num_workers: usize, cores: &[usize], work: W) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    W: Fn(&[T]) -> R + Sync,
+{
+    let mut results = Vec::new();
+    process_in_workers(items, chunk_size, num_workers, DEFAULT_QUEUE_CAPACITY, cores, work, |_, result| {
+        results.push(result)
+    });
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_arrive_in_chunk_order() {
+        let items: Vec<usize> = (0..100).collect();
+        let mut seen = Vec::new();
+        process_in_workers(&items, 7, 4, 2, &[], |chunk| chunk.iter().sum::<usize>(), |index, sum| {
+            seen.push((index, sum))
+        });
+
+        let expected: Vec<(usize, usize)> = items.chunks(7).map(|c| c.iter().sum()).enumerate().collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let texts: Vec<String> = (0..50).map(|i| format!("text {}", i)).collect();
+        let work = |chunk: &[String]| chunk.iter().map(|t| t.len()).collect::<Vec<_>>();
+
+        assert_eq!(map_in_workers(&texts, 4, 1, &[], work), map_in_workers(&texts, 4, 8, &[0], work));
+    }
+
+    #[test]
+    fn test_consumer_stops_the_workers() {
+        let items: Vec<usize> = (0..1000).collect();
+        let mut seen = Vec::new();
+        let flow = try_process_in_workers(&items, 1, 4, 2, &[], |chunk| chunk[0], |index, item| {
+            seen.push(item);
+            if index == 2 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        });
+
+        assert!(flow.is_break());
+        assert_eq!(seen, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_more_workers_than_chunks() {
+        let results = map_in_workers(&[1, 2, 3], 2, 16, &[], |chunk: &[i32]| chunk.len());
+        assert_eq!(results, vec![2, 1]);
+    }
+}
@@ -0,0 +1,72 @@
+use rand::Rng;
+
+/// Label of a sentence pair that appears in its original order.
+pub const IN_ORDER: usize = 0;
+/// Label of a sentence pair whose sentences were swapped.
+pub const SWAPPED: usize = 1;
+
+/// Splits a text into sentences on `.`, `!` and `?`, dropping empty pieces.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    text.split(['.', '!', '?'])
+        .map(str::trim)
+        .filter(|sentence| !sentence.is_empty())
+        .map(|sentence| sentence.to_string())
+        .collect()
+}
+
+/// Builds sentence-order prediction examples from unlabeled texts.
+///
+/// Every pair of consecutive sentences within a text becomes one example. With
+/// probability 0.5 the two sentences are swapped, so the model has to learn
+/// whether the pair reads in its original order.
+///
+/// # Arguments
+/// * `texts` - Unlabeled texts; texts with a single sentence yield no pairs.
+/// * `rng` - Random number generator deciding which pairs are swapped.
+///
+/// # Returns
+/// * The sentence pairs and their labels (`IN_ORDER` or `SWAPPED`).
+pub fn sentence_order_pairs<R: Rng>(texts: &[String], rng: &mut R) -> (Vec<(String, String)>, Vec<usize>) {
+    let mut pairs = Vec::new();
+    let mut labels = Vec::new();
+
+    for text in texts {
+        let sentences = split_sentences(text);
+        for window in sentences.windows(2) {
+            if rng.gen_bool(0.5) {
+                pairs.push((window[1].clone(), window[0].clone()));
+                labels.push(SWAPPED);
+            } else {
+                pairs.push((window[0].clone(), window[1].clone()));
+                labels.push(IN_ORDER);
+            }
+        }
+    }
+
+    (pairs, labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_split_sentences() {
+        let sentences = split_sentences("The food was great. Service was slow!  Would I return?");
+        assert_eq!(sentences, vec!["The food was great", "Service was slow", "Would I return"]);
+    }
+
+    #[test]
+    fn test_sentence_order_pairs_labels_match_order() {
+        let texts = vec!["One. Two. Three.".to_string(), "Single sentence".to_string()];
+        let (pairs, labels) = sentence_order_pairs(&texts, &mut StdRng::seed_from_u64(7));
+
+        assert_eq!(pairs.len(), 2);
+        for ((first, second), label) in pairs.iter().zip(labels.iter()) {
+            let in_order = (first == "One" && second == "Two") || (first == "Two" && second == "Three");
+            assert_eq!(*label == IN_ORDER, in_order);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// How the window predictions of one document are combined at evaluation.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WindowWeighting {
+    /// Every window counts the same.
+    Uniform,
+    /// Windows count by the tokens they add beyond the previous window, so overlapping
+    /// tokens are not counted twice and every token of the document has the same say.
+    NewTokens,
+}
+
+/// Expands documents longer than `max_seq_length` into overlapping windows that share the
+/// document's label, so training sees every part of a long text instead of its truncation.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SlidingWindow {
+    /// Tokens between the starts of consecutive windows; windows overlap by
+    /// `max_seq_length - stride` tokens. Clamped to `1..=max_seq_length`.
+    pub stride: usize,
+    pub weighting: WindowWeighting,
+}
+
+impl SlidingWindow {
+    /// Token ranges of the windows covering a document of `len` tokens.
+    ///
+    /// # Returns
+    /// * One range per window of at most `max_len` tokens, starting every `stride` tokens;
+    ///   the last window ends at the document's end. Documents that fit get a single window.
+    pub fn windows(&self, len: usize, max_len: usize) -> Vec<Range<usize>> {
+        if len <= max_len {
+            return vec![Range { start: 0, end: len }];
+        }
+        let stride = self.stride.clamp(1, max_len.max(1));
+        let last_start = len - max_len;
+        let mut windows: Vec<Range<usize>> = (0..last_start).step_by(stride).map(|start| start..start + max_len).collect();
+        windows.push(last_start..len);
+        windows
+    }
+
+    /// Weight of a window that adds `new_tokens` tokens beyond the previous window.
+    pub fn weight(&self, new_tokens: usize) -> f64 {
+        match self.weighting {
+            WindowWeighting::Uniform => 1.0,
+            WindowWeighting::NewTokens => new_tokens as f64,
+        }
+    }
+}
+
+/// A labelled dataset expanded into windows, one padded sequence per window.
+pub struct WindowedDataset {
+    pub inputs: Vec<Vec<usize>>,
+    /// Label of every window: the label of its document.
+    pub labels: Vec<usize>,
+    /// Index of the document every window comes from.
+    pub documents: Vec<usize>,
+    /// Tokens every window adds beyond the previous window of its document.
+    pub new_tokens: Vec<usize>,
+    /// Label of every document.
+    pub document_labels: Vec<usize>,
+}
+
+impl WindowedDataset {
+    /// Splits tokenized documents into padded windows.
+    ///
+    /// # Arguments
+    /// * `documents` - Unpadded token ids of every document.
+    /// * `labels` - Label of every document.
+    /// * `pad` - Pads a window to `max_seq_length`, e.g. `Tokenizer::pad_sequence`.
+    pub fn new<F>(documents: &[Vec<usize>], labels: &[usize], window: &SlidingWindow, max_len: usize, pad: F) -> Self
+    where
+        F: Fn(Vec<usize>) -> Vec<usize>,
+    {
+        let mut dataset = WindowedDataset {
+            inputs: Vec::new(),
+            labels: Vec::new(),
+            documents: Vec::new(),
+            new_tokens: Vec::new(),
+            document_labels: labels.to_vec(),
+        };
+        for (document, (tokens, &label)) in documents.iter().zip(labels).enumerate() {
+            let mut covered = 0;
+            for range in window.windows(tokens.len(), max_len) {
+                dataset.new_tokens.push(range.end - range.start.max(covered));
+                covered = range.end;
+                dataset.inputs.push(pad(tokens[range].to_vec()));
+                dataset.labels.push(label);
+                dataset.documents.push(document);
+            }
+        }
+        dataset
+    }
+
+    /// Combines window predictions into one prediction per document.
+    ///
+    /// # Arguments
+    /// * `probabilities` - Class probabilities of every window. Shape: [num_windows, num_classes].
+    ///
+    /// # Returns
+    /// * The weighted mean of each document's window probabilities. Shape: [num_documents, num_classes].
+    pub fn aggregate(&self, probabilities: &Array2<f64>, window: &SlidingWindow) -> Array2<f64> {
+        let mut combined = Array2::zeros((self.document_labels.len(), probabilities.ncols()));
+        let mut total_weight = vec![0.0; self.document_labels.len()];
+        for (row, (&document, &new_tokens)) in probabilities.outer_iter().zip(self.documents.iter().zip(&self.new_tokens)) {
+            let weight = window.weight(new_tokens);
+            combined.row_mut(document).scaled_add(weight, &row);
+            total_weight[document] += weight;
+        }
+        for (mut row, weight) in combined.outer_iter_mut().zip(total_weight) {
+            if weight > 0.0 {
+                row /= weight;
+            }
+        }
+        combined
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_windows_cover_the_document() {
+        let window = SlidingWindow { stride: 3, weighting: WindowWeighting::Uniform };
+        assert_eq!(window.windows(4, 4), vec![0..4]);
+        assert_eq!(window.windows(10, 4), vec![0..4, 3..7, 6..10]);
+        assert_eq!(window.windows(9, 4), vec![0..4, 3..7, 5..9]);
+        // A stride beyond the window length would skip tokens.
+        let wide = SlidingWindow { stride: 10, ..window };
+        assert_eq!(wide.windows(9, 4), vec![0..4, 4..8, 5..9]);
+    }
+
+    #[test]
+    fn test_windows_share_the_label_and_are_aggregated() {
+        let window = SlidingWindow { stride: 3, weighting: WindowWeighting::NewTokens };
+        let documents = vec![vec![1, 2, 3, 4, 5], vec![6]];
+        let dataset = WindowedDataset::new(&documents, &[1, 0], &window, 4, |mut tokens| {
+            tokens.resize(4, 0);
+            tokens
+        });
+
+        assert_eq!(dataset.inputs, vec![vec![1, 2, 3, 4], vec![2, 3, 4, 5], vec![6, 0, 0, 0]]);
+        assert_eq!(dataset.labels, vec![1, 1, 0]);
+        assert_eq!(dataset.documents, vec![0, 0, 1]);
+        assert_eq!(dataset.new_tokens, vec![4, 1, 1]);
+
+        let probabilities = array![[0.2, 0.8], [0.7, 0.3], [0.9, 0.1]];
+        let combined = dataset.aggregate(&probabilities, &window);
+        assert_eq!(combined.dim(), (2, 2));
+        assert!((combined[[0, 1]] - (4.0 * 0.8 + 0.3) / 5.0).abs() < 1e-12);
+        assert!((combined[[1, 0]] - 0.9).abs() < 1e-12);
+        let uniform = dataset.aggregate(&probabilities, &SlidingWindow { weighting: WindowWeighting::Uniform, ..window });
+        assert!((uniform[[0, 1]] - 0.55).abs() < 1e-12);
+    }
+}
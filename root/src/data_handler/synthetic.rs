@@ -0,0 +1,178 @@
+use rand::Rng;
+use serde_json::json;
+use std::error::Error;
+
+/// Parameters of a synthetic classification dataset.
+#[derive(Debug, Clone)]
+pub struct SyntheticConfig {
+    pub num_examples: usize,
+    pub num_classes: usize,
+    /// Number of distinct words. Words are named `w0`, `w1`, ...
+    pub vocab_size: usize,
+    /// Probability in [0, 1] that a token is drawn from its class's own words
+    /// instead of the shared words. 0 gives indistinguishable classes, 1 perfectly separable ones.
+    pub separability: f64,
+    /// Text lengths in words are drawn uniformly from `min_length..=max_length`.
+    pub min_length: usize,
+    pub max_length: usize,
+}
+
+impl Default for SyntheticConfig {
+    fn default() -> Self {
+        SyntheticConfig {
+            num_examples: 100,
+            num_classes: 2,
+            vocab_size: 50,
+            separability: 0.8,
+            min_length: 3,
+            max_length: 12,
+        }
+    }
+}
+
+/// A generated classification dataset, used by tests and benchmarks instead of real data files.
+pub struct SyntheticDataset {
+    pub texts: Vec<String>,
+    pub labels: Vec<usize>,
+}
+
+impl SyntheticDataset {
+    /// Generates a dataset from the given parameters.
+    ///
+    /// Word `w{i}` belongs to class `i % (num_classes + 1)`; the words of the extra
+    /// residue `num_classes` are shared by all classes. Each token of an example is
+    /// drawn from its class's words with probability `separability` and from the
+    /// shared words otherwise.
+    ///
+    /// # Arguments
+    /// * `config` - Dataset parameters.
+    /// * `rng` - Random number generator; a seeded one gives a reproducible dataset.
+    ///
+    /// # Returns
+    /// A new `SyntheticDataset` with `config.num_examples` examples, or an error if the
+    /// parameters cannot produce one.
+    pub fn generate<R: Rng>(config: &SyntheticConfig, rng: &mut R) -> Result<Self, Box<dyn Error>> {
+        if config.num_classes == 0 {
+            return Err("Synthetic datasets need at least one class.".into());
+        }
+        if config.vocab_size <= config.num_classes {
+            return Err(format!(
+                "vocab_size ({}) must exceed num_classes ({}) so every class and the shared pool have words.",
+                config.vocab_size, config.num_classes
+            )
+            .into());
+        }
+        if !(0.0..=1.0).contains(&config.separability) {
+            return Err(format!("separability must be in [0, 1], got {}.", config.separability).into());
+        }
+        if config.min_length == 0 || config.min_length > config.max_length {
+            return Err(format!(
+                "Lengths must satisfy 0 < min_length <= max_length, got {}..={}.",
+                config.min_length, config.max_length
+            )
+            .into());
+        }
+
+        let groups = config.num_classes + 1;
+        let words_of = |group: usize| -> Vec<String> {
+            (group..config.vocab_size).step_by(groups).map(|i| format!("w{}", i)).collect()
+        };
+        let class_words: Vec<Vec<String>> = (0..config.num_classes).map(words_of).collect();
+        let shared_words = words_of(config.num_classes);
+
+        let mut texts = Vec::with_capacity(config.num_examples);
+        let mut labels = Vec::with_capacity(config.num_examples);
+
+        for _ in 0..config.num_examples {
+            let label = rng.gen_range(0..config.num_classes);
+            let length = rng.gen_range(config.min_length..=config.max_length);
+
+            let words: Vec<&str> = (0..length)
+                .map(|_| {
+                    let pool = if shared_words.is_empty() || rng.gen_bool(config.separability) {
+                        &class_words[label]
+                    } else {
+                        &shared_words
+                    };
+                    pool[rng.gen_range(0..pool.len())].as_str()
+                })
+                .collect();
+
+            texts.push(words.join(" "));
+            labels.push(label);
+        }
+
+        Ok(SyntheticDataset { texts, labels })
+    }
+
+    /// Writes the dataset in the JSON format read by `DataLoader`.
+    pub fn save_json(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        let records: Vec<serde_json::Value> = self
+            .texts
+            .iter()
+            .zip(self.labels.iter())
+            .map(|(text, label)| json!({ "text": text, "label": label }))
+            .collect();
+        std::fs::write(file_path, serde_json::to_string_pretty(&records)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_generate_respects_config() {
+        let config = SyntheticConfig { num_examples: 40, num_classes: 3, ..SyntheticConfig::default() };
+        let dataset = SyntheticDataset::generate(&config, &mut StdRng::seed_from_u64(7)).unwrap();
+
+        assert_eq!(dataset.texts.len(), 40);
+        assert!(dataset.labels.iter().all(|&label| label < 3));
+        for text in &dataset.texts {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            assert!((config.min_length..=config.max_length).contains(&words.len()));
+            assert!(words.iter().all(|word| word[1..].parse::<usize>().unwrap() < config.vocab_size));
+        }
+    }
+
+    #[test]
+    fn test_fully_separable_classes_use_own_words() {
+        let config = SyntheticConfig { separability: 1.0, ..SyntheticConfig::default() };
+        let dataset = SyntheticDataset::generate(&config, &mut StdRng::seed_from_u64(1)).unwrap();
+
+        for (text, &label) in dataset.texts.iter().zip(dataset.labels.iter()) {
+            for word in text.split_whitespace() {
+                let index: usize = word[1..].parse().unwrap();
+                assert_eq!(index % (config.num_classes + 1), label);
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_dataset() {
+        let config = SyntheticConfig::default();
+        let first = SyntheticDataset::generate(&config, &mut StdRng::seed_from_u64(3)).unwrap();
+        let second = SyntheticDataset::generate(&config, &mut StdRng::seed_from_u64(3)).unwrap();
+
+        assert_eq!(first.texts, second.texts);
+        assert_eq!(first.labels, second.labels);
+    }
+
+    #[test]
+    fn test_invalid_config_is_an_error() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let invalid = [
+            SyntheticConfig { num_classes: 0, ..SyntheticConfig::default() },
+            SyntheticConfig { vocab_size: 2, ..SyntheticConfig::default() },
+            SyntheticConfig { separability: 1.5, ..SyntheticConfig::default() },
+            SyntheticConfig { min_length: 0, ..SyntheticConfig::default() },
+            SyntheticConfig { min_length: 5, max_length: 4, ..SyntheticConfig::default() },
+        ];
+        for config in &invalid {
+            assert!(SyntheticDataset::generate(config, &mut rng).is_err(), "{:?}", config);
+        }
+    }
+}
@@ -1,79 +1,454 @@
-use std::collections::HashMap;
-use ndarray::{Array2, Array};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use ndarray::{s, Array1, Array2};
 use ndarray_rand::RandomExt;
 use ndarray_rand::rand_distr::Uniform;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Serializer};
+use serde::ser::SerializeStruct;
 
-#[derive(Serialize, Deserialize)]
-pub struct Embeddings {
-    token_embedding_matrix: Array2<f64>,
+use crate::configurration::config::MAX_SEQ_LENGTH;
+use crate::quantization::embedding_compression::{CompressedMatrix, EmbeddingPrecision};
+use crate::layer_norm::{apply_layer_norm, layer_norm_backward};
+use crate::positional_encoding::{sinusoidal_encodings, SinusoidalVariant};
+use crate::numerics::Float;
+
+/// Rows of the segment embedding table: segment 0 is `[CLS] first [SEP]` (and padding),
+/// segment 1 is `second [SEP]` of a sentence pair, as in `EncodedBatch::token_type_ids`.
+pub const NUM_SEGMENTS: usize = 2;
+
+#[derive(Deserialize)]
+#[serde(try_from = "SavedEmbeddings<A>", bound = "A: Float")]
+pub struct Embeddings<A = f64> {
+    token_embedding_matrix: Array2<A>,
     vocab: HashMap<String, usize>,
     model_dim: usize,
+    /// How the matrix is written by `Serialize`; rows are always decoded to `A` in memory,
+    /// and `F64` writes them at that precision.
+    pub storage_precision: EmbeddingPrecision,
+    /// Multiplies token embeddings by `sqrt(model_dim)` before the positional encodings are
+    /// added, as in the original transformer. Checkpoints saved without it load as `false`.
+    pub scale_by_sqrt_d_model: bool,
+    /// Excludes the matrix from `parameters_mut` and `num_parameters`, so training leaves
+    /// it unchanged, e.g. when fine-tuning on pretrained vectors. A training setting, not
+    /// saved with the checkpoint.
+    pub frozen: bool,
+    /// Learned segment embeddings added to every position by `encode_with_segments`.
+    /// Shape: [NUM_SEGMENTS, model_dim]. `None` unless enabled with `with_segment_embeddings`.
+    segment_embedding_matrix: Option<Array2<A>>,
+    /// Epsilon of the layer norm over the summed token, positional and segment embeddings
+    /// (BERT's embedding block); `None` leaves the sum unnormalized.
+    pub layer_norm_epsilon: Option<A>,
+    /// Sinusoidal encodings added to every position. Checkpoints saved without it load as
+    /// `Standard`, the formula they were trained with.
+    positional_variant: SinusoidalVariant,
+    /// Positional encodings of the first positions, computed once so `encode` only slices
+    /// them. Starts at `MAX_SEQ_LENGTH` rows (see `reserve_positions`); longer inputs are
+    /// computed on the fly. Not saved with the checkpoint.
+    positional_cache: Array2<A>,
+}
+
+/// Serialized form of `Embeddings`: either the full matrix or its compressed rows.
+#[derive(Deserialize)]
+#[serde(bound = "A: Float")]
+struct SavedEmbeddings<A> {
+    #[serde(default)]
+    token_embedding_matrix: Option<Array2<A>>,
+    #[serde(default)]
+    compressed_embedding_matrix: Option<CompressedMatrix>,
+    vocab: HashMap<String, usize>,
+    model_dim: usize,
+    #[serde(default)]
+    scale_by_sqrt_d_model: bool,
+    #[serde(default)]
+    segment_embedding_matrix: Option<Array2<A>>,
+    #[serde(default)]
+    layer_norm_epsilon: Option<A>,
+    #[serde(default)]
+    positional_variant: SinusoidalVariant,
+}
+
+impl<A: Float> TryFrom<SavedEmbeddings<A>> for Embeddings<A> {
+    type Error = String;
+
+    fn try_from(saved: SavedEmbeddings<A>) -> Result<Self, String> {
+        let (token_embedding_matrix, storage_precision) = match (saved.token_embedding_matrix, saved.compressed_embedding_matrix) {
+            (Some(matrix), _) => (matrix, EmbeddingPrecision::F64),
+            (None, Some(compressed)) => (compressed.decompress()?.mapv(A::cast), compressed.precision),
+            (None, None) => (Array2::zeros((0, saved.model_dim)), EmbeddingPrecision::F64),
+        };
+        let positional_cache = positional_table(MAX_SEQ_LENGTH, saved.model_dim, saved.positional_variant);
+        Ok(Embeddings {
+            token_embedding_matrix,
+            vocab: saved.vocab,
+            model_dim: saved.model_dim,
+            storage_precision,
+            scale_by_sqrt_d_model: saved.scale_by_sqrt_d_model,
+            frozen: false,
+            segment_embedding_matrix: saved.segment_embedding_matrix,
+            layer_norm_epsilon: saved.layer_norm_epsilon,
+            positional_variant: saved.positional_variant,
+            positional_cache,
+        })
+    }
 }
 
-impl Embeddings {
+impl<A: Float> Serialize for Embeddings<A> {
+    /// Writes the matrix at the model's precision, or quantized per row when `storage_precision`
+    /// is `Int8` or `Int4`, which shrinks checkpoints of large vocabularies.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Embeddings", 7)?;
+        match self.storage_precision {
+            EmbeddingPrecision::F64 => state.serialize_field("token_embedding_matrix", &self.token_embedding_matrix)?,
+            precision => state.serialize_field(
+                "compressed_embedding_matrix",
+                &CompressedMatrix::compress(&self.token_embedding_matrix.mapv(A::as_f64), precision),
+            )?,
+        }
+        state.serialize_field("vocab", &self.vocab)?;
+        state.serialize_field("model_dim", &self.model_dim)?;
+        state.serialize_field("scale_by_sqrt_d_model", &self.scale_by_sqrt_d_model)?;
+        state.serialize_field("segment_embedding_matrix", &self.segment_embedding_matrix)?;
+        state.serialize_field("layer_norm_epsilon", &self.layer_norm_epsilon)?;
+        state.serialize_field("positional_variant", &self.positional_variant)?;
+        state.end()
+    }
+}
+
+impl<A: Float> Embeddings<A> {
     /// Creates a new `Embeddings` instance.
     pub fn new(vocab: HashMap<String, usize>, model_dim: usize) -> Self {
         let vocab_size = vocab.len();
-        let token_embedding_matrix = Array2::random((vocab_size, model_dim), Uniform::new(-0.1, 0.1));
+        let token_embedding_matrix = Array2::random((vocab_size, model_dim), Uniform::new(A::cast(-0.1), A::cast(0.1)));
         Embeddings {
             token_embedding_matrix,
             vocab,
             model_dim,
+            storage_precision: EmbeddingPrecision::F64,
+            scale_by_sqrt_d_model: false,
+            frozen: false,
+            segment_embedding_matrix: None,
+            layer_norm_epsilon: None,
+            positional_variant: SinusoidalVariant::Standard,
+            positional_cache: positional_table(MAX_SEQ_LENGTH, model_dim, SinusoidalVariant::Standard),
         }
     }
 
-    
-    pub fn generate_positional_encodings(&self, seq_len: usize) -> Array2<f64> {
-        let mut positional_encodings = Array2::zeros((seq_len, self.model_dim));
-
-        for pos in 0..seq_len {
-            for i in 0..self.model_dim {
-                let angle = pos as f64 / 10000f64.powf((2 * (i / 2)) as f64 / self.model_dim as f64);
-                positional_encodings[[pos, i]] = if i % 2 == 0 {
-                    angle.sin()
-                } else {
-                    angle.cos()
-                };
+    /// Creates `Embeddings` from an existing token embedding matrix, e.g. imported weights.
+    ///
+    /// # Arguments
+    /// * `token_embedding_matrix` - One row per token id. Shape: [vocab_size, model_dim].
+    /// * `vocab` - Token to id mapping; every id must index a row of the matrix.
+    pub fn from_matrix(token_embedding_matrix: Array2<A>, vocab: HashMap<String, usize>) -> Self {
+        let model_dim = token_embedding_matrix.ncols();
+        Embeddings {
+            token_embedding_matrix,
+            vocab,
+            model_dim,
+            storage_precision: EmbeddingPrecision::F64,
+            scale_by_sqrt_d_model: false,
+            frozen: false,
+            segment_embedding_matrix: None,
+            layer_norm_epsilon: None,
+            positional_variant: SinusoidalVariant::Standard,
+            positional_cache: positional_table(MAX_SEQ_LENGTH, model_dim, SinusoidalVariant::Standard),
+        }
+    }
+
+    /// Enables or disables the `sqrt(model_dim)` scaling of token embeddings in `encode`.
+    pub fn with_sqrt_d_model_scaling(mut self, enabled: bool) -> Self {
+        self.scale_by_sqrt_d_model = enabled;
+        self
+    }
+
+    /// Adds a learned segment embedding table, so `encode_with_segments` can tell the two
+    /// texts of a sentence pair apart.
+    pub fn with_segment_embeddings(mut self) -> Self {
+        self.segment_embedding_matrix = Some(Array2::random((NUM_SEGMENTS, self.model_dim), Uniform::new(A::cast(-0.1), A::cast(0.1))));
+        self
+    }
+
+    /// Normalizes every summed embedding with a layer norm (no learned scale or shift, like
+    /// the encoder layers) before it enters the first encoder layer.
+    pub fn with_layer_norm(mut self, epsilon: A) -> Self {
+        self.layer_norm_epsilon = Some(epsilon);
+        self
+    }
+
+    /// Selects the sinusoidal positional encodings `encode` adds.
+    pub fn with_positional_variant(mut self, variant: SinusoidalVariant) -> Self {
+        self.positional_variant = variant;
+        self.positional_cache = positional_table(self.positional_cache.nrows(), self.model_dim, variant);
+        self
+    }
+
+    /// Extends the positional encoding cache to at least `max_positions` rows, e.g. to the
+    /// `max_seq_length` of a tokenizer configured above `MAX_SEQ_LENGTH`.
+    pub fn reserve_positions(&mut self, max_positions: usize) {
+        if max_positions > self.positional_cache.nrows() {
+            self.positional_cache = positional_table(max_positions, self.model_dim, self.positional_variant);
+        }
+    }
+
+    /// Segment embedding table, if enabled. Shape: [NUM_SEGMENTS, model_dim].
+    pub fn segment_embedding_matrix(&self) -> Option<&Array2<A>> {
+        self.segment_embedding_matrix.as_ref()
+    }
+
+    /// Factor `encode` multiplies token embeddings by: `sqrt(model_dim)` when
+    /// `scale_by_sqrt_d_model` is set, otherwise 1.
+    pub fn input_scale(&self) -> A {
+        if self.scale_by_sqrt_d_model {
+            A::cast(self.model_dim as f64).sqrt()
+        } else {
+            A::one()
+        }
+    }
+
+    /// Rescales embedding rows by corpus frequency so rare tokens start with a smaller norm.
+    ///
+    /// Each row is multiplied by `min_scale + (1 - min_scale) * ln(1 + c) / ln(1 + c_max)`,
+    /// where `c` is the token's count and `c_max` the largest count. Tokens without a
+    /// count (e.g. special tokens) get `min_scale`, which must be in [0, 1].
+    pub fn scale_by_frequency(&mut self, token_counts: &HashMap<String, usize>, min_scale: f64) -> Result<(), Box<dyn Error>> {
+        if !(0.0..=1.0).contains(&min_scale) {
+            return Err(format!("min_scale must be in [0, 1], got {}", min_scale).into());
+        }
+
+        let max_log_count = token_counts
+            .values()
+            .map(|&count| (1.0 + count as f64).ln())
+            .fold(0.0, f64::max);
+
+        for (token, &idx) in &self.vocab {
+            if idx >= self.token_embedding_matrix.nrows() {
+                continue;
             }
+            let frequency = match token_counts.get(token) {
+                Some(&count) if max_log_count > 0.0 => (1.0 + count as f64).ln() / max_log_count,
+                _ => 0.0,
+            };
+            let scale = A::cast(min_scale + (1.0 - min_scale) * frequency);
+            self.token_embedding_matrix.row_mut(idx).mapv_inplace(|x| x * scale);
+        }
+        Ok(())
+    }
+
+    pub fn vocab(&self) -> &HashMap<String, usize> {
+        &self.vocab
+    }
+
+    /// Token embedding matrix. Shape: [vocab_size, model_dim].
+    pub fn token_embedding_matrix(&self) -> &Array2<A> {
+        &self.token_embedding_matrix
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.token_embedding_matrix.nrows()
+    }
+
+    /// Adds a token with a freshly initialised embedding row, e.g. `[MASK]` before
+    /// masked language modelling on a checkpoint that was trained without it.
+    ///
+    /// # Returns
+    /// The token's index; existing tokens are left untouched.
+    pub fn add_token(&mut self, token: &str) -> usize {
+        if let Some(&idx) = self.vocab.get(token) {
+            return idx;
+        }
+        let row = Array2::random((1, self.model_dim), Uniform::new(A::cast(-0.1), A::cast(0.1)));
+        self.token_embedding_matrix.push_row(row.row(0)).unwrap();
+        let idx = self.token_embedding_matrix.nrows() - 1;
+        self.vocab.insert(token.to_string(), idx);
+        idx
+    }
+
+    /// Positional encodings of the first `seq_len` positions, in `positional_variant`.
+    /// Shape: [seq_len, model_dim].
+    pub fn generate_positional_encodings(&self, seq_len: usize) -> Array2<A> {
+        if seq_len <= self.positional_cache.nrows() {
+            self.positional_cache.slice(s![..seq_len, ..]).to_owned()
+        } else {
+            positional_table(seq_len, self.model_dim, self.positional_variant)
         }
+    }
 
-        positional_encodings
+    /// Converts tokenized input into dense vectors, scaled by `input_scale`, and adds
+    /// positional encodings. Every position is in segment 0.
+    pub fn encode(&self, tokenized_input: &[usize]) -> Array2<A> {
+        self.encode_with_segments(tokenized_input, None)
     }
 
-    /// Converts tokenized input into dense vectors and adds positional encodings.
-    pub fn encode(&self, tokenized_input: &[usize]) -> Array2<f64> {
+    /// Same as `encode`, adding the embedding of every position's segment (when segment
+    /// embeddings are enabled) and applying the embedding layer norm (when enabled).
+    ///
+    /// # Arguments
+    /// * `tokenized_input` - Token ids of one sequence.
+    /// * `segments` - Segment id of every position, e.g. a row of `EncodedBatch::token_type_ids`;
+    ///   `None` puts every position in segment 0. Ids beyond `NUM_SEGMENTS` use the last segment.
+    pub fn encode_with_segments(&self, tokenized_input: &[usize], segments: Option<&[usize]>) -> Array2<A> {
+        let embeddings = self.sum_embeddings(tokenized_input, segments);
+        match self.layer_norm_epsilon {
+            Some(epsilon) => apply_layer_norm(&embeddings, epsilon),
+            None => embeddings,
+        }
+    }
+
+    /// Scaled token embeddings plus positional encodings and, when enabled, segment embeddings.
+    fn sum_embeddings(&self, tokenized_input: &[usize], segments: Option<&[usize]>) -> Array2<A> {
         let seq_len = tokenized_input.len();
         let mut embeddings = Array2::zeros((seq_len, self.model_dim));
 
         for (idx, &token_idx) in tokenized_input.iter().enumerate() {
-            if token_idx < self.token_embedding_matrix.nrows() {
-                embeddings.row_mut(idx).assign(&self.token_embedding_matrix.row(token_idx));
-            } else {
-             
-                let unknown_idx = self.vocab.get("<UNK>").copied().unwrap_or(0);
-                embeddings.row_mut(idx).assign(&self.token_embedding_matrix.row(unknown_idx));
+            embeddings.row_mut(idx).assign(&self.token_embedding_matrix.row(self.matrix_row(token_idx)));
+        }
+
+        if self.scale_by_sqrt_d_model {
+            embeddings *= self.input_scale();
+        }
+        let mut embeddings = if seq_len <= self.positional_cache.nrows() {
+            embeddings + self.positional_cache.slice(s![..seq_len, ..])
+        } else {
+            embeddings + self.generate_positional_encodings(seq_len)
+        };
+        if let Some(segment_embeddings) = &self.segment_embedding_matrix {
+            for (position, mut row) in embeddings.outer_iter_mut().enumerate() {
+                row += &segment_embeddings.row(segment_of(segments, position));
             }
         }
+        embeddings
+    }
+
+    /// Row of the token matrix used for `token_idx`; ids beyond the matrix use `<UNK>`.
+    fn matrix_row(&self, token_idx: usize) -> usize {
+        if token_idx < self.token_embedding_matrix.nrows() {
+            token_idx
+        } else {
+            self.vocab.get("<UNK>").copied().unwrap_or(0)
+        }
+    }
 
-        let positional_encodings = self.generate_positional_encodings(seq_len);
-        embeddings + positional_encodings
+    /// Backward pass of `encode_with_segments`.
+    ///
+    /// The gradient of every position is passed back through the layer norm (when enabled),
+    /// then scatter-added into the row of its token (times `input_scale`) and of its segment,
+    /// so tokens that occur several times accumulate the gradients of all their positions.
+    ///
+    /// # Arguments
+    /// * `tokenized_input` - The token ids used in the forward pass.
+    /// * `segments` - The segment ids used in the forward pass, if any.
+    /// * `grad_output` - Gradient of the loss with respect to the output of `encode_with_segments`.
+    ///   Shape: [seq_len, model_dim].
+    ///
+    /// # Returns
+    /// * `(offset, gradient)` of every row the sequence touches: token rows, then segment
+    ///   rows, where `offset` is the row's first index in `parameters_mut`. All other
+    ///   gradients are zero, so a step never allocates a `vocab_size × model_dim` gradient.
+    ///   Empty while `frozen`.
+    pub fn backward(&self, tokenized_input: &[usize], segments: Option<&[usize]>, grad_output: &Array2<A>) -> Vec<(usize, Array1<A>)> {
+        if self.frozen {
+            return Vec::new();
+        }
+        let grad_sum = match self.layer_norm_epsilon {
+            Some(epsilon) => layer_norm_backward(&self.sum_embeddings(tokenized_input, segments), epsilon, grad_output),
+            None => grad_output.clone(),
+        };
+
+        let mut grad_tokens: BTreeMap<usize, Array1<A>> = BTreeMap::new();
+        let scale = self.input_scale();
+        for (grad, &token_idx) in grad_sum.outer_iter().zip(tokenized_input) {
+            grad_tokens
+                .entry(self.matrix_row(token_idx))
+                .or_insert_with(|| Array1::zeros(self.model_dim))
+                .scaled_add(scale, &grad);
+        }
+        let mut rows: Vec<(usize, Array1<A>)> = grad_tokens.into_iter().map(|(row, grad)| (row * self.model_dim, grad)).collect();
+
+        if self.segment_embedding_matrix.is_some() {
+            let mut grad_segments: BTreeMap<usize, Array1<A>> = BTreeMap::new();
+            for (position, grad) in grad_sum.outer_iter().enumerate() {
+                grad_segments
+                    .entry(segment_of(segments, position))
+                    .or_insert_with(|| Array1::zeros(self.model_dim))
+                    .scaled_add(A::one(), &grad);
+            }
+            let segments_offset = self.token_embedding_matrix.len();
+            rows.extend(grad_segments.into_iter().map(|(segment, grad)| (segments_offset + segment * self.model_dim, grad)));
+        }
+        rows
     }
 
- 
-    pub fn parameters_mut(&mut self) -> Vec<&mut f64> {
+    /// Number of trainable values (token and segment embeddings); 0 while `frozen`.
+    pub fn num_parameters(&self) -> usize {
+        if self.frozen {
+            return 0;
+        }
+        self.token_embedding_matrix.len() + self.segment_embedding_matrix.as_ref().map_or(0, Array2::len)
+    }
+
+    /// Trainable values of the token matrix, then of the segment matrix; empty while `frozen`.
+    pub fn parameters_mut(&mut self) -> Vec<&mut A> {
         let mut params = vec![];
+        if self.frozen {
+            return params;
+        }
 
 
         for value in self.token_embedding_matrix.iter_mut() {
             params.push(value);
         }
+        if let Some(segment_embeddings) = &mut self.segment_embedding_matrix {
+            params.extend(segment_embeddings.iter_mut());
+        }
 
         params
     }
 }
 
+/// Sinusoidal encodings of the first `rows` positions at the model's precision.
+fn positional_table<A: Float>(rows: usize, model_dim: usize, variant: SinusoidalVariant) -> Array2<A> {
+    sinusoidal_encodings(rows, model_dim, variant).mapv(A::cast)
+}
+
+/// Segment id of `position`, 0 without segment ids; ids beyond `NUM_SEGMENTS` use the last segment.
+fn segment_of(segments: Option<&[usize]>, position: usize) -> usize {
+    segments.and_then(|segments| segments.get(position)).copied().unwrap_or(0).min(NUM_SEGMENTS - 1)
+}
+
+/// Inverted dropout on the embedding block output, applied by the model during training
+/// steps only. The mask of a sequence depends on `seed` and the sequence's row in the
+/// batch alone, so the forward pass and the recomputation in the backward pass drop the
+/// same values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EmbeddingDropout {
+    pub rate: f64,
+    pub seed: u64,
+}
+
+impl EmbeddingDropout {
+    /// Zeroes each value of `embeddings` with probability `rate` and scales the rest by
+    /// `1 / (1 - rate)`, so the expected value is unchanged.
+    ///
+    /// # Arguments
+    /// * `embeddings` - Output of `Embeddings::encode_with_segments` for one sequence.
+    /// * `row` - The sequence's row in the batch.
+    pub fn apply<A: Float>(&self, embeddings: Array2<A>, row: usize) -> Array2<A> {
+        if self.rate <= 0.0 {
+            return embeddings;
+        }
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (row as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let keep = 1.0 - self.rate;
+        embeddings.mapv(|value| if rng.gen::<f64>() < keep { value / A::cast(keep) } else { A::zero() })
+    }
+
+    /// Backward pass of `apply`: the gradient goes through the same mask and scaling.
+    pub fn backward<A: Float>(&self, grad_output: Array2<A>, row: usize) -> Array2<A> {
+        self.apply(grad_output, row)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,7 +462,7 @@ mod tests {
         ]);
         let model_dim = 4;
 
-        let embeddings = Embeddings::new(vocab.clone(), model_dim);
+        let embeddings: Embeddings = Embeddings::new(vocab.clone(), model_dim);
 
         let input = vec![0, 1, 3]; 
         let encoded = embeddings.encode(&input);
@@ -95,6 +470,100 @@ mod tests {
         assert_eq!(encoded.shape(), &[3, model_dim]);
     }
 
+    #[test]
+    fn test_positional_cache() {
+        let vocab = HashMap::from([("hello".to_string(), 0)]);
+        let mut embeddings: Embeddings = Embeddings::new(vocab, 6).with_positional_variant(SinusoidalVariant::PerDimension);
+        assert_eq!(embeddings.positional_cache.nrows(), MAX_SEQ_LENGTH);
+
+        // Slices of the cache and encodings past its end both match the formula.
+        for seq_len in [1, MAX_SEQ_LENGTH, MAX_SEQ_LENGTH + 5] {
+            let expected = sinusoidal_encodings(seq_len, 6, SinusoidalVariant::PerDimension);
+            assert_eq!(embeddings.generate_positional_encodings(seq_len), expected);
+            let encoded = embeddings.encode(&vec![0; seq_len]) - &expected;
+            let token = embeddings.token_embedding_matrix.row(0);
+            assert!(encoded.rows().into_iter().all(|row| (&row - &token).iter().all(|d| d.abs() < 1e-12)));
+        }
+
+        embeddings.reserve_positions(MAX_SEQ_LENGTH + 5);
+        assert_eq!(embeddings.positional_cache.nrows(), MAX_SEQ_LENGTH + 5);
+        embeddings.reserve_positions(2);
+        assert_eq!(embeddings.positional_cache.nrows(), MAX_SEQ_LENGTH + 5);
+
+        // Loading rebuilds the cache in the saved variant.
+        let loaded: Embeddings = serde_json::from_str(&serde_json::to_string(&embeddings).unwrap()).unwrap();
+        assert_eq!(loaded.positional_variant, SinusoidalVariant::PerDimension);
+        assert_eq!(loaded.positional_cache, sinusoidal_encodings(MAX_SEQ_LENGTH, 6, SinusoidalVariant::PerDimension));
+    }
+
+    #[test]
+    fn test_sqrt_d_model_scaling() {
+        let vocab = HashMap::from([("[PAD]".to_string(), 0), ("hello".to_string(), 1)]);
+        let embeddings: Embeddings = Embeddings::new(vocab, 16);
+        let unscaled = embeddings.encode(&[1, 0]);
+
+        let embeddings = embeddings.with_sqrt_d_model_scaling(true);
+        let scaled = embeddings.encode(&[1, 0]);
+        let positional = embeddings.generate_positional_encodings(2);
+        let expected = embeddings.token_embedding_matrix.select(ndarray::Axis(0), &[1, 0]) * 4.0 + &positional;
+        assert!(scaled.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(unscaled.iter().zip(scaled.iter()).any(|(a, b)| a != b));
+
+        // Checkpoints from before the setting keep their unscaled inputs.
+        let mut saved: serde_json::Value = serde_json::to_value(&embeddings).unwrap();
+        assert_eq!(saved["scale_by_sqrt_d_model"], true);
+        saved.as_object_mut().unwrap().remove("scale_by_sqrt_d_model");
+        let old: Embeddings = serde_json::from_value(saved).unwrap();
+        assert!(!old.scale_by_sqrt_d_model);
+        assert_eq!(old.encode(&[1, 0]), unscaled);
+    }
+
+    #[test]
+    fn test_frozen_embeddings_have_no_parameters() {
+        let vocab = HashMap::from([("[PAD]".to_string(), 0), ("hello".to_string(), 1)]);
+        let mut embeddings: Embeddings = Embeddings::new(vocab, 4);
+        assert_eq!(embeddings.parameters_mut().len(), 8);
+
+        embeddings.frozen = true;
+        assert_eq!(embeddings.num_parameters(), 0);
+        assert!(embeddings.parameters_mut().is_empty());
+        assert_eq!(embeddings.encode(&[1]).shape(), &[1, 4]);
+
+        let loaded: Embeddings = serde_json::from_str(&serde_json::to_string(&embeddings).unwrap()).unwrap();
+        assert!(!loaded.frozen);
+    }
+
+    #[test]
+    fn test_scale_by_frequency() {
+        let vocab = HashMap::from([
+            ("[PAD]".to_string(), 0),
+            ("common".to_string(), 1),
+            ("rare".to_string(), 2),
+        ]);
+        let counts = HashMap::from([("common".to_string(), 99), ("rare".to_string(), 9)]);
+
+        let mut embeddings: Embeddings = Embeddings::new(vocab, 4);
+        assert!(embeddings.scale_by_frequency(&counts, 1.5).is_err());
+        let original = embeddings.token_embedding_matrix.clone();
+        embeddings.scale_by_frequency(&counts, 0.1).unwrap();
+
+        let ratio = |row: usize| embeddings.token_embedding_matrix[[row, 0]] / original[[row, 0]];
+        assert!((ratio(0) - 0.1).abs() < 1e-9);
+        assert!((ratio(1) - 1.0).abs() < 1e-9);
+        assert!((ratio(2) - (0.1 + 0.9 * 10f64.ln() / 100f64.ln())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_add_token() {
+        let vocab = HashMap::from([("[PAD]".to_string(), 0), ("hello".to_string(), 1)]);
+        let mut embeddings: Embeddings = Embeddings::new(vocab, 4);
+
+        assert_eq!(embeddings.add_token("[MASK]"), 2);
+        assert_eq!(embeddings.add_token("hello"), 1);
+        assert_eq!(embeddings.vocab_size(), 3);
+        assert_eq!(embeddings.encode(&[2]).shape(), &[1, 4]);
+    }
+
     #[test]
     fn test_serialization() {
         let vocab = HashMap::from([
@@ -104,7 +573,7 @@ mod tests {
         ]);
         let model_dim = 4;
 
-        let embeddings = Embeddings::new(vocab.clone(), model_dim);
+        let embeddings: Embeddings = Embeddings::new(vocab.clone(), model_dim);
 
     
         let serialized = serde_json::to_string(&embeddings).expect("Serialization failed");
@@ -116,4 +585,111 @@ mod tests {
         assert_eq!(embeddings.model_dim, deserialized.model_dim);
         assert_eq!(embeddings.vocab, deserialized.vocab);
     }
+
+    #[test]
+    fn test_compressed_serialization() {
+        let vocab: HashMap<String, usize> = (0..50).map(|i| (format!("token{}", i), i)).collect();
+        let mut embeddings: Embeddings = Embeddings::new(vocab, 16);
+        let full_size = serde_json::to_string(&embeddings).unwrap().len();
+
+        for (precision, max_step) in [(EmbeddingPrecision::Int8, 0.1 / 127.0), (EmbeddingPrecision::Int4, 0.1 / 7.0)] {
+            embeddings.storage_precision = precision;
+            let serialized = serde_json::to_string(&embeddings).unwrap();
+            assert!(serialized.len() * 4 < full_size, "{:?}: {} vs {} bytes", precision, serialized.len(), full_size);
+
+            let loaded: Embeddings = serde_json::from_str(&serialized).unwrap();
+            assert_eq!(loaded.storage_precision, precision);
+            let max_error = loaded
+                .token_embedding_matrix
+                .iter()
+                .zip(embeddings.token_embedding_matrix.iter())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max);
+            assert!(max_error <= max_step / 2.0 + 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_segment_embeddings_and_layer_norm() {
+        let vocab = HashMap::from([("[PAD]".to_string(), 0), ("hello".to_string(), 1)]);
+        let plain: Embeddings = Embeddings::new(vocab, 4);
+        let unnormalized = plain.encode(&[1, 1]);
+        let embeddings = plain.with_segment_embeddings().with_layer_norm(1e-12);
+        assert_eq!(embeddings.num_parameters(), 2 * 4 + NUM_SEGMENTS * 4);
+
+        // Without segment ids every position is in segment 0.
+        let segments = embeddings.segment_embedding_matrix().unwrap().clone();
+        let expected = apply_layer_norm(&(&unnormalized + &segments.row(0)), 1e-12);
+        assert!(embeddings.encode(&[1, 1]).iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-9));
+        let pair = embeddings.encode_with_segments(&[1, 1], Some(&[0, 1]));
+        let second = apply_layer_norm(&(&unnormalized + &segments.row(1)), 1e-12);
+        assert!(pair.row(1).iter().zip(second.row(1).iter()).all(|(a, b)| (a - b).abs() < 1e-9));
+        assert!(pair.row(1).mean().unwrap().abs() < 1e-9);
+
+        let loaded: Embeddings = serde_json::from_str(&serde_json::to_string(&embeddings).unwrap()).unwrap();
+        let reloaded = loaded.encode_with_segments(&[1, 1], Some(&[0, 1]));
+        assert!(reloaded.iter().zip(pair.iter()).all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_embedding_dropout_is_reproducible() {
+        let embeddings: Array2<f64> = Array2::from_elem((8, 16), 1.0);
+        let dropout = EmbeddingDropout { rate: 0.25, seed: 3 };
+        let dropped = dropout.apply(embeddings.clone(), 1);
+
+        assert_eq!(dropped, dropout.apply(embeddings.clone(), 1));
+        assert_ne!(dropped, dropout.apply(embeddings.clone(), 2));
+        assert!(dropped.iter().all(|&value| value == 0.0 || (value - 1.0 / 0.75).abs() < 1e-12));
+        assert!(dropped.iter().any(|&value| value == 0.0));
+        assert_eq!(EmbeddingDropout { rate: 0.0, seed: 3 }.apply(embeddings.clone(), 1), embeddings);
+    }
+
+    /// Gradients of all trainable values, in the order of `parameters_mut`.
+    fn dense(embeddings: &Embeddings, rows: Vec<(usize, Array1<f64>)>) -> Vec<f64> {
+        let mut grads = vec![0.0; embeddings.num_parameters()];
+        for (offset, row) in rows {
+            for (grad, value) in grads[offset..offset + row.len()].iter_mut().zip(row) {
+                *grad += value;
+            }
+        }
+        grads
+    }
+
+    #[test]
+    fn test_backward_scatter_adds_into_used_rows() {
+        let vocab: HashMap<String, usize> = (0..5).map(|i| (format!("t{}", i), i)).collect();
+        let tokens = [3, 1, 3, 7];
+        let segments = [0, 0, 1, 1];
+        let grad_output = Array2::from_shape_fn((4, 4), |(i, j)| ((i * 4 + j) as f64 * 0.37).sin());
+
+        let plain = Embeddings::new(vocab.clone(), 4).with_sqrt_d_model_scaling(true);
+        let grads = dense(&plain, plain.backward(&tokens, None, &grad_output));
+        assert_eq!(grads.len(), plain.num_parameters());
+        // Token 3 accumulates both of its positions; unused rows get nothing; the
+        // out-of-vocabulary id falls back to row 0.
+        let row = |i: usize| &grads[i * 4..(i + 1) * 4];
+        for j in 0..4 {
+            assert!((row(3)[j] - 2.0 * (grad_output[[0, j]] + grad_output[[2, j]])).abs() < 1e-12);
+            assert!((row(0)[j] - 2.0 * grad_output[[3, j]]).abs() < 1e-12);
+        }
+        assert!(row(2).iter().chain(row(4)).all(|&grad| grad == 0.0));
+
+        // Finite differences through the segment embeddings and the layer norm.
+        let mut embeddings = Embeddings::new(vocab, 4).with_segment_embeddings().with_layer_norm(1e-6);
+        let grads = dense(&embeddings, embeddings.backward(&tokens, Some(&segments), &grad_output));
+        let objective = |embeddings: &Embeddings| (embeddings.encode_with_segments(&tokens, Some(&segments)) * &grad_output).sum();
+        let epsilon = 1e-6;
+        for (index, &analytic) in grads.iter().enumerate() {
+            *embeddings.parameters_mut()[index] += epsilon;
+            let plus = objective(&embeddings);
+            *embeddings.parameters_mut()[index] -= 2.0 * epsilon;
+            let minus = objective(&embeddings);
+            *embeddings.parameters_mut()[index] += epsilon;
+            let numeric = (plus - minus) / (2.0 * epsilon);
+            assert!((numeric - analytic).abs() < 1e-6, "{}: {} vs {}", index, numeric, analytic);
+        }
+
+        embeddings.frozen = true;
+        assert!(embeddings.backward(&tokens, Some(&segments), &grad_output).is_empty());
+    }
 }
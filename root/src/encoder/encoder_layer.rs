@@ -1,49 +1,226 @@
-use crate::attention::scaled_dot_product_attention;
+use crate::attention::{masked_scaled_dot_product_attention, masked_scaled_dot_product_attention_backward, multi_head_attention, multi_head_attention_backward, AttentionProjections, RelativePositions};
 use crate::feed_forward::FeedForwardNetwork;
-use crate::layer_norm::apply_layer_norm;
-use ndarray::{Array2, Axis};
+use crate::backend::tensor_backend::{CpuBackend, TensorBackend};
+use crate::layer_norm::layer_norm_backward_with;
+use crate::summation::Summation;
+use crate::profiling::profiler;
+use crate::quantization::quantized_feed_forward::QuantizedFeedForward;
+use crate::numerics::Float;
+use ndarray::{Array1, Array2};
 use serde::{Serialize, Deserialize};
 
+/// Intermediate outputs of one forward pass of an `EncoderLayer`.
+struct LayerStages<A> {
+    attention: Array2<A>,
+    norm1: Array2<A>,
+    feed_forward: Array2<A>,
+    output: Array2<A>,
+}
+
 #[derive(Serialize, Deserialize)]
-pub struct EncoderLayer {
-    pub feed_forward: FeedForwardNetwork,
-    pub epsilon: f64,
+pub struct EncoderLayer<A = f64> {
+    pub feed_forward: FeedForwardNetwork<A>,
+    pub epsilon: A,
+    /// Relative-position representations added to the self-attention; `None` for layers
+    /// created without them, including all checkpoints saved before they existed.
+    #[serde(default)]
+    pub relative_positions: Option<RelativePositions<A>>,
+    /// Learned query, key, value and output projections of the self-attention; `None` for
+    /// layers that attend over their raw input, including checkpoints saved before they existed.
+    #[serde(default)]
+    pub projections: Option<AttentionProjections<A>>,
+    /// How layer norm statistics are summed; a runtime setting, not saved with the model.
+    #[serde(skip)]
+    pub summation: Summation,
+    /// Int8 copy of `feed_forward` used by the forward pass when set, e.g. for serving;
+    /// `backward` keeps using the full-precision network. Its activations are f64 whatever
+    /// the layer's precision. Not saved with the model.
+    #[serde(skip)]
+    pub quantized_feed_forward: Option<QuantizedFeedForward>,
 }
 
-impl EncoderLayer {
-    /// Creates a new encoder layer with the specified dimensions
-    pub fn new(d_model: usize, num_heads: usize, d_ff: usize, epsilon: f64) -> Self {
+impl<A: Float> EncoderLayer<A> {
+    /// Creates a new encoder layer with the specified dimensions. The attention is
+    /// single-headed until `with_attention_projections` sets the number of heads.
+    pub fn new(d_model: usize, d_ff: usize, epsilon: A) -> Self {
         Self {
             feed_forward: FeedForwardNetwork::new(d_model, d_ff),
             epsilon,
+            relative_positions: None,
+            projections: None,
+            summation: Summation::Naive,
+            quantized_feed_forward: None,
         }
     }
 
-    /// Forward pass for the encoder layer
+    /// Adds learned relative-position representations for distances up to `max_distance`
+    /// to the self-attention (see `RelativePositions`).
     ///
     /// # Arguments
-    /// - `x`: Input embeddings with positional encodings (shape: [batch_size, seq_len, d_model]).
+    /// * `max_distance` - Largest distance with its own embedding.
+    /// * `dim` - Size of the attended vectors: d_model, or d_model / num_heads with attention projections.
+    pub fn with_relative_positions(mut self, max_distance: usize, dim: usize) -> Self {
+        self.relative_positions = Some(RelativePositions::new(max_distance, dim));
+        self
+    }
+
+    /// Adds learned query, key, value and output projections with `num_heads` heads to the
+    /// self-attention (see `AttentionProjections`). With relative positions every head gets
+    /// them, so their `dim` must then be `d_model / num_heads`.
+    ///
+    /// # Arguments
+    /// * `d_model` - Size of the layer input.
+    /// * `num_heads` - Number of attention heads; must divide `d_model`.
+    pub fn with_attention_projections(mut self, d_model: usize, num_heads: usize) -> Self {
+        self.projections = Some(AttentionProjections::new(d_model, num_heads));
+        self
+    }
+
+    fn self_attention(&self, x: &Array2<A>, key_mask: Option<&Array1<A>>) -> Array2<A> {
+        profiler::time("attention", || match &self.projections {
+            Some(projections) => {
+                let (query, key, value) = projections.project(x);
+                projections.output(&self.attention_kernel(&query, &key, &value, key_mask))
+            }
+            None => self.attention_kernel(x, x, x, key_mask),
+        })
+    }
+
+    /// Attention over already projected vectors, without the output projection.
+    fn attention_kernel(&self, query: &Array2<A>, key: &Array2<A>, value: &Array2<A>, key_mask: Option<&Array1<A>>) -> Array2<A> {
+        match (&self.relative_positions, &self.projections) {
+            (Some(positions), Some(projections)) => positions.multi_head_attention(query, key, value, projections.num_heads, key_mask),
+            (Some(positions), None) => positions.attention(query, key, value, key_mask),
+            (None, Some(projections)) => multi_head_attention(query, key, value, projections.num_heads, key_mask, false),
+            (None, None) => masked_scaled_dot_product_attention(query, key, value, key_mask),
+        }
+    }
+
+    /// Gradients of `attention_kernel`; relative-position gradients are appended to `param_grads`.
+    fn attention_kernel_backward(
+        &self,
+        query: &Array2<A>,
+        key: &Array2<A>,
+        value: &Array2<A>,
+        key_mask: Option<&Array1<A>>,
+        grad_output: &Array2<A>,
+        param_grads: &mut Vec<A>,
+    ) -> (Array2<A>, Array2<A>, Array2<A>) {
+        match (&self.relative_positions, &self.projections) {
+            (Some(positions), Some(projections)) => {
+                let (grad_query, grad_key, grad_value, grad_embeddings) =
+                    positions.multi_head_attention_backward(query, key, value, projections.num_heads, key_mask, grad_output);
+                param_grads.extend(grad_embeddings);
+                (grad_query, grad_key, grad_value)
+            }
+            (Some(positions), None) => {
+                let (grad_query, grad_key, grad_value, grad_embeddings) = positions.attention_backward(query, key, value, key_mask, grad_output);
+                param_grads.extend(grad_embeddings);
+                (grad_query, grad_key, grad_value)
+            }
+            (None, Some(projections)) => multi_head_attention_backward(query, key, value, projections.num_heads, key_mask, false, grad_output),
+            (None, None) => masked_scaled_dot_product_attention_backward(query, key, value, key_mask, grad_output),
+        }
+    }
+
+    /// Forward pass in which no position attends to a masked position.
+    ///
+    /// # Arguments
+    /// - `x`: Input embeddings of one sequence (shape: [seq_len, d_model]).
+    /// - `key_mask`: 1 for real tokens and 0 for PAD positions (shape: [seq_len]).
     ///
     /// # Returns
-    /// - Processed embeddings (shape: [batch_size, seq_len, d_model]).
-    pub fn forward(&self, x: &Array2<f64>) -> Array2<f64> {
-    
-        let attention_output = scaled_dot_product_attention(x, x, x);
+    /// - Processed embeddings (shape: [seq_len, d_model]).
+    pub fn forward_masked(&self, x: &Array2<A>, key_mask: Option<&Array1<A>>) -> Array2<A> {
+        self.forward_all(x, key_mask).output
+    }
 
-      
-        let residual1 = x + &attention_output;
-        let norm1 = apply_layer_norm(&residual1, self.epsilon);
+    /// Forward pass that also returns every intermediate output, for comparing the
+    /// layer against a reference implementation.
+    ///
+    /// # Returns
+    /// - `(stage, output)` pairs in order: `attention`, `norm1`, `feed_forward`, `output`.
+    pub fn forward_stages(&self, x: &Array2<A>) -> Vec<(&'static str, Array2<A>)> {
+        let stages = self.forward_all(x, None);
+        vec![
+            ("attention", stages.attention),
+            ("norm1", stages.norm1),
+            ("feed_forward", stages.feed_forward),
+            ("output", stages.output),
+        ]
+    }
+
+    /// The forward pass behind `forward_masked` and `forward_stages`.
+    fn forward_all(&self, x: &Array2<A>, key_mask: Option<&Array1<A>>) -> LayerStages<A> {
+        let attention = self.self_attention(x, key_mask);
 
-        
-        let ffn_output = self.feed_forward.forward(&norm1);
+        let residual1 = x + &attention;
+        let norm1 = profiler::time("layer_norm", || CpuBackend.layer_norm(&residual1, self.epsilon, self.summation));
+
+        let feed_forward = profiler::time("feed_forward", || match &self.quantized_feed_forward {
+            Some(quantized) => quantized.forward(&norm1.mapv(A::as_f64)).mapv(A::cast),
+            None => self.feed_forward.forward(&norm1),
+        });
+
+        let residual2 = &norm1 + &feed_forward;
+        let output = profiler::time("layer_norm", || CpuBackend.layer_norm(&residual2, self.epsilon, self.summation));
+        LayerStages { attention, norm1, feed_forward, output }
+    }
 
+    /// Backward pass of `forward_masked`.
+    pub fn backward_masked(&self, x: &Array2<A>, key_mask: Option<&Array1<A>>, grad_output: &Array2<A>) -> (Array2<A>, Vec<A>) {
+        let attention_output = self.self_attention(x, key_mask);
+        let residual1 = x + &attention_output;
+        let norm1 = profiler::time("layer_norm", || CpuBackend.layer_norm(&residual1, self.epsilon, self.summation));
+        let ffn_output = profiler::time("feed_forward", || self.feed_forward.forward(&norm1));
         let residual2 = &norm1 + &ffn_output;
-        apply_layer_norm(&residual2, self.epsilon)
+
+        let grad_residual2 =
+            profiler::time("layer_norm", || layer_norm_backward_with(&residual2, self.epsilon, grad_output, self.summation));
+        let (grad_ffn_input, mut param_grads) = profiler::time("feed_forward", || self.feed_forward.backward(&norm1, &grad_residual2));
+        let grad_norm1 = &grad_residual2 + &grad_ffn_input;
+
+        let grad_residual1 =
+            profiler::time("layer_norm", || layer_norm_backward_with(&residual1, self.epsilon, &grad_norm1, self.summation));
+        let grad_attention_input = profiler::time("attention", || match &self.projections {
+            Some(projections) => {
+                let (query, key, value) = projections.project(x);
+                let heads = self.attention_kernel(&query, &key, &value, key_mask);
+                let (grad_heads, grad_output_weights) = projections.output_backward(&heads, &grad_residual1);
+                let (grad_query, grad_key, grad_value) =
+                    self.attention_kernel_backward(&query, &key, &value, key_mask, &grad_heads, &mut param_grads);
+                let (grad_x, grad_weights) = projections.project_backward(x, &grad_query, &grad_key, &grad_value);
+                param_grads.extend(grad_weights);
+                param_grads.extend(grad_output_weights);
+                grad_x
+            }
+            None => {
+                let (grad_query, grad_key, grad_value) = self.attention_kernel_backward(x, x, x, key_mask, &grad_residual1, &mut param_grads);
+                grad_query + grad_key + grad_value
+            }
+        });
+        let grad_x = grad_residual1 + grad_attention_input;
+
+        (grad_x, param_grads)
     }
 
- 
-    pub fn parameters_mut(&mut self) -> Vec<&mut f64> {
-        self.feed_forward.parameters_mut()
+    pub fn num_parameters(&self) -> usize {
+        self.feed_forward.num_parameters()
+            + self.relative_positions.as_ref().map_or(0, RelativePositions::num_parameters)
+            + self.projections.as_ref().map_or(0, AttentionProjections::num_parameters)
+    }
+
+    /// Feed-forward parameters, then the relative-position embeddings and the attention
+    /// projections when present.
+    pub fn parameters_mut(&mut self) -> Vec<&mut A> {
+        let mut parameters = self.feed_forward.parameters_mut();
+        if let Some(positions) = &mut self.relative_positions {
+            parameters.extend(positions.parameters_mut());
+        }
+        if let Some(projections) = &mut self.projections {
+            parameters.extend(projections.parameters_mut());
+        }
+        parameters
     }
 }
 
@@ -55,19 +232,51 @@ mod tests {
     #[test]
     fn test_encoder_layer() {
         let d_model = 4;
-        let num_heads = 2;
         let d_ff = 8;
         let epsilon = 1e-6;
 
-        let encoder_layer = EncoderLayer::new(d_model, num_heads, d_ff, epsilon);
+        let encoder_layer = EncoderLayer::new(d_model, d_ff, epsilon);
 
         let input = array![
             [0.1, 0.2, 0.3, 0.4],
             [0.4, 0.3, 0.2, 0.1],
         ];
 
-        let output = encoder_layer.forward(&input);
+        let output = encoder_layer.forward_masked(&input, None);
 
         assert_eq!(output.shape(), input.shape());
     }
+
+    #[test]
+    fn test_attention_projections_gradients() {
+        let mut layer = EncoderLayer::new(4, 6, 1e-6).with_attention_projections(4, 2);
+        assert_eq!(layer.num_parameters(), layer.parameters_mut().len());
+        let x = array![[0.1, 0.2, 0.3, 0.4], [0.4, -0.3, 0.2, 0.1], [0.0, 0.5, -0.1, 0.2]];
+        let mask = array![1.0, 1.0, 0.0];
+        let upstream = array![[1.0, -0.5, 0.2, 0.1], [0.3, 0.8, -0.6, 0.4], [-0.2, 0.4, 0.5, -0.3]];
+        let loss = |layer: &EncoderLayer, x: &Array2<f64>| (layer.forward_masked(x, Some(&mask)) * &upstream).sum();
+
+        let (grad_x, param_grads) = layer.backward_masked(&x, Some(&mask), &upstream);
+        let h = 1e-6;
+        for ((i, j), &analytic) in grad_x.indexed_iter() {
+            let (mut plus, mut minus) = (x.clone(), x.clone());
+            plus[[i, j]] += h;
+            minus[[i, j]] -= h;
+            let numeric = (loss(&layer, &plus) - loss(&layer, &minus)) / (2.0 * h);
+            assert!((numeric - analytic).abs() < 1e-5, "input gradient {:?}: {} vs {}", (i, j), numeric, analytic);
+        }
+
+        // The projections come last in `parameters_mut`.
+        let projections = layer.projections.as_ref().unwrap().num_parameters();
+        let offset = layer.num_parameters() - projections;
+        for p in (offset..layer.num_parameters()).step_by(5) {
+            let original = *layer.parameters_mut()[p];
+            *layer.parameters_mut()[p] = original + h;
+            let plus = loss(&layer, &x);
+            *layer.parameters_mut()[p] = original - h;
+            let minus = loss(&layer, &x);
+            *layer.parameters_mut()[p] = original;
+            assert!(((plus - minus) / (2.0 * h) - param_grads[p]).abs() < 1e-5, "projection gradient {}", p);
+        }
+    }
 }
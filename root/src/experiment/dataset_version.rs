@@ -0,0 +1,65 @@
+use serde::{Serialize, Deserialize};
+use std::fs;
+
+/// Content fingerprint of a dataset file, recorded in a run's config so results can be
+/// traced to the exact data they were trained on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatasetVersion {
+    /// Path the dataset was read from; informational, only `hash` identifies the version.
+    pub path: String,
+    /// 64-bit FNV-1a hash of the file's bytes, as 16 hex digits.
+    pub hash: String,
+    pub bytes: u64,
+}
+
+impl DatasetVersion {
+    /// Hashes the dataset at `path`.
+    pub fn of(path: &str) -> Result<Self, std::io::Error> {
+        let data = fs::read(path)?;
+        Ok(DatasetVersion { path: path.to_string(), hash: format!("{:016x}", fnv1a(&data)), bytes: data.len() as u64 })
+    }
+
+    /// Compares the file at `self.path` with the pinned version.
+    ///
+    /// # Returns
+    /// * A description of the change, or `None` when the content is unchanged.
+    pub fn check_unchanged(&self) -> Result<Option<String>, std::io::Error> {
+        let current = DatasetVersion::of(&self.path)?;
+        Ok((current.hash != self.hash).then(|| {
+            format!(
+                "{} changed since the run was created (hash {} -> {}, {} -> {} bytes)",
+                self.path, self.hash, current.hash, self.bytes, current.bytes
+            )
+        }))
+    }
+}
+
+/// 64-bit FNV-1a: dependency-free and stable across platforms and Rust versions, unlike
+/// `DefaultHasher`. It detects changed data, not deliberate collisions.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::temp_path;
+
+    #[test]
+    fn test_hash_pins_content() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+
+        let path = &temp_path("dataset_version_test.json");
+        fs::write(path, r#"[{"text": "hi", "label": 0}]"#).unwrap();
+        let pinned = DatasetVersion::of(path).unwrap();
+        let unchanged = pinned.check_unchanged().unwrap();
+        fs::write(path, r#"[{"text": "hi", "label": 1}]"#).unwrap();
+        let changed = pinned.check_unchanged().unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(pinned.bytes, 28);
+        assert_eq!(unchanged, None);
+        assert!(changed.unwrap().contains(&pinned.hash));
+    }
+}
@@ -0,0 +1,287 @@
+use crate::configurration::config::{BATCH_SIZE, LEARNING_RATE, MAX_SEQ_LENGTH, SLIDING_WINDOW, TRAINING_SEED, WORD_DROPOUT};
+use crate::model_inference::inference::ExamplePrediction;
+use crate::experiment::dataset_version::DatasetVersion;
+use crate::data_handler::label_map::LabelMap;
+use crate::data_handler::input_template::InputTemplate;
+use crate::data_handler::sliding_window::SlidingWindow;
+use crate::data_handler::masking::WordDropout;
+use crate::tokenization::tokenizer::Tokenizer;
+use crate::transformer::TransformerConfig;
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CONFIG_FILE: &str = "config.json";
+const TOKENIZER_FILE: &str = "tokenizer.json";
+const LABEL_MAP_FILE: &str = "labels.json";
+const METRICS_FILE: &str = "metrics.jsonl";
+const PREDICTIONS_FILE: &str = "predictions.json";
+const CHECKPOINT_DIR: &str = "checkpoints";
+
+/// Snapshot of everything that determines a training run.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RunConfig {
+    pub model: TransformerConfig,
+    pub epochs: usize,
+    pub learning_rate: f64,
+    pub batch_size: usize,
+    pub max_seq_length: usize,
+    /// Version of the training dataset the run was created with; `None` for older runs.
+    #[serde(default)]
+    pub train_dataset: Option<DatasetVersion>,
+    /// Template the multi-field training records were rendered with; serving renders with the same.
+    #[serde(default)]
+    pub input_template: Option<InputTemplate>,
+    /// Seed of the trainer's random draws, so a run resumed from an epoch checkpoint draws
+    /// the same as an uninterrupted one; `None` for older runs.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Windows the run trains on, so evaluating or resuming it expands long documents the same way.
+    #[serde(default)]
+    pub sliding_window: Option<SlidingWindow>,
+    /// Word dropout the run trains with, so a resumed run corrupts its batches the same way.
+    #[serde(default)]
+    pub word_dropout: Option<WordDropout>,
+}
+
+impl RunConfig {
+    /// Snapshot of a model configuration together with the current training constants.
+    pub fn new(model: TransformerConfig, epochs: usize) -> Self {
+        RunConfig {
+            model,
+            epochs,
+            learning_rate: LEARNING_RATE,
+            batch_size: BATCH_SIZE,
+            max_seq_length: MAX_SEQ_LENGTH,
+            train_dataset: None,
+            input_template: None,
+            seed: Some(TRAINING_SEED.unwrap_or_else(rand::random)),
+            sliding_window: SLIDING_WINDOW,
+            word_dropout: WORD_DROPOUT,
+        }
+    }
+}
+
+/// Directory holding all artifacts of one experiment:
+///
+/// ```text
+/// <run_dir>/
+///   config.json          run configuration snapshot
+///   tokenizer.json       vocabulary, max_seq_length and special tokens
+///   labels.json          class names in id order (runs created before label maps have none)
+///   checkpoints/         epoch_<n>.json and the final model.json
+///   metrics.jsonl        one JSON object per logged metric record
+///   predictions.json     per-example predictions on the evaluation set
+///   pretrained.json      encoder adapted by `pretrain`, before fine-tuning
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExperimentRun {
+    pub dir: PathBuf,
+}
+
+impl ExperimentRun {
+    /// Creates a new run directory `<root>/run-<unix seconds>`.
+    pub fn create(root: &str) -> Result<Self, std::io::Error> {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let mut dir = Path::new(root).join(format!("run-{}", seconds));
+        let mut suffix = 1;
+        while dir.exists() {
+            dir = Path::new(root).join(format!("run-{}-{}", seconds, suffix));
+            suffix += 1;
+        }
+
+        fs::create_dir_all(dir.join(CHECKPOINT_DIR))?;
+        Ok(ExperimentRun { dir })
+    }
+
+    /// The most recently created run under `root`, e.g. to resume it without naming it.
+    /// Directories that are not runs are skipped; `None` when there is no run.
+    pub fn latest(root: &str) -> Result<Option<Self>, std::io::Error> {
+        if !Path::new(root).exists() {
+            return Ok(None);
+        }
+        // `run-<seconds>` or `run-<seconds>-<suffix>`, see `create`.
+        let created = |name: &str| -> Option<(u64, u64)> {
+            let mut parts = name.strip_prefix("run-")?.split('-');
+            let seconds = parts.next()?.parse().ok()?;
+            let suffix = parts.next().map_or(Some(0), |suffix| suffix.parse().ok())?;
+            Some((seconds, suffix))
+        };
+        let latest = fs::read_dir(root)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let order = created(entry.file_name().to_str()?)?;
+                entry.path().join(CONFIG_FILE).exists().then(|| (order, entry.path()))
+            })
+            .max_by_key(|(order, _)| *order);
+        Ok(latest.map(|(_, dir)| ExperimentRun { dir }))
+    }
+
+    /// Opens an existing run directory, e.g. to resume training.
+    pub fn open(dir: &str) -> Result<Self, std::io::Error> {
+        let dir = PathBuf::from(dir);
+        if !dir.join(CONFIG_FILE).exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} is not a run directory (missing {})", dir.display(), CONFIG_FILE),
+            ));
+        }
+        fs::create_dir_all(dir.join(CHECKPOINT_DIR))?;
+        Ok(ExperimentRun { dir })
+    }
+
+    pub fn save_config(&self, config: &RunConfig) -> Result<(), std::io::Error> {
+        fs::write(self.dir.join(CONFIG_FILE), serde_json::to_string_pretty(config)?)
+    }
+
+    pub fn load_config(&self) -> Result<RunConfig, std::io::Error> {
+        Ok(serde_json::from_str(&fs::read_to_string(self.dir.join(CONFIG_FILE))?)?)
+    }
+
+    /// Path of the run's tokenizer, which `Inference::new` loads alongside a checkpoint.
+    pub fn tokenizer_path(&self) -> String {
+        self.dir.join(TOKENIZER_FILE).to_string_lossy().into_owned()
+    }
+
+    pub fn save_tokenizer(&self, tokenizer: &Tokenizer) -> Result<(), std::io::Error> {
+        tokenizer.save(&self.tokenizer_path())
+    }
+
+    /// The run's tokenizer. Runs created before tokenizers were saved whole hold only a
+    /// vocabulary, which is read with the `max_seq_length` of the run config.
+    pub fn load_tokenizer(&self) -> Result<Tokenizer, std::io::Error> {
+        let max_seq_length = self.load_config().ok().map(|config| config.max_seq_length);
+        Tokenizer::load_or_migrate(&self.tokenizer_path(), max_seq_length)
+    }
+
+    pub fn save_label_map(&self, label_map: &LabelMap) -> Result<(), std::io::Error> {
+        label_map.save(&self.dir.join(LABEL_MAP_FILE).to_string_lossy())
+    }
+
+    /// The run's label map; `None` for runs created before label maps, whose datasets use class ids.
+    pub fn load_label_map(&self) -> Result<Option<LabelMap>, Box<dyn std::error::Error>> {
+        let path = self.dir.join(LABEL_MAP_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(LabelMap::load(&path.to_string_lossy())?))
+    }
+
+    /// Path of the checkpoint saved after `epoch`, or of the final model when `None`.
+    pub fn checkpoint_path(&self, epoch: Option<usize>) -> String {
+        let file = match epoch {
+            Some(epoch) => format!("epoch_{}.json", epoch),
+            None => "model.json".to_string(),
+        };
+        self.dir.join(CHECKPOINT_DIR).join(file).to_string_lossy().into_owned()
+    }
+
+    /// The most recent epoch checkpoint and its epoch number, if any.
+    pub fn latest_checkpoint(&self) -> Option<(usize, String)> {
+        fs::read_dir(self.dir.join(CHECKPOINT_DIR))
+            .ok()?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                name.strip_prefix("epoch_")?.strip_suffix(".json")?.parse::<usize>().ok()
+            })
+            .max()
+            .map(|epoch| (epoch, self.checkpoint_path(Some(epoch))))
+    }
+
+    /// Appends one metric record as a line of `metrics.jsonl`.
+    pub fn log_metrics(&self, record: &serde_json::Value) -> Result<(), std::io::Error> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(METRICS_FILE))?;
+        writeln!(file, "{}", record)
+    }
+
+    /// Reads all metric records logged so far.
+    pub fn load_metrics(&self) -> Result<Vec<serde_json::Value>, std::io::Error> {
+        let path = self.dir.join(METRICS_FILE);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(std::io::Error::from))
+            .collect()
+    }
+
+    pub fn save_predictions(&self, predictions: &[ExamplePrediction]) -> Result<(), std::io::Error> {
+        fs::write(self.dir.join(PREDICTIONS_FILE), serde_json::to_string_pretty(predictions)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{temp_path, tiny_config, tiny_vocab};
+    use crate::data_handler::sliding_window::WindowWeighting;
+    use crate::data_handler::masking::WordDropoutMode;
+    use serde_json::json;
+
+    #[test]
+    fn test_run_directory_round_trip() {
+        let root = &temp_path("experiment_run_test_root");
+        let run = ExperimentRun::create(root).unwrap();
+
+        let window = SlidingWindow { stride: 4, weighting: WindowWeighting::NewTokens };
+        let word_dropout = WordDropout { probability: 0.1, mode: WordDropoutMode::Drop };
+        let config = RunConfig { sliding_window: Some(window), word_dropout: Some(word_dropout), ..RunConfig::new(tiny_config(2), 3) };
+        run.save_config(&config).unwrap();
+        let vocab = tiny_vocab(&[]);
+        run.save_tokenizer(&Tokenizer::new(vocab, 16)).unwrap();
+        assert!(run.load_label_map().unwrap().is_none());
+        run.save_label_map(&LabelMap::from_names(&["ham", "spam"]).unwrap()).unwrap();
+        run.log_metrics(&json!({ "stage": "train", "epoch": 1 })).unwrap();
+        run.log_metrics(&json!({ "stage": "train", "epoch": 2 })).unwrap();
+        fs::write(run.checkpoint_path(Some(1)), "{}").unwrap();
+        fs::write(run.checkpoint_path(Some(2)), "{}").unwrap();
+
+        let resumed = ExperimentRun::open(run.dir.to_str().unwrap()).unwrap();
+        let loaded_config = resumed.load_config().unwrap();
+        let tokenizer = resumed.load_tokenizer().unwrap();
+        let label_map = resumed.load_label_map().unwrap();
+        let metrics = resumed.load_metrics().unwrap();
+        let latest = resumed.latest_checkpoint();
+        fs::remove_dir_all(root).unwrap();
+
+        assert_eq!(loaded_config.epochs, 3);
+        assert_eq!(loaded_config.sliding_window, Some(window));
+        assert_eq!(loaded_config.word_dropout, Some(word_dropout));
+        assert_eq!(tokenizer.vocab["[PAD]"], 0);
+        assert_eq!(tokenizer.max_seq_length, 16);
+        assert_eq!(label_map.unwrap().names(), ["ham", "spam"]);
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(latest.map(|(epoch, _)| epoch), Some(2));
+    }
+
+    #[test]
+    fn test_latest_run() {
+        let root = &temp_path("experiment_run_latest_root");
+        assert!(ExperimentRun::latest(root).unwrap().is_none());
+
+        for name in ["run-100", "run-100-2", "run-100-10", "run-99-50"] {
+            fs::create_dir_all(Path::new(root).join(name)).unwrap();
+            fs::write(Path::new(root).join(name).join(CONFIG_FILE), "{}").unwrap();
+        }
+        // Not runs: no config, or not named by `create`.
+        fs::create_dir_all(Path::new(root).join("run-200")).unwrap();
+        fs::create_dir_all(Path::new(root).join("notes")).unwrap();
+        fs::write(Path::new(root).join("notes").join(CONFIG_FILE), "{}").unwrap();
+
+        let latest = ExperimentRun::latest(root).unwrap();
+        fs::remove_dir_all(root).unwrap();
+        assert_eq!(latest.unwrap().dir, Path::new(root).join("run-100-10"));
+    }
+
+    #[test]
+    fn test_open_rejects_non_run_directory() {
+        assert!(ExperimentRun::open("src").is_err());
+    }
+}
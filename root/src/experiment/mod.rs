@@ -0,0 +1,3 @@
+pub mod experiment_run;
+pub mod run_comparison;
+pub mod dataset_version;
@@ -0,0 +1,230 @@
+use crate::experiment::experiment_run::ExperimentRun;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+/// Config key of the training dataset hash, see `RunConfig::train_dataset`.
+const DATASET_HASH_KEY: &str = "train_dataset.hash";
+
+/// Flattened config and final metrics of one run.
+pub struct RunSummary {
+    pub name: String,
+    /// Config values keyed by dotted path, e.g. `model.d_model`.
+    pub config: BTreeMap<String, Value>,
+    /// Last logged value of every numeric metric, keyed as `<stage>.<metric>`, e.g. `test.accuracy`.
+    pub metrics: BTreeMap<String, f64>,
+}
+
+impl RunSummary {
+    pub fn load(run: &ExperimentRun) -> Result<Self, Box<dyn Error>> {
+        let name = run
+            .dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| run.dir.display().to_string());
+
+        let mut config = BTreeMap::new();
+        flatten("", &serde_json::to_value(run.load_config()?)?, &mut config);
+
+        let mut metrics = BTreeMap::new();
+        for record in run.load_metrics()? {
+            let Some(stage) = record.get("stage").and_then(Value::as_str) else { continue };
+            for (key, value) in record.as_object().into_iter().flatten() {
+                if key == "epoch" {
+                    continue;
+                }
+                if let Some(number) = value.as_f64() {
+                    metrics.insert(format!("{}.{}", stage, key), number);
+                }
+            }
+        }
+
+        Ok(RunSummary { name, config, metrics })
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, out: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, nested) in map {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&path, nested, out);
+            }
+        }
+        _ => {
+            out.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
+
+/// Side-by-side comparison of several runs. Metric deltas are relative to the first run.
+pub struct RunComparison {
+    pub runs: Vec<RunSummary>,
+}
+
+impl RunComparison {
+    pub fn new(runs: Vec<RunSummary>) -> Self {
+        RunComparison { runs }
+    }
+
+    /// Config keys whose value is not the same in every run, with each run's value.
+    pub fn config_deltas(&self) -> BTreeMap<String, Vec<Option<Value>>> {
+        let keys: BTreeSet<&String> = self.runs.iter().flat_map(|run| run.config.keys()).collect();
+
+        keys.into_iter()
+            .filter_map(|key| {
+                let values: Vec<Option<Value>> = self.runs.iter().map(|run| run.config.get(key).cloned()).collect();
+                if values.windows(2).all(|pair| pair[0] == pair[1]) {
+                    None
+                } else {
+                    Some((key.clone(), values))
+                }
+            })
+            .collect()
+    }
+
+    /// Describes runs trained on different versions of the training dataset, whose metrics
+    /// are not comparable; `None` when all recorded versions agree. Runs without a recorded
+    /// version are not checked.
+    pub fn dataset_mismatch(&self) -> Option<String> {
+        let versions: Vec<(&str, &str)> = self
+            .runs
+            .iter()
+            .filter_map(|run| Some((run.name.as_str(), run.config.get(DATASET_HASH_KEY)?.as_str()?)))
+            .collect();
+        if versions.windows(2).all(|pair| pair[0].1 == pair[1].1) {
+            return None;
+        }
+        let listed: Vec<String> = versions.iter().map(|(name, hash)| format!("{} ({})", name, hash)).collect();
+        Some(format!("Runs were trained on different dataset versions: {}", listed.join(", ")))
+    }
+
+    /// Every metric logged by at least one run, with each run's value.
+    pub fn metrics(&self) -> BTreeMap<String, Vec<Option<f64>>> {
+        let keys: BTreeSet<&String> = self.runs.iter().flat_map(|run| run.metrics.keys()).collect();
+
+        keys.into_iter()
+            .map(|key| (key.clone(), self.runs.iter().map(|run| run.metrics.get(key).copied()).collect()))
+            .collect()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let header = |first: &str| {
+            let names: Vec<&str> = self.runs.iter().map(|run| run.name.as_str()).collect();
+            format!(
+                "| {} | {} |\n|{}|\n",
+                first,
+                names.join(" | "),
+                vec!["---"; names.len() + 1].join("|")
+            )
+        };
+
+        let mut output = String::from("## Config differences\n\n");
+        let deltas = self.config_deltas();
+        if deltas.is_empty() {
+            output.push_str("All runs share the same config.\n");
+        } else {
+            output.push_str(&header("Key"));
+            for (key, values) in deltas {
+                let cells: Vec<String> = values
+                    .iter()
+                    .map(|value| value.as_ref().map_or("-".to_string(), Value::to_string))
+                    .collect();
+                output.push_str(&format!("| {} | {} |\n", key, cells.join(" | ")));
+            }
+        }
+
+        output.push_str("\n## Metrics\n\n");
+        output.push_str(&header("Metric"));
+        for (key, values) in self.metrics() {
+            let baseline = values.first().copied().flatten();
+            let cells: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| match (value, baseline) {
+                    (None, _) => "-".to_string(),
+                    (Some(value), Some(baseline)) if i > 0 => format!("{:.4} ({:+.4})", value, value - baseline),
+                    (Some(value), _) => format!("{:.4}", value),
+                })
+                .collect();
+            output.push_str(&format!("| {} | {} |\n", key, cells.join(" | ")));
+        }
+
+        output
+    }
+
+    pub fn to_json(&self) -> Value {
+        let config_deltas: Map<String, Value> = self
+            .config_deltas()
+            .into_iter()
+            .map(|(key, values)| (key, json!(values)))
+            .collect();
+
+        let metrics: Map<String, Value> = self
+            .metrics()
+            .into_iter()
+            .map(|(key, values)| {
+                let baseline = values.first().copied().flatten();
+                let deltas: Vec<Option<f64>> = values
+                    .iter()
+                    .map(|value| Some((*value)? - baseline?))
+                    .collect();
+                (key, json!({ "values": values, "deltas": deltas }))
+            })
+            .collect();
+
+        json!({
+            "runs": self.runs.iter().map(|run| run.name.clone()).collect::<Vec<String>>(),
+            "config_deltas": config_deltas,
+            "metrics": metrics,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(name: &str, epochs: usize, accuracy: f64) -> RunSummary {
+        RunSummary {
+            name: name.to_string(),
+            config: BTreeMap::from([
+                ("epochs".to_string(), json!(epochs)),
+                ("model.d_model".to_string(), json!(128)),
+            ]),
+            metrics: BTreeMap::from([("test.accuracy".to_string(), accuracy)]),
+        }
+    }
+
+    #[test]
+    fn test_only_differing_config_keys_are_reported() {
+        let comparison = RunComparison::new(vec![summary("a", 10, 0.8), summary("b", 20, 0.85)]);
+
+        let deltas = comparison.config_deltas();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas["epochs"], vec![Some(json!(10)), Some(json!(20))]);
+
+        let markdown = comparison.to_markdown();
+        assert!(markdown.contains("| epochs | 10 | 20 |"));
+        assert!(markdown.contains("| test.accuracy | 0.8000 | 0.8500 (+0.0500) |"));
+
+        let report = comparison.to_json();
+        let delta = report["metrics"]["test.accuracy"]["deltas"][1].as_f64().unwrap();
+        assert!((delta - 0.05).abs() < 1e-12);
+    }
+    #[test]
+    fn test_dataset_mismatch() {
+        let pinned = |name: &str, hash: Option<&str>| {
+            let mut run = summary(name, 10, 0.8);
+            if let Some(hash) = hash {
+                run.config.insert(DATASET_HASH_KEY.to_string(), json!(hash));
+            }
+            run
+        };
+        let same = RunComparison::new(vec![pinned("a", Some("0001")), pinned("b", Some("0001")), pinned("old", None)]);
+        assert_eq!(same.dataset_mismatch(), None);
+
+        let different = RunComparison::new(vec![pinned("a", Some("0001")), pinned("b", Some("0002"))]);
+        assert_eq!(different.dataset_mismatch().unwrap(), "Runs were trained on different dataset versions: a (0001), b (0002)");
+    }
+}
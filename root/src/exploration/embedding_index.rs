@@ -0,0 +1,305 @@
+use crate::data_handler::data_loader::DataLoader;
+use crate::tokenization::tokenizer::Tokenizer;
+use crate::transformer::Transformer;
+use ndarray::{s, Array1, Array2, ArrayView1, Axis};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::error::Error;
+
+/// Rows of the similarity matrix computed at a time by the dataset-wide reports, so their
+/// memory grows with the dataset size instead of its square.
+const SIMILARITY_BLOCK_ROWS: usize = 256;
+
+/// An example of the index returned by a nearest-neighbour query.
+#[derive(Debug, Serialize)]
+pub struct Neighbor {
+    /// Position of the example in the dataset.
+    pub index: usize,
+    pub id: String,
+    pub text: String,
+    pub label: Option<usize>,
+    /// Cosine similarity of the pooled embeddings, in [-1, 1].
+    pub similarity: f64,
+}
+
+/// An example whose nearest neighbours mostly carry a different label.
+#[derive(Debug, Serialize)]
+pub struct LabelDisagreement {
+    pub index: usize,
+    pub id: String,
+    pub label: usize,
+    /// Most common label among the neighbours.
+    pub neighbor_label: usize,
+    /// Fraction of the labelled neighbours that share the example's label.
+    pub agreement: f64,
+}
+
+/// Two examples whose embeddings are almost identical.
+#[derive(Debug, Serialize)]
+pub struct NearDuplicate {
+    pub first: usize,
+    pub second: usize,
+    pub similarity: f64,
+}
+
+/// Pooled encoder outputs of every example of a dataset, for exploring it in embedding space.
+///
+/// Rows are L2-normalized, so the dot product of two rows is their cosine similarity.
+/// Queries scan every example and the reports compare all pairs a block of rows at a time,
+/// which is fine for datasets of tens of thousands of examples; `export::ann_index` adds an
+/// approximate index for larger ones.
+#[derive(Serialize, Deserialize)]
+pub struct EmbeddingIndex {
+    embeddings: Array2<f64>,
+    ids: Vec<String>,
+    texts: Vec<String>,
+    labels: Vec<Option<usize>>,
+}
+
+impl EmbeddingIndex {
+    /// Embeds every record of a dataset with the model.
+    ///
+    /// # Arguments
+    /// * `model` - The trained model; its masked mean-pooled encoder output is the embedding.
+    /// * `data_loader` - Reads the dataset with its schema and tokenizes it in batches.
+    /// * `dataset_path` - CSV or JSON dataset. Labels are optional.
+    pub fn build(model: &Transformer, data_loader: &DataLoader, dataset_path: &str) -> Result<Self, Box<dyn Error>> {
+        let records = data_loader.load_records(dataset_path)?;
+        let texts: Vec<String> = records.iter().map(|record| record.text.clone()).collect();
+        let encoded = data_loader.encode_texts(&texts);
+
+        let mut embeddings = Array2::zeros((0, model.config.d_model));
+        for start in (0..texts.len()).step_by(data_loader.batch_size.max(1)) {
+            let end = (start + data_loader.batch_size).min(texts.len());
+            let (batch_array, mask_array) = encoded.slice(start..end).to_arrays()?;
+            let pooled = model.pooled_output(&batch_array, Some(&mask_array));
+            embeddings.append(Axis(0), pooled.view())?;
+        }
+        normalize_rows(&mut embeddings);
+
+        Ok(EmbeddingIndex {
+            embeddings,
+            ids: records.iter().map(|record| record.id.clone()).collect(),
+            labels: records.iter().map(|record| record.label).collect(),
+            texts,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    /// L2-normalized embeddings of the examples, one row per example.
+    pub fn embeddings(&self) -> &Array2<f64> {
+        &self.embeddings
+    }
+
+    /// The `k` examples most similar to a text, most similar first.
+    pub fn query(&self, model: &Transformer, tokenizer: &Tokenizer, text: &str, k: usize) -> Result<Vec<Neighbor>, Box<dyn Error>> {
+        let embedding = embed_text(model, tokenizer, text)?;
+        Ok(self.nearest(embedding.view(), k, None))
+    }
+
+    fn nearest(&self, embedding: ArrayView1<f64>, k: usize, exclude: Option<usize>) -> Vec<Neighbor> {
+        let similarities = self.embeddings.dot(&embedding);
+        top_k(similarities.view(), k, |i| Some(i) != exclude)
+            .into_iter()
+            .map(|index| self.neighbor(index, similarities[index]))
+            .collect()
+    }
+
+    /// Cosine similarities of every example with each row of `start..end`. Shape: [end - start, len].
+    fn similarity_block(&self, start: usize, end: usize) -> Array2<f64> {
+        self.embeddings.slice(s![start..end, ..]).dot(&self.embeddings.t())
+    }
+
+    /// Example `index` as a query result with the given similarity.
+    pub fn neighbor(&self, index: usize, similarity: f64) -> Neighbor {
+        Neighbor {
+            index,
+            id: self.ids[index].clone(),
+            text: self.texts[index].clone(),
+            label: self.labels[index],
+            similarity,
+        }
+    }
+
+    /// Labelled examples whose `k` nearest labelled neighbours mostly disagree with their
+    /// label: likely mislabeled, or genuinely ambiguous.
+    ///
+    /// # Returns
+    /// * Examples where less than half of the neighbours share the label, lowest agreement first.
+    pub fn label_disagreements(&self, k: usize) -> Vec<LabelDisagreement> {
+        let mut disagreements = Vec::new();
+        for start in (0..self.len()).step_by(SIMILARITY_BLOCK_ROWS) {
+            let end = (start + SIMILARITY_BLOCK_ROWS).min(self.len());
+            let similarities = self.similarity_block(start, end);
+            for index in start..end {
+                let Some(label) = self.labels[index] else { continue };
+                let neighbors = top_k(similarities.row(index - start), k, |other| other != index && self.labels[other].is_some());
+                let neighbor_labels: Vec<usize> = neighbors.into_iter().filter_map(|other| self.labels[other]).collect();
+                if neighbor_labels.is_empty() {
+                    continue;
+                }
+                let agreement = neighbor_labels.iter().filter(|&&other| other == label).count() as f64 / neighbor_labels.len() as f64;
+                if agreement < 0.5 {
+                    disagreements.push(LabelDisagreement {
+                        index,
+                        id: self.ids[index].clone(),
+                        label,
+                        neighbor_label: most_common(&neighbor_labels),
+                        agreement,
+                    });
+                }
+            }
+        }
+        disagreements.sort_by(|a, b| a.agreement.total_cmp(&b.agreement));
+        disagreements
+    }
+
+    /// Pairs of examples with a cosine similarity of at least `threshold`, most similar first.
+    pub fn near_duplicates(&self, threshold: f64) -> Vec<NearDuplicate> {
+        let mut duplicates = Vec::new();
+        for start in (0..self.len()).step_by(SIMILARITY_BLOCK_ROWS) {
+            let end = (start + SIMILARITY_BLOCK_ROWS).min(self.len());
+            let similarities = self.similarity_block(start, end);
+            for first in start..end {
+                for second in first + 1..self.len() {
+                    let similarity = similarities[[first - start, second]];
+                    if similarity >= threshold {
+                        duplicates.push(NearDuplicate { first, second, similarity });
+                    }
+                }
+            }
+        }
+        duplicates.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        duplicates
+    }
+
+    /// Id and text of example `index`.
+    pub fn example(&self, index: usize) -> (&str, &str) {
+        (&self.ids[index], &self.texts[index])
+    }
+}
+
+/// L2-normalized pooled embedding of a text, comparable with the rows of an `EmbeddingIndex`.
+pub fn embed_text(model: &Transformer, tokenizer: &Tokenizer, text: &str) -> Result<Array1<f64>, Box<dyn Error>> {
+    let (batch_array, mask_array) = tokenizer.encode_batch(&[text.to_string()]).to_arrays()?;
+    let mut embedding = model.pooled_output(&batch_array, Some(&mask_array));
+    normalize_rows(&mut embedding);
+    Ok(embedding.row(0).to_owned())
+}
+
+fn normalize_rows(embeddings: &mut Array2<f64>) {
+    for mut row in embeddings.outer_iter_mut() {
+        let norm = row.dot(&row).sqrt();
+        if norm > 0.0 {
+            row /= norm;
+        }
+    }
+}
+
+/// Indices of the `k` highest similarities among those `keep` accepts, highest first and
+/// the lowest index on ties.
+fn top_k(similarities: ArrayView1<f64>, k: usize, keep: impl Fn(usize) -> bool) -> Vec<usize> {
+    let ranking = |a: &usize, b: &usize| -> Ordering { similarities[*b].total_cmp(&similarities[*a]).then(a.cmp(b)) };
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut candidates: Vec<usize> = (0..similarities.len()).filter(|&i| keep(i)).collect();
+    if candidates.len() > k {
+        candidates.select_nth_unstable_by(k - 1, ranking);
+        candidates.truncate(k);
+    }
+    candidates.sort_by(ranking);
+    candidates
+}
+
+/// The most common value, the smallest on ties.
+fn most_common(values: &[usize]) -> usize {
+    let mut counts = std::collections::BTreeMap::new();
+    for &value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    counts.into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0))).map(|(value, _)| value).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{temp_path, tiny_config, tiny_vocab};
+    use ndarray::array;
+
+    fn index(embeddings: Array2<f64>, labels: Vec<Option<usize>>) -> EmbeddingIndex {
+        let mut embeddings = embeddings;
+        normalize_rows(&mut embeddings);
+        let n = labels.len();
+        EmbeddingIndex {
+            embeddings,
+            ids: (0..n).map(|i| i.to_string()).collect(),
+            texts: (0..n).map(|i| format!("text {}", i)).collect(),
+            labels,
+        }
+    }
+
+    #[test]
+    fn test_neighbors_and_reports() {
+        let index = index(
+            array![[1.0, 0.0], [0.99, 0.1], [0.9, 0.2], [0.0, 1.0], [0.1, 1.0], [0.95, 0.0], [0.05, 1.0], [0.2, 1.0]],
+            vec![Some(0), Some(0), Some(0), Some(1), Some(1), Some(1), Some(1), Some(1)],
+        );
+
+        let neighbors = index.nearest(index.embeddings.row(0), 2, Some(0));
+        assert_eq!(neighbors.iter().map(|n| n.index).collect::<Vec<_>>(), vec![5, 1]);
+        assert!((neighbors[0].similarity - 1.0).abs() < 1e-12);
+
+        // Example 5 sits among class 0 but is labelled 1.
+        let disagreements = index.label_disagreements(3);
+        assert_eq!(disagreements.len(), 1);
+        assert_eq!((disagreements[0].index, disagreements[0].neighbor_label), (5, 0));
+        assert_eq!(disagreements[0].agreement, 0.0);
+
+        let duplicates = index.near_duplicates(0.999);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!((duplicates[0].first, duplicates[0].second), (0, 5));
+    }
+
+    #[test]
+    fn test_reports_span_similarity_blocks() {
+        // Pairs of identical embeddings whose copies lie in different blocks.
+        let n = SIMILARITY_BLOCK_ROWS + 10;
+        let embeddings = Array2::from_shape_fn((n, 2), |(i, j)| {
+            let angle = (i % (n / 2)) as f64 * 0.01;
+            if j == 0 { angle.cos() } else { angle.sin() }
+        });
+        let labels = (0..n).map(|i| Some(usize::from(i == n - 1))).collect();
+        let index = index(embeddings, labels);
+
+        let duplicates = index.near_duplicates(1.0 - 1e-12);
+        assert_eq!(duplicates.len(), n / 2);
+        assert!(duplicates.iter().all(|duplicate| duplicate.second == duplicate.first + n / 2));
+        // The only example of class 1 and its copy disagree with each other.
+        let disagreements = index.label_disagreements(1);
+        assert_eq!(disagreements.iter().map(|d| d.index).collect::<Vec<_>>(), vec![n / 2 - 1, n - 1]);
+    }
+
+    #[test]
+    fn test_build_and_query() {
+
+        let vocab = tiny_vocab(&["refund", "late", "great"]);
+        let config = tiny_config(2);
+        let model = Transformer::new(config, vocab.clone());
+        let tokenizer = Tokenizer::new(vocab, 4);
+        let data_loader = DataLoader::new(&tokenizer);
+        let path = &temp_path("embedding_index_test_dataset.json");
+        std::fs::write(path, r#"[{ "text": "refund late", "label": 0 }, { "text": "great", "label": 1 }, { "text": "late refund" }]"#).unwrap();
+        let index = EmbeddingIndex::build(&model, &data_loader, path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(index.len(), 3);
+        let neighbors = index.query(&model, &tokenizer, "great", 3).unwrap();
+        assert_eq!(neighbors[0].text, "great");
+        assert!((neighbors[0].similarity - 1.0).abs() < 1e-9);
+        assert!(neighbors.iter().any(|neighbor| neighbor.label.is_none()));
+    }
+}
@@ -0,0 +1 @@
+pub mod embedding_index;
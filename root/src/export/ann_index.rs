@@ -0,0 +1,320 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::error::Error;
+use std::fs;
+use ndarray::{Array2, ArrayView1};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::data_handler::data_loader::DataLoader;
+use crate::exploration::embedding_index::{embed_text, EmbeddingIndex, Neighbor};
+use crate::tokenization::tokenizer::Tokenizer;
+use crate::transformer::Transformer;
+
+/// Construction and search settings of an HNSW graph.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HnswParams {
+    /// Links per node on the upper layers; layer 0 keeps up to `2 * m`.
+    pub m: usize,
+    /// Candidates considered while linking a new node. Higher builds slower, with better recall.
+    pub ef_construction: usize,
+    /// Candidates considered per query (at least `k`). Higher searches slower, with better recall.
+    pub ef_search: usize,
+    /// Seed of the random layer assignment, so the same embeddings always give the same graph.
+    pub seed: u64,
+}
+
+/// Hierarchical navigable small world graph (Malkov & Yashunin) over L2-normalized vectors,
+/// with cosine distance `1 - a·b`.
+///
+/// The graph stores only node links; the vectors are passed to every call, so they are kept
+/// once, in the `EmbeddingIndex` the graph was built from.
+#[derive(Serialize, Deserialize)]
+pub struct HnswGraph {
+    params: HnswParams,
+    /// `links[node][layer]`: neighbours of `node` on each layer it is part of.
+    links: Vec<Vec<Vec<usize>>>,
+    entry_point: Option<usize>,
+}
+
+/// A node and its distance to the query, ordered by distance.
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f64,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.node.cmp(&other.node))
+    }
+}
+
+impl HnswGraph {
+    /// Builds the graph by inserting the rows of `vectors` in order.
+    ///
+    /// # Arguments
+    /// * `vectors` - L2-normalized vectors, one per row.
+    /// * `params` - Construction and search settings.
+    pub fn build(vectors: &Array2<f64>, params: HnswParams) -> Self {
+        assert!(params.m >= 2, "HNSW needs at least 2 links per node.");
+        let mut graph = HnswGraph { params, links: Vec::with_capacity(vectors.nrows()), entry_point: None };
+        let mut rng = StdRng::seed_from_u64(params.seed);
+        let level_multiplier = 1.0 / (params.m as f64).ln();
+        for node in 0..vectors.nrows() {
+            let level = (-(1.0 - rng.gen::<f64>()).ln() * level_multiplier).floor() as usize;
+            graph.insert(vectors, node, level);
+        }
+        graph
+    }
+
+    /// Highest layer of the graph; 0 when empty.
+    pub fn max_level(&self) -> usize {
+        self.entry_point.map_or(0, |entry| self.links[entry].len() - 1)
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { 2 * self.params.m } else { self.params.m }
+    }
+
+    fn insert(&mut self, vectors: &Array2<f64>, node: usize, level: usize) {
+        self.links.push(vec![Vec::new(); level + 1]);
+        let Some(entry) = self.entry_point else {
+            self.entry_point = Some(node);
+            return;
+        };
+        let query = vectors.row(node);
+        let top = self.max_level();
+        let mut entry_points = vec![Candidate { distance: distance(vectors, query, entry), node: entry }];
+        for layer in (level + 1..=top).rev() {
+            entry_points = self.search_layer(vectors, query, &entry_points, 1, layer);
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(vectors, query, &entry_points, self.params.ef_construction, layer);
+            let neighbors: Vec<usize> = candidates.iter().take(self.params.m).map(|candidate| candidate.node).collect();
+            for &neighbor in &neighbors {
+                self.links[neighbor][layer].push(node);
+                if self.links[neighbor][layer].len() > self.max_links(layer) {
+                    self.prune(vectors, neighbor, layer);
+                }
+            }
+            self.links[node][layer] = neighbors;
+            entry_points = candidates;
+        }
+        if level > top {
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// Keeps the `max_links(layer)` closest links of `node`.
+    fn prune(&mut self, vectors: &Array2<f64>, node: usize, layer: usize) {
+        let mut links: Vec<Candidate> = self.links[node][layer]
+            .iter()
+            .map(|&other| Candidate { distance: distance(vectors, vectors.row(node), other), node: other })
+            .collect();
+        links.sort();
+        links.truncate(self.max_links(layer));
+        self.links[node][layer] = links.into_iter().map(|candidate| candidate.node).collect();
+    }
+
+    /// Best-first search of one layer.
+    ///
+    /// # Returns
+    /// * Up to `ef` of the closest nodes found, closest first.
+    fn search_layer(&self, vectors: &Array2<f64>, query: ArrayView1<f64>, entry_points: &[Candidate], ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entry_points.iter().map(|candidate| candidate.node).collect();
+        let mut to_visit: BinaryHeap<Reverse<Candidate>> = entry_points.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Candidate> = entry_points.iter().copied().collect();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(Reverse(current)) = to_visit.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|furthest| current.distance > furthest.distance) {
+                break;
+            }
+            for &neighbor in &self.links[current.node][layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let candidate = Candidate { distance: distance(vectors, query, neighbor), node: neighbor };
+                if found.len() < ef || found.peek().is_some_and(|furthest| candidate < *furthest) {
+                    to_visit.push(Reverse(candidate));
+                    found.push(candidate);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    /// Approximate `k` nearest nodes of a query vector.
+    ///
+    /// # Arguments
+    /// * `vectors` - The vectors the graph was built from.
+    /// * `query` - An L2-normalized vector.
+    ///
+    /// # Returns
+    /// * Node indices with their cosine similarity, most similar first.
+    pub fn search(&self, vectors: &Array2<f64>, query: ArrayView1<f64>, k: usize) -> Vec<(usize, f64)> {
+        let Some(entry) = self.entry_point else { return Vec::new() };
+        let mut entry_points = vec![Candidate { distance: distance(vectors, query, entry), node: entry }];
+        for layer in (1..=self.max_level()).rev() {
+            entry_points = self.search_layer(vectors, query, &entry_points, 1, layer);
+        }
+        self.search_layer(vectors, query, &entry_points, self.params.ef_search.max(k), 0)
+            .into_iter()
+            .take(k)
+            .map(|candidate| (candidate.node, 1.0 - candidate.distance))
+            .collect()
+    }
+}
+
+fn distance(vectors: &Array2<f64>, query: ArrayView1<f64>, node: usize) -> f64 {
+    1.0 - vectors.row(node).dot(&query)
+}
+
+/// The pooled embeddings of a dataset with an HNSW graph over them, saved as one JSON file
+/// next to the model that produced them.
+#[derive(Serialize, Deserialize)]
+pub struct AnnIndex {
+    examples: EmbeddingIndex,
+    graph: HnswGraph,
+}
+
+impl AnnIndex {
+    /// Embeds every record of a dataset with the model and links the embeddings into a graph.
+    ///
+    /// # Arguments
+    /// * `model` - The encoder whose masked mean-pooled output is indexed.
+    /// * `data_loader` - Reads the dataset with its schema and tokenizes it in batches.
+    /// * `dataset_path` - CSV or JSON dataset, e.g. the documents to search.
+    /// * `params` - HNSW settings.
+    pub fn build(model: &Transformer, data_loader: &DataLoader, dataset_path: &str, params: HnswParams) -> Result<Self, Box<dyn Error>> {
+        let examples = EmbeddingIndex::build(model, data_loader, dataset_path)?;
+        let graph = HnswGraph::build(examples.embeddings(), params);
+        Ok(AnnIndex { examples, graph })
+    }
+
+    pub fn len(&self) -> usize {
+        self.examples.len()
+    }
+
+    /// Approximate `k` nearest examples of an embedding from `embed_text`, most similar first.
+    pub fn search_embedding(&self, embedding: ArrayView1<f64>, k: usize) -> Vec<Neighbor> {
+        self.graph
+            .search(self.examples.embeddings(), embedding, k)
+            .into_iter()
+            .map(|(index, similarity)| self.examples.neighbor(index, similarity))
+            .collect()
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(file_path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        serde_json::from_str(&fs::read_to_string(file_path)?).map_err(|e| format!("Failed to read index {}: {}", file_path, e).into())
+    }
+}
+
+/// A trained encoder, its tokenizer and an `AnnIndex` built with them: a semantic search
+/// backend without extra services.
+pub struct SemanticSearch {
+    model: Transformer,
+    tokenizer: Tokenizer,
+    index: AnnIndex,
+}
+
+impl SemanticSearch {
+    /// # Arguments
+    /// * `model` - Must be the model the index was built with; embeddings of other models are not comparable.
+    pub fn new(model: Transformer, tokenizer: Tokenizer, index: AnnIndex) -> Self {
+        SemanticSearch { model, tokenizer, index }
+    }
+
+    /// Loads the model, tokenizer and index files.
+    pub fn load(model_path: &str, tokenizer_path: &str, index_path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(SemanticSearch::new(Transformer::load(model_path)?, Tokenizer::load(tokenizer_path)?, AnnIndex::load(index_path)?))
+    }
+
+    /// The `k` indexed examples most similar to `text`, most similar first.
+    pub fn search(&self, text: &str, k: usize) -> Result<Vec<Neighbor>, Box<dyn Error>> {
+        let embedding = embed_text(&self.model, &self.tokenizer, text)?;
+        Ok(self.index.search_embedding(embedding.view(), k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{temp_path, tiny_config, tiny_vocab};
+    use ndarray::Axis;
+    use ndarray_rand::rand_distr::StandardNormal;
+    use ndarray_rand::RandomExt;
+
+    #[test]
+    fn test_hnsw_recall_against_exact_search() {
+        let mut vectors: Array2<f64> = Array2::random_using((500, 8), StandardNormal, &mut StdRng::seed_from_u64(5));
+        for mut row in vectors.axis_iter_mut(Axis(0)) {
+            let norm = row.dot(&row).sqrt();
+            row /= norm;
+        }
+        let params = HnswParams { m: 8, ef_construction: 64, ef_search: 32, seed: 1 };
+        let graph = HnswGraph::build(&vectors, params);
+        assert_eq!(graph.links.len(), 500);
+        assert!(graph.max_level() > 0);
+        assert!(graph.links.iter().all(|layers| layers.iter().enumerate().all(|(layer, links)| links.len() <= graph.max_links(layer))));
+
+        let (k, mut hits) = (10, 0);
+        for query in 0..50 {
+            let similarities = vectors.dot(&vectors.row(query));
+            let mut exact: Vec<usize> = (0..vectors.nrows()).collect();
+            exact.sort_by(|&a, &b| similarities[b].total_cmp(&similarities[a]));
+            let found = graph.search(&vectors, vectors.row(query), k);
+            assert_eq!(found[0].0, query);
+            assert!(found.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+            hits += found.iter().filter(|(node, _)| exact[..k].contains(node)).count();
+        }
+        assert!(hits as f64 / (50 * k) as f64 > 0.9, "recall {}", hits as f64 / (50 * k) as f64);
+
+        // The same seed gives the same graph.
+        assert_eq!(HnswGraph::build(&vectors, params).links, graph.links);
+    }
+
+    #[test]
+    fn test_semantic_search_round_trip() {
+
+        let vocab = tiny_vocab(&["refund", "late", "great"]);
+        let config = tiny_config(2);
+        let model = Transformer::new(config, vocab.clone());
+        let tokenizer = Tokenizer::new(vocab, 4);
+        let data_loader = DataLoader::new(&tokenizer);
+        let (dataset_path, index_path) = (&temp_path("ann_index_test_dataset.json"), &temp_path("ann_index_test_index.json"));
+        fs::write(dataset_path, r#"[{ "text": "refund late" }, { "text": "great" }, { "text": "late late refund" }]"#).unwrap();
+        let params = HnswParams { m: 4, ef_construction: 16, ef_search: 8, seed: 0 };
+        let index = AnnIndex::build(&model, &data_loader, dataset_path, params).unwrap();
+        index.save(index_path).unwrap();
+        let loaded = AnnIndex::load(index_path).unwrap();
+        fs::remove_file(dataset_path).unwrap();
+        fs::remove_file(index_path).unwrap();
+
+        let search = SemanticSearch::new(model, tokenizer, loaded);
+        let results = search.search("great", 2).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].text, "great");
+        assert!((results[0].similarity - 1.0).abs() < 1e-9);
+    }
+}
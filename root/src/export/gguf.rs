@@ -0,0 +1,407 @@
+use crate::configurration::config::{CLS_TOKEN, MASK_TOKEN, PAD_TOKEN, SEP_TOKEN, UNK_TOKEN};
+use crate::tokenization::huggingface::HuggingFaceModel;
+use crate::tokenization::tokenizer::{Segmentation, Tokenizer};
+use crate::transformer::Transformer;
+use ndarray::{Array1, Array2, ArrayView1};
+
+pub const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+pub const GGUF_VERSION: u32 = 3;
+/// Alignment of the tensor data section and of every tensor in it.
+pub const GGUF_ALIGNMENT: usize = 32;
+/// Value of `general.architecture`; architecture-specific keys are prefixed with it.
+pub const GGUF_ARCHITECTURE: &str = "transformer-classifier";
+
+// GGUF metadata value types.
+const TYPE_UINT32: u32 = 4;
+const TYPE_FLOAT32: u32 = 6;
+const TYPE_STRING: u32 = 8;
+const TYPE_ARRAY: u32 = 9;
+// GGML tensor types.
+const GGML_TYPE_F32: u32 = 0;
+// llama.cpp pooling type for mean pooling.
+const POOLING_TYPE_MEAN: u32 = 1;
+
+/// A metadata value supported by the exporter.
+pub enum MetadataValue {
+    U32(u32),
+    F32(f32),
+    String(String),
+    StringArray(Vec<String>),
+    F32Array(Vec<f32>),
+}
+
+struct GgufTensor {
+    name: String,
+    /// Row-major shape; GGUF stores dimensions innermost first, i.e. reversed.
+    shape: Vec<usize>,
+    data: Vec<f32>,
+}
+
+/// Builds a GGUF (version 3) file from metadata and f32 tensors.
+#[derive(Default)]
+pub struct GgufWriter {
+    metadata: Vec<(String, MetadataValue)>,
+    tensors: Vec<GgufTensor>,
+}
+
+impl GgufWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_metadata(&mut self, key: &str, value: MetadataValue) {
+        self.metadata.push((key.to_string(), value));
+    }
+
+    /// Adds a matrix as an f32 tensor. Linear layer weights follow the GGML convention
+    /// of one row per output feature, so `x.dot(w)` weights have to be transposed first.
+    pub fn add_matrix(&mut self, name: &str, matrix: &Array2<f64>) {
+        self.tensors.push(GgufTensor {
+            name: name.to_string(),
+            shape: matrix.shape().to_vec(),
+            data: matrix.iter().map(|&v| v as f32).collect(),
+        });
+    }
+
+    /// Adds a vector, e.g. a bias, as a one-dimensional f32 tensor.
+    pub fn add_vector(&mut self, name: &str, vector: ArrayView1<f64>) {
+        self.tensors.push(GgufTensor {
+            name: name.to_string(),
+            shape: vec![vector.len()],
+            data: vector.iter().map(|&v| v as f32).collect(),
+        });
+    }
+
+    /// Serializes the header, metadata, tensor infos and aligned tensor data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(GGUF_MAGIC);
+        bytes.extend_from_slice(&GGUF_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.tensors.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.metadata.len() as u64).to_le_bytes());
+
+        for (key, value) in &self.metadata {
+            write_string(&mut bytes, key);
+            write_value(&mut bytes, value);
+        }
+
+        let mut offset = 0;
+        for tensor in &self.tensors {
+            write_string(&mut bytes, &tensor.name);
+            bytes.extend_from_slice(&(tensor.shape.len() as u32).to_le_bytes());
+            for &dim in tensor.shape.iter().rev() {
+                bytes.extend_from_slice(&(dim as u64).to_le_bytes());
+            }
+            bytes.extend_from_slice(&GGML_TYPE_F32.to_le_bytes());
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset = align(offset + tensor.data.len() * 4);
+        }
+
+        let data_start = align(bytes.len());
+        bytes.resize(data_start, 0);
+        for tensor in &self.tensors {
+            bytes.resize(data_start + align(bytes.len() - data_start), 0);
+            for value in &tensor.data {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn write(&self, file_path: &str) -> Result<(), std::io::Error> {
+        std::fs::write(file_path, self.to_bytes())
+    }
+}
+
+fn align(offset: usize) -> usize {
+    offset.div_ceil(GGUF_ALIGNMENT) * GGUF_ALIGNMENT
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn write_value(bytes: &mut Vec<u8>, value: &MetadataValue) {
+    match value {
+        MetadataValue::U32(v) => {
+            bytes.extend_from_slice(&TYPE_UINT32.to_le_bytes());
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        MetadataValue::F32(v) => {
+            bytes.extend_from_slice(&TYPE_FLOAT32.to_le_bytes());
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        MetadataValue::String(v) => {
+            bytes.extend_from_slice(&TYPE_STRING.to_le_bytes());
+            write_string(bytes, v);
+        }
+        MetadataValue::StringArray(values) => {
+            bytes.extend_from_slice(&TYPE_ARRAY.to_le_bytes());
+            bytes.extend_from_slice(&TYPE_STRING.to_le_bytes());
+            bytes.extend_from_slice(&(values.len() as u64).to_le_bytes());
+            for v in values {
+                write_string(bytes, v);
+            }
+        }
+        MetadataValue::F32Array(values) => {
+            bytes.extend_from_slice(&TYPE_ARRAY.to_le_bytes());
+            bytes.extend_from_slice(&TYPE_FLOAT32.to_le_bytes());
+            bytes.extend_from_slice(&(values.len() as u64).to_le_bytes());
+            for v in values {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Collects the model's hyperparameters, tokenizer and weights into a GGUF writer.
+///
+/// Tensor names follow the llama.cpp BERT layout (`token_embd`, `position_embd`,
+/// `blk.{i}.ffn_up`, `blk.{i}.ffn_down`, `cls`). The sinusoidal positional encodings
+/// are exported as `position_embd.weight` for `context_length` positions.
+///
+/// # Arguments
+/// * `model` - The trained model.
+/// * `tokenizer` - The tokenizer the model was trained with.
+///
+/// # Returns
+/// * A writer holding the complete file.
+pub fn build_gguf(model: &Transformer, tokenizer: &Tokenizer) -> GgufWriter {
+    let mut writer = GgufWriter::new();
+    let config = &model.config;
+    let key = |name: &str| format!("{}.{}", GGUF_ARCHITECTURE, name);
+
+    writer.add_metadata("general.architecture", MetadataValue::String(GGUF_ARCHITECTURE.to_string()));
+    writer.add_metadata("general.alignment", MetadataValue::U32(GGUF_ALIGNMENT as u32));
+    writer.add_metadata(&key("context_length"), MetadataValue::U32(tokenizer.max_seq_length as u32));
+    writer.add_metadata(&key("embedding_length"), MetadataValue::U32(config.d_model as u32));
+    writer.add_metadata(&key("feed_forward_length"), MetadataValue::U32(config.ff_dim as u32));
+    writer.add_metadata(&key("block_count"), MetadataValue::U32(config.num_layers as u32));
+    writer.add_metadata(&key("attention.head_count"), MetadataValue::U32(config.num_heads as u32));
+    writer.add_metadata(&key("attention.layer_norm_epsilon"), MetadataValue::F32(config.epsilon as f32));
+    writer.add_metadata(&key("pooling_type"), MetadataValue::U32(POOLING_TYPE_MEAN));
+    writer.add_metadata(&key("num_classes"), MetadataValue::U32(model.classification_head.num_classes() as u32));
+    if let Some(max_distance) = config.relative_positions {
+        writer.add_metadata(&key("attention.relative_max_distance"), MetadataValue::U32(max_distance as u32));
+    }
+
+    let tokens = tokens_by_id(tokenizer, model);
+    let tokenizer_model = match &tokenizer.segmentation {
+        Segmentation::WordPiece => "bert",
+        Segmentation::Words => "word",
+        Segmentation::HuggingFace(pipeline) => match pipeline.model {
+            HuggingFaceModel::WordPiece { .. } => "bert",
+            HuggingFaceModel::WordLevel => "word",
+        },
+        Segmentation::Unigram(unigram) => {
+            // Piece log probabilities, needed by unigram segmentation; other tokens score 0.
+            let scores = tokens.iter().map(|token| unigram.pieces.get(token).copied().unwrap_or(0.0) as f32).collect();
+            writer.add_metadata("tokenizer.ggml.scores", MetadataValue::F32Array(scores));
+            "unigram"
+        }
+    };
+    writer.add_metadata("tokenizer.ggml.model", MetadataValue::String(tokenizer_model.to_string()));
+    writer.add_metadata("tokenizer.ggml.tokens", MetadataValue::StringArray(tokens));
+    for (name, token) in [
+        ("padding_token_id", PAD_TOKEN),
+        ("unknown_token_id", UNK_TOKEN),
+        ("seperator_token_id", SEP_TOKEN),
+        ("cls_token_id", CLS_TOKEN),
+        ("mask_token_id", MASK_TOKEN),
+    ] {
+        if let Some(&id) = tokenizer.vocab.get(token) {
+            writer.add_metadata(&format!("tokenizer.ggml.{}", name), MetadataValue::U32(id as u32));
+        }
+    }
+
+    // The `sqrt(d_model)` input scaling is folded into the table, so readers need no extra key.
+    writer.add_matrix("token_embd.weight", &(model.embeddings.token_embedding_matrix() * model.embeddings.input_scale()));
+    writer.add_matrix(
+        "position_embd.weight",
+        &model.embeddings.generate_positional_encodings(tokenizer.max_seq_length),
+    );
+    if let Some(segment_embeddings) = model.embeddings.segment_embedding_matrix() {
+        writer.add_matrix("token_types.weight", segment_embeddings);
+    }
+    // The embedding layer norm has no learned scale or shift, exported as ones and zeros;
+    // it uses the model's `attention.layer_norm_epsilon`.
+    if model.embeddings.layer_norm_epsilon.is_some() {
+        writer.add_vector("token_embd_norm.weight", Array1::ones(config.d_model).view());
+        writer.add_vector("token_embd_norm.bias", Array1::zeros(config.d_model).view());
+    }
+    for (i, layer) in model.encoder_layers.iter().enumerate() {
+        let (w1, b1, w2, b2) = layer.feed_forward.parameters();
+        writer.add_matrix(&format!("blk.{}.ffn_up.weight", i), &w1.t().to_owned());
+        writer.add_vector(&format!("blk.{}.ffn_up.bias", i), b1.row(0));
+        writer.add_matrix(&format!("blk.{}.ffn_down.weight", i), &w2.t().to_owned());
+        writer.add_vector(&format!("blk.{}.ffn_down.bias", i), b2.row(0));
+        if let Some(projections) = &layer.projections {
+            writer.add_matrix(&format!("blk.{}.attn_q.weight", i), &projections.query.t().to_owned());
+            writer.add_matrix(&format!("blk.{}.attn_k.weight", i), &projections.key.t().to_owned());
+            writer.add_matrix(&format!("blk.{}.attn_v.weight", i), &projections.value.t().to_owned());
+            writer.add_matrix(&format!("blk.{}.attn_output.weight", i), &projections.output.t().to_owned());
+        }
+        if let Some(positions) = &layer.relative_positions {
+            writer.add_matrix(&format!("blk.{}.attn_rel_k.weight", i), &positions.key_embeddings);
+            writer.add_matrix(&format!("blk.{}.attn_rel_v.weight", i), &positions.value_embeddings);
+        }
+    }
+    let (weights, biases) = model.classification_head.parameters();
+    writer.add_matrix("cls.weight", &weights.t().to_owned());
+    writer.add_vector("cls.bias", biases.row(0));
+
+    writer
+}
+
+/// Writes the model and tokenizer to a GGUF file.
+pub fn export_gguf(model: &Transformer, tokenizer: &Tokenizer, file_path: &str) -> Result<(), std::io::Error> {
+    build_gguf(model, tokenizer).write(file_path)
+}
+
+/// The vocabulary ordered by id, one entry per embedding row. Ids without a token get `[unused{id}]`.
+fn tokens_by_id(tokenizer: &Tokenizer, model: &Transformer) -> Vec<String> {
+    let size = model.embeddings.vocab_size().max(tokenizer.vocab.len());
+    let mut tokens: Vec<String> = (0..size).map(|id| format!("[unused{}]", id)).collect();
+    for (token, &id) in &tokenizer.vocab {
+        if id < size {
+            tokens[id] = token.clone();
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{tiny_config, tiny_vocab};
+    use crate::transformer::TransformerConfig;
+
+    /// Minimal GGUF reader returning the metadata keys and `(name, dims, offset)` of every tensor.
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn take(&mut self, n: usize) -> &'a [u8] {
+            self.pos += n;
+            &self.bytes[self.pos - n..self.pos]
+        }
+        fn u32(&mut self) -> u32 {
+            u32::from_le_bytes(self.take(4).try_into().unwrap())
+        }
+        fn u64(&mut self) -> u64 {
+            u64::from_le_bytes(self.take(8).try_into().unwrap())
+        }
+        fn string(&mut self) -> String {
+            let len = self.u64() as usize;
+            String::from_utf8(self.take(len).to_vec()).unwrap()
+        }
+        fn skip_value(&mut self, value_type: u32) {
+            match value_type {
+                TYPE_UINT32 | TYPE_FLOAT32 => {
+                    self.take(4);
+                }
+                TYPE_STRING => {
+                    self.string();
+                }
+                TYPE_ARRAY => {
+                    let element_type = self.u32();
+                    for _ in 0..self.u64() {
+                        self.skip_value(element_type);
+                    }
+                }
+                other => panic!("unexpected value type {}", other),
+            }
+        }
+    }
+
+    fn tiny_model() -> (Transformer, Tokenizer) {
+        let vocab = tiny_vocab(&["good"]);
+        let config = TransformerConfig { num_layers: 2, ff_dim: 6, ..tiny_config(3) };
+        (Transformer::new(config, vocab.clone()), Tokenizer::new(vocab, 5))
+    }
+
+    #[test]
+    fn test_gguf_layout() {
+        let (model, tokenizer) = tiny_model();
+        let bytes = build_gguf(&model, &tokenizer).to_bytes();
+        let mut reader = Reader { bytes: &bytes, pos: 0 };
+
+        assert_eq!(reader.take(4), GGUF_MAGIC);
+        assert_eq!(reader.u32(), GGUF_VERSION);
+        let tensor_count = reader.u64();
+        let metadata_count = reader.u64();
+        assert_eq!(tensor_count, 2 + 4 * 2 + 2);
+
+        let mut keys = Vec::new();
+        for _ in 0..metadata_count {
+            keys.push(reader.string());
+            let value_type = reader.u32();
+            reader.skip_value(value_type);
+        }
+        assert!(keys.contains(&"tokenizer.ggml.tokens".to_string()));
+        assert!(keys.contains(&format!("{}.block_count", GGUF_ARCHITECTURE)));
+
+        let mut infos = Vec::new();
+        for _ in 0..tensor_count {
+            let name = reader.string();
+            let dims: Vec<u64> = (0..reader.u32()).map(|_| reader.u64()).collect();
+            assert_eq!(reader.u32(), GGML_TYPE_F32);
+            infos.push((name, dims, reader.u64() as usize));
+        }
+
+        assert_eq!(infos[0].0, "token_embd.weight");
+        assert_eq!(infos[0].1, vec![4, 3]);
+        let ffn_up = infos.iter().find(|info| info.0 == "blk.1.ffn_up.weight").unwrap();
+        assert_eq!(ffn_up.1, vec![4, 6]);
+        assert!(infos.iter().all(|info| info.2 % GGUF_ALIGNMENT == 0));
+
+        let data_start = align(reader.pos);
+        let embeddings = model.embeddings.token_embedding_matrix();
+        let first = f32::from_le_bytes(bytes[data_start..data_start + 4].try_into().unwrap());
+        assert_eq!(first, (embeddings[(0, 0)] * model.embeddings.input_scale()) as f32);
+
+        let (w1, _, _, _) = model.encoder_layers[1].feed_forward.parameters();
+        let offset = data_start + ffn_up.2 + 4;
+        let second = f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        assert_eq!(second, w1[(1, 0)] as f32);
+    }
+
+    #[test]
+    fn test_tokens_fill_gaps() {
+        let (mut model, tokenizer) = tiny_model();
+        model.embeddings.add_token("extra");
+
+        let tokens = tokens_by_id(&tokenizer, &model);
+        assert_eq!(tokens, vec![PAD_TOKEN, UNK_TOKEN, "good", "[unused3]"]);
+    }
+
+    #[test]
+    fn test_bert_embedding_block_tensors() {
+        let (model, tokenizer) = tiny_model();
+        let config = TransformerConfig { bert_embeddings: true, ..model.config.clone() };
+        let model = Transformer::new(config, model.embeddings.vocab().clone());
+        let bytes = build_gguf(&model, &tokenizer).to_bytes();
+        let mut reader = Reader { bytes: &bytes, pos: 8 };
+        let (tensor_count, metadata_count) = (reader.u64(), reader.u64());
+        for _ in 0..metadata_count {
+            reader.string();
+            let value_type = reader.u32();
+            reader.skip_value(value_type);
+        }
+
+        let mut tensors = Vec::new();
+        for _ in 0..tensor_count {
+            let name = reader.string();
+            let dims: Vec<u64> = (0..reader.u32()).map(|_| reader.u64()).collect();
+            reader.u32();
+            reader.u64();
+            tensors.push((name, dims));
+        }
+        assert!(tensors.contains(&("token_types.weight".to_string(), vec![4, 2])));
+        assert!(tensors.contains(&("token_embd_norm.weight".to_string(), vec![4])));
+        assert!(tensors.contains(&("token_embd_norm.bias".to_string(), vec![4])));
+    }
+}
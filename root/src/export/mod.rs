@@ -0,0 +1,2 @@
+pub mod gguf;
+pub mod ann_index;
@@ -1,23 +1,26 @@
-use ndarray::{Array2, Axis};
+use ndarray::{Array2, Axis, ShapeError};
 use ndarray_rand::RandomExt;
 use ndarray_rand::rand_distr::Uniform;
 use serde::{Serialize, Deserialize};
+use crate::backend::tensor_backend::{CpuBackend, TensorBackend};
+use crate::numerics::Float;
 
+/// Position-wise feed-forward network `ReLU(x W1 + b1) W2 + b2`, in any `Float` precision.
 #[derive(Serialize, Deserialize)]
-pub struct FeedForwardNetwork {
-    w1: Array2<f64>,
-    b1: Array2<f64>,
-    w2: Array2<f64>,
-    b2: Array2<f64>,
+pub struct FeedForwardNetwork<A = f64> {
+    w1: Array2<A>,
+    b1: Array2<A>,
+    w2: Array2<A>,
+    b2: Array2<A>,
     hidden_dim: usize,
     input_dim: usize,
 }
 
-impl FeedForwardNetwork {
+impl<A: Float> FeedForwardNetwork<A> {
     pub fn new(input_dim: usize, hidden_dim: usize) -> Self {
-        let w1 = Array2::random((input_dim, hidden_dim), Uniform::new(-0.1, 0.1));
+        let w1 = Array2::random((input_dim, hidden_dim), Uniform::new(A::cast(-0.1), A::cast(0.1)));
         let b1 = Array2::zeros((1, hidden_dim));
-        let w2 = Array2::random((hidden_dim, input_dim), Uniform::new(-0.1, 0.1));
+        let w2 = Array2::random((hidden_dim, input_dim), Uniform::new(A::cast(-0.1), A::cast(0.1)));
         let b2 = Array2::zeros((1, input_dim));
 
         Self {
@@ -30,20 +33,94 @@ impl FeedForwardNetwork {
         }
     }
 
-    pub fn forward(&self, x: &Array2<f64>) -> Array2<f64> {
+    /// Creates a network from existing weights `w1` [input_dim, hidden_dim], `b1` [1, hidden_dim],
+    /// `w2` [hidden_dim, input_dim] and `b2` [1, input_dim].
+    pub fn from_parameters(w1: Array2<A>, b1: Array2<A>, w2: Array2<A>, b2: Array2<A>) -> Self {
+        let (input_dim, hidden_dim) = w1.dim();
+        assert_eq!(b1.dim(), (1, hidden_dim), "b1 does not match w1!");
+        assert_eq!(w2.dim(), (hidden_dim, input_dim), "w2 does not match w1!");
+        assert_eq!(b2.dim(), (1, input_dim), "b2 does not match w2!");
+
+        Self {
+            w1,
+            b1,
+            w2,
+            b2,
+            hidden_dim,
+            input_dim,
+        }
+    }
+
+    pub fn forward(&self, x: &Array2<A>) -> Array2<A> {
         assert_eq!(x.shape()[1], self.input_dim, "Input dimensions do not match!");
+        self.forward_on(&CpuBackend, x).expect("the input width is checked above")
+    }
 
-       
-        let mut h = x.dot(&self.w1) + &self.b1;
-        h.mapv_inplace(|v| v.max(0.0));
+    /// `forward` computed with the operations of `backend`. Fails with `IncompatibleShape`
+    /// when `x` is not [seq_len, input_dim].
+    pub fn forward_on(&self, backend: &dyn TensorBackend<A>, x: &Array2<A>) -> Result<Array2<A>, ShapeError> {
+        let h = self.hidden_activations_on(backend, x)?;
+        backend.add(&backend.matmul(&h, &self.w2)?, &self.b2)
+    }
 
-  
-        let y = h.dot(&self.w2) + &self.b2;
+    /// ReLU activations of the hidden layer, i.e. the input of the second linear layer.
+    /// Shape: [seq_len, hidden_dim].
+    pub fn hidden_activations(&self, x: &Array2<A>) -> Array2<A> {
+        assert_eq!(x.shape()[1], self.input_dim, "Input dimensions do not match!");
+        self.hidden_activations_on(&CpuBackend, x).expect("the input width is checked above")
+    }
+
+    /// `hidden_activations` computed with the operations of `backend`.
+    pub fn hidden_activations_on(&self, backend: &dyn TensorBackend<A>, x: &Array2<A>) -> Result<Array2<A>, ShapeError> {
+        Ok(backend.relu(&backend.add(&backend.matmul(x, &self.w1)?, &self.b1)?))
+    }
+
+    /// The weights and biases `(w1, b1, w2, b2)`.
+    pub fn parameters(&self) -> (&Array2<A>, &Array2<A>, &Array2<A>, &Array2<A>) {
+        (&self.w1, &self.b1, &self.w2, &self.b2)
+    }
 
-        y
+    /// Backward pass through the network.
+    ///
+    /// # Arguments
+    /// - `x`: The input used in the forward pass. Shape: [seq_len, input_dim].
+    /// - `grad_output`: Gradient of the loss with respect to the output. Shape: [seq_len, input_dim].
+    ///
+    /// # Returns
+    /// - Gradient with respect to `x`, and the parameter gradients flattened in
+    ///   the same order as `parameters_mut` (w1, b1, w2, b2).
+    pub fn backward(&self, x: &Array2<A>, grad_output: &Array2<A>) -> (Array2<A>, Vec<A>) {
+        let pre_activation = x.dot(&self.w1) + &self.b1;
+        let h = pre_activation.mapv(|v| v.max(A::zero()));
+
+        let grad_w2 = h.t().dot(grad_output);
+        let grad_b2 = grad_output.sum_axis(Axis(0));
+
+        let mut grad_h = grad_output.dot(&self.w2.t());
+        grad_h.zip_mut_with(&pre_activation, |g, &z| {
+            if z <= A::zero() {
+                *g = A::zero();
+            }
+        });
+
+        let grad_w1 = x.t().dot(&grad_h);
+        let grad_b1 = grad_h.sum_axis(Axis(0));
+        let grad_input = grad_h.dot(&self.w1.t());
+
+        let mut param_grads = Vec::with_capacity(grad_w1.len() + grad_b1.len() + grad_w2.len() + grad_b2.len());
+        param_grads.extend(grad_w1.iter());
+        param_grads.extend(grad_b1.iter());
+        param_grads.extend(grad_w2.iter());
+        param_grads.extend(grad_b2.iter());
+
+        (grad_input, param_grads)
     }
 
-    pub fn parameters_mut(&mut self) -> Vec<&mut f64> {
+    pub fn num_parameters(&self) -> usize {
+        self.w1.len() + self.b1.len() + self.w2.len() + self.b2.len()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut A> {
         let mut params = vec![];
 
         for value in self.w1.iter_mut() {
@@ -90,7 +167,7 @@ mod tests {
         let input_dim = 4;
         let hidden_dim = 8;
 
-        let ff = FeedForwardNetwork::new(input_dim, hidden_dim);
+        let ff: FeedForwardNetwork = FeedForwardNetwork::new(input_dim, hidden_dim);
 
         let serialized = serde_json::to_string(&ff).expect("Serialization failed");
         let deserialized: FeedForwardNetwork = serde_json::from_str(&serialized).expect("Deserialization failed");
@@ -98,4 +175,20 @@ mod tests {
         assert_eq!(ff.input_dim, deserialized.input_dim);
         assert_eq!(ff.hidden_dim, deserialized.hidden_dim);
     }
+
+    #[test]
+    fn test_single_precision() {
+        let ff: FeedForwardNetwork = FeedForwardNetwork::new(4, 8);
+        let (w1, b1, w2, b2) = ff.parameters();
+        let single = FeedForwardNetwork::from_parameters(w1.mapv(|v| v as f32), b1.mapv(|v| v as f32), w2.mapv(|v| v as f32), b2.mapv(|v| v as f32));
+
+        let x = array![[1.0, 2.0, 3.0, 4.0], [4.0, 3.0, 2.0, 1.0]];
+        let expected = ff.forward(&x);
+        let y = single.forward(&x.mapv(|v| v as f32));
+        assert!((y.mapv(f64::from) - expected).iter().all(|d| d.abs() < 1e-5));
+
+        let serialized = serde_json::to_string(&single).unwrap();
+        let deserialized: FeedForwardNetwork<f32> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.parameters().0, single.parameters().0);
+    }
 }
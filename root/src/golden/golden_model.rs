@@ -0,0 +1,142 @@
+use crate::transformer::Transformer;
+use crate::logging::logger::LogEvent;
+use ndarray::Array2;
+use serde::{Serialize, Deserialize};
+use std::error::Error;
+
+/// Default maximum absolute difference between a stage output and its reference.
+/// Both implementations compute in f64, so only summation order differs.
+pub const DEFAULT_GOLDEN_TOLERANCE: f64 = 1e-9;
+
+/// Reference fixture shipped with the repository.
+pub const DEFAULT_GOLDEN_FIXTURE: &str = "src/golden/fixtures/tiny_transformer.json";
+
+/// The expected output of one model stage.
+#[derive(Serialize, Deserialize)]
+pub struct NamedTensor {
+    pub name: String,
+    pub values: Array2<f64>,
+}
+
+/// Fixed weights, input sequences and the reference output of every stage for each sequence.
+#[derive(Serialize, Deserialize)]
+pub struct GoldenCase {
+    pub model: Transformer,
+    pub sequences: Vec<Vec<usize>>,
+    /// `references[i]` holds the stage outputs for `sequences[i]`, in `Transformer::layer_outputs` order.
+    pub references: Vec<Vec<NamedTensor>>,
+}
+
+/// Comparison of one stage of one sequence against its reference.
+pub struct StageComparison {
+    pub name: String,
+    pub max_abs_error: f64,
+    pub passed: bool,
+}
+
+pub struct GoldenReport {
+    pub comparisons: Vec<StageComparison>,
+}
+
+impl GoldenCase {
+    pub fn load(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        let data = std::fs::read_to_string(file_path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), std::io::Error> {
+        let serialized = serde_json::to_string(self).expect("Failed to serialize golden case");
+        std::fs::write(file_path, serialized)
+    }
+
+    /// Runs every sequence through the model and compares each stage with its reference.
+    ///
+    /// # Arguments
+    /// * `tolerance` - Largest absolute difference for a stage to pass.
+    ///
+    /// # Returns
+    /// * One comparison per sequence and reference stage, named `seq{i}/{stage}`.
+    ///   Stages the model no longer produces, or with a different shape, fail with an infinite error.
+    pub fn verify(&self, tolerance: f64) -> GoldenReport {
+        let mut comparisons = Vec::new();
+
+        for (i, (tokens, references)) in self.sequences.iter().zip(self.references.iter()).enumerate() {
+            let outputs = self.model.layer_outputs(tokens);
+
+            for reference in references {
+                let max_abs_error = match outputs.iter().find(|(name, _)| *name == reference.name) {
+                    Some((_, output)) if output.shape() == reference.values.shape() => output
+                        .iter()
+                        .zip(reference.values.iter())
+                        .map(|(a, b)| (a - b).abs())
+                        .fold(0.0, f64::max),
+                    _ => f64::INFINITY,
+                };
+
+                comparisons.push(StageComparison {
+                    name: format!("seq{}/{}", i, reference.name),
+                    max_abs_error,
+                    passed: max_abs_error <= tolerance,
+                });
+            }
+        }
+
+        GoldenReport { comparisons }
+    }
+}
+
+impl GoldenReport {
+    pub fn passed(&self) -> bool {
+        self.comparisons.iter().all(|comparison| comparison.passed)
+    }
+
+    /// The first failing stage; later stages usually fail only because their inputs differ.
+    pub fn first_failure(&self) -> Option<&StageComparison> {
+        self.comparisons.iter().find(|comparison| !comparison.passed)
+    }
+
+    pub fn print(&self) {
+        let mut table = format!("{:<32} {:>14} {:>8}", "Stage", "Max abs error", "Status");
+        for comparison in &self.comparisons {
+            table.push_str(&format!(
+                "\n{:<32} {:>14.3e} {:>8}",
+                comparison.name,
+                comparison.max_abs_error,
+                if comparison.passed { "OK" } else { "FAILED" }
+            ));
+        }
+        if let Some(failure) = self.first_failure() {
+            table.push_str(&format!("\nFirst divergence: {}", failure.name));
+        }
+        let errors: Vec<(&str, f64)> = self.comparisons.iter().map(|comparison| (comparison.name.as_str(), comparison.max_abs_error)).collect();
+        LogEvent::info("golden", table).metric("max_abs_errors", errors).metric("passed", self.passed()).emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::reference::generate_reference;
+
+    #[test]
+    fn test_shipped_fixture_matches_reference() {
+        let case = GoldenCase::load(DEFAULT_GOLDEN_FIXTURE).unwrap();
+        let report = case.verify(DEFAULT_GOLDEN_TOLERANCE);
+
+        assert!(!report.comparisons.is_empty());
+        assert!(report.passed(), "diverged at {}", report.first_failure().unwrap().name);
+    }
+
+    #[test]
+    fn test_verify_reports_first_divergent_stage() {
+        let mut case = generate_reference();
+        assert!(case.verify(DEFAULT_GOLDEN_TOLERANCE).passed());
+
+        case.references[0][2].values[(0, 0)] += 1e-3;
+        let report = case.verify(DEFAULT_GOLDEN_TOLERANCE);
+        assert_eq!(report.first_failure().unwrap().name, "seq0/encoder.0.norm1");
+
+        case.references.last_mut().unwrap().push(NamedTensor { name: "missing".to_string(), values: Array2::zeros((1, 1)) });
+        assert!(case.verify(DEFAULT_GOLDEN_TOLERANCE).comparisons.last().unwrap().max_abs_error.is_infinite());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod golden_model;
+pub mod reference;
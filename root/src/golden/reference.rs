@@ -0,0 +1,217 @@
+//! Independent reference implementation of the forward pass, used to generate the
+//! golden fixture.
+//!
+//! The forward pass below is written from the model definition (see the encoder,
+//! attention, layer_norm and embedding modules) with plain `Vec` arithmetic, and uses
+//! none of the crate's kernels. It follows PyTorch semantics: softmax over the last axis,
+//! layer norm with biased variance and no affine parameters, and `x @ W + b` linear layers.
+
+use super::golden_model::{GoldenCase, NamedTensor};
+use crate::classification::ClassificationHead;
+use crate::embedding::embeddings::Embeddings;
+use crate::encoder::EncoderLayer;
+use crate::feed_forward::FeedForwardNetwork;
+use crate::transformer::{Transformer, TransformerConfig};
+use ndarray::Array2;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+const SEED: u64 = 1250;
+const VOCAB: [&str; 8] = ["[PAD]", "[UNK]", "great", "movie", "boring", "plot", "loved", "it"];
+const NUM_LAYERS: usize = 2;
+const D_MODEL: usize = 8;
+const NUM_HEADS: usize = 2;
+const FF_DIM: usize = 16;
+const NUM_CLASSES: usize = 3;
+const EPSILON: f64 = 1e-6;
+
+type Matrix = Vec<Vec<f64>>;
+
+struct LayerWeights {
+    w1: Matrix,
+    b1: Matrix,
+    w2: Matrix,
+    b2: Matrix,
+}
+
+struct Weights {
+    embedding: Matrix,
+    layers: Vec<LayerWeights>,
+    head_w: Matrix,
+    head_b: Matrix,
+}
+
+fn matrix(rng: &mut StdRng, rows: usize, cols: usize, scale: f64) -> Matrix {
+    (0..rows).map(|_| (0..cols).map(|_| rng.gen_range(-scale..scale)).collect()).collect()
+}
+
+fn transpose(a: &Matrix) -> Matrix {
+    (0..a[0].len()).map(|j| a.iter().map(|row| row[j]).collect()).collect()
+}
+
+fn matmul(a: &Matrix, b: &Matrix) -> Matrix {
+    let columns = transpose(b);
+    a.iter()
+        .map(|row| columns.iter().map(|col| row.iter().zip(col).map(|(x, y)| x * y).sum()).collect())
+        .collect()
+}
+
+/// Broadcasts a single-row `b` over the rows of `a`, like `x + bias`.
+fn add(a: &Matrix, b: &Matrix) -> Matrix {
+    a.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let other = if b.len() == 1 { &b[0] } else { &b[i] };
+            row.iter().zip(other).map(|(x, y)| x + y).collect()
+        })
+        .collect()
+}
+
+fn softmax_rows(a: &Matrix) -> Matrix {
+    a.iter()
+        .map(|row| {
+            let max = row.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let exps: Vec<f64> = row.iter().map(|x| (x - max).exp()).collect();
+            let total: f64 = exps.iter().sum();
+            exps.iter().map(|e| e / total).collect()
+        })
+        .collect()
+}
+
+fn layer_norm(a: &Matrix, epsilon: f64) -> Matrix {
+    a.iter()
+        .map(|row| {
+            let mean = row.iter().sum::<f64>() / row.len() as f64;
+            let variance = row.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / row.len() as f64;
+            let std = (variance + epsilon).sqrt();
+            row.iter().map(|x| (x - mean) / std).collect()
+        })
+        .collect()
+}
+
+/// Sinusoidal encoding from "Attention Is All You Need": dims 2i and 2i+1 share a frequency.
+fn positional_encoding(seq_len: usize, d_model: usize) -> Matrix {
+    (0..seq_len)
+        .map(|pos| {
+            (0..d_model)
+                .map(|dim| {
+                    let angle = pos as f64 / 10000f64.powf((2 * (dim / 2)) as f64 / d_model as f64);
+                    if dim % 2 == 0 { angle.sin() } else { angle.cos() }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn self_attention(x: &Matrix) -> Matrix {
+    let scale = (x[0].len() as f64).sqrt();
+    let scores: Matrix = matmul(x, &transpose(x)).iter().map(|row| row.iter().map(|s| s / scale).collect()).collect();
+    matmul(&softmax_rows(&scores), x)
+}
+
+fn forward(weights: &Weights, tokens: &[usize]) -> Vec<(String, Matrix)> {
+    let mut stages = Vec::new();
+    let embedded: Matrix = tokens.iter().map(|&t| weights.embedding[t].clone()).collect();
+    let mut hidden = add(&embedded, &positional_encoding(tokens.len(), D_MODEL));
+    stages.push(("embeddings".to_string(), hidden.clone()));
+
+    for (i, layer) in weights.layers.iter().enumerate() {
+        let attention = self_attention(&hidden);
+        let norm1 = layer_norm(&add(&hidden, &attention), EPSILON);
+        let relu: Matrix =
+            add(&matmul(&norm1, &layer.w1), &layer.b1).iter().map(|row| row.iter().map(|v| v.max(0.0)).collect()).collect();
+        let ffn = add(&matmul(&relu, &layer.w2), &layer.b2);
+        hidden = layer_norm(&add(&norm1, &ffn), EPSILON);
+        stages.push((format!("encoder.{}.attention", i), attention));
+        stages.push((format!("encoder.{}.norm1", i), norm1));
+        stages.push((format!("encoder.{}.feed_forward", i), ffn));
+        stages.push((format!("encoder.{}.output", i), hidden.clone()));
+    }
+
+    let pooled = vec![transpose(&hidden).iter().map(|col| col.iter().sum::<f64>() / hidden.len() as f64).collect()];
+    let logits = add(&matmul(&pooled, &weights.head_w), &weights.head_b);
+    stages.push(("pooled".to_string(), pooled));
+    stages.push(("logits".to_string(), logits));
+    stages
+}
+
+fn to_array(a: &Matrix) -> Array2<f64> {
+    Array2::from_shape_fn((a.len(), a[0].len()), |(i, j)| a[i][j])
+}
+
+/// The model with the reference weights: no embedding scaling, standard sinusoidal
+/// encodings, single-head attention over the raw layer input.
+fn model(weights: &Weights) -> Transformer {
+    let vocab: HashMap<String, usize> = VOCAB.iter().enumerate().map(|(i, token)| (token.to_string(), i)).collect();
+    let config = TransformerConfig {
+        num_layers: NUM_LAYERS,
+        d_model: D_MODEL,
+        num_heads: NUM_HEADS,
+        ff_dim: FF_DIM,
+        num_classes: NUM_CLASSES,
+        epsilon: EPSILON,
+        bert_embeddings: false,
+        relative_positions: None,
+        attention_projections: false,
+    };
+    let mut model = Transformer::new(config, vocab.clone());
+    model.embeddings = Embeddings::from_matrix(to_array(&weights.embedding), vocab);
+    model.encoder_layers = weights
+        .layers
+        .iter()
+        .map(|layer| {
+            let mut encoder_layer = EncoderLayer::new(D_MODEL, FF_DIM, EPSILON);
+            encoder_layer.feed_forward =
+                FeedForwardNetwork::from_parameters(to_array(&layer.w1), to_array(&layer.b1), to_array(&layer.w2), to_array(&layer.b2));
+            encoder_layer
+        })
+        .collect();
+    model.classification_head = ClassificationHead::from_parameters(to_array(&weights.head_w), to_array(&weights.head_b));
+    model
+}
+
+/// Builds the golden case: seeded weights, a few sequences, and the reference output of
+/// every stage computed by this module's forward pass.
+pub fn generate_reference() -> GoldenCase {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let embedding = matrix(&mut rng, VOCAB.len(), D_MODEL, 0.5);
+    let layers = (0..NUM_LAYERS)
+        .map(|_| LayerWeights {
+            w1: matrix(&mut rng, D_MODEL, FF_DIM, 0.5),
+            b1: matrix(&mut rng, 1, FF_DIM, 0.1),
+            w2: matrix(&mut rng, FF_DIM, D_MODEL, 0.5),
+            b2: matrix(&mut rng, 1, D_MODEL, 0.1),
+        })
+        .collect();
+    let head_w = matrix(&mut rng, D_MODEL, NUM_CLASSES, 0.5);
+    let head_b = matrix(&mut rng, 1, NUM_CLASSES, 0.1);
+    let weights = Weights { embedding, layers, head_w, head_b };
+
+    let sequences = vec![vec![2, 3], vec![6, 7, 4, 5, 1], vec![4, 4, 5]];
+    let references = sequences
+        .iter()
+        .map(|tokens| {
+            forward(&weights, tokens)
+                .into_iter()
+                .map(|(name, values)| NamedTensor { name, values: to_array(&values) })
+                .collect()
+        })
+        .collect();
+
+    GoldenCase { model: model(&weights), sequences, references }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::golden_model::DEFAULT_GOLDEN_TOLERANCE;
+
+    #[test]
+    fn test_model_matches_reference_forward_pass() {
+        let case = generate_reference();
+        let report = case.verify(DEFAULT_GOLDEN_TOLERANCE);
+        assert_eq!(report.comparisons.len(), 3 * (4 * NUM_LAYERS + 3));
+        assert!(report.passed(), "diverged at {}", report.first_failure().unwrap().name);
+    }
+}
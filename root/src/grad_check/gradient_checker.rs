@@ -0,0 +1,328 @@
+//! Module for verifying analytic gradients against finite-difference estimates.
+//!
+//! Purpose:
+//! - Checks every backward pass (attention, feed-forward, layer norm, classification head).
+//! - Provides reusable helpers for debugging the gradients of custom layers.
+//!
+//! Each check uses the scalar objective `L = sum(output ⊙ R)` with a fixed random
+//! upstream gradient `R`, so the analytic gradient is the module's backward pass
+//! applied to `R`.
+
+use crate::attention::{scaled_dot_product_attention, scaled_dot_product_attention_backward};
+use crate::classification::ClassificationHead;
+use crate::feed_forward::FeedForwardNetwork;
+use crate::layer_norm::{apply_layer_norm, layer_norm_backward};
+use crate::transformer::{Transformer, TransformerConfig};
+use crate::logging::logger::LogEvent;
+use ndarray::{array, Array2};
+use std::collections::HashMap;
+use ndarray_rand::RandomExt;
+use ndarray_rand::rand_distr::Uniform;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Step size used for central differences.
+pub const FINITE_DIFFERENCE_STEP: f64 = 1e-5;
+/// Maximum relative error accepted for a gradient to pass.
+pub const GRAD_CHECK_TOLERANCE: f64 = 1e-5;
+/// Seed of the inputs, upstream gradients and parameters of every check, so a failing
+/// check fails the same way on the next run.
+pub const GRAD_CHECK_SEED: u64 = 42;
+
+/// Outcome of comparing one analytic gradient with its numerical estimate.
+pub struct GradCheckResult {
+    pub name: String,
+    pub max_relative_error: f64,
+    pub passed: bool,
+}
+
+/// Estimates the gradient of `loss` with respect to every element of `x` by central differences.
+pub fn numerical_input_gradient<F>(x: &Array2<f64>, loss: F) -> Array2<f64>
+where
+    F: Fn(&Array2<f64>) -> f64,
+{
+    let mut perturbed = x.clone();
+    let mut gradient = Array2::zeros(x.raw_dim());
+
+    for (index, grad) in gradient.indexed_iter_mut() {
+        let original = perturbed[index];
+
+        perturbed[index] = original + FINITE_DIFFERENCE_STEP;
+        let loss_plus = loss(&perturbed);
+        perturbed[index] = original - FINITE_DIFFERENCE_STEP;
+        let loss_minus = loss(&perturbed);
+        perturbed[index] = original;
+
+        *grad = (loss_plus - loss_minus) / (2.0 * FINITE_DIFFERENCE_STEP);
+    }
+
+    gradient
+}
+
+/// Estimates the gradient of `loss` with respect to every parameter exposed by `parameters`,
+/// in the same order as the parameters are returned.
+pub fn numerical_parameter_gradient<M, P, F>(module: &mut M, parameters: P, loss: F) -> Vec<f64>
+where
+    P: Fn(&mut M) -> Vec<&mut f64>,
+    F: Fn(&M) -> f64,
+{
+    let num_params = parameters(module).len();
+    let mut gradient = Vec::with_capacity(num_params);
+
+    for i in 0..num_params {
+        let original = *parameters(module)[i];
+
+        *parameters(module)[i] = original + FINITE_DIFFERENCE_STEP;
+        let loss_plus = loss(module);
+        *parameters(module)[i] = original - FINITE_DIFFERENCE_STEP;
+        let loss_minus = loss(module);
+        *parameters(module)[i] = original;
+
+        gradient.push((loss_plus - loss_minus) / (2.0 * FINITE_DIFFERENCE_STEP));
+    }
+
+    gradient
+}
+
+/// Compares an analytic gradient with a numerical estimate.
+/// The relative error of each element is `|a - n| / max(|a| + |n|, 1e-4)`.
+pub fn compare_gradients(name: &str, analytic: &[f64], numerical: &[f64]) -> GradCheckResult {
+    assert_eq!(analytic.len(), numerical.len(), "Gradient lengths must match for {}.", name);
+
+    let max_relative_error = analytic
+        .iter()
+        .zip(numerical.iter())
+        .map(|(a, n)| (a - n).abs() / (a.abs() + n.abs()).max(1e-4))
+        .fold(0.0, f64::max);
+
+    GradCheckResult {
+        name: name.to_string(),
+        max_relative_error,
+        passed: max_relative_error < GRAD_CHECK_TOLERANCE,
+    }
+}
+
+fn weighted_sum(output: &Array2<f64>, upstream: &Array2<f64>) -> f64 {
+    (output * upstream).sum()
+}
+
+fn random_matrix(rng: &mut StdRng, rows: usize, cols: usize) -> Array2<f64> {
+    Array2::random_using((rows, cols), Uniform::new(-1.0, 1.0), rng)
+}
+
+/// Overwrites randomly initialized parameters with values drawn from `rng`.
+fn reseed_parameters(parameters: Vec<&mut f64>, rng: &mut StdRng) {
+    for parameter in parameters {
+        *parameter = rng.gen_range(-0.1..0.1);
+    }
+}
+
+/// Checks the gradients of scaled dot-product attention with respect to Q, K and V.
+pub fn check_attention() -> Vec<GradCheckResult> {
+    let mut rng = StdRng::seed_from_u64(GRAD_CHECK_SEED);
+    let (query, key, value) = (random_matrix(&mut rng, 3, 4), random_matrix(&mut rng, 5, 4), random_matrix(&mut rng, 5, 2));
+    let upstream = random_matrix(&mut rng, 3, 2);
+
+    let (grad_query, grad_key, grad_value) =
+        scaled_dot_product_attention_backward(&query, &key, &value, &upstream);
+
+    let numerical_query = numerical_input_gradient(&query, |q| {
+        weighted_sum(&scaled_dot_product_attention(q, &key, &value), &upstream)
+    });
+    let numerical_key = numerical_input_gradient(&key, |k| {
+        weighted_sum(&scaled_dot_product_attention(&query, k, &value), &upstream)
+    });
+    let numerical_value = numerical_input_gradient(&value, |v| {
+        weighted_sum(&scaled_dot_product_attention(&query, &key, v), &upstream)
+    });
+
+    vec![
+        compare_gradients("attention/query", grad_query.as_slice().unwrap(), numerical_query.as_slice().unwrap()),
+        compare_gradients("attention/key", grad_key.as_slice().unwrap(), numerical_key.as_slice().unwrap()),
+        compare_gradients("attention/value", grad_value.as_slice().unwrap(), numerical_value.as_slice().unwrap()),
+    ]
+}
+
+/// Checks the gradients of the feed-forward network with respect to its input and parameters.
+pub fn check_feed_forward() -> Vec<GradCheckResult> {
+    let mut rng = StdRng::seed_from_u64(GRAD_CHECK_SEED);
+    let mut ffn = FeedForwardNetwork::new(4, 6);
+    reseed_parameters(ffn.parameters_mut(), &mut rng);
+    let x = random_matrix(&mut rng, 3, 4);
+    let upstream = random_matrix(&mut rng, 3, 4);
+
+    let (grad_input, grad_params) = ffn.backward(&x, &upstream);
+
+    let numerical_input = numerical_input_gradient(&x, |x| weighted_sum(&ffn.forward(x), &upstream));
+    let numerical_params = numerical_parameter_gradient(
+        &mut ffn,
+        |m| m.parameters_mut(),
+        |m| weighted_sum(&m.forward(&x), &upstream),
+    );
+
+    vec![
+        compare_gradients("feed_forward/input", grad_input.as_slice().unwrap(), numerical_input.as_slice().unwrap()),
+        compare_gradients("feed_forward/parameters", &grad_params, &numerical_params),
+    ]
+}
+
+/// Checks the gradient of layer normalization with respect to its input.
+pub fn check_layer_norm() -> Vec<GradCheckResult> {
+    let epsilon = 1e-5;
+    let mut rng = StdRng::seed_from_u64(GRAD_CHECK_SEED);
+    let x = random_matrix(&mut rng, 3, 5);
+    let upstream = random_matrix(&mut rng, 3, 5);
+
+    let grad_input = layer_norm_backward(&x, epsilon, &upstream);
+    let numerical_input = numerical_input_gradient(&x, |x| weighted_sum(&apply_layer_norm(x, epsilon), &upstream));
+
+    vec![compare_gradients("layer_norm/input", grad_input.as_slice().unwrap(), numerical_input.as_slice().unwrap())]
+}
+
+/// Checks the gradients of the classification head with respect to its input and parameters.
+pub fn check_classification_head() -> Vec<GradCheckResult> {
+    let mut rng = StdRng::seed_from_u64(GRAD_CHECK_SEED);
+    let mut head = ClassificationHead::new(4, 3);
+    reseed_parameters(head.parameters_mut(), &mut rng);
+    let pooled = random_matrix(&mut rng, 2, 4);
+    let upstream = random_matrix(&mut rng, 2, 3);
+
+    let (grad_input, grad_params) = head.backward(&pooled, &upstream);
+
+    let numerical_input = numerical_input_gradient(&pooled, |p| weighted_sum(&head.forward(p), &upstream));
+    let numerical_params = numerical_parameter_gradient(
+        &mut head,
+        |m| m.parameters_mut(),
+        |m| weighted_sum(&m.forward(&pooled), &upstream),
+    );
+
+    vec![
+        compare_gradients("classification_head/input", grad_input.as_slice().unwrap(), numerical_input.as_slice().unwrap()),
+        compare_gradients("classification_head/parameters", &grad_params, &numerical_params),
+    ]
+}
+
+/// Checks the gradients of the full Transformer (encoder layers and classification head)
+/// with respect to its parameters, using masked mean pooling, with and without relative
+/// positions and attention projections in the attention.
+pub fn check_transformer() -> Vec<GradCheckResult> {
+    let mut results = check_transformer_with("transformer", None, false);
+    results.extend(check_transformer_with("transformer_relative", Some(1), false));
+    results.extend(check_transformer_with("transformer_projected", None, true));
+    results.extend(check_transformer_with("transformer_projected_relative", Some(1), true));
+    results
+}
+
+fn check_transformer_with(name: &str, relative_positions: Option<usize>, attention_projections: bool) -> Vec<GradCheckResult> {
+    let vocab = HashMap::from([
+        ("[PAD]".to_string(), 0),
+        ("[UNK]".to_string(), 1),
+        ("a".to_string(), 2),
+        ("b".to_string(), 3),
+    ]);
+    let config = TransformerConfig {
+        num_layers: 2,
+        d_model: 4,
+        num_heads: 2,
+        ff_dim: 6,
+        num_classes: 3,
+        epsilon: 1e-5,
+        bert_embeddings: false,
+        relative_positions,
+        attention_projections,
+    };
+    let mut rng = StdRng::seed_from_u64(GRAD_CHECK_SEED);
+    let mut transformer = Transformer::new(config, vocab);
+    reseed_parameters(transformer.parameters_mut(), &mut rng);
+    let tokens = array![[2.0, 3.0, 1.0, 0.0], [3.0, 3.0, 0.0, 0.0]];
+    let mask = array![[1.0, 1.0, 1.0, 0.0], [1.0, 1.0, 0.0, 0.0]];
+    let upstream = random_matrix(&mut rng, 2, 3);
+
+    let logits = |model: &Transformer| {
+        model.classification_head.forward(&model.pooled_output(&tokens, Some(&mask)))
+    };
+
+    let analytic = transformer.backward(&tokens, Some(&mask), &upstream);
+    let numerical = numerical_parameter_gradient(
+        &mut transformer,
+        |m| m.parameters_mut(),
+        |m| weighted_sum(&logits(m), &upstream),
+    );
+
+    let embeddings = transformer.num_parameters() - transformer.embeddings.num_parameters();
+    vec![
+        compare_gradients(&format!("{}/parameters", name), &analytic[..embeddings], &numerical[..embeddings]),
+        compare_gradients(&format!("{}/embeddings", name), &analytic[embeddings..], &numerical[embeddings..]),
+    ]
+}
+
+/// Runs every gradient check and prints a report.
+///
+/// # Returns
+/// * `true` if all checks passed.
+pub fn run_grad_check() -> bool {
+    let results: Vec<GradCheckResult> = check_attention()
+        .into_iter()
+        .chain(check_feed_forward())
+        .chain(check_layer_norm())
+        .chain(check_classification_head())
+        .chain(check_transformer())
+        .collect();
+
+    let passed = results.iter().all(|result| result.passed);
+    let mut table = format!("{:<32} {:>18} {:>8}", "Gradient", "Max relative error", "Status");
+    for result in &results {
+        table.push_str(&format!(
+            "\n{:<32} {:>18.3e} {:>8}",
+            result.name,
+            result.max_relative_error,
+            if result.passed { "OK" } else { "FAILED" }
+        ));
+    }
+    let errors: Vec<(&str, f64)> = results.iter().map(|result| (result.name.as_str(), result.max_relative_error)).collect();
+    LogEvent::info("grad_check", table).metric("max_relative_errors", errors).metric("passed", passed).emit();
+
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_all_passed(results: Vec<GradCheckResult>) {
+        for result in results {
+            assert!(result.passed, "{} failed with relative error {}", result.name, result.max_relative_error);
+        }
+    }
+
+    #[test]
+    fn test_attention_gradients() {
+        assert_all_passed(check_attention());
+    }
+
+    #[test]
+    fn test_feed_forward_gradients() {
+        assert_all_passed(check_feed_forward());
+    }
+
+    #[test]
+    fn test_layer_norm_gradients() {
+        assert_all_passed(check_layer_norm());
+    }
+
+    #[test]
+    fn test_classification_head_gradients() {
+        assert_all_passed(check_classification_head());
+    }
+
+    #[test]
+    fn test_transformer_gradients() {
+        assert_all_passed(check_transformer());
+    }
+
+    #[test]
+    fn test_compare_gradients_detects_mismatch() {
+        let result = compare_gradients("mismatch", &[1.0, 2.0], &[1.0, 2.5]);
+        assert!(!result.passed);
+    }
+}
@@ -0,0 +1 @@
+pub mod gradient_checker;
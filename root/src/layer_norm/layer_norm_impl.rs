@@ -1,4 +1,6 @@
-use ndarray::{Array2, ArrayView2, Axis};
+use ndarray::{Array1, Array2, Axis};
+use crate::numerics::Float;
+use crate::summation::Summation;
 
 /// Applies layer normalization to stabilize training.
 ///
@@ -8,9 +10,13 @@ use ndarray::{Array2, ArrayView2, Axis};
 /// 
 /// # Returns
 /// - A 2D array of normalized outputs. Shape: [batch_size, feature_dim].
-pub fn apply_layer_norm(inputs: &Array2<f64>, epsilon: f64) -> Array2<f64> {
-	let mean = inputs.mean_axis(Axis(1)).unwrap();
-	let variance = inputs.var_axis(Axis(1), 0.0);
+pub fn apply_layer_norm<A: Float>(inputs: &Array2<A>, epsilon: A) -> Array2<A> {
+	apply_layer_norm_with(inputs, epsilon, Summation::Naive)
+}
+
+/// Same as `apply_layer_norm`, computing the row mean and variance with `summation`.
+pub fn apply_layer_norm_with<A: Float>(inputs: &Array2<A>, epsilon: A, summation: Summation) -> Array2<A> {
+	let (mean, variance) = row_statistics(inputs, summation);
 
 	let mut normed = inputs.clone();
 	for ((mut row, &m), &v) in normed.outer_iter_mut().zip(mean.iter()).zip(variance.iter()) {
@@ -20,27 +26,61 @@ pub fn apply_layer_norm(inputs: &Array2<f64>, epsilon: f64) -> Array2<f64> {
 	normed
 }
 
+/// Computes the gradient of layer normalization with respect to its inputs.
+///
+/// # Arguments
+/// - `inputs`: The inputs used in the forward pass. Shape: [batch_size, feature_dim].
+/// - `epsilon`: The epsilon used in the forward pass.
+/// - `grad_output`: Gradient of the loss with respect to the normalized outputs.
+///
+/// # Returns
+/// - Gradient of the loss with respect to `inputs`. Shape: [batch_size, feature_dim].
+pub fn layer_norm_backward<A: Float>(inputs: &Array2<A>, epsilon: A, grad_output: &Array2<A>) -> Array2<A> {
+	layer_norm_backward_with(inputs, epsilon, grad_output, Summation::Naive)
+}
 
+/// Same as `layer_norm_backward`, computing every row mean with `summation`.
+pub fn layer_norm_backward_with<A: Float>(inputs: &Array2<A>, epsilon: A, grad_output: &Array2<A>, summation: Summation) -> Array2<A> {
+	let normed = apply_layer_norm_with(inputs, epsilon, summation);
+	let (_, variance) = row_statistics(inputs, summation);
 
-pub fn test_apply_layer_norm() {
-    let inputs = Array2::from_shape_vec(
-        (2, 4),
-        vec![1.0, 2.0, 3.0, 4.0, 4.0, 3.0, 2.0, 1.0],
-    )
-    .unwrap();
-    let epsilon = 1e-5;
-
-    let normalized = apply_layer_norm(&inputs, epsilon);
-
-   
-    for row in normalized.outer_iter() {
-        let mean: f64 = row.mean().unwrap();
-        let variance: f64 = row.var(1e-5);
-        assert!((mean - 0.0).abs() < 1e-6, "Mean is not zero!");
-        assert!((variance - 1.0).abs() < 1e-5, "Variance is not one!");
-    }
+	let mut grad_inputs = Array2::zeros(inputs.raw_dim());
+	for (((mut grad_row, y_row), dy_row), &v) in grad_inputs
+			.outer_iter_mut()
+			.zip(normed.outer_iter())
+			.zip(grad_output.outer_iter())
+			.zip(variance.iter())
+	{
+			let std = (v + epsilon).sqrt();
+			let (mean_dy, mean_dy_y) = match summation {
+					Summation::Naive => (dy_row.mean().unwrap(), (&dy_row * &y_row).mean().unwrap()),
+					Summation::Compensated => (
+							A::cast(summation.mean(dy_row.iter().map(|dy| dy.as_f64()))),
+							A::cast(summation.mean(dy_row.iter().zip(y_row.iter()).map(|(&dy, &y)| (dy * y).as_f64()))),
+					),
+			};
+			for ((g, &y), &dy) in grad_row.iter_mut().zip(y_row.iter()).zip(dy_row.iter()) {
+					*g = (dy - mean_dy - y * mean_dy_y) / std;
+			}
+	}
+	grad_inputs
+}
 
-    println!("LayerNorm test passed!");
+/// Mean and (biased) variance of every row.
+fn row_statistics<A: Float>(inputs: &Array2<A>, summation: Summation) -> (Array1<A>, Array1<A>) {
+	match summation {
+		Summation::Naive => (inputs.mean_axis(Axis(1)).unwrap(), inputs.var_axis(Axis(1), A::zero())),
+		Summation::Compensated => {
+			let (mean, variance): (Vec<A>, Vec<A>) = inputs
+				.outer_iter()
+				.map(|row| {
+					let mean = summation.mean(row.iter().map(|x| x.as_f64()));
+					(A::cast(mean), A::cast(summation.mean(row.iter().map(|x| (x.as_f64() - mean) * (x.as_f64() - mean)))))
+				})
+				.unzip();
+			(Array1::from(mean), Array1::from(variance))
+		}
+	}
 }
 
 #[cfg(test)]
@@ -49,6 +89,34 @@ mod tests {
 
     #[test]
     fn test_layer_norm_basic() {
-        test_apply_layer_norm();
+        let inputs = Array2::from_shape_vec(
+            (2, 4),
+            vec![1.0, 2.0, 3.0, 4.0, 4.0, 3.0, 2.0, 1.0],
+        )
+        .unwrap();
+        let epsilon = 1e-5;
+
+        let normalized = apply_layer_norm(&inputs, epsilon);
+
+        for row in normalized.outer_iter() {
+            let mean: f64 = row.mean().unwrap();
+            let variance: f64 = row.var(1e-5);
+            assert!((mean - 0.0).abs() < 1e-6, "Mean is not zero!");
+            assert!((variance - 1.0).abs() < 1e-5, "Variance is not one!");
+        }
+    }
+
+    #[test]
+    fn test_compensated_statistics_match_naive() {
+        let inputs = Array2::from_shape_fn((3, 64), |(i, j)| 1e8 + (i * 64 + j) as f64 * 0.1);
+        let grad_output = Array2::from_shape_fn((3, 64), |(i, j)| ((i + j) as f64).sin());
+
+        let naive = apply_layer_norm(&inputs, 1e-6);
+        let compensated = apply_layer_norm_with(&inputs, 1e-6, Summation::Compensated);
+        assert!(naive.iter().zip(compensated.iter()).all(|(a, b)| (a - b).abs() < 1e-6));
+
+        let naive_grad = layer_norm_backward(&inputs, 1e-6, &grad_output);
+        let compensated_grad = layer_norm_backward_with(&inputs, 1e-6, &grad_output, Summation::Compensated);
+        assert!(naive_grad.iter().zip(compensated_grad.iter()).all(|(a, b)| (a - b).abs() < 1e-6));
     }
 }
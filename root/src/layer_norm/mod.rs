@@ -1,2 +1,2 @@
 pub mod layer_norm_impl;
-pub use layer_norm_impl::{apply_layer_norm, test_apply_layer_norm};
+pub use layer_norm_impl::{apply_layer_norm, apply_layer_norm_with, layer_norm_backward, layer_norm_backward_with};
@@ -0,0 +1,161 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Severity of a `LogEvent`. `Warn` and `Error` go to stderr, the others to stdout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// How `LogEvent::emit` writes events.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// The human-readable message only, as the pipeline has always printed it.
+    #[default]
+    Text,
+    /// One JSON object per line with the timestamp, level, module, message, step and
+    /// metrics, for log shippers such as Filebeat or the Datadog agent.
+    Json,
+}
+
+impl LogFormat {
+    /// Parses `text` or `json`, e.g. from `--log-format`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Sets the format of every event emitted afterwards, process-wide.
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+pub fn format() -> LogFormat {
+    if JSON.load(Ordering::Relaxed) { LogFormat::Json } else { LogFormat::Text }
+}
+
+/// A log line with structured context:
+///
+/// ```ignore
+/// LogEvent::info("trainer", format!("Epoch {}: Loss: {:.4}", epoch, loss))
+///     .step(epoch)
+///     .metric("loss", loss)
+///     .emit();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEvent {
+    pub level: LogLevel,
+    /// Part of the pipeline the event comes from, e.g. `trainer` or `inference`.
+    pub module: &'static str,
+    pub message: String,
+    /// Epoch or step the event belongs to.
+    pub step: Option<usize>,
+    pub metrics: Map<String, Value>,
+}
+
+impl LogEvent {
+    pub fn new(level: LogLevel, module: &'static str, message: impl Into<String>) -> Self {
+        LogEvent { level, module, message: message.into(), step: None, metrics: Map::new() }
+    }
+
+    pub fn info(module: &'static str, message: impl Into<String>) -> Self {
+        Self::new(LogLevel::Info, module, message)
+    }
+
+    pub fn warn(module: &'static str, message: impl Into<String>) -> Self {
+        Self::new(LogLevel::Warn, module, message)
+    }
+
+    pub fn error(module: &'static str, message: impl Into<String>) -> Self {
+        Self::new(LogLevel::Error, module, message)
+    }
+
+    pub fn step(mut self, step: usize) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    /// Attaches a named value; anything that serializes to JSON, including vectors and maps.
+    pub fn metric(mut self, name: &str, value: impl Serialize) -> Self {
+        self.metrics.insert(name.to_string(), serde_json::to_value(value).unwrap_or(Value::Null));
+        self
+    }
+
+    /// The event as one line of JSON. `timestamp` is in milliseconds since the Unix epoch;
+    /// `step` and `metrics` are left out when not set, and the message is trimmed.
+    pub fn to_json(&self) -> String {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let mut record = Map::new();
+        record.insert("timestamp".to_string(), Value::from(timestamp));
+        record.insert("level".to_string(), serde_json::to_value(self.level).unwrap());
+        record.insert("module".to_string(), Value::from(self.module));
+        record.insert("message".to_string(), Value::from(self.message.trim()));
+        if let Some(step) = self.step {
+            record.insert("step".to_string(), Value::from(step));
+        }
+        if !self.metrics.is_empty() {
+            record.insert("metrics".to_string(), Value::Object(self.metrics.clone()));
+        }
+        Value::Object(record).to_string()
+    }
+
+    pub fn render(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Text => self.message.clone(),
+            LogFormat::Json => self.to_json(),
+        }
+    }
+
+    /// Writes the event in the process-wide format (see `set_format`).
+    pub fn emit(self) {
+        let line = self.render(format());
+        if self.level >= LogLevel::Warn {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_and_json_rendering() {
+        let event = LogEvent::info("trainer", "\nEpoch 2: Loss: 0.5000")
+            .step(2)
+            .metric("loss", 0.5)
+            .metric("class_counts", vec![3, 1]);
+        assert_eq!(event.render(LogFormat::Text), "\nEpoch 2: Loss: 0.5000");
+
+        let line = event.render(LogFormat::Json);
+        assert!(!line.contains('\n'));
+        let record: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(record["level"], "info");
+        assert_eq!(record["module"], "trainer");
+        assert_eq!(record["message"], "Epoch 2: Loss: 0.5000");
+        assert_eq!(record["step"], 2);
+        assert_eq!(record["metrics"]["loss"], 0.5);
+        assert_eq!(record["metrics"]["class_counts"], serde_json::json!([3, 1]));
+        assert!(record["timestamp"].as_u64().unwrap() > 0);
+
+        let bare: Value = serde_json::from_str(&LogEvent::warn("inference", "slow").to_json()).unwrap();
+        assert_eq!(bare["level"], "warn");
+        assert!(bare.get("step").is_none() && bare.get("metrics").is_none());
+        assert_eq!(LogFormat::parse("json"), Some(LogFormat::Json));
+        assert_eq!(LogFormat::parse("xml"), None);
+    }
+}
@@ -14,126 +14,1986 @@ mod model_optimizer;
 mod training;
 mod model_evaluator;
 mod model_inference;
+mod grad_check;
+mod experiment;
+mod golden;
+mod quantization;
+mod export;
+mod onnx;
+mod summation;
+mod profiling;
+mod logging;
+mod numerics;
+mod backend;
+mod augmentation;
+mod exploration;
+#[cfg(test)]
+mod test_utils;
+
+// `cargo build --release --features accelerate` links Apple's Accelerate framework as the
+// BLAS backend of ndarray, so every `dot` product in the model runs on it.
+#[cfg(feature = "accelerate")]
+extern crate blas_src;
 
 use std::collections::HashMap;
 use std::fs;
-use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
 use transformer::{Transformer, TransformerConfig};
-use tokenization::tokenizer::Tokenizer;
+use transformer::parallelism::{Parallelism, Reduction};
+use summation::Summation;
+use profiling::profiler;
+use logging::logger::{self as log, LogEvent, LogFormat};
+use profiling::chrome_trace::DEFAULT_TRACE_DEPTH;
+use tokenization::tokenizer::{Tokenizer, Truncation, VocabOptions};
+use tokenization::fuzz::{fuzz_tokenizer, FUZZ_ITERATIONS, FUZZ_MAX_CHARS};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use data_handler::data_loader::DataLoader;
+use data_handler::label_map::LabelMap;
+use data_handler::synthetic::{SyntheticConfig, SyntheticDataset};
+use data_handler::input_template::InputTemplate;
+use configurration::data_schema::DataSchema;
+use data_handler::cpu_affinity::{check_cores, resolve_thread_count};
 use model_optimizer::optimizer::{Optimizer, OptimizerType};
-use training::trainer::Trainer;
+use training::trainer::{
+    Trainer, interrupted_checkpoint_path, training_state_path, OVERFIT_BATCH_SIZE, OVERFIT_LEARNING_RATE,
+    OVERFIT_STEPS, OVERFIT_TARGET_LOSS,
+};
+use training::shutdown::ShutdownSignal;
+use training::dry_run::{dry_run, DryRunSetup, DRY_RUN_SAMPLE_SIZE};
+use training::batch_size_tuner::{BatchSizeTuner, BatchSizeTuning};
+use training::probe_set::ProbeSet;
 use model_evaluator::evaluator::Evaluator;
-use model_inference::inference::Inference;
-use crate::configurration::config::{PAD_TOKEN, UNK_TOKEN, MAX_SEQ_LENGTH};
+use model_evaluator::reject_option::DEFAULT_ABSTAIN_THRESHOLDS;
+use model_evaluator::promotion::{install, PromotionRecord, PROMOTION_RECORD_FILE, SERVING_MODEL_FILE, SERVING_TOKENIZER_FILE};
+use model_evaluator::score_calibration::{expected_calibration_error, ScoreCalibrator, CALIBRATION_BINS};
+use model_inference::inference::{ExamplePrediction, Inference, OverflowPolicy};
+use model_inference::auth::ApiKeyAuth;
+use model_inference::cost_matrix::CostMatrix;
+use model_inference::request_limits::RateLimiter;
+use model_inference::server::Server;
+use training::domain_adversarial::DomainAdversary;
+use model_inference::ensemble::{Ensemble, EnsembleCombiner, StackingHead, STACKING_FOLDS};
+use crate::configurration::config::{TRAIN_DATASET_PATH, TEST_DATASET_PATH, TRAINED_MODEL_PATH, PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, MASK_TOKEN, MAX_SEQ_LENGTH, MAX_VOCAB_SIZE, MIN_TOKEN_FREQUENCY, VOCAB_BUILDER_MAX_WORDS, TOKENIZER_PATH, BYTE_FALLBACK, TEXT_NORMALIZER, NGRAMS, TOKEN_RULES, PHRASES, TASK_PREFIXES, ACTIVE_TASK, INFERENCE_OVERFLOW_POLICY, INFERENCE_WARMUP_PASSES, INFERENCE_QUANTIZATION, PROTOTYPE_INFERENCE, COST_MATRIX_PATH, QUANTIZATION_CALIBRATION_DATASET, QUANTIZATION_CALIBRATION_SAMPLES, PREDICTION_TOP_K, WORD_POOLING, INPUT_TEMPLATE, DATA_SCHEMA_PATH, BATCH_SIZE, EVALUATION_SLICE_FIELDS, FAIRNESS_GROUP, EVALUATION_NOISE_PROBABILITY, PROMOTION_GATE, PROMOTION_GATE_DATASET, SCORE_CALIBRATION, SCORE_CALIBRATION_DATASET, SERVING_DIR, SERVER_ADDRESS, SERVER_REQUEST_LIMITS, SERVER_RATE_LIMIT, SERVER_API_KEYS_PATH, SERVER_TLS, DATA_LOADER_WORKERS, DATA_LOADER_CORES, TRAINING_THREADS, TRAINING_CORES, DETERMINISTIC_REDUCTION, COMPENSATED_SUMMATION, FREEZE_EMBEDDINGS, BERT_EMBEDDINGS, RELATIVE_POSITIONS, ATTENTION_PROJECTIONS, EMBEDDING_DROPOUT, EMA_DECAY, SWA_START_EPOCH, EMBEDDING_FREQUENCY_SCALING, DOMAIN_FIELD, DOMAIN_ADVERSARIAL_WEIGHT, TIE_MLM_OUTPUT_WEIGHTS, SENTENCE_ORDER_PRETRAINING_EPOCHS, PARAPHRASE_COMMAND, PARAPHRASE_COPIES, PARAPHRASE_CACHE_PATH, TEXT_NOISE_AUGMENTATION, CONTRASTIVE_PRETRAINING, LAYER_NORM_EPSILON, AUTO_TUNE_BATCH_SIZE, BATCH_SIZE_TUNER_MAX_STEP_MS, BATCH_SIZE_TUNER_MAX_STEP_MB, BATCH_SIZE_TUNER_MAX, BATCH_SIZE_TUNER_RESET_PEAK_MEMORY, PROBE_SET_PATH, NEIGHBOR_COUNT, NEAR_DUPLICATE_SIMILARITY, ANN_INDEX_PARAMS, SEARCH_RESULTS, CLASS_MERGE_REDUCTION, LOG_FORMAT};
+use grad_check::gradient_checker::run_grad_check;
+use export::gguf::export_gguf;
+use export::ann_index::{AnnIndex, SemanticSearch};
+use onnx::onnx_import::import_onnx_file;
+use onnx::onnx_export::export_onnx;
+use quantization::embedding_compression::EmbeddingPrecision;
+use quantization::quantizer::Granularity;
+use quantization::calibration::{CalibrationRanges, DEFAULT_CALIBRATION_PERCENTILE};
+use quantization::quantized_feed_forward::ffn_quantization_error;
+use augmentation::paraphrase::{CommandParaphraser, ParaphraseAugmentation};
+use exploration::embedding_index::EmbeddingIndex;
+use classification::class_migration::{migrate_classes, ClassMigration};
+use tokenization::wordpiece::WordPieceTokenizer;
+use tokenization::vocab_builder::StreamingVocabBuilder;
+use tokenization::unigram::UnigramTrainer;
+use tokenization::token_rules::TokenRules;
+use golden::golden_model::{GoldenCase, DEFAULT_GOLDEN_FIXTURE, DEFAULT_GOLDEN_TOLERANCE};
+use golden::reference::generate_reference;
+use experiment::experiment_run::{ExperimentRun, RunConfig};
+use experiment::run_comparison::{RunComparison, RunSummary};
+use experiment::dataset_version::DatasetVersion;
+use data_handler::dataset_analysis::{DatasetAnalysis, DEFAULT_LENGTH_PERCENTILE, DEFAULT_VOCAB_COVERAGE};
+
+/// Flag that lets `--resume`, evaluation, `promote` and `compare-runs` proceed across training
+/// dataset versions.
+const ALLOW_DATASET_MISMATCH: &str = "--allow-dataset-mismatch";
 
 fn main() {
-    println!("Starting Transformer NLP Pipeline...\n");
+    let args: Vec<String> = std::env::args().collect();
+    // `--log-format json` writes pipeline and training events as one JSON object per line.
+    let log_format = match args.iter().position(|arg| arg == "--log-format").and_then(|i| args.get(i + 1)) {
+        Some(name) => LogFormat::parse(name).unwrap_or_else(|| {
+            LogEvent::error("pipeline", format!("Unknown log format '{}', expected text or json", name)).emit();
+            std::process::exit(1);
+        }),
+        None => LOG_FORMAT,
+    };
+    log::set_format(log_format);
+    match args.get(1).map(String::as_str) {
+        // `cargo run -- grad-check` compares analytic and numerical gradients of every module.
+        Some("grad-check") => {
+            let passed = run_grad_check();
+            std::process::exit(if passed { 0 } else { 1 });
+        }
+        // `cargo run -- golden-check [fixture] [--tolerance <t>]` compares every layer's
+        // output with stored reference tensors.
+        Some("golden-check") => {
+            let tolerance = match args.iter().position(|arg| arg == "--tolerance").map(|i| args.get(i + 1).and_then(|t| t.parse().ok())) {
+                Some(Some(tolerance)) => tolerance,
+                Some(None) => {
+                    LogEvent::error("pipeline", "Usage: golden-check [fixture] [--tolerance <t>], where <t> is a number").emit();
+                    std::process::exit(1);
+                }
+                None => DEFAULT_GOLDEN_TOLERANCE,
+            };
+            let fixture_path = args.get(2).filter(|arg| !arg.starts_with("--")).map(String::as_str);
+            let passed = golden_check(fixture_path.unwrap_or(DEFAULT_GOLDEN_FIXTURE), tolerance);
+            std::process::exit(if passed { 0 } else { 1 });
+        }
+        // `cargo run -- golden-generate [fixture]` rewrites the reference fixture from the
+        // independent forward pass in `golden::reference`.
+        Some("golden-generate") => {
+            let fixture_path = args.get(2).map(String::as_str).unwrap_or(DEFAULT_GOLDEN_FIXTURE);
+            if let Err(e) = generate_reference().save(fixture_path) {
+                LogEvent::error("golden", format!("Failed to write golden fixture {}: {}", fixture_path, e)).emit();
+                std::process::exit(1);
+            }
+            LogEvent::info("golden", format!("Wrote golden fixture to {}", fixture_path)).emit();
+            return;
+        }
+        // `cargo run -- analyze-dataset [path]` recommends MAX_SEQ_LENGTH and MAX_VOCAB_SIZE.
+        Some("analyze-dataset") => {
+            let dataset_path = args.get(2).map(String::as_str).unwrap_or(TRAIN_DATASET_PATH);
+            analyze_dataset(dataset_path);
+            return;
+        }
+        // `cargo run -- generate-dataset <output> [num_examples] [num_classes] [seed]` writes a
+        // synthetic classification dataset, e.g. to smoke-test the pipeline without real data.
+        Some("generate-dataset") if args.len() > 2 => {
+            if let Err(e) = generate_dataset(&args[2], &args[3..]) {
+                LogEvent::error("pipeline", format!("Failed to generate synthetic dataset: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- pretrain [corpus] [checkpoint]` runs MLM (and sentence-order prediction
+        // when configured) on an unlabeled in-domain corpus starting from a checkpoint, then
+        // fine-tunes the adapted encoder in a new run directory.
+        Some("pretrain") => {
+            let corpus_path = args.get(2).map(String::as_str).unwrap_or(TRAIN_DATASET_PATH);
+            let checkpoint_path = args.get(3).map(String::as_str).unwrap_or(TRAINED_MODEL_PATH);
+            match domain_adaptive_pretraining(corpus_path, checkpoint_path) {
+                Ok(run) => LogEvent::info("pipeline", format!("Domain-Adaptive Pretraining Completed: {}", run.dir.display())).emit(),
+                Err(e) => {
+                    LogEvent::error("pipeline", format!("Domain-adaptive pretraining failed: {}", e)).emit();
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        // `cargo run -- overfit-batch [path]` checks that the model can memorise a single batch.
+        Some("overfit-batch") => {
+            let dataset_path = args.get(2).map(String::as_str).unwrap_or(TRAIN_DATASET_PATH);
+            let passed = overfit_batch(dataset_path);
+            std::process::exit(if passed { 0 } else { 1 });
+        }
+        // `cargo run -- export-gguf <run_dir> [output]` writes the run's final model and tokenizer as GGUF.
+        Some("export-gguf") => {
+            let Some(run_dir) = args.get(2) else {
+                LogEvent::error("pipeline", "Usage: export-gguf <run_dir> [output]").emit();
+                std::process::exit(1);
+            };
+            let output_path = args.get(3).map(String::as_str).unwrap_or("model.gguf");
+            if let Err(e) = export_run_gguf(run_dir, output_path) {
+                LogEvent::error("pipeline", format!("GGUF export failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- export-onnx <run_dir> [output]` writes the run's final model as an ONNX graph.
+        Some("export-onnx") => {
+            let Some(run_dir) = args.get(2) else {
+                LogEvent::error("pipeline", "Usage: export-onnx <run_dir> [output]").emit();
+                std::process::exit(1);
+            };
+            let output_path = args.get(3).map(String::as_str).unwrap_or("model.onnx");
+            if let Err(e) = export_run_onnx(run_dir, output_path) {
+                LogEvent::error("pipeline", format!("ONNX export failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- export-index <run_dir> <dataset>` embeds a dataset with the run's final model
+        // and saves an HNSW index of it as `<run_dir>/ann_index.json`.
+        Some("export-index") => {
+            let (Some(run_dir), Some(dataset_path)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: export-index <run_dir> <dataset>").emit();
+                std::process::exit(1);
+            };
+            if let Err(e) = export_run_index(run_dir, dataset_path) {
+                LogEvent::error("pipeline", format!("Index export failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- search <run_dir> <text> [k]` prints the `k` indexed examples most similar to `text` as JSON.
+        Some("search") => {
+            let (Some(run_dir), Some(text)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: search <run_dir> <text> [k]").emit();
+                std::process::exit(1);
+            };
+            let k = args.get(4).and_then(|k| k.parse().ok()).unwrap_or(SEARCH_RESULTS);
+            if let Err(e) = search_run_index(run_dir, text, k) {
+                LogEvent::error("pipeline", format!("Search failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- fuzz-tokenizer [iterations] [seed] [dataset]` feeds random text and bytes
+        // to the tokenizer configured for a dataset and checks that it never panics and
+        // round-trips words.
+        Some("fuzz-tokenizer") => match fuzz_configured_tokenizer(&args[2..]) {
+            Ok(passed) => std::process::exit(if passed { 0 } else { 1 }),
+            Err(e) => {
+                LogEvent::error("pipeline", format!("Failed to fuzz the tokenizer: {}", e)).emit();
+                std::process::exit(1);
+            }
+        },
+        // `cargo run -- tune-batch-size [dataset]` probes increasing batch sizes against the
+        // step time and memory budget in `config.rs` and prints the largest that fits.
+        Some("tune-batch-size") => {
+            let dataset_path = args.get(2).map(String::as_str).unwrap_or(TRAIN_DATASET_PATH);
+            let tuning = build_vocab(dataset_path).and_then(|vocab| {
+                let tokenizer = configured_tokenizer(vocab);
+                let mut config = default_run_config();
+                let label_map = fit_classes_to_labels(&mut config, &tokenizer, dataset_path)?;
+                let data_loader = data_loader_with_workers(&tokenizer).with_schema(input_schema(config.input_template.clone())?).with_label_map(label_map);
+                let (inputs, labels) = data_loader.load_dataset(dataset_path)?;
+                tune_batch_size(&config, &data_loader, &inputs, &labels)
+            });
+            match tuning {
+                Ok(tuning) => LogEvent::info("pipeline", tuning.summary()).emit(),
+                Err(e) => {
+                    LogEvent::error("pipeline", format!("Batch size tuning failed: {}", e)).emit();
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        // `cargo run -- build-vocab <corpus.txt> [output]` builds a tokenizer from a plain-text
+        // corpus read line by line, for corpora too large to load at once.
+        Some("build-vocab") => {
+            let Some(corpus_path) = args.get(2) else {
+                LogEvent::error("pipeline", "Usage: build-vocab <corpus.txt> [output]").emit();
+                std::process::exit(1);
+            };
+            let output_path = args.get(3).map(String::as_str).unwrap_or("tokenizer.json");
+            if let Err(e) = build_streaming_vocab(corpus_path, output_path) {
+                LogEvent::error("pipeline", format!("Vocabulary construction failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- train-unigram <corpus.txt> <vocab_size> [output]` trains a unigram subword
+        // model on a plain-text corpus, one text per line, and saves it as a tokenizer file.
+        Some("train-unigram") => {
+            let (Some(corpus_path), Some(vocab_size)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: train-unigram <corpus.txt> <vocab_size> [output]").emit();
+                std::process::exit(1);
+            };
+            let Ok(vocab_size) = vocab_size.parse() else {
+                LogEvent::error("pipeline", format!("vocab_size must be a number, got '{}'", vocab_size)).emit();
+                std::process::exit(1);
+            };
+            let output_path = args.get(4).map(String::as_str).unwrap_or("tokenizer.json");
+            if let Err(e) = train_unigram_tokenizer(corpus_path, vocab_size, output_path) {
+                LogEvent::error("pipeline", format!("Unigram training failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- import-tokenizer <vocab.txt|tokenizer.json> [output]` converts a BERT-style
+        // WordPiece vocabulary or a HuggingFace tokenizer into a tokenizer file for `TOKENIZER_PATH`.
+        Some("import-tokenizer") => {
+            let Some(source_path) = args.get(2) else {
+                LogEvent::error("pipeline", "Usage: import-tokenizer <vocab.txt|tokenizer.json> [output]").emit();
+                std::process::exit(1);
+            };
+            let output_path = args.get(3).map(String::as_str).unwrap_or("tokenizer.json");
+            if let Err(e) = import_tokenizer(source_path, output_path) {
+                LogEvent::error("pipeline", format!("Tokenizer import failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- compress-embeddings <model.json> <output> [int8|int4]` rewrites a
+        // checkpoint with its embedding matrix quantized per row.
+        Some("compress-embeddings") => {
+            let (Some(model_path), Some(output_path)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: compress-embeddings <model.json> <output> [int8|int4]").emit();
+                std::process::exit(1);
+            };
+            let precision = match args.get(4).map(String::as_str).unwrap_or("int8") {
+                "int8" => EmbeddingPrecision::Int8,
+                "int4" => EmbeddingPrecision::Int4,
+                other => {
+                    LogEvent::error("pipeline", format!("Unknown embedding precision '{}', expected int8 or int4", other)).emit();
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = compress_embeddings(model_path, output_path, precision) {
+                LogEvent::error("pipeline", format!("Embedding compression failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- quantization-report <run_dir> [dataset]` compares the FFN quantization error
+        // of every scale granularity on a dataset, to choose `INFERENCE_QUANTIZATION`.
+        Some("quantization-report") => {
+            let Some(run_dir) = args.get(2) else {
+                LogEvent::error("pipeline", "Usage: quantization-report <run_dir> [dataset]").emit();
+                std::process::exit(1);
+            };
+            let dataset_path = args.get(3).map(String::as_str).unwrap_or(TEST_DATASET_PATH);
+            if let Err(e) = quantization_report(run_dir, dataset_path) {
+                LogEvent::error("pipeline", format!("Quantization report failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- import-onnx <model.onnx> <vocab.txt> [output]` converts an ONNX encoder-classifier into a model checkpoint.
+        Some("import-onnx") => {
+            let (Some(onnx_path), Some(vocab_path)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: import-onnx <model.onnx> <vocab.txt> [output]").emit();
+                std::process::exit(1);
+            };
+            let output_path = args.get(4).map(String::as_str).unwrap_or("model.json");
+            if let Err(e) = import_onnx_model(onnx_path, vocab_path, output_path) {
+                LogEvent::error("pipeline", format!("ONNX import failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- predict <run_dir> <text>` prints the run's prediction for `text` as JSON;
+        // runs trained with an input template take a JSON object of fields instead of text.
+        // Given the serving directory instead, it predicts with the promoted model and calibrator.
+        Some("predict") => {
+            let (Some(run_dir), Some(text)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: predict <run_dir|serving_dir> <text>").emit();
+                std::process::exit(1);
+            };
+            if let Err(e) = print_prediction(run_dir, text) {
+                LogEvent::error("pipeline", format!("Prediction failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- fit-ensemble <dataset> <output> <run_dir>...` fits a stacking head on the runs'
+        // predictions of a labelled held-out dataset, reports its accuracy against plain averaging and
+        // saves it to `output`.
+        Some("fit-ensemble") => {
+            let (Some(dataset_path), Some(output_path), run_dirs) = (args.get(2), args.get(3), args.get(4..).unwrap_or_default()) else {
+                LogEvent::error("pipeline", "Usage: fit-ensemble <dataset> <output> <run_dir> <run_dir>...").emit();
+                std::process::exit(1);
+            };
+            if let Err(e) = fit_ensemble(dataset_path, output_path, run_dirs) {
+                LogEvent::error("pipeline", format!("Ensemble fitting failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- predict-ensemble <text> <run_dir>... [--stacking <head>]` prints the combined
+        // prediction of several runs as JSON, averaged or with a head saved by `fit-ensemble`.
+        Some("predict-ensemble") => {
+            let stacking_path = args.iter().position(|arg| arg == "--stacking").and_then(|i| args.get(i + 1));
+            let run_dirs: Vec<String> = args.iter().skip(3).take_while(|arg| *arg != "--stacking").cloned().collect();
+            let Some(text) = args.get(2) else {
+                LogEvent::error("pipeline", "Usage: predict-ensemble <text> <run_dir>... [--stacking <head>]").emit();
+                std::process::exit(1);
+            };
+            if let Err(e) = print_ensemble_prediction(text, &run_dirs, stacking_path.map(String::as_str)) {
+                LogEvent::error("pipeline", format!("Ensemble prediction failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- serve [serving_dir|run_dir]` answers `/predict`, `/explain` and `/health`
+        // over HTTP on `SERVER_ADDRESS`, with the promoted model by default.
+        Some("serve") => {
+            let dir = args.get(2).map(String::as_str).unwrap_or(SERVING_DIR);
+            if let Err(e) = serve(dir) {
+                LogEvent::error("server", format!("Serving failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- evaluate <run_dir> [dataset] [--misclassified <output>]` scores the run's
+        // final model on `dataset` (the test set by default) and prints its accuracy-vs-coverage curve
+        // and the `EVALUATION_SLICE_FIELDS` and `FAIRNESS_GROUP` reports.
+        Some("evaluate") => {
+            let misclassified_path = args.iter().position(|arg| arg == "--misclassified").and_then(|i| args.get(i + 1));
+            let Some(run_dir) = args.get(2) else {
+                LogEvent::error("pipeline", "Usage: evaluate <run_dir> [dataset] [--misclassified <output>]").emit();
+                std::process::exit(1);
+            };
+            let dataset_path = args.get(3).filter(|arg| !arg.starts_with("--")).map_or(TEST_DATASET_PATH, String::as_str);
+            if let Err(e) = evaluate_run(run_dir, dataset_path, misclassified_path.map(String::as_str)) {
+                LogEvent::error("evaluator", format!("Evaluation failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- explain <run_dir> <text>` prints the prediction and per-token and per-word
+        // importances as JSON.
+        Some("explain") => {
+            let (Some(run_dir), Some(text)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: explain <run_dir> <text>").emit();
+                std::process::exit(1);
+            };
+            if let Err(e) = explain_prediction(run_dir, text) {
+                LogEvent::error("pipeline", format!("Explanation failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- word-embeddings <run_dir> <text>` prints the words of a text with their encoder
+        // outputs pooled over sub-word pieces (`WORD_POOLING`) as JSON.
+        Some("word-embeddings") => {
+            let (Some(run_dir), Some(text)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: word-embeddings <run_dir> <text>").emit();
+                std::process::exit(1);
+            };
+            if let Err(e) = print_word_embeddings(run_dir, text) {
+                LogEvent::error("pipeline", format!("Word embedding failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- neighbors <run_dir> <dataset> [text]` embeds a dataset with the run's model and
+        // prints the nearest examples of `text`; without one it reports likely mislabeled examples
+        // and near-duplicates, then answers one query per line of stdin.
+        Some("neighbors") => {
+            let (Some(run_dir), Some(dataset_path)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: neighbors <run_dir> <dataset> [text]").emit();
+                std::process::exit(1);
+            };
+            if let Err(e) = explore_neighbors(run_dir, dataset_path, args.get(4).map(String::as_str)) {
+                LogEvent::error("pipeline", format!("Neighbor search failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- remap-classes <run_dir> merge <into> <label>...`, `remove <label>...` or
+        // `rename <label> <new_label>` migrates the run's final model and label map to a changed
+        // taxonomy without retraining.
+        Some("remap-classes") => {
+            let usage = "Usage: remap-classes <run_dir> (merge <into> <label>... | remove <label>... | rename <label> <new_label>)";
+            let migration = match (args.get(3).map(String::as_str), args.get(4)) {
+                (Some("rename"), Some(name)) if args.len() == 6 => {
+                    if let Err(e) = rename_run_class(&args[2], name, &args[5]) {
+                        LogEvent::error("pipeline", format!("Renaming class {} failed: {}", name, e)).emit();
+                        std::process::exit(1);
+                    }
+                    return;
+                }
+                (Some("merge"), Some(into)) if args.len() > 5 => ClassMigration::Merge { names: args[5..].to_vec(), into: into.clone() },
+                (Some("remove"), Some(_)) => ClassMigration::Remove { names: args[4..].to_vec() },
+                _ => {
+                    LogEvent::error("pipeline", usage).emit();
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = remap_run_classes(&args[2], &migration) {
+                LogEvent::error("pipeline", format!("Class remapping failed: {}", e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- add-class <run_dir> <label> <example>...` registers a new class from a few
+        // example texts in the run's final model and label map without retraining.
+        Some("add-class") => {
+            let (Some(run_dir), Some(label)) = (args.get(2), args.get(3)) else {
+                LogEvent::error("pipeline", "Usage: add-class <run_dir> <label> <example>...").emit();
+                std::process::exit(1);
+            };
+            let examples: Vec<&str> = args[4..].iter().map(String::as_str).collect();
+            if let Err(e) = add_run_class(run_dir, label, &examples) {
+                LogEvent::error("pipeline", format!("Adding class {} failed: {}", label, e)).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `cargo run -- promote [--allow-dataset-mismatch] <run_dir> [serving_dir]` installs the
+        // run's final model for serving if it passes `PROMOTION_GATE` on `PROMOTION_GATE_DATASET`.
+        Some("promote") => {
+            let allow_mismatch = args.iter().any(|arg| arg == ALLOW_DATASET_MISMATCH);
+            let positional: Vec<&String> = args[2..].iter().filter(|arg| !arg.starts_with("--")).collect();
+            let Some(run_dir) = positional.first() else {
+                LogEvent::error("pipeline", format!("Usage: promote [{}] <run_dir> [serving_dir]", ALLOW_DATASET_MISMATCH)).emit();
+                std::process::exit(1);
+            };
+            let serving_dir = positional.get(1).map(|dir| dir.as_str()).unwrap_or(SERVING_DIR);
+            match promote_run(run_dir, serving_dir, PROMOTION_GATE_DATASET, allow_mismatch) {
+                Ok(promoted) => std::process::exit(if promoted { 0 } else { 1 }),
+                Err(e) => {
+                    LogEvent::error("pipeline", format!("Promotion failed: {}", e)).emit();
+                    std::process::exit(1);
+                }
+            }
+        }
+        // `cargo run -- compare-runs [--json] [--allow-dataset-mismatch] <run_dir>...` prints config
+        // and metric deltas between runs trained on the same dataset version.
+        Some("compare-runs") => {
+            let as_json = args.iter().any(|arg| arg == "--json");
+            let allow_mismatch = args.iter().any(|arg| arg == ALLOW_DATASET_MISMATCH);
+            let run_dirs: Vec<&String> = args[2..].iter().filter(|arg| !arg.starts_with("--")).collect();
+            if let Err(e) = compare_runs(&run_dirs, as_json, allow_mismatch) {
+                LogEvent::error("pipeline", e.to_string()).emit();
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    // `--resume` names the run to continue; without a directory it continues the latest run.
+    let resume = args.iter().position(|arg| arg == "--resume").map(|i| args.get(i + 1).filter(|arg| !arg.starts_with("--")));
 
-    let vocab = build_vocab("src/train_dataset.json");
+    // `cargo run -- --dry-run` validates config, data, model construction and one
+    // forward/backward pass on a tiny batch, then exits without training.
+    if args.iter().any(|arg| arg == "--dry-run") {
+        let report = match resume {
+            Some(run_dir) => dry_run(TRAIN_DATASET_PATH, DRY_RUN_SAMPLE_SIZE, || {
+                let run = resumed_run(run_dir)?;
+                let config = run.load_config()?;
+                let schema = input_schema(config.input_template.clone())?;
+                Ok(DryRunSetup { config, tokenizer: load_run_tokenizer(&run)?, label_map: run.load_label_map()?, schema })
+            }),
+            None => dry_run(TRAIN_DATASET_PATH, DRY_RUN_SAMPLE_SIZE, || {
+                let (config, tokenizer, label_map) = prepare_run(TRAIN_DATASET_PATH)?;
+                let schema = input_schema(config.input_template.clone())?;
+                Ok(DryRunSetup { config, tokenizer, label_map: Some(label_map), schema })
+            }),
+        };
+        report.print();
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+
+    // `--max-duration <seconds>` stops training after a wall-clock budget.
+    let max_duration = match args.iter().position(|arg| arg == "--max-duration").map(|i| args.get(i + 1).and_then(|seconds| seconds.parse().ok())) {
+        Some(Some(seconds)) => Some(Duration::from_secs(seconds)),
+        Some(None) => {
+            LogEvent::error("pipeline", "Usage: --max-duration <seconds>, where <seconds> is a whole number").emit();
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    LogEvent::info("pipeline", "Starting Transformer NLP Pipeline...\n").emit();
+
+    if let Err(e) = check_augmentation_config() {
+        LogEvent::error("pipeline", format!("Invalid augmentation configuration: {}", e)).emit();
+        std::process::exit(1);
+    }
+
+    // `cargo run -- --resume [run_dir]` continues a run from its latest checkpoint instead of
+    // creating a new one.
+    let run = match resume {
+        Some(run_dir) => match resumed_run(run_dir) {
+            Ok(run) => run,
+            Err(e) => {
+                LogEvent::error("pipeline", format!("Failed to open the run to resume: {}", e)).emit();
+                std::process::exit(1);
+            }
+        },
+        None => match create_run() {
+            Ok(run) => run,
+            Err(e) => {
+                LogEvent::error("pipeline", format!("Failed to create the run: {}", e)).emit();
+                std::process::exit(1);
+            }
+        },
+    };
+    LogEvent::info("pipeline", format!("Run directory: {}", run.dir.display())).metric("run_dir", &run.dir).emit();
+    if let Err(e) = check_thread_config() {
+        LogEvent::error("pipeline", format!("Invalid thread configuration: {}", e)).emit();
+        std::process::exit(1);
+    }
+
+    let run_config = run.load_config().expect("Failed to load run config");
+    let allow_dataset_mismatch = args.iter().any(|arg| arg == ALLOW_DATASET_MISMATCH);
+    // A resumed run must keep training on the data it was created with.
+    if resume.is_some() {
+        if let Err(e) = check_run_dataset(&run_config, "resume", allow_dataset_mismatch) {
+            LogEvent::error("pipeline", e).emit();
+            std::process::exit(1);
+        }
+    }
+    let tokenizer = load_run_tokenizer(&run).expect("Failed to load run tokenizer");
+    let vocab = tokenizer.vocab.clone();
 
  
-    let tokenizer = Tokenizer::new(vocab.clone(), MAX_SEQ_LENGTH);
+    let mut data_loader = run_data_loader(&run, &tokenizer).expect("Failed to load run label map").with_batch_size(run_config.batch_size);
+    if let Some(window) = run_config.sliding_window {
+        data_loader = data_loader.with_sliding_window(window);
+    }
+
+    // `--profile` records the time spent per module during training, prints a summary
+    // and writes `<run_dir>/profile.folded` for flame graph tools. `--trace` also writes
+    // a `chrome://tracing` timeline of steps, data loading and checkpoints to `<run_dir>/trace.json`.
+    let trace = args.iter().any(|arg| arg == "--trace");
+    let profile = trace || args.iter().any(|arg| arg == "--profile");
+    if trace {
+        profiler::enable_trace(DEFAULT_TRACE_DEPTH);
+    } else if profile {
+        profiler::enable();
+    }
+  
+    let outcome = train_model(&run_config, &vocab, &data_loader, &run, max_duration);
+    if profile {
+        report_profile(&run, trace);
+    }
+    match outcome {
+        Ok(TrainingOutcome::Completed) => LogEvent::info("pipeline", "Model Training Completed.\n").emit(),
+        Ok(TrainingOutcome::Interrupted) => {
+            LogEvent::info("pipeline", format!("Training interrupted. Continue with: cargo run -- --resume {}", run.dir.display())).emit();
+            std::process::exit(130);
+        }
+        Err(e) => {
+            LogEvent::error("trainer", format!("Training failed: {}", e)).emit();
+            std::process::exit(1);
+        }
+    }
+
+    // Scores are only comparable with the run's own if the data did not change during training.
+    if let Err(e) = check_run_dataset(&run_config, "evaluate", allow_dataset_mismatch) {
+        LogEvent::error("pipeline", e).emit();
+        std::process::exit(1);
+    }
+    if let Err(e) = evaluate_model(&data_loader, &run, TEST_DATASET_PATH) {
+        LogEvent::error("evaluator", format!("Evaluation error: {}", e)).emit();
+    }
+
+  
+    perform_inference(&run.tokenizer_path(), &run.checkpoint_path(None), data_loader.label_map.clone());
+
+    LogEvent::info("pipeline", "\nPipeline Execution Completed Successfully!").emit();
+}
+
+
+fn report_profile(run: &ExperimentRun, trace: bool) {
+    profiler::disable();
+    let profile = profiler::take_profile();
+    LogEvent::info("pipeline", format!("Training profile:\n{}", profile.summary())).emit();
+
+    let folded_path = run.dir.join("profile.folded");
+    match std::fs::write(&folded_path, profile.folded()) {
+        Ok(()) => LogEvent::info("pipeline", format!("Folded stacks written to {}", folded_path.display())).emit(),
+        Err(e) => LogEvent::error("pipeline", format!("Failed to write {}: {}", folded_path.display(), e)).emit(),
+    }
+    if trace {
+        let trace_path = run.dir.join("trace.json");
+        match profiler::take_trace().save(&trace_path.to_string_lossy()) {
+            Ok(()) => LogEvent::info("pipeline", format!("Timeline written to {} (open in chrome://tracing)", trace_path.display())).emit(),
+            Err(e) => LogEvent::error("pipeline", format!("Failed to write {}: {}", trace_path.display(), e)).emit(),
+        }
+    }
+}
+
+/// Writes a `SyntheticDataset` with the default parameters, overridden by the optional
+/// `[num_examples] [num_classes] [seed]` arguments.
+fn generate_dataset(output_path: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let defaults = SyntheticConfig::default();
+    let config = SyntheticConfig {
+        num_examples: args.first().map(|n| n.parse()).transpose()?.unwrap_or(defaults.num_examples),
+        num_classes: args.get(1).map(|n| n.parse()).transpose()?.unwrap_or(defaults.num_classes),
+        ..defaults
+    };
+    let seed = args.get(2).map(|n| n.parse()).transpose()?.unwrap_or(0);
+    let dataset = SyntheticDataset::generate(&config, &mut StdRng::seed_from_u64(seed))?;
+    dataset.save_json(output_path)?;
+    LogEvent::info("pipeline", format!("Wrote {} synthetic examples to {}", dataset.texts.len(), output_path))
+        .metric("num_classes", config.num_classes as f64)
+        .emit();
+    Ok(())
+}
+
+fn export_run_gguf(run_dir: &str, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let tokenizer = load_run_tokenizer(&run)?;
+    let model = Transformer::load(&run.checkpoint_path(None))?;
+
+    export_gguf(&model, &tokenizer, output_path)?;
+    LogEvent::info("pipeline", format!("Exported {} to {}", run_dir, output_path)).emit();
+    Ok(())
+}
+
+fn export_run_onnx(run_dir: &str, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let tokenizer = load_run_tokenizer(&run)?;
+    let model = Transformer::load(&run.checkpoint_path(None))?;
+
+    export_onnx(&model, tokenizer.max_seq_length, output_path)?;
+    LogEvent::info("pipeline", format!("Exported {} to {}", run_dir, output_path)).emit();
+    Ok(())
+}
+
+/// Index file written by `export-index` into a run directory.
+const ANN_INDEX_FILE: &str = "ann_index.json";
+
+fn export_run_index(run_dir: &str, dataset_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let tokenizer = load_run_tokenizer(&run)?;
+    let model = Transformer::load(&run.checkpoint_path(None))?;
+    let index = AnnIndex::build(&model, &run_data_loader(&run, &tokenizer)?, dataset_path, ANN_INDEX_PARAMS)?;
+
+    let index_path = run.dir.join(ANN_INDEX_FILE);
+    index.save(&index_path.to_string_lossy())?;
+    LogEvent::info("pipeline", format!("Indexed {} examples of {} into {}", index.len(), dataset_path, index_path.display())).emit();
+    Ok(())
+}
+
+fn search_run_index(run_dir: &str, text: &str, k: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let index_path = run.dir.join(ANN_INDEX_FILE);
+    let search = SemanticSearch::load(&run.checkpoint_path(None), &run.tokenizer_path(), &index_path.to_string_lossy())?;
+    println!("{}", serde_json::to_string_pretty(&search.search(text, k)?)?);
+    Ok(())
+}
+
+/// Prints the `Prediction` of the final model of a run, named with the run's label map, or of
+/// the model promoted into a serving directory.
+/// For runs trained with an input template, `input` is a JSON object of the template's fields.
+fn print_prediction(dir: &str, input: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let inference = load_predictor(dir)?;
+    let prediction = match &inference.input_template {
+        Some(template) => {
+            let fields: HashMap<String, String> = serde_json::from_str(input).map_err(|e| format!("The run renders {:?}; pass its fields as a JSON object of strings: {}", template.as_str(), e))?;
+            inference.predict_fields(&fields)?
+        }
+        None => inference.decide(input)?,
+    };
+    println!("{}", serde_json::to_string_pretty(&prediction)?);
+    Ok(())
+}
+
+/// `Inference` over the model promoted into a serving directory, or over the final model of a run,
+/// deciding with the costs of `COST_MATRIX_PATH` when it is set.
+fn load_predictor(dir: &str) -> Result<Inference, Box<dyn std::error::Error>> {
+    let inference = if Path::new(dir).join(SERVING_MODEL_FILE).exists() {
+        Inference::from_serving_dir(Path::new(dir))?.with_task(ACTIVE_TASK)?.with_overflow_policy(INFERENCE_OVERFLOW_POLICY).with_top_k(PREDICTION_TOP_K).with_word_pooling(WORD_POOLING)
+    } else {
+        run_inference(&ExperimentRun::open(dir)?)?
+    };
+    Ok(match COST_MATRIX_PATH {
+        Some(path) => inference.with_cost_matrix(CostMatrix::from_file(path)?),
+        None => inference,
+    })
+}
+
+/// Serves the model of `dir` (see `load_predictor`) on `SERVER_ADDRESS`, with
+/// `SERVER_REQUEST_LIMITS`, `SERVER_RATE_LIMIT`, `SERVER_API_KEYS_PATH` and `SERVER_TLS`.
+fn serve(dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut server = Server::new(load_predictor(dir)?).with_request_limits(SERVER_REQUEST_LIMITS);
+    if let Some((requests_per_second, burst)) = SERVER_RATE_LIMIT {
+        server = server.with_rate_limiter(RateLimiter::new(requests_per_second, burst)?);
+    }
+    if let Some(path) = SERVER_API_KEYS_PATH {
+        let auth = ApiKeyAuth::from_file(path)?;
+        if auth.is_empty() {
+            return Err(format!("{} has no API keys; every request would be rejected", path).into());
+        }
+        server = server.with_auth(auth);
+    }
+    if let Some((certificate_path, private_key_path)) = SERVER_TLS {
+        server = server.with_tls(certificate_path, private_key_path)?;
+    }
+    server.serve(SERVER_ADDRESS)
+}
+
+/// The run's tokenizer, prefixing every text with the token of `ACTIVE_TASK`.
+fn load_run_tokenizer(run: &ExperimentRun) -> Result<Tokenizer, std::io::Error> {
+    let tokenizer = run.load_tokenizer()?;
+    match ACTIVE_TASK {
+        Some(task) => tokenizer.for_task(task),
+        None => Ok(tokenizer),
+    }
+}
+
+/// `INFERENCE_OVERFLOW_POLICY` for a run. Runs trained on sliding windows chunk long texts
+/// instead of truncating them, as their evaluation does.
+fn run_overflow_policy(config: &RunConfig) -> OverflowPolicy {
+    match (INFERENCE_OVERFLOW_POLICY, config.sliding_window) {
+        (OverflowPolicy::Truncate, Some(_)) => OverflowPolicy::ChunkAndAggregate,
+        (policy, _) => policy,
+    }
+}
+
+/// `Inference` over the final model of a run, with the run's label map, input template and
+/// overflow policy, quantized when `INFERENCE_QUANTIZATION` is set and in nearest-centroid mode
+/// when `PROTOTYPE_INFERENCE` is.
+fn run_inference(run: &ExperimentRun) -> Result<Inference, Box<dyn std::error::Error>> {
+    let config = run.load_config()?;
+    let mut inference = Inference::new(&run.checkpoint_path(None), &run.tokenizer_path())?
+        .with_task(ACTIVE_TASK)?
+        .with_overflow_policy(run_overflow_policy(&config))
+        .with_top_k(PREDICTION_TOP_K)
+        .with_word_pooling(WORD_POOLING);
+    if let Some(label_map) = run.load_label_map()? {
+        inference = inference.with_label_map(label_map)?;
+    }
+    if let Some(template) = config.input_template {
+        inference = inference.with_input_template(template);
+    }
+    if let Some(granularity) = INFERENCE_QUANTIZATION {
+        let (mut sequences, _) = run_data_loader(run, &inference.tokenizer)?.load_dataset(QUANTIZATION_CALIBRATION_DATASET)?;
+        sequences.truncate(QUANTIZATION_CALIBRATION_SAMPLES);
+        let errors = inference.quantize_feed_forward(granularity, &sequences)?;
+        LogEvent::info("inference", format!("Serving int8 feed-forward networks ({:?} scales), mean absolute error per layer: {:?}", granularity, errors))
+            .metric("ffn_quantization_error", &errors)
+            .emit();
+    }
+    if PROTOTYPE_INFERENCE {
+        let tokenizer = inference.tokenizer.clone();
+        inference.fit_prototypes(&run_data_loader(run, &tokenizer)?, TRAIN_DATASET_PATH)?;
+    }
+    Ok(inference)
+}
+
+/// Fits a stacking head on the predictions every run makes for a labelled dataset, logs
+/// its cross-validated accuracy (`STACKING_FOLDS` folds) next to plain averaging and saves
+/// it to `output_path`.
+fn fit_ensemble(dataset_path: &str, output_path: &str, run_dirs: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut member_predictions = Vec::new();
+    for run_dir in run_dirs {
+        let run = ExperimentRun::open(run_dir)?;
+        let inference = run_inference(&run)?;
+        let records = run_data_loader(&run, &inference.tokenizer)?.load_records(dataset_path)?;
+        member_predictions.push(inference.predict_records(&records)?);
+    }
+    let head = StackingHead::fit(&member_predictions)?;
+
+    let accuracy = |predictions: &[ExamplePrediction]| {
+        let labelled: Vec<&ExamplePrediction> = predictions.iter().filter(|prediction| prediction.label.is_some()).collect();
+        labelled.iter().filter(|prediction| prediction.is_correct()).count() as f64 / labelled.len() as f64
+    };
+    let averaged = EnsembleCombiner::Average.combine_predictions(&member_predictions)?;
+    let stacked = StackingHead::cross_validate(&member_predictions, STACKING_FOLDS)?;
+    LogEvent::info(
+        "ensemble",
+        format!(
+            "Accuracy of {} runs on {}: {:.4} averaged, {:.4} stacked (out of fold)",
+            run_dirs.len(),
+            dataset_path,
+            accuracy(&averaged),
+            accuracy(&stacked)
+        ),
+    )
+    .emit();
+    head.save(output_path)?;
+    Ok(())
+}
+
+/// Prints the combined prediction of several runs. When the runs were trained with input
+/// templates, `input` is a JSON object of the templates' fields.
+fn print_ensemble_prediction(input: &str, run_dirs: &[String], stacking_path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let members = run_dirs.iter().map(|run_dir| run_inference(&ExperimentRun::open(run_dir)?)).collect::<Result<Vec<_>, _>>()?;
+    let mut ensemble = Ensemble::new(members)?;
+    if let Some(stacking_path) = stacking_path {
+        ensemble = ensemble.with_stacking_head(StackingHead::load(stacking_path)?)?;
+    }
+    let prediction = if ensemble.members.iter().any(|member| member.input_template.is_some()) {
+        let fields: HashMap<String, String> = serde_json::from_str(input).map_err(|e| format!("The runs render input templates; pass their fields as a JSON object of strings: {}", e))?;
+        ensemble.predict_fields(&fields)?
+    } else {
+        ensemble.predict(input)?
+    };
+    println!("{}", serde_json::to_string_pretty(&prediction)?);
+    Ok(())
+}
+
+fn explain_prediction(run_dir: &str, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let inference = Inference::new(&run.checkpoint_path(None), &run.tokenizer_path())?
+        .with_task(ACTIVE_TASK)?
+        .with_overflow_policy(INFERENCE_OVERFLOW_POLICY)
+        .with_word_pooling(WORD_POOLING);
+    let explanation = inference.explain(text)?;
+    if explanation.prediction.overflow.truncated() {
+        LogEvent::warn("inference", format!("Explained {} of {} tokens; the rest exceeded max_seq_length", explanation.tokens.len(), explanation.prediction.overflow.input_tokens)).emit();
+    }
+    println!("{}", serde_json::to_string_pretty(&explanation)?);
+    Ok(())
+}
+
+fn print_word_embeddings(run_dir: &str, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let inference = Inference::new(&run.checkpoint_path(None), &run.tokenizer_path())?.with_task(ACTIVE_TASK)?;
+    let (words, vectors) = inference.word_embeddings(text, WORD_POOLING)?;
+    let words: Vec<serde_json::Value> = words.iter().zip(vectors.outer_iter()).map(|(word, vector)| serde_json::json!({ "word": word, "vector": vector.to_vec() })).collect();
+    println!("{}", serde_json::to_string_pretty(&words)?);
+    Ok(())
+}
+
+/// Applies a class merge or removal to the final model of a run, and saves the model, the
+/// label map and the class count of the run config. Runs without a label map use class ids
+/// as names.
+fn remap_run_classes(run_dir: &str, migration: &ClassMigration) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let model_path = run.checkpoint_path(None);
+    let mut model = Transformer::load(&model_path)?;
+    let mut label_map = run.load_label_map()?.unwrap_or_else(|| LabelMap::numeric(model.classification_head.num_classes()));
+    let previous = label_map.clone();
+
+    let remap = migrate_classes(&mut model, &mut label_map, migration, CLASS_MERGE_REDUCTION)?;
+    for (class, new_class) in remap.iter().enumerate() {
+        match new_class {
+            Some(new_class) => LogEvent::info("pipeline", format!("  {} -> {}", previous.display_name(class), label_map.display_name(*new_class))).emit(),
+            None => LogEvent::info("pipeline", format!("  {} removed", previous.display_name(class))).emit(),
+        }
+    }
+
+    let mut config = run.load_config()?;
+    config.model.num_classes = label_map.len();
+    model.save(&model_path)?;
+    run.save_label_map(&label_map)?;
+    run.save_config(&config)?;
+    LogEvent::info("pipeline", format!("Saved {} classes to {}", label_map.len(), run.dir.display())).emit();
+    Ok(())
+}
+
+/// Renames a class in the run's label map, which starts from the class ids (`"0"`, `"1"`, ...)
+/// when the run has none. Class ids are unchanged, so the model is not touched.
+fn rename_run_class(run_dir: &str, name: &str, new_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let mut label_map = match run.load_label_map()? {
+        Some(label_map) => label_map,
+        None => LabelMap::numeric(run.load_config()?.model.num_classes),
+    };
+    label_map.rename(name, new_name)?;
+    run.save_label_map(&label_map)?;
+    LogEvent::info("pipeline", format!("Renamed class {} to {} in {}", name, new_name, run.dir.display())).emit();
+    Ok(())
+}
+
+/// Registers `label` in the run's final model and label map from example texts (see
+/// `Inference::register_class`) and saves them with the new class count.
+fn add_run_class(run_dir: &str, label: &str, examples: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let model_path = run.checkpoint_path(None);
+    let mut inference = Inference::new(&model_path, &run.tokenizer_path())?.with_task(ACTIVE_TASK)?;
+    if let Some(label_map) = run.load_label_map()? {
+        inference = inference.with_label_map(label_map)?;
+    }
+    let class = inference.register_class(label, examples)?;
+
+    let mut config = run.load_config()?;
+    config.model.num_classes = inference.model.config.num_classes;
+    inference.model.save(&model_path)?;
+    run.save_label_map(inference.label_map.as_ref().ok_or("register_class sets a label map")?)?;
+    run.save_config(&config)?;
+    LogEvent::info("pipeline", format!("Added class {} ({}) to {} from {} examples", label, class, run.dir.display(), examples.len())).emit();
+    Ok(())
+}
+
+fn explore_neighbors(run_dir: &str, dataset_path: &str, text: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let tokenizer = load_run_tokenizer(&run)?;
+    let model = Transformer::load(&run.checkpoint_path(None))?;
+    let data_loader = run_data_loader(&run, &tokenizer)?;
+    let index = EmbeddingIndex::build(&model, &data_loader, dataset_path)?;
+    LogEvent::info("pipeline", format!("Indexed {} examples of {}", index.len(), dataset_path)).emit();
+
+    if let Some(text) = text {
+        println!("{}", serde_json::to_string_pretty(&index.query(&model, &tokenizer, text, NEIGHBOR_COUNT)?)?);
+        return Ok(());
+    }
+
+    let disagreements = index.label_disagreements(NEIGHBOR_COUNT);
+    LogEvent::info("pipeline", format!("{} examples disagree with the labels of their {} nearest neighbours:", disagreements.len(), NEIGHBOR_COUNT)).emit();
+    for disagreement in &disagreements {
+        let (id, text) = index.example(disagreement.index);
+        LogEvent::info(
+            "pipeline",
+            format!(
+                "  [{}] label {}, neighbours mostly {} ({:.0}% agree): {}",
+                id,
+                disagreement.label,
+                disagreement.neighbor_label,
+                disagreement.agreement * 100.0,
+                text
+            ),
+        )
+        .emit();
+    }
+    let duplicates = index.near_duplicates(NEAR_DUPLICATE_SIMILARITY);
+    LogEvent::info("pipeline", format!("{} near-duplicate pairs (similarity >= {}):", duplicates.len(), NEAR_DUPLICATE_SIMILARITY)).emit();
+    for duplicate in &duplicates {
+        let ((first_id, first_text), (second_id, second_text)) = (index.example(duplicate.first), index.example(duplicate.second));
+        LogEvent::info("pipeline", format!("  {:.4} [{}] {} <-> [{}] {}", duplicate.similarity, first_id, first_text, second_id, second_text)).emit();
+    }
+
+    LogEvent::info("pipeline", "Enter a text to list its nearest examples (Ctrl-D to quit):").emit();
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        for neighbor in index.query(&model, &tokenizer, &line, NEIGHBOR_COUNT)? {
+            let label = neighbor.label.map_or("-".to_string(), |label| label.to_string());
+            LogEvent::info("pipeline", format!("  {:.4} [{}] label {}: {}", neighbor.similarity, neighbor.id, label, neighbor.text)).emit();
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates a run's final model on the gate dataset, together with the model currently in
+/// `serving_dir`, and installs it there if it passes `PROMOTION_GATE`.
+///
+/// # Returns
+/// * Whether the model was promoted. The decision is written to `<serving_dir>/promotion.json`
+///   and the run's metrics either way.
+fn promote_run(run_dir: &str, serving_dir: &str, gate_dataset: &str, allow_dataset_mismatch: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    check_run_dataset(&run.load_config()?, "evaluate", allow_dataset_mismatch)?;
+    let tokenizer = load_run_tokenizer(&run)?;
+    let data_loader = run_data_loader(&run, &tokenizer)?;
+    let candidate = Evaluator::new(&run.checkpoint_path(None), &data_loader)?.compute_report(gate_dataset)?;
+
+    let serving_path = Path::new(serving_dir);
+    let served_model = serving_path.join(SERVING_MODEL_FILE);
+    let served = if served_model.exists() {
+        let report = Tokenizer::load(&serving_path.join(SERVING_TOKENIZER_FILE).to_string_lossy()).map_err(Into::into).and_then(|served_tokenizer| {
+            // The gate dataset's labels are read with the candidate's label map.
+            let mut served_loader = data_loader_with_workers(&served_tokenizer).with_schema(data_loader.schema.clone());
+            served_loader.label_map = data_loader.label_map.clone();
+            Evaluator::new(&served_model.to_string_lossy(), &served_loader)?.compute_report(gate_dataset)
+        });
+        match report {
+            Ok(report) => Some(report),
+            Err(e) => {
+                LogEvent::warn("promotion", format!("Served model could not be evaluated, only absolute thresholds apply: {}", e)).emit();
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let failures = PROMOTION_GATE.check(&candidate, served.as_ref());
+    let mut record = PromotionRecord::new(run_dir, gate_dataset, candidate, served, failures);
+    if record.promoted {
+        if let Some(method) = SCORE_CALIBRATION {
+            let predictions = Evaluator::new(&run.checkpoint_path(None), &data_loader)?.predict_examples(SCORE_CALIBRATION_DATASET)?;
+            let calibrator = ScoreCalibrator::fit(method, &predictions)?;
+            let calibrated: Vec<_> = predictions
+                .iter()
+                .map(|prediction| ExamplePrediction::new(prediction.id.clone(), prediction.label, calibrator.apply(&prediction.prediction.probabilities)))
+                .collect();
+            LogEvent::info(
+                "promotion",
+                format!(
+                    "Calibrated scores on {} ({:?}): expected calibration error {:.4} -> {:.4}",
+                    SCORE_CALIBRATION_DATASET,
+                    method,
+                    expected_calibration_error(&predictions, CALIBRATION_BINS),
+                    expected_calibration_error(&calibrated, CALIBRATION_BINS)
+                ),
+            )
+            .emit();
+            record.calibrator = Some(calibrator);
+        }
+        install(&run.checkpoint_path(None), &run.tokenizer_path(), run.load_label_map()?.as_ref(), record.calibrator.as_ref(), serving_path)?;
+    } else {
+        fs::create_dir_all(serving_path)?;
+    }
+    fs::write(serving_path.join(PROMOTION_RECORD_FILE), serde_json::to_string_pretty(&record)?)?;
+    run.log_metrics(&serde_json::json!({ "stage": "promotion", "promotion": &record }))?;
+
+    let mut lines = vec![format!(
+        "Gate metrics on {}: accuracy {:.2}%, F1-score {:.2}%",
+        gate_dataset,
+        candidate.accuracy * 100.0,
+        candidate.f1_score * 100.0
+    )];
+    if let Some(served) = served {
+        lines.push(format!("Served model: accuracy {:.2}%, F1-score {:.2}%", served.accuracy * 100.0, served.f1_score * 100.0));
+    }
+    if record.promoted {
+        lines.push(format!("Promoted {} to {}", run_dir, serving_dir));
+        LogEvent::info("promotion", lines.join("\n")).metric("promotion", &record).emit();
+    } else {
+        lines.extend(record.failures.iter().map(|failure| format!("  - {}", failure)));
+        lines.push(format!("Not promoted; {} is unchanged", serving_dir));
+        LogEvent::error("promotion", lines.join("\n")).metric("promotion", &record).emit();
+    }
+    Ok(record.promoted)
+}
+
+fn compress_embeddings(model_path: &str, output_path: &str, precision: EmbeddingPrecision) -> Result<(), Box<dyn std::error::Error>> {
+    let mut model: Transformer = Transformer::load(model_path)?;
+    model.embeddings.storage_precision = precision;
+    model.save(output_path)?;
+
+    let size = |path: &str| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    LogEvent::info(
+        "pipeline",
+        format!("Saved {} with {:?} embeddings to {} ({} -> {} bytes)", model_path, precision, output_path, size(model_path), size(output_path)),
+    )
+    .emit();
+    Ok(())
+}
+
+/// Calibrates the run's final model on `QUANTIZATION_CALIBRATION_DATASET` like serving does and
+/// prints the mean absolute FFN output error per layer on `dataset_path` for per-tensor, per-row
+/// and per-column weight scales.
+fn quantization_report(run_dir: &str, dataset_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let tokenizer = load_run_tokenizer(&run)?;
+    let data_loader = run_data_loader(&run, &tokenizer)?;
+    let model: Transformer = Transformer::load(&run.checkpoint_path(None))?;
+
+    let (mut calibration, _) = data_loader.load_dataset(QUANTIZATION_CALIBRATION_DATASET)?;
+    calibration.truncate(QUANTIZATION_CALIBRATION_SAMPLES);
+    if calibration.is_empty() {
+        return Err("Cannot calibrate quantization without examples".into());
+    }
+    let ranges = CalibrationRanges::calibrate(&model, &calibration, DEFAULT_CALIBRATION_PERCENTILE);
+    let (sequences, _) = data_loader.load_dataset(dataset_path)?;
+
+    let mut table = format!("{:<12} Mean absolute FFN error per layer on {}", "Granularity", dataset_path);
+    let mut errors = Vec::new();
+    for granularity in [Granularity::Tensor, Granularity::Row, Granularity::Column] {
+        let layer_errors = ffn_quantization_error(&model, &sequences, granularity, &ranges);
+        table.push_str(&format!("\n{:<12} {:?}", format!("{:?}", granularity), layer_errors));
+        errors.push((format!("{:?}", granularity), layer_errors));
+    }
+    LogEvent::info("pipeline", table).metric("ffn_quantization_error", errors).emit();
+    Ok(())
+}
+
+fn import_onnx_model(onnx_path: &str, vocab_path: &str, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let vocab = WordPieceTokenizer::load_vocab(vocab_path)?;
+    let model = import_onnx_file(onnx_path, vocab)?;
+
+    model.save(output_path)?;
+    LogEvent::info(
+        "pipeline",
+        format!(
+            "Imported {} ({} layers, d_model {}, {} classes) to {}",
+            onnx_path, model.config.num_layers, model.config.d_model, model.config.num_classes, output_path
+        ),
+    )
+    .emit();
+    Ok(())
+}
+
+
+fn golden_check(fixture_path: &str, tolerance: f64) -> bool {
+    match GoldenCase::load(fixture_path) {
+        Ok(case) => {
+            let report = case.verify(tolerance);
+            report.print();
+            report.passed()
+        }
+        Err(e) => {
+            LogEvent::error("pipeline", format!("Failed to load golden fixture {}: {}", fixture_path, e)).emit();
+            false
+        }
+    }
+}
+
+
+/// Sanity check for new layers and backprop changes: a correct model drives the loss
+/// on a single small batch towards zero.
+fn overfit_batch(dataset_path: &str) -> bool {
+    let vocab = match build_vocab(dataset_path) {
+        Ok(vocab) => vocab,
+        Err(e) => {
+            LogEvent::error("pipeline", format!("Failed to build the vocabulary: {}", e)).emit();
+            return false;
+        }
+    };
+    let tokenizer = configured_tokenizer(vocab.clone());
     let data_loader = DataLoader::new(&tokenizer);
+    let model = Transformer::new(default_run_config().model, vocab);
+    let mut trainer = Trainer::new(model, Optimizer::new(OptimizerType::Sgd), &data_loader, 1);
+
+    match trainer.overfit_single_batch(
+        dataset_path,
+        OVERFIT_BATCH_SIZE,
+        OVERFIT_STEPS,
+        OVERFIT_LEARNING_RATE,
+        OVERFIT_TARGET_LOSS,
+    ) {
+        Ok(_) => {
+            LogEvent::info("pipeline", "Overfit check passed.").emit();
+            true
+        }
+        Err(e) => {
+            LogEvent::error("pipeline", format!("Overfit check failed: {}", e)).emit();
+            false
+        }
+    }
+}
+
+
+/// Prints the comparison of several runs.
+///
+/// # Returns
+/// * An error when a run cannot be loaded, or when the runs were trained on different
+///   dataset versions and `allow_dataset_mismatch` is not set.
+fn compare_runs(run_dirs: &[&String], as_json: bool, allow_dataset_mismatch: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if run_dirs.is_empty() {
+        return Err(format!("Usage: compare-runs [--json] [{}] <run_dir>...", ALLOW_DATASET_MISMATCH).into());
+    }
+    let summaries = run_dirs
+        .iter()
+        .map(|dir| RunSummary::load(&ExperimentRun::open(dir)?))
+        .collect::<Result<Vec<RunSummary>, Box<dyn std::error::Error>>>()
+        .map_err(|e| format!("Failed to load runs: {}", e))?;
+
+    let comparison = RunComparison::new(summaries);
+    if let Some(mismatch) = comparison.dataset_mismatch() {
+        if !allow_dataset_mismatch {
+            return Err(format!("{}; pass {} to compare them anyway", mismatch, ALLOW_DATASET_MISMATCH).into());
+        }
+        LogEvent::warn("pipeline", mismatch.to_string()).emit();
+    }
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&comparison.to_json())?);
+    } else {
+        print!("{}", comparison.to_markdown());
+    }
+    Ok(())
+}
+
+
+/// Checks that the training dataset a run pinned in its config is unchanged, before `action`
+/// (e.g. "resume", "evaluate") uses the run. Runs without a pinned version are not checked.
+///
+/// # Returns
+/// * An error describing the change, unless `allow_dataset_mismatch` turns it into a warning.
+fn check_run_dataset(run_config: &RunConfig, action: &str, allow_dataset_mismatch: bool) -> Result<(), String> {
+    let Some(pinned) = run_config.train_dataset.as_ref() else {
+        return Ok(());
+    };
+    match pinned.check_unchanged() {
+        Ok(None) => Ok(()),
+        Ok(Some(change)) if allow_dataset_mismatch => {
+            LogEvent::warn("pipeline", format!("Continuing to {} although {}", action, change)).emit();
+            Ok(())
+        }
+        Ok(Some(change)) => Err(format!("{}; pass {} to {} anyway", change, ALLOW_DATASET_MISMATCH, action)),
+        Err(e) => Err(format!("Failed to hash {}: {}", pinned.path, e)),
+    }
+}
 
 
+fn default_run_config() -> RunConfig {
     let transformer_config = TransformerConfig {
         num_layers: 2,
         d_model: 128,
         num_heads: 8,
         ff_dim: 256,
         num_classes: 2,
-        epsilon: 1e-6,
+        epsilon: LAYER_NORM_EPSILON,
+        bert_embeddings: BERT_EMBEDDINGS,
+        relative_positions: RELATIVE_POSITIONS,
+        attention_projections: ATTENTION_PROJECTIONS,
     };
 
-  
-    train_model(&transformer_config, &vocab, &data_loader);
+    RunConfig::new(transformer_config, 10)
+}
 
-  
-    evaluate_model(&data_loader);
 
-  
-    perform_inference(&tokenizer);
+/// The run `--resume` continues: `run_dir`, or the most recent run under `runs/` when none is given.
+fn resumed_run(run_dir: Option<&String>) -> Result<ExperimentRun, Box<dyn std::error::Error>> {
+    match run_dir {
+        Some(run_dir) => Ok(ExperimentRun::open(run_dir)?),
+        None => ExperimentRun::latest("runs")?.ok_or_else(|| "There is no run under runs/ to resume".into()),
+    }
+}
+
+
+/// Creates a new run directory with the config snapshot and the vocabulary built from the training set.
+fn create_run() -> Result<ExperimentRun, Box<dyn std::error::Error>> {
+    let (mut config, tokenizer, label_map) = prepare_run(TRAIN_DATASET_PATH)?;
+    let run = ExperimentRun::create("runs")?;
+    if AUTO_TUNE_BATCH_SIZE {
+        let data_loader = data_loader_with_workers(&tokenizer).with_schema(input_schema(config.input_template.clone())?).with_label_map(label_map.clone());
+        let (inputs, labels) = data_loader.load_dataset(TRAIN_DATASET_PATH)?;
+        let tuning = tune_batch_size(&config, &data_loader, &inputs, &labels)?;
+        LogEvent::info("pipeline", format!("Tuned batch size:\n{}", tuning.summary())).metric("batch_size", tuning.batch_size).emit();
+        config.batch_size = tuning.batch_size;
+    }
+    config.train_dataset = Some(DatasetVersion::of(TRAIN_DATASET_PATH)?);
+    run.save_config(&config)?;
+    run.save_tokenizer(&tokenizer)?;
+    run.save_label_map(&label_map)?;
+    Ok(run)
+}
+
+
+/// The config, tokenizer and label map of a new run on a training set, shared by `create_run`
+/// and `--dry-run`.
+fn prepare_run(training_dataset_path: &str) -> Result<(RunConfig, Tokenizer, LabelMap), Box<dyn std::error::Error>> {
+    // Surface a bad pattern as an error, which `configured_token_rules` would panic on.
+    TokenRules::from_patterns(TOKEN_RULES).map_err(|e| format!("Invalid pattern in TOKEN_RULES: {}", e))?;
+    let mut tokenizer = match TOKENIZER_PATH {
+        Some(path) => Tokenizer::load(path).map_err(|e| format!("Failed to load TOKENIZER_PATH {}: {}", path, e))?,
+        None => configured_tokenizer(build_vocab(training_dataset_path)?),
+    };
+    for &(task, token) in TASK_PREFIXES {
+        tokenizer.register_task(task, token).map_err(|e| format!("Invalid entry in TASK_PREFIXES: {}", e))?;
+    }
+    let mut config = default_run_config();
+    config.max_seq_length = tokenizer.max_seq_length;
+    config.input_template = configured_input_template()?;
+    if let Some(template) = &config.input_template {
+        // Markers such as `[SEP]` in the template stay single tokens.
+        for word in template.literal_words().into_iter().filter(|word| [CLS_TOKEN, SEP_TOKEN, MASK_TOKEN].contains(word)) {
+            tokenizer.register_special_token(word)?;
+        }
+    }
+    let label_map = fit_classes_to_labels(&mut config, &tokenizer, training_dataset_path)?;
+    LogEvent::info("pipeline", format!("Classes: {}", label_map.names().join(", "))).emit();
+    Ok((config, tokenizer, label_map))
+}
+
 
-    println!("\nPipeline Execution Completed Successfully!");
+/// Builds the label map of a training set and sizes the model to its number of classes, so
+/// the run's label map and class count always agree. Class ids follow the labels, which may
+/// be names or sparse numbers.
+///
+/// # Returns
+/// * The label map, or an error for a training set with fewer than two distinct labels.
+fn fit_classes_to_labels(config: &mut RunConfig, tokenizer: &Tokenizer, dataset_path: &str) -> Result<LabelMap, Box<dyn std::error::Error>> {
+    let label_map = DataLoader::new(tokenizer).with_schema(input_schema(config.input_template.clone())?).collect_label_map(dataset_path)?;
+    if label_map.len() < 2 {
+        return Err(format!("{} needs at least two distinct labels to train a classifier, found {:?}", dataset_path, label_map.names()).into());
+    }
+    config.model.num_classes = label_map.len();
+    Ok(label_map)
 }
 
 
-fn build_vocab(training_dataset_path: &str) -> HashMap<String, usize> {
+/// Probes training steps of a freshly initialised model on the loaded training examples to
+/// find the largest batch size within the `BATCH_SIZE_TUNER_*` budget.
+///
+/// # Arguments
+/// * `config` - Run config whose classes were already fitted to the labels (`fit_classes_to_labels`).
+/// * `inputs`, `labels` - The training set, loaded by `data_loader`.
+fn tune_batch_size(config: &RunConfig, data_loader: &DataLoader, inputs: &[Vec<usize>], labels: &[usize]) -> Result<BatchSizeTuning, Box<dyn std::error::Error>> {
+    let mut model = Transformer::new(config.model.clone(), data_loader.tokenizer.vocab.clone());
+    model.parallelism = training_parallelism();
 
-    let file_content = fs::read_to_string(training_dataset_path)
-        .expect("Failed to read the training dataset file");
-    let data: Value = serde_json::from_str(&file_content)
-        .expect("Failed to parse the training dataset as JSON");
+    let max_step_memory = BATCH_SIZE_TUNER_MAX_STEP_MB.map(|megabytes| megabytes * 1024 * 1024);
+    let tuner = BatchSizeTuner::new(Duration::from_millis(BATCH_SIZE_TUNER_MAX_STEP_MS), max_step_memory, BATCH_SIZE_TUNER_MAX)
+        .with_peak_memory_reset(BATCH_SIZE_TUNER_RESET_PEAK_MEMORY);
+    tuner.tune(&model, data_loader, inputs, labels)
+}
 
-    let mut dataset = Vec::new();
-    if let Some(array) = data.as_array() {
-        for item in array {
-            if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                dataset.push(text.to_string());
-            }
+
+/// Builds the vocabulary of a training set, reading its texts like the data loader does.
+///
+/// # Returns
+/// * The vocabulary, or an error for a dataset the data loader would reject, e.g. one with a
+///   record missing a field of the input template.
+fn build_vocab(training_dataset_path: &str) -> Result<HashMap<String, usize>, Box<dyn std::error::Error>> {
+    // Reading records needs no vocabulary beyond the [PAD]/[UNK] every tokenizer requires.
+    let reader = Tokenizer::new(HashMap::from([(PAD_TOKEN.to_string(), 0), (UNK_TOKEN.to_string(), 1)]), MAX_SEQ_LENGTH);
+    let dataset = DataLoader::new(&reader).with_schema(input_schema(configured_input_template()?)?).load_texts(training_dataset_path)?;
+
+
+    let special_tokens = &[PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN];
+
+
+    let options = VocabOptions {
+        max_vocab_size: Some(MAX_VOCAB_SIZE),
+        min_freq: MIN_TOKEN_FREQUENCY,
+        normalizer: TEXT_NORMALIZER,
+        ngrams: NGRAMS,
+        token_rules: configured_token_rules(),
+        phrases: PHRASES,
+    };
+    let (mut vocab, stats) = Tokenizer::build_vocab_with_stats(&dataset, special_tokens, &options);
+    LogEvent::info("pipeline", stats.summary())
+        .metric("vocab_tokens", stats.vocab_tokens)
+        .metric("coverage", stats.coverage())
+        .metric("oov_rate", stats.oov_rate())
+        .emit();
+    if BYTE_FALLBACK {
+        Tokenizer::add_byte_tokens(&mut vocab);
+    }
+    Ok(vocab)
+}
+
+
+/// Fuzzes the tokenizer built from a dataset, and variants of it with very short sequences
+/// and each truncation strategy.
+///
+/// # Arguments
+/// * `args` - Optional `[iterations] [seed] [dataset]`; the dataset defaults to the training set.
+///
+/// # Returns
+/// * Whether every input passed, or an error for an argument that is not a number.
+fn fuzz_configured_tokenizer(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let iterations = args.first().map(|n| n.parse().map_err(|_| format!("iterations must be a number, got '{}'", n))).transpose()?.unwrap_or(FUZZ_ITERATIONS);
+    let seed = args.get(1).map(|n| n.parse().map_err(|_| format!("seed must be a number, got '{}'", n))).transpose()?.unwrap_or(0);
+    let dataset_path = args.get(2).map(String::as_str).unwrap_or(TRAIN_DATASET_PATH);
+    let tokenizer = configured_tokenizer(build_vocab(dataset_path)?);
+    let variants = [
+        tokenizer.clone(),
+        Tokenizer { max_seq_length: 0, ..tokenizer.clone() },
+        Tokenizer { max_seq_length: 3, truncation: Truncation::Tail, ..tokenizer.clone() },
+        Tokenizer { max_seq_length: 5, truncation: Truncation::HeadAndTail { head_tokens: 2, separator: true }, ..tokenizer.clone() },
+    ];
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut passed = true;
+    for variant in &variants {
+        let failures = fuzz_tokenizer(variant, iterations, FUZZ_MAX_CHARS, &mut rng);
+        LogEvent::info(
+            "pipeline",
+            format!("max_seq_length {}, {:?}: {} of {} inputs failed", variant.max_seq_length, variant.truncation, failures.len(), iterations),
+        )
+        .emit();
+        for (text, error) in failures.iter().take(5) {
+            LogEvent::info("pipeline", format!("  {:?}: {}", text, error)).emit();
         }
+        passed &= failures.is_empty();
     }
+    Ok(passed)
+}
 
 
-    let special_tokens = &[PAD_TOKEN, UNK_TOKEN];
+fn build_streaming_vocab(corpus_path: &str, output_path: &str) -> Result<(), std::io::Error> {
+    let mut builder = StreamingVocabBuilder::new(TEXT_NORMALIZER, VOCAB_BUILDER_MAX_WORDS).with_ngrams(NGRAMS).with_token_rules(configured_token_rules());
+    builder.add_file(corpus_path)?;
+    LogEvent::info(
+        "pipeline",
+        format!(
+            "Read {} lines from {} ({} prunings, counts exact to within {})",
+            builder.texts_read(), corpus_path, builder.prunings(), builder.count_error_bound()
+        ),
+    )
+    .emit();
+
+    let special_tokens = &[PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN];
+    let (mut vocab, _) = builder.finish(special_tokens, Some(MAX_VOCAB_SIZE));
+    if BYTE_FALLBACK {
+        Tokenizer::add_byte_tokens(&mut vocab);
+    }
+    let tokenizer = configured_tokenizer(vocab);
+    tokenizer.save(output_path)?;
+    LogEvent::info("pipeline", format!("Saved {} tokens to {}", tokenizer.vocab.len(), output_path)).emit();
+    Ok(())
+}
 
 
-    Tokenizer::build_vocab(&dataset, special_tokens, Some(100))
+fn train_unigram_tokenizer(corpus_path: &str, vocab_size: usize, output_path: &str) -> Result<(), std::io::Error> {
+    let texts: Vec<String> = std::fs::read_to_string(corpus_path)?.lines().map(str::to_string).collect();
+    let model = UnigramTrainer::new(vocab_size).train(&texts);
+    let tokenizer = Tokenizer::from_unigram(model, &[PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN], MAX_SEQ_LENGTH);
+    tokenizer.save(output_path)?;
+    LogEvent::info("pipeline", format!("Trained {} pieces on {} lines of {}, saved to {}", tokenizer.vocab.len(), texts.len(), corpus_path, output_path)).emit();
+    Ok(())
 }
 
 
-fn train_model(config: &TransformerConfig, vocab: &HashMap<String, usize>, data_loader: &DataLoader) {
-    println!("\nTraining the Transformer Model...");
+fn import_tokenizer(source_path: &str, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // HuggingFace tokenizers are JSON files; anything else is read as a one-token-per-line vocab.txt.
+    let tokenizer = if source_path.ends_with(".json") {
+        Tokenizer::from_huggingface(source_path, MAX_SEQ_LENGTH)?
+    } else {
+        Tokenizer::from_wordpiece_vocab(source_path, MAX_SEQ_LENGTH)?
+    };
+    tokenizer.save(output_path)?;
+    LogEvent::info("pipeline", format!("Saved {} tokens from {} to {}", tokenizer.vocab.len(), source_path, output_path)).emit();
+    Ok(())
+}
 
- 
-    let transformer = Transformer::new(config.clone(), vocab.clone());
-    let optimizer = Optimizer::new(OptimizerType::SGD);
-    let mut trainer = Trainer::new(transformer, optimizer, data_loader, 10);
 
-   
-    trainer.train("src/train_dataset.json", "src/trained_model.json");
-    println!("Model Training Completed.\n");
+fn analyze_dataset(dataset_path: &str) {
+    let vocab = HashMap::from([(PAD_TOKEN.to_string(), 0), (UNK_TOKEN.to_string(), 1)]);
+    let tokenizer = Tokenizer::new(vocab, MAX_SEQ_LENGTH);
+    let data_loader = DataLoader::new(&tokenizer);
+
+    match data_loader.load_texts(dataset_path) {
+        Ok(texts) => DatasetAnalysis::analyze(&texts, DEFAULT_LENGTH_PERCENTILE, DEFAULT_VOCAB_COVERAGE).print_report(),
+        Err(e) => LogEvent::error("pipeline", format!("Failed to load dataset for analysis: {}", e)).emit(),
+    }
 }
 
 
-fn evaluate_model(data_loader: &DataLoader) {
-    println!("\nEvaluating the Transformer Model...");
+fn configured_token_rules() -> TokenRules {
+    TokenRules::from_patterns(TOKEN_RULES).expect("Invalid pattern in TOKEN_RULES")
+}
+
+fn configured_tokenizer(vocab: HashMap<String, usize>) -> Tokenizer {
+    Tokenizer::configured(vocab, MAX_SEQ_LENGTH, configured_token_rules())
+}
+
+fn training_parallelism() -> Parallelism {
+    let reduction = if DETERMINISTIC_REDUCTION { Reduction::Deterministic } else { Reduction::Unordered };
+    Parallelism::new(resolve_thread_count(TRAINING_THREADS), reduction).with_cores(TRAINING_CORES)
+}
+
+fn configured_input_template() -> Result<Option<InputTemplate>, Box<dyn std::error::Error>> {
+    INPUT_TEMPLATE.map(InputTemplate::parse).transpose().map_err(|e| format!("Invalid INPUT_TEMPLATE: {}", e).into())
+}
+
+/// The schema of `DATA_SCHEMA_PATH`, or the default one, reading the fields of `input_template`
+/// when there is one and `DOMAIN_FIELD`, `EVALUATION_SLICE_FIELDS` and the `FAIRNESS_GROUP` field
+/// as metadata.
+fn input_schema(input_template: Option<InputTemplate>) -> Result<DataSchema, Box<dyn std::error::Error>> {
+    let mut schema = match DATA_SCHEMA_PATH {
+        Some(path) => DataSchema::from_config_file(path)?,
+        None => DataSchema::default(),
+    };
+    schema.input_template = input_template.or(schema.input_template);
+    let fairness_field = FAIRNESS_GROUP.map(|(field, _)| field);
+    for field in DOMAIN_FIELD.iter().chain(EVALUATION_SLICE_FIELDS).chain(fairness_field.iter()) {
+        if !schema.metadata_fields.iter().any(|name| name == field) {
+            schema.metadata_fields.push(field.to_string());
+        }
+    }
+    Ok(schema)
+}
+
+/// Data loader for the datasets of a run, reading their labels with the run's label map and
+/// rendering records with the run's input template.
+fn run_data_loader<'a>(run: &ExperimentRun, tokenizer: &'a Tokenizer) -> Result<DataLoader<'a>, Box<dyn std::error::Error>> {
+    let data_loader = data_loader_with_workers(tokenizer).with_schema(input_schema(run.load_config()?.input_template)?);
+    Ok(match run.load_label_map()? {
+        Some(label_map) => data_loader.with_label_map(label_map),
+        None => data_loader,
+    })
+}
+
+fn data_loader_with_workers(tokenizer: &Tokenizer) -> DataLoader<'_> {
+    DataLoader::new(tokenizer)
+        .with_workers(resolve_thread_count(DATA_LOADER_WORKERS))
+        .with_worker_cores(DATA_LOADER_CORES)
+}
+
+/// Rejects augmentation combined with `DOMAIN_FIELD`, which `Trainer::train` does not support,
+/// before a run is created for it.
+fn check_augmentation_config() -> Result<(), String> {
+    let augmented = PARAPHRASE_COMMAND.is_some() || TEXT_NOISE_AUGMENTATION.is_some();
+    match DOMAIN_FIELD {
+        Some(domain_field) if augmented => Err(format!(
+            "domain-adversarial training on {} does not support augmentation; unset PARAPHRASE_COMMAND and TEXT_NOISE_AUGMENTATION or DOMAIN_FIELD",
+            domain_field
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the core lists in `config.rs` and prints the thread settings in use.
+fn check_thread_config() -> Result<(), std::io::Error> {
+    check_cores(TRAINING_CORES)?;
+    check_cores(DATA_LOADER_CORES)?;
+    let pinning = |cores: &[usize]| if cores.is_empty() { "unpinned".to_string() } else { format!("pinned to cores {:?}", cores) };
+    LogEvent::info(
+        "pipeline",
+        format!(
+            "Threads: {} training ({}), {} data loader ({})",
+            resolve_thread_count(TRAINING_THREADS),
+            pinning(TRAINING_CORES),
+            resolve_thread_count(DATA_LOADER_WORKERS),
+            pinning(DATA_LOADER_CORES)
+        ),
+    )
+    .metric("training_threads", resolve_thread_count(TRAINING_THREADS))
+    .metric("data_loader_workers", resolve_thread_count(DATA_LOADER_WORKERS))
+    .emit();
+    Ok(())
+}
+
+fn training_summation() -> Summation {
+    if COMPENSATED_SUMMATION { Summation::Compensated } else { Summation::Naive }
+}
+
+/// How `train_model` ended.
+enum TrainingOutcome {
+    Completed,
+    /// A shutdown signal stopped training after saving an interrupt checkpoint; the run
+    /// continues with `--resume`.
+    Interrupted,
+}
+
+/// Trains the run's model, resuming from its interrupt checkpoint or latest epoch checkpoint.
+///
+/// # Returns
+/// * Whether training completed or was interrupted, or an error for an invalid
+///   configuration or a failed training step.
+fn train_model(
+    config: &RunConfig,
+    vocab: &HashMap<String, usize>,
+    data_loader: &DataLoader,
+    run: &ExperimentRun,
+    max_duration: Option<Duration>,
+) -> Result<TrainingOutcome, Box<dyn std::error::Error>> {
+    LogEvent::info("pipeline", "\nTraining the Transformer Model...").emit();
 
  
-    let model_path = "src/trained_model.json";
-    match Evaluator::new(model_path, data_loader) {
-        Ok(evaluator) => {
-       
-            let test_dataset_path = "src/test_dataset.json";
-            if let Err(e) = evaluator.evaluate(test_dataset_path) {
-                eprintln!("Evaluation error: {}", e);
-            } else {
-                println!("Model Evaluation Completed.\n");
+    let final_path = run.checkpoint_path(None);
+    let interrupted_path = interrupted_checkpoint_path(&final_path);
+    let optimizer = Optimizer::new(OptimizerType::Sgd);
+    let shutdown = ShutdownSignal::install()?;
+
+    let resume_interrupted = Path::new(&interrupted_path).exists();
+    let latest_checkpoint = if resume_interrupted { None } else { run.latest_checkpoint() };
+    let (mut transformer, completed_epochs) = if resume_interrupted {
+        LogEvent::info("pipeline", format!("Resuming from interrupted checkpoint {}", interrupted_path)).emit();
+        (Transformer::load(&interrupted_path)?, 0)
+    } else {
+        match &latest_checkpoint {
+            Some((epoch, checkpoint_path)) => {
+                LogEvent::info("pipeline", format!("Resuming from {} (epoch {})", checkpoint_path, epoch)).step(*epoch).emit();
+                (Transformer::load(checkpoint_path)?, *epoch)
             }
+            None => (new_model(config, vocab, data_loader), 0),
         }
-        Err(e) => eprintln!("Failed to load model for evaluation: {}", e),
+    };
+    transformer.train_dataset = config.train_dataset.clone();
+    let mut trainer = Trainer::new(transformer, optimizer, data_loader, config.epochs)
+        .resume_from_epoch(completed_epochs)
+        .with_run(run.clone())
+        .with_shutdown_signal(shutdown)
+        .with_parallelism(training_parallelism())
+        .with_summation(training_summation())
+        .with_frozen_embeddings(FREEZE_EMBEDDINGS)
+        .with_embedding_dropout(EMBEDDING_DROPOUT)
+        .map_err(|e| format!("Invalid training configuration: {}", e))?;
+    if let Some(command) = PARAPHRASE_COMMAND {
+        let paraphraser = CommandParaphraser::new(command).map_err(|e| format!("Invalid PARAPHRASE_COMMAND: {}", e))?;
+        let paraphrases = ParaphraseAugmentation::new(paraphraser, PARAPHRASE_COPIES).with_cache_file(PARAPHRASE_CACHE_PATH);
+        trainer = trainer.with_augmentation(paraphrases);
+    }
+    if let Some(augmentation) = TEXT_NOISE_AUGMENTATION {
+        trainer = trainer.with_augmentation(augmentation);
+    }
+    if let Some(word_dropout) = config.word_dropout {
+        trainer = trainer.with_word_dropout(word_dropout);
+    }
+    if let Some(seed) = config.seed {
+        trainer = trainer.with_seed(seed);
+    }
+    if let Some(domain_field) = DOMAIN_FIELD {
+        trainer = trainer.with_domain_adversary(DomainAdversary::new(domain_field, DOMAIN_ADVERSARIAL_WEIGHT));
+    }
+    if let Some(decay) = EMA_DECAY {
+        trainer = trainer.with_ema(decay);
+    }
+    if let Some(start_epoch) = SWA_START_EPOCH {
+        trainer = trainer.with_swa(start_epoch);
+    }
+    // Restored last, so the saved seed, optimizer, domain classifier and EMA/SWA weights replace the configured ones.
+    if resume_interrupted {
+        trainer = trainer.resume_from_state(&training_state_path(&final_path))?;
+    } else if let Some((_, checkpoint_path)) = &latest_checkpoint {
+        trainer = trainer.resume_shadows_from(checkpoint_path)?;
+    }
+    if let Some(max_duration) = max_duration {
+        trainer = trainer.with_max_duration(max_duration);
+    }
+    if let Some(probe_path) = PROBE_SET_PATH {
+        let probe_set = ProbeSet::load(probe_path, data_loader)?;
+        LogEvent::info("pipeline", format!("Tracking {} probe examples from {}", probe_set.examples.len(), probe_path)).emit();
+        trainer = trainer.with_probe_set(probe_set);
+    }
+
+    // Only a new model starts with the contrastive stage; resumed runs are past it.
+    if let Some(contrastive) = CONTRASTIVE_PRETRAINING.filter(|_| completed_epochs == 0 && !resume_interrupted) {
+        LogEvent::info("pipeline", "Contrastive pretraining of the encoder...").emit();
+        trainer
+            .pretrain_contrastive(TRAIN_DATASET_PATH, contrastive.epochs, contrastive.min_per_class, contrastive.temperature)
+            .map_err(|e| format!("Contrastive pretraining failed: {}", e))?;
+    }
+
+    trainer.train(TRAIN_DATASET_PATH, &final_path)?;
+    Ok(if trainer.interrupted { TrainingOutcome::Interrupted } else { TrainingOutcome::Completed })
+}
+
+
+/// A new model for the run, with its token embeddings scaled by their frequency in the
+/// training set when `EMBEDDING_FREQUENCY_SCALING` is set.
+fn new_model(config: &RunConfig, vocab: &HashMap<String, usize>, data_loader: &DataLoader) -> Transformer {
+    let mut transformer = Transformer::new(config.model.clone(), vocab.clone());
+    if let Some(min_scale) = EMBEDDING_FREQUENCY_SCALING {
+        let scaled = training_token_counts(data_loader, TRAIN_DATASET_PATH)
+            .and_then(|counts| transformer.embeddings.scale_by_frequency(&counts, min_scale));
+        if let Err(e) = scaled {
+            LogEvent::error("pipeline", format!("Failed to scale the embeddings by frequency: {}", e)).emit();
+            std::process::exit(1);
+        }
+    }
+    transformer
+}
+
+
+/// How often the tokenizer produces every vocabulary token on a dataset, leaving out the
+/// special and task prefix tokens.
+fn training_token_counts(data_loader: &DataLoader, dataset_path: &str) -> Result<HashMap<String, usize>, Box<dyn std::error::Error>> {
+    let tokenizer = data_loader.tokenizer;
+    let tokens: HashMap<usize, &String> = tokenizer.vocab.iter().map(|(token, &id)| (id, token)).collect();
+    let skipped: Vec<&str> = [PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, MASK_TOKEN].into_iter().chain(TASK_PREFIXES.iter().map(|&(_, token)| token)).collect();
+    let mut counts = HashMap::new();
+    for text in data_loader.load_texts(dataset_path)? {
+        for id in tokenizer.tokenize(&text) {
+            if let Some(token) = tokens.get(&id).filter(|token| !skipped.contains(&token.as_str())) {
+                *counts.entry(token.to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+
+/// Adapts a checkpoint to an unlabeled corpus with MLM (and sentence-order prediction when
+/// configured), then fine-tunes it in a new run directory like `train` does. The adapted
+/// encoder is saved as `<run_dir>/pretrained.json`.
+fn domain_adaptive_pretraining(corpus_path: &str, checkpoint_path: &str) -> Result<ExperimentRun, Box<dyn std::error::Error>> {
+    LogEvent::info("pipeline", format!("Domain-adaptive pretraining on {} from {}...", corpus_path, checkpoint_path)).emit();
+
+    let mut transformer = Transformer::load(checkpoint_path).map_err(|e| format!("Failed to load checkpoint {}: {}", checkpoint_path, e))?;
+    transformer.embeddings.add_token(MASK_TOKEN);
+
+    // The tokenizer has to use the checkpoint's vocabulary so token ids line up.
+    let tokenizer = configured_tokenizer(transformer.embeddings.vocab().clone());
+    let mut config = default_run_config();
+    config.model = transformer.config.clone();
+    config.max_seq_length = tokenizer.max_seq_length;
+    config.train_dataset = Some(DatasetVersion::of(TRAIN_DATASET_PATH)?);
+    transformer.train_dataset = config.train_dataset.clone();
+    let run = ExperimentRun::create("runs")?;
+    run.save_config(&config)?;
+    run.save_tokenizer(&tokenizer)?;
+    LogEvent::info("pipeline", format!("Run directory: {}", run.dir.display())).metric("run_dir", &run.dir).emit();
+
+    let data_loader = data_loader_with_workers(&tokenizer);
+    let optimizer = Optimizer::new(OptimizerType::Sgd);
+    let mut trainer = Trainer::new(transformer, optimizer, &data_loader, config.epochs)
+        .with_run(run.clone())
+        .with_tied_mlm_head(TIE_MLM_OUTPUT_WEIGHTS);
+    if let Some(seed) = config.seed {
+        trainer = trainer.with_seed(seed);
+    }
+
+    trainer.pretrain_mlm(corpus_path, 3)?;
+    if let Some(epochs) = SENTENCE_ORDER_PRETRAINING_EPOCHS {
+        trainer.pretrain_sentence_order(corpus_path, epochs).map_err(|e| format!("Sentence-order pretraining failed: {}", e))?;
+    }
+    trainer.model.save(&run.dir.join("pretrained.json").to_string_lossy())?;
+
+    LogEvent::info("pipeline", "Fine-tuning the adapted encoder...").emit();
+    trainer.train(TRAIN_DATASET_PATH, &run.checkpoint_path(None))?;
+    Ok(run)
+}
+
+
+/// Scores the run's final model on a dataset, logs the metrics to the run and saves its
+/// predictions, then prints the accuracy-vs-coverage curve of an abstain threshold, a slice
+/// report for every field of `EVALUATION_SLICE_FIELDS`, the `FAIRNESS_GROUP` report and the
+/// `EVALUATION_NOISE_PROBABILITY` noise report.
+fn evaluate_model(data_loader: &DataLoader, run: &ExperimentRun, dataset_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    LogEvent::info("pipeline", "\nEvaluating the Transformer Model...").emit();
+
+    let model_path = run.checkpoint_path(None);
+    let evaluator = Evaluator::new(&model_path, data_loader).map_err(|e| format!("Failed to load model for evaluation: {}", e))?;
+    let report = evaluator.evaluate(dataset_path)?;
+    run.log_metrics(&serde_json::json!({
+        "stage": "test",
+        "dataset": dataset_path,
+        "accuracy": report.accuracy,
+        "precision": report.precision,
+        "recall": report.recall,
+        "f1_score": report.f1_score,
+    }))?;
+    run.save_predictions(&evaluator.predict_examples(dataset_path)?)?;
+    // The EMA and SWA weights are scored next to the raw ones, so either can be shipped.
+    if EMA_DECAY.is_some() || SWA_START_EPOCH.is_some() {
+        Evaluator::compare_variants(&model_path, data_loader, dataset_path)?;
+    }
+    evaluator.coverage_curve(dataset_path, &DEFAULT_ABSTAIN_THRESHOLDS)?;
+    for field in EVALUATION_SLICE_FIELDS {
+        evaluator.slice_report(dataset_path, field)?;
+    }
+    if let Some((field, positive_label)) = FAIRNESS_GROUP {
+        let positive_class = match &data_loader.label_map {
+            Some(label_map) => label_map.id(positive_label).ok_or_else(|| format!("FAIRNESS_GROUP names an unknown label {}", positive_label))?,
+            None => positive_label.parse().map_err(|_| format!("FAIRNESS_GROUP needs a class id without a label map, got {}", positive_label))?,
+        };
+        evaluator.fairness_report(dataset_path, field, positive_class)?;
+    }
+    if let Some(char_probability) = EVALUATION_NOISE_PROBABILITY {
+        evaluator.noise_report(dataset_path, char_probability)?;
+    }
+    LogEvent::info("pipeline", "Model Evaluation Completed.\n").emit();
+    Ok(())
+}
+
+/// Evaluates the final model of a run on a dataset (see `evaluate_model`), reading it like
+/// the run's training data, and writes the misclassified examples to `misclassified_path`.
+fn evaluate_run(run_dir: &str, dataset_path: &str, misclassified_path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let run = ExperimentRun::open(run_dir)?;
+    let tokenizer = load_run_tokenizer(&run)?;
+    let mut data_loader = run_data_loader(&run, &tokenizer)?;
+    if let Some(window) = run.load_config()?.sliding_window {
+        data_loader = data_loader.with_sliding_window(window);
+    }
+    evaluate_model(&data_loader, &run, dataset_path)?;
+    if let Some(output_path) = misclassified_path {
+        let count = Evaluator::new(&run.checkpoint_path(None), &data_loader)?.export_misclassified(dataset_path, output_path)?;
+        LogEvent::info("evaluator", format!("Wrote {} misclassified examples to {}", count, output_path)).metric("misclassified", count).emit();
     }
+    Ok(())
 }
 
 
-fn perform_inference(tokenizer: &Tokenizer) {
-    println!("\nPerforming Inference...");
+fn perform_inference(tokenizer_path: &str, model_path: &str, label_map: Option<LabelMap>) {
+    LogEvent::info("pipeline", "\nPerforming Inference...").emit();
 
 
-    let model_path = "src/trained_model.json";
-    match Inference::new(model_path, tokenizer) {
+    let inference = Inference::new(model_path, tokenizer_path).and_then(|inference| inference.with_task(ACTIVE_TASK)).and_then(|inference| match label_map {
+        Some(label_map) => inference.with_label_map(label_map),
+        None => Ok(inference),
+    });
+    match inference {
         Ok(inference) => {
+            let inference = inference.with_overflow_policy(INFERENCE_OVERFLOW_POLICY).with_top_k(PREDICTION_TOP_K);
+            match inference.warm_up(INFERENCE_WARMUP_PASSES, BATCH_SIZE) {
+                Ok(durations) => {
+                    if let (Some(first), Some(last)) = (durations.first(), durations.last()) {
+                        LogEvent::info("inference", format!("Warm-up: {} passes, first {:?}, last {:?}", durations.len(), first, last))
+                            .metric("warmup_first_ms", first.as_secs_f64() * 1000.0)
+                            .metric("warmup_last_ms", last.as_secs_f64() * 1000.0)
+                            .emit();
+                    }
+                }
+                Err(e) => LogEvent::warn("inference", format!("Warm-up failed: {}", e)).emit(),
+            }
             let input_text = "Exclusive deal: Buy 1 Get 1 Free!";
             match inference.predict(input_text) {
-                Ok((predicted_class, probabilities)) => {
-                    println!("Input: {}", input_text);
-                    println!("Predicted Class: {}", predicted_class);
-                    println!("Probabilities: {:?}", probabilities);
+                Ok(prediction) => {
+                    let overflow = prediction.overflow;
+                    let mut lines = vec![
+                        format!("Input: {}", input_text),
+                        format!("Predicted Class: {}", inference.label_name(prediction.label_id)),
+                        format!("Probabilities: {:?}", prediction.probabilities),
+                    ];
+                    if overflow.truncated() {
+                        lines.push(format!("Truncated: {} of {} tokens dropped", overflow.dropped_tokens, overflow.input_tokens));
+                    } else if overflow.chunked() {
+                        lines.push(format!("Chunked: {} tokens in {} windows", overflow.input_tokens, overflow.chunks));
+                    }
+                    LogEvent::info("inference", lines.join("\n"))
+                        .metric("input", input_text)
+                        .metric("prediction", &prediction)
+                        .emit();
                 }
-                Err(e) => eprintln!("Error during inference: {}", e),
+                Err(e) => LogEvent::error("inference", format!("Error during inference: {}", e)).emit(),
             }
         }
-        Err(e) => eprintln!("Failed to load inference model: {}", e),
+        Err(e) => LogEvent::error("inference", format!("Failed to load inference model: {}", e)).emit(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_handler::synthetic::{SyntheticConfig, SyntheticDataset};
+    use crate::data_handler::sliding_window::{SlidingWindow, WindowWeighting};
+    use crate::test_utils::fixtures::{temp_path, tiny_config, tiny_vocab};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// A run under `root` with a two-class model over `words` and no label map.
+    fn tiny_run(root: &str, words: &[&str]) -> ExperimentRun {
+        let run = ExperimentRun::create(root).unwrap();
+        let vocab = tiny_vocab(words);
+        run.save_config(&RunConfig::new(tiny_config(2), 1)).unwrap();
+        run.save_tokenizer(&Tokenizer::new(vocab.clone(), 16)).unwrap();
+        Transformer::<f64>::new(tiny_config(2), vocab).save(&run.checkpoint_path(None)).unwrap();
+        run
+    }
+
+    #[test]
+    fn test_build_vocab_reads_a_small_dataset() {
+        let dataset_path = &temp_path("main_build_vocab_dataset.json");
+        let synthetic_config = SyntheticConfig { num_examples: 8, vocab_size: 12, ..SyntheticConfig::default() };
+        let dataset = SyntheticDataset::generate(&synthetic_config, &mut StdRng::seed_from_u64(0)).unwrap();
+        dataset.save_json(dataset_path).unwrap();
+
+        let vocab = build_vocab(dataset_path);
+        std::fs::remove_file(dataset_path).unwrap();
+        let vocab = vocab.unwrap();
+        for token in [PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN] {
+            assert!(vocab.contains_key(token), "{} missing", token);
+        }
+        let first_word = dataset.texts[0].split_whitespace().next().unwrap();
+        assert!(vocab.contains_key(first_word), "{} missing", first_word);
+    }
+
+    #[test]
+    fn test_dry_run_uses_the_run_setup() {
+        let dataset_path = &temp_path("main_dry_run_dataset.json");
+        let synthetic_config = SyntheticConfig { num_examples: 8, vocab_size: 12, ..SyntheticConfig::default() };
+        SyntheticDataset::generate(&synthetic_config, &mut StdRng::seed_from_u64(0)).unwrap().save_json(dataset_path).unwrap();
+
+        let report = dry_run(dataset_path, 8, || {
+            let (config, tokenizer, label_map) = prepare_run(dataset_path)?;
+            assert_eq!(tokenizer.vocab, build_vocab(dataset_path)?);
+            let schema = input_schema(config.input_template.clone())?;
+            Ok(DryRunSetup { config, tokenizer, label_map: Some(label_map), schema })
+        });
+        std::fs::remove_file(dataset_path).unwrap();
+        assert!(report.passed(), "{:?}", report.steps);
+    }
+
+    #[test]
+    fn test_build_vocab_reports_a_missing_dataset() {
+        assert!(build_vocab(&temp_path("main_missing_dataset.json")).is_err());
+    }
+
+    #[test]
+    fn test_add_run_class_saves_the_new_class() {
+        let root = &temp_path("main_add_class_runs");
+        let run = tiny_run(root, &["refund", "invoice"]);
+        run.save_label_map(&LabelMap::from_names(&["ham", "spam"]).unwrap()).unwrap();
+
+        let run_dir = run.dir.to_str().unwrap();
+        add_run_class(run_dir, "billing", &["refund invoice", "invoice"]).unwrap();
+        let duplicate = add_run_class(run_dir, "billing", &["refund"]);
+        let label_map = run.load_label_map().unwrap().unwrap();
+        let config = run.load_config().unwrap();
+        let model: Transformer = Transformer::load(&run.checkpoint_path(None)).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+
+        assert!(duplicate.is_err());
+        assert_eq!(label_map.names(), ["ham", "spam", "billing"]);
+        assert_eq!(config.model.num_classes, 3);
+        assert_eq!(model.classification_head.num_classes(), 3);
+    }
+
+    #[test]
+    fn test_evaluate_run_logs_metrics_and_misclassified_examples() {
+        let root = &temp_path("main_evaluate_runs");
+        let dataset_path = &temp_path("main_evaluate_dataset.json");
+        let misclassified_path = &temp_path("main_evaluate_misclassified.json");
+        let synthetic_config = SyntheticConfig { num_examples: 8, vocab_size: 4, ..SyntheticConfig::default() };
+        SyntheticDataset::generate(&synthetic_config, &mut StdRng::seed_from_u64(0)).unwrap().save_json(dataset_path).unwrap();
+        let run = tiny_run(root, &["w0", "w1", "w2", "w3"]);
+
+        let result = evaluate_run(run.dir.to_str().unwrap(), dataset_path, Some(misclassified_path));
+        let metrics = run.load_metrics();
+        let misclassified = std::fs::read_to_string(misclassified_path);
+        std::fs::remove_dir_all(root).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+        let _ = std::fs::remove_file(misclassified_path);
+
+        result.unwrap();
+        let metrics = metrics.unwrap();
+        assert_eq!(metrics.last().unwrap()["stage"], "test");
+        let misclassified: Vec<serde_json::Value> = serde_json::from_str(&misclassified.unwrap()).unwrap();
+        assert!(misclassified.len() <= 8);
+    }
+
+    #[test]
+    fn test_sliding_window_runs_chunk_long_texts() {
+        let window = SlidingWindow { stride: 8, weighting: WindowWeighting::Uniform };
+        let windowed = RunConfig { sliding_window: Some(window), ..RunConfig::new(tiny_config(2), 1) };
+        let unwindowed = RunConfig { sliding_window: None, ..windowed.clone() };
+
+        assert_eq!(run_overflow_policy(&unwindowed), INFERENCE_OVERFLOW_POLICY);
+        assert_eq!(run_overflow_policy(&windowed), OverflowPolicy::ChunkAndAggregate);
     }
 }
@@ -1,6 +1,47 @@
 use crate::transformer::Transformer;
-use crate::data_handler::data_loader::DataLoader;
+use crate::data_handler::data_loader::{DataLoader, IdentifiedBatch, RawRecord};
+use crate::training::trainer::{ema_checkpoint_path, swa_checkpoint_path};
+use crate::model_inference::inference::ExamplePrediction;
+use crate::cross_entropy::loss::Loss;
+use crate::model_evaluator::reject_option::{accuracy_coverage_curve, CoveragePoint};
+use crate::model_evaluator::slices::{group_by_metadata, slice_reports, SliceReport};
+use crate::model_evaluator::fairness::FairnessReport;
+use crate::augmentation::noise::{NoiseOperation, TextNoise, ALL_NOISE_OPERATIONS};
+use crate::logging::logger::LogEvent;
 use ndarray::Array2;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+
+/// Which weights of a checkpoint to evaluate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheckpointVariant {
+    /// The raw weights saved at `model_path`.
+    Raw,
+    /// The EMA shadow copy saved next to the checkpoint by the trainer.
+    Ema,
+    /// The stochastic weight average saved next to the checkpoint by the trainer.
+    Swa,
+}
+
+impl CheckpointVariant {
+    pub fn path(&self, model_path: &str) -> String {
+        match self {
+            CheckpointVariant::Raw => model_path.to_string(),
+            CheckpointVariant::Ema => ema_checkpoint_path(model_path),
+            CheckpointVariant::Swa => swa_checkpoint_path(model_path),
+        }
+    }
+}
+
+/// Metrics computed by an evaluation run.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct EvaluationReport {
+    pub accuracy: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1_score: f64,
+}
 
 pub struct Evaluator<'a> {
     pub model: Transformer,
@@ -14,32 +55,278 @@ impl<'a> Evaluator<'a> {
         Ok(Evaluator { model, data_loader })
     }
 
-   
-    pub fn evaluate(&self, dataset_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-   
-        let (inputs, labels) = self.data_loader.load_dataset(dataset_path)?;
+    /// Creates an Evaluator for the raw, EMA or SWA weights of the checkpoint at `model_path`.
+    pub fn with_variant(
+        model_path: &str,
+        variant: CheckpointVariant,
+        data_loader: &'a DataLoader,
+    ) -> Result<Self, std::io::Error> {
+        Self::new(&variant.path(model_path), data_loader)
+    }
 
-      
-        let batch_array = Array2::from_shape_vec(
-            (inputs.len(), inputs[0].len()),
-            inputs.iter().flatten().map(|&x| x as f64).collect(),
-        )?;
+    /// Scores the raw checkpoint and its EMA and SWA copies on the same dataset and
+    /// prints the metrics side by side. Variants without a saved file are skipped.
+    pub fn compare_variants(
+        model_path: &str,
+        data_loader: &'a DataLoader,
+        dataset_path: &str,
+    ) -> Result<Vec<(CheckpointVariant, EvaluationReport)>, Box<dyn std::error::Error>> {
+        let mut reports = Vec::new();
+        for variant in [CheckpointVariant::Raw, CheckpointVariant::Ema, CheckpointVariant::Swa] {
+            if !std::path::Path::new(&variant.path(model_path)).exists() {
+                continue;
+            }
+            let evaluator = Self::with_variant(model_path, variant, data_loader)?;
+            reports.push((variant, evaluator.compute_report(dataset_path)?));
+        }
 
-   
-        let logits = self.model.forward(&batch_array);
+        let mut table = format!("{:<8} {:>9} {:>10} {:>8} {:>9}", "Weights", "Accuracy", "Precision", "Recall", "F1-Score");
+        for (variant, report) in &reports {
+            table.push_str(&format!(
+                "\n{:<8} {:>8.2}% {:>9.2}% {:>7.2}% {:>8.2}%",
+                format!("{:?}", variant),
+                report.accuracy * 100.0,
+                report.precision * 100.0,
+                report.recall * 100.0,
+                report.f1_score * 100.0
+            ));
+        }
+        let metrics: Vec<(String, EvaluationReport)> = reports.iter().map(|(variant, report)| (format!("{:?}", variant), *report)).collect();
+        LogEvent::info("evaluator", table).metric("variants", metrics).emit();
+
+        Ok(reports)
+    }
+
+    /// Computes the metrics of a dataset (see `compute_report`) and logs them.
+    pub fn evaluate(&self, dataset_path: &str) -> Result<EvaluationReport, Box<dyn std::error::Error>> {
+        let report = self.compute_report(dataset_path)?;
 
+        LogEvent::info(
+            "evaluator",
+            format!(
+                "Accuracy: {:.2}%\nPrecision: {:.2}%, Recall: {:.2}%, F1-Score: {:.2}%",
+                report.accuracy * 100.0,
+                report.precision * 100.0,
+                report.recall * 100.0,
+                report.f1_score * 100.0
+            ),
+        )
+        .metric("accuracy", report.accuracy)
+        .metric("precision", report.precision)
+        .metric("recall", report.recall)
+        .metric("f1_score", report.f1_score)
+        .emit();
+
+        Ok(report)
+    }
+
+    /// Computes accuracy, precision, recall and F1-score on a dataset.
+    ///
+    /// With a sliding window on the data loader, every document is scored on the weighted
+    /// mean of its window probabilities.
+    pub fn compute_report(&self, dataset_path: &str) -> Result<EvaluationReport, Box<dyn std::error::Error>> {
+        let (logits, labels) = match &self.data_loader.sliding_window {
+            Some(window) => {
+                let dataset = self.data_loader.load_windowed_dataset(dataset_path, window)?;
+                let probabilities = Loss::softmax(&self.compute_logits(&dataset.inputs)?);
+                (dataset.aggregate(&probabilities, window), dataset.document_labels)
+            }
+            None => {
+                let (inputs, labels) = self.data_loader.load_dataset(dataset_path)?;
+                (self.compute_logits(&inputs)?, labels)
+            }
+        };
+
+        if let Some(&label) = labels.iter().find(|&&label| label >= logits.ncols()) {
+            let name = self.data_loader.label_map.as_ref().map_or_else(|| label.to_string(), |label_map| label_map.display_name(label));
+            return Err(format!("Label {} (class {}) is not one of the model's {} classes", name, label, logits.ncols()).into());
+        }
         let accuracy = self.compute_accuracy(&logits, &labels);
-        println!("Accuracy: {:.2}%", accuracy * 100.0);
+        let (precision, recall, f1_score) = self.compute_metrics(&logits, &labels);
+
+        Ok(EvaluationReport {
+            accuracy,
+            precision,
+            recall,
+            f1_score,
+        })
+    }
+
+    /// Predicts every example of a dataset, keeping its id and true label, named with the
+    /// data loader's label map when it has one.
+    pub fn predict_examples(&self, dataset_path: &str) -> Result<Vec<ExamplePrediction>, Box<dyn std::error::Error>> {
+        self.predict_batch(self.data_loader.load_dataset_with_ids(dataset_path)?)
+    }
+
+    /// Same as `predict_examples` for records already read with `DataLoader::load_records`.
+    pub fn predict_records(&self, records: &[RawRecord]) -> Result<Vec<ExamplePrediction>, Box<dyn std::error::Error>> {
+        self.predict_batch(self.data_loader.tokenize_records(records)?)
+    }
+
+    fn predict_batch(&self, (inputs, labels, ids): IdentifiedBatch) -> Result<Vec<ExamplePrediction>, Box<dyn std::error::Error>> {
+        let logits = self.compute_logits(&inputs)?;
+        let probabilities = Loss::softmax(&logits);
+
+        Ok(ids
+            .into_iter()
+            .zip(labels)
+            .zip(probabilities.outer_iter())
+            .map(|((id, label), row)| ExamplePrediction::new(id, Some(label), row.to_vec()).with_label_names(self.data_loader.label_map.as_ref()))
+            .collect())
+    }
+
+    /// Writes the misclassified examples of a dataset as a JSON array of `ExamplePrediction`s:
+    /// the `id` and `label` of the example next to the fields of its `Prediction`, plus the
+    /// label's `label_name` with a label map.
+    ///
+    /// # Returns
+    /// * The number of misclassified examples.
+    pub fn export_misclassified(&self, dataset_path: &str, output_path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let misclassified: Vec<ExamplePrediction> = self
+            .predict_examples(dataset_path)?
+            .into_iter()
+            .filter(|prediction| !prediction.is_correct())
+            .collect();
+
+        std::fs::write(output_path, serde_json::to_string_pretty(&misclassified)?)?;
+        Ok(misclassified.len())
+    }
+
+    /// Accuracy-vs-coverage curve when the model abstains on examples whose top-class
+    /// probability is below each threshold (e.g. `DEFAULT_ABSTAIN_THRESHOLDS`).
+    /// Prints the curve so an operating point for human review can be chosen.
+    pub fn coverage_curve(
+        &self,
+        dataset_path: &str,
+        thresholds: &[f64],
+    ) -> Result<Vec<CoveragePoint>, Box<dyn std::error::Error>> {
+        let curve = accuracy_coverage_curve(&self.predict_examples(dataset_path)?, thresholds);
+
+        let mut table = format!("{:>9} {:>9} {:>9}", "Threshold", "Coverage", "Accuracy");
+        for point in &curve {
+            table.push_str(&format!(
+                "\n{:>9.2} {:>8.2}% {:>8.2}%",
+                point.threshold,
+                point.coverage * 100.0,
+                point.accuracy * 100.0
+            ));
+        }
+        LogEvent::info("evaluator", table).metric("coverage_curve", &curve).emit();
+
+        Ok(curve)
+    }
+
+    /// Computes metrics per value of a metadata column (see `DataSchema::metadata_fields`),
+    /// e.g. language, source or length bucket, and prints a slice report.
+    pub fn slice_report(&self, dataset_path: &str, slice_field: &str) -> Result<Vec<SliceReport>, Box<dyn std::error::Error>> {
+        let records = self.data_loader.load_records(dataset_path)?;
+        let predictions = self.predict_records(&records)?;
+
+        let groups = group_by_metadata(&records, &predictions, slice_field);
+        let reports = slice_reports(&groups, self.model.config.num_classes);
+
+        let mut table = format!("{:<16} {:>6} {:>9} {:>10} {:>8} {:>9}", slice_field, "Count", "Accuracy", "Precision", "Recall", "F1-Score");
+        for slice in &reports {
+            table.push_str(&format!(
+                "\n{:<16} {:>6} {:>8.2}% {:>9.2}% {:>7.2}% {:>8.2}%",
+                slice.slice,
+                slice.count,
+                slice.report.accuracy * 100.0,
+                slice.report.precision * 100.0,
+                slice.report.recall * 100.0,
+                slice.report.f1_score * 100.0
+            ));
+        }
+        let metrics: Vec<(&str, usize, EvaluationReport)> = reports.iter().map(|slice| (slice.slice.as_str(), slice.count, slice.report)).collect();
+        LogEvent::info("evaluator", table).metric("slices", metrics).emit();
+
+        Ok(reports)
+    }
+
+    /// Group-conditional metrics across a protected attribute: per-group accuracy,
+    /// positive rate, FPR and FNR for `positive_class`, plus the largest gaps between
+    /// groups (demographic parity, FPR and FNR). Undefined rates print as `n/a`. The group
+    /// column must be listed in the data schema's `metadata_fields`.
+    pub fn fairness_report(
+        &self,
+        dataset_path: &str,
+        group_field: &str,
+        positive_class: usize,
+    ) -> Result<FairnessReport, Box<dyn std::error::Error>> {
+        let records = self.data_loader.load_records(dataset_path)?;
+        if !records.iter().any(|record| record.metadata.contains_key(group_field)) {
+            return Err(format!("No record has a value for group field {}", group_field).into());
+        }
+        let predictions = self.predict_records(&records)?;
+
+        let report = FairnessReport::from_groups(&group_by_metadata(&records, &predictions, group_field), positive_class);
+
+        // Rates are undefined for groups without true negatives (FPR) or true positives (FNR).
+        let percent = |rate: Option<f64>| rate.map_or_else(|| "n/a".to_string(), |rate| format!("{:.2}%", rate * 100.0));
+        let mut table = format!("{:<16} {:>6} {:>9} {:>9} {:>8} {:>8}", group_field, "Count", "Accuracy", "Pos.Rate", "FPR", "FNR");
+        for group in &report.groups {
+            table.push_str(&format!(
+                "\n{:<16} {:>6} {:>8.2}% {:>8.2}% {:>8} {:>8}",
+                group.group,
+                group.count,
+                group.accuracy * 100.0,
+                group.positive_rate * 100.0,
+                percent(group.false_positive_rate),
+                percent(group.false_negative_rate)
+            ));
+        }
+        table.push_str(&format!(
+            "\nDemographic parity gap: {:.2}%, FPR gap: {}, FNR gap: {}",
+            report.demographic_parity_gap * 100.0,
+            percent(report.false_positive_rate_gap),
+            percent(report.false_negative_rate_gap)
+        ));
+        LogEvent::info("evaluator", table)
+            .metric("demographic_parity_gap", report.demographic_parity_gap)
+            .metric("false_positive_rate_gap", report.false_positive_rate_gap)
+            .metric("false_negative_rate_gap", report.false_negative_rate_gap)
+            .emit();
+
+        Ok(report)
+    }
+
+    /// Accuracy on copies of a dataset corrupted by each `NoiseOperation` alone, every letter
+    /// or digit with `char_probability`, next to the accuracy on the clean texts. Prints the
+    /// report so typo or OCR sensitivity shows before the model meets messy input. The noise
+    /// is seeded, so reports of different models see the same corrupted texts.
+    pub fn noise_report(&self, dataset_path: &str, char_probability: f64) -> Result<Vec<(NoiseOperation, f64)>, Box<dyn std::error::Error>> {
+        let records = self.data_loader.load_records(dataset_path)?;
+        let accuracy = |records: &[RawRecord]| -> Result<f64, Box<dyn std::error::Error>> {
+            let predictions = self.predict_records(records)?;
+            Ok(predictions.iter().filter(|prediction| prediction.is_correct()).count() as f64 / predictions.len() as f64)
+        };
+        let clean_accuracy = accuracy(&records)?;
+
+        let mut table = format!("{:<22} {:>9}\n{:<22} {:>8.2}%", "Noise", "Accuracy", "None", clean_accuracy * 100.0);
+        let mut report = Vec::with_capacity(ALL_NOISE_OPERATIONS.len());
+        for operation in ALL_NOISE_OPERATIONS {
+            let noise = TextNoise { char_probability, operations: std::slice::from_ref(operation) };
+            let mut rng = StdRng::seed_from_u64(0);
+            let noisy: Vec<RawRecord> = records.iter().map(|record| RawRecord { text: noise.apply(&record.text, &mut rng), ..record.clone() }).collect();
+            let noisy_accuracy = accuracy(&noisy)?;
+            table.push_str(&format!("\n{:<22} {:>8.2}%", format!("{:?}", operation), noisy_accuracy * 100.0));
+            report.push((*operation, noisy_accuracy));
+        }
+        let metrics: Vec<(String, f64)> = report.iter().map(|(operation, accuracy)| (format!("{:?}", operation), *accuracy)).collect();
+        LogEvent::info("evaluator", table).metric("clean_accuracy", clean_accuracy).metric("noise_accuracy", metrics).emit();
+
+        Ok(report)
+    }
 
-        let metrics = self.compute_metrics(&logits, &labels);
-        println!(
-            "Precision: {:.2}%, Recall: {:.2}%, F1-Score: {:.2}%",
-            metrics.0 * 100.0,
-            metrics.1 * 100.0,
-            metrics.2 * 100.0
-        );
+    fn compute_logits(&self, inputs: &[Vec<usize>]) -> Result<Array2<f64>, Box<dyn std::error::Error>> {
+        if inputs.is_empty() {
+            return Err("Cannot evaluate an empty dataset".into());
+        }
+      
+        let (batch_array, mask_array, segments) = self.data_loader.model_inputs(inputs);
 
-        Ok(())
+   
+        Ok(self.model.forward_with_segments(&batch_array, Some(&mask_array), Some(&segments)))
     }
 
   
@@ -51,7 +338,7 @@ impl<'a> Evaluator<'a> {
                 let predicted_label = logit
                     .iter()
                     .enumerate()
-                    .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                    .max_by(|a, b| a.1.total_cmp(b.1))
                     .map(|(index, _)| index)
                     .unwrap_or(0);
                 predicted_label == label
@@ -63,47 +350,253 @@ impl<'a> Evaluator<'a> {
 
   
     fn compute_metrics(&self, logits: &Array2<f64>, labels: &[usize]) -> (f64, f64, f64) {
-        let num_classes = logits.shape()[1];
-        let mut true_positives = vec![0; num_classes];
-        let mut false_positives = vec![0; num_classes];
-        let mut false_negatives = vec![0; num_classes];
-
-        for (logit, &label) in logits.outer_iter().zip(labels.iter()) {
-            let predicted_label = logit
-                .iter()
-                .enumerate()
-                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-                .map(|(index, _)| index)
-                .unwrap_or(0);
-
-            if predicted_label == label {
-                true_positives[label] += 1;
-            } else {
-                false_positives[predicted_label] += 1;
-                false_negatives[label] += 1;
-            }
-        }
+        let predicted: Vec<usize> = logits
+            .outer_iter()
+            .map(|logit| {
+                logit
+                    .iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(b.1))
+                    .map(|(index, _)| index)
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        classification_metrics(&predicted, labels, logits.shape()[1])
+    }
+}
+
+/// Macro-averaged precision, recall and F1-score of predicted against true classes.
+pub fn classification_metrics(predicted: &[usize], labels: &[usize], num_classes: usize) -> (f64, f64, f64) {
+    let mut true_positives = vec![0; num_classes];
+    let mut false_positives = vec![0; num_classes];
+    let mut false_negatives = vec![0; num_classes];
 
-        let precision: f64 = true_positives
-            .iter()
-            .zip(false_positives.iter())
-            .map(|(tp, fp)| *tp as f64 / (*tp + *fp).max(1) as f64)
-            .sum::<f64>()
-            / num_classes as f64;
-
-        let recall: f64 = true_positives
-            .iter()
-            .zip(false_negatives.iter())
-            .map(|(tp, fn_val)| *tp as f64 / (*tp + *fn_val).max(1) as f64)
-            .sum::<f64>()
-            / num_classes as f64;
-
-        let f1_score = if precision + recall > 0.0 {
-            2.0 * (precision * recall) / (precision + recall)
+    for (&predicted_label, &label) in predicted.iter().zip(labels.iter()) {
+        if predicted_label == label {
+            true_positives[label] += 1;
         } else {
-            0.0
-        };
+            false_positives[predicted_label] += 1;
+            false_negatives[label] += 1;
+        }
+    }
+
+    let precision: f64 = true_positives
+        .iter()
+        .zip(false_positives.iter())
+        .map(|(tp, fp)| *tp as f64 / (*tp + *fp).max(1) as f64)
+        .sum::<f64>()
+        / num_classes as f64;
+
+    let recall: f64 = true_positives
+        .iter()
+        .zip(false_negatives.iter())
+        .map(|(tp, fn_val)| *tp as f64 / (*tp + *fn_val).max(1) as f64)
+        .sum::<f64>()
+        / num_classes as f64;
+
+    let f1_score = if precision + recall > 0.0 {
+        2.0 * (precision * recall) / (precision + recall)
+    } else {
+        0.0
+    };
+
+    (precision, recall, f1_score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{synthetic_dataset_path, temp_path, tiny_config, tiny_vocab};
+    use crate::data_handler::sliding_window::{SlidingWindow, WindowWeighting};
+    use crate::tokenization::tokenizer::Tokenizer;
+    use crate::configurration::data_schema::DataSchema;
+
+    #[test]
+    fn test_compare_raw_and_ema_variants() {
+        let vocab = tiny_vocab(&["free"]);
+        let config = tiny_config(2);
+
+        let model_path = &temp_path("compare_variants_model.json");
+        Transformer::<f64>::new(config.clone(), vocab.clone()).save(model_path).unwrap();
+
+        let tokenizer = Tokenizer::new(vocab.clone(), 16);
+        let data_loader = DataLoader::new(&tokenizer);
+        let dataset_path = &synthetic_dataset_path("compare_variants_dataset.json");
+
+        let raw_only = Evaluator::compare_variants(model_path, &data_loader, dataset_path).unwrap();
+        assert_eq!(raw_only.len(), 1);
 
-        (precision, recall, f1_score)
+        let ema_path = CheckpointVariant::Ema.path(model_path);
+        assert!(ema_path.ends_with("compare_variants_model.ema.json"));
+        Transformer::<f64>::new(config.clone(), vocab.clone()).save(&ema_path).unwrap();
+
+        let swa_path = CheckpointVariant::Swa.path(model_path);
+        assert!(swa_path.ends_with("compare_variants_model.swa.json"));
+        Transformer::<f64>::new(config, vocab).save(&swa_path).unwrap();
+
+        let reports = Evaluator::compare_variants(model_path, &data_loader, dataset_path).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+        std::fs::remove_file(model_path).unwrap();
+        std::fs::remove_file(&ema_path).unwrap();
+        std::fs::remove_file(&swa_path).unwrap();
+
+        let variants: Vec<CheckpointVariant> = reports.iter().map(|(variant, _)| *variant).collect();
+        assert_eq!(variants, [CheckpointVariant::Raw, CheckpointVariant::Ema, CheckpointVariant::Swa]);
+        for (_, report) in reports {
+            assert!((0.0..=1.0).contains(&report.accuracy));
+        }
+    }
+
+    #[test]
+    fn test_export_misclassified_keeps_ids() {
+        let vocab = tiny_vocab(&[]);
+        let config = tiny_config(2);
+
+        let model_path = &temp_path("export_misclassified_model.json");
+        Transformer::<f64>::new(config, vocab.clone()).save(model_path).unwrap();
+        let tokenizer = Tokenizer::new(vocab, 16);
+        let data_loader = DataLoader::new(&tokenizer);
+        let evaluator = Evaluator::new(model_path, &data_loader).unwrap();
+        std::fs::remove_file(model_path).unwrap();
+
+        let dataset_path = &synthetic_dataset_path("export_misclassified_dataset.json");
+        let predictions = evaluator.predict_examples(dataset_path).unwrap();
+        assert_eq!(predictions[3].id, "3");
+
+        let output_path = &temp_path("export_misclassified_output.json");
+        let count = evaluator.export_misclassified(dataset_path, output_path).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+        let exported: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(output_path).unwrap()).unwrap();
+        std::fs::remove_file(output_path).unwrap();
+
+        let expected = predictions.iter().filter(|p| !p.is_correct()).count();
+        assert_eq!(count, expected);
+        assert_eq!(exported.as_array().unwrap().len(), expected);
+    }
+
+    #[test]
+    fn test_sliding_window_report_scores_documents() {
+        let vocab = tiny_vocab(&["free", "offer"]);
+        let config = tiny_config(2);
+        let model_path = &temp_path("sliding_window_model.json");
+        let dataset_path = &temp_path("sliding_window_dataset.json");
+        Transformer::<f64>::new(config, vocab.clone()).save(model_path).unwrap();
+        std::fs::write(dataset_path, r#"[{ "text": "free offer free offer free", "label": 1 }, { "text": "offer", "label": 0 }]"#).unwrap();
+
+        let tokenizer = Tokenizer::new(vocab, 2);
+        let window = SlidingWindow { stride: 2, weighting: WindowWeighting::NewTokens };
+        let data_loader = DataLoader::new(&tokenizer).with_sliding_window(window);
+        let evaluator = Evaluator::new(model_path, &data_loader).unwrap();
+        let windowed = data_loader.load_windowed_dataset(dataset_path, &window).unwrap();
+        let (truncated, _) = data_loader.load_dataset(dataset_path).unwrap();
+        let report = evaluator.compute_report(dataset_path).unwrap();
+        let window_probabilities = Loss::softmax(&evaluator.compute_logits(&windowed.inputs).unwrap());
+        std::fs::remove_file(model_path).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+
+        // "free offer free offer free" becomes the windows 0..2, 2..4 and 3..5, which add
+        // 2, 2 and 1 new tokens; "offer" fits in one window.
+        assert_eq!(windowed.documents, vec![0, 0, 0, 1]);
+        assert_eq!(windowed.new_tokens, vec![2, 2, 1, 1]);
+        assert_eq!(windowed.labels, vec![1, 1, 1, 0]);
+        // Only evaluation opts into windows; `load_dataset` keeps one sequence per example.
+        assert_eq!(truncated.len(), 2);
+
+        // Accuracy is over the two documents, each predicted from its token-weighted windows.
+        let rows = |range: std::ops::Range<usize>| window_probabilities.slice(ndarray::s![range, ..]).to_owned();
+        let first_document = (rows(0..1) * 2.0 + rows(1..2) * 2.0 + rows(2..3)) / 5.0;
+        let predicted = |probabilities: &Array2<f64>| usize::from(probabilities[[0, 1]] > probabilities[[0, 0]]);
+        let correct = usize::from(predicted(&first_document) == 1) + usize::from(predicted(&rows(3..4)) == 0);
+        assert_eq!(report.accuracy, correct as f64 / 2.0);
+    }
+
+    #[test]
+    fn test_slice_report_groups_by_metadata() {
+        let vocab = tiny_vocab(&["free", "offer"]);
+        let model_path = &temp_path("slice_report_model.json");
+        let dataset_path = &temp_path("slice_report_dataset.json");
+        Transformer::<f64>::new(tiny_config(2), vocab.clone()).save(model_path).unwrap();
+        std::fs::write(
+            dataset_path,
+            r#"[{ "text": "free", "label": 1, "language": "en" }, { "text": "offer", "label": 0, "language": "en" },
+                { "text": "free offer", "label": 0, "language": "de" }, { "text": "offer", "label": 1 }]"#,
+        )
+        .unwrap();
+
+        let tokenizer = Tokenizer::new(vocab, 16);
+        let schema = DataSchema { metadata_fields: vec!["language".to_string()], ..DataSchema::default() };
+        let data_loader = DataLoader::new(&tokenizer).with_schema(schema);
+        let evaluator = Evaluator::new(model_path, &data_loader).unwrap();
+        let reports = evaluator.slice_report(dataset_path, "language");
+        std::fs::remove_file(model_path).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+
+        let reports = reports.unwrap();
+        let slices: Vec<(&str, usize)> = reports.iter().map(|slice| (slice.slice.as_str(), slice.count)).collect();
+        assert_eq!(slices, [("<missing>", 1), ("de", 1), ("en", 2)]);
+        for slice in &reports {
+            assert!((0.0..=1.0).contains(&slice.report.accuracy));
+        }
+    }
+
+    #[test]
+    fn test_fairness_report_skips_undefined_rates() {
+        let vocab = tiny_vocab(&["free", "offer"]);
+        let model_path = &temp_path("fairness_report_model.json");
+        let dataset_path = &temp_path("fairness_report_dataset.json");
+        Transformer::<f64>::new(tiny_config(2), vocab.clone()).save(model_path).unwrap();
+        std::fs::write(
+            dataset_path,
+            r#"[{ "text": "free", "label": 1, "group": "a" }, { "text": "offer", "label": 0, "group": "a" },
+                { "text": "free offer", "label": 1, "group": "b" }]"#,
+        )
+        .unwrap();
+
+        let tokenizer = Tokenizer::new(vocab, 16);
+        let schema = DataSchema { metadata_fields: vec!["group".to_string()], ..DataSchema::default() };
+        let data_loader = DataLoader::new(&tokenizer).with_schema(schema);
+        let evaluator = Evaluator::new(model_path, &data_loader).unwrap();
+        let report = evaluator.fairness_report(dataset_path, "group", 1);
+        let missing_field = evaluator.fairness_report(dataset_path, "language", 1);
+        std::fs::remove_file(model_path).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+
+        let report = report.unwrap();
+        let groups: Vec<(&str, usize)> = report.groups.iter().map(|group| (group.group.as_str(), group.count)).collect();
+        assert_eq!(groups, [("a", 2), ("b", 1)]);
+        // Group b has no true negatives, so only group a defines a false positive rate.
+        assert!(report.groups[0].false_positive_rate.is_some());
+        assert_eq!(report.groups[1].false_positive_rate, None);
+        assert_eq!(report.false_positive_rate_gap, Some(0.0));
+        assert!(report.false_negative_rate_gap.is_some());
+        assert!(missing_field.is_err());
+    }
+
+    #[test]
+    fn test_noise_report_scores_every_operation() {
+        let vocab = tiny_vocab(&["free", "offer"]);
+        let model_path = &temp_path("noise_report_model.json");
+        let dataset_path = &temp_path("noise_report_dataset.json");
+        Transformer::<f64>::new(tiny_config(2), vocab.clone()).save(model_path).unwrap();
+        std::fs::write(dataset_path, r#"[{ "text": "free", "label": 1 }, { "text": "offer", "label": 0 }, { "text": "free offer", "label": 0 }]"#).unwrap();
+
+        let tokenizer = Tokenizer::new(vocab, 16);
+        let data_loader = DataLoader::new(&tokenizer);
+        let evaluator = Evaluator::new(model_path, &data_loader).unwrap();
+        let clean = evaluator.evaluate(dataset_path).unwrap();
+        let unchanged = evaluator.noise_report(dataset_path, 0.0);
+        let noisy = evaluator.noise_report(dataset_path, 1.0);
+        std::fs::remove_file(model_path).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+
+        let operations: Vec<NoiseOperation> = unchanged.as_ref().unwrap().iter().map(|(operation, _)| *operation).collect();
+        assert_eq!(operations, ALL_NOISE_OPERATIONS);
+        for (_, accuracy) in unchanged.unwrap() {
+            assert_eq!(accuracy, clean.accuracy);
+        }
+        for (_, accuracy) in noisy.unwrap() {
+            assert!((0.0..=1.0).contains(&accuracy));
+        }
     }
 }
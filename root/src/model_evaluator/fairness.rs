@@ -0,0 +1,161 @@
+use crate::model_inference::inference::ExamplePrediction;
+use std::collections::BTreeMap;
+
+/// Binary outcome metrics of one protected group, treating `positive_class` as the positive outcome.
+#[derive(Clone, Debug)]
+pub struct GroupMetrics {
+    pub group: String,
+    pub count: usize,
+    pub accuracy: f64,
+    /// Fraction of the group predicted as the positive class.
+    pub positive_rate: f64,
+    /// Fraction of the group's true negatives predicted as positive; `None` without true negatives.
+    pub false_positive_rate: Option<f64>,
+    /// Fraction of the group's true positives predicted as negative; `None` without true positives.
+    pub false_negative_rate: Option<f64>,
+}
+
+/// Group-conditional metrics and the largest gaps between groups.
+#[derive(Clone, Debug)]
+pub struct FairnessReport {
+    pub groups: Vec<GroupMetrics>,
+    /// Largest difference in positive rate between two groups with labeled examples.
+    pub demographic_parity_gap: f64,
+    /// Largest difference in false positive rate between two groups where it is defined;
+    /// `None` when it is defined for no group.
+    pub false_positive_rate_gap: Option<f64>,
+    /// Largest difference in false negative rate between two groups where it is defined;
+    /// `None` when it is defined for no group.
+    pub false_negative_rate_gap: Option<f64>,
+}
+
+impl FairnessReport {
+    /// Computes per-group metrics from labeled predictions grouped by a protected attribute
+    /// (see `slices::group_by_metadata`). Unlabeled predictions are ignored, and groups where a
+    /// rate is undefined are left out of its gap.
+    pub fn from_groups(groups: &BTreeMap<String, Vec<&ExamplePrediction>>, positive_class: usize) -> Self {
+        let groups: Vec<GroupMetrics> = groups
+            .iter()
+            .map(|(group, predictions)| group_metrics(group, predictions, positive_class))
+            .collect();
+
+        let gap = |metric: fn(&GroupMetrics) -> Option<f64>| {
+            let values: Vec<f64> = groups.iter().filter_map(metric).collect();
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            (!values.is_empty()).then_some(max - min)
+        };
+
+        FairnessReport {
+            demographic_parity_gap: gap(|g| (g.count > 0).then_some(g.positive_rate)).unwrap_or(0.0),
+            false_positive_rate_gap: gap(|g| g.false_positive_rate),
+            false_negative_rate_gap: gap(|g| g.false_negative_rate),
+            groups,
+        }
+    }
+}
+
+fn group_metrics(group: &str, predictions: &[&ExamplePrediction], positive_class: usize) -> GroupMetrics {
+    let (mut correct, mut predicted_positive) = (0, 0);
+    let (mut positives, mut negatives) = (0, 0);
+    let (mut false_positives, mut false_negatives) = (0, 0);
+
+    for prediction in predictions {
+        let Some(label) = prediction.label else { continue };
+        let predicted_is_positive = prediction.prediction.label_id == positive_class;
+
+        if prediction.prediction.label_id == label {
+            correct += 1;
+        }
+        if predicted_is_positive {
+            predicted_positive += 1;
+        }
+        if label == positive_class {
+            positives += 1;
+            if !predicted_is_positive {
+                false_negatives += 1;
+            }
+        } else {
+            negatives += 1;
+            if predicted_is_positive {
+                false_positives += 1;
+            }
+        }
+    }
+
+    let count = positives + negatives;
+    GroupMetrics {
+        group: group.to_string(),
+        count,
+        accuracy: correct as f64 / count.max(1) as f64,
+        positive_rate: predicted_positive as f64 / count.max(1) as f64,
+        false_positive_rate: (negatives > 0).then(|| false_positives as f64 / negatives as f64),
+        false_negative_rate: (positives > 0).then(|| false_negatives as f64 / positives as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_rates_and_gaps() {
+        let positive = |label| ExamplePrediction::new(String::new(), Some(label), vec![0.1, 0.9]);
+        let negative = |label| ExamplePrediction::new(String::new(), Some(label), vec![0.9, 0.1]);
+
+        let group_a = [positive(1), positive(0), negative(0), negative(0)];
+        let group_b = [positive(1), negative(1)];
+        let groups = BTreeMap::from([
+            ("a".to_string(), group_a.iter().collect()),
+            ("b".to_string(), group_b.iter().collect()),
+        ]);
+
+        let report = FairnessReport::from_groups(&groups, 1);
+
+        let a = &report.groups[0];
+        assert_eq!(a.count, 4);
+        assert_eq!(a.accuracy, 0.75);
+        assert_eq!(a.positive_rate, 0.5);
+        assert!((a.false_positive_rate.unwrap() - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!(a.false_negative_rate, Some(0.0));
+
+        let b = &report.groups[1];
+        assert_eq!(b.false_negative_rate, Some(0.5));
+        // Group b has no true negatives, so its false positive rate is undefined.
+        assert_eq!(b.false_positive_rate, None);
+
+        assert_eq!(report.demographic_parity_gap, 0.0);
+        assert_eq!(report.false_positive_rate_gap, Some(0.0));
+        assert_eq!(report.false_negative_rate_gap, Some(0.5));
+    }
+
+    #[test]
+    fn test_undefined_rates_are_left_out_of_gaps() {
+        let positive = |label| ExamplePrediction::new(String::new(), Some(label), vec![0.1, 0.9]);
+        let negative = |label| ExamplePrediction::new(String::new(), Some(label), vec![0.9, 0.1]);
+
+        // Group a has no true positives and group b no true negatives.
+        let group_a = [positive(0), negative(0)];
+        let group_b = [negative(1), negative(1)];
+        let groups = BTreeMap::from([
+            ("a".to_string(), group_a.iter().collect()),
+            ("b".to_string(), group_b.iter().collect()),
+        ]);
+
+        let report = FairnessReport::from_groups(&groups, 1);
+
+        assert_eq!(report.groups[0].false_positive_rate, Some(0.5));
+        assert_eq!(report.groups[0].false_negative_rate, None);
+        assert_eq!(report.groups[1].false_positive_rate, None);
+        assert_eq!(report.groups[1].false_negative_rate, Some(1.0));
+        // Each rate is defined for one group only, so its gap is zero.
+        assert_eq!(report.false_positive_rate_gap, Some(0.0));
+        assert_eq!(report.false_negative_rate_gap, Some(0.0));
+        assert_eq!(report.demographic_parity_gap, 0.5);
+
+        let unlabeled = [ExamplePrediction::new(String::new(), None, vec![0.1, 0.9])];
+        let report = FairnessReport::from_groups(&BTreeMap::from([("a".to_string(), unlabeled.iter().collect())]), 1);
+        assert_eq!(report.false_positive_rate_gap, None);
+        assert_eq!(report.false_negative_rate_gap, None);
+    }
+}
@@ -1 +1,6 @@
 pub mod evaluator;
+pub mod reject_option;
+pub mod slices;
+pub mod fairness;
+pub mod promotion;
+pub mod score_calibration;
@@ -0,0 +1,188 @@
+use crate::data_handler::label_map::LabelMap;
+use crate::model_evaluator::evaluator::EvaluationReport;
+use crate::model_evaluator::score_calibration::ScoreCalibrator;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Files of the serving directory, loadable with `Inference::new`.
+pub const SERVING_MODEL_FILE: &str = "model.json";
+pub const SERVING_TOKENIZER_FILE: &str = "tokenizer.json";
+/// Score calibrator of the served model, when promotion fitted one (see `score_calibration.rs`).
+pub const SERVING_CALIBRATION_FILE: &str = "calibration.json";
+/// Class names of the served model, when its run has a label map.
+pub const SERVING_LABEL_MAP_FILE: &str = "labels.json";
+/// Record of the last promotion attempt, written next to the served model.
+pub const PROMOTION_RECORD_FILE: &str = "promotion.json";
+
+/// Metrics a checkpoint must reach on the gate dataset before it replaces the served model.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PromotionGate {
+    pub min_accuracy: f64,
+    pub min_f1_score: f64,
+    /// Largest accepted F1-score drop against the currently served model on the same
+    /// dataset; `None` only applies the absolute thresholds.
+    pub max_f1_drop: Option<f64>,
+}
+
+impl PromotionGate {
+    /// Checks a candidate's metrics.
+    ///
+    /// # Arguments
+    /// * `candidate` - Metrics of the new checkpoint on the gate dataset.
+    /// * `served` - Metrics of the served model on the same dataset, if there is one.
+    ///
+    /// # Returns
+    /// * One message per violated threshold; empty when the candidate may be promoted.
+    pub fn check(&self, candidate: &EvaluationReport, served: Option<&EvaluationReport>) -> Vec<String> {
+        let mut failures = Vec::new();
+        if candidate.accuracy < self.min_accuracy {
+            failures.push(format!("Accuracy {:.2}% is below {:.2}%", candidate.accuracy * 100.0, self.min_accuracy * 100.0));
+        }
+        if candidate.f1_score < self.min_f1_score {
+            failures.push(format!("F1-score {:.2}% is below {:.2}%", candidate.f1_score * 100.0, self.min_f1_score * 100.0));
+        }
+        if let (Some(max_drop), Some(served)) = (self.max_f1_drop, served) {
+            let drop = served.f1_score - candidate.f1_score;
+            if drop > max_drop {
+                failures.push(format!(
+                    "F1-score {:.2}% is {:.2} points below the served model's {:.2}% (at most {:.2} allowed)",
+                    candidate.f1_score * 100.0,
+                    drop * 100.0,
+                    served.f1_score * 100.0,
+                    max_drop * 100.0
+                ));
+            }
+        }
+        failures
+    }
+}
+
+/// Outcome of a promotion attempt, saved as `promotion.json` and logged to the run.
+#[derive(Clone, Debug, Serialize)]
+pub struct PromotionRecord {
+    /// Run directory of the candidate.
+    pub source: String,
+    pub gate_dataset: String,
+    pub candidate: EvaluationReport,
+    pub served: Option<EvaluationReport>,
+    pub failures: Vec<String>,
+    pub promoted: bool,
+    /// Calibrator installed with the model, when promotion recalibrates scores.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calibrator: Option<ScoreCalibrator>,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl PromotionRecord {
+    pub fn new(
+        source: &str,
+        gate_dataset: &str,
+        candidate: EvaluationReport,
+        served: Option<EvaluationReport>,
+        failures: Vec<String>,
+    ) -> Self {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let promoted = failures.is_empty();
+        PromotionRecord { source: source.to_string(), gate_dataset: gate_dataset.to_string(), candidate, served, failures, promoted, calibrator: None, timestamp }
+    }
+}
+
+/// Copies a model and its tokenizer into `serving_dir`. Each file is written next to its
+/// destination first and then renamed over it, so a reader never sees a partial file.
+///
+/// # Arguments
+/// * `label_map` - Class names of the model's run, installed so served predictions keep them.
+/// * `calibrator` - Score calibrator fitted for the model.
+///
+/// The label map and calibrator are installed after the model; when either is missing, the
+/// file of the previous model is removed, since it does not apply to the new one.
+pub fn install(
+    model_path: &str,
+    tokenizer_path: &str,
+    label_map: Option<&LabelMap>,
+    calibrator: Option<&ScoreCalibrator>,
+    serving_dir: &Path,
+) -> Result<(), std::io::Error> {
+    fs::create_dir_all(serving_dir)?;
+    for (source, name) in [(tokenizer_path, SERVING_TOKENIZER_FILE), (model_path, SERVING_MODEL_FILE)] {
+        let staged = serving_dir.join(format!(".{}.tmp", name));
+        fs::copy(source, &staged)?;
+        fs::rename(&staged, serving_dir.join(name))?;
+    }
+    replace_optional(serving_dir, SERVING_LABEL_MAP_FILE, label_map.map(|label_map| move |path: &str| label_map.save(path)))?;
+    replace_optional(serving_dir, SERVING_CALIBRATION_FILE, calibrator.map(|calibrator| move |path: &str| calibrator.save(path)))
+}
+
+/// Writes `name` with `save` through a staged file, or removes it when there is nothing to save.
+fn replace_optional(serving_dir: &Path, name: &str, save: Option<impl FnOnce(&str) -> Result<(), std::io::Error>>) -> Result<(), std::io::Error> {
+    let path = serving_dir.join(name);
+    match save {
+        Some(save) => {
+            let staged = serving_dir.join(format!(".{}.tmp", name));
+            save(&staged.to_string_lossy())?;
+            fs::rename(&staged, &path)
+        }
+        None if path.exists() => fs::remove_file(&path),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(accuracy: f64, f1_score: f64) -> EvaluationReport {
+        EvaluationReport { accuracy, precision: f1_score, recall: f1_score, f1_score }
+    }
+
+    #[test]
+    fn test_gate_checks_thresholds_and_regressions() {
+        let gate = PromotionGate { min_accuracy: 0.8, min_f1_score: 0.75, max_f1_drop: Some(0.02) };
+        assert!(gate.check(&report(0.9, 0.85), Some(&report(0.9, 0.86))).is_empty());
+        assert!(gate.check(&report(0.9, 0.85), None).is_empty());
+
+        let failures = gate.check(&report(0.7, 0.8), Some(&report(0.95, 0.9)));
+        assert_eq!(failures.len(), 2);
+        assert!(failures[0].starts_with("Accuracy 70.00%"));
+        assert!(failures[1].contains("10.00 points below"));
+
+        let no_comparison = PromotionGate { max_f1_drop: None, ..gate };
+        assert!(no_comparison.check(&report(0.9, 0.8), Some(&report(0.95, 0.9))).is_empty());
+    }
+
+    #[test]
+    fn test_install_replaces_served_files() {
+        let dir = std::env::temp_dir().join(format!("promotion_test_{}", std::process::id()));
+        let serving_dir = dir.join("serving");
+        fs::create_dir_all(&dir).unwrap();
+        let (model, tokenizer) = (dir.join("model.json"), dir.join("tokenizer.json"));
+        fs::write(&model, "new model").unwrap();
+        fs::write(&tokenizer, "new tokenizer").unwrap();
+        fs::create_dir_all(&serving_dir).unwrap();
+        fs::write(serving_dir.join(SERVING_MODEL_FILE), "old model").unwrap();
+        fs::write(serving_dir.join(SERVING_CALIBRATION_FILE), "old calibration").unwrap();
+
+        fs::write(serving_dir.join(SERVING_LABEL_MAP_FILE), "old labels").unwrap();
+
+        let result = install(model.to_str().unwrap(), tokenizer.to_str().unwrap(), None, None, &serving_dir);
+        let served = fs::read_to_string(serving_dir.join(SERVING_MODEL_FILE));
+        let files = fs::read_dir(&serving_dir).unwrap().count();
+        let calibrator = ScoreCalibrator::Platt { slope: 1.5, intercept: -0.2 };
+        let label_map = LabelMap::from_names(&["ham", "spam"]).unwrap();
+        let recalibrated = install(model.to_str().unwrap(), tokenizer.to_str().unwrap(), Some(&label_map), Some(&calibrator), &serving_dir);
+        let installed = ScoreCalibrator::load(&serving_dir.join(SERVING_CALIBRATION_FILE).to_string_lossy());
+        let installed_labels = LabelMap::load(&serving_dir.join(SERVING_LABEL_MAP_FILE).to_string_lossy());
+        fs::remove_dir_all(&dir).unwrap();
+
+        result.unwrap();
+        recalibrated.unwrap();
+        assert_eq!(served.unwrap(), "new model");
+        // The stale calibrator and label map of the old model are gone.
+        assert_eq!(files, 2);
+        assert_eq!(installed.unwrap(), calibrator);
+        assert_eq!(installed_labels.unwrap(), label_map);
+    }
+}
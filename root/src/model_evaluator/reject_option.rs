@@ -0,0 +1,69 @@
+use crate::model_inference::inference::ExamplePrediction;
+use serde::Serialize;
+
+/// Confidence thresholds swept by `Evaluator::coverage_curve` when none are given.
+pub const DEFAULT_ABSTAIN_THRESHOLDS: [f64; 10] = [0.0, 0.5, 0.6, 0.7, 0.75, 0.8, 0.85, 0.9, 0.95, 0.99];
+
+/// One operating point of a reject-option classifier.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct CoveragePoint {
+    /// Examples whose top-class probability is below this value are abstained on.
+    pub threshold: f64,
+    /// Fraction of examples the model still answers.
+    pub coverage: f64,
+    /// Accuracy on the answered examples (1.0 when nothing is answered).
+    pub accuracy: f64,
+}
+
+/// Accuracy-vs-coverage curve for an abstain threshold on the top-class probability.
+///
+/// # Arguments
+/// * `predictions` - Labeled predictions, e.g. from `Evaluator::predict_examples`.
+/// * `thresholds` - Abstain thresholds to evaluate.
+///
+/// # Returns
+/// * One `CoveragePoint` per threshold, in the given order.
+pub fn accuracy_coverage_curve(predictions: &[ExamplePrediction], thresholds: &[f64]) -> Vec<CoveragePoint> {
+    let labeled: Vec<&ExamplePrediction> = predictions.iter().filter(|p| p.label.is_some()).collect();
+
+    thresholds
+        .iter()
+        .map(|&threshold| {
+            let answered: Vec<&&ExamplePrediction> = labeled
+                .iter()
+                .filter(|p| p.prediction.probability >= threshold)
+                .collect();
+            let correct = answered.iter().filter(|p| p.is_correct()).count();
+
+            CoveragePoint {
+                threshold,
+                coverage: answered.len() as f64 / labeled.len().max(1) as f64,
+                accuracy: if answered.is_empty() { 1.0 } else { correct as f64 / answered.len() as f64 },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accuracy_rises_as_coverage_drops() {
+        let predictions = vec![
+            ExamplePrediction::new("a".to_string(), Some(0), vec![0.95, 0.05]),
+            ExamplePrediction::new("b".to_string(), Some(1), vec![0.2, 0.8]),
+            ExamplePrediction::new("c".to_string(), Some(1), vec![0.6, 0.4]),
+            ExamplePrediction::new("d".to_string(), Some(0), vec![0.55, 0.45]),
+        ];
+
+        let curve = accuracy_coverage_curve(&predictions, &[0.0, 0.7, 0.99]);
+
+        assert_eq!(curve[0].coverage, 1.0);
+        assert_eq!(curve[0].accuracy, 0.75);
+        assert_eq!(curve[1].coverage, 0.5);
+        assert_eq!(curve[1].accuracy, 1.0);
+        assert_eq!(curve[2].coverage, 0.0);
+        assert_eq!(curve[2].accuracy, 1.0);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::model_inference::inference::ExamplePrediction;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+/// Confidence bins of the expected calibration error reported when calibrating.
+pub const CALIBRATION_BINS: usize = 10;
+
+/// Probabilities are clipped to `[CLIP, 1 - CLIP]` before taking their log-odds.
+const CLIP: f64 = 1e-7;
+
+/// Maximum Newton steps when fitting Platt scaling.
+const PLATT_ITERATIONS: usize = 100;
+
+/// How a `ScoreCalibrator` is fitted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CalibrationMethod {
+    /// A sigmoid of the score's log-odds: two parameters, robust on small held-out sets.
+    Platt,
+    /// A non-decreasing step function fitted by pool-adjacent-violators: follows any
+    /// monotonic miscalibration, but needs a few hundred labelled examples.
+    Isotonic,
+}
+
+/// Maps the class probabilities of one model version to calibrated probabilities, so a
+/// fixed probability threshold keeps its meaning (e.g. "90% of answers above 0.9 are
+/// correct") when a new checkpoint replaces the served one.
+///
+/// One mapping is shared by all classes and fitted one-vs-rest: every probability
+/// `p[k]` of a labelled example is a sample, correct when `k` is the label.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method")]
+pub enum ScoreCalibrator {
+    /// `sigmoid(slope * logit(p) + intercept)`.
+    Platt { slope: f64, intercept: f64 },
+    /// Linear interpolation through `(scores[i], calibrated[i])`, constant beyond the ends.
+    Isotonic { scores: Vec<f64>, calibrated: Vec<f64> },
+}
+
+impl ScoreCalibrator {
+    /// Fits a calibrator to the predictions of a model on a held-out dataset.
+    ///
+    /// # Arguments
+    /// * `method` - Platt scaling or isotonic regression.
+    /// * `predictions` - Predictions of the model being calibrated, e.g. from
+    ///   `Evaluator::predict_examples`. Unlabelled examples are ignored.
+    ///
+    /// # Returns
+    /// * An error if no prediction is labelled.
+    pub fn fit(method: CalibrationMethod, predictions: &[ExamplePrediction]) -> Result<Self, Box<dyn Error>> {
+        let samples: Vec<(f64, bool)> = predictions
+            .iter()
+            .filter_map(|prediction| prediction.label.map(|label| (prediction, label)))
+            .flat_map(|(prediction, label)| prediction.prediction.probabilities.iter().enumerate().map(move |(class, &p)| (p, class == label)))
+            .collect();
+        if samples.is_empty() {
+            return Err("Score calibration needs labelled predictions".into());
+        }
+        Ok(match method {
+            CalibrationMethod::Platt => fit_platt(&samples),
+            CalibrationMethod::Isotonic => fit_isotonic(&samples),
+        })
+    }
+
+    /// Calibrated value of a single probability.
+    pub fn calibrate_score(&self, score: f64) -> f64 {
+        match self {
+            ScoreCalibrator::Platt { slope, intercept } => sigmoid(slope * logit(score) + intercept),
+            ScoreCalibrator::Isotonic { scores, calibrated } => interpolate(scores, calibrated, score),
+        }
+    }
+
+    /// Calibrates every class probability and renormalizes them to sum to 1. The mapping is
+    /// monotonic, so the order of the classes is kept (isotonic steps may tie them).
+    pub fn apply(&self, probabilities: &[f64]) -> Vec<f64> {
+        let calibrated: Vec<f64> = probabilities.iter().map(|&p| self.calibrate_score(p)).collect();
+        let total: f64 = calibrated.iter().sum();
+        if total > 0.0 {
+            calibrated.iter().map(|p| p / total).collect()
+        } else {
+            probabilities.to_vec()
+        }
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), std::io::Error> {
+        fs::write(file_path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(file_path)?)?)
+    }
+}
+
+/// Expected calibration error of the top-class probabilities: the mean gap between
+/// confidence and accuracy over `bins` equal-width confidence bins, weighted by the
+/// number of predictions in each.
+///
+/// # Returns
+/// * 0.0 for perfectly calibrated predictions, or when none is labelled.
+pub fn expected_calibration_error(predictions: &[ExamplePrediction], bins: usize) -> f64 {
+    let bins = bins.max(1);
+    let mut confidence = vec![0.0; bins];
+    let mut correct = vec![0.0; bins];
+    let mut counts = vec![0usize; bins];
+    for prediction in predictions {
+        if prediction.label.is_none() {
+            continue;
+        }
+        let top = prediction.prediction.probability;
+        let bin = ((top * bins as f64) as usize).min(bins - 1);
+        confidence[bin] += top;
+        correct[bin] += prediction.is_correct() as usize as f64;
+        counts[bin] += 1;
+    }
+    let total: usize = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    (0..bins).map(|bin| (confidence[bin] - correct[bin]).abs()).sum::<f64>() / total as f64
+}
+
+/// Platt scaling by Newton's method on the log loss, with Platt's smoothed targets
+/// `(positives + 1) / (positives + 2)` and `1 / (negatives + 2)` against overfitting.
+fn fit_platt(samples: &[(f64, bool)]) -> ScoreCalibrator {
+    let positives = samples.iter().filter(|(_, correct)| *correct).count() as f64;
+    let negatives = samples.len() as f64 - positives;
+    let (high, low) = ((positives + 1.0) / (positives + 2.0), 1.0 / (negatives + 2.0));
+    let data: Vec<(f64, f64)> = samples.iter().map(|&(p, correct)| (logit(p), if correct { high } else { low })).collect();
+
+    let loss = |slope: f64, intercept: f64| -> f64 {
+        data.iter()
+            .map(|&(x, t)| {
+                let f = sigmoid(slope * x + intercept).clamp(CLIP, 1.0 - CLIP);
+                -(t * f.ln() + (1.0 - t) * (1.0 - f).ln())
+            })
+            .sum()
+    };
+
+    let (mut slope, mut intercept) = (1.0, 0.0);
+    let mut current = loss(slope, intercept);
+    for _ in 0..PLATT_ITERATIONS {
+        let (mut g_slope, mut g_intercept, mut h_ss, mut h_si, mut h_ii) = (0.0, 0.0, 1e-12, 0.0, 1e-12);
+        for &(x, t) in &data {
+            let f = sigmoid(slope * x + intercept);
+            let weight = f * (1.0 - f);
+            g_slope += (f - t) * x;
+            g_intercept += f - t;
+            h_ss += weight * x * x;
+            h_si += weight * x;
+            h_ii += weight;
+        }
+        let determinant = h_ss * h_ii - h_si * h_si;
+        if determinant.abs() < 1e-18 {
+            break;
+        }
+        let step_slope = (h_ii * g_slope - h_si * g_intercept) / determinant;
+        let step_intercept = (h_ss * g_intercept - h_si * g_slope) / determinant;
+
+        // Halve the step until the loss stops increasing.
+        let mut scale = 1.0;
+        while scale > 1e-6 {
+            let candidate = loss(slope - scale * step_slope, intercept - scale * step_intercept);
+            if candidate <= current {
+                break;
+            }
+            scale /= 2.0;
+        }
+        slope -= scale * step_slope;
+        intercept -= scale * step_intercept;
+        let next = loss(slope, intercept);
+        let converged = (current - next).abs() < 1e-10;
+        current = next;
+        if converged {
+            break;
+        }
+    }
+    ScoreCalibrator::Platt { slope, intercept }
+}
+
+/// Isotonic regression by pool-adjacent-violators: blocks of sorted scores are merged
+/// while a block's accuracy is not above the previous one's. Each block becomes one knot
+/// at its mean score.
+fn fit_isotonic(samples: &[(f64, bool)]) -> ScoreCalibrator {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // (sum of scores, number of correct samples, number of samples)
+    let mut blocks: Vec<(f64, f64, f64)> = Vec::new();
+    for (score, correct) in sorted {
+        blocks.push((score, correct as usize as f64, 1.0));
+        while blocks.len() > 1 {
+            let (last, previous) = (blocks[blocks.len() - 1], blocks[blocks.len() - 2]);
+            if previous.1 / previous.2 < last.1 / last.2 {
+                break;
+            }
+            blocks.pop();
+            let merged = blocks.last_mut().unwrap();
+            *merged = (merged.0 + last.0, merged.1 + last.1, merged.2 + last.2);
+        }
+    }
+    ScoreCalibrator::Isotonic {
+        scores: blocks.iter().map(|&(sum, _, count)| sum / count).collect(),
+        calibrated: blocks.iter().map(|&(_, correct, count)| correct / count).collect(),
+    }
+}
+
+fn interpolate(xs: &[f64], ys: &[f64], x: f64) -> f64 {
+    let Some((&first, &last)) = xs.first().zip(xs.last()) else { return x };
+    if x <= first {
+        return ys[0];
+    }
+    if x >= last {
+        return ys[ys.len() - 1];
+    }
+    let upper = xs.partition_point(|&knot| knot < x);
+    let (x0, x1, y0, y1) = (xs[upper - 1], xs[upper], ys[upper - 1], ys[upper]);
+    if x1 == x0 {
+        return y1;
+    }
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+fn logit(p: f64) -> f64 {
+    let p = p.clamp(CLIP, 1.0 - CLIP);
+    (p / (1.0 - p)).ln()
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::temp_path;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// An overconfident model: it reports `confidence` but is right with probability `accuracy`.
+    fn overconfident(n: usize, seed: u64) -> Vec<ExamplePrediction> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|i| {
+                let confidence: f64 = rng.gen_range(0.6..1.0);
+                let accuracy = 0.5 + (confidence - 0.6) * 0.75;
+                let correct = rng.gen_bool(accuracy);
+                ExamplePrediction::new(i.to_string(), Some(if correct { 0 } else { 1 }), vec![confidence, 1.0 - confidence])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_calibration_reduces_expected_calibration_error() {
+        let fit = overconfident(2000, 1);
+        let held_out = overconfident(2000, 2);
+        let before = expected_calibration_error(&held_out, 10);
+        assert!(before > 0.1);
+
+        for method in [CalibrationMethod::Platt, CalibrationMethod::Isotonic] {
+            let calibrator = ScoreCalibrator::fit(method, &fit).unwrap();
+            let calibrated: Vec<ExamplePrediction> = held_out
+                .iter()
+                .map(|p| ExamplePrediction::new(p.id.clone(), p.label, calibrator.apply(&p.prediction.probabilities)))
+                .collect();
+            let after = expected_calibration_error(&calibrated, 10);
+            assert!(after < before / 2.0, "{:?}: {} -> {}", method, before, after);
+            // Calibration is monotonic.
+            assert!(calibrator.calibrate_score(0.9) >= calibrator.calibrate_score(0.7));
+
+            let path = temp_path(&format!("score_calibration_test_{:?}.json", method));
+            calibrator.save(&path).unwrap();
+            let loaded = ScoreCalibrator::load(&path);
+            fs::remove_file(&path).unwrap();
+            assert!((loaded.unwrap().calibrate_score(0.8) - calibrator.calibrate_score(0.8)).abs() < 1e-9);
+        }
+        assert!(ScoreCalibrator::fit(CalibrationMethod::Platt, &[ExamplePrediction::new("a".into(), None, vec![0.5, 0.5])]).is_err());
+    }
+
+    #[test]
+    fn test_isotonic_pools_violators() {
+        let samples = [(0.1, false), (0.2, true), (0.3, false), (0.8, true), (0.9, true)];
+        let ScoreCalibrator::Isotonic { scores, calibrated } = fit_isotonic(&samples) else { panic!("expected isotonic") };
+        assert_eq!(calibrated, vec![0.0, 0.5, 1.0]);
+        assert!((scores[1] - 0.25).abs() < 1e-12);
+        assert_eq!(interpolate(&scores, &calibrated, 0.0), 0.0);
+        assert!((interpolate(&scores, &calibrated, 0.175) - 0.25).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::data_handler::data_loader::RawRecord;
+use crate::model_evaluator::evaluator::{classification_metrics, EvaluationReport};
+use crate::model_inference::inference::ExamplePrediction;
+use std::collections::BTreeMap;
+
+/// Slice name used for records without a value for the slice field.
+pub const MISSING_SLICE: &str = "<missing>";
+
+/// Metrics of the examples sharing one metadata value.
+#[derive(Clone, Debug)]
+pub struct SliceReport {
+    pub slice: String,
+    pub count: usize,
+    pub report: EvaluationReport,
+}
+
+/// Groups predictions by the value of a metadata field of their records.
+///
+/// # Arguments
+/// * `records` - Dataset records, in the same order as `predictions`.
+/// * `predictions` - Predictions with ground-truth labels.
+/// * `field` - Metadata field to slice on (see `DataSchema::metadata_fields`).
+///
+/// # Returns
+/// * Predictions per slice value, ordered by value. Records without the field
+///   are grouped under `MISSING_SLICE`.
+pub fn group_by_metadata<'p>(
+    records: &[RawRecord],
+    predictions: &'p [ExamplePrediction],
+    field: &str,
+) -> BTreeMap<String, Vec<&'p ExamplePrediction>> {
+    assert_eq!(records.len(), predictions.len(), "Every record needs exactly one prediction.");
+
+    let mut groups: BTreeMap<String, Vec<&ExamplePrediction>> = BTreeMap::new();
+    for (record, prediction) in records.iter().zip(predictions.iter()) {
+        let slice = record.metadata.get(field).map_or(MISSING_SLICE, String::as_str);
+        groups.entry(slice.to_string()).or_default().push(prediction);
+    }
+    groups
+}
+
+/// Computes accuracy, precision, recall and F1-score for every slice.
+/// Predictions without a ground-truth label are counted but not scored.
+pub fn slice_reports(
+    groups: &BTreeMap<String, Vec<&ExamplePrediction>>,
+    num_classes: usize,
+) -> Vec<SliceReport> {
+    groups
+        .iter()
+        .map(|(slice, predictions)| {
+            let (predicted, labels): (Vec<usize>, Vec<usize>) = predictions
+                .iter()
+                .filter_map(|p| p.label.map(|label| (p.prediction.label_id, label)))
+                .unzip();
+            let correct = predicted.iter().zip(labels.iter()).filter(|(p, l)| p == l).count();
+            let (precision, recall, f1_score) = classification_metrics(&predicted, &labels, num_classes);
+
+            SliceReport {
+                slice: slice.clone(),
+                count: predictions.len(),
+                report: EvaluationReport {
+                    accuracy: correct as f64 / labels.len().max(1) as f64,
+                    precision,
+                    recall,
+                    f1_score,
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn record(language: Option<&str>) -> RawRecord {
+        RawRecord {
+            id: String::new(),
+            text: String::new(),
+            label: Some(0),
+            metadata: language
+                .map(|value| HashMap::from([("language".to_string(), value.to_string())]))
+                .unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_slice_reports_by_language() {
+        let records = vec![record(Some("en")), record(Some("de")), record(Some("en")), record(None)];
+        let predictions = vec![
+            ExamplePrediction::new("0".to_string(), Some(0), vec![0.9, 0.1]),
+            ExamplePrediction::new("1".to_string(), Some(0), vec![0.2, 0.8]),
+            ExamplePrediction::new("2".to_string(), Some(1), vec![0.3, 0.7]),
+            ExamplePrediction::new("3".to_string(), Some(1), vec![0.6, 0.4]),
+        ];
+
+        let groups = group_by_metadata(&records, &predictions, "language");
+        let reports = slice_reports(&groups, 2);
+
+        let slices: Vec<&str> = reports.iter().map(|r| r.slice.as_str()).collect();
+        assert_eq!(slices, vec![MISSING_SLICE, "de", "en"]);
+        assert_eq!(reports[1].report.accuracy, 0.0);
+        assert_eq!(reports[2].count, 2);
+        assert_eq!(reports[2].report.accuracy, 1.0);
+    }
+}
@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why a request was not authenticated. Both cases should be answered with
+/// `401 Unauthorized` and a `WWW-Authenticate: Bearer` header.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthError {
+    /// Neither an `Authorization: Bearer` nor an `X-API-Key` header was sent.
+    MissingCredentials,
+    /// The key is not one of the configured keys.
+    InvalidKey,
+}
+
+impl AuthError {
+    pub fn status(&self) -> u16 {
+        401
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingCredentials => write!(f, "Missing API key; send 'Authorization: Bearer <key>' or 'X-API-Key: <key>'"),
+            AuthError::InvalidKey => write!(f, "Invalid API key"),
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+/// A named client key.
+#[derive(Clone)]
+struct ApiKey {
+    client: String,
+    key: String,
+}
+
+/// Bearer-token / API-key authentication for a serving endpoint. Every key belongs to a
+/// named client, which can also be used as the `RateLimiter` key.
+#[derive(Clone, Default)]
+pub struct ApiKeyAuth {
+    keys: Vec<ApiKey>,
+}
+
+impl fmt::Debug for ApiKeyAuth {
+    // Keys are secrets; only the client names are printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.keys.iter().map(|key| &key.client)).finish()
+    }
+}
+
+impl ApiKeyAuth {
+    /// Adds the key of a client.
+    pub fn with_key(mut self, client: &str, key: &str) -> Result<Self, Box<dyn Error>> {
+        if key.len() < 16 {
+            return Err(format!("API key of {} must have at least 16 characters", client).into());
+        }
+        if self.keys.iter().any(|existing| existing.key == key) {
+            return Err(format!("API key of {} is already in use", client).into());
+        }
+        self.keys.push(ApiKey { client: client.to_string(), key: key.to_string() });
+        Ok(self)
+    }
+
+    /// Loads keys from a file with one `<client> <key>` pair per line; empty lines and lines
+    /// starting with `#` are skipped.
+    pub fn from_file(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut auth = ApiKeyAuth::default();
+        for (number, line) in std::fs::read_to_string(file_path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [client, key] => auth = auth.with_key(client, key)?,
+                _ => return Err(format!("{}:{}: expected '<client> <key>'", file_path, number + 1).into()),
+            }
+        }
+        Ok(auth)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Authenticates a request from its headers.
+    ///
+    /// # Arguments
+    /// * `authorization` - The `Authorization` header, e.g. `Bearer <key>`.
+    /// * `api_key` - The `X-API-Key` header, used when there is no bearer token.
+    ///
+    /// # Returns
+    /// * The name of the client the key belongs to.
+    pub fn authenticate(&self, authorization: Option<&str>, api_key: Option<&str>) -> Result<&str, AuthError> {
+        let bearer = authorization.and_then(|value| {
+            let (scheme, token) = value.trim().split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        });
+        let presented = bearer.or(api_key.map(str::trim)).ok_or(AuthError::MissingCredentials)?;
+        // Every key is compared in full, so timing does not reveal which prefix matched.
+        self.keys
+            .iter()
+            .fold(None, |found, key| if constant_time_eq(key.key.as_bytes(), presented.as_bytes()) { Some(key) } else { found })
+            .map(|key| key.client.as_str())
+            .ok_or(AuthError::InvalidKey)
+    }
+}
+
+/// Compares two byte strings in time that depends only on their lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::temp_path;
+
+    #[test]
+    fn test_authenticate_bearer_and_api_key_headers() {
+        let auth = ApiKeyAuth::default()
+            .with_key("dashboard", "k-0123456789abcdef")
+            .unwrap()
+            .with_key("batch-jobs", "k-fedcba9876543210")
+            .unwrap();
+
+        assert_eq!(auth.authenticate(Some("Bearer k-0123456789abcdef"), None), Ok("dashboard"));
+        assert_eq!(auth.authenticate(Some("bearer  k-fedcba9876543210 "), None), Ok("batch-jobs"));
+        assert_eq!(auth.authenticate(None, Some("k-fedcba9876543210")), Ok("batch-jobs"));
+        assert_eq!(auth.authenticate(Some("Bearer k-0123456789abcdeX"), None), Err(AuthError::InvalidKey));
+        assert_eq!(auth.authenticate(Some("Basic dXNlcjpwYXNz"), None), Err(AuthError::MissingCredentials));
+        assert_eq!(auth.authenticate(None, None).unwrap_err().status(), 401);
+
+        assert!(ApiKeyAuth::default().with_key("short", "abc").is_err());
+        assert!(auth.clone().with_key("copy", "k-0123456789abcdef").is_err());
+        assert!(!format!("{:?}", auth).contains("k-0123"));
+    }
+
+    #[test]
+    fn test_from_file() {
+        let path = &temp_path("api_keys_test.txt");
+        std::fs::write(path, "# clients\ndashboard k-0123456789abcdef\n\nbroken\n").unwrap();
+        let result = ApiKeyAuth::from_file(path);
+        std::fs::write(path, "# clients\ndashboard k-0123456789abcdef\n").unwrap();
+        let loaded = ApiKeyAuth::from_file(path);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(result.unwrap_err().to_string().contains(":4:"));
+        assert_eq!(loaded.unwrap().authenticate(None, Some("k-0123456789abcdef")), Ok("dashboard"));
+    }
+}
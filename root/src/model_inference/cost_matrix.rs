@@ -0,0 +1,154 @@
+use serde::{Serialize, Deserialize};
+use std::error::Error;
+
+/// Outcome of a cost-sensitive decision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    /// Predict this class.
+    Class(usize),
+    /// Abstain and defer the example, e.g. to human review.
+    Abstain,
+}
+
+/// Misclassification costs used to pick the class with the lowest expected cost
+/// instead of the most probable one.
+///
+/// `costs[i][j]` is the cost of predicting class `j` when the true class is `i`.
+/// The optional `abstain_costs[i]` is the cost of abstaining when the true class is `i`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CostMatrix {
+    pub costs: Vec<Vec<f64>>,
+    pub abstain_costs: Option<Vec<f64>>,
+}
+
+impl CostMatrix {
+    /// Creates a cost matrix, checking that it is square and non-negative.
+    pub fn new(costs: Vec<Vec<f64>>) -> Result<Self, Box<dyn Error>> {
+        let num_classes = costs.len();
+        if num_classes == 0 {
+            return Err("Cost matrix must have at least one class".into());
+        }
+        if costs.iter().any(|row| row.len() != num_classes) {
+            return Err(format!("Cost matrix must be {0}x{0}", num_classes).into());
+        }
+        if costs.iter().flatten().any(|&cost| cost < 0.0 || !cost.is_finite()) {
+            return Err("Costs must be finite and non-negative".into());
+        }
+        Ok(CostMatrix { costs, abstain_costs: None })
+    }
+
+    /// Enables abstention with one cost per true class.
+    pub fn with_abstain_costs(mut self, abstain_costs: Vec<f64>) -> Result<Self, Box<dyn Error>> {
+        if abstain_costs.len() != self.num_classes() {
+            return Err(format!("Expected {} abstain costs, got {}", self.num_classes(), abstain_costs.len()).into());
+        }
+        if abstain_costs.iter().any(|&cost| cost < 0.0 || !cost.is_finite()) {
+            return Err("Abstain costs must be finite and non-negative".into());
+        }
+        self.abstain_costs = Some(abstain_costs);
+        Ok(self)
+    }
+
+    /// Loads a cost matrix from a JSON file such as
+    /// `{"costs": [[0, 1], [5, 0]], "abstain_costs": [0.5, 0.5]}`.
+    pub fn from_file(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        let data = std::fs::read_to_string(file_path)?;
+        let matrix: CostMatrix = serde_json::from_str(&data)?;
+        let validated = CostMatrix::new(matrix.costs)?;
+        match matrix.abstain_costs {
+            Some(abstain_costs) => validated.with_abstain_costs(abstain_costs),
+            None => Ok(validated),
+        }
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.costs.len()
+    }
+
+    /// Expected cost of predicting each class: `Σ_i p(i) · costs[i][j]`.
+    pub fn expected_costs(&self, probabilities: &[f64]) -> Vec<f64> {
+        assert_eq!(probabilities.len(), self.num_classes(), "Probabilities must cover every class of the cost matrix.");
+        (0..self.num_classes())
+            .map(|predicted| {
+                probabilities
+                    .iter()
+                    .zip(self.costs.iter())
+                    .map(|(p, row)| p * row[predicted])
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Expected cost of abstaining, if abstention is enabled.
+    pub fn expected_abstain_cost(&self, probabilities: &[f64]) -> Option<f64> {
+        self.abstain_costs
+            .as_ref()
+            .map(|costs| probabilities.iter().zip(costs.iter()).map(|(p, c)| p * c).sum())
+    }
+
+    /// The class with the lowest expected cost, ignoring abstention.
+    pub fn min_cost_class(&self, probabilities: &[f64]) -> usize {
+        self.expected_costs(probabilities)
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    }
+
+    /// Picks the class with the lowest expected cost, or abstains when that is cheaper.
+    pub fn decide(&self, probabilities: &[f64]) -> Decision {
+        let class = self.min_cost_class(probabilities);
+        let class_cost = self.expected_costs(probabilities)[class];
+
+        match self.expected_abstain_cost(probabilities) {
+            Some(abstain_cost) if abstain_cost < class_cost => Decision::Abstain,
+            _ => Decision::Class(class),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_costly_false_negatives_shift_decision() {
+        // Missing class 1 (e.g. harmful content) is ten times worse than a false alarm.
+        let matrix = CostMatrix::new(vec![vec![0.0, 1.0], vec![10.0, 0.0]]).unwrap();
+        let probabilities = [0.8, 0.2];
+
+        assert_eq!(matrix.expected_costs(&probabilities), vec![2.0, 0.8]);
+        assert_eq!(matrix.decide(&probabilities), Decision::Class(1));
+    }
+
+    #[test]
+    fn test_abstain_when_cheaper() {
+        let matrix = CostMatrix::new(vec![vec![0.0, 4.0], vec![4.0, 0.0]])
+            .unwrap()
+            .with_abstain_costs(vec![1.0, 1.0])
+            .unwrap();
+
+        assert_eq!(matrix.decide(&[0.5, 0.5]), Decision::Abstain);
+        assert_eq!(matrix.decide(&[0.95, 0.05]), Decision::Class(0));
+    }
+
+    #[test]
+    fn test_rejects_non_square_matrix() {
+        assert!(CostMatrix::new(vec![vec![0.0, 1.0], vec![1.0]]).is_err());
+    }
+
+    #[test]
+    fn test_from_file_validates_costs() {
+        let path = &crate::test_utils::fixtures::temp_path("cost_matrix_from_file.json");
+        std::fs::write(path, r#"{"costs": [[0, 1], [5, 0]], "abstain_costs": [0.25, 0.25]}"#).unwrap();
+        let matrix = CostMatrix::from_file(path).unwrap();
+        std::fs::write(path, r#"{"costs": [[0, 1], [5, 0]], "abstain_costs": [0.5]}"#).unwrap();
+        let mismatched = CostMatrix::from_file(path);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(matrix.abstain_costs, Some(vec![0.25, 0.25]));
+        assert_eq!(matrix.decide(&[0.5, 0.5]), Decision::Abstain);
+        assert!(mismatched.is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use crate::cross_entropy::loss::Loss;
+use crate::model_inference::inference::{argmax, ExamplePrediction, Inference};
+use crate::model_inference::prediction::Prediction;
+use ndarray::{Array1, Array2, Axis};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::time::Instant;
+
+/// Member probabilities are clipped to at least `CLIP` before taking their log.
+const CLIP: f64 = 1e-7;
+
+/// Full-batch gradient steps when fitting a `StackingHead`.
+pub const STACKING_EPOCHS: usize = 500;
+pub const STACKING_LEARNING_RATE: f64 = 0.1;
+/// Strength of the L2 penalty that pulls a `StackingHead` towards plain averaging, so
+/// a small validation set cannot move it far from the members' consensus.
+pub const STACKING_L2: f64 = 1e-3;
+/// Folds of `StackingHead::cross_validate` when `fit-ensemble` reports the head's accuracy.
+pub const STACKING_FOLDS: usize = 5;
+
+/// A learned combination of the class probabilities of several models (stacking).
+///
+/// A softmax regression over the concatenated log-probabilities of every member:
+/// `softmax(W · [log p_1, ..., log p_M] + b)`. It starts as the average of the members'
+/// log-probabilities and is fitted on their predictions for a held-out dataset, so it can
+/// learn how far to trust each member, per class, and which classes members confuse.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StackingHead {
+    pub num_members: usize,
+    pub num_classes: usize,
+    /// Shape: [num_members * num_classes, num_classes].
+    pub weights: Array2<f64>,
+    /// Shape: [num_classes].
+    pub bias: Array1<f64>,
+}
+
+impl StackingHead {
+    /// A head that averages the members' log-probabilities.
+    pub fn averaging(num_members: usize, num_classes: usize) -> Self {
+        let mut weights = Array2::zeros((num_members * num_classes, num_classes));
+        for member in 0..num_members {
+            for class in 0..num_classes {
+                weights[[member * num_classes + class, class]] = 1.0 / num_members as f64;
+            }
+        }
+        StackingHead { num_members, num_classes, weights, bias: Array1::zeros(num_classes) }
+    }
+
+    /// Fits a head to the members' predictions on a held-out dataset.
+    ///
+    /// # Arguments
+    /// * `member_predictions` - One list per member, e.g. from `Inference::predict_records`,
+    ///   all over the same examples in the same order. Unlabelled examples are ignored.
+    ///
+    /// # Returns
+    /// * An error if the lists disagree on examples or classes, or no example is labelled.
+    pub fn fit(member_predictions: &[Vec<ExamplePrediction>]) -> Result<Self, Box<dyn Error>> {
+        let (num_members, num_classes) = check_members(member_predictions)?;
+        let labelled: Vec<usize> = (0..member_predictions[0].len()).filter(|&i| member_predictions[0][i].label.is_some()).collect();
+        if labelled.is_empty() {
+            return Err("Fitting a stacking head needs labelled predictions".into());
+        }
+
+        let mut features = Array2::zeros((labelled.len(), num_members * num_classes));
+        let mut targets = Array2::zeros((labelled.len(), num_classes));
+        for (mut row, &example) in features.outer_iter_mut().zip(&labelled) {
+            let member_probabilities: Vec<&[f64]> = member_predictions.iter().map(|predictions| predictions[example].prediction.probabilities.as_slice()).collect();
+            row.assign(&log_features(&member_probabilities));
+        }
+        for (mut row, &example) in targets.outer_iter_mut().zip(&labelled) {
+            let label = member_predictions[0][example].label.unwrap();
+            if label >= num_classes {
+                return Err(format!("Example {} has label {} but the members predict {} classes", member_predictions[0][example].id, label, num_classes).into());
+            }
+            row[label] = 1.0;
+        }
+
+        let prior = StackingHead::averaging(num_members, num_classes);
+        let mut head = prior.clone();
+        let num_examples = labelled.len() as f64;
+        for _ in 0..STACKING_EPOCHS {
+            let probabilities = Loss::softmax(&(features.dot(&head.weights) + &head.bias));
+            // Gradient of the mean cross-entropy with respect to the logits.
+            let grad_logits = (probabilities - &targets) / num_examples;
+            let grad_weights = features.t().dot(&grad_logits) + (&head.weights - &prior.weights) * STACKING_L2;
+            let grad_bias = grad_logits.sum_axis(Axis(0)) + &head.bias * STACKING_L2;
+            head.weights.scaled_add(-STACKING_LEARNING_RATE, &grad_weights);
+            head.bias.scaled_add(-STACKING_LEARNING_RATE, &grad_bias);
+        }
+        Ok(head)
+    }
+
+    /// Out-of-fold predictions of stacking: example `i` falls into fold `i % folds`, and each
+    /// fold is combined by a head fitted on the other folds. Their accuracy estimates how a
+    /// head fitted on all examples does on new data, which its accuracy on its own training
+    /// examples overstates.
+    ///
+    /// # Returns
+    /// * One prediction per example, in order, or an error for fewer than two folds, more
+    ///   folds than examples, or a fold whose complement has no labelled example.
+    pub fn cross_validate(member_predictions: &[Vec<ExamplePrediction>], folds: usize) -> Result<Vec<ExamplePrediction>, Box<dyn Error>> {
+        check_members(member_predictions)?;
+        let num_examples = member_predictions[0].len();
+        if folds < 2 || folds > num_examples {
+            return Err(format!("Cannot cross-validate {} examples in {} folds", num_examples, folds).into());
+        }
+
+        let mut combined = vec![None; num_examples];
+        for fold in 0..folds {
+            let split = |held_out: bool| -> Vec<Vec<ExamplePrediction>> {
+                member_predictions
+                    .iter()
+                    .map(|predictions| predictions.iter().enumerate().filter(|(i, _)| (i % folds == fold) == held_out).map(|(_, prediction)| prediction.clone()).collect())
+                    .collect()
+            };
+            let head = StackingHead::fit(&split(false))?;
+            let predictions = EnsembleCombiner::Stacking(head).combine_predictions(&split(true))?;
+            for (i, prediction) in (fold..num_examples).step_by(folds).zip(predictions) {
+                combined[i] = Some(prediction);
+            }
+        }
+        Ok(combined.into_iter().flatten().collect())
+    }
+
+    /// Combined class probabilities of one example.
+    ///
+    /// # Arguments
+    /// * `member_probabilities` - Class probabilities of every member, in member order.
+    pub fn probabilities(&self, member_probabilities: &[&[f64]]) -> Vec<f64> {
+        let features = log_features(member_probabilities).insert_axis(Axis(0));
+        Loss::softmax(&(features.dot(&self.weights) + &self.bias)).row(0).to_vec()
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), std::io::Error> {
+        fs::write(file_path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(file_path)?)?)
+    }
+}
+
+/// How an `Ensemble` combines the probabilities of its members.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum EnsembleCombiner {
+    /// The mean of the members' probabilities.
+    #[default]
+    Average,
+    Stacking(StackingHead),
+}
+
+impl EnsembleCombiner {
+    /// Combined class probabilities of one example, from every member's probabilities.
+    pub fn combine(&self, member_probabilities: &[&[f64]]) -> Vec<f64> {
+        match self {
+            EnsembleCombiner::Average => {
+                let mut mean = vec![0.0; member_probabilities[0].len()];
+                for probabilities in member_probabilities {
+                    for (sum, p) in mean.iter_mut().zip(probabilities.iter()) {
+                        *sum += p / member_probabilities.len() as f64;
+                    }
+                }
+                mean
+            }
+            EnsembleCombiner::Stacking(head) => head.probabilities(member_probabilities),
+        }
+    }
+
+    /// Combines the members' predictions of a dataset, e.g. to compare combiners on a
+    /// labelled held-out set.
+    ///
+    /// # Returns
+    /// * One prediction per example, or an error if the lists disagree on examples or classes.
+    pub fn combine_predictions(&self, member_predictions: &[Vec<ExamplePrediction>]) -> Result<Vec<ExamplePrediction>, Box<dyn Error>> {
+        check_members(member_predictions)?;
+        Ok((0..member_predictions[0].len())
+            .map(|i| {
+                let first = &member_predictions[0][i];
+                let member_probabilities: Vec<&[f64]> = member_predictions.iter().map(|predictions| predictions[i].prediction.probabilities.as_slice()).collect();
+                ExamplePrediction::new(first.id.clone(), first.label, self.combine(&member_probabilities))
+            })
+            .collect())
+    }
+}
+
+/// Several models that classify into the same classes, combined into one prediction.
+///
+/// The members keep their own tokenizers, calibrators, input templates and overflow
+/// policies; they must share a label map, and the first member's top-k is used for the
+/// combined prediction.
+pub struct Ensemble {
+    pub members: Vec<Inference>,
+    pub combiner: EnsembleCombiner,
+}
+
+impl Ensemble {
+    /// Averages the probabilities of `members` until a stacking head is set.
+    ///
+    /// # Returns
+    /// * An error without members, or if they predict different numbers of classes or name
+    ///   their classes with different label maps.
+    pub fn new(members: Vec<Inference>) -> Result<Self, Box<dyn Error>> {
+        let Some(first) = members.first() else {
+            return Err("An ensemble needs at least one member".into());
+        };
+        let num_classes = first.model.config.num_classes;
+        if let Some(member) = members.iter().position(|member| member.model.config.num_classes != num_classes) {
+            return Err(format!("Ensemble member {} predicts {} classes but member 0 predicts {}", member, members[member].model.config.num_classes, num_classes).into());
+        }
+        if let Some(member) = members.iter().position(|member| member.label_map != first.label_map) {
+            return Err(format!("Ensemble member {} names its classes differently from member 0", member).into());
+        }
+        Ok(Ensemble { members, combiner: EnsembleCombiner::Average })
+    }
+
+    /// Combines the members with a fitted stacking head.
+    ///
+    /// # Returns
+    /// * An error if the head was fitted for a different number of members or classes.
+    pub fn with_stacking_head(mut self, head: StackingHead) -> Result<Self, Box<dyn Error>> {
+        let num_classes = self.members[0].model.config.num_classes;
+        if head.num_members != self.members.len() || head.num_classes != num_classes {
+            return Err(format!(
+                "The stacking head combines {} members of {} classes, the ensemble has {} members of {} classes",
+                head.num_members, head.num_classes, self.members.len(), num_classes
+            )
+            .into());
+        }
+        self.combiner = EnsembleCombiner::Stacking(head);
+        Ok(self)
+    }
+
+    /// Predicts a text with every member and combines their probabilities.
+    ///
+    /// # Returns
+    /// * The combined prediction; its `model_version` joins the members' versions with `+`
+    ///   and its `overflow` is the first member's. An error if any member fails.
+    pub fn predict(&self, input_text: &str) -> Result<Prediction, Box<dyn Error>> {
+        let start = Instant::now();
+        let predictions = self.members.iter().map(|member| member.predict(input_text)).collect::<Result<Vec<_>, _>>()?;
+        Ok(self.combine(&predictions, start))
+    }
+
+    /// Same as `predict` for a multi-field record, which every member renders with its own
+    /// input template (see `Inference::predict_fields`).
+    ///
+    /// # Returns
+    /// * An error if a member has no template or the record lacks one of its fields.
+    pub fn predict_fields(&self, fields: &HashMap<String, String>) -> Result<Prediction, Box<dyn Error>> {
+        let start = Instant::now();
+        let predictions = self.members.iter().map(|member| member.predict_fields(fields)).collect::<Result<Vec<_>, _>>()?;
+        Ok(self.combine(&predictions, start))
+    }
+
+    /// Combines the members' predictions of one input, in member order.
+    fn combine(&self, predictions: &[Prediction], start: Instant) -> Prediction {
+        let member_probabilities: Vec<&[f64]> = predictions.iter().map(|prediction| prediction.probabilities.as_slice()).collect();
+        let probabilities = self.combiner.combine(&member_probabilities);
+
+        let first = &self.members[0];
+        let model_version = predictions
+            .iter()
+            .map(|prediction| prediction.model_version.clone())
+            .collect::<Option<Vec<_>>>()
+            .map(|versions| versions.join("+"));
+        let overflow = predictions[0].overflow;
+        Prediction::new(argmax(&probabilities), probabilities, first.top_k, first.label_map.as_ref())
+            .with_model_version(model_version)
+            .with_overflow(overflow)
+            .with_latency(start.elapsed())
+    }
+}
+
+/// Concatenated log-probabilities of the members. Shape: [num_members * num_classes].
+fn log_features(member_probabilities: &[&[f64]]) -> Array1<f64> {
+    member_probabilities.iter().flat_map(|probabilities| probabilities.iter().map(|&p| p.max(CLIP).ln())).collect()
+}
+
+/// Checks that every member predicted the same examples with the same number of classes.
+///
+/// # Returns
+/// * `(num_members, num_classes)`.
+fn check_members(member_predictions: &[Vec<ExamplePrediction>]) -> Result<(usize, usize), Box<dyn Error>> {
+    let Some(first) = member_predictions.first() else {
+        return Err("An ensemble needs at least one member".into());
+    };
+    let num_classes = first.first().map(|prediction| prediction.prediction.probabilities.len()).ok_or("The members predicted no examples")?;
+    for (member, predictions) in member_predictions.iter().enumerate() {
+        if predictions.len() != first.len() {
+            return Err(format!("Ensemble member {} predicted {} examples but member 0 predicted {}", member, predictions.len(), first.len()).into());
+        }
+        for (prediction, reference) in predictions.iter().zip(first) {
+            if prediction.id != reference.id || prediction.prediction.probabilities.len() != num_classes {
+                return Err(format!("Ensemble member {} disagrees with member 0 on example {}", member, reference.id).into());
+            }
+        }
+    }
+    Ok((member_predictions.len(), num_classes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_handler::input_template::InputTemplate;
+    use crate::data_handler::label_map::LabelMap;
+    use crate::test_utils::fixtures::{tiny_config, tiny_vocab};
+    use crate::tokenization::tokenizer::Tokenizer;
+    use crate::transformer::{Transformer, TransformerConfig};
+
+    fn accuracy(predictions: &[ExamplePrediction]) -> f64 {
+        predictions.iter().filter(|prediction| prediction.is_correct()).count() as f64 / predictions.len() as f64
+    }
+
+    #[test]
+    fn test_stacking_beats_averaging() {
+        // Member 0 is right but unsure; member 1 is confidently wrong on class 0.
+        let labels = [0, 1, 2, 0, 1, 2, 0, 0];
+        let member = |probabilities: &dyn Fn(usize) -> Vec<f64>| -> Vec<ExamplePrediction> {
+            labels.iter().enumerate().map(|(i, &label)| ExamplePrediction::new(i.to_string(), Some(label), probabilities(label))).collect()
+        };
+        let unsure = member(&|label| (0..3).map(|class| if class == label { 0.4 } else { 0.3 }).collect());
+        let confused = member(&|label| if label == 0 { vec![0.05, 0.9, 0.05] } else { (0..3).map(|class| if class == label { 0.8 } else { 0.1 }).collect() });
+        let members = vec![unsure, confused];
+
+        let averaged = EnsembleCombiner::Average.combine_predictions(&members).unwrap();
+        assert!(accuracy(&averaged) < 0.6);
+
+        let head = StackingHead::fit(&members).unwrap();
+        let stacked = EnsembleCombiner::Stacking(head.clone()).combine_predictions(&members).unwrap();
+        assert_eq!(accuracy(&stacked), 1.0);
+        assert!((stacked[0].prediction.probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+
+        let json = serde_json::to_string(&head).unwrap();
+        let loaded: StackingHead = serde_json::from_str(&json).unwrap();
+        assert!((loaded.weights - &head.weights).iter().all(|d| d.abs() < 1e-12));
+
+        let out_of_fold = StackingHead::cross_validate(&members, 4).unwrap();
+        assert_eq!(out_of_fold.iter().map(|prediction| prediction.id.as_str()).collect::<Vec<_>>(), ["0", "1", "2", "3", "4", "5", "6", "7"]);
+        assert!(StackingHead::cross_validate(&members, 9).is_err());
+
+        // Members must cover the same examples.
+        let mut short = members.clone();
+        short[1].pop();
+        assert!(StackingHead::fit(&short).is_err());
+    }
+
+    #[test]
+    fn test_ensemble_predict() {
+        let vocab = tiny_vocab(&["late"]);
+        let config = tiny_config(3);
+        let member = || Inference::from_parts(Transformer::new(config.clone(), vocab.clone()), Tokenizer::new(vocab.clone(), 8)).unwrap();
+        let ensemble = Ensemble::new(vec![member(), member()]).unwrap();
+
+        let expected: Vec<Vec<f64>> = ensemble.members.iter().map(|member| member.predict("late").unwrap().probabilities).collect();
+        let prediction = ensemble.predict("late").unwrap();
+        for (class, p) in prediction.probabilities.iter().enumerate() {
+            assert!((p - (expected[0][class] + expected[1][class]) / 2.0).abs() < 1e-12);
+        }
+
+        assert!(ensemble.with_stacking_head(StackingHead::averaging(3, 3)).is_err());
+        let two_classes = TransformerConfig { num_classes: 2, ..config.clone() };
+        let other = Inference::from_parts(Transformer::new(two_classes, vocab.clone()), Tokenizer::new(vocab.clone(), 8)).unwrap();
+        assert!(Ensemble::new(vec![member(), other]).is_err());
+
+        let named = |names: &[&str]| member().with_label_map(LabelMap::from_names(names).unwrap()).unwrap();
+        assert!(Ensemble::new(vec![named(&["a", "b", "c"]), named(&["a", "b", "c"])]).is_ok());
+        assert!(Ensemble::new(vec![named(&["a", "b", "c"]), named(&["c", "b", "a"])]).is_err());
+    }
+
+    #[test]
+    fn test_ensemble_predict_fields() {
+        let vocab = tiny_vocab(&["late", "parcel"]);
+        let member = || Inference::from_parts(Transformer::new(tiny_config(2), vocab.clone()), Tokenizer::new(vocab.clone(), 8)).unwrap();
+        let fields = HashMap::from([("title".to_string(), "late".to_string()), ("body".to_string(), "parcel".to_string())]);
+        assert!(Ensemble::new(vec![member(), member()]).unwrap().predict_fields(&fields).is_err());
+
+        let template = InputTemplate::parse("{title} {body}").unwrap();
+        let ensemble = Ensemble::new(vec![member().with_input_template(template.clone()), member().with_input_template(template)]).unwrap();
+        assert_eq!(ensemble.predict_fields(&fields).unwrap().probabilities, ensemble.predict("late parcel").unwrap().probabilities);
+    }
+}
@@ -1,58 +1,688 @@
 use crate::transformer::Transformer;
 use crate::tokenization::tokenizer::Tokenizer;
-use ndarray::Array2;
+use crate::tokenization::offsets::Offset;
+use crate::data_handler::data_loader::{DataLoader, ModelInputs, RawRecord};
+use crate::data_handler::label_map::LabelMap;
+use crate::classification::ClassPrototypes;
+use crate::classification::word_pooling::{pool_words, WordPooling};
+use crate::cross_entropy::loss::Loss;
+use crate::configurration::config::{PAD_TOKEN, PREDICTION_TOP_K, SEP_TOKEN, UNK_TOKEN};
+use crate::model_inference::cost_matrix::{CostMatrix, Decision};
+use crate::model_evaluator::promotion::{SERVING_CALIBRATION_FILE, SERVING_LABEL_MAP_FILE, SERVING_MODEL_FILE, SERVING_TOKENIZER_FILE};
+use crate::model_evaluator::score_calibration::ScoreCalibrator;
+use crate::model_inference::prediction::Prediction;
+use crate::data_handler::input_template::InputTemplate;
+use crate::experiment::dataset_version::fnv1a;
+use crate::quantization::calibration::{CalibrationRanges, DEFAULT_CALIBRATION_PERCENTILE};
+use crate::quantization::quantized_feed_forward::ffn_quantization_error;
+use crate::quantization::quantizer::Granularity;
+use ndarray::{Array1, Array2, Axis};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-pub struct Inference<'a> {
+/// `Prediction` for one dataset example, keyed by the example's id so it can be
+/// joined back to the source record.
+#[derive(Clone, Debug, Serialize)]
+pub struct ExamplePrediction {
+    pub id: String,
+    /// Ground-truth label, when the dataset provides one.
+    pub label: Option<usize>,
+    /// Name of `label`, when predicted with a label map.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_name: Option<String>,
+    #[serde(flatten)]
+    pub prediction: Prediction,
+}
+
+impl ExamplePrediction {
+    /// Builds a prediction of the most probable class, listing `PREDICTION_TOP_K` classes.
+    pub fn new(id: String, label: Option<usize>, probabilities: Vec<f64>) -> Self {
+        let prediction = Prediction::new(argmax(&probabilities), probabilities, PREDICTION_TOP_K, None);
+        ExamplePrediction { id, label, label_name: None, prediction }
+    }
+
+    /// Adds the class names of `label_map` to the label and the prediction; without one it
+    /// is unchanged.
+    pub fn with_label_names(mut self, label_map: Option<&LabelMap>) -> Self {
+        if let Some(label_map) = label_map {
+            self.label_name = self.label.map(|label| label_map.display_name(label));
+            self.prediction = self.prediction.with_label_names(Some(label_map));
+        }
+        self
+    }
+
+    /// Whether the predicted class is the true label; `false` for unlabelled examples.
+    pub fn is_correct(&self) -> bool {
+        self.label == Some(self.prediction.label_id)
+    }
+}
+
+/// What `Inference` does with texts that tokenize to more than `max_seq_length` tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OverflowPolicy {
+    /// Keeps `max_seq_length` tokens chosen by the tokenizer's `Truncation` strategy.
+    #[default]
+    Truncate,
+    /// Splits the tokens into consecutive windows of `max_seq_length` tokens and averages
+    /// the class probabilities of the windows, so no token is dropped.
+    ChunkAndAggregate,
+    /// Fails instead of predicting from part of the text.
+    Error,
+}
+
+/// How an input text fitted into `max_seq_length`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OverflowReport {
+    /// Tokens of the whole text.
+    pub input_tokens: usize,
+    /// Tokens the model never saw; only non-zero under `OverflowPolicy::Truncate`.
+    pub dropped_tokens: usize,
+    /// Windows the model was run on; more than 1 only under `OverflowPolicy::ChunkAndAggregate`.
+    pub chunks: usize,
+}
+
+impl OverflowReport {
+    pub fn truncated(&self) -> bool {
+        self.dropped_tokens > 0
+    }
+
+    pub fn chunked(&self) -> bool {
+        self.chunks > 1
+    }
+}
+
+/// Contribution of one input token to a prediction.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TokenImportance {
+    /// The token as decoded by the tokenizer.
+    pub token: String,
+    pub token_id: usize,
+    /// Byte range of the token in the explained text (see `Tokenizer::tokenize_with_word_ids`),
+    /// to highlight it.
+    pub offset: Offset,
+    /// Drop in the predicted class's probability when the token is masked out; negative
+    /// when the token argues against the prediction.
+    pub importance: f64,
+    /// Index of the word the token belongs to (see `Tokenizer::tokenize_with_word_ids`);
+    /// `None` for special tokens.
+    pub word: Option<usize>,
+}
+
+/// Importance of a whole word, pooled from its sub-word tokens by `Explanation::word_importances`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WordImportance {
+    /// The word as written in the explained text.
+    pub word: String,
+    pub offset: Offset,
+    pub importance: f64,
+}
+
+/// Rationale for one prediction, returned by `Inference::explain`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Explanation {
+    /// The explained text; the token offsets index into it.
+    pub text: String,
+    /// The explained prediction, as `predict` would return it.
+    #[serde(flatten)]
+    pub prediction: Prediction,
+    /// One entry per token the model saw, in input order.
+    pub tokens: Vec<TokenImportance>,
+    /// The token importances pooled to words with `Inference::word_pooling`.
+    pub words: Vec<WordImportance>,
+}
+
+impl Explanation {
+    /// Token importances pooled to words, so a word split into several sub-word pieces
+    /// gets one score. `explain` fills `words` with this; call it to pool differently.
+    ///
+    /// # Arguments
+    /// * `pooling` - How the importances of a word's pieces are combined.
+    pub fn word_importances(&self, pooling: WordPooling) -> Vec<WordImportance> {
+        let importances = Array1::from_iter(self.tokens.iter().map(|token| token.importance)).insert_axis(Axis(1));
+        let word_ids: Vec<Option<usize>> = self.tokens.iter().map(|token| token.word).collect();
+        let (words, pooled) = pool_words(importances.view(), &word_ids, pooling);
+        words
+            .iter()
+            .zip(pooled.column(0))
+            .map(|(&word, &importance)| {
+                let offset = word_span(self.tokens.iter().filter(|token| token.word == Some(word)).map(|token| token.offset));
+                WordImportance { word: self.text[offset.0..offset.1].to_string(), offset, importance }
+            })
+            .collect()
+    }
+}
+
+/// Byte range covering the given token ranges.
+fn word_span(offsets: impl Iterator<Item = Offset>) -> Offset {
+    offsets.reduce(|(start, end), (from, to)| (start.min(from), end.max(to))).unwrap_or_default()
+}
+
+/// How `Inference::predict` turns the encoder output into a class.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InferenceMode {
+    /// Argmax over the classification head's softmax probabilities.
+    Head,
+    /// Cosine similarity to class centroids computed from training data.
+    NearestCentroid,
+}
+
+pub struct Inference {
     pub model: Transformer,
-    pub tokenizer: &'a Tokenizer,
+    pub tokenizer: Tokenizer,
+    pub mode: InferenceMode,
+    pub prototypes: Option<ClassPrototypes>,
+    /// When set, predictions minimise expected misclassification cost instead of taking the argmax.
+    pub cost_matrix: Option<CostMatrix>,
+    pub overflow_policy: OverflowPolicy,
+    /// Class names of the model's outputs; `None` names classes by id.
+    pub label_map: Option<LabelMap>,
+    /// Maps the model's probabilities onto a calibrated scale before they are returned.
+    pub calibrator: Option<ScoreCalibrator>,
+    /// Reported with every `Prediction`; `new` sets it to a hash of the model file.
+    pub model_version: Option<String>,
+    /// Classes listed in `Prediction::top_k`.
+    pub top_k: usize,
+    /// How `explain` pools token importances to words.
+    pub word_pooling: WordPooling,
+    /// Renders multi-field records for `predict_fields`, as the training data was rendered.
+    pub input_template: Option<InputTemplate>,
 }
 
-impl<'a> Inference<'a> {
-    /// Creates a new `Inference` instance with the loaded model and tokenizer.
-    pub fn new(model_path: &str, tokenizer: &'a Tokenizer) -> Result<Self, Box<dyn Error>> {
-        let model = Transformer::load(model_path)?;
-        Ok(Inference { model, tokenizer })
+impl Inference {
+    /// Creates a new `Inference` instance from a saved model and the tokenizer saved with
+    /// `Tokenizer::save` during training, so texts map to the same token ids.
+    pub fn new(model_path: &str, tokenizer_path: &str) -> Result<Self, Box<dyn Error>> {
+        let data = fs::read(model_path)?;
+        let model: Transformer = serde_json::from_slice(&data).map_err(|e| format!("Cannot read model {}: {}", model_path, e))?;
+        let model_version = format!("{:016x}", fnv1a(&data));
+        Ok(Self::from_parts(model, Tokenizer::load(tokenizer_path)?)?.with_model_version(&model_version))
+    }
+
+    /// Loads the model `promote` installed into `serving_dir`, with its tokenizer, its label map
+    /// when the run had one and, when promotion fitted one, its score calibrator (see
+    /// `promotion::install`).
+    pub fn from_serving_dir(serving_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let path = |name: &str| serving_dir.join(name).to_string_lossy().into_owned();
+        let mut inference = Self::new(&path(SERVING_MODEL_FILE), &path(SERVING_TOKENIZER_FILE))?;
+        if serving_dir.join(SERVING_LABEL_MAP_FILE).exists() {
+            inference = inference.with_label_map(LabelMap::load(&path(SERVING_LABEL_MAP_FILE))?)?;
+        }
+        if serving_dir.join(SERVING_CALIBRATION_FILE).exists() {
+            inference = inference.with_calibrator(ScoreCalibrator::load(&path(SERVING_CALIBRATION_FILE))?);
+        }
+        Ok(inference)
+    }
+
+    /// Creates an `Inference` instance from an in-memory model and tokenizer.
+    ///
+    /// # Returns
+    /// * An error if the tokenizer produces ids the model has no embeddings for.
+    pub fn from_parts(mut model: Transformer, tokenizer: Tokenizer) -> Result<Self, Box<dyn Error>> {
+        let vocab_size = model.embeddings.vocab_size();
+        if let Some((token, id)) = tokenizer.vocab.iter().find(|(_, &id)| id >= vocab_size) {
+            return Err(format!(
+                "Tokenizer does not match the model: {} has id {} but the model has {} embeddings",
+                token, id, vocab_size
+            )
+            .into());
+        }
+        model.embeddings.reserve_positions(tokenizer.max_seq_length);
+
+        Ok(Inference {
+            model,
+            tokenizer,
+            mode: InferenceMode::Head,
+            prototypes: None,
+            cost_matrix: None,
+            overflow_policy: OverflowPolicy::default(),
+            label_map: None,
+            calibrator: None,
+            model_version: None,
+            top_k: PREDICTION_TOP_K,
+            word_pooling: WordPooling::Mean,
+            input_template: None,
+        })
+    }
+
+    /// Names predictions with the label map the model was trained with.
+    ///
+    /// # Returns
+    /// * An error if the map names more classes than the model predicts.
+    pub fn with_label_map(mut self, label_map: LabelMap) -> Result<Self, Box<dyn Error>> {
+        if label_map.len() > self.model.config.num_classes {
+            return Err(format!("The label map has {} classes but the model predicts {}", label_map.len(), self.model.config.num_classes).into());
+        }
+        self.label_map = Some(label_map);
+        Ok(self)
+    }
+
+    /// Name of a predicted class: its label map name, or the id without a map.
+    pub fn label_name(&self, class: usize) -> String {
+        self.label_map.as_ref().map_or_else(|| class.to_string(), |label_map| label_map.display_name(class))
+    }
+
+    /// Returns calibrated probabilities, fitted for this model with `ScoreCalibrator::fit`, so
+    /// fixed thresholds downstream keep their meaning when the model is replaced. Without a
+    /// cost matrix the predicted class is still the argmax of the raw probabilities.
+    pub fn with_calibrator(mut self, calibrator: ScoreCalibrator) -> Self {
+        self.calibrator = Some(calibrator);
+        self
+    }
+
+    /// Version reported with every prediction, e.g. a release tag instead of the file hash.
+    pub fn with_model_version(mut self, model_version: &str) -> Self {
+        self.model_version = Some(model_version.to_string());
+        self
+    }
+
+    /// Puts the prefix token of `task` in front of every text, as during training on that
+    /// task (see `Tokenizer::for_task`); `None` adds no prefix.
+    ///
+    /// # Returns
+    /// * An error if the tokenizer has no prefix registered for `task`.
+    pub fn with_task(mut self, task: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        self.tokenizer.task_prefixes.activate(task)?;
+        Ok(self)
+    }
+
+    /// Renders the records passed to `predict_fields`; use the template of the run config so
+    /// fields are combined exactly as during training.
+    pub fn with_input_template(mut self, input_template: InputTemplate) -> Self {
+        self.input_template = Some(input_template);
+        self
+    }
+
+    /// Sets how many of the most probable classes every `Prediction` lists.
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Sets how `explain` combines the importances of a word's sub-word pieces.
+    pub fn with_word_pooling(mut self, word_pooling: WordPooling) -> Self {
+        self.word_pooling = word_pooling;
+        self
+    }
+
+    /// Sets how texts longer than the tokenizer's `max_seq_length` are handled.
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Makes `predict` pick the class with the lowest expected cost under `cost_matrix`.
+    pub fn with_cost_matrix(mut self, cost_matrix: CostMatrix) -> Self {
+        self.cost_matrix = Some(cost_matrix);
+        self
+    }
+
+    /// Serves the encoder feed-forward networks in int8 (see `Transformer::quantize_feed_forward`).
+    /// Activation ranges are calibrated on the full-precision model, so calling this again
+    /// re-quantizes from scratch.
+    ///
+    /// # Arguments
+    /// * `granularity` - Scale granularity of the quantized weights.
+    /// * `calibration_sequences` - Token ids of representative inputs.
+    ///
+    /// # Returns
+    /// * The mean absolute FFN output error of every layer on the calibration sequences, or an
+    ///   error if there are none.
+    pub fn quantize_feed_forward(&mut self, granularity: Granularity, calibration_sequences: &[Vec<usize>]) -> Result<Vec<f64>, Box<dyn Error>> {
+        if calibration_sequences.is_empty() {
+            return Err("Cannot calibrate quantization without examples".into());
+        }
+        self.model.dequantize_feed_forward();
+        let ranges = CalibrationRanges::calibrate(&self.model, calibration_sequences, DEFAULT_CALIBRATION_PERCENTILE);
+        let errors = ffn_quantization_error(&self.model, calibration_sequences, granularity, &ranges);
+        self.model.quantize_feed_forward(granularity, &ranges)?;
+        Ok(errors)
+    }
+
+    /// Computes class centroids from a labeled dataset and switches to nearest-centroid mode.
+    pub fn fit_prototypes(&mut self, data_loader: &DataLoader, dataset_path: &str) -> Result<(), Box<dyn Error>> {
+        let (inputs, labels) = data_loader.load_dataset(dataset_path)?;
+        if inputs.is_empty() {
+            return Err("Cannot compute prototypes from an empty dataset".into());
+        }
+
+        let (batch_array, mask_array, segments) = self.to_model_input(&inputs)?;
+
+        let pooled = self.model.pooled_output_with_segments(&batch_array, Some(&mask_array), Some(&segments));
+        let num_classes = self.model.config.num_classes.max(labels.iter().max().map_or(0, |&l| l + 1));
+
+        self.prototypes = Some(ClassPrototypes::fit(&pooled, &labels, num_classes));
+        self.mode = InferenceMode::NearestCentroid;
+        Ok(())
+    }
+
+    /// Registers a new class named `name` from a handful of example texts without retraining.
+    ///
+    /// The mean pooled encoder output of the examples becomes the class centroid
+    /// (when prototypes are in use) and is imprinted as a new column of the
+    /// classification head. The name is added to the label map; a map that names the
+    /// existing classes by id is created when there is none. Returns the index of the new class.
+    pub fn register_class(&mut self, name: &str, examples: &[&str]) -> Result<usize, Box<dyn Error>> {
+        if examples.is_empty() {
+            return Err("At least one example is required to register a class".into());
+        }
+
+        let new_class = self.model.classification_head.num_classes();
+        let mut label_map = self.label_map.clone().unwrap_or_else(|| LabelMap::numeric(new_class));
+        if label_map.id(name).is_some() {
+            return Err(format!("Label '{}' already exists", name).into());
+        }
+        if label_map.len() != new_class {
+            return Err(format!("The label map has {} classes but the model predicts {}", label_map.len(), new_class).into());
+        }
+        if let Some(prototypes) = &self.prototypes {
+            if prototypes.num_classes() != new_class {
+                return Err(format!(
+                    "Prototypes cover {} classes but the classification head has {}",
+                    prototypes.num_classes(),
+                    new_class
+                )
+                .into());
+            }
+        }
+
+        let texts: Vec<String> = examples.iter().map(|text| text.to_string()).collect();
+        let batch = self.tokenizer.encode_batch(&texts);
+        let (batch_array, mask_array) = batch.to_arrays()?;
+        let segments = batch.token_type_array()?;
+
+        let pooled = self.model.pooled_output_with_segments(&batch_array, Some(&mask_array), Some(&segments));
+        let centroid = pooled.mean_axis(Axis(0)).unwrap();
+
+        self.model.classification_head.imprint_class(centroid.view())?;
+        if let Some(prototypes) = self.prototypes.as_mut() {
+            prototypes.add_class(centroid.view())?;
+        }
+        self.model.config.num_classes = new_class + 1;
+        label_map.add(name);
+        self.label_map = Some(label_map);
+
+        Ok(new_class)
     }
 
     /// Perform inference on a single input text.
-    pub fn predict(&self, input_text: &str) -> Result<(usize, Vec<f64>), Box<dyn Error>> {
-   
-        let tokenized_input = self.tokenizer.tokenize(input_text);
+    ///
+    /// In `NearestCentroid` mode the returned probabilities are the softmax of the
+    /// cosine similarities to each class centroid. With a cost matrix, the predicted
+    /// class is the one with the lowest expected cost (abstention is ignored; see `decide`).
+    /// Long texts are handled with `overflow_policy`; the prediction's `overflow` reports
+    /// whether the text was truncated or chunked.
+    ///
+    /// # Returns
+    /// * An error if the text is too long under `OverflowPolicy::Error`.
+    pub fn predict(&self, input_text: &str) -> Result<Prediction, Box<dyn Error>> {
+        self.predict_with_abstention(input_text, false)
+    }
+
+    /// Cost-sensitive decision for a single input text: the class with the lowest
+    /// expected cost, or `abstained` when abstaining is cheaper.
+    /// Without a cost matrix this is the same as `predict`.
+    pub fn decide(&self, input_text: &str) -> Result<Prediction, Box<dyn Error>> {
+        self.predict_with_abstention(input_text, true)
+    }
 
-      
-        let padded_input = self.tokenizer.pad_sequence(tokenized_input);
+    /// Predicts a multi-field record, e.g. `{"title": ..., "body": ...}`, rendered into one
+    /// input text with the instance's input template.
+    ///
+    /// # Returns
+    /// * An error without a template or if the record lacks one of the template's fields.
+    pub fn predict_fields(&self, fields: &HashMap<String, String>) -> Result<Prediction, Box<dyn Error>> {
+        let template = self.input_template.as_ref().ok_or("No input template is set; use with_input_template")?;
+        self.predict(&template.render(fields)?)
+    }
 
-      
-        let input_array = Array2::from_shape_vec(
-            (1, padded_input.len()), 
-            padded_input.into_iter().map(|x| x as f64).collect(),
-        )?;
+    fn predict_with_abstention(&self, input_text: &str, allow_abstention: bool) -> Result<Prediction, Box<dyn Error>> {
+        let start = Instant::now();
+        let (raw_probabilities, overflow) = self.class_probabilities(input_text)?;
+        let probabilities = self.calibrate(&raw_probabilities);
 
-        let logits = self.model.forward(&input_array);
+        let (predicted_class, abstained) = match &self.cost_matrix {
+            Some(cost_matrix) => {
+                self.check_cost_matrix(cost_matrix, &probabilities)?;
+                let abstained = allow_abstention && cost_matrix.decide(&probabilities) == Decision::Abstain;
+                (cost_matrix.min_cost_class(&probabilities), abstained)
+            }
+            // Isotonic calibration may tie classes; the raw probabilities break the tie.
+            None => (argmax(&raw_probabilities), false),
+        };
+        Ok(self
+            .prediction(predicted_class, probabilities)
+            .with_abstained(abstained)
+            .with_overflow(overflow)
+            .with_latency(start.elapsed()))
+    }
 
-        
-        let logits_slice = logits.row(0).to_vec(); 
-        let exp_values: Vec<f64> = logits_slice.iter().map(|x| x.exp()).collect();
-        let sum_exp: f64 = exp_values.iter().sum();
-        let probabilities: Vec<f64> = exp_values.iter().map(|p| p / sum_exp).collect();
+    /// A `Prediction` of `predicted_class` with the instance's label map, top-k and model version.
+    fn prediction(&self, predicted_class: usize, probabilities: Vec<f64>) -> Prediction {
+        Prediction::new(predicted_class, probabilities, self.top_k, self.label_map.as_ref()).with_model_version(self.model_version.clone())
+    }
 
-  
-        let predicted_class = probabilities
+    /// Explains a prediction by occlusion: every token is masked out in turn (as if it were
+    /// padding) and its importance is how much the predicted class's probability drops.
+    /// All occluded copies run as one batch, so this costs one forward pass over
+    /// `tokens + 1` sequences.
+    ///
+    /// # Returns
+    /// * The prediction, one `TokenImportance` per token and their pooled word
+    ///   importances. Texts longer than
+    ///   `max_seq_length` are explained on the tokens kept by truncation, or rejected
+    ///   under `OverflowPolicy::Error`.
+    pub fn explain(&self, input_text: &str) -> Result<Explanation, Box<dyn Error>> {
+        let start = Instant::now();
+        let aligned = self.tokenizer.tokenize_with_word_ids(input_text);
+        let tokens: Vec<usize> = aligned.iter().map(|&(id, _, _)| id).collect();
+        let offsets: Vec<Offset> = aligned.iter().map(|&(_, offset, _)| offset).collect();
+        let max_len = self.tokenizer.max_seq_length;
+        if self.overflow_policy == OverflowPolicy::Error && tokens.len() > max_len {
+            return Err(format!("Input has {} tokens but max_seq_length is {}", tokens.len(), max_len).into());
+        }
+        let input_tokens = tokens.len();
+        // Positions go through the same truncation as the tokens so offsets line up. A
+        // separator inserted by truncation spans the dropped text between its neighbours.
+        let kept_positions = self.tokenizer.truncated_positions(input_tokens, max_len);
+        let (kept, offsets): (Vec<usize>, Vec<Offset>) = kept_positions
             .iter()
             .enumerate()
-            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-            .map(|(index, _)| index)
-            .unwrap();
+            .map(|(index, &position)| match position {
+                Some(position) => (tokens[position], offsets[position]),
+                None => {
+                    let start = kept_positions[..index].iter().rev().flatten().next().map_or(0, |&before| offsets[before].1);
+                    let end = kept_positions[index + 1..].iter().flatten().next().map_or(input_text.len(), |&after| offsets[after].0);
+                    (self.tokenizer.vocab[SEP_TOKEN], (start, end))
+                }
+            })
+            .unzip();
+        let dropped_tokens = input_tokens - kept_positions.iter().flatten().count();
+        let overflow = OverflowReport { input_tokens, dropped_tokens, chunks: 1 };
+
+        let pad_id = self.tokenizer.vocab.get(PAD_TOKEN).copied().unwrap_or(0);
+        let original = self.tokenizer.pad_sequence(kept.clone());
+        let mut sequences = vec![original.clone()];
+        for position in 0..kept.len() {
+            let mut occluded = original.clone();
+            occluded[position] = pad_id;
+            sequences.push(occluded);
+        }
+        let probabilities = self.sequence_probabilities(&sequences)?;
+
+        // Importances are measured on the raw probabilities; the reported ones are calibrated.
+        let base = probabilities.row(0).to_vec();
+        let calibrated = self.calibrate(&base);
+        let predicted_class = match &self.cost_matrix {
+            Some(cost_matrix) => {
+                self.check_cost_matrix(cost_matrix, &calibrated)?;
+                cost_matrix.min_cost_class(&calibrated)
+            }
+            None => argmax(&base),
+        };
+        let tokens = kept
+            .iter()
+            .enumerate()
+            .map(|(position, &token_id)| TokenImportance {
+                token: self.tokenizer.decode(&[token_id]),
+                token_id,
+                offset: offsets[position],
+                importance: base[predicted_class] - probabilities[[position + 1, predicted_class]],
+                word: kept_positions[position].and_then(|position| aligned[position].2),
+            })
+            .collect();
+        let prediction = self.prediction(predicted_class, calibrated).with_overflow(overflow).with_latency(start.elapsed());
+        let mut explanation = Explanation { text: input_text.to_string(), prediction, tokens, words: Vec::new() };
+        explanation.words = explanation.word_importances(self.word_pooling);
+        Ok(explanation)
+    }
+
+    /// Word-level vectors of a text: the encoder output of every token, pooled over the
+    /// sub-word pieces of each word, e.g. for token-level heads that label words.
+    ///
+    /// # Returns
+    /// * The words as written in the text, and one vector per word. Shape: [num_words, d_model].
+    ///   Words dropped by truncation are left out.
+    pub fn word_embeddings(&self, input_text: &str, pooling: WordPooling) -> Result<(Vec<String>, Array2<f64>), Box<dyn Error>> {
+        let sequence = self.tokenizer.tokenize_and_pad_batch(&[input_text.to_string()]).remove(0);
+        let mask = Array1::from_iter(self.tokenizer.attention_mask(&sequence).into_iter().map(|value| value as f64));
+        let encoded = self.model.encode_sequence_masked(&sequence, Some(&mask));
+        let (words, vectors) = pool_words(encoded.view(), &self.tokenizer.word_ids(input_text), pooling);
+
+        let aligned = self.tokenizer.tokenize_with_word_ids(input_text);
+        let texts = words
+            .iter()
+            .map(|&word| {
+                let (start, end) = word_span(aligned.iter().filter(|token| token.2 == Some(word)).map(|token| token.1));
+                input_text[start..end].to_string()
+            })
+            .collect();
+        Ok((texts, vectors))
+    }
+
+    fn check_cost_matrix(&self, cost_matrix: &CostMatrix, probabilities: &[f64]) -> Result<(), Box<dyn Error>> {
+        if cost_matrix.num_classes() != probabilities.len() {
+            return Err(format!(
+                "Cost matrix covers {} classes but the model predicts {}",
+                cost_matrix.num_classes(),
+                probabilities.len()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Class probabilities of a single input text, applying `overflow_policy` when it
+    /// has more than `max_seq_length` tokens.
+    fn class_probabilities(&self, input_text: &str) -> Result<(Vec<f64>, OverflowReport), Box<dyn Error>> {
+        let tokens = self.tokenizer.tokenize(input_text);
+        let max_len = self.tokenizer.max_seq_length;
+        let mut overflow = OverflowReport { input_tokens: tokens.len(), dropped_tokens: 0, chunks: 1 };
+
+        let sequences = match self.overflow_policy {
+            _ if tokens.len() <= max_len => vec![self.tokenizer.pad_sequence(tokens)],
+            OverflowPolicy::Truncate => {
+                let (kept, dropped) = self.tokenizer.truncate(tokens);
+                overflow.dropped_tokens = dropped;
+                vec![self.tokenizer.pad_sequence(kept)]
+            }
+            OverflowPolicy::ChunkAndAggregate => {
+                tokens.chunks(max_len.max(1)).map(|chunk| self.tokenizer.pad_sequence(chunk.to_vec())).collect()
+            }
+            OverflowPolicy::Error => {
+                return Err(format!("Input has {} tokens but max_seq_length is {}", tokens.len(), max_len).into());
+            }
+        };
+        overflow.chunks = sequences.len();
 
-        Ok((predicted_class, probabilities))
+        let probabilities = self.sequence_probabilities(&sequences)?;
+        Ok((probabilities.mean_axis(Axis(0)).unwrap().to_vec(), overflow))
     }
+
+    /// Runs dummy forward passes so the first real request does not pay for cold caches
+    /// and first-time allocations, e.g. when a server starts.
+    ///
+    /// # Arguments
+    /// * `passes` - Number of forward passes.
+    /// * `batch_size` - Sequences per pass, at full `max_seq_length`; the largest batch
+    ///   expected in production warms up the most memory.
+    ///
+    /// # Returns
+    /// * The duration of every pass, so callers can log how long the cold start took.
+    pub fn warm_up(&self, passes: usize, batch_size: usize) -> Result<Vec<Duration>, Box<dyn Error>> {
+        let unk_id = self.tokenizer.vocab.get(UNK_TOKEN).copied().unwrap_or(0);
+        let sequences = vec![vec![unk_id; self.tokenizer.max_seq_length]; batch_size.max(1)];
+        (0..passes)
+            .map(|_| {
+                let start = Instant::now();
+                self.sequence_probabilities(&sequences)?;
+                Ok(start.elapsed())
+            })
+            .collect()
+    }
+
+    /// Probabilities on the calibrated scale, or unchanged without a calibrator.
+    fn calibrate(&self, probabilities: &[f64]) -> Vec<f64> {
+        match &self.calibrator {
+            Some(calibrator) => calibrator.apply(probabilities),
+            None => probabilities.to_vec(),
+        }
+    }
+
+    /// Class probabilities of padded token sequences. Shape: [num_sequences, num_classes].
+    fn sequence_probabilities(&self, sequences: &[Vec<usize>]) -> Result<Array2<f64>, Box<dyn Error>> {
+        let (input_array, mask_array, segments) = self.to_model_input(sequences)?;
+
+        if self.mode == InferenceMode::NearestCentroid {
+            let prototypes = self.prototypes.as_ref().ok_or("Nearest-centroid mode requires class prototypes")?;
+            let pooled = self.model.pooled_output_with_segments(&input_array, Some(&mask_array), Some(&segments));
+            let similarities: Vec<f64> = pooled.rows().into_iter().flat_map(|row| prototypes.similarities(row)).collect();
+            let similarities = Array2::from_shape_vec((sequences.len(), prototypes.num_classes()), similarities)?;
+            return Ok(Loss::softmax(&similarities));
+        }
+
+        let logits = self.model.forward_with_segments(&input_array, Some(&mask_array), Some(&segments));
+        Ok(Loss::softmax(&logits))
+    }
+
+    /// Predicts every record loaded by `DataLoader::load_records`, keeping its id and label.
+    pub fn predict_records(&self, records: &[RawRecord]) -> Result<Vec<ExamplePrediction>, Box<dyn Error>> {
+        records
+            .iter()
+            .map(|record| {
+                let prediction = ExamplePrediction { id: record.id.clone(), label: record.label, label_name: None, prediction: self.predict(&record.text)? };
+                Ok(prediction.with_label_names(self.label_map.as_ref()))
+            })
+            .collect()
+    }
+
+    /// Converts padded token sequences into the token, attention-mask and segment-id arrays
+    /// expected by the model. Shape of each: [num_sequences, seq_len].
+    fn to_model_input(&self, sequences: &[Vec<usize>]) -> Result<ModelInputs, Box<dyn Error>> {
+        let batch = self.tokenizer.mask_batch(sequences.to_vec());
+        let (input_array, mask_array) = batch.to_arrays()?;
+        Ok((input_array, mask_array, batch.token_type_array()?))
+    }
+}
+
+/// Index of the largest probability.
+pub(crate) fn argmax(probabilities: &[f64]) -> usize {
+    probabilities
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(index, _)| index)
+        .unwrap_or(0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tokenization::tokenizer::Tokenizer;
+    use crate::test_utils::fixtures::{synthetic_dataset_path, temp_path, tiny_config, tiny_vocab};
+    use crate::tokenization::tokenizer::{Tokenizer, Truncation};
     use crate::transformer::{Transformer, TransformerConfig};
     use std::collections::HashMap;
 
@@ -66,29 +696,326 @@ mod tests {
             ("[UNK]".to_string(), 3),
         ]);
 
-        let config = TransformerConfig {
-            num_layers: 2,
-            d_model: 4,
-            num_heads: 2,
-            ff_dim: 8,
-            num_classes: 2,
-            epsilon: 1e-6,
-        };
+        let config = TransformerConfig { num_layers: 2, ..tiny_config(2) };
 
-        let transformer = Transformer::new(config, vocab.clone());
+        let transformer: Transformer = Transformer::new(config, vocab.clone());
         let tokenizer = Tokenizer::new(vocab, 128);
 
-        let model_path = "trained_model.json";
+        let model_path = &temp_path("trained_model.json");
+        let tokenizer_path = &temp_path("trained_model_tokenizer.json");
         transformer.save(model_path).unwrap();
+        tokenizer.save(tokenizer_path).unwrap();
 
    
-        let inference = Inference::new(model_path, &tokenizer).unwrap();
+        let inference = Inference::new(model_path, tokenizer_path).unwrap();
 
-        let (predicted_class, probabilities) = inference.predict("Your appointment is booked.").unwrap();
-        println!("Predicted Class: {}", predicted_class);
-        println!("Probabilities: {:?}", probabilities);
+        let prediction = inference.predict("Your appointment is booked.").unwrap();
+        assert!(prediction.label_id < 2);
+        assert!((prediction.probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        let model_version = format!("{:016x}", fnv1a(&std::fs::read(model_path).unwrap()));
+        assert_eq!(prediction.model_version, Some(model_version));
+        assert_eq!(inference.tokenizer.vocab, tokenizer.vocab);
 
      
         std::fs::remove_file(model_path).unwrap();
+        std::fs::remove_file(tokenizer_path).unwrap();
+    }
+
+    #[test]
+    fn test_predict_fields_renders_template() {
+        let vocab = tiny_vocab(&["late", "parcel"]);
+        let config = tiny_config(2);
+        let inference = Inference::from_parts(Transformer::new(config, vocab.clone()), Tokenizer::new(vocab, 8)).unwrap();
+        let fields = HashMap::from([("title".to_string(), "late".to_string()), ("body".to_string(), "parcel".to_string())]);
+        assert!(inference.predict_fields(&fields).is_err());
+
+        let inference = inference.with_input_template(InputTemplate::parse("{title} {body}").unwrap());
+        assert_eq!(inference.predict_fields(&fields).unwrap().probabilities, inference.predict("late parcel").unwrap().probabilities);
+        assert!(inference.predict_fields(&HashMap::from([("title".to_string(), "late".to_string())])).is_err());
+    }
+
+    #[test]
+    fn test_with_task_prefixes_inputs() {
+        let mut tokenizer = Tokenizer::new(tiny_vocab(&["late", "parcel"]), 8);
+        let prefix = tokenizer.register_task("sentiment", "[TASK1]").unwrap();
+        let inference = || Inference::from_parts(Transformer::new(tiny_config(2), tokenizer.vocab.clone()), tokenizer.clone()).unwrap();
+        assert!(inference().tokenizer.tokenize("late parcel").first() != Some(&prefix));
+
+        assert!(inference().with_task(Some("topic")).is_err());
+        let inference = inference().with_task(Some("sentiment")).unwrap();
+        assert_eq!(inference.tokenizer.tokenize("late parcel").first(), Some(&prefix));
+        assert!(inference.predict("late parcel").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_tokenizer_with_unknown_ids() {
+        let vocab = tiny_vocab(&[]);
+        let config = tiny_config(2);
+        let model = Transformer::new(config, vocab.clone());
+
+        let mut larger_vocab = vocab;
+        larger_vocab.insert("new".to_string(), 2);
+        assert!(Inference::from_parts(model, Tokenizer::new(larger_vocab, 8)).is_err());
+    }
+
+    #[test]
+    fn test_nearest_centroid_predict() {
+        let vocab = tiny_vocab(&["free", "meeting"]);
+
+        let config = tiny_config(2);
+
+        let model_path = &temp_path("nearest_centroid_model.json");
+        Transformer::<f64>::new(config, vocab.clone()).save(model_path).unwrap();
+
+        let tokenizer = Tokenizer::new(vocab, 16);
+        let data_loader = DataLoader::new(&tokenizer);
+        let mut inference = Inference::from_parts(Transformer::load(model_path).unwrap(), tokenizer.clone()).unwrap();
+        std::fs::remove_file(model_path).unwrap();
+
+        let dataset_path = &synthetic_dataset_path("nearest_centroid_dataset.json");
+        inference.fit_prototypes(&data_loader, dataset_path).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+        assert_eq!(inference.mode, InferenceMode::NearestCentroid);
+
+        let prediction = inference.predict("free meeting").unwrap();
+        assert!(prediction.label_id < 2);
+        assert!((prediction.probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_register_class() {
+        let vocab = tiny_vocab(&["refund", "invoice"]);
+
+        let config = tiny_config(2);
+
+        let model_path = &temp_path("register_class_model.json");
+        Transformer::<f64>::new(config, vocab.clone()).save(model_path).unwrap();
+
+        let tokenizer = Tokenizer::new(vocab, 16);
+        let data_loader = DataLoader::new(&tokenizer);
+        let mut inference = Inference::from_parts(Transformer::load(model_path).unwrap(), tokenizer.clone()).unwrap();
+        std::fs::remove_file(model_path).unwrap();
+
+        let dataset_path = &synthetic_dataset_path("register_class_dataset.json");
+        inference.fit_prototypes(&data_loader, dataset_path).unwrap();
+        std::fs::remove_file(dataset_path).unwrap();
+        let new_class = inference.register_class("billing", &["refund invoice", "invoice refund refund"]).unwrap();
+
+        assert_eq!(new_class, 2);
+        assert_eq!(inference.model.config.num_classes, 3);
+        assert_eq!(inference.prototypes.as_ref().unwrap().num_classes(), 3);
+        assert_eq!(inference.label_map.as_ref().unwrap().names(), ["0", "1", "billing"]);
+        assert_eq!(inference.label_name(new_class), "billing");
+
+        assert_eq!(inference.predict("refund invoice").unwrap().probabilities.len(), 3);
+        assert!(inference.register_class("billing", &["refund"]).is_err());
+        assert_eq!(inference.model.config.num_classes, 3);
+    }
+
+    #[test]
+    fn test_cost_matrix_overrides_argmax() {
+        let vocab = tiny_vocab(&["offer"]);
+
+        let config = tiny_config(2);
+
+        let model_path = &temp_path("cost_matrix_model.json");
+        Transformer::<f64>::new(config, vocab.clone()).save(model_path).unwrap();
+
+        let tokenizer = Tokenizer::new(vocab, 16);
+        let inference = Inference::from_parts(Transformer::load(model_path).unwrap(), tokenizer.clone()).unwrap();
+        std::fs::remove_file(model_path).unwrap();
+
+        // Predicting class 0 is prohibitively expensive, so class 1 always wins.
+        let costs = CostMatrix::new(vec![vec![1000.0, 1.0], vec![1000.0, 0.0]]).unwrap();
+        let inference = inference.with_cost_matrix(costs.clone().with_abstain_costs(vec![2000.0, 2000.0]).unwrap());
+
+        assert_eq!(inference.predict("offer").unwrap().label_id, 1);
+        let decision = inference.decide("offer").unwrap();
+        assert_eq!((decision.label_id, decision.abstained), (1, false));
+
+        // Abstaining for free beats any class; only `decide` reports it.
+        let inference = inference.with_cost_matrix(costs.with_abstain_costs(vec![0.0, 0.0]).unwrap());
+        let decision = inference.decide("offer").unwrap();
+        assert_eq!((decision.label_id, decision.abstained), (1, true));
+        assert!(!inference.predict("offer").unwrap().abstained);
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let vocab = tiny_vocab(&["free", "offer", "now"]);
+        let config = tiny_config(2);
+        let inference = Inference::from_parts(Transformer::new(config, vocab.clone()), Tokenizer::new(vocab, 4)).unwrap();
+        let long_text = "free free free free offer offer offer offer now now";
+
+        let short = inference.predict("free offer").unwrap();
+        assert_eq!(short.overflow, OverflowReport { input_tokens: 2, dropped_tokens: 0, chunks: 1 });
+
+        let truncated = inference.predict(long_text).unwrap();
+        assert!(truncated.overflow.truncated() && !truncated.overflow.chunked());
+        assert_eq!(truncated.overflow.dropped_tokens, 6);
+        assert_eq!(truncated.probabilities, inference.predict("free free free free").unwrap().probabilities);
+
+        let inference = inference.with_overflow_policy(OverflowPolicy::ChunkAndAggregate);
+        let chunked = inference.predict(long_text).unwrap();
+        assert_eq!(chunked.overflow, OverflowReport { input_tokens: 10, dropped_tokens: 0, chunks: 3 });
+        // The windows are predicted on their own and their probabilities averaged.
+        for class in 0..2 {
+            let mean = ["free free free free", "offer offer offer offer", "now now"]
+                .iter()
+                .map(|chunk| inference.predict(chunk).unwrap().probabilities[class])
+                .sum::<f64>()
+                / 3.0;
+            assert!((chunked.probabilities[class] - mean).abs() < 1e-12);
+        }
+
+        let inference = inference.with_overflow_policy(OverflowPolicy::Error);
+        assert!(inference.predict(long_text).is_err());
+        assert!(inference.predict("free offer now").is_ok());
+    }
+
+    #[test]
+    fn test_serving_dir_predicts_with_promoted_calibrator() {
+        let vocab = tiny_vocab(&["offer"]);
+        let (model_path, tokenizer_path) = (temp_path("promoted_model.json"), temp_path("promoted_tokenizer.json"));
+        Transformer::<f64>::new(tiny_config(2), vocab.clone()).save(&model_path).unwrap();
+        Tokenizer::new(vocab, 4).save(&tokenizer_path).unwrap();
+        let serving_dir = Path::new(&temp_path("serving")).to_path_buf();
+        let flat = ScoreCalibrator::Isotonic { scores: vec![0.5], calibrated: vec![0.3] };
+
+        let label_map = LabelMap::from_names(&["ham", "spam"]).unwrap();
+        let promoted = crate::model_evaluator::promotion::install(&model_path, &tokenizer_path, Some(&label_map), Some(&flat), &serving_dir);
+        let served = Inference::from_serving_dir(&serving_dir);
+        let direct = Inference::new(&model_path, &tokenizer_path).unwrap().with_calibrator(flat.clone());
+        for path in [&model_path, &tokenizer_path] {
+            fs::remove_file(path).unwrap();
+        }
+        fs::remove_dir_all(&serving_dir).unwrap();
+
+        promoted.unwrap();
+        let served = served.unwrap();
+        assert_eq!(served.calibrator, Some(flat));
+        assert_eq!(served.label_map, Some(label_map));
+        assert_eq!(served.predict("offer").unwrap().probabilities, direct.predict("offer").unwrap().probabilities);
+    }
+
+    #[test]
+    fn test_calibrator_rescales_probabilities_only() {
+        let vocab = tiny_vocab(&["offer"]);
+        let config = tiny_config(3);
+        let inference = Inference::from_parts(Transformer::new(config, vocab.clone()), Tokenizer::new(vocab, 4)).unwrap();
+        let raw = inference.predict("offer").unwrap();
+
+        // A calibrator that flattens every score keeps the argmax of the raw probabilities.
+        let flat = ScoreCalibrator::Isotonic { scores: vec![0.5], calibrated: vec![0.3] };
+        let inference = inference.with_calibrator(flat);
+        let prediction = inference.predict("offer").unwrap();
+        assert_eq!(prediction.label_id, raw.label_id);
+        assert!(prediction.probabilities.iter().all(|&p| (p - 1.0 / 3.0).abs() < 1e-12));
+        assert_eq!(inference.explain("offer").unwrap().prediction.probabilities, prediction.probabilities);
+
+        let sharpen = ScoreCalibrator::Platt { slope: 2.0, intercept: 0.0 };
+        let inference = inference.with_calibrator(sharpen);
+        let probabilities = inference.predict("offer").unwrap().probabilities;
+        assert!(probabilities[raw.label_id] > raw.probabilities[raw.label_id]);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_word_embeddings_pool_sub_words() {
+        let path = &temp_path("inference_word_pooling_vocab.txt");
+        std::fs::write(path, "[PAD]\n[UNK]\nnew\nyo\n##rk\n").unwrap();
+        let tokenizer = Tokenizer::from_wordpiece_vocab(path, 6);
+        std::fs::remove_file(path).unwrap();
+        let tokenizer = tokenizer.unwrap();
+        let config = tiny_config(2);
+        let inference = Inference::from_parts(Transformer::new(config, tokenizer.vocab.clone()), tokenizer).unwrap();
+
+        let (words, first) = inference.word_embeddings("New York", WordPooling::First).unwrap();
+        assert_eq!(words, vec!["New", "York"]);
+        assert_eq!(first.dim(), (2, 4));
+
+        // "York" is `yo ##rk`: its mean is the mean of the two piece outputs.
+        let sequence = inference.tokenizer.tokenize_and_pad_batch(&["New York".to_string()]).remove(0);
+        let mask = Array1::from_iter(inference.tokenizer.attention_mask(&sequence).into_iter().map(|value| value as f64));
+        let encoded = inference.model.encode_sequence_masked(&sequence, Some(&mask));
+        let (_, mean) = inference.word_embeddings("New York", WordPooling::Mean).unwrap();
+        let expected = (&encoded.row(1) + &encoded.row(2)) / 2.0;
+        assert!(mean.row(1).iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert_eq!(first.row(1), encoded.row(1));
+    }
+
+    #[test]
+    fn test_explain_by_occlusion() {
+        let vocab = tiny_vocab(&["free", "offer"]);
+        let config = tiny_config(2);
+        let inference = Inference::from_parts(Transformer::new(config.clone(), vocab.clone()), Tokenizer::new(vocab.clone(), 4)).unwrap();
+
+        let explanation = inference.explain("free offer").unwrap();
+        let prediction = inference.predict("free offer").unwrap();
+        assert_eq!(explanation.prediction.label_id, prediction.label_id);
+        assert_eq!(explanation.prediction.probabilities, prediction.probabilities);
+        let tokens: Vec<&str> = explanation.tokens.iter().map(|token| token.token.as_str()).collect();
+        assert_eq!(tokens, vec!["free", "offer"]);
+        assert_eq!(explanation.tokens[1].offset, (5, 10));
+        assert_eq!(explanation.tokens[1].word, Some(1));
+        assert_eq!((explanation.words[1].word.as_str(), explanation.words[1].importance), ("offer", explanation.tokens[1].importance));
+        assert_eq!(explanation.word_importances(WordPooling::Max), explanation.words);
+
+        // Masking out the last token leaves the same input as the text without it.
+        let without_offer = inference.predict("free").unwrap().probabilities;
+        let expected = prediction.probability - without_offer[prediction.label_id];
+        assert!((explanation.tokens[1].importance - expected).abs() < 1e-12);
+
+        // Long texts are explained on the tokens the model sees.
+        let truncated = inference.explain("free free offer offer free").unwrap();
+        assert_eq!(truncated.tokens.len(), 4);
+        assert_eq!(truncated.tokens[3].offset, (16, 21));
+        assert!(truncated.prediction.overflow.truncated());
+        let inference = inference.with_overflow_policy(OverflowPolicy::Error);
+        assert!(inference.explain("free free offer offer free").is_err());
+
+        // The separator of head+tail truncation spans the dropped middle.
+        let mut vocab = vocab;
+        vocab.insert("[SEP]".to_string(), 4);
+        let tokenizer = Tokenizer::new(vocab.clone(), 4).with_truncation(Truncation::HeadAndTail { head_tokens: 1, separator: true });
+        let inference = Inference::from_parts(Transformer::new(config, vocab), tokenizer).unwrap();
+        let explanation = inference.explain("free free offer offer free").unwrap();
+        let tokens: Vec<&str> = explanation.tokens.iter().map(|token| token.token.as_str()).collect();
+        assert_eq!(tokens, vec!["free", "[SEP]", "offer", "free"]);
+        assert_eq!(explanation.tokens[1].offset, (4, 16));
+        assert_eq!(explanation.prediction.overflow.dropped_tokens, 2);
+    }
+    #[test]
+    fn test_warm_up_runs_full_length_passes() {
+        let vocab = tiny_vocab(&["free"]);
+        let config = tiny_config(2);
+        let inference = Inference::from_parts(Transformer::new(config, vocab.clone()), Tokenizer::new(vocab, 6)).unwrap();
+
+        assert_eq!(inference.warm_up(3, 4).unwrap().len(), 3);
+        assert!(inference.warm_up(0, 4).unwrap().is_empty());
+        // Warming up changes nothing about later predictions.
+        let before = inference.predict("free").unwrap().probabilities;
+        inference.warm_up(1, 1).unwrap();
+        assert_eq!(inference.predict("free").unwrap().probabilities, before);
+    }
+
+    #[test]
+    fn test_quantized_feed_forward_stays_close() {
+        let vocab = tiny_vocab(&["free", "deal", "meeting"]);
+        let config = TransformerConfig { d_model: 8, ff_dim: 16, ..tiny_config(2) };
+        let mut inference = Inference::from_parts(Transformer::new(config, vocab.clone()), Tokenizer::new(vocab, 6)).unwrap();
+        let full_precision = inference.predict("free deal").unwrap().probabilities;
+        assert!(inference.quantize_feed_forward(Granularity::Column, &[]).is_err());
+
+        let sequences = vec![vec![2, 3, 0], vec![4, 2, 3], vec![3, 3, 4]];
+        let errors = inference.quantize_feed_forward(Granularity::Column, &sequences).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(inference.model.encoder_layers.iter().all(|layer| layer.quantized_feed_forward.is_some()));
+        let quantized = inference.predict("free deal").unwrap().probabilities;
+        assert_ne!(quantized, full_precision);
+        assert!(quantized.iter().zip(&full_precision).all(|(q, f)| (q - f).abs() < 0.05));
+
+        // Re-quantizing calibrates on the full-precision model again.
+        assert_eq!(inference.quantize_feed_forward(Granularity::Column, &sequences).unwrap(), errors);
     }
 }
@@ -1 +1,7 @@
 pub mod inference;
+pub mod prediction;
+pub mod ensemble;
+pub mod cost_matrix;
+pub mod request_limits;
+pub mod auth;
+pub mod server;
@@ -0,0 +1,136 @@
+use crate::data_handler::label_map::LabelMap;
+use crate::model_inference::inference::OverflowReport;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A class and its probability, as listed in `Prediction::top_k`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClassScore {
+    pub label_id: usize,
+    /// Name of the class, when the model has a label map.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub label_name: Option<String>,
+    pub probability: f64,
+}
+
+/// Result of predicting one text, returned by every `Inference` prediction path and printed
+/// by the CLI.
+///
+/// The JSON form is the stable output schema for clients: fields are only ever added,
+/// optional ones are left out when unset, and clients should ignore fields they do not know.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Prediction {
+    /// The predicted class. When `abstained` is set, the class that would have been chosen.
+    pub label_id: usize,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub label_name: Option<String>,
+    /// Probability of `label_id`.
+    pub probability: f64,
+    /// The most probable classes, most probable first.
+    pub top_k: Vec<ClassScore>,
+    /// Probability of every class, indexed by class id.
+    pub probabilities: Vec<f64>,
+    /// Version of the model that made the prediction (see `Inference::with_model_version`).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub model_version: Option<String>,
+    /// Set when a cost matrix made abstaining cheaper than any class; callers should then
+    /// defer the input, e.g. to a human.
+    pub abstained: bool,
+    /// Wall-clock time of the prediction, from tokenization to the final probabilities.
+    pub latency_ms: f64,
+    /// How the text fitted into `max_seq_length`.
+    pub overflow: OverflowReport,
+}
+
+impl Prediction {
+    /// Builds a prediction of `label_id` from the class probabilities.
+    ///
+    /// # Arguments
+    /// * `top_k` - Number of classes listed in `top_k`; all of them if there are fewer.
+    /// * `label_map` - Names the classes; without one the names are left out.
+    pub fn new(label_id: usize, probabilities: Vec<f64>, top_k: usize, label_map: Option<&LabelMap>) -> Self {
+        let mut ranked: Vec<usize> = (0..probabilities.len()).collect();
+        ranked.sort_by(|&a, &b| probabilities[b].total_cmp(&probabilities[a]));
+        let top_k = ranked
+            .into_iter()
+            .take(top_k)
+            .map(|class| ClassScore { label_id: class, label_name: None, probability: probabilities[class] })
+            .collect();
+
+        Prediction {
+            label_id,
+            label_name: None,
+            probability: probabilities.get(label_id).copied().unwrap_or(0.0),
+            top_k,
+            probabilities,
+            model_version: None,
+            abstained: false,
+            latency_ms: 0.0,
+            overflow: OverflowReport::default(),
+        }
+        .with_label_names(label_map)
+    }
+
+    /// Names the predicted class and the `top_k` classes with `label_map`; without one the
+    /// names are left out.
+    pub fn with_label_names(mut self, label_map: Option<&LabelMap>) -> Self {
+        let name = |class: usize| label_map.map(|label_map| label_map.display_name(class));
+        self.label_name = name(self.label_id);
+        for score in &mut self.top_k {
+            score.label_name = name(score.label_id);
+        }
+        self
+    }
+
+    pub fn with_model_version(mut self, model_version: Option<String>) -> Self {
+        self.model_version = model_version;
+        self
+    }
+
+    pub fn with_abstained(mut self, abstained: bool) -> Self {
+        self.abstained = abstained;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency_ms = latency.as_secs_f64() * 1000.0;
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: OverflowReport) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prediction_schema() {
+        let label_map = LabelMap::from_names(&["billing", "shipping", "other"]).unwrap();
+        let prediction = Prediction::new(1, vec![0.2, 0.7, 0.1], 2, Some(&label_map))
+            .with_model_version(Some("0123abcd".to_string()))
+            .with_latency(Duration::from_micros(1500));
+
+        assert_eq!(prediction.label_name.as_deref(), Some("shipping"));
+        assert_eq!(prediction.probability, 0.7);
+        assert_eq!(prediction.top_k.iter().map(|score| score.label_id).collect::<Vec<_>>(), vec![1, 0]);
+        assert_eq!(prediction.top_k[1].label_name.as_deref(), Some("billing"));
+        assert_eq!(prediction.latency_ms, 1.5);
+
+        let json = serde_json::to_value(&prediction).unwrap();
+        assert_eq!(json["label_id"], 1);
+        assert_eq!(json["model_version"], "0123abcd");
+        assert_eq!(json["abstained"], false);
+        assert_eq!(json["overflow"]["chunks"], 0);
+        let parsed: Prediction = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.top_k, prediction.top_k);
+
+        // Unset optional fields are left out.
+        let unnamed = serde_json::to_value(Prediction::new(0, vec![1.0], 3, None)).unwrap();
+        assert!(unnamed.get("label_name").is_none() && unnamed.get("model_version").is_none());
+        assert_eq!(unnamed["top_k"].as_array().unwrap().len(), 1);
+    }
+}
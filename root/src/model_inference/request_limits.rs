@@ -0,0 +1,212 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Why a request was turned away, with the HTTP status a serving layer should answer with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LimitError {
+    /// A text longer than `RequestLimits::max_input_chars`.
+    InputTooLong { index: usize, chars: usize, max_chars: usize },
+    /// More texts than `RequestLimits::max_batch_size`.
+    BatchTooLarge { size: usize, max_size: usize },
+    /// A request without texts.
+    EmptyBatch,
+    /// The client used up its rate limit; it may retry after `retry_after`.
+    RateLimited { client: String, retry_after: Duration },
+}
+
+impl LimitError {
+    /// 413 for oversized requests, 400 for empty ones and 429 when rate limited.
+    pub fn status(&self) -> u16 {
+        match self {
+            LimitError::InputTooLong { .. } | LimitError::BatchTooLarge { .. } => 413,
+            LimitError::EmptyBatch => 400,
+            LimitError::RateLimited { .. } => 429,
+        }
+    }
+
+    /// Seconds for a `Retry-After` header, rounded up.
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            LimitError::RateLimited { retry_after, .. } => Some(retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)),
+            _ => None,
+        }
+    }
+
+    /// The error as a JSON response body, e.g.
+    /// `{"error":"batch_too_large","message":"...","status":413}`.
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            error: &'a str,
+            message: String,
+            status: u16,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            retry_after_secs: Option<u64>,
+        }
+        let error = match self {
+            LimitError::InputTooLong { .. } => "input_too_long",
+            LimitError::BatchTooLarge { .. } => "batch_too_large",
+            LimitError::EmptyBatch => "empty_batch",
+            LimitError::RateLimited { .. } => "rate_limited",
+        };
+        let body = Body { error, message: self.to_string(), status: self.status(), retry_after_secs: self.retry_after_secs() };
+        serde_json::to_string(&body).unwrap()
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::InputTooLong { index, chars, max_chars } => {
+                write!(f, "Text {} has {} characters; at most {} are accepted", index, chars, max_chars)
+            }
+            LimitError::BatchTooLarge { size, max_size } => {
+                write!(f, "Request has {} texts; at most {} are accepted per request", size, max_size)
+            }
+            LimitError::EmptyBatch => write!(f, "Request has no texts"),
+            LimitError::RateLimited { client, retry_after } => {
+                write!(f, "Rate limit exceeded for {}; retry in {:.1}s", client, retry_after.as_secs_f64())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+/// Size limits checked before a request reaches the model, so a single oversized request
+/// cannot stall a public endpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RequestLimits {
+    /// Longest accepted text in characters. Longer texts would be truncated to
+    /// `max_seq_length` tokens anyway, but tokenizing them still costs time.
+    pub max_input_chars: usize,
+    /// Most texts per request.
+    pub max_batch_size: usize,
+}
+
+impl RequestLimits {
+    /// Checks the texts of one request.
+    ///
+    /// # Returns
+    /// * The first violated limit; the batch size is checked before the texts.
+    pub fn check<S: AsRef<str>>(&self, texts: &[S]) -> Result<(), LimitError> {
+        if texts.is_empty() {
+            return Err(LimitError::EmptyBatch);
+        }
+        if texts.len() > self.max_batch_size {
+            return Err(LimitError::BatchTooLarge { size: texts.len(), max_size: self.max_batch_size });
+        }
+        for (index, text) in texts.iter().enumerate() {
+            let chars = text.as_ref().chars().count();
+            if chars > self.max_input_chars {
+                return Err(LimitError::InputTooLong { index, chars, max_chars: self.max_input_chars });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remaining requests of one client.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Per-client token-bucket rate limiter: each client may send `burst` requests at once and
+/// `requests_per_second` on average. Clients are identified by any key, e.g. an API key or
+/// remote address.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    pub requests_per_second: f64,
+    pub burst: usize,
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    /// # Arguments
+    /// * `requests_per_second` - Sustained rate per client; must be positive.
+    /// * `burst` - Requests a client may send at once; at least 1.
+    ///
+    /// # Returns
+    /// * The limiter, or an error if `requests_per_second` is not positive.
+    pub fn new(requests_per_second: f64, burst: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        if requests_per_second.is_nan() || requests_per_second <= 0.0 {
+            return Err(format!("requests_per_second must be positive, got {}", requests_per_second).into());
+        }
+        Ok(RateLimiter { requests_per_second, burst: burst.max(1), buckets: HashMap::new() })
+    }
+
+    /// Admits one request of `client` now.
+    pub fn check(&mut self, client: &str) -> Result<(), LimitError> {
+        self.check_at(client, Instant::now())
+    }
+
+    /// Admits one request of `client` at time `now`.
+    pub fn check_at(&mut self, client: &str, now: Instant) -> Result<(), LimitError> {
+        let capacity = self.burst as f64;
+        let bucket = self.buckets.entry(client.to_string()).or_insert(Bucket { tokens: capacity, updated: now });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / self.requests_per_second);
+            Err(LimitError::RateLimited { client: client.to_string(), retry_after })
+        }
+    }
+
+    /// Forgets clients whose bucket has refilled completely, so idle clients do not
+    /// accumulate in memory.
+    pub fn prune(&mut self, now: Instant) {
+        let (rate, capacity) = (self.requests_per_second, self.burst as f64);
+        self.buckets.retain(|_, bucket| {
+            bucket.tokens + now.saturating_duration_since(bucket.updated).as_secs_f64() * rate < capacity
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_limits() {
+        let limits = RequestLimits { max_input_chars: 5, max_batch_size: 2 };
+        assert_eq!(limits.check(&["héllo", "ok"]), Ok(()));
+        assert_eq!(limits.check(&["ok", "too long"]), Err(LimitError::InputTooLong { index: 1, chars: 8, max_chars: 5 }));
+        assert_eq!(limits.check(&["a", "b", "c"]).unwrap_err().status(), 413);
+        assert_eq!(limits.check::<&str>(&[]).unwrap_err().status(), 400);
+
+        let body: serde_json::Value = serde_json::from_str(&limits.check(&["a", "b", "c"]).unwrap_err().to_json()).unwrap();
+        assert_eq!(body["error"], "batch_too_large");
+        assert_eq!(body["message"], "Request has 3 texts; at most 2 are accepted per request");
+        assert!(body.get("retry_after_secs").is_none());
+    }
+
+    #[test]
+    fn test_rate_limiter_refills_per_client() {
+        assert!(RateLimiter::new(0.0, 2).is_err());
+        assert!(RateLimiter::new(f64::NAN, 2).is_err());
+        let mut limiter = RateLimiter::new(2.0, 2).unwrap();
+        let start = Instant::now();
+        assert!(limiter.check_at("a", start).is_ok());
+        assert!(limiter.check_at("a", start).is_ok());
+        let limited = limiter.check_at("a", start).unwrap_err();
+        assert_eq!(limited.status(), 429);
+        assert_eq!(limited.retry_after_secs(), Some(1));
+        // Other clients have their own bucket.
+        assert!(limiter.check_at("b", start).is_ok());
+
+        assert!(limiter.check_at("a", start + Duration::from_millis(500)).is_ok());
+        assert!(limiter.check_at("a", start + Duration::from_millis(500)).is_err());
+
+        limiter.prune(start + Duration::from_secs(10));
+        assert_eq!(limiter.buckets.len(), 0);
+    }
+}
@@ -0,0 +1,425 @@
+use crate::logging::logger::LogEvent;
+use crate::model_inference::auth::{ApiKeyAuth, AuthError};
+use crate::model_inference::inference::Inference;
+use crate::model_inference::request_limits::{LimitError, RateLimiter, RequestLimits};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+/// How often `Server` forgets idle clients of its rate limiter.
+const RATE_LIMITER_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Largest request body `serve` reads; larger bodies are answered with 413.
+const MAX_BODY_BYTES: u64 = 1 << 20;
+
+/// An HTTP request as seen by `Server`, independent of the HTTP library.
+#[derive(Clone, Debug, Default)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are matched case-insensitively.
+    pub headers: Vec<(String, String)>,
+    /// Remote address; the rate limiter's key for requests without an API key.
+    pub client: String,
+    pub body: String,
+}
+
+impl HttpRequest {
+    /// Value of the first header called `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(field, _)| field.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// A JSON response of `Server`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    fn json<T: Serialize>(status: u16, body: &T) -> Self {
+        HttpResponse { status, headers: Vec::new(), body: serde_json::to_string(body).unwrap() }
+    }
+
+    /// An error body, e.g. `{"error":"bad_request","message":"...","status":400}`.
+    fn error(status: u16, error: &str, message: impl Into<String>) -> Self {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            error: &'a str,
+            message: String,
+            status: u16,
+        }
+        Self::json(status, &Body { error, message: message.into(), status })
+    }
+
+    fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// A `LimitError` with its status, JSON body and, when rate limited, `Retry-After` header.
+    fn limited(error: &LimitError) -> Self {
+        let response = HttpResponse { status: error.status(), headers: Vec::new(), body: error.to_json() };
+        match error.retry_after_secs() {
+            Some(seconds) => response.with_header("Retry-After", seconds.to_string()),
+            None => response,
+        }
+    }
+
+    /// 401 with a `WWW-Authenticate: Bearer` challenge.
+    fn unauthorized(error: &AuthError) -> Self {
+        Self::error(error.status(), "unauthorized", error.to_string()).with_header("WWW-Authenticate", "Bearer")
+    }
+}
+
+/// Body of `POST /predict`: one text, a batch of texts, or the fields of a multi-field
+/// record for a model trained with an input template.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PredictRequest {
+    Text { text: String },
+    Batch { texts: Vec<String> },
+    Fields { fields: HashMap<String, String> },
+}
+
+impl PredictRequest {
+    /// The texts `RequestLimits` checks; the values of a record's fields count as texts.
+    fn texts(&self) -> Vec<&str> {
+        match self {
+            PredictRequest::Text { text } => vec![text.as_str()],
+            PredictRequest::Batch { texts } => texts.iter().map(String::as_str).collect(),
+            PredictRequest::Fields { fields } => fields.values().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// Body of `POST /explain`.
+#[derive(Deserialize)]
+struct ExplainRequest {
+    text: String,
+}
+
+/// JSON endpoints over an `Inference`:
+///
+/// - `POST /predict` with `{"text": ...}`, `{"texts": [...]}` or `{"fields": {...}}` returns
+///   the `Prediction`, or `{"predictions": [...]}` for a batch.
+/// - `POST /explain` with `{"text": ...}` returns the `Explanation`. The `X-Input-Truncated`
+///   header tells whether tokens were cut off before the model saw them.
+/// - `GET /health` returns `{"status": "ok"}`.
+///
+/// `/predict` and `/explain` need an API key when authentication is configured, count
+/// against the client's rate limit and must fit the request limits; `/health` is exempt.
+/// Requests are answered one at a time, in arrival order.
+pub struct Server {
+    pub inference: Inference,
+    pub request_limits: Option<RequestLimits>,
+    pub rate_limiter: Option<RateLimiter>,
+    pub auth: Option<ApiKeyAuth>,
+    /// PEM certificate chain and private key; `serve` speaks HTTPS when set.
+    tls: Option<tiny_http::SslConfig>,
+    last_pruned: Instant,
+}
+
+impl Server {
+    pub fn new(inference: Inference) -> Self {
+        Server { inference, request_limits: None, rate_limiter: None, auth: None, tls: None, last_pruned: Instant::now() }
+    }
+
+    /// Answers requests without a valid API key with 401 (see `ApiKeyAuth::authenticate`).
+    /// The client a key belongs to replaces the remote address as the rate limiter's key.
+    pub fn with_auth(mut self, auth: ApiKeyAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Serves HTTPS with a PEM certificate chain and private key, e.g. from Let's Encrypt,
+    /// so API keys never cross the network in plain text.
+    ///
+    /// # Returns
+    /// * The server, or an error if either file cannot be read.
+    pub fn with_tls(mut self, certificate_path: &str, private_key_path: &str) -> Result<Self, Box<dyn Error>> {
+        let read = |path: &str| std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path, e));
+        self.tls = Some(tiny_http::SslConfig { certificate: read(certificate_path)?, private_key: read(private_key_path)? });
+        Ok(self)
+    }
+
+    /// Rejects oversized requests with 413 and empty ones with 400 before they reach the model.
+    pub fn with_request_limits(mut self, request_limits: RequestLimits) -> Self {
+        self.request_limits = Some(request_limits);
+        self
+    }
+
+    /// Answers clients that exceed their rate with 429 and a `Retry-After` header.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Authenticates a request to `/predict` or `/explain` and counts it against the rate
+    /// limit. Only the headers and the client are read, so `serve` can turn a request away
+    /// before reading its body.
+    fn admit(&mut self, request: &HttpRequest) -> Result<(), HttpResponse> {
+        if !matches!(request.path.as_str(), "/predict" | "/explain") {
+            return Ok(());
+        }
+        let authenticated = self
+            .auth
+            .as_ref()
+            .map(|auth| auth.authenticate(request.header("Authorization"), request.header("X-API-Key")).map(str::to_string));
+        let client = match authenticated {
+            Some(Ok(client)) => client,
+            Some(Err(error)) => {
+                // Rejected keys count against the remote address, so keys cannot be guessed at full speed.
+                self.count_request(&request.client).map_err(|error| HttpResponse::limited(&error))?;
+                return Err(HttpResponse::unauthorized(&error));
+            }
+            None => request.client.clone(),
+        };
+        self.count_request(&client).map_err(|error| HttpResponse::limited(&error))
+    }
+
+    /// Answers an admitted request; errors become JSON error responses.
+    fn route(&self, request: &HttpRequest) -> HttpResponse {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/predict") => self.predict(&request.body),
+            ("POST", "/explain") => self.explain(&request.body),
+            ("GET", "/health") => HttpResponse::json(200, &serde_json::json!({ "status": "ok" })),
+            (_, "/predict" | "/explain" | "/health") => HttpResponse::error(405, "method_not_allowed", format!("{} is not allowed on {}", request.method, request.path)),
+            _ => HttpResponse::error(404, "not_found", format!("No endpoint at {}", request.path)),
+        }
+    }
+
+    /// Counts a request of `client` against the rate limit.
+    fn count_request(&mut self, client: &str) -> Result<(), LimitError> {
+        let Some(rate_limiter) = self.rate_limiter.as_mut() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if now.duration_since(self.last_pruned) >= RATE_LIMITER_PRUNE_INTERVAL {
+            rate_limiter.prune(now);
+            self.last_pruned = now;
+        }
+        rate_limiter.check(client)
+    }
+
+    /// Checks the texts of a request against the request limits.
+    fn check_limits(&self, texts: &[&str]) -> Result<(), HttpResponse> {
+        match &self.request_limits {
+            Some(limits) => limits.check(texts).map_err(|error| HttpResponse::limited(&error)),
+            None => Ok(()),
+        }
+    }
+
+    fn predict(&self, body: &str) -> HttpResponse {
+        let request: PredictRequest = match serde_json::from_str(body) {
+            Ok(request) => request,
+            Err(e) => return HttpResponse::error(400, "bad_request", format!("Expected {{\"text\": ...}}, {{\"texts\": [...]}} or {{\"fields\": {{...}}}}: {}", e)),
+        };
+        if let Err(response) = self.check_limits(&request.texts()) {
+            return response;
+        }
+        let result = match &request {
+            PredictRequest::Text { text } => self.inference.predict(text).map(|prediction| HttpResponse::json(200, &prediction)),
+            PredictRequest::Batch { texts } => texts
+                .iter()
+                .map(|text| self.inference.predict(text))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|predictions| HttpResponse::json(200, &serde_json::json!({ "predictions": predictions }))),
+            PredictRequest::Fields { fields } => self.inference.predict_fields(fields).map(|prediction| HttpResponse::json(200, &prediction)),
+        };
+        result.unwrap_or_else(|e| HttpResponse::error(422, "unprocessable_input", e.to_string()))
+    }
+
+    fn explain(&self, body: &str) -> HttpResponse {
+        let request: ExplainRequest = match serde_json::from_str(body) {
+            Ok(request) => request,
+            Err(e) => return HttpResponse::error(400, "bad_request", format!("Expected {{\"text\": ...}}: {}", e)),
+        };
+        if let Err(response) = self.check_limits(&[request.text.as_str()]) {
+            return response;
+        }
+        match self.inference.explain(&request.text) {
+            Ok(explanation) => {
+                let truncated = explanation.prediction.overflow.truncated();
+                HttpResponse::json(200, &explanation).with_header("X-Input-Truncated", truncated.to_string())
+            }
+            Err(e) => HttpResponse::error(422, "unprocessable_input", e.to_string()),
+        }
+    }
+
+    /// Listens on `address` (e.g. `127.0.0.1:8080`) and answers requests until the process
+    /// is stopped.
+    pub fn serve(mut self, address: &str) -> Result<(), Box<dyn Error>> {
+        let (server, scheme) = match self.tls.take() {
+            Some(tls) => (tiny_http::Server::https(address, tls), "https"),
+            None => (tiny_http::Server::http(address), "http"),
+        };
+        let server = server.map_err(|e| format!("Cannot listen on {}: {}", address, e))?;
+        if self.auth.is_none() {
+            LogEvent::warn("server", "No API keys are configured; anyone who can reach the server can use it").emit();
+        }
+        LogEvent::info("server", format!("Listening on {}://{}", scheme, address)).emit();
+        for mut request in server.incoming_requests() {
+            let headers = request.headers().iter().map(|header| (header.field.as_str().to_string(), header.value.as_str().to_string())).collect();
+            let client = request.remote_addr().map_or_else(|| "unknown".to_string(), |address| address.ip().to_string());
+            let mut http_request = HttpRequest { method: request.method().as_str().to_string(), path: request.url().to_string(), headers, client, body: String::new() };
+            let response = match self.admit(&http_request).and_then(|()| read_body(request.body_length(), request.as_reader())) {
+                Ok(body) => {
+                    http_request.body = body;
+                    self.route(&http_request)
+                }
+                Err(response) => response,
+            };
+            if response.status >= 400 {
+                LogEvent::warn("server", format!("{} {} -> {}", request.method(), request.url(), response.status)).emit();
+            }
+            let mut reply = tiny_http::Response::from_string(response.body).with_status_code(response.status);
+            for (name, value) in std::iter::once(("Content-Type".to_string(), "application/json".to_string())).chain(response.headers) {
+                if let Ok(header) = tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+                    reply.add_header(header);
+                }
+            }
+            if let Err(e) = request.respond(reply) {
+                LogEvent::warn("server", format!("Failed to send a response: {}", e)).emit();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the body of a request, turning away one larger than `MAX_BODY_BYTES` by its
+/// `Content-Length` or, without one, after reading one byte too many.
+fn read_body(content_length: Option<usize>, reader: &mut dyn Read) -> Result<String, HttpResponse> {
+    let too_large = || HttpResponse::error(413, "body_too_large", format!("Request bodies are limited to {} bytes", MAX_BODY_BYTES));
+    if content_length.is_some_and(|length| length as u64 > MAX_BODY_BYTES) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    if let Err(e) = reader.take(MAX_BODY_BYTES + 1).read_to_end(&mut body) {
+        return Err(HttpResponse::error(400, "bad_request", format!("Unreadable body: {}", e)));
+    }
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err(too_large());
+    }
+    String::from_utf8(body).map_err(|e| HttpResponse::error(400, "bad_request", format!("Unreadable body: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_handler::input_template::InputTemplate;
+    use crate::test_utils::fixtures::{tiny_config, tiny_vocab};
+    use crate::tokenization::tokenizer::Tokenizer;
+    use crate::transformer::Transformer;
+
+    impl Server {
+        /// What `serve` does with a request whose body fits `MAX_BODY_BYTES`.
+        fn handle(&mut self, request: &HttpRequest) -> HttpResponse {
+            match self.admit(request) {
+                Ok(()) => self.route(request),
+                Err(response) => response,
+            }
+        }
+    }
+
+    fn server(max_seq_length: usize) -> Server {
+        let vocab = tiny_vocab(&["late", "parcel"]);
+        Server::new(Inference::from_parts(Transformer::new(tiny_config(2), vocab.clone()), Tokenizer::new(vocab, max_seq_length)).unwrap())
+    }
+
+    fn post(path: &str, body: &str) -> HttpRequest {
+        HttpRequest { method: "POST".to_string(), path: path.to_string(), headers: Vec::new(), client: "10.0.0.7".to_string(), body: body.to_string() }
+    }
+
+    #[test]
+    fn test_predict_endpoint() {
+        let mut server = server(8);
+        let response = server.handle(&post("/predict", r#"{"text": "late parcel"}"#));
+        assert_eq!(response.status, 200);
+        let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(body["probabilities"].as_array().unwrap().len(), 2);
+
+        let batch: serde_json::Value = serde_json::from_str(&server.handle(&post("/predict", r#"{"texts": ["late", "parcel"]}"#)).body).unwrap();
+        assert_eq!(batch["predictions"].as_array().unwrap().len(), 2);
+
+        assert_eq!(server.handle(&post("/predict", r#"{"fields": {"title": "late"}}"#)).status, 422);
+        let mut templated = Server::new(self::server(8).inference.with_input_template(InputTemplate::parse("{title}").unwrap()));
+        assert_eq!(templated.handle(&post("/predict", r#"{"fields": {"title": "late"}}"#)).status, 200);
+
+        assert_eq!(server.handle(&post("/predict", "not json")).status, 400);
+        assert_eq!(server.handle(&post("/missing", "{}")).status, 404);
+        assert_eq!(server.handle(&HttpRequest { method: "GET".to_string(), path: "/predict".to_string(), ..HttpRequest::default() }).status, 405);
+        assert_eq!(server.handle(&HttpRequest { method: "GET".to_string(), path: "/health".to_string(), ..HttpRequest::default() }).status, 200);
+    }
+
+    #[test]
+    fn test_explain_endpoint_reports_truncation() {
+        let mut server = server(4);
+        let short = server.handle(&post("/explain", r#"{"text": "late parcel"}"#));
+        assert_eq!(short.status, 200);
+        assert!(short.headers.contains(&("X-Input-Truncated".to_string(), "false".to_string())));
+
+        let long = server.handle(&post("/explain", r#"{"text": "late parcel late parcel late parcel"}"#));
+        assert!(long.headers.contains(&("X-Input-Truncated".to_string(), "true".to_string())));
+        let body: serde_json::Value = serde_json::from_str(&long.body).unwrap();
+        assert!(body["overflow"]["dropped_tokens"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_request_and_rate_limits() {
+        let mut server = server(8)
+            .with_request_limits(RequestLimits { max_input_chars: 11, max_batch_size: 2 })
+            .with_rate_limiter(RateLimiter::new(0.001, 4).unwrap());
+        assert_eq!(server.handle(&post("/predict", r#"{"text": "late parcel"}"#)).status, 200);
+        assert_eq!(server.handle(&post("/explain", r#"{"text": "late parcel late"}"#)).status, 413);
+        assert_eq!(server.handle(&post("/predict", r#"{"texts": ["late", "late", "late"]}"#)).status, 413);
+        let empty = server.handle(&post("/predict", r#"{"texts": []}"#));
+        assert_eq!((empty.status, serde_json::from_str::<serde_json::Value>(&empty.body).unwrap()["error"].as_str()), (400, Some("empty_batch")));
+
+        // The burst of 4 is used up; other clients and health checks are not affected.
+        let limited = server.handle(&post("/predict", r#"{"text": "late"}"#));
+        assert_eq!(limited.status, 429);
+        assert!(limited.headers.iter().any(|(name, _)| name == "Retry-After"));
+        assert_eq!(server.handle(&HttpRequest { client: "10.0.0.8".to_string(), ..post("/predict", r#"{"text": "late"}"#) }).status, 200);
+        assert_eq!(server.handle(&HttpRequest { method: "GET".to_string(), path: "/health".to_string(), client: "10.0.0.7".to_string(), ..HttpRequest::default() }).status, 200);
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let auth = ApiKeyAuth::default().with_key("dashboard", "k-0123456789abcdef").unwrap();
+        let mut server = server(8).with_auth(auth).with_rate_limiter(RateLimiter::new(0.001, 1).unwrap());
+        let with_key = |key: &str| HttpRequest { headers: vec![("authorization".to_string(), format!("Bearer {}", key))], ..post("/predict", r#"{"text": "late"}"#) };
+
+        let missing = server.handle(&post("/predict", r#"{"text": "late"}"#));
+        assert_eq!(missing.status, 401);
+        assert!(missing.headers.contains(&("WWW-Authenticate".to_string(), "Bearer".to_string())));
+        // Rejected keys use up the address's burst, but a valid key is limited per client.
+        assert_eq!(server.handle(&with_key("k-0123456789abcdeX")).status, 429);
+        assert_eq!(server.handle(&with_key("k-0123456789abcdef")).status, 200);
+        assert_eq!(server.handle(&HttpRequest { client: "10.0.0.9".to_string(), ..with_key("k-0123456789abcdeX") }).status, 401);
+        // The key's client is rate limited wherever it connects from.
+        assert_eq!(server.handle(&HttpRequest { client: "10.0.0.8".to_string(), ..with_key("k-0123456789abcdef") }).status, 429);
+        assert_eq!(server.handle(&HttpRequest { method: "GET".to_string(), path: "/health".to_string(), ..HttpRequest::default() }).status, 200);
+
+        assert!(self::server(8).with_tls("/nonexistent/cert.pem", "/nonexistent/key.pem").is_err());
+    }
+
+    #[test]
+    fn test_body_size_limit() {
+        let body = r#"{"text": "late"}"#;
+        assert_eq!(read_body(Some(body.len()), &mut body.as_bytes()).unwrap(), body);
+
+        let oversized = vec![b' '; MAX_BODY_BYTES as usize + 1];
+        // Rejected by the declared length before anything is read, and without one after the limit.
+        let mut unread = oversized.as_slice();
+        assert_eq!(read_body(Some(oversized.len()), &mut unread).unwrap_err().status, 413);
+        assert_eq!(unread.len(), oversized.len());
+        assert_eq!(read_body(None, &mut oversized.as_slice()).unwrap_err().status, 413);
+        assert_eq!(read_body(None, &mut [0xff, 0xfe].as_slice()).unwrap_err().status, 400);
+    }
+}
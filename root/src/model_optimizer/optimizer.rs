@@ -6,30 +6,40 @@
 ///
 /// Input:
 /// - Gradients of all layers.
+///
 /// Output:
 /// - Updated parameters.
-
 use ndarray::{Array2, ArrayView2, ArrayViewMut2};
-use crate::configurration::config::{LEARNING_RATE, BETA1, BETA2, EPSILON};
+use crate::configurration::config::{LEARNING_RATE, BETA1, BETA2, EPSILON, ADAM_EPSILON_PLACEMENT};
+use crate::numerics::{EpsilonPlacement, Float};
+use serde::{Serialize, Deserialize};
 
 /// Optimizer enum to choose between different optimization algorithms.
+#[derive(Serialize, Deserialize)]
 pub enum OptimizerType {
-    SGD,
+    /// Saved as `"SGD"`, as before the variant was renamed.
+    #[serde(rename = "SGD")]
+    Sgd,
     Adam,
 }
 
-pub struct Optimizer {
+/// Optimizer over parameters of element type `A`. Hyperparameters are kept in f64 and
+/// converted on every step; the Adam moments are stored in `A`.
+#[derive(Serialize, Deserialize)]
+pub struct Optimizer<A = f64> {
     optimizer_type: OptimizerType,
     learning_rate: f64,
     beta1: f64,       
     beta2: f64,       
     epsilon: f64,     
-    moment1: Option<Array2<f64>>, 
-    moment2: Option<Array2<f64>>,
+    #[serde(default)]
+    epsilon_placement: EpsilonPlacement,
+    moment1: Option<Array2<A>>, 
+    moment2: Option<Array2<A>>,
     timestep: usize, 
 }
 
-impl Optimizer {
+impl<A: Float> Optimizer<A> {
     /// new optimizer.
     pub fn new(optimizer_type: OptimizerType) -> Self {
         Self {
@@ -37,36 +47,46 @@ impl Optimizer {
             learning_rate: LEARNING_RATE,
             beta1: BETA1,
             beta2: BETA2,
-            epsilon: EPSILON,
+            // Never below what `A` can represent, e.g. for f16 parameters.
+            epsilon: EPSILON.max(A::DTYPE.adam_epsilon()),
+            epsilon_placement: ADAM_EPSILON_PLACEMENT,
             moment1: None,
             moment2: None,
             timestep: 0,
         }
     }
 
+    /// Forgets the Adam moments and step count, e.g. when the parameters are reinitialized.
+    pub fn reset(&mut self) {
+        self.moment1 = None;
+        self.moment2 = None;
+        self.timestep = 0;
+    }
+
     /// Applies gradients to update parameters using the specified optimizer type.
     ///
     /// # Arguments
     /// * `params` - A mutable reference to the parameters to be updated.
     /// * `grads` - A reference to the gradients corresponding to the parameters.
-    pub fn step(&mut self, params: &mut ArrayViewMut2<f64>, grads: &ArrayView2<f64>) {
+    pub fn step(&mut self, params: &mut ArrayViewMut2<A>, grads: &ArrayView2<A>) {
         match self.optimizer_type {
-            OptimizerType::SGD => self.sgd_step(params, grads),
+            OptimizerType::Sgd => self.sgd_step(params, grads),
             OptimizerType::Adam => self.adam_step(params, grads),
         }
     }
 
  
-    fn sgd_step(&self, params: &mut ArrayViewMut2<f64>, grads: &ArrayView2<f64>) {
+    fn sgd_step(&self, params: &mut ArrayViewMut2<A>, grads: &ArrayView2<A>) {
         assert_eq!(params.shape(), grads.shape(), "Parameter and gradient shapes must match.");
 
+        let learning_rate = A::cast(self.learning_rate);
         params.zip_mut_with(grads, |param, &grad| {
-            *param -= self.learning_rate * grad;
+            *param -= learning_rate * grad;
         });
     }
 
   
-    fn adam_step(&mut self, params: &mut ArrayViewMut2<f64>, grads: &ArrayView2<f64>) {
+    fn adam_step(&mut self, params: &mut ArrayViewMut2<A>, grads: &ArrayView2<A>) {
         assert_eq!(params.shape(), grads.shape(), "Parameter and gradient shapes must match.");
 
   
@@ -80,25 +100,26 @@ impl Optimizer {
 
         self.timestep += 1;
         let t = self.timestep as f64;
+        let (beta1, beta2) = (A::cast(self.beta1), A::cast(self.beta2));
+        let (learning_rate, epsilon) = (A::cast(self.learning_rate), A::cast(self.epsilon));
 
         moment1.zip_mut_with(grads, |m1, &grad| {
-            *m1 = self.beta1 * *m1 + (1.0 - self.beta1) * grad;
+            *m1 = beta1 * *m1 + (A::one() - beta1) * grad;
         });
 
         moment2.zip_mut_with(grads, |m2, &grad| {
-            *m2 = self.beta2 * *m2 + (1.0 - self.beta2) * grad.powi(2);
+            *m2 = beta2 * *m2 + (A::one() - beta2) * grad.powi(2);
         });
 
      
-        let bias_corrected_m1 = moment1.mapv(|m1| m1 / (1.0 - self.beta1.powf(t)));
-        let bias_corrected_m2 = moment2.mapv(|m2| m2 / (1.0 - self.beta2.powf(t)));
-
-        params.zip_mut_with(&bias_corrected_m1, |param, &m1| {
-					*param -= self.learning_rate * m1 / (bias_corrected_m2.mapv(f64::sqrt) + self.epsilon)
-							.iter()
-							.fold(0.0, |acc, &val| acc + val); 
-				});
-			
+        let bias_correction1 = A::cast(1.0 - self.beta1.powf(t));
+        let bias_correction2 = A::cast(1.0 - self.beta2.powf(t));
+        let epsilon_placement = self.epsilon_placement;
+        let bias_corrected_m2 = moment2.mapv(|m2| m2 / bias_correction2);
+        // Per element: theta -= lr * m_hat / (sqrt(v_hat) + epsilon).
+        ndarray::Zip::from(params).and(&*moment1).and(&bias_corrected_m2).for_each(|param, &m1, &m2| {
+            *param -= learning_rate * (m1 / bias_correction1) / epsilon_placement.sqrt_with_epsilon(m2, epsilon);
+        });
     }
 }
 
@@ -111,7 +132,7 @@ mod tests {
     fn test_sgd() {
         let mut params = array![[1.0, 2.0], [3.0, 4.0]];
         let grads = array![[0.1, 0.2], [0.3, 0.4]];
-        let mut optimizer = Optimizer::new(OptimizerType::SGD);
+        let mut optimizer = Optimizer::new(OptimizerType::Sgd);
 
         optimizer.step(&mut params.view_mut(), &grads.view());
 
@@ -120,13 +141,31 @@ mod tests {
 
     #[test]
     fn test_adam() {
-        let mut params = array![[1.0, 2.0], [3.0, 4.0]];
+        let mut params: Array2<f64> = array![[1.0, 2.0], [3.0, 4.0]];
         let grads = array![[0.1, 0.2], [0.3, 0.4]];
         let mut optimizer = Optimizer::new(OptimizerType::Adam);
 
         optimizer.step(&mut params.view_mut(), &grads.view());
 
-      
-        assert_eq!(params.shape(), [2, 2]); 
+        // The first bias-corrected step is lr * g / (|g| + epsilon): about lr per element,
+        // whatever the gradient's magnitude.
+        let expected = array![[0.999, 1.999], [2.999, 3.999]];
+        assert!((&params - &expected).iter().all(|d| d.abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_single_precision_step() {
+        let mut params = array![[1.0f32, 2.0], [3.0, 4.0]];
+        let grads = array![[0.1f32, 0.2], [0.3, 0.4]];
+        let mut optimizer = Optimizer::new(OptimizerType::Sgd);
+
+        optimizer.step(&mut params.view_mut(), &grads.view());
+
+        let expected = array![[0.9999, 1.9998], [2.9997, 3.9996]];
+        assert!((params.mapv(f64::from) - expected).iter().all(|d| d.abs() < 1e-6));
+
+        let mut adam = Optimizer::new(OptimizerType::Adam);
+        adam.step(&mut params.view_mut(), &grads.view());
+        assert!(params.iter().all(|p| p.is_finite()));
     }
 }
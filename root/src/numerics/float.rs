@@ -0,0 +1,88 @@
+use ndarray::{LinalgScalar, ScalarOperand};
+use ndarray_rand::rand_distr::uniform::SampleUniform;
+use num_traits::FromPrimitive;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{AddAssign, DivAssign, MulAssign, SubAssign};
+
+use super::Dtype;
+
+/// Floating-point element type of the model's tensors.
+///
+/// The attention kernels, layer normalization, the loss, the feed-forward network and the
+/// optimizer are generic over it, with `f64` as the default, so the precision is chosen at
+/// compile time by the type of the arrays passed in. Reductions that use `Summation` are
+/// accumulated in f64 whatever the element type.
+pub trait Float:
+    num_traits::Float
+    + FromPrimitive
+    + LinalgScalar
+    + ScalarOperand
+    + SampleUniform
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + Sum
+    + Default
+    + Debug
+    + Display
+    + Serialize
+    + DeserializeOwned
+    + Send
+    + Sync
+    + 'static
+{
+    /// Format of the type, which sets its stability constants (see `Dtype`).
+    const DTYPE: Dtype;
+
+    /// Converts an f64 constant, e.g. a hyperparameter, rounding to the nearest value.
+    fn cast(value: f64) -> Self;
+
+    /// Widens to f64, e.g. to report a loss or accumulate a reduction.
+    fn as_f64(self) -> f64;
+}
+
+impl Float for f64 {
+    const DTYPE: Dtype = Dtype::F64;
+
+    fn cast(value: f64) -> Self {
+        value
+    }
+
+    fn as_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Float for f32 {
+    const DTYPE: Dtype = Dtype::F32;
+
+    fn cast(value: f64) -> Self {
+        value as f32
+    }
+
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer_norm_epsilon<A: Float>() -> A {
+        A::cast(A::DTYPE.layer_norm_epsilon())
+    }
+
+    #[test]
+    fn test_float_conversions() {
+        assert_eq!(f64::cast(0.1), 0.1);
+        assert_eq!(f32::cast(0.1), 0.1f32);
+        assert_eq!(0.5f32.as_f64(), 0.5);
+        assert_eq!(layer_norm_epsilon::<f32>(), 1e-6f32);
+        assert_eq!(<f32 as Float>::DTYPE, Dtype::F32);
+    }
+}
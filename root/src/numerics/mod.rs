@@ -0,0 +1,5 @@
+pub mod float;
+pub mod stability;
+
+pub use float::Float;
+pub use stability::{softmax_inplace, Dtype, EpsilonPlacement};
@@ -0,0 +1,139 @@
+use ndarray::ArrayViewMut1;
+use serde::{Deserialize, Serialize};
+
+use super::Float;
+
+/// Default layer norm epsilon for formats that can represent it.
+const LAYER_NORM_EPSILON: f64 = 1e-6;
+/// Default Adam epsilon for formats that can represent it.
+const ADAM_EPSILON: f64 = 1e-8;
+/// Default lower bound of vector norms that are divided by.
+const NORM_FLOOR: f64 = 1e-12;
+
+/// Floating-point format the model is computed in. The default is f64; `Float::DTYPE` gives
+/// the format of an element type, and the other formats set the stability constants a
+/// lower-precision backend needs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Dtype {
+    #[default]
+    F64,
+    F32,
+    /// IEEE half precision: 10 mantissa bits, smallest normal value 2^-14.
+    F16,
+    /// bfloat16: the exponent range of f32 with 7 mantissa bits.
+    BF16,
+}
+
+impl Dtype {
+    /// Smallest positive value stored at full precision. Smaller constants become
+    /// subnormal or flush to zero, so the defaults below never go under it.
+    pub const fn min_positive_normal(self) -> f64 {
+        match self {
+            Dtype::F64 => f64::MIN_POSITIVE,
+            Dtype::F32 | Dtype::BF16 => f32::MIN_POSITIVE as f64,
+            Dtype::F16 => 6.103515625e-5,
+        }
+    }
+
+    /// Epsilon added to the variance in layer normalization.
+    pub const fn layer_norm_epsilon(self) -> f64 {
+        LAYER_NORM_EPSILON.max(self.min_positive_normal())
+    }
+
+    /// Epsilon of the Adam denominator. The usual 1e-8 underflows to zero in f16.
+    pub const fn adam_epsilon(self) -> f64 {
+        ADAM_EPSILON.max(self.min_positive_normal())
+    }
+
+    /// Lower bound of a vector norm before dividing by it, e.g. in cosine similarities.
+    pub const fn norm_floor(self) -> f64 {
+        NORM_FLOOR.max(self.min_positive_normal())
+    }
+}
+
+/// Where an epsilon enters a square-root denominator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum EpsilonPlacement {
+    /// `sqrt(x + epsilon)`, as in layer normalization and TensorFlow's Adam `epsilon_hat`.
+    InsideSqrt,
+    /// `sqrt(x) + epsilon`, as in the Adam paper.
+    #[default]
+    OutsideSqrt,
+}
+
+impl EpsilonPlacement {
+    pub fn sqrt_with_epsilon<A: Float>(self, x: A, epsilon: A) -> A {
+        match self {
+            EpsilonPlacement::InsideSqrt => (x + epsilon).sqrt(),
+            EpsilonPlacement::OutsideSqrt => x.sqrt() + epsilon,
+        }
+    }
+}
+
+/// Softmax of one row in place. The row maximum is subtracted before `exp`, so the largest
+/// term is exactly 1 and no term overflows; entries of `-inf` (masked) get probability 0.
+/// A row that is entirely `-inf` (every key masked) becomes all zeros rather than NaN.
+///
+/// # Arguments
+/// * `row` - Scores of one query.
+pub fn softmax_inplace<A: Float>(mut row: ArrayViewMut1<A>) {
+    let max = row.iter().cloned().fold(A::neg_infinity(), A::max);
+    if max == A::neg_infinity() {
+        row.fill(A::zero());
+        return;
+    }
+    let exp_sum: A = row.iter().map(|&x| (x - max).exp()).sum();
+    row.mapv_inplace(|x| (x - max).exp() / exp_sum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_defaults_are_representable() {
+        // f64 keeps the constants the crate has always used.
+        assert_eq!(Dtype::F64.layer_norm_epsilon(), 1e-6);
+        assert_eq!(Dtype::F64.adam_epsilon(), 1e-8);
+        assert_eq!(Dtype::F64.norm_floor(), 1e-12);
+
+        assert_eq!(Dtype::F32.adam_epsilon(), 1e-8);
+        // 1e-8 is below the smallest f16 normal value.
+        assert_eq!(Dtype::F16.adam_epsilon(), Dtype::F16.min_positive_normal());
+        for dtype in [Dtype::F64, Dtype::F32, Dtype::F16, Dtype::BF16] {
+            for constant in [dtype.layer_norm_epsilon(), dtype.adam_epsilon(), dtype.norm_floor()] {
+                assert!(constant >= dtype.min_positive_normal());
+            }
+        }
+    }
+
+    #[test]
+    fn test_epsilon_placement() {
+        assert_eq!(EpsilonPlacement::InsideSqrt.sqrt_with_epsilon(0.0, 1e-6), 1e-3);
+        assert_eq!(EpsilonPlacement::OutsideSqrt.sqrt_with_epsilon(0.0, 1e-6), 1e-6);
+        assert_eq!(EpsilonPlacement::OutsideSqrt.sqrt_with_epsilon(4.0, 0.5), 2.5);
+    }
+
+    #[test]
+    fn test_softmax_does_not_overflow() {
+        let mut row = array![1000.0, 1001.0, f64::NEG_INFINITY];
+        softmax_inplace(row.view_mut());
+
+        assert!(row.iter().all(|p| p.is_finite()));
+        assert!((row.sum() - 1.0).abs() < 1e-12);
+        assert_eq!(row[2], 0.0);
+        assert!((row[1] / row[0] - 1f64.exp()).abs() < 1e-9);
+
+        let mut single = array![1000.0f32, 1001.0, f32::NEG_INFINITY];
+        softmax_inplace(single.view_mut());
+        assert!((single.mapv(f64::from) - row).iter().all(|d| d.abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_softmax_of_fully_masked_row() {
+        let mut row = array![f64::NEG_INFINITY, f64::NEG_INFINITY];
+        softmax_inplace(row.view_mut());
+        assert_eq!(row, array![0.0, 0.0]);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod protobuf;
+pub mod onnx_import;
+pub mod onnx_export;
@@ -0,0 +1,280 @@
+use crate::onnx::onnx_import::DATA_TYPE_FLOAT;
+use crate::onnx::protobuf::WireWriter;
+use crate::transformer::Transformer;
+use ndarray::Array2;
+use std::error::Error;
+
+// ONNX `TensorProto.DataType` of the index constants.
+const DATA_TYPE_INT64: u64 = 7;
+// `AttributeProto.AttributeType` values of the attributes the exporter writes.
+const ATTRIBUTE_FLOAT: u64 = 1;
+const ATTRIBUTE_INT: u64 = 2;
+const ATTRIBUTE_INTS: u64 = 7;
+/// IR version and default-domain opset of the exported model; opset 17 is the first
+/// with `LayerNormalization`.
+const IR_VERSION: u64 = 8;
+const OPSET_VERSION: u64 = 17;
+const PRODUCER_NAME: &str = "transformer-classifier";
+
+/// A dimension of a graph input or output.
+enum Dimension {
+    Fixed(usize),
+    Named(&'static str),
+}
+
+/// Nodes, initializers, inputs and outputs of the graph being written, in graph order.
+#[derive(Default)]
+struct GraphWriter {
+    nodes: Vec<WireWriter>,
+    initializers: Vec<WireWriter>,
+    inputs: Vec<WireWriter>,
+    outputs: Vec<WireWriter>,
+}
+
+impl GraphWriter {
+    fn node(&mut self, op_type: &str, inputs: &[&str], output: &str, attributes: Vec<WireWriter>) {
+        let mut node = WireWriter::new();
+        for input in inputs {
+            node.string(1, input);
+        }
+        node.string(2, output).string(4, op_type);
+        for attribute in &attributes {
+            node.message(5, attribute);
+        }
+        self.nodes.push(node);
+    }
+
+    /// Adds a float initializer; the values are rounded to f32, the precision ONNX runtimes compute in.
+    fn float_tensor(&mut self, name: &str, dims: &[usize], values: impl IntoIterator<Item = f64>) {
+        let raw: Vec<u8> = values.into_iter().flat_map(|value| (value as f32).to_le_bytes()).collect();
+        let mut tensor = WireWriter::new();
+        tensor
+            .packed_varints(1, &dims.iter().map(|&dim| dim as u64).collect::<Vec<u64>>())
+            .varint(2, DATA_TYPE_FLOAT)
+            .string(8, name)
+            .bytes(9, &raw);
+        self.initializers.push(tensor);
+    }
+
+    fn matrix(&mut self, name: &str, matrix: &Array2<f64>) {
+        self.float_tensor(name, matrix.shape(), matrix.iter().copied());
+    }
+
+    /// Adds a one-dimensional int64 initializer, e.g. the bounds of a `Slice`.
+    fn int64_tensor(&mut self, name: &str, values: &[i64]) {
+        let raw: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        let mut tensor = WireWriter::new();
+        tensor.packed_varints(1, &[values.len() as u64]).varint(2, DATA_TYPE_INT64).string(8, name).bytes(9, &raw);
+        self.initializers.push(tensor);
+    }
+
+    fn value_info(name: &str, elem_type: u64, dims: &[Dimension]) -> WireWriter {
+        let mut shape = WireWriter::new();
+        for dim in dims {
+            let mut dimension = WireWriter::new();
+            match dim {
+                Dimension::Fixed(size) => dimension.varint(1, *size as u64),
+                Dimension::Named(name) => dimension.string(2, name),
+            };
+            shape.message(1, &dimension);
+        }
+        let mut tensor_type = WireWriter::new();
+        tensor_type.varint(1, elem_type).message(2, &shape);
+        let mut type_proto = WireWriter::new();
+        type_proto.message(1, &tensor_type);
+        let mut value_info = WireWriter::new();
+        value_info.string(1, name).message(2, &type_proto);
+        value_info
+    }
+
+    fn to_message(&self, name: &str) -> WireWriter {
+        let mut graph = WireWriter::new();
+        for node in &self.nodes {
+            graph.message(1, node);
+        }
+        graph.string(2, name);
+        for initializer in &self.initializers {
+            graph.message(5, initializer);
+        }
+        for input in &self.inputs {
+            graph.message(11, input);
+        }
+        for output in &self.outputs {
+            graph.message(12, output);
+        }
+        graph
+    }
+}
+
+fn attribute(name: &str, attribute_type: u64) -> WireWriter {
+    let mut attribute = WireWriter::new();
+    attribute.string(1, name).varint(20, attribute_type);
+    attribute
+}
+
+fn int_attribute(name: &str, value: i64) -> WireWriter {
+    let mut attribute = attribute(name, ATTRIBUTE_INT);
+    attribute.varint(3, value as u64);
+    attribute
+}
+
+fn ints_attribute(name: &str, values: &[i64]) -> WireWriter {
+    let mut attribute = attribute(name, ATTRIBUTE_INTS);
+    attribute.packed_varints(8, &values.iter().map(|&value| value as u64).collect::<Vec<u64>>());
+    attribute
+}
+
+fn float_attribute(name: &str, value: f64) -> WireWriter {
+    let mut attribute = attribute(name, ATTRIBUTE_FLOAT);
+    attribute.fixed32(2, (value as f32).to_bits());
+    attribute
+}
+
+/// Writes the model as an ONNX `ModelProto` (IR version 8, opset 17) that standard
+/// runtimes execute and `import_onnx` reads back.
+///
+/// The graph takes `input_ids` (int64, [batch, sequence]) and returns `logits` (float,
+/// [batch, num_classes]). It spells out this crate's encoder: the token table (with the
+/// `sqrt(d_model)` input scaling folded in) plus the sinusoidal positions, parameter-free
+/// attention, post-norm residuals with `LayerNormalization`, ReLU feed-forward layers, mean
+/// pooling and a `Gemm` classifier. There is no attention mask, so every position of the
+/// batch is attended to and pooled, as in `Transformer::layer_outputs`. Weights are stored as f32.
+///
+/// # Arguments
+/// * `model` - The trained model.
+/// * `max_seq_length` - Longest sequence the graph accepts (rows of the position table).
+///
+/// # Returns
+/// * The serialized model, or an error if the model uses attention projections, relative
+///   positions, segment embeddings or the embedding layer norm, which the graph does not cover.
+pub fn build_onnx(model: &Transformer, max_seq_length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let config = &model.config;
+    if model.encoder_layers.iter().any(|layer| layer.projections.is_some()) {
+        return Err("attention projections are not supported by the ONNX export".into());
+    }
+    if model.encoder_layers.iter().any(|layer| layer.relative_positions.is_some()) {
+        return Err("relative positions are not supported by the ONNX export".into());
+    }
+    if model.embeddings.segment_embedding_matrix().is_some() || model.embeddings.layer_norm_epsilon.is_some() {
+        return Err("BERT-style embeddings (segment embeddings and the embedding layer norm) are not supported by the ONNX export".into());
+    }
+
+    let mut graph = GraphWriter::default();
+    graph.inputs.push(GraphWriter::value_info("input_ids", DATA_TYPE_INT64, &[Dimension::Named("batch"), Dimension::Named("sequence")]));
+    graph.outputs.push(GraphWriter::value_info(
+        "logits",
+        DATA_TYPE_FLOAT,
+        &[Dimension::Named("batch"), Dimension::Fixed(model.classification_head.num_classes())],
+    ));
+
+    // Token embeddings plus the first `sequence` rows of the position table.
+    graph.matrix("token_embd.weight", &(model.embeddings.token_embedding_matrix() * model.embeddings.input_scale()));
+    graph.matrix("position_embd.weight", &model.embeddings.generate_positional_encodings(max_seq_length));
+    graph.int64_tensor("zero", &[0]);
+    graph.int64_tensor("one", &[1]);
+    graph.int64_tensor("two", &[2]);
+    graph.node("Gather", &["token_embd.weight", "input_ids"], "token_embeddings", Vec::new());
+    graph.node("Shape", &["input_ids"], "input_shape", Vec::new());
+    graph.node("Slice", &["input_shape", "one", "two"], "sequence_length", Vec::new());
+    graph.node("Slice", &["position_embd.weight", "zero", "sequence_length"], "position_embeddings", Vec::new());
+    graph.node("Add", &["token_embeddings", "position_embeddings"], "blk.0.input", Vec::new());
+
+    graph.float_tensor("attn_scale", &[], [(config.d_model as f64).sqrt()]);
+    graph.float_tensor("norm.weight", &[config.d_model], vec![1.0; config.d_model]);
+    let epsilon = || vec![float_attribute("epsilon", config.epsilon)];
+    for (i, layer) in model.encoder_layers.iter().enumerate() {
+        let name = |stage: &str| format!("blk.{}.{}", i, stage);
+        let input = name("input");
+        let (w1, b1, w2, b2) = layer.feed_forward.parameters();
+        graph.matrix(&name("ffn_up.weight"), w1);
+        graph.float_tensor(&name("ffn_up.bias"), &[b1.len()], b1.iter().copied());
+        graph.matrix(&name("ffn_down.weight"), w2);
+        graph.float_tensor(&name("ffn_down.bias"), &[b2.len()], b2.iter().copied());
+
+        // softmax(x x^T / sqrt(d_model)) x
+        graph.node("Transpose", &[&input], &name("keys_t"), vec![ints_attribute("perm", &[0, 2, 1])]);
+        graph.node("MatMul", &[&input, &name("keys_t")], &name("scores"), Vec::new());
+        graph.node("Div", &[&name("scores"), "attn_scale"], &name("scaled_scores"), Vec::new());
+        graph.node("Softmax", &[&name("scaled_scores")], &name("attn_weights"), Vec::new());
+        graph.node("MatMul", &[&name("attn_weights"), &input], &name("attn"), Vec::new());
+        graph.node("Add", &[&input, &name("attn")], &name("attn_residual"), Vec::new());
+        graph.node("LayerNormalization", &[&name("attn_residual"), "norm.weight"], &name("attn_norm"), epsilon());
+
+        graph.node("MatMul", &[&name("attn_norm"), &name("ffn_up.weight")], &name("ffn_up"), Vec::new());
+        graph.node("Add", &[&name("ffn_up"), &name("ffn_up.bias")], &name("ffn_up_biased"), Vec::new());
+        graph.node("Relu", &[&name("ffn_up_biased")], &name("ffn_hidden"), Vec::new());
+        graph.node("MatMul", &[&name("ffn_hidden"), &name("ffn_down.weight")], &name("ffn_down"), Vec::new());
+        graph.node("Add", &[&name("ffn_down"), &name("ffn_down.bias")], &name("ffn_out"), Vec::new());
+        graph.node("Add", &[&name("attn_norm"), &name("ffn_out")], &name("ffn_residual"), Vec::new());
+        graph.node("LayerNormalization", &[&name("ffn_residual"), "norm.weight"], &format!("blk.{}.input", i + 1), epsilon());
+    }
+
+    // Mean pooling over the sequence, then the classifier with one weight row per class.
+    let (weights, biases) = model.classification_head.parameters();
+    graph.matrix("cls.weight", &weights.t().to_owned());
+    graph.float_tensor("cls.bias", &[biases.len()], biases.iter().copied());
+    let encoder_output = format!("blk.{}.input", model.encoder_layers.len());
+    graph.node("ReduceMean", &[&encoder_output], "pooled", vec![ints_attribute("axes", &[1]), int_attribute("keepdims", 0)]);
+    graph.node("Gemm", &["pooled", "cls.weight", "cls.bias"], "logits", vec![int_attribute("transB", 1)]);
+
+    let mut opset = WireWriter::new();
+    opset.string(1, "").varint(2, OPSET_VERSION);
+    let mut num_heads = WireWriter::new();
+    num_heads.string(1, "num_heads").string(2, &config.num_heads.to_string());
+    let mut onnx_model = WireWriter::new();
+    onnx_model
+        .varint(1, IR_VERSION)
+        .string(2, PRODUCER_NAME)
+        .message(7, &graph.to_message(PRODUCER_NAME))
+        .message(8, &opset)
+        .message(14, &num_heads);
+    Ok(onnx_model.into_bytes())
+}
+
+/// Writes the model to an `.onnx` file with [`build_onnx`].
+pub fn export_onnx(model: &Transformer, max_seq_length: usize, file_path: &str) -> Result<(), Box<dyn Error>> {
+    std::fs::write(file_path, build_onnx(model, max_seq_length)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::onnx::onnx_import::{import_onnx, OnnxModel, SUPPORTED_OPS};
+    use crate::test_utils::fixtures::{tiny_config, tiny_vocab};
+    use crate::transformer::TransformerConfig;
+
+    fn test_model(config: TransformerConfig) -> Transformer {
+        Transformer::new(config, tiny_vocab(&["good", "bad", "movie"]))
+    }
+
+    #[test]
+    fn test_export_round_trips_through_import() {
+        let config = TransformerConfig { num_layers: 2, d_model: 8, ff_dim: 12, epsilon: 1e-5, ..tiny_config(3) };
+        let original = test_model(config);
+        let bytes = build_onnx(&original, 16).unwrap();
+
+        let parsed = OnnxModel::parse(&bytes).unwrap();
+        assert!(parsed.nodes.iter().all(|node| SUPPORTED_OPS.contains(&node.op_type.as_str())));
+        assert_eq!(parsed.metadata["num_heads"], original.config.num_heads.to_string());
+
+        let imported = import_onnx(&parsed, original.embeddings.vocab().clone()).unwrap();
+        assert_eq!((imported.config.num_layers, imported.config.ff_dim, imported.config.num_classes), (2, 12, 3));
+        for tokens in [vec![2, 4], vec![3, 1, 4, 2]] {
+            let expected = original.layer_outputs(&tokens).pop().unwrap().1;
+            let actual = imported.layer_outputs(&tokens).pop().unwrap().1;
+            // Up to the f32 rounding of the exported weights.
+            assert!(expected.iter().zip(actual.iter()).all(|(a, b)| (a - b).abs() < 1e-4), "{:?} vs {:?}", expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_rejects_models_outside_the_graph() {
+        let projections = test_model(TransformerConfig { attention_projections: true, ..tiny_config(2) });
+        let error = build_onnx(&projections, 16).unwrap_err();
+        assert!(error.to_string().contains("attention projections"), "{}", error);
+
+        let relative = test_model(TransformerConfig { relative_positions: Some(4), ..tiny_config(2) });
+        assert!(build_onnx(&relative, 16).is_err());
+    }
+}
@@ -0,0 +1,675 @@
+use crate::embedding::embeddings::Embeddings;
+use crate::encoder::EncoderLayer;
+use crate::classification::ClassificationHead;
+use crate::feed_forward::FeedForwardNetwork;
+use crate::onnx::protobuf::{invalid, little_endian_chunks, WireReader};
+use crate::transformer::parallelism::Parallelism;
+use crate::summation::Summation;
+use crate::transformer::{Transformer, TransformerConfig};
+use ndarray::Array2;
+use std::collections::HashMap;
+use std::error::Error;
+
+// ONNX `TensorProto.DataType` values of the supported initializer types.
+pub(crate) const DATA_TYPE_FLOAT: u64 = 1;
+const DATA_TYPE_DOUBLE: u64 = 11;
+// `TensorProto.DataLocation` of tensors stored next to the model file.
+const DATA_LOCATION_EXTERNAL: u64 = 1;
+/// ONNX default of the `epsilon` attribute of `LayerNormalization`.
+const ONNX_LAYER_NORM_EPSILON: f64 = 1e-5;
+/// Largest difference tolerated between imported constants and the values this crate
+/// hard-codes (layer norm scale/shift, sinusoidal positions). Covers f32 rounding.
+const CONSTANT_TOLERANCE: f64 = 1e-4;
+
+/// Operators that may appear in a compatible graph. Besides the weighted ops they cover
+/// the parameter-free attention, residuals, mean pooling and shape/mask plumbing.
+pub const SUPPORTED_OPS: &[&str] = &[
+    "Gather", "MatMul", "Gemm", "Add", "Relu", "LayerNormalization", "Softmax", "Transpose",
+    "Div", "Mul", "Sub", "Sqrt", "ReduceMean", "ReduceSum", "Unsqueeze", "Squeeze", "Reshape",
+    "Shape", "Constant", "ConstantOfShape", "Cast", "Where", "Equal", "Expand", "Identity",
+    "Slice", "Concat",
+];
+
+/// An initializer of the graph. Only float and double tensors carry `data`.
+pub struct OnnxTensor {
+    pub name: String,
+    pub dims: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+pub struct OnnxNode {
+    pub op_type: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// Integer and float attributes by name; other attribute types are skipped.
+    pub int_attributes: HashMap<String, i64>,
+    pub float_attributes: HashMap<String, f64>,
+}
+
+/// The parts of an ONNX `ModelProto` the importer needs.
+pub struct OnnxModel {
+    /// Nodes in graph order, which ONNX requires to be topologically sorted.
+    pub nodes: Vec<OnnxNode>,
+    pub initializers: HashMap<String, OnnxTensor>,
+    /// `metadata_props` of the model, e.g. `num_heads`.
+    pub metadata: HashMap<String, String>,
+}
+
+impl OnnxModel {
+    /// Decodes a serialized ONNX `ModelProto`.
+    ///
+    /// # Arguments
+    /// * `bytes` - Contents of a `.onnx` file.
+    ///
+    /// # Returns
+    /// * The graph's nodes, initializers and metadata, or an error if the bytes are not a
+    ///   valid model or store weights outside the file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, std::io::Error> {
+        let mut model = OnnxModel { nodes: Vec::new(), initializers: HashMap::new(), metadata: HashMap::new() };
+        let mut has_graph = false;
+
+        let mut reader = WireReader::new(bytes);
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                // ModelProto.graph
+                7 => {
+                    has_graph = true;
+                    model.parse_graph(value.as_bytes()?)?;
+                }
+                // ModelProto.metadata_props
+                14 => {
+                    let (mut key, mut text) = (String::new(), String::new());
+                    let mut entry = WireReader::new(value.as_bytes()?);
+                    while let Some((field, value)) = entry.next_field()? {
+                        match field {
+                            1 => key = value.as_string()?,
+                            2 => text = value.as_string()?,
+                            _ => {}
+                        }
+                    }
+                    model.metadata.insert(key, text);
+                }
+                _ => {}
+            }
+        }
+
+        if !has_graph {
+            return Err(invalid("the file does not contain an ONNX graph"));
+        }
+        Ok(model)
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, std::io::Error> {
+        Self::parse(&std::fs::read(file_path)?)
+    }
+
+    fn parse_graph(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+        let mut reader = WireReader::new(bytes);
+        while let Some((field, value)) = reader.next_field()? {
+            match field {
+                // GraphProto.node
+                1 => self.nodes.push(parse_node(value.as_bytes()?)?),
+                // GraphProto.initializer
+                5 => {
+                    let tensor = parse_tensor(value.as_bytes()?)?;
+                    self.initializers.insert(tensor.name.clone(), tensor);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The initializer feeding input `index` of `node`, if that input is a constant weight.
+    fn initializer_input(&self, node: &OnnxNode, index: usize) -> Option<&OnnxTensor> {
+        node.inputs.get(index).and_then(|name| self.initializers.get(name))
+    }
+}
+
+fn parse_node(bytes: &[u8]) -> Result<OnnxNode, std::io::Error> {
+    let mut node = OnnxNode {
+        op_type: String::new(),
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        int_attributes: HashMap::new(),
+        float_attributes: HashMap::new(),
+    };
+
+    let mut reader = WireReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            1 => node.inputs.push(value.as_string()?),
+            2 => node.outputs.push(value.as_string()?),
+            4 => node.op_type = value.as_string()?,
+            5 => {
+                // AttributeProto: name = 1, f = 2, i = 3.
+                let mut name = String::new();
+                let (mut float, mut int) = (None, None);
+                let mut attribute = WireReader::new(value.as_bytes()?);
+                while let Some((field, value)) = attribute.next_field()? {
+                    match field {
+                        1 => name = value.as_string()?,
+                        2 => float = Some(value.as_f32()? as f64),
+                        3 => int = Some(value.as_u64()? as i64),
+                        _ => {}
+                    }
+                }
+                if let Some(float) = float {
+                    node.float_attributes.insert(name.clone(), float);
+                }
+                if let Some(int) = int {
+                    node.int_attributes.insert(name, int);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+fn parse_tensor(bytes: &[u8]) -> Result<OnnxTensor, std::io::Error> {
+    let mut tensor = OnnxTensor { name: String::new(), dims: Vec::new(), data: Vec::new() };
+    let mut data_type = 0;
+    let mut raw_data: &[u8] = &[];
+
+    let mut reader = WireReader::new(bytes);
+    while let Some((field, value)) = reader.next_field()? {
+        match field {
+            1 => tensor.dims.extend(value.varints()?.into_iter().map(|dim| dim as usize)),
+            2 => data_type = value.as_u64()?,
+            4 => tensor.data.extend(value.floats()?.into_iter().map(f64::from)),
+            8 => tensor.name = value.as_string()?,
+            9 => raw_data = value.as_bytes()?,
+            10 => tensor.data.extend(value.doubles()?),
+            14 if value.as_u64()? == DATA_LOCATION_EXTERNAL => {
+                return Err(invalid(&format!(
+                    "initializer {} uses external data; save the model with all tensors in one file",
+                    tensor.name
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    if !raw_data.is_empty() {
+        match data_type {
+            DATA_TYPE_FLOAT => tensor.data = little_endian_chunks::<4>(raw_data)?.map(|b| f32::from_le_bytes(b) as f64).collect(),
+            DATA_TYPE_DOUBLE => tensor.data = little_endian_chunks::<8>(raw_data)?.map(f64::from_le_bytes).collect(),
+            // Integer tensors (shapes, indices) are never used as weights.
+            _ => {}
+        }
+    }
+    Ok(tensor)
+}
+
+impl OnnxTensor {
+    fn is_float(&self) -> bool {
+        !self.data.is_empty() && self.data.len() == self.dims.iter().product::<usize>()
+    }
+
+    /// The tensor as a matrix, dropping leading dimensions of size 1.
+    fn matrix(&self) -> Result<Array2<f64>, Box<dyn Error>> {
+        let dims: Vec<usize> = self.dims.iter().copied().skip_while(|&dim| dim == 1).collect();
+        let shape = match dims.len() {
+            0 | 1 => (1, self.data.len()),
+            2 => (dims[0], dims[1]),
+            _ => return Err(format!("initializer {} has shape {:?}, expected a matrix", self.name, self.dims).into()),
+        };
+        if !self.is_float() {
+            return Err(format!("initializer {} is not a float or double tensor", self.name).into());
+        }
+        Ok(Array2::from_shape_vec(shape, self.data.clone())?)
+    }
+
+    /// The tensor as a single-row matrix.
+    fn row(&self) -> Result<Array2<f64>, Box<dyn Error>> {
+        if !self.is_float() {
+            return Err(format!("initializer {} is not a float or double tensor", self.name).into());
+        }
+        Ok(Array2::from_shape_vec((1, self.data.len()), self.data.clone())?)
+    }
+}
+
+/// A weighted linear layer `x.dot(weight) + bias` found in the graph.
+struct Linear {
+    weight: Array2<f64>,
+    bias: Array2<f64>,
+}
+
+/// Maps an ONNX encoder-classifier graph onto this crate's `Transformer`.
+///
+/// The graph is matched by structure, not by tensor names: the token embeddings are the
+/// initializer read by the first `Gather`, and every `MatMul`/`Gemm` with a constant weight
+/// is a linear layer, taken in graph order as `ffn_up`, `ffn_down` per encoder layer
+/// followed by the classifier. A compatible graph therefore has no attention projections,
+/// identity `LayerNormalization` scale/shift, ReLU activations and either no positional
+/// table or the sinusoidal one. The activation-only parts of the graph (attention, residuals,
+/// pooling) are assumed to match this crate's encoder and are not checked.
+///
+/// # Arguments
+/// * `model` - The parsed ONNX model.
+/// * `vocab` - Vocabulary the model was trained with, e.g. from its `vocab.txt`.
+///
+/// # Returns
+/// * The model, or an error describing why the graph cannot be represented.
+pub fn import_onnx(model: &OnnxModel, vocab: HashMap<String, usize>) -> Result<Transformer, Box<dyn Error>> {
+    let mut unsupported: Vec<&str> = model
+        .nodes
+        .iter()
+        .map(|node| node.op_type.as_str())
+        .filter(|op| !SUPPORTED_OPS.contains(op))
+        .collect();
+    unsupported.sort_unstable();
+    unsupported.dedup();
+    if !unsupported.is_empty() {
+        return Err(format!("unsupported operators: {}", unsupported.join(", ")).into());
+    }
+
+    let token_embeddings = model
+        .nodes
+        .iter()
+        .filter(|node| node.op_type == "Gather")
+        .find_map(|node| model.initializer_input(node, 0))
+        .ok_or("no Gather node reads a token embedding initializer")?
+        .matrix()?;
+    let (vocab_size, d_model) = token_embeddings.dim();
+    if let Some((token, &id)) = vocab.iter().find(|(_, &id)| id >= vocab_size) {
+        return Err(format!("token {} has id {} but the embedding table has {} rows", token, id, vocab_size).into());
+    }
+
+    let embeddings = Embeddings::from_matrix(token_embeddings, vocab);
+    check_positional_table(model, &embeddings)?;
+    let epsilon = layer_norm_epsilon(model)?;
+    let mut linears = linear_layers(model)?;
+
+    if linears.len() % 2 != 1 {
+        return Err(format!(
+            "found {} linear layers; expected two per encoder layer plus the classifier (attention projections are not supported)",
+            linears.len()
+        )
+        .into());
+    }
+    let classifier = linears.pop().unwrap();
+    let num_layers = linears.len() / 2;
+    let ff_dim = linears.first().map_or(0, |up| up.weight.ncols());
+
+    let mut encoder_layers = Vec::with_capacity(num_layers);
+    let mut linears = linears.into_iter();
+    while let (Some(up), Some(down)) = (linears.next(), linears.next()) {
+        if up.weight.dim() != (d_model, ff_dim) || down.weight.dim() != (ff_dim, d_model) {
+            return Err(format!(
+                "encoder layer {} has feed-forward weights {:?} and {:?}, expected {:?} and {:?}",
+                encoder_layers.len(),
+                up.weight.dim(),
+                down.weight.dim(),
+                (d_model, ff_dim),
+                (ff_dim, d_model)
+            )
+            .into());
+        }
+        encoder_layers.push(EncoderLayer {
+            feed_forward: FeedForwardNetwork::from_parameters(up.weight, up.bias, down.weight, down.bias),
+            epsilon,
+            relative_positions: None,
+            projections: None,
+            summation: Summation::Naive,
+            quantized_feed_forward: None,
+        });
+    }
+    if classifier.weight.nrows() != d_model {
+        return Err(format!("classifier weight has shape {:?}, expected {} rows", classifier.weight.dim(), d_model).into());
+    }
+
+    let relu_count = model.nodes.iter().filter(|node| node.op_type == "Relu").count();
+    if relu_count != num_layers {
+        return Err(format!("found {} Relu nodes for {} encoder layers", relu_count, num_layers).into());
+    }
+
+    let num_heads = match model.metadata.get("num_heads") {
+        Some(value) => value.parse()?,
+        None => 1,
+    };
+    let config = TransformerConfig {
+        num_layers,
+        d_model,
+        num_heads,
+        ff_dim,
+        num_classes: classifier.weight.ncols(),
+        epsilon,
+        bert_embeddings: false,
+        relative_positions: None,
+        attention_projections: false,
+    };
+
+    Ok(Transformer {
+        encoder_layers,
+        classification_head: ClassificationHead::from_parameters(classifier.weight, classifier.bias),
+        embeddings,
+        config,
+        parallelism: Parallelism::default(),
+        summation: Summation::Naive,
+        embedding_dropout: None,
+        train_dataset: None,
+    })
+}
+
+/// Reads an `.onnx` file and imports it with [`import_onnx`].
+pub fn import_onnx_file(file_path: &str, vocab: HashMap<String, usize>) -> Result<Transformer, Box<dyn Error>> {
+    import_onnx(&OnnxModel::load(file_path)?, vocab)
+}
+
+/// Collects the constant-weight `MatMul` and `Gemm` nodes in graph order. A `MatMul` bias is
+/// the constant added to its output by the following `Add`, if any.
+fn linear_layers(model: &OnnxModel) -> Result<Vec<Linear>, Box<dyn Error>> {
+    let mut linears = Vec::new();
+    for node in &model.nodes {
+        let Some(weight) = model.initializer_input(node, 1) else {
+            continue;
+        };
+        let (weight, bias) = match node.op_type.as_str() {
+            "MatMul" => {
+                let bias = model
+                    .nodes
+                    .iter()
+                    .filter(|add| add.op_type == "Add" && add.inputs.contains(&node.outputs[0]))
+                    .find_map(|add| add.inputs.iter().find_map(|input| model.initializers.get(input)));
+                (weight.matrix()?, bias)
+            }
+            "Gemm" => {
+                if node.int_attributes.get("transA").copied().unwrap_or(0) != 0 {
+                    return Err("Gemm with transA is not supported".into());
+                }
+                let alpha = node.float_attributes.get("alpha").copied().unwrap_or(1.0);
+                let beta = node.float_attributes.get("beta").copied().unwrap_or(1.0);
+                if alpha != 1.0 || beta != 1.0 {
+                    return Err("Gemm with alpha or beta other than 1 is not supported".into());
+                }
+                let mut matrix = weight.matrix()?;
+                if node.int_attributes.get("transB").copied().unwrap_or(0) != 0 {
+                    matrix = matrix.t().to_owned();
+                }
+                (matrix, model.initializer_input(node, 2))
+            }
+            _ => continue,
+        };
+
+        let bias = match bias {
+            Some(bias) => bias.row()?,
+            None => Array2::zeros((1, weight.ncols())),
+        };
+        if bias.ncols() != weight.ncols() {
+            return Err(format!("bias of length {} does not match weight {:?}", bias.ncols(), weight.dim()).into());
+        }
+        linears.push(Linear { weight, bias });
+    }
+    Ok(linears)
+}
+
+/// Checks that every `LayerNormalization` has no learned scale or shift and returns the
+/// shared epsilon.
+fn layer_norm_epsilon(model: &OnnxModel) -> Result<f64, Box<dyn Error>> {
+    let mut epsilon: Option<f64> = None;
+    for node in model.nodes.iter().filter(|node| node.op_type == "LayerNormalization") {
+        for (index, expected) in [(1, 1.0), (2, 0.0)] {
+            if let Some(tensor) = model.initializer_input(node, index) {
+                if tensor.data.iter().any(|&v| (v - expected).abs() > CONSTANT_TOLERANCE) {
+                    return Err(format!(
+                        "LayerNormalization {} {} is learned; only layer norms without scale and shift are supported",
+                        if index == 1 { "scale" } else { "bias" },
+                        tensor.name
+                    )
+                    .into());
+                }
+            }
+        }
+        let node_epsilon = node.float_attributes.get("epsilon").copied().unwrap_or(ONNX_LAYER_NORM_EPSILON);
+        match epsilon {
+            // Epsilons are f32 attributes, so compare them relative to their size.
+            Some(epsilon) if (epsilon - node_epsilon).abs() > 1e-6 * epsilon => {
+                return Err("layer norms with different epsilons are not supported".into());
+            }
+            _ => epsilon = Some(node_epsilon),
+        }
+    }
+    Ok(epsilon.unwrap_or(ONNX_LAYER_NORM_EPSILON))
+}
+
+/// Rejects learned position embeddings: a [positions, d_model] constant added to the token
+/// embeddings, directly or through a `Slice` to the sequence length, must equal the
+/// sinusoidal encodings this crate computes.
+fn check_positional_table(model: &OnnxModel, embeddings: &Embeddings) -> Result<(), Box<dyn Error>> {
+    let d_model = embeddings.token_embedding_matrix().ncols();
+    let constant = |input: &String| {
+        model.initializers.get(input).or_else(|| {
+            let slice = model.nodes.iter().find(|node| node.op_type == "Slice" && node.outputs.contains(input))?;
+            model.initializer_input(slice, 0)
+        })
+    };
+    for node in model.nodes.iter().filter(|node| node.op_type == "Add") {
+        for tensor in node.inputs.iter().filter_map(constant) {
+            let Ok(table) = tensor.matrix() else {
+                continue;
+            };
+            if table.nrows() < 2 || table.ncols() != d_model {
+                continue;
+            }
+            let sinusoidal = embeddings.generate_positional_encodings(table.nrows());
+            if table.iter().zip(sinusoidal.iter()).any(|(a, b)| (a - b).abs() > CONSTANT_TOLERANCE) {
+                return Err(format!(
+                    "initializer {} is a learned position embedding; only sinusoidal positions are supported",
+                    tensor.name
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::tiny_config;
+
+    /// Minimal protobuf writer for building test graphs.
+    fn key(buf: &mut Vec<u8>, field: u64, wire_type: u64) {
+        varint(buf, field << 3 | wire_type);
+    }
+
+    fn varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    fn bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        key(buf, field, 2);
+        varint(buf, bytes.len() as u64);
+        buf.extend_from_slice(bytes);
+    }
+
+    fn tensor(name: &str, matrix: &Array2<f64>, as_float: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let dims: Vec<u8> = matrix.shape().iter().fold(Vec::new(), |mut packed, &dim| {
+            varint(&mut packed, dim as u64);
+            packed
+        });
+        bytes_field(&mut buf, 1, &dims);
+        key(&mut buf, 2, 0);
+        varint(&mut buf, if as_float { DATA_TYPE_FLOAT } else { DATA_TYPE_DOUBLE });
+        bytes_field(&mut buf, 8, name.as_bytes());
+        if as_float {
+            let raw: Vec<u8> = matrix.iter().flat_map(|&v| (v as f32).to_le_bytes()).collect();
+            bytes_field(&mut buf, 9, &raw);
+        } else {
+            let packed: Vec<u8> = matrix.iter().flat_map(|v| v.to_le_bytes()).collect();
+            bytes_field(&mut buf, 10, &packed);
+        }
+        buf
+    }
+
+    fn node(op_type: &str, inputs: &[&str], output: &str, int_attributes: &[(&str, i64)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for input in inputs {
+            bytes_field(&mut buf, 1, input.as_bytes());
+        }
+        bytes_field(&mut buf, 2, output.as_bytes());
+        bytes_field(&mut buf, 4, op_type.as_bytes());
+        for (name, value) in int_attributes {
+            let mut attribute = Vec::new();
+            bytes_field(&mut attribute, 1, name.as_bytes());
+            key(&mut attribute, 3, 0);
+            varint(&mut attribute, *value as u64);
+            bytes_field(&mut buf, 5, &attribute);
+        }
+        buf
+    }
+
+    /// Graph nodes and initializers, serialized into a `ModelProto` by `to_bytes`.
+    #[derive(Default)]
+    struct GraphBuilder {
+        nodes: Vec<Vec<u8>>,
+        initializers: Vec<Vec<u8>>,
+    }
+
+    impl GraphBuilder {
+        fn to_bytes(&self) -> Vec<u8> {
+            let mut graph = Vec::new();
+            for node in &self.nodes {
+                bytes_field(&mut graph, 1, node);
+            }
+            for initializer in &self.initializers {
+                bytes_field(&mut graph, 5, initializer);
+            }
+            let mut model = Vec::new();
+            bytes_field(&mut model, 7, &graph);
+            model
+        }
+
+        fn layer_norm(&mut self, input: &str, output: &str, scale: f64, d_model: usize) {
+            let scale_name = format!("{}.scale", output);
+            self.initializers.push(tensor(&scale_name, &Array2::from_elem((1, d_model), scale), true));
+            self.nodes.push(node("LayerNormalization", &[input, &scale_name], output, &[]));
+        }
+    }
+
+    /// Writes `model` as the graph an exporter of this architecture would produce.
+    fn graph_for(model: &Transformer, seq_len: usize) -> GraphBuilder {
+        let d_model = model.config.d_model;
+        let mut graph = GraphBuilder::default();
+
+        let token_embd = model.embeddings.token_embedding_matrix() * model.embeddings.input_scale();
+        graph.initializers.push(tensor("token_embd", &token_embd, false));
+        graph.initializers.push(tensor("position_embd", &model.embeddings.generate_positional_encodings(seq_len), true));
+        graph.nodes.push(node("Gather", &["token_embd", "input_ids"], "tok", &[]));
+        graph.nodes.push(node("Add", &["tok", "position_embd"], "h0", &[]));
+
+        for (i, layer) in model.encoder_layers.iter().enumerate() {
+            let name = |stage: &str| format!("l{}.{}", i, stage);
+            let input = format!("h{}", i);
+            let (w1, b1, w2, b2) = layer.feed_forward.parameters();
+            for (tensor_name, matrix) in [("w1", w1), ("b1", b1), ("w2", w2), ("b2", b2)] {
+                graph.initializers.push(tensor(&name(tensor_name), matrix, false));
+            }
+
+            graph.nodes.push(node("Transpose", &[&input], &name("kt"), &[]));
+            graph.nodes.push(node("MatMul", &[&input, &name("kt")], &name("scores"), &[]));
+            graph.nodes.push(node("Div", &[&name("scores"), "sqrt_d"], &name("scaled"), &[]));
+            graph.nodes.push(node("Softmax", &[&name("scaled")], &name("weights"), &[]));
+            graph.nodes.push(node("MatMul", &[&name("weights"), &input], &name("attn"), &[]));
+            graph.nodes.push(node("Add", &[&input, &name("attn")], &name("res1"), &[]));
+            graph.layer_norm(&name("res1"), &name("norm1"), 1.0, d_model);
+            graph.nodes.push(node("MatMul", &[&name("norm1"), &name("w1")], &name("up"), &[]));
+            graph.nodes.push(node("Add", &[&name("up"), &name("b1")], &name("up_b"), &[]));
+            graph.nodes.push(node("Relu", &[&name("up_b")], &name("relu"), &[]));
+            graph.nodes.push(node("MatMul", &[&name("relu"), &name("w2")], &name("down"), &[]));
+            graph.nodes.push(node("Add", &[&name("down"), &name("b2")], &name("down_b"), &[]));
+            graph.nodes.push(node("Add", &[&name("norm1"), &name("down_b")], &name("res2"), &[]));
+            graph.layer_norm(&name("res2"), &format!("h{}", i + 1), 1.0, d_model);
+        }
+
+        let last = format!("h{}", model.encoder_layers.len());
+        let (weights, biases) = model.classification_head.parameters();
+        // The classifier is exported as a Gemm with transposed weights, as PyTorch does.
+        graph.initializers.push(tensor("cls.weight", &weights.t().to_owned(), false));
+        graph.initializers.push(tensor("cls.bias", biases, false));
+        graph.nodes.push(node("ReduceMean", &[&last], "pooled", &[]));
+        graph.nodes.push(node("Gemm", &["pooled", "cls.weight", "cls.bias"], "logits", &[("transB", 1)]));
+        graph
+    }
+
+    fn test_model() -> Transformer {
+        let vocab: HashMap<String, usize> =
+            ["[PAD]", "[UNK]", "good", "bad", "movie"].iter().enumerate().map(|(i, t)| (t.to_string(), i)).collect();
+        let config = TransformerConfig { num_layers: 2, d_model: 8, ff_dim: 12, epsilon: 1e-5, ..tiny_config(3) };
+        Transformer::new(config, vocab)
+    }
+
+    fn logits(model: &Transformer, tokens: &[usize]) -> Array2<f64> {
+        model.layer_outputs(tokens).pop().unwrap().1
+    }
+
+    #[test]
+    fn test_imported_model_matches_original() {
+        let original = test_model();
+        let bytes = graph_for(&original, 6).to_bytes();
+
+        let parsed = OnnxModel::parse(&bytes).unwrap();
+        let imported = import_onnx(&parsed, original.embeddings.vocab().clone()).unwrap();
+
+        assert_eq!(imported.config.num_layers, 2);
+        assert_eq!(imported.config.ff_dim, 12);
+        assert_eq!(imported.config.num_classes, 3);
+        for tokens in [vec![2, 4], vec![3, 1, 4, 2]] {
+            let diff = &logits(&original, &tokens) - &logits(&imported, &tokens);
+            assert!(diff.iter().all(|v| v.abs() < 1e-12), "logits differ: {:?}", diff);
+        }
+    }
+
+    #[test]
+    fn test_rejects_attention_projections() {
+        let original = test_model();
+        let mut graph = graph_for(&original, 6);
+        graph.initializers.push(tensor("q_proj", &Array2::eye(8), false));
+        graph.nodes.insert(2, node("MatMul", &["h0", "q_proj"], "q", &[]));
+
+        let error = import_onnx(&OnnxModel::parse(&graph.to_bytes()).unwrap(), HashMap::new()).err().expect("import should fail");
+        assert!(error.to_string().contains("attention projections"), "{}", error);
+    }
+
+    #[test]
+    fn test_rejects_learned_layer_norm_scale() {
+        let original = test_model();
+        let mut graph = graph_for(&original, 6);
+        graph.layer_norm("logits", "normed_logits", 2.0, 3);
+
+        let error = import_onnx(&OnnxModel::parse(&graph.to_bytes()).unwrap(), HashMap::new()).err().expect("import should fail");
+        assert!(error.to_string().contains("scale"), "{}", error);
+    }
+
+    #[test]
+    fn test_rejects_learned_positions_and_unknown_ops() {
+        let original = test_model();
+        let mut graph = graph_for(&original, 6);
+        graph.initializers[1] = tensor("position_embd", &Array2::from_elem((6, 8), 0.5), true);
+        let error = import_onnx(&OnnxModel::parse(&graph.to_bytes()).unwrap(), HashMap::new()).err().expect("import should fail");
+        assert!(error.to_string().contains("learned position embedding"), "{}", error);
+
+        let mut graph = graph_for(&original, 6);
+        graph.nodes.push(node("Erf", &["logits"], "erf", &[]));
+        let error = import_onnx(&OnnxModel::parse(&graph.to_bytes()).unwrap(), HashMap::new()).err().expect("import should fail");
+        assert_eq!(error.to_string(), "unsupported operators: Erf");
+    }
+
+    #[test]
+    fn test_rejects_vocab_larger_than_embeddings() {
+        let original = test_model();
+        let mut vocab = original.embeddings.vocab().clone();
+        vocab.insert("extra".to_string(), 5);
+
+        let parsed = OnnxModel::parse(&graph_for(&original, 6).to_bytes()).unwrap();
+        assert!(import_onnx(&parsed, vocab).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use std::io::{Error, ErrorKind};
+
+/// A single protobuf field value, before it is interpreted by the message that owns it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> WireValue<'a> {
+    pub fn as_u64(&self) -> Result<u64, Error> {
+        match *self {
+            WireValue::Varint(v) | WireValue::Fixed64(v) => Ok(v),
+            WireValue::Fixed32(v) => Ok(v as u64),
+            WireValue::Bytes(_) => Err(invalid("expected a numeric field, found bytes")),
+        }
+    }
+
+    pub fn as_bytes(&self) -> Result<&'a [u8], Error> {
+        match *self {
+            WireValue::Bytes(bytes) => Ok(bytes),
+            _ => Err(invalid("expected a length-delimited field")),
+        }
+    }
+
+    pub fn as_string(&self) -> Result<String, Error> {
+        String::from_utf8(self.as_bytes()?.to_vec()).map_err(|_| invalid("string field is not valid UTF-8"))
+    }
+
+    pub fn as_f32(&self) -> Result<f32, Error> {
+        match *self {
+            WireValue::Fixed32(v) => Ok(f32::from_bits(v)),
+            _ => Err(invalid("expected a 32-bit float field")),
+        }
+    }
+
+    /// Values of a repeated varint field, which may be packed into one length-delimited field.
+    pub fn varints(&self) -> Result<Vec<u64>, Error> {
+        match *self {
+            WireValue::Bytes(bytes) => {
+                let mut reader = WireReader::new(bytes);
+                let mut values = Vec::new();
+                while !reader.is_empty() {
+                    values.push(reader.read_varint()?);
+                }
+                Ok(values)
+            }
+            _ => Ok(vec![self.as_u64()?]),
+        }
+    }
+
+    /// Values of a repeated `float` field, packed or not.
+    pub fn floats(&self) -> Result<Vec<f32>, Error> {
+        match *self {
+            WireValue::Bytes(bytes) => {
+                Ok(little_endian_chunks::<4>(bytes)?.map(f32::from_le_bytes).collect())
+            }
+            _ => Ok(vec![self.as_f32()?]),
+        }
+    }
+
+    /// Values of a repeated `double` field, packed or not.
+    pub fn doubles(&self) -> Result<Vec<f64>, Error> {
+        match *self {
+            WireValue::Bytes(bytes) => {
+                Ok(little_endian_chunks::<8>(bytes)?.map(f64::from_le_bytes).collect())
+            }
+            WireValue::Fixed64(v) => Ok(vec![f64::from_bits(v)]),
+            _ => Err(invalid("expected a 64-bit float field")),
+        }
+    }
+}
+
+/// Reads the fields of a protobuf message in wire order.
+///
+/// Only the wire format is decoded; mapping field numbers to meaning is left to the caller,
+/// which keeps the reader independent of any `.proto` schema.
+pub struct WireReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        WireReader { bytes, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    /// Reads the next field.
+    ///
+    /// # Returns
+    /// * `(field_number, value)`, or `None` at the end of the message.
+    pub fn next_field(&mut self) -> Result<Option<(u32, WireValue<'a>)>, Error> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let key = self.read_varint()?;
+        let field = (key >> 3) as u32;
+        let value = match key & 0x7 {
+            0 => WireValue::Varint(self.read_varint()?),
+            1 => WireValue::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => {
+                let len = self.read_varint()? as usize;
+                WireValue::Bytes(self.take(len)?)
+            }
+            5 => WireValue::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+            wire_type => return Err(invalid(&format!("unsupported wire type {}", wire_type))),
+        };
+        Ok(Some((field, value)))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint is longer than 10 bytes"))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if n > self.bytes.len() - self.pos {
+            return Err(invalid("message is truncated"));
+        }
+        self.pos += n;
+        Ok(&self.bytes[self.pos - n..self.pos])
+    }
+}
+
+/// Writes the fields of a protobuf message, the counterpart of `WireReader`.
+#[derive(Default)]
+pub struct WireWriter {
+    bytes: Vec<u8>,
+}
+
+impl WireWriter {
+    pub fn new() -> Self {
+        WireWriter::default()
+    }
+
+    pub fn varint(&mut self, field: u32, value: u64) -> &mut Self {
+        self.key(field, 0);
+        self.write_varint(value);
+        self
+    }
+
+    pub fn fixed32(&mut self, field: u32, value: u32) -> &mut Self {
+        self.key(field, 5);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn bytes(&mut self, field: u32, bytes: &[u8]) -> &mut Self {
+        self.key(field, 2);
+        self.write_varint(bytes.len() as u64);
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    /// Writes `message` as an embedded message field.
+    pub fn message(&mut self, field: u32, message: &WireWriter) -> &mut Self {
+        self.bytes(field, &message.bytes)
+    }
+
+    /// Writes a repeated varint field in packed form.
+    pub fn packed_varints(&mut self, field: u32, values: &[u64]) -> &mut Self {
+        let mut packed = WireWriter::new();
+        for &value in values {
+            packed.write_varint(value);
+        }
+        self.bytes(field, &packed.bytes)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn key(&mut self, field: u32, wire_type: u64) {
+        self.write_varint((field as u64) << 3 | wire_type);
+    }
+
+    fn write_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+}
+
+/// Splits packed little-endian values into fixed-size chunks.
+pub fn little_endian_chunks<const N: usize>(bytes: &[u8]) -> Result<impl Iterator<Item = [u8; N]> + '_, Error> {
+    if !bytes.len().is_multiple_of(N) {
+        return Err(invalid(&format!("packed data is not a multiple of {} bytes", N)));
+    }
+    Ok(bytes.chunks_exact(N).map(|chunk| chunk.try_into().unwrap()))
+}
+
+pub fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_varint_and_length_delimited_fields() {
+        // field 1 = 150 (varint), field 2 = "hi"
+        let bytes = [0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i'];
+        let mut reader = WireReader::new(&bytes);
+
+        assert_eq!(reader.next_field().unwrap(), Some((1, WireValue::Varint(150))));
+        let (field, value) = reader.next_field().unwrap().unwrap();
+        assert_eq!((field, value.as_string().unwrap()), (2, "hi".to_string()));
+        assert_eq!(reader.next_field().unwrap(), None);
+    }
+
+    #[test]
+    fn test_packed_repeated_fields() {
+        let packed = [0x03, 0x8e, 0x02];
+        assert_eq!(WireValue::Bytes(&packed).varints().unwrap(), vec![3, 270]);
+
+        let floats: Vec<u8> = [1.5f32, -2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(WireValue::Bytes(&floats).floats().unwrap(), vec![1.5, -2.0]);
+    }
+
+    #[test]
+    fn test_writer_round_trips_through_reader() {
+        let mut nested = WireWriter::new();
+        nested.string(1, "hi");
+        let mut writer = WireWriter::new();
+        writer.varint(1, 300).fixed32(2, 1.5f32.to_bits()).message(3, &nested).packed_varints(4, &[3, 270]);
+        let bytes = writer.into_bytes();
+
+        let mut reader = WireReader::new(&bytes);
+        assert_eq!(reader.next_field().unwrap(), Some((1, WireValue::Varint(300))));
+        assert_eq!(reader.next_field().unwrap().unwrap().1.as_f32().unwrap(), 1.5);
+        let (field, value) = reader.next_field().unwrap().unwrap();
+        let mut nested = WireReader::new(value.as_bytes().unwrap());
+        assert_eq!((field, nested.next_field().unwrap().unwrap().1.as_string().unwrap()), (3, "hi".to_string()));
+        assert_eq!(reader.next_field().unwrap().unwrap().1.varints().unwrap(), vec![3, 270]);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_message_is_an_error() {
+        let bytes = [0x12, 0x05, b'a'];
+        assert!(WireReader::new(&bytes).next_field().is_err());
+    }
+}
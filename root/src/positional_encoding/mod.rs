@@ -1,3 +1,3 @@
 pub mod positional_encoder;
 
-pub use positional_encoder::{position_encoding_calculator};
+pub use positional_encoder::{sinusoidal_encodings, SinusoidalVariant};
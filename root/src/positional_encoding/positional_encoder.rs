@@ -1,47 +1,50 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+/// Frequencies of the sinusoidal positional encodings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SinusoidalVariant {
+	/// The original transformer: dimensions `2i` and `2i + 1` share the angle
+	/// `pos / 10000^(2i / d)`, taking its sine and cosine respectively.
+	#[default]
+	Standard,
+	/// Every dimension gets its own angle `pos / 10000^(dim / d)`: sine on even dimensions,
+	/// cosine on odd ones.
+	PerDimension,
+}
 
+impl SinusoidalVariant {
+	/// Angle of position `pos` in dimension `dim` of `embedding_dimensions`.
+	fn angle(self, pos: usize, dim: usize, embedding_dimensions: usize) -> f64 {
+		let exponent = match self {
+			SinusoidalVariant::Standard => 2 * (dim / 2),
+			SinusoidalVariant::PerDimension => dim,
+		};
+		pos as f64 / 10000f64.powf(exponent as f64 / embedding_dimensions as f64)
+	}
+}
 
-// I tweaked the math a bit in this module. the original formula is slightly different. refer the readme i made for this module.
-use std::f64::consts::PI;
-
-/// module for positional encoding
-/// Functional: position_encoding_calculator: Generates positional encodings for a sequence of tokens.
-/// Parameters: 
-///           "sequence_length": The number of tokens in the sequence (usize)
-///           "embedding_dimensions": The dimensionality  of each token's embedding
-
-/// Return:    A 2D vector (Vec<Vec<f64>>). Each row represents positional encoding for a specific token in the sequence.
+/// Sinusoidal positional encodings, shared by this module and `Embeddings`.
 ///
-
-
-pub fn position_encoding_calculator(sequence_length: usize, embedding_dimensions: usize) -> Vec<Vec<f64>>{
-	let mut positional_encoding = vec![vec![0.0; embedding_dimensions]; sequence_length];
-
-
-
-	for pos in 0..sequence_length{
-		for dim in 0..embedding_dimensions{
-			let angle = (pos as f64) / (10000f64.powf( dim as f64 / embedding_dimensions as f64));
-
-			if dim % 2 == 0{
-				positional_encoding[pos][dim] = angle.sin()
-			}
-			else{
-				positional_encoding[pos][dim] = angle.cos()
-			}
+/// # Arguments
+/// * `sequence_length` - Number of positions.
+/// * `embedding_dimensions` - Size of each encoding.
+/// * `variant` - How frequencies are assigned to dimensions.
+///
+/// # Returns
+/// * One encoding per position. Shape: [sequence_length, embedding_dimensions].
+pub fn sinusoidal_encodings(sequence_length: usize, embedding_dimensions: usize, variant: SinusoidalVariant) -> Array2<f64> {
+	Array2::from_shape_fn((sequence_length, embedding_dimensions), |(pos, dim)| {
+		let angle = variant.angle(pos, dim, embedding_dimensions);
+		if dim % 2 == 0 {
+			angle.sin()
+		} else {
+			angle.cos()
 		}
-	}
-
-
-	positional_encoding
+	})
 }
 
-
-
-
-
-/// Test Section of the module(as it's recommended to put tests in same file for Rust)
-
-
+// Test Section of the module(as it's recommended to put tests in same file for Rust)
 #[cfg(test)]
 mod tests {
     use super::*; 
@@ -50,25 +53,39 @@ mod tests {
     fn test_positional_encoding_dimensions() {
         let sequence_length = 4;
         let embedding_dimensions = 6;
-        let encodings = position_encoding_calculator(sequence_length, embedding_dimensions);
+        let encodings = sinusoidal_encodings(sequence_length, embedding_dimensions, SinusoidalVariant::PerDimension);
 
         
-        assert_eq!(encodings.len(), sequence_length, "Sequence length mismatch");
-        assert_eq!(encodings[0].len(), embedding_dimensions, "Embedding dimension mismatch");
+        assert_eq!(encodings.nrows(), sequence_length, "Sequence length mismatch");
+        assert_eq!(encodings.ncols(), embedding_dimensions, "Embedding dimension mismatch");
     }
 
     #[test]
     fn test_positional_encoding_values() {
         let sequence_length = 2;
         let embedding_dimensions = 4;
-        let encodings = position_encoding_calculator(sequence_length, embedding_dimensions);
+        let encodings = sinusoidal_encodings(sequence_length, embedding_dimensions, SinusoidalVariant::PerDimension);
 
       
         let expected_sin_value = (0.0 / 10000f64.powf(0.0)).sin();
-        assert!((encodings[0][0] - expected_sin_value).abs() < 1e-6);
+        assert!((encodings[[0, 0]] - expected_sin_value).abs() < 1e-6);
 
         let expected_cos_value = (1.0 / 10000f64.powf(0.25)).cos();
-        assert!((encodings[1][1] - expected_cos_value).abs() < 1e-6);
+        assert!((encodings[[1, 1]] - expected_cos_value).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sinusoidal_variants() {
+        let standard = sinusoidal_encodings(3, 4, SinusoidalVariant::Standard);
+        let per_dimension = sinusoidal_encodings(3, 4, SinusoidalVariant::PerDimension);
+
+        // Standard pairs sin/cos on one frequency: dims 2 and 3 both use 10000^(2/4).
+        assert!((standard[[2, 2]] - (2.0 / 100.0f64).sin()).abs() < 1e-12);
+        assert!((standard[[2, 3]] - (2.0 / 100.0f64).cos()).abs() < 1e-12);
+        assert!((per_dimension[[2, 3]] - (2.0 / 10000f64.powf(0.75)).cos()).abs() < 1e-12);
+        // Even dimensions agree; position 0 is (0, 1, 0, 1) in both.
+        assert_eq!(standard.column(2), per_dimension.column(2));
+        assert_eq!(standard.row(0), per_dimension.row(0));
     }
 }
 
@@ -0,0 +1,99 @@
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Scope nesting recorded in traces by `cargo run -- --trace`: steps, their forward,
+/// backward, loss and optimizer phases, and the encoder layers inside them.
+pub const DEFAULT_TRACE_DEPTH: usize = 3;
+
+/// One completed scope on the timeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    /// Innermost scope name, e.g. `attention`.
+    pub name: String,
+    /// Full scope path, e.g. `step;forward;encoder_layer_0`.
+    pub path: String,
+    /// Start relative to the moment tracing was enabled.
+    pub start: Duration,
+    pub duration: Duration,
+    /// Small per-thread number, in the order threads first recorded an event.
+    pub thread: usize,
+}
+
+/// Events collected while tracing was enabled, in the order the scopes ended.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    /// Converts the trace to the Trace Event Format read by `chrome://tracing` and Perfetto.
+    ///
+    /// # Returns
+    /// * A JSON object with one complete (`"ph": "X"`) event per scope, timestamps in
+    ///   microseconds, and a name for every thread.
+    pub fn to_chrome_json(&self) -> Value {
+        let mut threads: Vec<usize> = self.events.iter().map(|event| event.thread).collect();
+        threads.sort_unstable();
+        threads.dedup();
+
+        let thread_names = threads.iter().map(|&thread| {
+            let name = if thread == 0 { "main".to_string() } else { format!("worker {}", thread) };
+            json!({ "name": "thread_name", "ph": "M", "pid": 1, "tid": thread, "args": { "name": name } })
+        });
+        let events = self.events.iter().map(|event| {
+            json!({
+                "name": event.name,
+                "cat": event.path.split(';').next().unwrap_or(&event.name),
+                "ph": "X",
+                "ts": event.start.as_secs_f64() * 1e6,
+                "dur": event.duration.as_secs_f64() * 1e6,
+                "pid": 1,
+                "tid": event.thread,
+                "args": { "path": event.path },
+            })
+        });
+        json!({ "traceEvents": thread_names.chain(events).collect::<Vec<Value>>(), "displayTimeUnit": "ms" })
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), std::io::Error> {
+        std::fs::write(file_path, self.to_chrome_json().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chrome_json_has_complete_events_in_microseconds() {
+        let trace = Trace {
+            events: vec![
+                TraceEvent {
+                    name: "forward".to_string(),
+                    path: "step;forward".to_string(),
+                    start: Duration::from_micros(10),
+                    duration: Duration::from_micros(250),
+                    thread: 0,
+                },
+                TraceEvent {
+                    name: "tokenization".to_string(),
+                    path: "tokenization".to_string(),
+                    start: Duration::from_micros(5),
+                    duration: Duration::from_micros(3),
+                    thread: 2,
+                },
+            ],
+        };
+        let json = trace.to_chrome_json();
+        let events = json["traceEvents"].as_array().unwrap();
+
+        assert_eq!(events.iter().filter(|event| event["ph"] == "M").count(), 2);
+        let forward = events.iter().find(|event| event["name"] == "forward").unwrap();
+        assert_eq!(forward["ph"], "X");
+        assert_eq!(forward["cat"], "step");
+        assert_eq!(forward["ts"].as_f64(), Some(10.0));
+        assert_eq!(forward["dur"].as_f64(), Some(250.0));
+        assert_eq!(forward["args"]["path"], "step;forward");
+        assert!(events.iter().any(|event| event["args"]["name"] == "worker 2"));
+    }
+}
@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::profiling::chrome_trace::{Trace, TraceEvent};
+
+/// Width of the bars in `Profile::summary`.
+const SUMMARY_BAR_WIDTH: usize = 30;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STEPS: AtomicUsize = AtomicUsize::new(0);
+static TIMINGS: Mutex<BTreeMap<String, Timing>> = Mutex::new(BTreeMap::new());
+/// Deepest scope nesting recorded on the timeline; 0 while tracing is off.
+static TRACE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+static TRACE_EVENTS: Mutex<Vec<TraceEvent>> = Mutex::new(Vec::new());
+static TRACE_START: OnceLock<Instant> = OnceLock::new();
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Names of the scopes open on this thread, outermost first.
+    static STACK: RefCell<Vec<Cow<'static, str>>> = const { RefCell::new(Vec::new()) };
+    /// Trace thread number, assigned when the thread opens its first scope.
+    static THREAD: usize = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Total time and number of calls of one scope path.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timing {
+    pub total: Duration,
+    pub calls: usize,
+}
+
+/// Starts recording. Scopes opened while the profiler is disabled cost one atomic load.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Also records every scope nested at most `max_depth` deep as an event on a timeline,
+/// which `take_trace` returns for `chrome://tracing`. Enables the profiler.
+pub fn enable_trace(max_depth: usize) {
+    TRACE_START.get_or_init(Instant::now);
+    TRACE_DEPTH.store(max_depth, Ordering::Relaxed);
+    enable();
+}
+
+/// Stops recording timings and trace events.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+    TRACE_DEPTH.store(0, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Times the enclosing block as `name`, nested under the scopes already open on this thread.
+///
+/// # Returns
+/// * A guard that records the elapsed time when it is dropped.
+pub fn scope(name: &'static str) -> Scope {
+    if is_enabled() { Scope::open(Cow::Borrowed(name)) } else { Scope { started: None } }
+}
+
+/// Same as `scope`, for repeated modules such as `encoder_layer_0`, `encoder_layer_1`, ...
+pub fn scope_indexed(name: &'static str, index: usize) -> Scope {
+    if is_enabled() { Scope::open(Cow::Owned(format!("{}_{}", name, index))) } else { Scope { started: None } }
+}
+
+/// Runs `f` inside `scope(name)`.
+pub fn time<R>(name: &'static str, f: impl FnOnce() -> R) -> R {
+    let _scope = scope(name);
+    f()
+}
+
+/// Counts one training step, so the summary can report time per step.
+pub fn record_step() {
+    if is_enabled() {
+        STEPS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns everything recorded so far and starts over.
+pub fn take_profile() -> Profile {
+    let timings = std::mem::take(&mut *TIMINGS.lock().unwrap());
+    Profile { timings, steps: STEPS.swap(0, Ordering::Relaxed) }
+}
+
+/// Returns the trace events recorded so far and starts over.
+pub fn take_trace() -> Trace {
+    Trace { events: std::mem::take(&mut *TRACE_EVENTS.lock().unwrap()) }
+}
+
+/// Guard returned by `scope`.
+pub struct Scope {
+    started: Option<Instant>,
+}
+
+impl Scope {
+    fn open(name: Cow<'static, str>) -> Self {
+        THREAD.with(|_| ());
+        STACK.with(|stack| stack.borrow_mut().push(name));
+        Scope { started: Some(Instant::now()) }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let Some(started) = self.started else {
+            return;
+        };
+        let elapsed = started.elapsed();
+        let (path, depth, name) = STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let path = stack.join(";");
+            let depth = stack.len();
+            (path, depth, stack.pop().unwrap_or_default())
+        });
+        if depth <= TRACE_DEPTH.load(Ordering::Relaxed) {
+            let trace_start = *TRACE_START.get_or_init(Instant::now);
+            TRACE_EVENTS.lock().unwrap().push(TraceEvent {
+                name: name.into_owned(),
+                path: path.clone(),
+                start: started.saturating_duration_since(trace_start),
+                duration: elapsed,
+                thread: THREAD.with(|thread| *thread),
+            });
+        }
+        let mut timings = TIMINGS.lock().unwrap();
+        let timing = timings.entry(path).or_default();
+        timing.total += elapsed;
+        timing.calls += 1;
+    }
+}
+
+/// Timings per scope path, e.g. `step;forward;encoder_layer_0;attention`.
+///
+/// Scopes opened on worker threads (see `Parallelism`) start a new path, because the
+/// worker does not see the scopes of the thread that spawned it.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub timings: BTreeMap<String, Timing>,
+    pub steps: usize,
+}
+
+impl Profile {
+    /// Time spent in a path itself, excluding its direct children.
+    pub fn self_time(&self, path: &str) -> Duration {
+        let children: Duration = self
+            .timings
+            .iter()
+            .filter(|(child, _)| child.strip_prefix(path).and_then(|rest| rest.strip_prefix(';')).is_some_and(|rest| !rest.contains(';')))
+            .map(|(_, timing)| timing.total)
+            .sum();
+        self.timings.get(path).map_or(Duration::ZERO, |timing| timing.total.saturating_sub(children))
+    }
+
+    /// Self times in the folded-stack format read by flame graph tools
+    /// (`path microseconds` per line).
+    pub fn folded(&self) -> String {
+        self.tree_order()
+            .into_iter()
+            .map(|(path, _)| format!("{} {}\n", path, self.self_time(path).as_micros()))
+            .collect()
+    }
+
+    /// Paths sorted by their components, so every scope is followed by its children.
+    fn tree_order(&self) -> Vec<(&String, &Timing)> {
+        let mut entries: Vec<(&String, &Timing)> = self.timings.iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.split(';').cmp(b.split(';')));
+        entries
+    }
+
+    /// Indented tree of all scopes with total time, time per step, calls and share of the
+    /// outermost scopes, with a bar per row.
+    pub fn summary(&self) -> String {
+        let root_total: Duration =
+            self.timings.iter().filter(|(path, _)| !path.contains(';')).map(|(_, timing)| timing.total).sum();
+        let steps = self.steps.max(1);
+
+        let mut lines = vec![format!(
+            "{:<40} {:>12} {:>12} {:>8} {:>7}",
+            "scope", "total ms", "ms/step", "calls", "share"
+        )];
+        for (path, timing) in self.tree_order() {
+            let depth = path.matches(';').count();
+            let name = path.rsplit(';').next().unwrap_or(path);
+            let share = if root_total.is_zero() { 0.0 } else { timing.total.as_secs_f64() / root_total.as_secs_f64() };
+            lines.push(format!(
+                "{:<40} {:>12.3} {:>12.3} {:>8} {:>6.1}% {}",
+                format!("{}{}", "  ".repeat(depth), name),
+                timing.total.as_secs_f64() * 1e3,
+                timing.total.as_secs_f64() * 1e3 / steps as f64,
+                timing.calls,
+                share * 100.0,
+                "#".repeat((share * SUMMARY_BAR_WIDTH as f64).round() as usize)
+            ));
+        }
+        lines.push(format!("{} steps", self.steps));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    #[test]
+    fn test_scopes_nest_fold_and_trace() {
+        // The profiler is process-wide, so the scope names are unique to this test.
+        enable_trace(2);
+        for _ in 0..2 {
+            let _step = scope("profiler_test_step");
+            record_step();
+            {
+                let _layer = scope_indexed("encoder_layer", 0);
+                let _attention = scope("attention");
+                sleep(Duration::from_millis(2));
+            }
+            sleep(Duration::from_millis(1));
+        }
+        disable();
+        drop(scope("ignored"));
+        let profile = take_profile();
+        let trace = take_trace();
+
+        let paths: Vec<&str> = profile.timings.keys().map(String::as_str).collect();
+        assert!(paths.contains(&"profiler_test_step"));
+        assert!(paths.contains(&"profiler_test_step;encoder_layer_0;attention"));
+        assert!(!paths.contains(&"ignored"));
+        assert!(profile.steps >= 2);
+        assert_eq!(profile.timings["profiler_test_step"].calls, 2);
+
+        let attention = profile.timings["profiler_test_step;encoder_layer_0;attention"].total;
+        assert!(attention >= Duration::from_millis(4));
+        assert!(profile.self_time("profiler_test_step") >= Duration::from_millis(2));
+        assert!(profile.self_time("profiler_test_step") < profile.timings["profiler_test_step"].total);
+        assert!(profile.folded().lines().any(|line| line.starts_with("profiler_test_step;encoder_layer_0;attention ")));
+        assert!(profile.summary().contains("    attention"));
+
+        let traced: Vec<&TraceEvent> = trace.events.iter().filter(|event| event.path.starts_with("profiler_test_step")).collect();
+        // Depth 3 (`attention`) is deeper than the trace depth.
+        assert_eq!(traced.iter().filter(|event| event.name == "profiler_test_step").count(), 2);
+        assert_eq!(traced.iter().filter(|event| event.name == "encoder_layer_0").count(), 2);
+        assert!(traced.iter().all(|event| event.name != "attention"));
+        let steps: Vec<&&TraceEvent> = traced.iter().filter(|event| event.name == "profiler_test_step").collect();
+        assert!(steps[1].start >= steps[0].start + steps[0].duration);
+    }
+}
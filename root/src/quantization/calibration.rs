@@ -0,0 +1,106 @@
+use crate::transformer::Transformer;
+
+/// Percentile of absolute activation values used as the clipping range. Clipping the
+/// few largest outliers leaves a finer quantization step for all other values.
+pub const DEFAULT_CALIBRATION_PERCENTILE: f64 = 99.9;
+
+/// Clipping ranges for the two activations quantized in an encoder layer's feed-forward network.
+#[derive(Debug, Clone)]
+pub struct LayerRanges {
+    /// Range of the FFN input (the output of the first layer norm).
+    pub ffn_input: f64,
+    /// Range of the ReLU activations fed into the second linear layer.
+    pub ffn_hidden: f64,
+}
+
+/// Activation clipping ranges for every encoder layer, chosen from a sample dataset.
+#[derive(Debug, Clone)]
+pub struct CalibrationRanges {
+    pub layers: Vec<LayerRanges>,
+}
+
+impl CalibrationRanges {
+    /// Runs the sequences through the model and records the FFN activations of every layer.
+    ///
+    /// # Arguments
+    /// * `model` - The full-precision model.
+    /// * `sequences` - Calibration inputs, representative of the data seen at inference.
+    /// * `percentile` - Percentile of the absolute activations used as range, in (0, 100].
+    ///   100 uses the largest activation seen.
+    ///
+    /// # Returns
+    /// A new instance of `CalibrationRanges` with one entry per encoder layer.
+    pub fn calibrate(model: &Transformer, sequences: &[Vec<usize>], percentile: f64) -> Self {
+        assert!(percentile > 0.0 && percentile <= 100.0, "percentile must be in (0, 100].");
+
+        let num_layers = model.encoder_layers.len();
+        let mut inputs: Vec<Vec<f64>> = vec![Vec::new(); num_layers];
+        let mut hiddens: Vec<Vec<f64>> = vec![Vec::new(); num_layers];
+
+        for tokens in sequences {
+            let mut hidden = model.embeddings.encode(tokens);
+            for (i, layer) in model.encoder_layers.iter().enumerate() {
+                let mut stages = layer.forward_stages(&hidden);
+                let norm1 = &stages[1].1;
+                inputs[i].extend(norm1.iter().map(|v| v.abs()));
+                hiddens[i].extend(layer.feed_forward.hidden_activations(norm1).iter().map(|v| v.abs()));
+                hidden = stages.pop().unwrap().1;
+            }
+        }
+
+        let layers = inputs
+            .iter_mut()
+            .zip(hiddens.iter_mut())
+            .map(|(input, hidden)| LayerRanges {
+                ffn_input: percentile_value(input, percentile),
+                ffn_hidden: percentile_value(hidden, percentile),
+            })
+            .collect();
+
+        CalibrationRanges { layers }
+    }
+}
+
+/// Returns the value below which `percentile` percent of `values` fall (nearest rank).
+pub fn percentile_value(values: &mut [f64], percentile: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let rank = ((percentile / 100.0) * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::tiny_config;
+    use crate::transformer::TransformerConfig;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_percentile_ignores_outliers() {
+        let mut values: Vec<f64> = (1..=1000).map(|v| v as f64 / 1000.0).collect();
+        values.push(50.0);
+
+        assert!((percentile_value(&mut values, 99.0) - 0.991).abs() < 1e-9);
+        assert_eq!(percentile_value(&mut values, 100.0), 50.0);
+    }
+
+    #[test]
+    fn test_calibrate_ranges_per_layer() {
+        let vocab = HashMap::from([("[PAD]".to_string(), 0), ("a".to_string(), 1), ("b".to_string(), 2)]);
+        let config = TransformerConfig { num_layers: 2, ..tiny_config(2) };
+        let model = Transformer::new(config, vocab);
+        let sequences = vec![vec![1, 2, 0], vec![2, 2, 1]];
+
+        let clipped = CalibrationRanges::calibrate(&model, &sequences, 90.0);
+        let full = CalibrationRanges::calibrate(&model, &sequences, 100.0);
+
+        assert_eq!(clipped.layers.len(), 2);
+        for (clipped, full) in clipped.layers.iter().zip(full.layers.iter()) {
+            assert!(clipped.ffn_input > 0.0 && clipped.ffn_input <= full.ffn_input);
+            assert!(clipped.ffn_hidden <= full.ffn_hidden);
+        }
+    }
+}
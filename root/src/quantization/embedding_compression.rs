@@ -0,0 +1,204 @@
+use ndarray::{Array1, Array2};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// How the token embedding matrix is stored in checkpoints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum EmbeddingPrecision {
+    /// Full f64 values.
+    #[default]
+    F64,
+    /// Symmetric int8 values with one f64 scale per token row.
+    Int8,
+    /// Symmetric int4 values in [-7, 7], two per byte, with one f64 scale per token row.
+    Int4,
+}
+
+impl EmbeddingPrecision {
+    /// Largest magnitude of a quantized value, or `None` for full precision.
+    fn max_level(self) -> Option<f64> {
+        match self {
+            EmbeddingPrecision::F64 => None,
+            EmbeddingPrecision::Int8 => Some(127.0),
+            EmbeddingPrecision::Int4 => Some(7.0),
+        }
+    }
+}
+
+/// An embedding matrix quantized row by row, so that a token's row can be decoded
+/// without touching the rest of the matrix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompressedMatrix {
+    pub precision: EmbeddingPrecision,
+    pub rows: usize,
+    pub cols: usize,
+    /// Step size of every row: `value = level * scale`.
+    pub scales: Vec<f64>,
+    /// Quantized levels in row-major order: one byte per int8 value, or two int4
+    /// values per byte (low nibble first, each row starting on a new byte).
+    /// Stored as a hex string in JSON checkpoints.
+    #[serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+    pub data: Vec<u8>,
+}
+
+impl CompressedMatrix {
+    /// Quantizes every row so that its largest magnitude maps to the largest level.
+    ///
+    /// # Arguments
+    /// * `matrix` - Embedding matrix. Shape: [vocab_size, model_dim].
+    /// * `precision` - `Int8` or `Int4`.
+    ///
+    /// # Returns
+    /// A new instance of `CompressedMatrix`.
+    pub fn compress(matrix: &Array2<f64>, precision: EmbeddingPrecision) -> Self {
+        let max_level = precision.max_level().expect("Compression needs an integer precision.");
+        let (rows, cols) = matrix.dim();
+        let mut scales = Vec::with_capacity(rows);
+        let mut data = Vec::with_capacity(rows * Self::row_bytes(precision, cols));
+
+        for row in matrix.rows() {
+            let range = row.iter().fold(0.0f64, |m, &v| m.max(v.abs()));
+            let scale = if range > 0.0 { range / max_level } else { 1.0 };
+            let levels: Vec<i8> = row.iter().map(|&v| (v / scale).round().clamp(-max_level, max_level) as i8).collect();
+            match precision {
+                EmbeddingPrecision::Int4 => {
+                    data.extend(levels.chunks(2).map(|pair| {
+                        let high = pair.get(1).copied().unwrap_or(0);
+                        (pair[0] as u8 & 0x0F) | ((high as u8 & 0x0F) << 4)
+                    }));
+                }
+                _ => data.extend(levels.iter().map(|&level| level as u8)),
+            }
+            scales.push(scale);
+        }
+        CompressedMatrix { precision, rows, cols, scales, data }
+    }
+
+    fn row_bytes(precision: EmbeddingPrecision, cols: usize) -> usize {
+        match precision {
+            EmbeddingPrecision::Int4 => cols.div_ceil(2),
+            _ => cols,
+        }
+    }
+
+    /// Decodes one token row.
+    pub fn row(&self, index: usize) -> Array1<f64> {
+        let row_bytes = Self::row_bytes(self.precision, self.cols);
+        let bytes = &self.data[index * row_bytes..(index + 1) * row_bytes];
+        let scale = self.scales[index];
+        let levels: Vec<i8> = match self.precision {
+            // Shifting the nibble into the high bits and back sign-extends it.
+            EmbeddingPrecision::Int4 => bytes
+                .iter()
+                .flat_map(|&byte| [((byte << 4) as i8) >> 4, (byte as i8) >> 4])
+                .take(self.cols)
+                .collect(),
+            _ => bytes.iter().map(|&byte| byte as i8).collect(),
+        };
+        levels.into_iter().map(|level| level as f64 * scale).collect()
+    }
+
+    /// Decodes the whole matrix. Shape: [rows, cols].
+    ///
+    /// # Returns
+    /// An error if the scales or data do not cover `rows` x `cols` values, e.g. for a
+    /// truncated or corrupt checkpoint.
+    pub fn decompress(&self) -> Result<Array2<f64>, String> {
+        let row_bytes = Self::row_bytes(self.precision, self.cols);
+        if self.precision.max_level().is_none() {
+            return Err("compressed embeddings need an integer precision".to_string());
+        }
+        if self.scales.len() != self.rows || self.data.len() != self.rows * row_bytes {
+            return Err(format!(
+                "compressed embeddings hold {} scales and {} bytes, expected {} and {} for a {}x{} {:?} matrix",
+                self.scales.len(), self.data.len(), self.rows, self.rows * row_bytes, self.rows, self.cols, self.precision
+            ));
+        }
+        let mut matrix = Array2::zeros((self.rows, self.cols));
+        for (index, mut row) in matrix.rows_mut().into_iter().enumerate() {
+            row.assign(&self.row(index));
+        }
+        Ok(matrix)
+    }
+}
+
+fn serialize_hex<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&data.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+fn deserialize_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    if hex.len() % 2 != 0 {
+        return Err(serde::de::Error::custom("hex data must have an even number of digits"));
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let digit = |c: u8| (c as char).to_digit(16).ok_or_else(|| serde::de::Error::custom(format!("invalid hex digit {:?}", c as char)));
+            Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn max_row_error(matrix: &Array2<f64>, compressed: &CompressedMatrix) -> Vec<f64> {
+        let decoded = compressed.decompress().unwrap();
+        matrix
+            .rows()
+            .into_iter()
+            .zip(decoded.rows())
+            .map(|(a, b)| a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f64::max))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_within_half_a_step() {
+        let matrix = array![[0.5, -1.0, 0.25], [0.001, 0.009, -0.003], [0.0, 0.0, 0.0]];
+        for precision in [EmbeddingPrecision::Int8, EmbeddingPrecision::Int4] {
+            let compressed = CompressedMatrix::compress(&matrix, precision);
+            for (error, scale) in max_row_error(&matrix, &compressed).iter().zip(&compressed.scales) {
+                assert!(*error <= scale / 2.0 + 1e-12, "{:?}: error {} for scale {}", precision, error, scale);
+            }
+            assert_eq!(compressed.row(1), compressed.decompress().unwrap().row(1));
+        }
+    }
+
+    #[test]
+    fn test_int4_packs_two_values_per_byte() {
+        let matrix = array![[7.0, -7.0, 3.0], [-14.0, 2.0, -4.0]];
+        let compressed = CompressedMatrix::compress(&matrix, EmbeddingPrecision::Int4);
+
+        assert_eq!(compressed.data.len(), 4);
+        assert_eq!(compressed.scales.len(), 2);
+        assert_eq!(compressed.decompress().unwrap(), matrix);
+    }
+
+    #[test]
+    fn test_json_stores_hex() {
+        let matrix = array![[1.0, -0.5], [0.25, 0.0]];
+        let compressed = CompressedMatrix::compress(&matrix, EmbeddingPrecision::Int8);
+        let json = serde_json::to_value(&compressed).unwrap();
+
+        assert_eq!(json["data"], "7fc07f00");
+        let loaded: CompressedMatrix = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded, compressed);
+    }
+
+    #[test]
+    fn test_corrupt_data_is_an_error() {
+        let matrix = array![[1.0, -0.5], [0.25, 0.0]];
+        let mut json = serde_json::to_value(CompressedMatrix::compress(&matrix, EmbeddingPrecision::Int8)).unwrap();
+
+        json["data"] = "7fc0".into();
+        let truncated: CompressedMatrix = serde_json::from_value(json.clone()).unwrap();
+        assert!(truncated.decompress().is_err());
+
+        json["data"] = "7fé0".into();
+        assert!(serde_json::from_value::<CompressedMatrix>(json.clone()).is_err());
+        json["data"] = "7fzz7f00".into();
+        assert!(serde_json::from_value::<CompressedMatrix>(json).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod quantizer;
+pub mod calibration;
+pub mod quantized_feed_forward;
+pub mod embedding_compression;
@@ -0,0 +1,123 @@
+use crate::feed_forward::FeedForwardNetwork;
+use crate::quantization::calibration::{CalibrationRanges, LayerRanges};
+use crate::quantization::quantizer::{fake_quantize, Granularity, QuantizedMatrix};
+use crate::transformer::Transformer;
+use ndarray::Array2;
+
+/// A feed-forward network with int8 weights and int8-simulated activations.
+///
+/// Biases stay in f64. Activations are clipped to their calibrated ranges before
+/// quantization, so the computation matches an int8 kernel up to accumulation order.
+pub struct QuantizedFeedForward {
+    w1: QuantizedMatrix,
+    b1: Array2<f64>,
+    w2: QuantizedMatrix,
+    b2: Array2<f64>,
+    ranges: LayerRanges,
+}
+
+impl QuantizedFeedForward {
+    /// Quantizes the weights of a feed-forward network.
+    ///
+    /// # Arguments
+    /// * `network` - The full-precision network.
+    /// * `granularity` - Scale granularity of the weights. `Column` gives every output channel its own scale.
+    /// * `ranges` - Calibrated clipping ranges of the layer's activations.
+    ///
+    /// # Returns
+    /// A new instance of `QuantizedFeedForward`.
+    pub fn new(network: &FeedForwardNetwork, granularity: Granularity, ranges: &LayerRanges) -> Self {
+        let (w1, b1, w2, b2) = network.parameters();
+        QuantizedFeedForward {
+            w1: QuantizedMatrix::quantize(w1, granularity),
+            b1: b1.clone(),
+            w2: QuantizedMatrix::quantize(w2, granularity),
+            b2: b2.clone(),
+            ranges: ranges.clone(),
+        }
+    }
+
+    pub fn forward(&self, x: &Array2<f64>) -> Array2<f64> {
+        let x = fake_quantize(x, self.ranges.ffn_input);
+        let mut h = x.dot(&self.w1.dequantize()) + &self.b1;
+        h.mapv_inplace(|v| v.max(0.0));
+
+        let h = fake_quantize(&h, self.ranges.ffn_hidden);
+        h.dot(&self.w2.dequantize()) + &self.b2
+    }
+}
+
+/// Mean absolute difference between the full-precision and quantized FFN outputs of
+/// every encoder layer, evaluated on the full-precision FFN inputs.
+///
+/// # Arguments
+/// * `model` - The full-precision model.
+/// * `sequences` - Evaluation inputs; use data not seen during calibration.
+/// * `granularity` - Scale granularity of the quantized weights.
+/// * `ranges` - Calibrated activation ranges.
+///
+/// # Returns
+/// * One mean absolute error per encoder layer.
+pub fn ffn_quantization_error(
+    model: &Transformer,
+    sequences: &[Vec<usize>],
+    granularity: Granularity,
+    ranges: &CalibrationRanges,
+) -> Vec<f64> {
+    let quantized: Vec<QuantizedFeedForward> = model
+        .encoder_layers
+        .iter()
+        .zip(ranges.layers.iter())
+        .map(|(layer, layer_ranges)| QuantizedFeedForward::new(&layer.feed_forward, granularity, layer_ranges))
+        .collect();
+
+    let mut total_errors = vec![0.0; quantized.len()];
+    let mut counts = vec![0usize; quantized.len()];
+
+    for tokens in sequences {
+        let mut hidden = model.embeddings.encode(tokens);
+        for (i, (layer, quantized_ffn)) in model.encoder_layers.iter().zip(quantized.iter()).enumerate() {
+            let mut stages = layer.forward_stages(&hidden);
+            let (norm1, expected) = (&stages[1].1, &stages[2].1);
+
+            let actual = quantized_ffn.forward(norm1);
+            total_errors[i] += actual.iter().zip(expected.iter()).map(|(a, e)| (a - e).abs()).sum::<f64>();
+            counts[i] += expected.len();
+
+            hidden = stages.pop().unwrap().1;
+        }
+    }
+
+    total_errors
+        .iter()
+        .zip(counts.iter())
+        .map(|(&error, &count)| error / count.max(1) as f64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::tiny_config;
+    use crate::quantization::calibration::DEFAULT_CALIBRATION_PERCENTILE;
+    use crate::transformer::TransformerConfig;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_quantized_ffn_close_to_full_precision() {
+        let vocab: HashMap<String, usize> = (0..6).map(|i| (format!("t{}", i), i)).collect();
+        let config = TransformerConfig { d_model: 8, ff_dim: 16, ..tiny_config(2) };
+        let model = Transformer::new(config, vocab);
+        let sequences = vec![vec![0, 1, 2, 3], vec![4, 5, 1, 0], vec![2, 2, 3, 5]];
+
+        let ranges = CalibrationRanges::calibrate(&model, &sequences, DEFAULT_CALIBRATION_PERCENTILE);
+        let errors = ffn_quantization_error(&model, &sequences, Granularity::Column, &ranges);
+
+        let layer = &model.encoder_layers[0];
+        let ffn_input = layer.forward_stages(&model.embeddings.encode(&sequences[0])).remove(1).1;
+        let output_scale = layer.feed_forward.forward(&ffn_input).mapv(f64::abs).mean().unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0] < 0.05 * output_scale, "error {} vs output scale {}", errors[0], output_scale);
+    }
+}
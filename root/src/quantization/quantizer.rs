@@ -0,0 +1,137 @@
+use ndarray::{Array2, Axis};
+
+/// Largest magnitude of a symmetric int8 value.
+const INT8_MAX: f64 = 127.0;
+
+/// Which entries of a matrix share a scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Granularity {
+    /// One scale for the whole matrix.
+    Tensor,
+    /// One scale per row, i.e. per input channel of a `x.dot(w)` weight.
+    Row,
+    /// One scale per column, i.e. per output channel of a `x.dot(w)` weight.
+    Column,
+}
+
+/// A matrix stored as symmetric int8 values with one or more f64 scales.
+pub struct QuantizedMatrix {
+    values: Array2<i8>,
+    scales: Vec<f64>,
+    granularity: Granularity,
+}
+
+impl QuantizedMatrix {
+    /// Quantizes a matrix so that the largest magnitude in each group maps to ±127.
+    ///
+    /// # Arguments
+    /// * `matrix` - Values to quantize.
+    /// * `granularity` - Whether the scale is shared by the whole matrix, each row or each column.
+    ///
+    /// # Returns
+    /// A new instance of `QuantizedMatrix`.
+    pub fn quantize(matrix: &Array2<f64>, granularity: Granularity) -> Self {
+        let max_abs = |values: ndarray::ArrayView1<f64>| values.iter().fold(0.0f64, |m, &v| m.max(v.abs()));
+        let ranges: Vec<f64> = match granularity {
+            Granularity::Tensor => vec![matrix.iter().fold(0.0f64, |m, &v| m.max(v.abs()))],
+            Granularity::Row => matrix.axis_iter(Axis(0)).map(max_abs).collect(),
+            Granularity::Column => matrix.axis_iter(Axis(1)).map(max_abs).collect(),
+        };
+        let scales: Vec<f64> = ranges.iter().map(|&range| scale_for_range(range)).collect();
+
+        let mut quantized = QuantizedMatrix { values: Array2::zeros(matrix.raw_dim()), scales, granularity };
+        for ((row, col), &value) in matrix.indexed_iter() {
+            quantized.values[(row, col)] = quantize_value(value, quantized.scale_at(row, col));
+        }
+        quantized
+    }
+
+    /// Converts the int8 values back to f64.
+    pub fn dequantize(&self) -> Array2<f64> {
+        let mut matrix = Array2::zeros(self.values.raw_dim());
+        for ((row, col), &value) in self.values.indexed_iter() {
+            matrix[(row, col)] = value as f64 * self.scale_at(row, col);
+        }
+        matrix
+    }
+
+    fn scale_at(&self, row: usize, col: usize) -> f64 {
+        match self.granularity {
+            Granularity::Tensor => self.scales[0],
+            Granularity::Row => self.scales[row],
+            Granularity::Column => self.scales[col],
+        }
+    }
+}
+
+/// Simulates int8 quantization of activations clipped to `[-clip, clip]`.
+///
+/// # Arguments
+/// * `activations` - Values to quantize.
+/// * `clip` - Clipping range, e.g. from calibration. Values beyond it saturate.
+///
+/// # Returns
+/// * The activations after quantizing and dequantizing them.
+pub fn fake_quantize(activations: &Array2<f64>, clip: f64) -> Array2<f64> {
+    let scale = scale_for_range(clip);
+    activations.mapv(|value| quantize_value(value, scale) as f64 * scale)
+}
+
+fn scale_for_range(range: f64) -> f64 {
+    if range > 0.0 { range / INT8_MAX } else { 1.0 }
+}
+
+fn quantize_value(value: f64, scale: f64) -> i8 {
+    (value / scale).round().clamp(-INT8_MAX, INT8_MAX) as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn max_error(a: &Array2<f64>, b: &Array2<f64>) -> f64 {
+        a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn test_round_trip_within_half_a_step() {
+        let matrix = array![[0.5, -1.0, 0.25], [0.1, 0.9, -0.3]];
+        let quantized = QuantizedMatrix::quantize(&matrix, Granularity::Tensor);
+
+        assert_eq!(quantized.scales.len(), 1);
+        assert!(max_error(&matrix, &quantized.dequantize()) <= quantized.scales[0] / 2.0 + 1e-12);
+    }
+
+    #[test]
+    fn test_per_row_scales_preserve_small_rows() {
+        // The second row is 1000x smaller; a shared scale rounds it to zero.
+        let matrix = array![[10.0, -7.0, 3.0], [0.01, -0.007, 0.003]];
+
+        let per_tensor = QuantizedMatrix::quantize(&matrix, Granularity::Tensor).dequantize();
+        let per_row = QuantizedMatrix::quantize(&matrix, Granularity::Row);
+
+        assert_eq!(per_row.scales.len(), 2);
+        assert_eq!(per_tensor.row(1).iter().filter(|&&v| v != 0.0).count(), 0);
+        assert!(max_error(&matrix, &per_row.dequantize()) < max_error(&matrix, &per_tensor));
+    }
+
+    #[test]
+    fn test_per_column_scales() {
+        let matrix = array![[1.0, 0.01], [-2.0, 0.02]];
+        let quantized = QuantizedMatrix::quantize(&matrix, Granularity::Column);
+
+        assert!((quantized.scales[0] - 2.0 / INT8_MAX).abs() < 1e-12);
+        assert!((quantized.scales[1] - 0.02 / INT8_MAX).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_fake_quantize_saturates_at_clip() {
+        let activations = array![[0.5, 3.0, -4.0]];
+        let quantized = fake_quantize(&activations, 1.0);
+
+        assert!((quantized[(0, 1)] - 1.0).abs() < 1e-12);
+        assert!((quantized[(0, 2)] + 1.0).abs() < 1e-12);
+        assert!((quantized[(0, 0)] - 0.5).abs() < 1.0 / INT8_MAX);
+    }
+}
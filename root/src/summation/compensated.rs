@@ -0,0 +1,149 @@
+use crate::numerics::Float;
+
+/// How long reductions (loss averages, layer norm statistics, gradient sums) add up values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Summation {
+    /// Plain left-to-right addition.
+    #[default]
+    Naive,
+    /// Neumaier's variant of Kahan summation: the rounding error of every addition is
+    /// carried in a separate compensation term, so the error no longer grows with the
+    /// number of values. Costs a few extra floating-point operations per value.
+    Compensated,
+}
+
+impl Summation {
+    pub fn sum(self, values: impl IntoIterator<Item = f64>) -> f64 {
+        let mut sum = CompensatedSum::new(self);
+        for value in values {
+            sum.add(value);
+        }
+        sum.value()
+    }
+
+    /// Mean of `values`, or `NaN` when there are none.
+    pub fn mean(self, values: impl IntoIterator<Item = f64>) -> f64 {
+        let mut count = 0;
+        let sum = self.sum(values.into_iter().inspect(|_| count += 1));
+        sum / count as f64
+    }
+}
+
+/// Running sum of scalars, e.g. the loss over an epoch.
+#[derive(Clone, Copy, Debug)]
+pub struct CompensatedSum {
+    summation: Summation,
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    pub fn new(summation: Summation) -> Self {
+        CompensatedSum { summation, sum: 0.0, compensation: 0.0 }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        add_compensated(self.summation, &mut self.sum, &mut self.compensation, value);
+    }
+
+    pub fn value(&self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+/// Running element-wise sum of vectors, e.g. parameter gradients over a batch.
+#[derive(Clone, Debug)]
+pub struct CompensatedVec {
+    summation: Summation,
+    sums: Vec<f64>,
+    compensation: Vec<f64>,
+}
+
+impl CompensatedVec {
+    pub fn zeros(len: usize, summation: Summation) -> Self {
+        let compensation = match summation {
+            Summation::Naive => Vec::new(),
+            Summation::Compensated => vec![0.0; len],
+        };
+        CompensatedVec { summation, sums: vec![0.0; len], compensation }
+    }
+
+    /// Adds `values`, widened to f64, to the elements starting at `offset`.
+    pub fn add_at<A: Float>(&mut self, offset: usize, values: &[A]) {
+        let sums = &mut self.sums[offset..offset + values.len()];
+        match self.summation {
+            Summation::Naive => {
+                for (sum, value) in sums.iter_mut().zip(values) {
+                    *sum += value.as_f64();
+                }
+            }
+            Summation::Compensated => {
+                let compensation = &mut self.compensation[offset..offset + values.len()];
+                for ((sum, c), &value) in sums.iter_mut().zip(compensation.iter_mut()).zip(values) {
+                    add_compensated(Summation::Compensated, sum, c, value.as_f64());
+                }
+            }
+        }
+    }
+
+    pub fn into_values(self) -> Vec<f64> {
+        match self.summation {
+            Summation::Naive => self.sums,
+            Summation::Compensated => self.sums.iter().zip(&self.compensation).map(|(sum, c)| sum + c).collect(),
+        }
+    }
+}
+
+fn add_compensated(summation: Summation, sum: &mut f64, compensation: &mut f64, value: f64) {
+    match summation {
+        Summation::Naive => *sum += value,
+        Summation::Compensated => {
+            let total = *sum + value;
+            // Recover the low-order bits lost by the addition from the smaller operand.
+            *compensation += if sum.abs() >= value.abs() { (*sum - total) + value } else { (value - total) + *sum };
+            *sum = total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1.0 followed by many values that are each below half an ulp of 1.0, which plain
+    /// addition drops entirely.
+    fn small_increments() -> impl Iterator<Item = f64> {
+        std::iter::once(1.0).chain(std::iter::repeat_n(1e-17, 100_000))
+    }
+
+    #[test]
+    fn test_compensated_keeps_small_increments() {
+        assert_eq!(Summation::Naive.sum(small_increments()), 1.0);
+        let compensated = Summation::Compensated.sum(small_increments());
+        assert!((compensated - (1.0 + 1e-12)).abs() < 1e-16, "{}", compensated);
+    }
+
+    #[test]
+    fn test_compensated_handles_cancellation() {
+        // Neumaier's example where plain Kahan summation also returns 0.
+        assert_eq!(Summation::Compensated.sum([1.0, 1e100, 1.0, -1e100]), 2.0);
+        assert_eq!(Summation::Naive.sum([1.0, 1e100, 1.0, -1e100]), 0.0);
+        assert_eq!(Summation::Compensated.mean([1.0, 2.0, 3.0, 6.0]), 3.0);
+    }
+
+    #[test]
+    fn test_vector_sums_add_partials_with_compensation() {
+        let mut total = CompensatedVec::zeros(2, Summation::Compensated);
+        for _ in 0..4 {
+            let mut partial = CompensatedVec::zeros(2, Summation::Compensated);
+            partial.add_at(0, &[1.0, 3.0]);
+            for _ in 0..25_000 {
+                partial.add_at(1, &[1e-16]);
+            }
+            total.add_at(0, &partial.into_values());
+        }
+        let values = total.into_values();
+        assert_eq!(values[0], 4.0);
+        assert!((values[1] - (12.0 + 1e-11)).abs() < 1e-15, "{}", values[1]);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod compensated;
+
+pub use compensated::{CompensatedSum, CompensatedVec, Summation};
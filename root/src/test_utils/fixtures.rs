@@ -0,0 +1,43 @@
+use crate::configurration::config::{PAD_TOKEN, UNK_TOKEN};
+use crate::data_handler::synthetic::{SyntheticConfig, SyntheticDataset};
+use crate::transformer::TransformerConfig;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+/// The model configuration most tests train and run: one encoder layer, `d_model` 4, two
+/// heads and no optional components. Override single fields with struct update syntax,
+/// e.g. `TransformerConfig { num_layers: 2, ..tiny_config(3) }`.
+pub fn tiny_config(num_classes: usize) -> TransformerConfig {
+    TransformerConfig {
+        num_layers: 1,
+        d_model: 4,
+        num_heads: 2,
+        ff_dim: 8,
+        num_classes,
+        epsilon: 1e-6,
+        bert_embeddings: false,
+        relative_positions: None,
+        attention_projections: false,
+    }
+}
+
+/// Path of a scratch file `name` in the system temp directory, prefixed with the process id so
+/// that concurrent test runs do not share files. Tests still remove the files they write.
+pub fn temp_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("transformer_test_{}_{}", std::process::id(), name)).to_string_lossy().into_owned()
+}
+
+/// Writes a seeded two-class `SyntheticDataset` of 20 examples to the scratch file `name` and
+/// returns its path, for tests that read a dataset file. Remove it like other scratch files.
+pub fn synthetic_dataset_path(name: &str) -> String {
+    let config = SyntheticConfig { num_examples: 20, vocab_size: 12, ..SyntheticConfig::default() };
+    let path = temp_path(name);
+    SyntheticDataset::generate(&config, &mut StdRng::seed_from_u64(0)).unwrap().save_json(&path).unwrap();
+    path
+}
+
+/// Vocabulary of `[PAD]` (id 0), `[UNK]` (id 1) and then `words` in order.
+pub fn tiny_vocab(words: &[&str]) -> HashMap<String, usize> {
+    [PAD_TOKEN, UNK_TOKEN].iter().chain(words).enumerate().map(|(id, word)| (word.to_string(), id)).collect()
+}
@@ -0,0 +1,128 @@
+use ndarray::{Array1, Array2};
+use ndarray_rand::RandomExt;
+use ndarray_rand::rand_distr::Uniform;
+use rand::Rng;
+
+/// Draws a matrix with entries uniform in [-scale, scale], for checking invariants on random inputs.
+pub fn random_matrix<R: Rng>(rows: usize, cols: usize, scale: f64, rng: &mut R) -> Array2<f64> {
+    Array2::random_using((rows, cols), Uniform::new_inclusive(-scale, scale), rng)
+}
+
+/// Asserts that every row is a probability distribution: non-negative entries summing to 1.
+/// Holds for softmax outputs and attention weights.
+///
+/// # Arguments
+/// * `probabilities` - Matrix to check. Shape: [rows, classes].
+/// * `tolerance` - Allowed absolute deviation of each row sum from 1.
+pub fn assert_rows_sum_to_one(probabilities: &Array2<f64>, tolerance: f64) {
+    for (i, row) in probabilities.outer_iter().enumerate() {
+        assert!(row.iter().all(|&p| p >= 0.0), "Row {} has negative entries: {}", i, row);
+        let sum = row.sum();
+        assert!((sum - 1.0).abs() <= tolerance, "Row {} sums to {} instead of 1", i, sum);
+    }
+}
+
+/// Asserts that every row has zero mean and unit variance, as produced by layer
+/// normalization without a learned scale and shift.
+///
+/// # Arguments
+/// * `normalized` - Layer norm output. Shape: [seq_len, d_model].
+/// * `tolerance` - Allowed absolute deviation of the mean from 0 and the variance from 1.
+///   The variance of a normalized row is `var / (var + epsilon)`, so it must cover `epsilon`.
+pub fn assert_layer_norm_stats(normalized: &Array2<f64>, tolerance: f64) {
+    for (i, row) in normalized.outer_iter().enumerate() {
+        let mean = row.mean().unwrap();
+        let variance = row.var(0.0);
+        assert!(mean.abs() <= tolerance, "Row {} has mean {} instead of 0", i, mean);
+        assert!((variance - 1.0).abs() <= tolerance, "Row {} has variance {} instead of 1", i, variance);
+    }
+}
+
+/// Asserts that no query attends to a masked key.
+///
+/// # Arguments
+/// * `weights` - Attention weights. Shape: [num_queries, num_keys].
+/// * `key_mask` - 1 for real tokens and 0 for PAD positions. Shape: [num_keys].
+/// * `tolerance` - Largest weight allowed on a masked key.
+pub fn assert_masked_keys_ignored(weights: &Array2<f64>, key_mask: &Array1<f64>, tolerance: f64) {
+    assert_eq!(weights.ncols(), key_mask.len(), "Mask length must match the number of keys.");
+
+    for (i, row) in weights.outer_iter().enumerate() {
+        for (j, (&weight, &mask)) in row.iter().zip(key_mask.iter()).enumerate() {
+            assert!(
+                mask != 0.0 || weight.abs() <= tolerance,
+                "Query {} puts weight {} on masked key {}",
+                i,
+                weight,
+                j
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attention::attention_mechanism::masked_attention_weights;
+    use crate::cross_entropy::loss::Loss;
+    use crate::layer_norm::layer_norm_impl::apply_layer_norm;
+    use ndarray::array;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const NUM_TRIALS: usize = 20;
+
+    #[test]
+    fn test_softmax_rows_sum_to_one() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..NUM_TRIALS {
+            let logits = random_matrix(4, 5, 50.0, &mut rng);
+            assert_rows_sum_to_one(&Loss::softmax(&logits), 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_attention_weights_rows_sum_to_one() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..NUM_TRIALS {
+            let query = random_matrix(6, 8, 3.0, &mut rng);
+            let key = random_matrix(6, 8, 3.0, &mut rng);
+            assert_rows_sum_to_one(&masked_attention_weights(&query, &key, None), 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_layer_norm_stats() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..NUM_TRIALS {
+            let inputs = random_matrix(3, 16, 10.0, &mut rng);
+            assert_layer_norm_stats(&apply_layer_norm(&inputs, 1e-6), 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_masked_keys_ignored() {
+        let weights = array![[0.5, 0.5, 0.0], [1.0, 0.0, 0.0]];
+        assert_masked_keys_ignored(&weights, &array![1.0, 1.0, 0.0], 1e-12);
+    }
+
+    #[test]
+    fn test_masked_attention_weights_ignore_pad() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let key_mask = array![1.0, 1.0, 1.0, 0.0, 0.0, 0.0];
+        for _ in 0..NUM_TRIALS {
+            let query = random_matrix(6, 8, 3.0, &mut rng);
+            let key = random_matrix(6, 8, 3.0, &mut rng);
+            let weights = masked_attention_weights(&query, &key, Some(&key_mask));
+            assert_rows_sum_to_one(&weights, 1e-9);
+            assert_masked_keys_ignored(&weights, &key_mask, 0.0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "masked key")]
+    fn test_masked_keys_violation_panics() {
+        let weights = array![[0.4, 0.3, 0.3]];
+        assert_masked_keys_ignored(&weights, &array![1.0, 1.0, 0.0], 1e-12);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use crate::configurration::config::{PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, MASK_TOKEN};
+use crate::tokenization::huggingface::{HuggingFaceModel, HuggingFacePipeline};
+use crate::tokenization::ngrams::is_ngram;
+use crate::tokenization::phrases::PHRASE_SEPARATOR;
+use crate::tokenization::tokenizer::{Segmentation, Tokenizer};
+use crate::tokenization::unigram::WORD_BOUNDARY;
+use crate::tokenization::wordpiece::CONTINUATION_PREFIX;
+
+/// Byte value of a byte token such as `<0x41>`, or `None` for any other token.
+pub fn parse_byte_token(token: &str) -> Option<u8> {
+    let hex = token.strip_prefix("<0x")?.strip_suffix('>')?;
+    if hex.len() == 2 { u8::from_str_radix(hex, 16).ok() } else { None }
+}
+
+impl Tokenizer {
+    /// Turns token ids back into text, e.g. to inspect an encoding.
+    ///
+    /// Padding and ids outside the vocabulary are skipped, other special tokens are kept
+    /// as written (`[CLS]`, `[UNK]`). Sub-word pieces are joined to their word, and runs of
+    /// byte tokens are decoded as UTF-8 with invalid sequences replaced by U+FFFD. Words
+    /// come out normalized, as the tokenizer saw them. With n-grams, only single words are
+    /// decoded, since every n-gram repeats words that are also encoded on their own.
+    pub fn decode(&self, ids: &[usize]) -> String {
+        self.decode_with(ids, false)
+    }
+
+    /// Same as `decode`; with `skip_special_tokens`, `[UNK]`, `[CLS]`, `[SEP]`, `[MASK]` and
+    /// registered special tokens
+    /// are left out too, so only the text remains, e.g. to print a misclassified example.
+    pub fn decode_with(&self, ids: &[usize], skip_special_tokens: bool) -> String {
+        let tokens: HashMap<usize, &str> = self.vocab.iter().map(|(token, &id)| (id, token.as_str())).collect();
+        let continuation_prefix = match &self.segmentation {
+            Segmentation::WordPiece => Some(CONTINUATION_PREFIX),
+            Segmentation::HuggingFace(HuggingFacePipeline {
+                model: HuggingFaceModel::WordPiece { continuing_subword_prefix, .. },
+                ..
+            }) => Some(continuing_subword_prefix.as_str()),
+            _ => None,
+        };
+        let mut words: Vec<String> = Vec::new();
+        let mut bytes: Vec<u8> = Vec::new();
+
+        for token in ids.iter().filter_map(|id| tokens.get(id).copied()) {
+            if let Some(byte) = parse_byte_token(token) {
+                bytes.push(byte);
+                continue;
+            }
+            if !bytes.is_empty() {
+                words.push(String::from_utf8_lossy(&std::mem::take(&mut bytes)).into_owned());
+            }
+            if token == PAD_TOKEN || (self.ngrams.max > 1 && is_ngram(token)) {
+                continue;
+            }
+            let special = [UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, MASK_TOKEN].contains(&token) || self.special_tokens.contains(token);
+            if special && skip_special_tokens {
+                continue;
+            }
+            let continued = match (&self.segmentation, continuation_prefix) {
+                _ if special => None,
+                (_, Some(prefix)) => token.strip_prefix(prefix),
+                (Segmentation::Unigram(_), _) if !token.starts_with(WORD_BOUNDARY) && !words.is_empty() => Some(token),
+                _ => None,
+            };
+            match (continued, words.last_mut()) {
+                (Some(piece), Some(word)) => word.push_str(piece),
+                (Some(piece), None) => words.push(piece.to_string()),
+                (None, _) if self.merge_phrases && !special => words.push(token.replace(PHRASE_SEPARATOR, " ")),
+                (None, _) => words.push(token.trim_start_matches(WORD_BOUNDARY).to_string()),
+            }
+        }
+        if !bytes.is_empty() {
+            words.push(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        words.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_skips_padding_and_optionally_special_tokens() {
+        let vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, "a", "b"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        let tokenizer = Tokenizer::new(vocab, 6);
+        let encoded = tokenizer.encode_pair("a x", "b");
+
+        assert_eq!(tokenizer.decode(&encoded), "[CLS] a [UNK] [SEP] b [SEP]");
+        assert_eq!(tokenizer.decode_with(&encoded, true), "a b");
+        assert_eq!(tokenizer.decode(&tokenizer.encode_single("")), "[CLS] [SEP]");
+        assert_eq!(tokenizer.decode_with(&[0, 0, 99], true), "");
+    }
+}
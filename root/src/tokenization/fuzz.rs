@@ -0,0 +1,185 @@
+use std::any::Any;
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+
+use rand::Rng;
+
+use crate::tokenization::tokenizer::{Segmentation, Tokenizer};
+
+/// Random inputs per tokenizer of `cargo run -- fuzz-tokenizer`.
+pub const FUZZ_ITERATIONS: usize = 10_000;
+/// Longest random input in characters (or bytes), well above typical `MAX_SEQ_LENGTH` values.
+pub const FUZZ_MAX_CHARS: usize = 300;
+
+/// Characters random inputs are mostly drawn from: letters and digits, whitespace,
+/// punctuation, combining marks, characters whose lowercase or NFKC form is longer
+/// (`İ`, `ß`, `ﬁ`), non-Latin scripts, emoji, invisible characters and the markers used
+/// by the tokenizers themselves (`▁`, `##`, `<0x..>`, `[PAD]`).
+const FUZZ_ALPHABET: &[char] = &[
+    'a', 'b', 'Z', '0', '9', ' ', ' ', '\t', '\n', '.', '!', '?', '-', '\'', '#', '<', '>', '[', ']',
+    'é', 'e', '\u{301}', 'ß', 'İ', 'ﬁ', 'Ａ', 'न', 'ि', '中', '😀', '\u{200B}', '\u{0}', '\u{FFFD}', '▁',
+];
+
+/// Checks the tokenizer's guarantees on one input:
+///
+/// * nothing panics, whatever the text (empty, only punctuation or whitespace, longer
+///   than `max_seq_length`, invalid characters);
+/// * every token id is in the vocabulary;
+/// * truncation keeps at most `max_seq_length` tokens and reports the rest as dropped;
+/// * padded, single and pair encodings are exactly `max_seq_length` long;
+/// * for word segmentation with byte fallback, `decode` gives back every normalized word.
+///
+/// # Returns
+/// * `Err` describing the first violated guarantee; a panic is reported as one.
+pub fn check_input(tokenizer: &Tokenizer, text: &str) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(|| check_guarantees(tokenizer, text)))
+        .unwrap_or_else(|payload| Err(format!("panicked: {}", panic_message(payload.as_ref()))))
+}
+
+/// Entry point for coverage-guided fuzzers: arbitrary bytes are read as UTF-8, with
+/// invalid sequences replaced by U+FFFD.
+pub fn fuzz_bytes(tokenizer: &Tokenizer, data: &[u8]) -> Result<(), String> {
+    check_input(tokenizer, &String::from_utf8_lossy(data))
+}
+
+/// Runs `iterations` random inputs of up to `max_chars` characters through `check_input`;
+/// every other input is raw random bytes instead.
+///
+/// # Returns
+/// * The failing inputs and why they failed.
+pub fn fuzz_tokenizer<R: Rng>(tokenizer: &Tokenizer, iterations: usize, max_chars: usize, rng: &mut R) -> Vec<(String, String)> {
+    (0..iterations)
+        .filter_map(|iteration| {
+            let (text, result) = if iteration % 2 == 0 {
+                let text = random_text(max_chars, rng);
+                let result = check_input(tokenizer, &text);
+                (text, result)
+            } else {
+                let data: Vec<u8> = (0..rng.gen_range(0..=max_chars)).map(|_| rng.gen()).collect();
+                (String::from_utf8_lossy(&data).into_owned(), fuzz_bytes(tokenizer, &data))
+            };
+            result.err().map(|error| (text, error))
+        })
+        .collect()
+}
+
+/// Random text of up to `max_chars` characters, mostly from `FUZZ_ALPHABET`.
+pub fn random_text<R: Rng>(max_chars: usize, rng: &mut R) -> String {
+    (0..rng.gen_range(0..=max_chars))
+        .map(|_| if rng.gen_bool(0.9) { FUZZ_ALPHABET[rng.gen_range(0..FUZZ_ALPHABET.len())] } else { rng.gen::<char>() })
+        .collect()
+}
+
+fn check_guarantees(tokenizer: &Tokenizer, text: &str) -> Result<(), String> {
+    let max_len = tokenizer.max_seq_length;
+    let ids: HashSet<usize> = tokenizer.vocab.values().copied().collect();
+
+    let tokens = tokenizer.tokenize(text);
+    if let Some(id) = tokens.iter().find(|id| !ids.contains(id)) {
+        return Err(format!("token id {} is not in the vocabulary", id));
+    }
+
+    let (kept, dropped) = tokenizer.truncate(tokens.clone());
+    let separators = tokenizer.truncated_positions(tokens.len(), max_len).iter().filter(|position| position.is_none()).count();
+    if kept.len() > max_len || kept.len() + dropped != tokens.len() + separators {
+        return Err(format!("truncating {} tokens kept {} and dropped {}", tokens.len(), kept.len(), dropped));
+    }
+    for (name, encoded) in [
+        ("padded", tokenizer.pad_sequence(tokens.clone())),
+        ("single", tokenizer.encode_single(text)),
+        ("pair", tokenizer.encode_pair(text, text)),
+    ] {
+        if encoded.len() != max_len {
+            return Err(format!("{} encoding has {} tokens instead of {}", name, encoded.len(), max_len));
+        }
+    }
+
+    let decoded = tokenizer.decode(&tokens);
+    let round_trips = tokenizer.segmentation == Segmentation::Words && tokenizer.byte_fallback && tokenizer.ngrams.max <= 1;
+    if round_trips {
+        // Adjacent words spelled out in bytes decode as one word, so spaces are ignored.
+        let expected: String = tokenizer.normalizer.words(text).concat();
+        let actual: String = decoded.split_whitespace().collect();
+        if actual != expected {
+            return Err(format!("decoded {:?} instead of {:?}", actual, expected));
+        }
+    }
+    Ok(())
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configurration::config::{CLS_TOKEN, PAD_TOKEN, SEP_TOKEN, UNK_TOKEN};
+    use crate::tokenization::normalization::{TextNormalizer, UnicodeForm};
+    use crate::tokenization::tokenizer::{Truncation, VocabOptions};
+    use crate::tokenization::unigram::UnigramTrainer;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn corpus() -> Vec<String> {
+        ["the cat sat on the mat", "Crème brûlée, s'il vous plaît!", "नमस्ते दुनिया", "playing players played"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    fn tokenizers() -> Vec<Tokenizer> {
+        let specials = &[PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN];
+        let (mut vocab, _) = Tokenizer::build_vocab_with_stats(&corpus(), specials, &VocabOptions::default());
+        Tokenizer::add_byte_tokens(&mut vocab);
+        let words = Tokenizer::new(vocab, 16);
+        let normalizer = TextNormalizer { unicode_form: UnicodeForm::Nfkc, fold_accents: true, keep_punctuation: true, split_cjk: true };
+        let (normalized_vocab, _) = Tokenizer::build_vocab_with_stats(&corpus(), specials, &VocabOptions { normalizer, ..VocabOptions::default() });
+        let normalized = Tokenizer::new(normalized_vocab, 8)
+            .with_normalizer(normalizer)
+            .with_truncation(Truncation::HeadAndTail { head_tokens: 3, separator: true });
+
+        let mut wordpiece_vocab: HashMap<String, usize> = words.vocab.clone();
+        for piece in ["##s", "##ing", "##ed", "play"] {
+            let id = wordpiece_vocab.len();
+            wordpiece_vocab.insert(piece.to_string(), id);
+        }
+        let mut wordpiece = Tokenizer::new(wordpiece_vocab, 1).with_truncation(Truncation::Tail);
+        wordpiece.segmentation = Segmentation::WordPiece;
+
+        let unigram = Tokenizer::from_unigram(UnigramTrainer::new(40).train(&corpus()), specials, 0);
+        vec![words, normalized, wordpiece, unigram]
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let long_text = "the cat ".repeat(100);
+        for tokenizer in tokenizers() {
+            for text in ["", "   ", "!?.,", "\u{0}\u{301}", "[PAD] [UNK]", "<0x41> ##ing ▁the", long_text.as_str()] {
+                assert_eq!(check_input(&tokenizer, text), Ok(()), "{:?} with {:?}", text, tokenizer.segmentation);
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_inputs() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for tokenizer in tokenizers() {
+            let failures = fuzz_tokenizer(&tokenizer, 300, 40, &mut rng);
+            assert!(failures.is_empty(), "{:?}", &failures[..failures.len().min(3)]);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let tokenizer = &tokenizers()[0];
+        let tokens = tokenizer.encode_single("The cat, naïve");
+        assert_eq!(tokenizer.decode(&tokens), "[CLS] the cat naïve [SEP]");
+        assert_eq!(tokenizer.decode_with(&tokens, true), "the cat naïve");
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::configurration::config::UNK_TOKEN;
+use crate::tokenization::wordpiece::{is_punctuation, WordPieceTokenizer};
+
+/// Text normalization steps of a HuggingFace tokenizer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Normalizer {
+    /// `BertNormalizer`: removes control characters, puts spaces around CJK characters,
+    /// then optionally strips accents and lowercases.
+    Bert { clean_text: bool, handle_chinese_chars: bool, strip_accents: bool, lowercase: bool },
+    Lowercase,
+    /// Removes combining marks; usually preceded by `Nfd`.
+    StripAccents,
+    Nfd,
+    Nfc,
+    Sequence(Vec<Normalizer>),
+}
+
+/// How normalized text is split into words before the model sees it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PreTokenizer {
+    /// `BertPreTokenizer`: splits on whitespace and keeps every punctuation character as a word.
+    Bert,
+    /// `Whitespace`: runs of word characters or of other non-whitespace characters (`\w+|[^\w\s]+`).
+    Whitespace,
+    /// `WhitespaceSplit`: splits on whitespace only.
+    WhitespaceSplit,
+    Sequence(Vec<PreTokenizer>),
+}
+
+/// The vocabulary model of a HuggingFace tokenizer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HuggingFaceModel {
+    WordPiece { continuing_subword_prefix: String, max_input_chars_per_word: usize },
+    /// Every word is a token; words outside the vocabulary are unknown.
+    WordLevel,
+}
+
+/// Normalizer, pre-tokenizer and model read from a HuggingFace `tokenizer.json`.
+/// Post-processors (e.g. adding `[CLS]`/`[SEP]`) are not part of it, so ids match
+/// `encode(text, add_special_tokens=False)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HuggingFacePipeline {
+    pub normalizer: Option<Normalizer>,
+    pub pre_tokenizer: Option<PreTokenizer>,
+    pub model: HuggingFaceModel,
+}
+
+/// Contents of a HuggingFace `tokenizer.json` that this crate can represent.
+pub struct HuggingFaceTokenizer {
+    pub pipeline: HuggingFacePipeline,
+    /// Model vocabulary plus the added tokens.
+    pub vocab: HashMap<String, usize>,
+    /// `truncation.max_length`, when truncation is configured.
+    pub max_length: Option<usize>,
+}
+
+impl HuggingFaceTokenizer {
+    /// Parses a HuggingFace `tokenizer.json` with a `WordLevel` or `WordPiece` model.
+    ///
+    /// # Returns
+    /// * The pipeline and vocabulary, or an `InvalidData` error naming the first component
+    ///   that is not supported.
+    pub fn load(file_path: &str) -> Result<Self, Error> {
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(file_path)?)?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &Value) -> Result<Self, Error> {
+        let model_json = json.get("model").ok_or_else(|| invalid("tokenizer.json has no model"))?;
+        let model = match model_json["type"].as_str() {
+            Some("WordPiece") => HuggingFaceModel::WordPiece {
+                continuing_subword_prefix: model_json["continuing_subword_prefix"].as_str().unwrap_or("##").to_string(),
+                max_input_chars_per_word: model_json["max_input_chars_per_word"].as_u64().unwrap_or(100) as usize,
+            },
+            Some("WordLevel") => HuggingFaceModel::WordLevel,
+            other => return Err(invalid(&format!("unsupported model type {:?}; expected WordLevel or WordPiece", other))),
+        };
+        if let Some(unk_token) = model_json["unk_token"].as_str() {
+            if unk_token != UNK_TOKEN {
+                return Err(invalid(&format!("unk_token must be {}, found {}", UNK_TOKEN, unk_token)));
+            }
+        }
+
+        let mut vocab: HashMap<String, usize> = model_json["vocab"]
+            .as_object()
+            .ok_or_else(|| invalid("model.vocab must be an object of token ids"))?
+            .iter()
+            .map(|(token, id)| id.as_u64().map(|id| (token.clone(), id as usize)).ok_or_else(|| invalid("token ids must be integers")))
+            .collect::<Result<_, _>>()?;
+        for added in json["added_tokens"].as_array().into_iter().flatten() {
+            if let (Some(content), Some(id)) = (added["content"].as_str(), added["id"].as_u64()) {
+                vocab.insert(content.to_string(), id as usize);
+            }
+        }
+
+        Ok(HuggingFaceTokenizer {
+            pipeline: HuggingFacePipeline {
+                normalizer: parse_optional(&json["normalizer"], parse_normalizer)?,
+                pre_tokenizer: parse_optional(&json["pre_tokenizer"], parse_pre_tokenizer)?,
+                model,
+            },
+            vocab,
+            max_length: json["truncation"]["max_length"].as_u64().map(|length| length as usize),
+        })
+    }
+}
+
+impl HuggingFacePipeline {
+    /// Normalizes and pre-tokenizes text into words.
+    pub fn words(&self, text: &str) -> Vec<String> {
+        let normalized = match &self.normalizer {
+            Some(normalizer) => normalizer.apply(text),
+            None => text.to_string(),
+        };
+        match &self.pre_tokenizer {
+            Some(pre_tokenizer) => pre_tokenizer.split(vec![normalized]),
+            None => vec![normalized],
+        }
+    }
+
+    /// Splits text into `(word, pieces)` pairs. Words the model cannot represent have the
+    /// single piece `[UNK]`.
+    pub fn segment(&self, text: &str, vocab: &HashMap<String, usize>) -> Vec<(String, Vec<String>)> {
+        let words = self.words(text);
+        match &self.model {
+            HuggingFaceModel::WordPiece { continuing_subword_prefix, max_input_chars_per_word } => {
+                let wordpiece = WordPieceTokenizer::new(vocab).with_options(continuing_subword_prefix, *max_input_chars_per_word);
+                words
+                    .into_iter()
+                    .map(|word| {
+                        let pieces = wordpiece.word_pieces(&word);
+                        (word, pieces)
+                    })
+                    .collect()
+            }
+            HuggingFaceModel::WordLevel => words
+                .into_iter()
+                .map(|word| {
+                    let piece = if vocab.contains_key(&word) { word.clone() } else { UNK_TOKEN.to_string() };
+                    (word, vec![piece])
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Normalizer {
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            Normalizer::Bert { clean_text, handle_chinese_chars, strip_accents, lowercase } => {
+                let mut text = text.to_string();
+                if *clean_text {
+                    text = text
+                        .chars()
+                        .filter(|&c| c != '\0' && c != '\u{fffd}' && (c.is_whitespace() || !c.is_control()))
+                        .map(|c| if c.is_whitespace() { ' ' } else { c })
+                        .collect();
+                }
+                if *handle_chinese_chars {
+                    text = text
+                        .chars()
+                        .flat_map(|c| if is_chinese_char(c) { vec![' ', c, ' '] } else { vec![c] })
+                        .collect();
+                }
+                if *strip_accents {
+                    text = Normalizer::StripAccents.apply(&text.nfd().collect::<String>());
+                }
+                if *lowercase {
+                    text = text.to_lowercase();
+                }
+                text
+            }
+            Normalizer::Lowercase => text.to_lowercase(),
+            Normalizer::StripAccents => text.chars().filter(|&c| !is_combining_mark(c)).collect(),
+            Normalizer::Nfd => text.nfd().collect(),
+            Normalizer::Nfc => text.nfc().collect(),
+            Normalizer::Sequence(normalizers) => {
+                normalizers.iter().fold(text.to_string(), |text, normalizer| normalizer.apply(&text))
+            }
+        }
+    }
+}
+
+impl PreTokenizer {
+    /// Splits every word further.
+    pub fn split(&self, words: Vec<String>) -> Vec<String> {
+        match self {
+            PreTokenizer::Bert => words.iter().flat_map(|word| split_runs(word, |c| if is_punctuation(c) { None } else { Some(0) })).collect(),
+            PreTokenizer::Whitespace => words
+                .iter()
+                .flat_map(|word| split_runs(word, |c| Some(if c.is_alphanumeric() || c == '_' { 0 } else { 1 })))
+                .collect(),
+            PreTokenizer::WhitespaceSplit => {
+                words.iter().flat_map(|word| word.split_whitespace().map(str::to_string)).collect()
+            }
+            PreTokenizer::Sequence(pre_tokenizers) => {
+                pre_tokenizers.iter().fold(words, |words, pre_tokenizer| pre_tokenizer.split(words))
+            }
+        }
+    }
+}
+
+/// Splits text into runs of non-whitespace characters of the same class. A class of
+/// `None` makes every such character a word of its own.
+fn split_runs(text: &str, class: impl Fn(char) -> Option<u8>) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut current_class = None;
+
+    for c in text.chars() {
+        let c_class = class(c);
+        if (c.is_whitespace() || c_class.is_none() || c_class != current_class) && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        if c.is_whitespace() {
+            current_class = None;
+        } else if c_class.is_none() {
+            words.push(c.to_string());
+            current_class = None;
+        } else {
+            current.push(c);
+            current_class = c_class;
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// CJK Unified Ideographs and their extensions, as in BERT's `_is_chinese_char`.
+fn is_chinese_char(c: char) -> bool {
+    matches!(c as u32,
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0x20000..=0x2A6DF | 0x2A700..=0x2B73F |
+        0x2B740..=0x2B81F | 0x2B820..=0x2CEAF | 0xF900..=0xFAFF | 0x2F800..=0x2FA1F)
+}
+
+fn parse_optional<T>(json: &Value, parse: fn(&Value) -> Result<T, Error>) -> Result<Option<T>, Error> {
+    if json.is_null() { Ok(None) } else { parse(json).map(Some) }
+}
+
+fn parse_normalizer(json: &Value) -> Result<Normalizer, Error> {
+    let flag = |name: &str, default: bool| json[name].as_bool().unwrap_or(default);
+    match json["type"].as_str() {
+        Some("BertNormalizer") => {
+            let lowercase = flag("lowercase", true);
+            Ok(Normalizer::Bert {
+                clean_text: flag("clean_text", true),
+                handle_chinese_chars: flag("handle_chinese_chars", true),
+                // A missing or null `strip_accents` follows `lowercase`, as in BERT.
+                strip_accents: flag("strip_accents", lowercase),
+                lowercase,
+            })
+        }
+        Some("Lowercase") => Ok(Normalizer::Lowercase),
+        Some("StripAccents") => Ok(Normalizer::StripAccents),
+        Some("NFD") => Ok(Normalizer::Nfd),
+        Some("NFC") => Ok(Normalizer::Nfc),
+        Some("Sequence") => json["normalizers"]
+            .as_array()
+            .ok_or_else(|| invalid("Sequence normalizer without normalizers"))?
+            .iter()
+            .map(parse_normalizer)
+            .collect::<Result<_, _>>()
+            .map(Normalizer::Sequence),
+        other => Err(invalid(&format!("unsupported normalizer {:?}", other))),
+    }
+}
+
+fn parse_pre_tokenizer(json: &Value) -> Result<PreTokenizer, Error> {
+    match json["type"].as_str() {
+        Some("BertPreTokenizer") => Ok(PreTokenizer::Bert),
+        Some("Whitespace") => Ok(PreTokenizer::Whitespace),
+        Some("WhitespaceSplit") => Ok(PreTokenizer::WhitespaceSplit),
+        Some("Sequence") => json["pretokenizers"]
+            .as_array()
+            .ok_or_else(|| invalid("Sequence pre-tokenizer without pretokenizers"))?
+            .iter()
+            .map(parse_pre_tokenizer)
+            .collect::<Result<_, _>>()
+            .map(PreTokenizer::Sequence),
+        other => Err(invalid(&format!("unsupported pre-tokenizer {:?}", other))),
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bert_uncased() -> Value {
+        json!({
+            "version": "1.0",
+            "truncation": { "max_length": 16, "strategy": "LongestFirst" },
+            "added_tokens": [
+                { "id": 0, "content": "[PAD]", "special": true },
+                { "id": 1, "content": "[UNK]", "special": true }
+            ],
+            "normalizer": { "type": "BertNormalizer", "clean_text": true, "handle_chinese_chars": true,
+                            "strip_accents": null, "lowercase": true },
+            "pre_tokenizer": { "type": "BertPreTokenizer" },
+            "post_processor": { "type": "BertProcessing" },
+            "model": {
+                "type": "WordPiece", "unk_token": "[UNK]", "continuing_subword_prefix": "##",
+                "max_input_chars_per_word": 100,
+                "vocab": { "[PAD]": 0, "[UNK]": 1, "cafe": 2, "play": 3, "##ing": 4, "!": 5, "中": 6 }
+            }
+        })
+    }
+
+    #[test]
+    fn test_bert_pipeline_matches_huggingface_splits() {
+        let tokenizer = HuggingFaceTokenizer::from_json(&bert_uncased()).unwrap();
+        assert_eq!(tokenizer.max_length, Some(16));
+
+        // Accents are stripped, CJK characters become words and punctuation is split off.
+        assert_eq!(tokenizer.pipeline.words("Café\tPLAYING!中文"), vec!["cafe", "playing", "!", "中", "文"]);
+        let pieces: Vec<Vec<String>> = tokenizer
+            .pipeline
+            .segment("Café playing 文", &tokenizer.vocab)
+            .into_iter()
+            .map(|(_, pieces)| pieces)
+            .collect();
+        assert_eq!(pieces, vec![vec!["cafe"], vec!["play", "##ing"], vec!["[UNK]"]]);
+    }
+
+    #[test]
+    fn test_word_level_with_whitespace_pre_tokenizer() {
+        let json = json!({
+            "normalizer": { "type": "Sequence", "normalizers": [{ "type": "NFD" }, { "type": "Lowercase" }] },
+            "pre_tokenizer": { "type": "Whitespace" },
+            "model": { "type": "WordLevel", "unk_token": "[UNK]", "vocab": { "[PAD]": 0, "[UNK]": 1, "don": 2, "'": 3, "t": 4 } }
+        });
+        let tokenizer = HuggingFaceTokenizer::from_json(&json).unwrap();
+
+        assert_eq!(tokenizer.pipeline.words("Don't  stop_it"), vec!["don", "'", "t", "stop_it"]);
+        let pieces: Vec<String> =
+            tokenizer.pipeline.segment("Don't go", &tokenizer.vocab).into_iter().flat_map(|(_, p)| p).collect();
+        assert_eq!(pieces, vec!["don", "'", "t", "[UNK]"]);
+    }
+
+    #[test]
+    fn test_unsupported_components_are_rejected() {
+        let mut json = bert_uncased();
+        json["model"]["type"] = json!("BPE");
+        assert!(HuggingFaceTokenizer::from_json(&json).is_err());
+
+        let mut json = bert_uncased();
+        json["pre_tokenizer"] = json!({ "type": "ByteLevel" });
+        let error = HuggingFaceTokenizer::from_json(&json).err().unwrap();
+        assert!(error.to_string().contains("ByteLevel"));
+    }
+}
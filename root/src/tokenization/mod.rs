@@ -1 +1,15 @@
-pub mod tokenizer; 
\ No newline at end of file
+pub mod tokenizer; 
+pub mod wordpiece;
+pub mod unigram;
+pub mod huggingface;
+pub mod normalization;
+pub mod vocab_builder;
+pub mod ngrams;
+pub mod vocab_stats;
+pub mod fuzz;
+pub mod decoding;
+pub mod token_rules;
+pub mod phrases;
+pub mod special_tokens;
+pub mod offsets;
+pub mod task_prefix;
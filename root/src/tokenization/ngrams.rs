@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Separator between the words of an n-gram token, e.g. `new york`. Words never contain
+/// whitespace, so n-gram tokens cannot collide with word tokens.
+pub const NGRAM_SEPARATOR: &str = " ";
+
+/// Lengths of the word n-grams used as vocabulary tokens, from `min` to `max` words.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NGramRange {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl Default for NGramRange {
+    fn default() -> Self {
+        NGramRange::UNIGRAMS
+    }
+}
+
+impl NGramRange {
+    /// Single words only, the behaviour without n-grams.
+    pub const UNIGRAMS: NGramRange = NGramRange { min: 1, max: 1 };
+
+    /// All n-grams of `words` with a length in the range, ordered by start position and then
+    /// by length, so each phrase stays next to the words it covers:
+    /// `[a, b, c]` with 1..=2 gives `a`, `a b`, `b`, `b c`, `c`.
+    pub fn expand(&self, words: &[String]) -> Vec<String> {
+        self.windows(words.len()).into_iter().map(|range| words[range].join(NGRAM_SEPARATOR)).collect()
+    }
+
+    /// Index ranges of the n-grams of `word_count` words, in the order of `expand`.
+    pub fn windows(&self, word_count: usize) -> Vec<Range<usize>> {
+        let min = self.min.max(1);
+        let mut windows = Vec::new();
+        for start in 0..word_count {
+            for n in min..=self.max {
+                if start + n > word_count {
+                    break;
+                }
+                windows.push(start..start + n);
+            }
+        }
+        windows
+    }
+}
+
+/// Whether a vocabulary token is an n-gram of two or more words.
+pub fn is_ngram(token: &str) -> bool {
+    token.contains(NGRAM_SEPARATOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_expand_orders_by_position() {
+        let range = NGramRange { min: 1, max: 2 };
+        assert_eq!(range.expand(&words("a b c")), vec!["a", "a b", "b", "b c", "c"]);
+
+        let bigrams_and_trigrams = NGramRange { min: 2, max: 3 };
+        assert_eq!(bigrams_and_trigrams.expand(&words("a b c")), vec!["a b", "a b c", "b c"]);
+        assert!(bigrams_and_trigrams.expand(&words("a")).is_empty());
+
+        assert_eq!(NGramRange::UNIGRAMS.expand(&words("a b")), vec!["a", "b"]);
+        assert!(is_ngram("a b") && !is_ngram("a"));
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Serialize, Deserialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::tokenization::wordpiece::is_punctuation;
+
+/// Unicode normalization form applied before splitting text into words.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum UnicodeForm {
+    /// Text is used as is; combining marks are dropped like other non-alphanumeric characters.
+    #[default]
+    None,
+    /// Canonical composition: `e` + combining acute and `é` become the same character.
+    Nfc,
+    /// Compatibility composition: additionally folds ligatures, full-width forms,
+    /// superscripts etc. (`ﬁ` → `fi`, `Ａ` → `A`).
+    Nfkc,
+}
+
+/// How the word-level and unigram tokenizers turn text into words.
+///
+/// The default reproduces `Tokenizer::preprocess_text`: lowercase, drop everything that is
+/// neither alphanumeric nor whitespace, split on whitespace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TextNormalizer {
+    pub unicode_form: UnicodeForm,
+    /// Removes accents and other combining marks after decomposition (`café` → `cafe`).
+    pub fold_accents: bool,
+    /// Keeps every punctuation character as a word of its own instead of dropping it.
+    pub keep_punctuation: bool,
+    /// Makes every CJK character (see `is_cjk_char`) a word of its own, since Chinese and
+    /// Japanese text has no spaces between words. Latin words next to them are unaffected.
+    #[serde(default)]
+    pub split_cjk: bool,
+}
+
+/// CJK Unified Ideographs and their extensions (as in BERT's `_is_chinese_char`),
+/// Hiragana and Katakana. Hangul is not included, since Korean separates words with spaces.
+pub fn is_cjk_char(c: char) -> bool {
+    matches!(c as u32,
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0x20000..=0x2A6DF | 0x2A700..=0x2B73F |
+        0x2B740..=0x2B81F | 0x2B820..=0x2CEAF | 0xF900..=0xFAFF | 0x2F800..=0x2FA1F |
+        0x3040..=0x309F | 0x30A0..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F)
+}
+
+impl TextNormalizer {
+    /// Applies the Unicode form, lowercasing and accent folding, without splitting.
+    pub fn normalize(&self, text: &str) -> String {
+        let text: String = match self.unicode_form {
+            UnicodeForm::None => text.to_string(),
+            UnicodeForm::Nfc => text.nfc().collect(),
+            UnicodeForm::Nfkc => text.nfkc().collect(),
+        };
+        let text = text.to_lowercase();
+        if self.fold_accents {
+            text.nfd().filter(|&c| !is_combining_mark(c)).nfc().collect()
+        } else {
+            text
+        }
+    }
+
+    /// Puts spaces around CJK characters when `split_cjk` is set, so whitespace-based
+    /// splitting (e.g. WordPiece's) treats each of them as a word.
+    pub fn isolate_cjk(&self, text: &str) -> String {
+        if !self.split_cjk {
+            return text.to_string();
+        }
+        let mut isolated = String::with_capacity(text.len());
+        for c in text.chars() {
+            if is_cjk_char(c) {
+                isolated.push(' ');
+                isolated.push(c);
+                isolated.push(' ');
+            } else {
+                isolated.push(c);
+            }
+        }
+        isolated
+    }
+
+    /// Normalizes text and splits it into words.
+    ///
+    /// # Returns
+    /// * The words. With a Unicode form set, combining marks that survive normalization stay
+    ///   part of their word, so scripts that need them (e.g. Devanagari vowel signs) are kept intact.
+    pub fn words(&self, text: &str) -> Vec<String> {
+        let keep_marks = self.unicode_form != UnicodeForm::None;
+        let mut words = Vec::new();
+        let mut current = String::new();
+
+        for c in self.normalize(text).chars() {
+            if self.split_cjk && is_cjk_char(c) {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+                words.push(c.to_string());
+            } else if c.is_alphanumeric() || (keep_marks && is_combining_mark(c)) {
+                current.push(c);
+            } else if c.is_whitespace() || (self.keep_punctuation && is_punctuation(c)) {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+                if !c.is_whitespace() {
+                    words.push(c.to_string());
+                }
+            }
+        }
+        if !current.is_empty() {
+            words.push(current);
+        }
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_keeps_previous_preprocessing() {
+        let text = "Hello, World! Don't stop: cafe\u{301} 42";
+        let previous: Vec<String> = text
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+            .collect::<String>()
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(TextNormalizer::default().words(text), previous);
+        assert_eq!(TextNormalizer::default().words(text), vec!["hello", "world", "dont", "stop", "cafe", "42"]);
+    }
+
+    #[test]
+    fn test_unicode_forms_and_accent_folding() {
+        let nfc = TextNormalizer { unicode_form: UnicodeForm::Nfc, ..Default::default() };
+        // Precomposed and decomposed spellings become the same word.
+        assert_eq!(nfc.words("Café cafe\u{301}"), vec!["café", "café"]);
+        // Vowel signs are kept instead of splitting the word apart.
+        assert_eq!(nfc.words("नमस्ते"), vec!["नमस्ते"]);
+
+        let nfkc = TextNormalizer { unicode_form: UnicodeForm::Nfkc, ..Default::default() };
+        assert_eq!(nfkc.words("ﬁne ＡＢＣ"), vec!["fine", "abc"]);
+
+        let folded = TextNormalizer { unicode_form: UnicodeForm::Nfc, fold_accents: true, ..Default::default() };
+        assert_eq!(folded.words("Crème Brûlée"), vec!["creme", "brulee"]);
+    }
+
+    #[test]
+    fn test_keep_punctuation() {
+        let normalizer = TextNormalizer { keep_punctuation: true, ..Default::default() };
+        assert_eq!(normalizer.words("Wait... what?!"), vec!["wait", ".", ".", ".", "what", "?", "!"]);
+    }
+
+    #[test]
+    fn test_split_cjk() {
+        let text = "我喜欢Rust语言 and 東京タワー!";
+        // Without the CJK mode each run of CJK characters is one giant word.
+        assert_eq!(TextNormalizer::default().words(text), vec!["我喜欢rust语言", "and", "東京タワー"]);
+
+        let cjk = TextNormalizer { split_cjk: true, ..Default::default() };
+        assert_eq!(cjk.words(text), vec!["我", "喜", "欢", "rust", "语", "言", "and", "東", "京", "タ", "ワ", "ー"]);
+        // Korean keeps its space-separated words.
+        assert_eq!(cjk.words("안녕하세요 세계"), vec!["안녕하세요", "세계"]);
+        assert_eq!(cjk.isolate_cjk("a中b"), "a 中 b");
+        assert_eq!(TextNormalizer::default().isolate_cjk("a中b"), "a中b");
+    }
+}
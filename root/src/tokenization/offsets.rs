@@ -0,0 +1,94 @@
+use unicode_normalization::char::is_combining_mark;
+
+/// Byte range `(start, end)` of a token in the original text, as in HuggingFace's
+/// `offset_mapping`.
+pub type Offset = (usize, usize);
+
+/// Maps normalized text back to the original: every character and its combining marks are
+/// normalized on their own, so each normalized character knows the bytes it came from.
+/// Tokens are then found in order by their characters.
+pub struct TextAlignment {
+    /// Normalized characters with the byte range of the original character they came from.
+    chars: Vec<(char, Offset)>,
+    /// Index into `chars` after the last match.
+    cursor: usize,
+    /// End of the last match, where tokens without a match are placed.
+    last_end: usize,
+}
+
+impl TextAlignment {
+    /// # Arguments
+    /// * `text` - The original text.
+    /// * `normalize` - The normalization the tokenizer applies before segmentation.
+    pub fn new<F>(text: &str, normalize: F) -> Self
+    where
+        F: Fn(&str) -> String,
+    {
+        let mut chars = Vec::with_capacity(text.len());
+        let mut indices = text.char_indices().peekable();
+        while let Some((start, _)) = indices.next() {
+            while indices.next_if(|&(_, c)| is_combining_mark(c)).is_some() {}
+            let end = indices.peek().map_or(text.len(), |&(end, _)| end);
+            chars.extend(normalize(&text[start..end]).chars().map(|c| (c, (start, end))));
+        }
+        TextAlignment { chars, cursor: 0, last_end: 0 }
+    }
+
+    /// Finds the next occurrence of `piece`, a word or sub-word of the normalized text.
+    ///
+    /// Characters normalization dropped inside a word (`don't` → `dont`) are skipped, but
+    /// the match does not cross whitespace unless the piece contains some.
+    ///
+    /// # Returns
+    /// * The original byte range of the piece. Characters that cannot be found (e.g. after
+    ///   context-dependent normalization) are left out, and a piece without any match gets an
+    ///   empty range at the end of the previous one.
+    pub fn find(&mut self, piece: &str) -> Offset {
+        let crosses_whitespace = piece.chars().any(char::is_whitespace);
+        let mut span: Option<Offset> = None;
+        for c in piece.chars() {
+            let found = self.chars[self.cursor..]
+                .iter()
+                .take_while(|&&(other, _)| span.is_none() || crosses_whitespace || !other.is_whitespace())
+                .position(|&(other, _)| other == c);
+            let Some(position) = found else { break };
+            let (_, (start, end)) = self.chars[self.cursor + position];
+            span = Some((span.map_or(start, |(start, _)| start), end));
+            self.cursor += position + 1;
+        }
+        match span {
+            Some((start, end)) => {
+                self.last_end = end;
+                (start, end)
+            }
+            None => (self.last_end, self.last_end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_skips_dropped_characters() {
+        let text = "Don't  CAFÉ-bar";
+        let mut alignment = TextAlignment::new(text, |part| part.to_lowercase());
+        let spans: Vec<Offset> = ["dont", "café", "bar", "missing"].iter().map(|word| alignment.find(word)).collect();
+        let found: Vec<&str> = spans.iter().map(|&(start, end)| &text[start..end]).collect();
+        assert_eq!(found, vec!["Don't", "CAFÉ", "bar", ""]);
+        assert_eq!(spans[3], (text.len(), text.len()));
+    }
+
+    #[test]
+    fn test_combining_marks_stay_with_their_character() {
+        // `e` + combining acute normalizes to a single `é` spanning both characters.
+        let text = "Cafe\u{301} ok";
+        let mut alignment = TextAlignment::new(text, |part| {
+            use unicode_normalization::UnicodeNormalization;
+            part.nfc().collect::<String>().to_lowercase()
+        });
+        assert_eq!(alignment.find("café"), (0, 6));
+        assert_eq!(alignment.find("ok"), (7, 9));
+    }
+}
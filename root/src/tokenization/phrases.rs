@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+
+/// Joins the two words of a merged phrase, e.g. `new_york`. Unlike `NGRAM_SEPARATOR`, a
+/// phrase is a word of its own rather than an extra n-gram token.
+pub const PHRASE_SEPARATOR: &str = "_";
+
+/// How candidate word pairs are scored by `PhraseDetector`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PhraseScoring {
+    /// Every pair seen at least `min_count` times is a phrase.
+    Count,
+    /// Pairs seen at least `min_count` times whose normalized pointwise mutual information
+    /// reaches the threshold (in `-1.0..=1.0`), as in gensim's `npmi` scoring. Frequent
+    /// pairs of common words such as `of the` score low.
+    Npmi(f64),
+}
+
+/// Finds frequent word pairs (collocations such as `new york`) to merge into single
+/// vocabulary entries, like gensim's `Phrases`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhraseDetector {
+    /// Pairs seen fewer times than this are never merged.
+    pub min_count: usize,
+    pub scoring: PhraseScoring,
+}
+
+impl PhraseDetector {
+    /// Scores every adjacent word pair of the corpus.
+    ///
+    /// # Arguments
+    /// * `texts` - The corpus, already split into words.
+    ///
+    /// # Returns
+    /// * The accepted phrases, joined by `PHRASE_SEPARATOR`.
+    pub fn detect(&self, texts: &[Vec<String>]) -> HashSet<String> {
+        let mut word_counts: HashMap<&str, usize> = HashMap::new();
+        let mut pair_counts: HashMap<(&str, &str), usize> = HashMap::new();
+        for words in texts {
+            for word in words {
+                *word_counts.entry(word.as_str()).or_insert(0) += 1;
+            }
+            for pair in words.windows(2) {
+                *pair_counts.entry((pair[0].as_str(), pair[1].as_str())).or_insert(0) += 1;
+            }
+        }
+        let total_words = word_counts.values().sum::<usize>() as f64;
+
+        pair_counts
+            .into_iter()
+            .filter(|&((first, second), count)| {
+                count >= self.min_count.max(1)
+                    && match self.scoring {
+                        PhraseScoring::Count => true,
+                        PhraseScoring::Npmi(threshold) => {
+                            let p_pair = count as f64 / total_words;
+                            let p_first = word_counts[first] as f64 / total_words;
+                            let p_second = word_counts[second] as f64 / total_words;
+                            // A pair covering the whole corpus has -ln(p) = 0; it is perfectly associated.
+                            let npmi = if p_pair >= 1.0 { 1.0 } else { (p_pair / (p_first * p_second)).ln() / -p_pair.ln() };
+                            npmi >= threshold
+                        }
+                    }
+            })
+            .map(|((first, second), _)| phrase(first, second))
+            .collect()
+    }
+}
+
+/// The phrase token of two words.
+pub fn phrase(first: &str, second: &str) -> String {
+    format!("{}{}{}", first, PHRASE_SEPARATOR, second)
+}
+
+/// Merges adjacent words into phrases from left to right: `new york city` with the phrases
+/// `new_york` and `york_city` becomes `new_york city`.
+///
+/// # Arguments
+/// * `is_phrase` - Whether a joined pair is a phrase, e.g. whether it is in the vocabulary.
+pub fn merge_phrases<F>(words: Vec<String>, is_phrase: F) -> Vec<String>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut merged = Vec::with_capacity(words.len());
+    let mut words = words.into_iter().peekable();
+    while let Some(word) = words.next() {
+        match words.peek() {
+            Some(next) if is_phrase(&phrase(&word, next)) => {
+                let next = words.next().unwrap();
+                merged.push(phrase(&word, &next));
+            }
+            _ => merged.push(word),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus(texts: &[&str]) -> Vec<Vec<String>> {
+        texts.iter().map(|text| text.split_whitespace().map(String::from).collect()).collect()
+    }
+
+    #[test]
+    fn test_detect_by_count_and_npmi() {
+        let texts = corpus(&[
+            "flights to new york",
+            "new york of the year",
+            "hotels in new york",
+            "best of the best",
+            "the cat of the house",
+        ]);
+
+        let by_count = PhraseDetector { min_count: 3, scoring: PhraseScoring::Count }.detect(&texts);
+        assert_eq!(by_count, HashSet::from(["new_york".to_string(), "of_the".to_string()]));
+
+        // `of` and `the` are frequent on their own, so the pair is less surprising than `new york`.
+        let by_npmi = PhraseDetector { min_count: 3, scoring: PhraseScoring::Npmi(0.9) }.detect(&texts);
+        assert_eq!(by_npmi, HashSet::from(["new_york".to_string()]));
+    }
+
+    #[test]
+    fn test_merge_from_left_to_right() {
+        let phrases = HashSet::from(["new_york".to_string(), "york_city".to_string()]);
+        let words: Vec<String> = ["new", "york", "city", "new"].iter().map(|w| w.to_string()).collect();
+        assert_eq!(merge_phrases(words, |p| phrases.contains(p)), vec!["new_york", "city", "new"]);
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+
+use crate::configurration::config::PAD_TOKEN;
+
+/// A piece of text split by `SpecialTokens::split`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextSegment<'a> {
+    /// Ordinary text, segmented by the tokenizer.
+    Text(&'a str),
+    /// A registered special token: its id and the matched text.
+    Special(usize, &'a str),
+}
+
+/// Special tokens registered on top of the built-in ones, e.g. `[MASK]`, domain markers such
+/// as `<product>` or language tags such as `<lang:de>`.
+///
+/// Registered tokens keep their id for the lifetime of the vocabulary, are matched verbatim
+/// in the input before any normalization, so they are never lowercased or split, and are
+/// saved with the tokenizer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SpecialTokens {
+    tokens: BTreeMap<String, usize>,
+}
+
+impl SpecialTokens {
+    /// Registers `token`, adding it to `vocab` after the largest id unless it is already there.
+    ///
+    /// # Returns
+    /// * The token's id, unchanged when it was already in the vocabulary or registered.
+    /// * An error for an empty token, a token containing whitespace, or `[PAD]`, which must
+    ///   never appear inside a sequence.
+    pub fn register(&mut self, vocab: &mut HashMap<String, usize>, token: &str) -> Result<usize, Error> {
+        if token.is_empty() || token.chars().any(char::is_whitespace) || token == PAD_TOKEN {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{:?} cannot be registered as a special token", token)));
+        }
+        let next_id = vocab.values().max().map_or(0, |&id| id + 1);
+        let id = *vocab.entry(token.to_string()).or_insert(next_id);
+        self.tokens.insert(token.to_string(), id);
+        Ok(id)
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.tokens.contains_key(token)
+    }
+
+    /// Registered tokens and their ids, sorted by token.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.tokens.iter().map(|(token, &id)| (token.as_str(), id))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Splits text at every registered token. When several tokens match at the same
+    /// position, the longest wins (`<lang:de-ch>` before `<lang:de>`).
+    pub fn split<'a>(&self, text: &'a str) -> Vec<TextSegment<'a>> {
+        if self.tokens.is_empty() {
+            return vec![TextSegment::Text(text)];
+        }
+        let mut segments = Vec::new();
+        let mut text_start = 0;
+        let mut position = 0;
+        while position < text.len() {
+            let matched = self
+                .tokens
+                .iter()
+                .filter(|(token, _)| text[position..].starts_with(token.as_str()))
+                .max_by_key(|(token, _)| token.len());
+            match matched {
+                Some((token, &id)) => {
+                    let token = &text[position..position + token.len()];
+                    if text_start < position {
+                        segments.push(TextSegment::Text(&text[text_start..position]));
+                    }
+                    segments.push(TextSegment::Special(id, token));
+                    position += token.len();
+                    text_start = position;
+                }
+                None => position += text[position..].chars().next().map_or(1, char::len_utf8),
+            }
+        }
+        if text_start < text.len() {
+            segments.push(TextSegment::Text(&text[text_start..]));
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_keeps_ids_stable() {
+        let mut vocab = HashMap::from([("[PAD]".to_string(), 0), ("[UNK]".to_string(), 1), ("<lang:de>".to_string(), 2)]);
+        let mut special = SpecialTokens::default();
+
+        assert_eq!(special.register(&mut vocab, "<lang:de>").unwrap(), 2);
+        assert_eq!(special.register(&mut vocab, "[MASK]").unwrap(), 3);
+        assert_eq!(special.register(&mut vocab, "[MASK]").unwrap(), 3);
+        assert_eq!(vocab.len(), 4);
+        for invalid in ["", "two words", "[PAD]"] {
+            assert!(special.register(&mut vocab, invalid).is_err());
+        }
+        assert_eq!(special.iter().collect::<Vec<_>>(), vec![("<lang:de>", 2), ("[MASK]", 3)]);
+    }
+
+    #[test]
+    fn test_split_prefers_longest_match() {
+        let mut vocab = HashMap::new();
+        let mut special = SpecialTokens::default();
+        let short = special.register(&mut vocab, "<lang:de>").unwrap();
+        let long = special.register(&mut vocab, "<lang:de-ch>").unwrap();
+
+        assert_eq!(
+            special.split("<lang:de>Grüezi<lang:de-ch> wohl"),
+            vec![
+                TextSegment::Special(short, "<lang:de>"),
+                TextSegment::Text("Grüezi"),
+                TextSegment::Special(long, "<lang:de-ch>"),
+                TextSegment::Text(" wohl"),
+            ]
+        );
+        assert_eq!(SpecialTokens::default().split("<lang:de>"), vec![TextSegment::Text("<lang:de>")]);
+    }
+}
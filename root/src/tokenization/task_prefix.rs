@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+
+/// Prefix tokens that tell a shared encoder which task an input belongs to, e.g. `[TASK1]`
+/// or `[SENTIMENT]`.
+///
+/// Each task maps to a registered special token. A tokenizer switched to a task with
+/// `Tokenizer::for_task` puts the task's token in front of every tokenized text, so every
+/// path that tokenizes (data loading, batching, inference) injects it without changes,
+/// and truncation never drops it. The task map is saved with the tokenizer; the active
+/// task is a runtime setting.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TaskPrefixes {
+    /// Prefix token of every task.
+    tokens: BTreeMap<String, String>,
+    #[serde(skip)]
+    active: Option<String>,
+}
+
+impl TaskPrefixes {
+    /// Maps `task` to `token`, which must already be a registered special token.
+    ///
+    /// # Returns
+    /// * An error if another task already uses the token.
+    pub fn add(&mut self, task: &str, token: &str) -> Result<(), Error> {
+        if let Some((other, _)) = self.tokens.iter().find(|(other, existing)| existing.as_str() == token && other.as_str() != task) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} is already the prefix of task {}", token, other)));
+        }
+        self.tokens.insert(task.to_string(), token.to_string());
+        Ok(())
+    }
+
+    /// Makes `task` the active task; `None` stops injecting prefixes.
+    ///
+    /// # Returns
+    /// * An error for a task without a prefix.
+    pub fn activate(&mut self, task: Option<&str>) -> Result<(), Error> {
+        if let Some(task) = task {
+            if !self.tokens.contains_key(task) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("No prefix token is registered for task {}", task)));
+            }
+        }
+        self.active = task.map(str::to_string);
+        Ok(())
+    }
+
+    /// Prefix token of the active task.
+    pub fn active_token(&self) -> Option<&str> {
+        self.active.as_ref().map(|task| self.tokens[task].as_str())
+    }
+
+    /// Tasks and their prefix tokens, sorted by task.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.tokens.iter().map(|(task, token)| (task.as_str(), token.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_prefixes() {
+        let mut prefixes = TaskPrefixes::default();
+        prefixes.add("sentiment", "[TASK1]").unwrap();
+        prefixes.add("topic", "[TASK2]").unwrap();
+        assert!(prefixes.add("intent", "[TASK1]").is_err());
+        assert_eq!(prefixes.active_token(), None);
+
+        prefixes.activate(Some("topic")).unwrap();
+        assert_eq!(prefixes.active_token(), Some("[TASK2]"));
+        assert!(prefixes.activate(Some("unknown")).is_err());
+        assert_eq!(prefixes.active_token(), Some("[TASK2]"));
+
+        // Only the map is saved.
+        let json = serde_json::to_string(&prefixes).unwrap();
+        assert_eq!(json, r#"{"sentiment":"[TASK1]","topic":"[TASK2]"}"#);
+        let loaded: TaskPrefixes = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.active_token(), None);
+        assert_eq!(loaded.iter().count(), 2);
+    }
+}
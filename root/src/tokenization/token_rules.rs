@@ -0,0 +1,146 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::tokenization::normalization::TextNormalizer;
+
+/// Hashtags such as `#MachineLearning`.
+pub const HASHTAG_PATTERN: &str = r"#\w+";
+/// Mentions such as `@support`.
+pub const MENTION_PATTERN: &str = r"@\w+";
+/// E-mail addresses such as `jane.doe+news@example.co.uk`.
+pub const EMAIL_PATTERN: &str = r"[\w.+-]+@[\w-]+(?:\.[\w-]+)+";
+
+/// A named pattern whose matches are kept as single tokens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenRule {
+    pub name: String,
+    pub pattern: String,
+}
+
+/// Registry of `TokenRule`s applied before text is split into words, so spans such as
+/// hashtags, mentions, e-mail addresses or product SKUs survive as one token instead of
+/// losing their punctuation or being split apart.
+///
+/// Patterns are matched against the raw text, leftmost match first; when several rules
+/// match at the same position, the one registered first wins. Matches are normalized
+/// (lowercased, accents folded, ...) like the words around them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "Vec<TokenRule>", into = "Vec<TokenRule>")]
+pub struct TokenRules {
+    rules: Vec<TokenRule>,
+    /// All patterns as one alternation, `None` while the registry is empty.
+    combined: Option<Regex>,
+}
+
+impl PartialEq for TokenRules {
+    fn eq(&self, other: &Self) -> bool {
+        self.rules == other.rules
+    }
+}
+
+impl TryFrom<Vec<TokenRule>> for TokenRules {
+    type Error = regex::Error;
+
+    fn try_from(rules: Vec<TokenRule>) -> Result<Self, Self::Error> {
+        let patterns: Vec<(&str, &str)> = rules.iter().map(|rule| (rule.name.as_str(), rule.pattern.as_str())).collect();
+        Self::from_patterns(&patterns)
+    }
+}
+
+impl From<TokenRules> for Vec<TokenRule> {
+    fn from(registry: TokenRules) -> Self {
+        registry.rules
+    }
+}
+
+impl TokenRules {
+    /// Hashtags, mentions and e-mail addresses.
+    pub fn social() -> Self {
+        Self::from_patterns(&[("email", EMAIL_PATTERN), ("hashtag", HASHTAG_PATTERN), ("mention", MENTION_PATTERN)])
+            .expect("Built-in token rules are valid.")
+    }
+
+    /// Registers `(name, pattern)` pairs in order, e.g. `TOKEN_RULES` from `config.rs`.
+    pub fn from_patterns(patterns: &[(&str, &str)]) -> Result<Self, regex::Error> {
+        let mut registry = TokenRules::default();
+        for (name, pattern) in patterns {
+            registry.register(name, pattern)?;
+        }
+        Ok(registry)
+    }
+
+    /// Adds a rule after the existing ones.
+    ///
+    /// # Arguments
+    /// * `name` - Label of the rule, e.g. `sku`.
+    /// * `pattern` - Regular expression (`regex` crate syntax) whose matches become tokens.
+    ///
+    /// # Returns
+    /// * An error if the pattern does not compile; the registry is then unchanged.
+    pub fn register(&mut self, name: &str, pattern: &str) -> Result<(), regex::Error> {
+        Regex::new(pattern)?;
+        let mut rules = self.rules.clone();
+        rules.push(TokenRule { name: name.to_string(), pattern: pattern.to_string() });
+        let alternation: Vec<String> = rules.iter().map(|rule| format!("(?:{})", rule.pattern)).collect();
+        self.combined = Some(Regex::new(&alternation.join("|"))?);
+        self.rules = rules;
+        Ok(())
+    }
+
+    pub fn rules(&self) -> &[TokenRule] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Splits text into words: rule matches are kept whole and the text between them is
+    /// split by `normalizer`.
+    pub fn words(&self, text: &str, normalizer: &TextNormalizer) -> Vec<String> {
+        let Some(combined) = &self.combined else {
+            return normalizer.words(text);
+        };
+        let mut words = Vec::new();
+        let mut last_end = 0;
+        // Empty matches would produce empty tokens, so they are left to the normalizer.
+        for found in combined.find_iter(text).filter(|found| !found.is_empty()) {
+            words.extend(normalizer.words(&text[last_end..found.start()]));
+            words.push(normalizer.normalize(found.as_str()));
+            last_end = found.end();
+        }
+        words.extend(normalizer.words(&text[last_end..]));
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_are_kept_whole() {
+        let mut rules = TokenRules::social();
+        rules.register("sku", r"\b[A-Z]{2,}-\d+\b").unwrap();
+        let normalizer = TextNormalizer::default();
+
+        let words = rules.words("Mail jane.doe@example.com about #RustLang, @Bob and AB-1234!", &normalizer);
+        assert_eq!(words, vec!["mail", "jane.doe@example.com", "about", "#rustlang", "@bob", "and", "ab-1234"]);
+        // Without rules the same text loses its punctuation.
+        assert_eq!(TokenRules::default().words("#RustLang @Bob", &normalizer), vec!["rustlang", "bob"]);
+    }
+
+    #[test]
+    fn test_invalid_pattern_and_serialization() {
+        let mut rules = TokenRules::default();
+        assert!(rules.register("broken", "(").is_err());
+        assert!(rules.is_empty());
+
+        rules.register("hashtag", HASHTAG_PATTERN).unwrap();
+        let json = serde_json::to_string(&rules).unwrap();
+        let loaded: TokenRules = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, rules);
+        assert_eq!(loaded.words("#a b", &TextNormalizer::default()), vec!["#a", "b"]);
+        assert!(serde_json::from_str::<TokenRules>(r#"[{ "name": "x", "pattern": "[" }]"#).is_err());
+    }
+}
@@ -1,11 +1,215 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+use ndarray::{Array2, ShapeError};
+use serde::{Serialize, Deserialize};
 
-use crate::configurration::config::{PAD_TOKEN, UNK_TOKEN, MAX_SEQ_LENGTH};
+use crate::configurration::config::{PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, MASK_TOKEN, TEXT_NORMALIZER, TRUNCATION, NGRAMS, PHRASES};
+use crate::tokenization::huggingface::{HuggingFaceModel, HuggingFacePipeline, HuggingFaceTokenizer};
+use crate::tokenization::normalization::TextNormalizer;
+use crate::tokenization::ngrams::{is_ngram, NGramRange, NGRAM_SEPARATOR};
+use crate::tokenization::offsets::{Offset, TextAlignment};
+use crate::tokenization::vocab_stats::VocabStats;
+use crate::tokenization::token_rules::TokenRules;
+use crate::tokenization::phrases::{merge_phrases, PhraseDetector, PHRASE_SEPARATOR};
+use crate::tokenization::special_tokens::{SpecialTokens, TextSegment};
+use crate::tokenization::task_prefix::TaskPrefixes;
+use crate::profiling::profiler;
+use crate::tokenization::unigram::{UnigramModel, WORD_BOUNDARY};
+use crate::tokenization::wordpiece::{WordPieceTokenizer, CONTINUATION_PREFIX};
+
+/// Vocabulary token of a raw byte, e.g. `<0x41>`, following the SentencePiece convention.
+pub fn byte_token(byte: u8) -> String {
+    format!("<0x{:02X}>", byte)
+}
+
+/// How text is split into vocabulary tokens.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Segmentation {
+    /// Whole words after `TextNormalizer::words`.
+    #[default]
+    Words,
+    /// WordPiece sub-words, for BERT-style `vocab.txt` vocabularies.
+    WordPiece,
+    /// Most likely pieces under a trained unigram language model.
+    Unigram(UnigramModel),
+    /// Normalizer, pre-tokenizer and model imported from a HuggingFace `tokenizer.json`.
+    HuggingFace(HuggingFacePipeline),
+}
+
+/// Which tokens `pad_sequence` keeps when a sequence is longer than `max_seq_length`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Truncation {
+    /// Keeps the first tokens and drops the end.
+    #[default]
+    Head,
+    /// Keeps the last tokens and drops the beginning, e.g. for reviews that end with a verdict.
+    Tail,
+    /// Keeps the first `head_tokens` tokens and fills the rest with the last tokens,
+    /// dropping the middle, e.g. for documents whose conclusion carries the label.
+    HeadAndTail {
+        head_tokens: usize,
+        /// Marks the dropped middle with `[SEP]` (if it is in the vocabulary), which takes
+        /// one of the tail's positions.
+        #[serde(default)]
+        separator: bool,
+    },
+}
+
+/// Options of `Tokenizer::build_vocab_with_stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VocabOptions {
+    /// Maximum vocabulary size, including the special tokens.
+    pub max_vocab_size: Option<usize>,
+    /// Tokens seen fewer times than this are left out, however much room is left.
+    pub min_freq: usize,
+    pub normalizer: TextNormalizer,
+    pub ngrams: NGramRange,
+    pub token_rules: TokenRules,
+    /// Detects frequent word pairs and counts them as single tokens (`new_york`) instead of
+    /// their words; `None` keeps every word separate.
+    pub phrases: Option<PhraseDetector>,
+}
+
+impl Default for VocabOptions {
+    fn default() -> Self {
+        VocabOptions {
+            max_vocab_size: None,
+            min_freq: 1,
+            normalizer: TextNormalizer::default(),
+            ngrams: NGramRange::UNIGRAMS,
+            token_rules: TokenRules::default(),
+            phrases: None,
+        }
+    }
+}
+
+/// Number of sequences and tokens removed by truncation.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TruncationReport {
+    pub sequences: usize,
+    pub truncated_sequences: usize,
+    pub dropped_tokens: usize,
+}
+
+impl TruncationReport {
+    pub fn merge(&mut self, other: &TruncationReport) {
+        self.sequences += other.sequences;
+        self.truncated_sequences += other.truncated_sequences;
+        self.dropped_tokens += other.dropped_tokens;
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {} sequences truncated, {} tokens dropped",
+            self.truncated_sequences, self.sequences, self.dropped_tokens
+        )
+    }
+}
+
+/// Padded token ids of a batch with their attention masks, one row per sequence.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EncodedBatch {
+    pub input_ids: Vec<Vec<usize>>,
+    /// 1 for real tokens, 0 for PAD positions.
+    pub attention_mask: Vec<Vec<usize>>,
+    /// Segment of every position: 0 for `[CLS] first [SEP]` and padding, 1 for
+    /// `second [SEP]` of a sentence pair, or for everything after the first `[SEP]` of a
+    /// single sequence (see `Tokenizer::segment_ids`).
+    pub token_type_ids: Vec<Vec<usize>>,
+}
+
+impl EncodedBatch {
+    /// Converts the batch into the token and attention-mask arrays passed to
+    /// `Transformer::forward`. Shape of both: [batch_size, seq_len].
+    pub fn to_arrays(&self) -> Result<(Array2<f64>, Array2<f64>), ShapeError> {
+        let shape = (self.input_ids.len(), self.input_ids.first().map_or(0, Vec::len));
+        let to_array = |rows: &[Vec<usize>]| Array2::from_shape_vec(shape, rows.iter().flatten().map(|&x| x as f64).collect());
+        Ok((to_array(&self.input_ids)?, to_array(&self.attention_mask)?))
+    }
+
+    /// Segment ids as an array for a segment embedding. Shape: [batch_size, seq_len].
+    pub fn token_type_array(&self) -> Result<Array2<f64>, ShapeError> {
+        let shape = (self.token_type_ids.len(), self.token_type_ids.first().map_or(0, Vec::len));
+        Array2::from_shape_vec(shape, self.token_type_ids.iter().flatten().map(|&x| x as f64).collect())
+    }
+
+    /// Rows `range` of the batch.
+    pub fn slice(&self, range: std::ops::Range<usize>) -> EncodedBatch {
+        EncodedBatch {
+            input_ids: self.input_ids[range.clone()].to_vec(),
+            attention_mask: self.attention_mask[range.clone()].to_vec(),
+            token_type_ids: self.token_type_ids[range].to_vec(),
+        }
+    }
+}
 
 /// Tokenizer structure for managing tokenization and padding
+#[derive(Clone)]
 pub struct Tokenizer {
     pub vocab: HashMap<String, usize>, // Vocabulary mapping tokens to indices
     pub max_seq_length: usize,         // Maximum sequence length for padding
+    pub segmentation: Segmentation,
+    /// Split unknown words into byte tokens instead of mapping them to `[UNK]`.
+    /// Enabled automatically when the vocabulary contains all byte tokens.
+    pub byte_fallback: bool,
+    /// Unicode normalization, accent folding and punctuation handling before segmentation.
+    /// Not used by `Segmentation::HuggingFace`, which brings its own normalizer.
+    pub normalizer: TextNormalizer,
+    pub truncation: Truncation,
+    /// Word n-grams looked up next to the words; only used by `Segmentation::Words`.
+    pub ngrams: NGramRange,
+    /// Patterns kept as single tokens before word splitting; only used by `Segmentation::Words`
+    /// and `Segmentation::Unigram`.
+    pub token_rules: TokenRules,
+    /// Merges adjacent words whose phrase (`new_york`) is in the vocabulary into that
+    /// token; only used by `Segmentation::Words`.
+    pub merge_phrases: bool,
+    /// Tokens added with `register_special_token`, matched in the raw text before segmentation.
+    pub special_tokens: SpecialTokens,
+    /// Per-task prefix tokens added with `register_task`; the active one is put in front of
+    /// every tokenized text (see `for_task`).
+    pub task_prefixes: TaskPrefixes,
+}
+
+/// On-disk form of a tokenizer written by `Tokenizer::save`.
+#[derive(Serialize, Deserialize)]
+struct SavedTokenizer {
+    max_seq_length: usize,
+    /// Ids of the special tokens in the vocabulary, checked again on load.
+    #[serde(default)]
+    special_tokens: BTreeMap<String, usize>,
+    #[serde(default)]
+    segmentation: Segmentation,
+    /// `None` in files written before byte fallback, detected from the vocabulary on load.
+    #[serde(default)]
+    byte_fallback: Option<bool>,
+    #[serde(default)]
+    normalizer: TextNormalizer,
+    #[serde(default)]
+    truncation: Truncation,
+    #[serde(default)]
+    ngrams: NGramRange,
+    #[serde(default)]
+    token_rules: TokenRules,
+    #[serde(default)]
+    merge_phrases: bool,
+    /// Tokens added with `register_special_token`; their ids are checked on load too.
+    #[serde(default)]
+    registered_special_tokens: SpecialTokens,
+    #[serde(default)]
+    task_prefixes: TaskPrefixes,
+    /// Sorted so saved files are stable and diffable.
+    vocab: BTreeMap<String, usize>,
+}
+
+/// Contents of a tokenizer file: the current format, or the bare vocabulary that runs
+/// saved before `Tokenizer::save` existed.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenizerFile {
+    Saved(Box<SavedTokenizer>),
+    Vocab(BTreeMap<String, usize>),
 }
 
 impl Tokenizer {
@@ -13,7 +217,190 @@ impl Tokenizer {
     pub fn new(vocab: HashMap<String, usize>, max_seq_length: usize) -> Self {
         // Ensure special tokens are in the vocabulary
         Self::verify_vocab(&vocab);
-        Tokenizer { vocab, max_seq_length }
+        let byte_fallback = Self::has_byte_tokens(&vocab);
+        Tokenizer { vocab, max_seq_length, segmentation: Segmentation::Words, byte_fallback, normalizer: TextNormalizer::default(), truncation: Truncation::Head, ngrams: NGramRange::UNIGRAMS, token_rules: TokenRules::default(), merge_phrases: false, special_tokens: SpecialTokens::default(), task_prefixes: TaskPrefixes::default() }
+    }
+
+    /// A tokenizer with the `TEXT_NORMALIZER`, `TRUNCATION`, `NGRAMS` and phrase merging of
+    /// `config.rs`, as the pipeline builds its vocabularies with them.
+    pub fn configured(vocab: HashMap<String, usize>, max_seq_length: usize, token_rules: TokenRules) -> Self {
+        Self::new(vocab, max_seq_length)
+            .with_normalizer(TEXT_NORMALIZER)
+            .with_truncation(TRUNCATION)
+            .with_ngrams(NGRAMS)
+            .with_token_rules(token_rules)
+            .with_phrase_merging(PHRASES.is_some())
+    }
+
+    /// Uses `normalizer` instead of the default lowercase-and-strip preprocessing. The
+    /// vocabulary should be built with the same normalizer (`VocabOptions::normalizer`).
+    pub fn with_normalizer(mut self, normalizer: TextNormalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Selects which tokens of long sequences are kept.
+    pub fn with_truncation(mut self, truncation: Truncation) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    /// Also looks up the word n-grams of `ngrams` (word segmentation only). The vocabulary
+    /// should be built with the same range (`VocabOptions::ngrams`).
+    pub fn with_ngrams(mut self, ngrams: NGramRange) -> Self {
+        self.ngrams = ngrams;
+        self
+    }
+
+    /// Keeps the matches of `token_rules` as single tokens. The vocabulary should be built
+    /// with the same rules (`VocabOptions::token_rules`).
+    pub fn with_token_rules(mut self, token_rules: TokenRules) -> Self {
+        self.token_rules = token_rules;
+        self
+    }
+
+    /// Merges adjacent words into the phrase tokens of the vocabulary (word segmentation
+    /// only). The vocabulary should be built with a phrase detector (`VocabOptions::phrases`).
+    pub fn with_phrase_merging(mut self, merge_phrases: bool) -> Self {
+        self.merge_phrases = merge_phrases;
+        self
+    }
+
+    /// Registers a special token such as `[MASK]`, a domain marker (`<product>`) or a language
+    /// tag (`<lang:de>`). It is matched verbatim in the input before normalization and
+    /// segmentation, so it always becomes exactly its own id, and it is saved with the tokenizer.
+    ///
+    /// # Returns
+    /// * The token's id: its existing id when it is already in the vocabulary, otherwise a new
+    ///   id after the largest one. New ids need an embedding row, so register tokens before
+    ///   the model is created, or pass them to `build_vocab_with_stats` as special tokens.
+    pub fn register_special_token(&mut self, token: &str) -> Result<usize, Error> {
+        self.special_tokens.register(&mut self.vocab, token)
+    }
+
+    /// Registers `token` (e.g. `[TASK1]`) as a special token and as the prefix of `task`, so a
+    /// single encoder shared by several task heads can condition on the task identity.
+    ///
+    /// # Returns
+    /// * The token's id. Like `register_special_token`, new ids need an embedding row.
+    /// * An error if the token is invalid or already the prefix of another task.
+    pub fn register_task(&mut self, task: &str, token: &str) -> Result<usize, Error> {
+        let id = self.register_special_token(token)?;
+        self.task_prefixes.add(task, token)?;
+        Ok(id)
+    }
+
+    /// A copy of the tokenizer that puts the prefix token of `task` in front of every
+    /// tokenized text: `tokenize`, the batch and padding functions, `encode_single` and the
+    /// first sentence of `encode_pair`. Truncation always keeps the prefix.
+    ///
+    /// # Returns
+    /// * An error if no prefix is registered for `task`.
+    pub fn for_task(&self, task: &str) -> Result<Self, Error> {
+        let mut tokenizer = self.clone();
+        tokenizer.task_prefixes.activate(Some(task))?;
+        Ok(tokenizer)
+    }
+
+    /// Id of the active task's prefix token.
+    fn task_prefix(&self) -> Option<usize> {
+        self.task_prefixes.active_token().and_then(|token| self.vocab.get(token).copied())
+    }
+
+    /// Creates a WordPiece tokenizer from a BERT-style `vocab.txt` (one token per line).
+    /// It can be passed to `DataLoader` like any other tokenizer.
+    ///
+    /// # Returns
+    /// * The tokenizer, or an `InvalidData` error for a vocabulary without `[PAD]` and `[UNK]`.
+    pub fn from_wordpiece_vocab(vocab_path: &str, max_seq_length: usize) -> Result<Self, Error> {
+        let vocab = WordPieceTokenizer::load_vocab(vocab_path)?;
+        for token in [PAD_TOKEN, UNK_TOKEN] {
+            if !vocab.contains_key(token) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("{} is missing {}", vocab_path, token)));
+            }
+        }
+        let byte_fallback = Self::has_byte_tokens(&vocab);
+        Ok(Tokenizer { vocab, max_seq_length, segmentation: Segmentation::WordPiece, byte_fallback, normalizer: TextNormalizer::default(), truncation: Truncation::Head, ngrams: NGramRange::UNIGRAMS, token_rules: TokenRules::default(), merge_phrases: false, special_tokens: SpecialTokens::default(), task_prefixes: TaskPrefixes::default() })
+    }
+
+    /// Saves the vocabulary, `max_seq_length`, special tokens and segmentation as JSON, so
+    /// inference can reproduce the token ids used in training.
+    pub fn save(&self, file_path: &str) -> Result<(), Error> {
+        let special_tokens = [PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, MASK_TOKEN]
+            .iter()
+            .filter_map(|&token| self.vocab.get(token).map(|&id| (token.to_string(), id)))
+            .collect();
+        let saved = SavedTokenizer {
+            max_seq_length: self.max_seq_length,
+            special_tokens,
+            segmentation: self.segmentation.clone(),
+            byte_fallback: Some(self.byte_fallback),
+            normalizer: self.normalizer,
+            truncation: self.truncation,
+            ngrams: self.ngrams,
+            token_rules: self.token_rules.clone(),
+            merge_phrases: self.merge_phrases,
+            registered_special_tokens: self.special_tokens.clone(),
+            task_prefixes: self.task_prefixes.clone(),
+            vocab: self.vocab.iter().map(|(token, &id)| (token.clone(), id)).collect(),
+        };
+        std::fs::write(file_path, serde_json::to_string_pretty(&saved)?)
+    }
+
+    /// Loads a tokenizer written by `save`.
+    pub fn load(file_path: &str) -> Result<Self, Error> {
+        Self::load_or_migrate(file_path, None)
+    }
+
+    /// Same as `load`, also reading files that hold only a vocabulary, as saved by runs
+    /// created before `save` existed, as a word-level tokenizer with `legacy_max_seq_length`.
+    /// Fields added to the format since `save` was introduced take their defaults.
+    pub fn load_or_migrate(file_path: &str, legacy_max_seq_length: Option<usize>) -> Result<Self, Error> {
+        let saved = match serde_json::from_str(&std::fs::read_to_string(file_path)?)? {
+            TokenizerFile::Saved(saved) => *saved,
+            TokenizerFile::Vocab(vocab) => {
+                let max_seq_length = legacy_max_seq_length.ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, format!("{} holds only a vocabulary and needs the run's max_seq_length", file_path))
+                })?;
+                SavedTokenizer { max_seq_length, special_tokens: BTreeMap::new(), segmentation: Segmentation::Words, byte_fallback: None, normalizer: TextNormalizer::default(), truncation: Truncation::Head, ngrams: NGramRange::UNIGRAMS, token_rules: TokenRules::default(), merge_phrases: false, registered_special_tokens: SpecialTokens::default(), task_prefixes: TaskPrefixes::default(), vocab }
+            }
+        };
+        let vocab: HashMap<String, usize> = saved.vocab.into_iter().collect();
+
+        for token in [PAD_TOKEN, UNK_TOKEN] {
+            if !vocab.contains_key(token) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("{} is missing {}", file_path, token)));
+            }
+        }
+        if let Some((token, id)) = saved.special_tokens
+            .iter()
+            .map(|(token, &id)| (token.as_str(), id))
+            .chain(saved.registered_special_tokens.iter())
+            .find(|&(token, id)| vocab.get(token) != Some(&id))
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: special token {} should have id {}", file_path, token, id),
+            ));
+        }
+        if let Some((task, token)) = saved.task_prefixes.iter().find(|&(_, token)| !saved.registered_special_tokens.contains(token)) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("{}: prefix {} of task {} is not a special token", file_path, token, task)));
+        }
+
+        let byte_fallback = saved.byte_fallback.unwrap_or_else(|| Self::has_byte_tokens(&vocab));
+        Ok(Tokenizer {
+            vocab,
+            max_seq_length: saved.max_seq_length,
+            segmentation: saved.segmentation,
+            byte_fallback,
+            normalizer: saved.normalizer,
+            truncation: saved.truncation,
+            ngrams: saved.ngrams,
+            token_rules: saved.token_rules,
+            merge_phrases: saved.merge_phrases,
+            special_tokens: saved.registered_special_tokens,
+            task_prefixes: saved.task_prefixes,
+        })
     }
 
   
@@ -26,23 +413,79 @@ impl Tokenizer {
         }
     }
 
- // function to build vocab dynamically utilizing the dataset
-    pub fn build_vocab(
+    /// Appends the byte tokens that are missing from the vocabulary, e.g. to enable byte
+    /// fallback for a loaded WordPiece vocabulary. New ids follow the largest existing id.
+    pub fn add_byte_tokens(vocab: &mut HashMap<String, usize>) {
+        let mut next_id = vocab.values().max().map_or(0, |&id| id + 1);
+        for byte in 0..=u8::MAX {
+            if let Entry::Vacant(entry) = vocab.entry(byte_token(byte)) {
+                entry.insert(next_id);
+                next_id += 1;
+            }
+        }
+    }
+
+    fn has_byte_tokens(vocab: &HashMap<String, usize>) -> bool {
+        (0..=u8::MAX).all(|byte| vocab.contains_key(&byte_token(byte)))
+    }
+
+    /// Builds a vocabulary with every option, including a minimum token frequency, and
+    /// reports how much of the dataset it covers, e.g. to choose `max_vocab_size`.
+    ///
+    /// # Arguments
+    /// * `dataset` - Texts to count tokens in.
+    /// * `special_tokens` - Tokens that receive the first ids.
+    /// * `options` - Size limit, minimum frequency, normalizer, n-gram range and token rules.
+    ///
+    /// # Returns
+    /// * The vocabulary and its coverage statistics.
+    pub fn build_vocab_with_stats(
         dataset: &[String],
         special_tokens: &[&str],
-        max_vocab_size: Option<usize>,
+        options: &VocabOptions,
+    ) -> (HashMap<String, usize>, VocabStats) {
+        let mut token_counts =
+            Self::count_words(dataset, &options.normalizer, options.ngrams, &options.token_rules, options.phrases.as_ref());
+        let stats = VocabStats::new(&token_counts, special_tokens.len(), options.max_vocab_size, options.min_freq);
+        token_counts.retain(|_, count| *count >= options.min_freq);
+        let (vocab, _) = Self::rank_words(token_counts, special_tokens, options.max_vocab_size);
+        (vocab, stats)
+    }
+
+    fn count_words(
+        dataset: &[String],
+        normalizer: &TextNormalizer,
+        ngrams: NGramRange,
+        token_rules: &TokenRules,
+        phrases: Option<&PhraseDetector>,
     ) -> HashMap<String, usize> {
         let mut token_counts: HashMap<String, usize> = HashMap::new();
 
-     
-        for text in dataset {
-            let tokens = Self::preprocess_text(text);
+        let mut texts: Vec<Vec<String>> = dataset.iter().map(|text| token_rules.words(text, normalizer)).collect();
+        if let Some(detector) = phrases {
+            let found = detector.detect(&texts);
+            texts = texts.into_iter().map(|words| merge_phrases(words, |phrase| found.contains(phrase))).collect();
+        }
+        for words in &texts {
+            let tokens = ngrams.expand(words);
             for token in tokens {
                 *token_counts.entry(token).or_insert(0) += 1;
             }
         }
 
-      
+        token_counts
+    }
+
+    /// Turns word counts into a vocabulary: the special tokens first, then the most frequent
+    /// words up to `max_vocab_size` entries in total. Also returns the counts of the kept words.
+    ///
+    /// Words with the same count are ordered lexicographically, so the ids depend only on
+    /// the counts and never on `HashMap` iteration order or the order of the dataset.
+    pub(crate) fn rank_words(
+        token_counts: HashMap<String, usize>,
+        special_tokens: &[&str],
+        max_vocab_size: Option<usize>,
+    ) -> (HashMap<String, usize>, HashMap<String, usize>) {
         let mut vocab: HashMap<String, usize> = HashMap::new();
         for (i, &token) in special_tokens.iter().enumerate() {
             vocab.insert(token.to_string(), i);
@@ -50,63 +493,514 @@ impl Tokenizer {
 
 
         let mut sorted_tokens: Vec<_> = token_counts.into_iter().collect();
-        sorted_tokens.sort_by(|a, b| b.1.cmp(&a.1));
-        let max_vocab_size = max_vocab_size.unwrap_or(sorted_tokens.len());
+        sorted_tokens.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let max_vocab_size = max_vocab_size.unwrap_or(sorted_tokens.len() + special_tokens.len());
 
-        let mut index = special_tokens.len();
-        for (token, _) in sorted_tokens.into_iter().take(max_vocab_size - index) {
+        let mut vocab_counts: HashMap<String, usize> = HashMap::new();
+        let kept = sorted_tokens.into_iter().take(max_vocab_size.saturating_sub(special_tokens.len()));
+        for (index, (token, count)) in (special_tokens.len()..).zip(kept) {
+            vocab_counts.insert(token.clone(), count);
             vocab.insert(token, index);
-            index += 1;
         }
 
-        vocab
+        (vocab, vocab_counts)
+    }
+
+    /// Creates a tokenizer that segments text with a trained unigram model.
+    /// The vocabulary holds the special tokens first, followed by the model's pieces.
+    pub fn from_unigram(model: UnigramModel, special_tokens: &[&str], max_seq_length: usize) -> Self {
+        let vocab = model.vocab(special_tokens);
+        Self::verify_vocab(&vocab);
+        let byte_fallback = Self::has_byte_tokens(&vocab);
+        Tokenizer { vocab, max_seq_length, segmentation: Segmentation::Unigram(model), byte_fallback, normalizer: TextNormalizer::default(), truncation: Truncation::Head, ngrams: NGramRange::UNIGRAMS, token_rules: TokenRules::default(), merge_phrases: false, special_tokens: SpecialTokens::default(), task_prefixes: TaskPrefixes::default() }
+    }
+
+    /// Imports a HuggingFace `tokenizer.json` with a `WordLevel` or `WordPiece` model, so text
+    /// gets the same ids as `encode(text, add_special_tokens=False)` in HuggingFace.
+    ///
+    /// # Arguments
+    /// * `file_path` - Path to `tokenizer.json`.
+    /// * `max_seq_length` - Used when the file configures no truncation length.
+    ///
+    /// # Returns
+    /// * The tokenizer, or an `InvalidData` error for unsupported components (e.g. BPE or
+    ///   ByteLevel) or a vocabulary without `[PAD]` and `[UNK]`.
+    pub fn from_huggingface(file_path: &str, max_seq_length: usize) -> Result<Self, Error> {
+        let imported = HuggingFaceTokenizer::load(file_path)?;
+        for token in [PAD_TOKEN, UNK_TOKEN] {
+            if !imported.vocab.contains_key(token) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("{} is missing {}", file_path, token)));
+            }
+        }
+        Ok(Tokenizer {
+            vocab: imported.vocab,
+            max_seq_length: imported.max_length.unwrap_or(max_seq_length),
+            segmentation: Segmentation::HuggingFace(imported.pipeline),
+            // HuggingFace maps unknown words to [UNK]; byte tokens would change the ids.
+            byte_fallback: false,
+            normalizer: TextNormalizer::default(),
+            truncation: Truncation::Head,
+            ngrams: NGramRange::UNIGRAMS,
+            token_rules: TokenRules::default(),
+            merge_phrases: false,
+            special_tokens: SpecialTokens::default(),
+            task_prefixes: TaskPrefixes::default(),
+        })
     }
 
     pub fn tokenize(&self, text: &str) -> Vec<usize> {
-        let tokens = Self::preprocess_text(text);
+        let tokens = self.tokenize_without_prefix(text);
+        match self.task_prefix() {
+            Some(prefix) => std::iter::once(prefix).chain(tokens).collect(),
+            None => tokens,
+        }
+    }
+
+    /// `tokenize` without the task prefix.
+    fn tokenize_without_prefix(&self, text: &str) -> Vec<usize> {
+        let _scope = profiler::scope("tokenization");
+        if self.special_tokens.is_empty() {
+            return self.tokenize_text(text);
+        }
+        self.special_tokens
+            .split(text)
+            .into_iter()
+            .flat_map(|segment| match segment {
+                TextSegment::Text(text) => self.tokenize_text(text),
+                TextSegment::Special(id, _) => vec![id],
+            })
+            .collect()
+    }
+
+    /// Segments text without registered special tokens.
+    fn tokenize_text(&self, text: &str) -> Vec<usize> {
+        let tokens: Vec<String> = match &self.segmentation {
+            // N-grams missing from the vocabulary are skipped rather than mapped to `[UNK]`.
+            Segmentation::Words => self.ngrams
+                .expand(&self.phrase_words(text))
+                .into_iter()
+                .filter(|token| !is_ngram(token) || self.vocab.contains_key(token))
+                .collect(),
+            Segmentation::WordPiece => {
+                let wordpiece = WordPieceTokenizer::new(&self.vocab);
+                WordPieceTokenizer::basic_tokenize(&self.normalizer.isolate_cjk(&self.normalizer.normalize(text)))
+                    .into_iter()
+                    .flat_map(|word| self.unless_unknown(wordpiece.word_pieces(&word), word))
+                    .collect()
+            }
+            Segmentation::Unigram(model) => self.words(text)
+                .into_iter()
+                .flat_map(|word| self.unless_unknown(model.segment(&word), word))
+                .collect(),
+            Segmentation::HuggingFace(pipeline) => pipeline
+                .segment(text, &self.vocab)
+                .into_iter()
+                .flat_map(|(word, pieces)| self.unless_unknown(pieces, word))
+                .collect(),
+        };
+        tokens
+            .into_iter()
+            .flat_map(|token| self.token_ids(&token))
+            .collect()
+    }
+
+    /// Same as `tokenize`, with the byte range of every token in `text`, like HuggingFace's
+    /// `return_offsets_mapping`, e.g. to highlight the words behind a prediction, and the word
+    /// every token belongs to, like HuggingFace's `word_ids()`: the sub-word pieces and byte
+    /// tokens of a word share its index, so token outputs can be pooled back to words (see
+    /// `word_pooling`).
+    ///
+    /// # Returns
+    /// * `(id, (start, end), word)` triples, where `&text[start..end]` is the original text of
+    ///   the token. N-grams and phrases span all their words, byte-fallback tokens and `[UNK]`
+    ///   share the range of their word, and tokens that normalization changed beyond
+    ///   recognition get an empty range. Words are numbered from 0 in text order; n-grams and
+    ///   phrases belong to their first word, and registered special tokens to none.
+    pub fn tokenize_with_word_ids(&self, text: &str) -> Vec<(usize, Offset, Option<usize>)> {
+        // The task prefix is not in the text.
+        let mut tokens: Vec<(usize, Offset, Option<usize>)> = self.task_prefix().map(|prefix| (prefix, (0, 0), None)).into_iter().collect();
+        let mut start = 0;
+        let mut words_before = 0;
+        for segment in self.special_tokens.split(text) {
+            match segment {
+                TextSegment::Text(part) => {
+                    let part_tokens = self.tokenize_text_with_offsets(part);
+                    let part_words = part_tokens.iter().map(|&(_, _, word)| word + 1).max().unwrap_or(0);
+                    tokens.extend(part_tokens.into_iter().map(|(id, (from, to), word)| (id, (start + from, start + to), Some(words_before + word))));
+                    start += part.len();
+                    words_before += part_words;
+                }
+                TextSegment::Special(id, token) => {
+                    tokens.push((id, (start, start + token.len()), None));
+                    start += token.len();
+                }
+            }
+        }
+        tokens
+    }
+
+    /// Word of every position of the padded encoding `tokenize_and_pad_batch` produces for
+    /// `text`, after truncation; `None` for padding, special tokens and a separator inserted
+    /// by truncation.
+    pub fn word_ids(&self, text: &str) -> Vec<Option<usize>> {
+        let words: Vec<Option<usize>> = self.tokenize_with_word_ids(text).into_iter().map(|(_, _, word)| word).collect();
+        let mut word_ids: Vec<Option<usize>> = self
+            .truncated_positions(words.len(), self.max_seq_length)
+            .into_iter()
+            .map(|position| position.and_then(|position| words[position]))
+            .collect();
+        word_ids.resize(self.max_seq_length, None);
+        word_ids
+    }
+
+    /// `tokenize_text` with offsets and word indices: the words and pieces of every
+    /// segmentation are found again in the original text with a `TextAlignment` using the
+    /// same normalization.
+    fn tokenize_text_with_offsets(&self, text: &str) -> Vec<(usize, Offset, usize)> {
+        let tokens: Vec<(String, Offset, usize)> = match &self.segmentation {
+            Segmentation::Words => {
+                let mut alignment = TextAlignment::new(text, |part| self.normalizer.normalize(part));
+                let words = self.phrase_words(text);
+                let spans: Vec<Offset> = words
+                    .iter()
+                    .map(|word| {
+                        if self.merge_phrases {
+                            word.split(PHRASE_SEPARATOR).map(|part| alignment.find(part)).reduce(|first, last| (first.0, last.1)).unwrap_or_default()
+                        } else {
+                            alignment.find(word)
+                        }
+                    })
+                    .collect();
+                self.ngrams
+                    .windows(words.len())
+                    .into_iter()
+                    .map(|range| (words[range.clone()].join(NGRAM_SEPARATOR), (spans[range.start].0, spans[range.end - 1].1), range.start))
+                    .filter(|(token, _, _)| !is_ngram(token) || self.vocab.contains_key(token))
+                    .collect()
+            }
+            Segmentation::WordPiece => {
+                let normalize = |part: &str| self.normalizer.isolate_cjk(&self.normalizer.normalize(part)).to_lowercase();
+                let mut alignment = TextAlignment::new(text, normalize);
+                let wordpiece = WordPieceTokenizer::new(&self.vocab);
+                WordPieceTokenizer::basic_tokenize(&normalize(text))
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(index, word)| {
+                        let pieces = self.unless_unknown(wordpiece.word_pieces(&word), word.clone());
+                        with_word(align_pieces(&mut alignment, &word, pieces, CONTINUATION_PREFIX), index)
+                    })
+                    .collect()
+            }
+            Segmentation::Unigram(model) => {
+                let mut alignment = TextAlignment::new(text, |part| self.normalizer.normalize(part));
+                self.words(text)
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(index, word)| {
+                        let pieces = self.unless_unknown(model.segment(&word), word.clone());
+                        with_word(align_pieces(&mut alignment, &word, pieces, &WORD_BOUNDARY.to_string()), index)
+                    })
+                    .collect()
+            }
+            Segmentation::HuggingFace(pipeline) => {
+                let mut alignment = TextAlignment::new(text, |part| match &pipeline.normalizer {
+                    Some(normalizer) => normalizer.apply(part),
+                    None => part.to_string(),
+                });
+                let prefix = match &pipeline.model {
+                    HuggingFaceModel::WordPiece { continuing_subword_prefix, .. } => continuing_subword_prefix.as_str(),
+                    HuggingFaceModel::WordLevel => "",
+                };
+                pipeline
+                    .segment(text, &self.vocab)
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(index, (word, pieces))| {
+                        let pieces = self.unless_unknown(pieces, word.clone());
+                        with_word(align_pieces(&mut alignment, &word, pieces, prefix), index)
+                    })
+                    .collect()
+            }
+        };
         tokens
             .into_iter()
-            .map(|token| *self.vocab.get(&token).unwrap_or(&self.vocab[UNK_TOKEN]))
+            .flat_map(|(token, span, word)| self.token_ids(&token).into_iter().map(move |id| (id, span, word)))
             .collect()
     }
 
-    
+    /// Sub-word segmenters give up on a word with a single `[UNK]`; with byte fallback the
+    /// whole word is kept instead so that `token_ids` spells it out in bytes.
+    fn unless_unknown(&self, pieces: Vec<String>, word: String) -> Vec<String> {
+        if self.byte_fallback && pieces.len() == 1 && pieces[0] == UNK_TOKEN {
+            vec![word]
+        } else {
+            pieces
+        }
+    }
+
+    /// Id of a vocabulary token. Unknown tokens become `[UNK]`, or their UTF-8 bytes
+    /// when byte fallback is enabled.
+    fn token_ids(&self, token: &str) -> Vec<usize> {
+        match self.vocab.get(token) {
+            Some(&id) => vec![id],
+            None if self.byte_fallback => token
+                .bytes()
+                .map(|byte| self.vocab.get(&byte_token(byte)).copied().unwrap_or(self.vocab[UNK_TOKEN]))
+                .collect(),
+            None => vec![self.vocab[UNK_TOKEN]],
+        }
+    }
+
+    /// Truncates a sequence to `max_seq_length` with the tokenizer's strategy and pads it
+    /// to exactly that length.
     pub fn pad_sequence(&self, sequence: Vec<usize>) -> Vec<usize> {
-        let mut padded_sequence = sequence;
+        let (mut padded_sequence, _) = self.truncate(sequence);
         padded_sequence.resize(self.max_seq_length, self.vocab[PAD_TOKEN]);
         padded_sequence
     }
 
+    /// Shortens a sequence to at most `max_seq_length` tokens.
+    ///
+    /// # Returns
+    /// * The kept tokens and the number of dropped tokens.
+    pub fn truncate(&self, sequence: Vec<usize>) -> (Vec<usize>, usize) {
+        self.truncate_to(sequence, self.max_seq_length)
+    }
+
+    /// Same as `truncate` with a custom length, e.g. the room left next to special tokens.
+    fn truncate_to(&self, sequence: Vec<usize>, max_len: usize) -> (Vec<usize>, usize) {
+        if sequence.len() <= max_len {
+            return (sequence, 0);
+        }
+        let separator = self.vocab.get(SEP_TOKEN).copied().unwrap_or_default();
+        let positions = self.truncated_positions(sequence.len(), max_len);
+        let dropped = sequence.len() - positions.iter().flatten().count();
+        let kept = positions.into_iter().map(|position| position.map_or(separator, |position| sequence[position])).collect();
+        (kept, dropped)
+    }
+
+    /// Positions of a `len`-token sequence that truncation to `max_len` keeps, in order.
+    ///
+    /// # Returns
+    /// * One entry per kept token; `None` is the `[SEP]` inserted by
+    ///   `Truncation::HeadAndTail { separator: true, .. }` in place of the dropped middle.
+    pub fn truncated_positions(&self, len: usize, max_len: usize) -> Vec<Option<usize>> {
+        if len <= max_len {
+            return (0..len).map(Some).collect();
+        }
+        if self.task_prefix().is_some() && max_len > 0 {
+            // The task prefix is never truncated; the strategy applies to the rest.
+            let rest = self.strategy_positions(len - 1, max_len - 1);
+            return std::iter::once(Some(0)).chain(rest.into_iter().map(|position| position.map(|i| i + 1))).collect();
+        }
+        self.strategy_positions(len, max_len)
+    }
+
+    /// `truncated_positions` by the truncation strategy alone, for `len > max_len`.
+    fn strategy_positions(&self, len: usize, max_len: usize) -> Vec<Option<usize>> {
+        let (head_tokens, separator) = match self.truncation {
+            Truncation::Head => (max_len, false),
+            Truncation::Tail => (0, false),
+            Truncation::HeadAndTail { head_tokens, separator } => {
+                let head_tokens = head_tokens.min(max_len);
+                // Only between a non-empty head and tail.
+                (head_tokens, separator && head_tokens > 0 && head_tokens + 1 < max_len && self.vocab.contains_key(SEP_TOKEN))
+            }
+        };
+        let tail_tokens = max_len - head_tokens - separator as usize;
+        (0..head_tokens)
+            .map(Some)
+            .chain(separator.then_some(None))
+            .chain((len - tail_tokens..len).map(Some))
+            .collect()
+    }
+
+    /// Encodes a single sentence as `[CLS] text [SEP]`, padded to `max_seq_length`.
+    ///
+    /// Long texts are truncated with the tokenizer's strategy so the special tokens
+    /// always fit. `[CLS]` and `[SEP]` are only inserted if they are part of the vocabulary.
+    pub fn encode_single(&self, text: &str) -> Vec<usize> {
+        let (cls, sep) = (self.vocab.get(CLS_TOKEN).copied(), self.vocab.get(SEP_TOKEN).copied());
+        let budget = self.max_seq_length.saturating_sub(cls.is_some() as usize + sep.is_some() as usize);
+        let (tokens, _) = self.truncate_to(self.tokenize(text), budget);
+
+        let mut sequence: Vec<usize> = cls.into_iter().collect();
+        sequence.extend(tokens);
+        sequence.extend(sep);
+        self.pad_sequence(sequence)
+    }
+
+    /// Encodes a sentence pair as `[CLS] first [SEP] second [SEP]`, padded to `max_seq_length`.
+    ///
+    /// When the pair is too long, tokens are dropped from the end of the longer
+    /// sentence first so both sides keep some context. `[CLS]` and `[SEP]` are only
+    /// inserted if they are part of the vocabulary.
+    pub fn encode_pair(&self, first: &str, second: &str) -> Vec<usize> {
+        self.encode_pair_with_segments(first, second).0
+    }
+
+    /// Same as `encode_pair`, also returning the segment id of every position:
+    /// 0 for `[CLS] first [SEP]` and padding, 1 for `second [SEP]`.
+    pub fn encode_pair_with_segments(&self, first: &str, second: &str) -> (Vec<usize>, Vec<usize>) {
+        let mut first_tokens = self.tokenize(first);
+        let mut second_tokens = self.tokenize_without_prefix(second);
+        let (cls, sep) = (self.vocab.get(CLS_TOKEN).copied(), self.vocab.get(SEP_TOKEN).copied());
+
+        let budget = self.max_seq_length.saturating_sub(cls.is_some() as usize + 2 * sep.is_some() as usize);
+        while first_tokens.len() + second_tokens.len() > budget {
+            if first_tokens.len() > second_tokens.len() {
+                first_tokens.pop();
+            } else {
+                second_tokens.pop();
+            }
+        }
+
+        let mut sequence: Vec<usize> = cls.into_iter().collect();
+        sequence.extend(first_tokens);
+        sequence.extend(sep);
+        let first_segment_length = sequence.len();
+        sequence.extend(second_tokens);
+        sequence.extend(sep);
+
+        // Segments go through the same truncation as the tokens so positions line up; an
+        // inserted separator stays in the segment of the token before it.
+        let mut segments: Vec<usize> = self
+            .truncated_positions(sequence.len(), self.max_seq_length)
+            .into_iter()
+            .scan(0, |segment, position| {
+                *segment = position.map_or(*segment, |i| (i >= first_segment_length) as usize);
+                Some(*segment)
+            })
+            .collect();
+        segments.resize(self.max_seq_length, 0);
+        (self.pad_sequence(sequence), segments)
+    }
+
+    /// Encodes sentence pairs with their attention masks and segment ids.
+    pub fn encode_pair_batch(&self, pairs: &[(String, String)]) -> EncodedBatch {
+        let (input_ids, token_type_ids): (Vec<Vec<usize>>, Vec<Vec<usize>>) =
+            pairs.iter().map(|(first, second)| self.encode_pair_with_segments(first, second)).unzip();
+        EncodedBatch { token_type_ids, ..self.mask_batch(input_ids) }
+    }
+
+    /// Attention mask of a padded sequence: 1 for real tokens, 0 for PAD positions.
+    pub fn attention_mask(&self, sequence: &[usize]) -> Vec<usize> {
+        let pad_id = self.vocab[PAD_TOKEN];
+        sequence
+            .iter()
+            .map(|&token| if token == pad_id { 0 } else { 1 })
+            .collect()
+    }
+
+
+    /// Tokenizes and pads a batch and returns the attention mask of every sequence with its ids.
+    pub fn encode_batch(&self, texts: &[String]) -> EncodedBatch {
+        self.mask_batch(self.tokenize_and_pad_batch(texts))
+    }
+
+    /// Adds the attention masks and segment ids (`segment_ids`) to already padded sequences.
+    pub fn mask_batch(&self, input_ids: Vec<Vec<usize>>) -> EncodedBatch {
+        let attention_mask = input_ids.iter().map(|sequence| self.attention_mask(sequence)).collect();
+        let token_type_ids = input_ids.iter().map(|sequence| self.segment_ids(sequence)).collect();
+        EncodedBatch { input_ids, attention_mask, token_type_ids }
+    }
+
+    /// Segment ids of a padded sequence: real tokens after its first `[SEP]` are in segment 1,
+    /// everything else in segment 0. A template such as `"{title} [SEP] {body}"` thus puts
+    /// its second field in segment 1, as `encode_pair_batch` does for sentence pairs.
+    pub fn segment_ids(&self, sequence: &[usize]) -> Vec<usize> {
+        let (sep, pad) = (self.vocab.get(SEP_TOKEN).copied(), self.vocab[PAD_TOKEN]);
+        let mut segment = 0;
+        sequence
+            .iter()
+            .map(|&token| {
+                let id = if token == pad { 0 } else { segment };
+                if Some(token) == sep {
+                    segment = 1;
+                }
+                id
+            })
+            .collect()
+    }
 
     pub fn tokenize_and_pad_batch(&self, texts: &[String]) -> Vec<Vec<usize>> {
-        texts
+        self.tokenize_and_pad_batch_with_report(texts).0
+    }
+
+    /// Same as `tokenize_and_pad_batch`, also counting what truncation removed.
+    pub fn tokenize_and_pad_batch_with_report(&self, texts: &[String]) -> (Vec<Vec<usize>>, TruncationReport) {
+        let mut report = TruncationReport { sequences: texts.len(), ..Default::default() };
+        let sequences = texts
             .iter()
             .map(|text| {
-                let tokenized = self.tokenize(text);
-                self.pad_sequence(tokenized)
+                let (mut sequence, dropped) = self.truncate(self.tokenize(text));
+                if dropped > 0 {
+                    report.truncated_sequences += 1;
+                    report.dropped_tokens += dropped;
+                }
+                sequence.resize(self.max_seq_length, self.vocab[PAD_TOKEN]);
+                sequence
             })
-            .collect()
+            .collect();
+        (sequences, report)
     }
 
+    /// Lowercases the text, strips non-alphanumeric characters and splits on whitespace.
+    pub fn preprocess_text(text: &str) -> Vec<String> {
+        TextNormalizer::default().words(text)
+    }
 
-    fn preprocess_text(text: &str) -> Vec<String> {
-        text.to_lowercase()
-            .chars()
-            .filter(|c| c.is_alphanumeric() || c.is_whitespace())
-            .collect::<String>()
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect()
+    /// Same as `preprocess_text` with the tokenizer's token rules and normalizer.
+    pub fn words(&self, text: &str) -> Vec<String> {
+        self.token_rules.words(text, &self.normalizer)
     }
+
+    /// `words` with adjacent words merged into the vocabulary's phrases when `merge_phrases` is set.
+    fn phrase_words(&self, text: &str) -> Vec<String> {
+        let words = self.words(text);
+        if self.merge_phrases {
+            merge_phrases(words, |phrase| self.vocab.contains_key(phrase))
+        } else {
+            words
+        }
+    }
+}
+
+/// Tags the aligned pieces of a word with its index.
+fn with_word(pieces: Vec<(String, Offset)>, word: usize) -> Vec<(String, Offset, usize)> {
+    pieces.into_iter().map(|(piece, offset)| (piece, offset, word)).collect()
+}
+
+/// Offsets of the pieces of one word. Each piece is found without its `marker` (a
+/// continuation prefix or word boundary); a word the segmenter gave up on (`[UNK]`) is found whole.
+fn align_pieces(alignment: &mut TextAlignment, word: &str, pieces: Vec<String>, marker: &str) -> Vec<(String, Offset)> {
+    if pieces.len() == 1 && pieces[0] == UNK_TOKEN {
+        return vec![(UNK_TOKEN.to_string(), alignment.find(word))];
+    }
+    pieces
+        .into_iter()
+        .map(|piece| {
+            let span = alignment.find(piece.strip_prefix(marker).unwrap_or(&piece));
+            (piece, span)
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::fixtures::{temp_path, tiny_vocab};
+    use crate::tokenization::normalization::UnicodeForm;
+    use crate::tokenization::phrases::PhraseScoring;
+
+    /// Vocabulary of `dataset` built with the default `VocabOptions` and `max_vocab_size`.
+    fn build_vocab(dataset: &[String], special_tokens: &[&str], max_vocab_size: Option<usize>) -> HashMap<String, usize> {
+        Tokenizer::build_vocab_with_stats(dataset, special_tokens, &VocabOptions { max_vocab_size, ..VocabOptions::default() }).0
+    }
 
     #[test]
     fn test_vocab_verification() {
-        let vocab = HashMap::from([(PAD_TOKEN.to_string(), 0), (UNK_TOKEN.to_string(), 1)]);
+        let vocab = tiny_vocab(&[]);
         Tokenizer::verify_vocab(&vocab);
     }
 
@@ -119,12 +1013,7 @@ mod tests {
 
     #[test]
     fn test_tokenization_and_padding() {
-        let vocab = HashMap::from([
-            (PAD_TOKEN.to_string(), 0),
-            (UNK_TOKEN.to_string(), 1),
-            ("hello".to_string(), 2),
-            ("world".to_string(), 3),
-        ]);
+        let vocab = tiny_vocab(&["hello", "world"]);
         let tokenizer = Tokenizer::new(vocab.clone(), 5);
 
         let tokenized = tokenizer.tokenize("hello world unknown");
@@ -132,6 +1021,9 @@ mod tests {
 
         let padded = tokenizer.pad_sequence(tokenized);
         assert_eq!(padded, vec![2, 3, 1, 0, 0]);
+
+        let mask = tokenizer.attention_mask(&padded);
+        assert_eq!(mask, vec![1, 1, 1, 0, 0]);
     }
 
     #[test]
@@ -142,11 +1034,467 @@ mod tests {
             "hello hello".to_string(),
         ];
         let special_tokens = &[PAD_TOKEN, UNK_TOKEN];
-        let vocab = Tokenizer::build_vocab(&dataset, special_tokens, Some(5));
+        let vocab = build_vocab(&dataset, special_tokens, Some(5));
 
         assert!(vocab.contains_key(PAD_TOKEN));
         assert!(vocab.contains_key(UNK_TOKEN));
         assert!(vocab.contains_key("hello"));
         assert!(vocab.contains_key("world"));
     }
+
+    #[test]
+    fn test_build_vocab_is_deterministic() {
+        let dataset: Vec<String> =
+            ["pear fig", "kiwi apple", "fig date", "banana cherry", "apple lime"].iter().map(|s| s.to_string()).collect();
+        let special_tokens = &[PAD_TOKEN, UNK_TOKEN];
+        let vocab = build_vocab(&dataset, special_tokens, None);
+
+        // Ties are broken lexicographically: `apple` and `fig` occur twice, the rest once.
+        let mut by_id: Vec<(&String, &usize)> = vocab.iter().collect();
+        by_id.sort_by_key(|&(_, &id)| id);
+        let order: Vec<&str> = by_id.iter().map(|(token, _)| token.as_str()).collect();
+        assert_eq!(order, vec![PAD_TOKEN, UNK_TOKEN, "apple", "fig", "banana", "cherry", "date", "kiwi", "lime", "pear"]);
+
+        // Rebuilding, reordering the dataset or truncating at a tie gives the same ids.
+        let mut reversed = dataset.clone();
+        reversed.reverse();
+        for _ in 0..10 {
+            assert_eq!(build_vocab(&dataset, special_tokens, None), vocab);
+            assert_eq!(build_vocab(&reversed, special_tokens, None), vocab);
+            let truncated = build_vocab(&reversed, special_tokens, Some(6));
+            assert!(truncated.contains_key("cherry") && !truncated.contains_key("date"));
+        }
+    }
+
+    #[test]
+    fn test_encode_pair_truncates_longer_sentence() {
+        let vocab = HashMap::from([
+            (PAD_TOKEN.to_string(), 0),
+            (UNK_TOKEN.to_string(), 1),
+            (SEP_TOKEN.to_string(), 2),
+            ("a".to_string(), 3),
+            ("b".to_string(), 4),
+        ]);
+        let tokenizer = Tokenizer::new(vocab, 6);
+
+        assert_eq!(tokenizer.encode_pair("a a", "b"), vec![3, 3, 2, 4, 2, 0]);
+        assert_eq!(tokenizer.encode_pair("a a a a", "b b"), vec![3, 3, 2, 4, 4, 2]);
+    }
+
+    #[test]
+    fn test_pair_segment_ids() {
+        let vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, "a", "b"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        let tokenizer = Tokenizer::new(vocab, 7);
+
+        let (ids, segments) = tokenizer.encode_pair_with_segments("a a", "b");
+        assert_eq!(ids, vec![2, 4, 4, 3, 5, 3, 0]);
+        assert_eq!(segments, vec![0, 0, 0, 0, 1, 1, 0]);
+
+        let batch = tokenizer.encode_pair_batch(&[("a".to_string(), "b b".to_string())]);
+        assert_eq!(batch.input_ids, vec![vec![2, 4, 3, 5, 5, 3, 0]]);
+        assert_eq!(batch.attention_mask, vec![vec![1, 1, 1, 1, 1, 1, 0]]);
+        assert_eq!(batch.token_type_ids, vec![vec![0, 0, 0, 1, 1, 1, 0]]);
+        assert_eq!(batch.token_type_array().unwrap().row(0).sum(), 3.0);
+        assert_eq!(tokenizer.encode_batch(&["a b".to_string()]).token_type_ids, vec![vec![0; 7]]);
+        // Single sequences get the same segments from their first separator on.
+        assert_eq!(tokenizer.segment_ids(&ids), segments);
+        assert_eq!(tokenizer.mask_batch(batch.input_ids.clone()).token_type_ids, batch.token_type_ids);
+
+        // Segments are truncated like the tokens when even the special tokens do not fit.
+        let tail = Tokenizer { max_seq_length: 2, truncation: Truncation::Tail, ..tokenizer };
+        assert_eq!(tail.encode_pair_with_segments("a", "b"), (vec![3, 3], vec![0, 1]));
+    }
+
+    #[test]
+    fn test_encode_single_and_pair_insert_cls_and_sep() {
+        let vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, "a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        let tokenizer = Tokenizer::new(vocab, 6);
+
+        assert_eq!(tokenizer.encode_single("a b"), vec![2, 4, 5, 3, 0, 0]);
+        // Truncation leaves room for both special tokens.
+        assert_eq!(tokenizer.encode_single("a b c a b c"), vec![2, 4, 5, 6, 4, 3]);
+        let tail = tokenizer.clone().with_truncation(Truncation::Tail);
+        assert_eq!(tail.encode_single("a b c a b c"), vec![2, 6, 4, 5, 6, 3]);
+
+        assert_eq!(tokenizer.encode_pair("a", "b"), vec![2, 4, 3, 5, 3, 0]);
+        assert_eq!(tokenizer.encode_pair("a a a", "b c"), vec![2, 4, 4, 3, 5, 3]);
+    }
+
+    #[test]
+    fn test_byte_fallback_spells_out_unknown_words() {
+        let dataset = vec!["hello world".to_string()];
+        let mut vocab = build_vocab(&dataset, &[PAD_TOKEN, UNK_TOKEN], None);
+        Tokenizer::add_byte_tokens(&mut vocab);
+        assert_eq!(vocab.len(), 4 + 256);
+        assert_eq!(vocab["<0x00>"], 4);
+
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        assert!(tokenizer.byte_fallback);
+        let expected: Vec<usize> = std::iter::once(vocab["hello"])
+            .chain("hé".bytes().map(|byte| vocab[&byte_token(byte)]))
+            .collect();
+        assert_eq!(tokenizer.tokenize("hello hé"), expected);
+        assert!(!tokenizer.tokenize("anything ünseen").contains(&vocab[UNK_TOKEN]));
+    }
+
+    #[test]
+    fn test_byte_fallback_for_wordpiece() {
+        let mut vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, "play", "##ing"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        Tokenizer::add_byte_tokens(&mut vocab);
+        let mut tokenizer = Tokenizer::new(vocab.clone(), 8);
+        tokenizer.segmentation = Segmentation::WordPiece;
+
+        assert_eq!(tokenizer.tokenize("playing ok"), vec![2, 3, vocab["<0x6F>"], vocab["<0x6B>"]]);
+
+        tokenizer.byte_fallback = false;
+        assert_eq!(tokenizer.tokenize("playing ok"), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dataset = vec!["hello world".to_string(), "hello rust".to_string()];
+        let vocab = build_vocab(&dataset, &[PAD_TOKEN, UNK_TOKEN, SEP_TOKEN], None);
+        let mut tokenizer = Tokenizer::new(vocab, 7);
+        tokenizer.segmentation = Segmentation::WordPiece;
+
+        let path = &temp_path("tokenizer_round_trip_test.json");
+        tokenizer.save(path).unwrap();
+        let loaded = Tokenizer::load(path);
+        std::fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.vocab, tokenizer.vocab);
+        assert_eq!(loaded.max_seq_length, 7);
+        assert_eq!(loaded.segmentation, Segmentation::WordPiece);
+        assert_eq!(loaded.byte_fallback, tokenizer.byte_fallback);
+        assert_eq!(loaded.encode_pair("hello", "rust world"), tokenizer.encode_pair("hello", "rust world"));
+    }
+
+    #[test]
+    fn test_load_migrates_older_files() {
+        let vocab_only = &temp_path("tokenizer_vocab_only_test.json");
+        let first_format = &temp_path("tokenizer_first_format_test.json");
+        std::fs::write(vocab_only, r#"{"[PAD]": 0, "[UNK]": 1, "hello": 2}"#).unwrap();
+        std::fs::write(first_format, r#"{"max_seq_length": 4, "vocab": {"[PAD]": 0, "[UNK]": 1, "hello": 2}}"#).unwrap();
+        let without_length = Tokenizer::load(vocab_only);
+        let migrated = Tokenizer::load_or_migrate(vocab_only, Some(6));
+        let first = Tokenizer::load(first_format);
+        std::fs::remove_file(vocab_only).unwrap();
+        std::fs::remove_file(first_format).unwrap();
+
+        assert!(without_length.is_err());
+        let migrated = migrated.unwrap();
+        assert_eq!((migrated.max_seq_length, &migrated.segmentation), (6, &Segmentation::Words));
+        assert_eq!(migrated.tokenize("hello there"), vec![2, 1]);
+        let first = first.unwrap();
+        assert_eq!(first.max_seq_length, 4);
+        assert!(!first.byte_fallback);
+    }
+
+    #[test]
+    fn test_load_rejects_inconsistent_special_tokens() {
+        let path = &temp_path("tokenizer_inconsistent_test.json");
+        std::fs::write(
+            path,
+            r#"{"max_seq_length": 4, "special_tokens": {"[PAD]": 1}, "segmentation": "Words",
+                "byte_fallback": false, "vocab": {"[PAD]": 0, "[UNK]": 1}}"#,
+        )
+        .unwrap();
+        let result = Tokenizer::load(path);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_from_huggingface_wordpiece() {
+        let path = &temp_path("tokenizer_huggingface_test.json");
+        std::fs::write(
+            path,
+            r###"{"normalizer": {"type": "BertNormalizer", "lowercase": true},
+                "pre_tokenizer": {"type": "BertPreTokenizer"},
+                "model": {"type": "WordPiece", "unk_token": "[UNK]", "continuing_subword_prefix": "##",
+                          "vocab": {"[PAD]": 0, "[UNK]": 1, "[SEP]": 2, "play": 3, "##ing": 4, "!": 5}}}"###,
+        )
+        .unwrap();
+        let tokenizer = Tokenizer::from_huggingface(path, 6);
+        std::fs::remove_file(path).unwrap();
+        let tokenizer = tokenizer.unwrap();
+
+        assert_eq!(tokenizer.max_seq_length, 6);
+        assert!(!tokenizer.byte_fallback);
+        assert_eq!(tokenizer.tokenize("PLAYING, play!"), vec![3, 4, 1, 3, 5]);
+    }
+
+    #[test]
+    fn test_normalizer_is_used_and_saved() {
+        let normalizer = TextNormalizer { unicode_form: UnicodeForm::Nfc, fold_accents: true, keep_punctuation: true, split_cjk: false };
+        let dataset = vec!["Café, crème!".to_string()];
+        let options = VocabOptions { normalizer, ..VocabOptions::default() };
+        let (vocab, _) = Tokenizer::build_vocab_with_stats(&dataset, &[PAD_TOKEN, UNK_TOKEN], &options);
+        assert!(["cafe", "creme", ",", "!"].iter().all(|token| vocab.contains_key(*token)));
+
+        let tokenizer = Tokenizer::new(vocab.clone(), 4).with_normalizer(normalizer);
+        assert_eq!(tokenizer.tokenize("CAFE\u{301}!"), vec![vocab["cafe"], vocab["!"]]);
+
+        let path = &temp_path("tokenizer_normalizer_test.json");
+        tokenizer.save(path).unwrap();
+        let loaded = Tokenizer::load(path);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.unwrap().normalizer, normalizer);
+    }
+
+    #[test]
+    fn test_truncation_strategies() {
+        let vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, "a", "b", "c", "d", "e", "f"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        let text = "a b c d e f";
+        let tokenizer = Tokenizer::new(vocab, 4);
+
+        assert_eq!(tokenizer.pad_sequence(tokenizer.tokenize(text)), vec![2, 3, 4, 5]);
+        assert_eq!(tokenizer.clone().with_truncation(Truncation::Tail).truncate(tokenizer.tokenize(text)), (vec![4, 5, 6, 7], 2));
+        let head_and_tail = tokenizer.clone().with_truncation(Truncation::HeadAndTail { head_tokens: 1, separator: false });
+        assert_eq!(head_and_tail.truncate(tokenizer.tokenize(text)), (vec![2, 5, 6, 7], 2));
+        assert_eq!(head_and_tail.truncate(vec![2, 3]), (vec![2, 3], 0));
+
+        let (batch, report) = tokenizer.tokenize_and_pad_batch_with_report(&[text.to_string(), "a b".to_string()]);
+        assert_eq!(batch, vec![vec![2, 3, 4, 5], vec![2, 3, 0, 0]]);
+        assert_eq!(report, TruncationReport { sequences: 2, truncated_sequences: 1, dropped_tokens: 2 });
+    }
+
+    #[test]
+    fn test_head_and_tail_separator() {
+        let vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, SEP_TOKEN, "a", "b", "c", "d", "e", "f"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        let tokenizer = Tokenizer::new(vocab.clone(), 5).with_truncation(Truncation::HeadAndTail { head_tokens: 2, separator: true });
+        let tokens = tokenizer.tokenize("a b c d e f");
+
+        assert_eq!(tokenizer.truncate(tokens.clone()), (vec![3, 4, 2, 7, 8], 2));
+        assert_eq!(tokenizer.truncated_positions(6, 5), vec![Some(0), Some(1), None, Some(4), Some(5)]);
+        assert_eq!(tokenizer.truncate(vec![3, 4, 5]), (vec![3, 4, 5], 0));
+        // No separator without room for a tail, or without `[SEP]` in the vocabulary.
+        assert_eq!(tokenizer.truncated_positions(6, 3), vec![Some(0), Some(1), Some(5)]);
+        let mut without_sep = Tokenizer { vocab, ..tokenizer.clone() };
+        without_sep.vocab.remove(SEP_TOKEN);
+        assert_eq!(without_sep.truncate(tokens), (vec![3, 4, 6, 7, 8], 1));
+
+        let saved = serde_json::to_string(&Truncation::HeadAndTail { head_tokens: 2, separator: true }).unwrap();
+        assert_eq!(serde_json::from_str::<Truncation>(&saved).unwrap(), tokenizer.truncation);
+        let old: Truncation = serde_json::from_str(r#"{"HeadAndTail":{"head_tokens":2}}"#).unwrap();
+        assert_eq!(old, Truncation::HeadAndTail { head_tokens: 2, separator: false });
+    }
+
+    #[test]
+    fn test_encode_batch_masks_padding() {
+        let vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, "a", "b"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        let tokenizer = Tokenizer::new(vocab, 4);
+        let batch = tokenizer.encode_batch(&["a b".to_string(), "b c a".to_string()]);
+
+        assert_eq!(batch.input_ids, vec![vec![2, 3, 0, 0], vec![3, 1, 2, 0]]);
+        assert_eq!(batch.attention_mask, vec![vec![1, 1, 0, 0], vec![1, 1, 1, 0]]);
+        let (ids, mask) = batch.to_arrays().unwrap();
+        assert_eq!(ids.shape(), &[2, 4]);
+        assert_eq!(mask.row(1).to_vec(), vec![1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_min_freq_and_coverage_stats() {
+        let dataset: Vec<String> = ["a a b", "a b c", "d"].iter().map(|s| s.to_string()).collect();
+        let options = VocabOptions { max_vocab_size: Some(10), min_freq: 2, ..VocabOptions::default() };
+        let (vocab, stats) = Tokenizer::build_vocab_with_stats(&dataset, &[PAD_TOKEN, UNK_TOKEN], &options);
+
+        assert_eq!(vocab.len(), 4);
+        assert!(!vocab.contains_key("c") && !vocab.contains_key("d"));
+        assert_eq!((stats.corpus_tokens, stats.covered_tokens, stats.below_min_freq), (7, 5, 2));
+        assert!((stats.oov_rate() - 2.0 / 7.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_ngram_vocab_and_tokenization() {
+        let dataset: Vec<String> = ["new york is big", "new york city", "york new"].iter().map(|s| s.to_string()).collect();
+        let normalizer = TextNormalizer::default();
+        // 2 special tokens and the 6 most frequent words and n-grams.
+        let options = VocabOptions { max_vocab_size: Some(8), normalizer, ngrams: NGramRange { min: 1, max: 2 }, ..VocabOptions::default() };
+        let (vocab, _) = Tokenizer::build_vocab_with_stats(&dataset, &[PAD_TOKEN, UNK_TOKEN], &options);
+        assert_eq!(vocab.len(), 8);
+        assert!(vocab.contains_key("new york") && vocab.contains_key("york"));
+
+        let tokenizer = Tokenizer::new(vocab.clone(), 8).with_ngrams(NGramRange { min: 1, max: 2 });
+        // `york unknown` is not in the vocabulary and is skipped instead of becoming [UNK].
+        let tokens = tokenizer.tokenize("New York unknown");
+        assert_eq!(tokens, vec![vocab["new"], vocab["new york"], vocab["york"], vocab[UNK_TOKEN]]);
+
+        let path = &temp_path("tokenizer_ngrams_test.json");
+        tokenizer.save(path).unwrap();
+        let loaded = Tokenizer::load(path);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.unwrap().ngrams, NGramRange { min: 1, max: 2 });
+    }
+
+    #[test]
+    fn test_phrases_in_vocab_tokenization_and_save() {
+        let dataset: Vec<String> =
+            ["flights to New York", "New York hotels", "new york pizza", "a new car"].iter().map(|s| s.to_string()).collect();
+        let phrases = PhraseDetector { min_count: 3, scoring: PhraseScoring::Count };
+        let options = VocabOptions { phrases: Some(phrases), ..VocabOptions::default() };
+        let (vocab, _) = Tokenizer::build_vocab_with_stats(&dataset, &[PAD_TOKEN, UNK_TOKEN], &options);
+        // The phrase replaces its words: `york` never occurs on its own.
+        assert_eq!(vocab["new_york"], 2);
+        assert!(vocab.contains_key("new") && !vocab.contains_key("york"));
+
+        let tokenizer = Tokenizer::new(vocab.clone(), 8).with_phrase_merging(true);
+        let ids = tokenizer.tokenize("a New York car");
+        assert_eq!(ids, vec![vocab["a"], vocab["new_york"], vocab["car"]]);
+        assert_eq!(tokenizer.decode(&ids), "a new york car");
+        // Without merging the pair is split and `york` is unknown.
+        assert_eq!(Tokenizer::new(vocab.clone(), 8).tokenize("new york"), vec![vocab["new"], 1]);
+
+        let path = &temp_path("tokenizer_phrases_test.json");
+        tokenizer.save(path).unwrap();
+        let loaded = Tokenizer::load(path);
+        std::fs::remove_file(path).unwrap();
+        assert!(loaded.unwrap().merge_phrases);
+    }
+
+    #[test]
+    fn test_token_rules_in_vocab_tokenization_and_save() {
+        let dataset = vec!["Loving #RustLang, ask @ferris".to_string(), "#rustlang rocks".to_string()];
+        let options = VocabOptions { token_rules: TokenRules::social(), ..VocabOptions::default() };
+        let (vocab, _) = Tokenizer::build_vocab_with_stats(&dataset, &[PAD_TOKEN, UNK_TOKEN], &options);
+        assert_eq!(vocab["#rustlang"], 2);
+        assert!(vocab.contains_key("@ferris") && !vocab.contains_key("rustlang"));
+
+        let tokenizer = Tokenizer::new(vocab.clone(), 8).with_token_rules(TokenRules::social());
+        assert_eq!(tokenizer.tokenize("#RUSTLANG"), vec![vocab["#rustlang"]]);
+        // Without the rules the hashtag loses its `#` and is unknown.
+        assert_eq!(Tokenizer::new(vocab, 8).tokenize("#RUSTLANG"), vec![1]);
+
+        let path = &temp_path("token_rules_tokenizer.json");
+        tokenizer.save(path).unwrap();
+        let loaded = Tokenizer::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.token_rules, tokenizer.token_rules);
+        assert_eq!(loaded.tokenize("ask @Ferris"), tokenizer.tokenize("ask @Ferris"));
+    }
+    #[test]
+    fn test_registered_special_tokens_are_never_split() {
+        let dataset = vec!["hello world".to_string(), "hello rust".to_string()];
+        let vocab = build_vocab(&dataset, &[PAD_TOKEN, UNK_TOKEN], None);
+        let mut tokenizer = Tokenizer::new(vocab, 8);
+        let lang = tokenizer.register_special_token("<lang:de>").unwrap();
+        let mask = tokenizer.register_special_token(MASK_TOKEN).unwrap();
+        assert_eq!((lang, mask), (5, 6));
+        assert_eq!(tokenizer.register_special_token("<lang:de>").unwrap(), lang);
+
+        // Not lowercased or stripped of punctuation, even inside a word.
+        let hello = tokenizer.vocab["hello"];
+        assert_eq!(tokenizer.tokenize("<lang:de>Hello [MASK]!"), vec![lang, hello, mask]);
+        // Matching is exact; a differently cased tag is ordinary text.
+        assert_eq!(tokenizer.tokenize("<LANG:DE> hello"), vec![1, hello]);
+        assert_eq!(tokenizer.decode_with(&[lang, hello, mask], true), "hello");
+
+        let path = &temp_path("tokenizer_registered_special_test.json");
+        tokenizer.save(path).unwrap();
+        let loaded = Tokenizer::load(path);
+        std::fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.special_tokens, tokenizer.special_tokens);
+        assert_eq!(loaded.tokenize("<lang:de>Hello [MASK]!"), vec![lang, hello, mask]);
+    }
+
+    #[test]
+    fn test_task_prefix_tokens() {
+        let dataset = vec!["hello world".to_string(), "hello rust".to_string()];
+        let vocab = build_vocab(&dataset, &[PAD_TOKEN, UNK_TOKEN, SEP_TOKEN], None);
+        let mut tokenizer = Tokenizer::new(vocab, 3).with_truncation(Truncation::Tail);
+        let task = tokenizer.register_task("sentiment", "[TASK1]").unwrap();
+        assert!(tokenizer.register_task("topic", "[TASK1]").is_err());
+        assert!(tokenizer.for_task("topic").is_err());
+        let (hello, world, rust, pad) = (tokenizer.vocab["hello"], tokenizer.vocab["world"], tokenizer.vocab["rust"], tokenizer.vocab[PAD_TOKEN]);
+
+        // Without an active task nothing is injected.
+        assert_eq!(tokenizer.tokenize("hello world"), vec![hello, world]);
+        let sentiment = tokenizer.for_task("sentiment").unwrap();
+        assert_eq!(sentiment.tokenize("hello world"), vec![task, hello, world]);
+        assert_eq!(sentiment.tokenize_and_pad_batch(&["world".to_string()]), vec![vec![task, world, pad]]);
+        // Truncation from the head keeps the prefix.
+        assert_eq!(sentiment.pad_sequence(sentiment.tokenize("hello world rust")), vec![task, world, rust]);
+        assert_eq!(sentiment.word_ids("hello world rust"), vec![None, Some(1), Some(2)]);
+        // Only the first sentence of a pair is prefixed.
+        let sep = tokenizer.vocab[SEP_TOKEN];
+        let pairs = Tokenizer { max_seq_length: 8, ..sentiment.clone() };
+        assert_eq!(pairs.encode_pair("hello", "rust"), vec![task, hello, sep, rust, sep, pad, pad, pad]);
+
+        let path = &temp_path("tokenizer_task_prefix_test.json");
+        sentiment.save(path).unwrap();
+        let loaded = Tokenizer::load(path);
+        std::fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.tokenize("hello"), vec![hello]);
+        assert_eq!(loaded.for_task("sentiment").unwrap().tokenize("hello"), vec![task, hello]);
+    }
+    #[test]
+    fn test_tokenize_with_offsets() {
+        let text = "Don't go to NEW York, Zürich!";
+        let spans_of = |tokenizer: &Tokenizer| -> Vec<&str> {
+            let tokens = tokenizer.tokenize_with_word_ids(text);
+            assert_eq!(tokens.iter().map(|&(id, _, _)| id).collect::<Vec<_>>(), tokenizer.tokenize(text));
+            tokens.iter().map(|&(_, (start, end), _)| &text[start..end]).collect()
+        };
+
+        let dataset = vec!["dont go to new york zürich".to_string()];
+        let options = VocabOptions { ngrams: NGramRange { min: 1, max: 2 }, ..VocabOptions::default() };
+        let (vocab, _) = Tokenizer::build_vocab_with_stats(&dataset, &[PAD_TOKEN, UNK_TOKEN], &options);
+        let words = Tokenizer::new(vocab.clone(), 16);
+        assert_eq!(spans_of(&words), vec!["Don't", "go", "to", "NEW", "York", "Zürich"]);
+        let ngrams = Tokenizer::new(vocab, 16).with_ngrams(NGramRange { min: 1, max: 2 });
+        assert_eq!(spans_of(&ngrams)[..4], ["Don't", "Don't go", "go", "go to"]);
+
+        let mut vocab: HashMap<String, usize> = [PAD_TOKEN, UNK_TOKEN, "don", "'", "t", "go", "new", "yo", "##rk", ",", "z", "##ur", "##ich", "!"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect();
+        vocab.insert("<lang:de>".to_string(), vocab.len());
+        let mut wordpiece = Tokenizer::new(vocab, 16).with_normalizer(TextNormalizer { fold_accents: true, ..TextNormalizer::default() });
+        wordpiece.segmentation = Segmentation::WordPiece;
+        wordpiece.register_special_token("<lang:de>").unwrap();
+        assert_eq!(spans_of(&wordpiece), vec!["Don", "'", "t", "go", "to", "NEW", "Yo", "rk", ",", "Z", "ür", "ich", "!"]);
+
+        let tokens = wordpiece.tokenize_with_word_ids("<lang:de>Go");
+        assert_eq!(tokens, vec![(wordpiece.vocab["<lang:de>"], (0, 9), None), (wordpiece.vocab["go"], (9, 11), Some(0))]);
+
+        // Pieces share the index of their word; special tokens belong to none.
+        let word_of = |text: &str| -> Vec<Option<usize>> { wordpiece.tokenize_with_word_ids(text).into_iter().map(|(_, _, word)| word).collect() };
+        assert_eq!(word_of("New York, Zürich"), vec![Some(0), Some(1), Some(1), Some(2), Some(3), Some(3), Some(3)]);
+        assert_eq!(word_of("go <lang:de>York"), vec![Some(0), None, Some(1), Some(1)]);
+
+        wordpiece.max_seq_length = 4;
+        assert_eq!(wordpiece.word_ids("go Zürich"), vec![Some(0), Some(1), Some(1), Some(1)]);
+        assert_eq!(wordpiece.word_ids("go York"), vec![Some(0), Some(1), Some(1), None]);
+        let ngrams = ngrams.tokenize_with_word_ids("go to new");
+        assert_eq!(ngrams.iter().map(|&(_, _, word)| word).collect::<Vec<_>>(), vec![Some(0), Some(0), Some(1), Some(1), Some(2)]);
+    }
 }
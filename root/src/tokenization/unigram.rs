@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Serialize, Deserialize};
+
+use crate::configurration::config::UNK_TOKEN;
+use crate::tokenization::tokenizer::Tokenizer;
+
+/// Marks the start of a word, as in SentencePiece, so `▁play` and `play` are different pieces.
+pub const WORD_BOUNDARY: char = '\u{2581}';
+
+/// A unigram language model over subword pieces.
+///
+/// Every piece has a log probability; a word is segmented into the sequence of
+/// pieces with the highest total log probability (Viterbi).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnigramModel {
+    pub pieces: HashMap<String, f64>,
+    pub max_piece_length: usize,
+}
+
+impl UnigramModel {
+    /// Segments one word into its most likely pieces. The first piece carries `▁`.
+    /// Words containing characters without a piece become `[UNK]`.
+    pub fn segment(&self, word: &str) -> Vec<String> {
+        let chars: Vec<char> = std::iter::once(WORD_BOUNDARY).chain(word.chars()).collect();
+        match viterbi(&chars, &self.pieces, self.max_piece_length, None) {
+            Some((pieces, _)) => pieces,
+            None => vec![UNK_TOKEN.to_string()],
+        }
+    }
+
+    /// Builds a vocabulary with the special tokens first, then the pieces from most to least likely.
+    pub fn vocab(&self, special_tokens: &[&str]) -> HashMap<String, usize> {
+        let mut pieces: Vec<(&String, &f64)> = self.pieces.iter().collect();
+        pieces.sort_by(|a, b| b.1.total_cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+        special_tokens
+            .iter()
+            .map(|token| token.to_string())
+            .chain(pieces.into_iter().map(|(piece, _)| piece.clone()))
+            .enumerate()
+            .map(|(id, token)| (token, id))
+            .collect()
+    }
+}
+
+/// Learns a unigram model with EM and iterative pruning, following SentencePiece:
+///
+/// 1. Seed the vocabulary with frequent substrings of the corpus words.
+/// 2. Re-estimate piece probabilities with EM over all segmentations of each word.
+/// 3. Drop the pieces whose removal costs the least likelihood, keeping `shrink_factor`
+///    of them, until `vocab_size` pieces remain. Single characters are never dropped,
+///    so every word seen in training stays segmentable.
+pub struct UnigramTrainer {
+    pub vocab_size: usize,
+    pub max_piece_length: usize,
+    pub em_iterations: usize,
+    pub shrink_factor: f64,
+    /// Size of the seed vocabulary relative to `vocab_size`.
+    pub seed_factor: usize,
+}
+
+impl UnigramTrainer {
+    pub fn new(vocab_size: usize) -> Self {
+        UnigramTrainer { vocab_size, max_piece_length: 8, em_iterations: 2, shrink_factor: 0.75, seed_factor: 10 }
+    }
+
+    /// Trains a unigram model on raw texts.
+    ///
+    /// # Arguments
+    /// * `texts` - Training corpus; words are split like `Tokenizer::preprocess_text`.
+    ///
+    /// # Returns
+    /// * A model with at most `vocab_size` pieces, or more if the corpus has more distinct characters.
+    pub fn train(&self, texts: &[String]) -> UnigramModel {
+        let mut word_counts: HashMap<Vec<char>, f64> = HashMap::new();
+        for text in texts {
+            for word in Tokenizer::preprocess_text(text) {
+                let chars = std::iter::once(WORD_BOUNDARY).chain(word.chars()).collect();
+                *word_counts.entry(chars).or_insert(0.0) += 1.0;
+            }
+        }
+        let words: Vec<(Vec<char>, f64)> = word_counts.into_iter().collect();
+
+        let mut pieces = self.seed_pieces(&words);
+        loop {
+            for _ in 0..self.em_iterations {
+                pieces = self.em_step(&words, &pieces);
+            }
+            if pieces.len() <= self.vocab_size {
+                break;
+            }
+            let pruned = self.prune(&words, &pieces);
+            if pruned.len() == pieces.len() {
+                break;
+            }
+            pieces = pruned;
+        }
+
+        UnigramModel { pieces, max_piece_length: self.max_piece_length }
+    }
+
+    /// All characters plus the most frequent longer substrings, weighted by frequency times length.
+    fn seed_pieces(&self, words: &[(Vec<char>, f64)]) -> HashMap<String, f64> {
+        let mut substring_counts: HashMap<String, f64> = HashMap::new();
+        for (chars, count) in words {
+            for start in 0..chars.len() {
+                for end in start + 1..=(start + self.max_piece_length).min(chars.len()) {
+                    let piece: String = chars[start..end].iter().collect();
+                    *substring_counts.entry(piece).or_insert(0.0) += count;
+                }
+            }
+        }
+
+        let (chars, mut longer): (Vec<_>, Vec<_>) =
+            substring_counts.into_iter().partition(|(piece, _)| piece.chars().count() == 1);
+        longer.retain(|(piece, count)| *count > 1.0 && piece != &WORD_BOUNDARY.to_string());
+        longer.sort_by(|a, b| {
+            let score = |(piece, count): &(String, f64)| count * piece.chars().count() as f64;
+            score(b).total_cmp(&score(a)).then_with(|| a.0.cmp(&b.0))
+        });
+        longer.truncate(self.vocab_size * self.seed_factor);
+
+        let seeds: Vec<(String, f64)> = chars.into_iter().chain(longer).collect();
+        let total: f64 = seeds.iter().map(|(_, count)| count).sum();
+        seeds.into_iter().map(|(piece, count)| (piece, (count / total).ln())).collect()
+    }
+
+    /// One EM iteration: expected piece counts from forward-backward, then renormalized log probabilities.
+    fn em_step(&self, words: &[(Vec<char>, f64)], pieces: &HashMap<String, f64>) -> HashMap<String, f64> {
+        let mut expected: HashMap<String, f64> = HashMap::new();
+
+        for (chars, count) in words {
+            let n = chars.len();
+            let mut alpha = vec![f64::NEG_INFINITY; n + 1];
+            let mut beta = vec![f64::NEG_INFINITY; n + 1];
+            alpha[0] = 0.0;
+            beta[n] = 0.0;
+
+            for end in 1..=n {
+                for start in end.saturating_sub(self.max_piece_length)..end {
+                    if let Some(&logp) = pieces.get(&chars[start..end].iter().collect::<String>()) {
+                        alpha[end] = log_add(alpha[end], alpha[start] + logp);
+                    }
+                }
+            }
+            for start in (0..n).rev() {
+                for end in start + 1..=(start + self.max_piece_length).min(n) {
+                    if let Some(&logp) = pieces.get(&chars[start..end].iter().collect::<String>()) {
+                        beta[start] = log_add(beta[start], beta[end] + logp);
+                    }
+                }
+            }
+            if alpha[n] == f64::NEG_INFINITY {
+                continue;
+            }
+
+            for start in 0..n {
+                for end in start + 1..=(start + self.max_piece_length).min(n) {
+                    let piece: String = chars[start..end].iter().collect();
+                    if let Some(&logp) = pieces.get(&piece) {
+                        let posterior = (alpha[start] + logp + beta[end] - alpha[n]).exp();
+                        *expected.entry(piece).or_insert(0.0) += count * posterior;
+                    }
+                }
+            }
+        }
+
+        // Pieces that are never used disappear, except single characters.
+        let kept: Vec<(String, f64)> = pieces
+            .keys()
+            .filter_map(|piece| {
+                let count = expected.get(piece).copied().unwrap_or(0.0);
+                if count > 1e-6 {
+                    Some((piece.clone(), count))
+                } else if piece.chars().count() == 1 {
+                    Some((piece.clone(), 1e-6))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let total: f64 = kept.iter().map(|(_, count)| count).sum();
+        kept.into_iter().map(|(piece, count)| (piece, (count / total).ln())).collect()
+    }
+
+    /// Removes the pieces whose loss of likelihood is smallest when their occurrences
+    /// are re-segmented with the remaining pieces.
+    fn prune(&self, words: &[(Vec<char>, f64)], pieces: &HashMap<String, f64>) -> HashMap<String, f64> {
+        let mut usage: HashMap<String, f64> = HashMap::new();
+        for (chars, count) in words {
+            if let Some((segmentation, _)) = viterbi(chars, pieces, self.max_piece_length, None) {
+                for piece in segmentation {
+                    *usage.entry(piece).or_insert(0.0) += count;
+                }
+            }
+        }
+
+        let mut candidates: Vec<(String, f64)> = pieces
+            .iter()
+            .filter(|(piece, _)| piece.chars().count() > 1)
+            .map(|(piece, &logp)| {
+                let count = usage.get(piece).copied().unwrap_or(0.0);
+                let chars: Vec<char> = piece.chars().collect();
+                let alternative = viterbi(&chars, pieces, self.max_piece_length, Some(piece))
+                    .map(|(_, score)| score)
+                    .unwrap_or(f64::NEG_INFINITY);
+                (piece.clone(), count * (logp - alternative))
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let num_chars = pieces.len() - candidates.len();
+        let target = ((pieces.len() as f64 * self.shrink_factor) as usize).max(self.vocab_size);
+        candidates.truncate(target.saturating_sub(num_chars));
+
+        let kept: HashSet<&String> = candidates.iter().map(|(piece, _)| piece).collect();
+        pieces
+            .iter()
+            .filter(|(piece, _)| piece.chars().count() == 1 || kept.contains(piece))
+            .map(|(piece, &logp)| (piece.clone(), logp))
+            .collect()
+    }
+}
+
+/// Best segmentation of `chars` and its log probability, optionally without one piece.
+fn viterbi(
+    chars: &[char],
+    pieces: &HashMap<String, f64>,
+    max_piece_length: usize,
+    excluded: Option<&String>,
+) -> Option<(Vec<String>, f64)> {
+    let n = chars.len();
+    let mut best = vec![f64::NEG_INFINITY; n + 1];
+    let mut back = vec![0usize; n + 1];
+    best[0] = 0.0;
+
+    for end in 1..=n {
+        for start in end.saturating_sub(max_piece_length)..end {
+            if best[start] == f64::NEG_INFINITY {
+                continue;
+            }
+            let piece: String = chars[start..end].iter().collect();
+            if Some(&piece) == excluded {
+                continue;
+            }
+            if let Some(&logp) = pieces.get(&piece) {
+                if best[start] + logp > best[end] {
+                    best[end] = best[start] + logp;
+                    back[end] = start;
+                }
+            }
+        }
+    }
+    if best[n] == f64::NEG_INFINITY {
+        return None;
+    }
+
+    let mut segmentation = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        let start = back[end];
+        segmentation.push(chars[start..end].iter().collect());
+        end = start;
+    }
+    segmentation.reverse();
+    Some((segmentation, best[n]))
+}
+
+fn log_add(a: f64, b: f64) -> f64 {
+    if a == f64::NEG_INFINITY {
+        return b;
+    }
+    if b == f64::NEG_INFINITY {
+        return a;
+    }
+    let max = a.max(b);
+    max + ((a - max).exp() + (b - max).exp()).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus() -> Vec<String> {
+        [
+            "the player is playing the game",
+            "players played games and plays",
+            "a great game with great players",
+            "the games were played by the player",
+        ]
+        .iter()
+        .map(|text| text.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn test_trained_vocab_size() {
+        let model = UnigramTrainer::new(40).train(&corpus());
+        let num_chars = model.pieces.keys().filter(|piece| piece.chars().count() == 1).count();
+
+        assert!(model.pieces.len() <= 40.max(num_chars));
+        assert!(model.pieces.values().all(|logp| *logp <= 0.0));
+    }
+
+    #[test]
+    fn test_segmentation_reconstructs_word() {
+        let model = UnigramTrainer::new(40).train(&corpus());
+
+        for word in ["playing", "games", "gameplay"] {
+            let pieces = model.segment(word);
+            assert!(pieces[0].starts_with(WORD_BOUNDARY));
+            assert_eq!(pieces.concat(), format!("{}{}", WORD_BOUNDARY, word));
+        }
+        assert_eq!(model.segment("zzz"), vec![UNK_TOKEN]);
+    }
+
+    #[test]
+    fn test_frequent_word_is_single_piece() {
+        let model = UnigramTrainer::new(60).train(&corpus());
+        assert_eq!(model.segment("the"), vec![format!("{}the", WORD_BOUNDARY)]);
+    }
+
+    #[test]
+    fn test_tokenizer_interface() {
+        let model = UnigramTrainer::new(40).train(&corpus());
+        let tokenizer = Tokenizer::from_unigram(model.clone(), &["[PAD]", UNK_TOKEN], 12);
+
+        let batch = tokenizer.tokenize_and_pad_batch(&["Players playing!".to_string(), "zzz".to_string()]);
+        let expected: Vec<usize> = ["players", "playing"].iter().flat_map(|word| model.segment(word)).map(|piece| tokenizer.vocab[&piece]).collect();
+
+        assert_eq!(&batch[0][..expected.len()], &expected[..]);
+        assert!(batch[0][expected.len()..].iter().all(|&id| id == 0));
+        assert_eq!(batch[1][0], tokenizer.vocab[UNK_TOKEN]);
+    }
+
+    #[test]
+    fn test_log_add() {
+        assert!((log_add(0.5f64.ln(), 0.25f64.ln()) - 0.75f64.ln()).abs() < 1e-12);
+        assert_eq!(log_add(f64::NEG_INFINITY, -1.0), -1.0);
+    }
+}
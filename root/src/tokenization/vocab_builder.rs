@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error};
+
+use crate::tokenization::ngrams::NGramRange;
+use crate::tokenization::normalization::TextNormalizer;
+use crate::tokenization::token_rules::TokenRules;
+use crate::tokenization::tokenizer::Tokenizer;
+
+/// Builds a word vocabulary from texts that are fed one at a time, so corpora that do not
+/// fit in memory can be read from a file line by line.
+///
+/// At most `max_tracked_words` distinct words are counted. When a new word would exceed
+/// this, the less frequent half of the table is dropped. Frequent words survive every
+/// pruning, so the top of the ranking matches `Tokenizer::build_vocab_with_stats` as long
+/// as `max_tracked_words` is well above the vocabulary size; the counts of words that were
+/// dropped and seen again are underestimated by at most `count_error_bound`.
+pub struct StreamingVocabBuilder {
+    normalizer: TextNormalizer,
+    ngrams: NGramRange,
+    token_rules: TokenRules,
+    max_tracked_words: usize,
+    counts: HashMap<String, usize>,
+    texts_read: usize,
+    prunings: usize,
+    count_error_bound: usize,
+}
+
+impl StreamingVocabBuilder {
+    /// Creates an empty builder.
+    ///
+    /// # Arguments
+    /// * `normalizer` - Splits texts into words, as `VocabOptions::normalizer` does.
+    /// * `max_tracked_words` - Maximum number of distinct words counted at once (at least 2).
+    ///
+    /// # Returns
+    /// A new instance of `StreamingVocabBuilder`.
+    pub fn new(normalizer: TextNormalizer, max_tracked_words: usize) -> Self {
+        StreamingVocabBuilder {
+            normalizer,
+            ngrams: NGramRange::UNIGRAMS,
+            token_rules: TokenRules::default(),
+            max_tracked_words: max_tracked_words.max(2),
+            counts: HashMap::new(),
+            texts_read: 0,
+            prunings: 0,
+            count_error_bound: 0,
+        }
+    }
+
+    /// Also counts the word n-grams of `ngrams`, as `VocabOptions::ngrams` does.
+    /// N-grams share the `max_tracked_words` table with the words.
+    pub fn with_ngrams(mut self, ngrams: NGramRange) -> Self {
+        self.ngrams = ngrams;
+        self
+    }
+
+    /// Keeps the matches of `token_rules` as single words, as `Tokenizer::with_token_rules`.
+    pub fn with_token_rules(mut self, token_rules: TokenRules) -> Self {
+        self.token_rules = token_rules;
+        self
+    }
+
+    /// Counts the words of one text.
+    pub fn add_text(&mut self, text: &str) {
+        for word in self.ngrams.expand(&self.token_rules.words(text, &self.normalizer)) {
+            if let Some(count) = self.counts.get_mut(&word) {
+                *count += 1;
+                continue;
+            }
+            if self.counts.len() >= self.max_tracked_words {
+                self.prune();
+            }
+            self.counts.insert(word, 1);
+        }
+        self.texts_read += 1;
+    }
+
+    /// Reads one text per line, holding a single line in memory at a time.
+    pub fn add_lines<R: BufRead>(&mut self, mut reader: R) -> Result<(), Error> {
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            self.add_text(&line);
+            line.clear();
+        }
+        Ok(())
+    }
+
+    /// Reads a plain-text corpus with one text per line.
+    pub fn add_file(&mut self, file_path: &str) -> Result<(), Error> {
+        self.add_lines(BufReader::new(File::open(file_path)?))
+    }
+
+    /// Drops the less frequent half of the counted words.
+    fn prune(&mut self) {
+        let mut counts: Vec<usize> = self.counts.values().copied().collect();
+        let middle = counts.len() / 2;
+        let (_, &mut threshold, _) = counts.select_nth_unstable_by(middle, |a, b| b.cmp(a));
+        self.counts.retain(|_, count| *count > threshold);
+        self.prunings += 1;
+        self.count_error_bound += threshold;
+    }
+
+    pub fn texts_read(&self) -> usize {
+        self.texts_read
+    }
+
+    /// Number of times the word table was pruned; 0 means every count is exact.
+    pub fn prunings(&self) -> usize {
+        self.prunings
+    }
+
+    /// Largest number of occurrences that may be missing from a word's count.
+    pub fn count_error_bound(&self) -> usize {
+        self.count_error_bound
+    }
+
+    /// Builds the vocabulary from the counted words.
+    ///
+    /// # Arguments
+    /// * `special_tokens` - Tokens that receive the first ids.
+    /// * `max_vocab_size` - Maximum vocabulary size, including the special tokens.
+    ///
+    /// # Returns
+    /// * The vocabulary, ranked as in `Tokenizer::build_vocab_with_stats`, and the counts of its words.
+    pub fn finish(
+        self,
+        special_tokens: &[&str],
+        max_vocab_size: Option<usize>,
+    ) -> (HashMap<String, usize>, HashMap<String, usize>) {
+        Tokenizer::rank_words(self.counts, special_tokens, max_vocab_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus() -> Vec<String> {
+        // Frequent words repeated across lines, with a long tail of words seen once.
+        (0..200)
+            .map(|i| match i % 4 {
+                0 => format!("the cat sat rare{}", i),
+                1 => format!("the dog sat rare{}", i),
+                2 => format!("the cat ran rare{}", i),
+                _ => format!("a cat rare{}", i),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bounded_counts_keep_frequent_words() {
+        let specials = &["[PAD]", "[UNK]"];
+        // A table that never fills up gives the exact counts.
+        let mut exact = StreamingVocabBuilder::new(TextNormalizer::default(), usize::MAX);
+        let mut builder = StreamingVocabBuilder::new(TextNormalizer::default(), 20);
+        for text in corpus() {
+            exact.add_text(&text);
+            builder.add_text(&text);
+        }
+        assert_eq!(exact.prunings(), 0);
+        let (expected, expected_counts) = exact.finish(specials, Some(8));
+        assert_eq!(builder.texts_read(), 200);
+        assert!(builder.prunings() > 0);
+        // Every pruning drops words seen once.
+        assert_eq!(builder.count_error_bound(), builder.prunings());
+        let (vocab, counts) = builder.finish(specials, Some(8));
+
+        assert_eq!(vocab.len(), 8);
+        assert_eq!(vocab["[PAD]"], 0);
+        let mut words: Vec<&String> = vocab.keys().filter(|word| word.as_str() != "[PAD]" && word.as_str() != "[UNK]").collect();
+        words.sort();
+        assert_eq!(words, vec!["a", "cat", "dog", "ran", "sat", "the"]);
+        for word in words {
+            assert!(expected.contains_key(word));
+            assert_eq!(counts[word], expected_counts[word]);
+        }
+    }
+
+    #[test]
+    fn test_reads_lines_incrementally() {
+        let text = "Hello world\nhello again\n\nWORLD";
+        let mut builder = StreamingVocabBuilder::new(TextNormalizer::default(), 100);
+        builder.add_lines(text.as_bytes()).unwrap();
+
+        let (vocab, counts) = builder.finish(&["[PAD]"], None);
+        assert_eq!(vocab.len(), 4);
+        assert_eq!(counts["hello"], 2);
+        assert_eq!(counts["world"], 2);
+        assert_eq!(counts["again"], 1);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+/// How much of a corpus a vocabulary covers, returned by `Tokenizer::build_vocab_with_stats`.
+///
+/// Coverage counts token occurrences, not distinct tokens: a vocabulary of the 1000 most
+/// frequent words usually covers far more than 1000 / `unique_tokens` of the running text.
+/// Occurrences outside the vocabulary become `[UNK]`, or byte tokens with byte fallback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VocabStats {
+    /// Token occurrences in the corpus.
+    pub corpus_tokens: usize,
+    /// Distinct tokens in the corpus.
+    pub unique_tokens: usize,
+    pub min_freq: usize,
+    /// Distinct tokens seen fewer than `min_freq` times, which are never added.
+    pub below_min_freq: usize,
+    /// Tokens of the built vocabulary, without the special tokens.
+    pub vocab_tokens: usize,
+    /// Corpus occurrences of the vocabulary's tokens.
+    pub covered_tokens: usize,
+    special_tokens: usize,
+    /// Counts of the tokens with at least `min_freq` occurrences, most frequent first.
+    eligible_counts: Vec<usize>,
+}
+
+impl VocabStats {
+    /// Computes the statistics of the vocabulary that keeps the most frequent tokens.
+    ///
+    /// # Arguments
+    /// * `token_counts` - Occurrences of every distinct corpus token.
+    /// * `special_tokens` - Number of special tokens, which count towards `max_vocab_size`.
+    /// * `max_vocab_size` - Vocabulary size limit, including the special tokens.
+    /// * `min_freq` - Minimum number of occurrences of a vocabulary token.
+    pub fn new(token_counts: &HashMap<String, usize>, special_tokens: usize, max_vocab_size: Option<usize>, min_freq: usize) -> Self {
+        let mut eligible_counts: Vec<usize> = token_counts.values().copied().filter(|&count| count >= min_freq).collect();
+        eligible_counts.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut stats = VocabStats {
+            corpus_tokens: token_counts.values().sum(),
+            unique_tokens: token_counts.len(),
+            min_freq,
+            below_min_freq: token_counts.len() - eligible_counts.len(),
+            vocab_tokens: 0,
+            covered_tokens: 0,
+            special_tokens,
+            eligible_counts,
+        };
+        let vocab_size = max_vocab_size.unwrap_or(usize::MAX);
+        stats.vocab_tokens = stats.kept_tokens(vocab_size);
+        stats.covered_tokens = stats.eligible_counts[..stats.vocab_tokens].iter().sum();
+        stats
+    }
+
+    /// Number of non-special tokens that fit in a vocabulary of `vocab_size`.
+    fn kept_tokens(&self, vocab_size: usize) -> usize {
+        vocab_size.saturating_sub(self.special_tokens).min(self.eligible_counts.len())
+    }
+
+    /// Fraction of corpus token occurrences that are in the built vocabulary.
+    pub fn coverage(&self) -> f64 {
+        self.fraction(self.covered_tokens)
+    }
+
+    /// Fraction of corpus token occurrences that are out of vocabulary.
+    pub fn oov_rate(&self) -> f64 {
+        1.0 - self.coverage()
+    }
+
+    /// Smallest vocabulary size (including special tokens) that covers `target` of the
+    /// corpus, or `None` when `min_freq` excludes too many tokens to reach it.
+    pub fn size_for_coverage(&self, target: f64) -> Option<usize> {
+        let needed = target * self.corpus_tokens as f64;
+        let mut covered = 0;
+        for (kept, count) in self.eligible_counts.iter().enumerate() {
+            if covered as f64 >= needed {
+                return Some(kept + self.special_tokens);
+            }
+            covered += count;
+        }
+        (covered as f64 >= needed).then_some(self.eligible_counts.len() + self.special_tokens)
+    }
+
+    /// An empty corpus has nothing out of vocabulary.
+    fn fraction(&self, tokens: usize) -> f64 {
+        if self.corpus_tokens == 0 { 1.0 } else { tokens as f64 / self.corpus_tokens as f64 }
+    }
+
+    /// Coverage of the built vocabulary and the sizes needed for common coverage targets.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "Vocabulary: {} tokens + {} special, covering {:.2}% of {} corpus tokens (OOV rate {:.2}%)",
+            self.vocab_tokens,
+            self.special_tokens,
+            self.coverage() * 100.0,
+            self.corpus_tokens,
+            self.oov_rate() * 100.0
+        )];
+        lines.push(format!(
+            "{} of {} distinct tokens occur fewer than {} times",
+            self.below_min_freq, self.unique_tokens, self.min_freq
+        ));
+        for target in [0.9, 0.95, 0.99] {
+            let size = self.size_for_coverage(target).map_or("unreachable".to_string(), |size| size.to_string());
+            lines.push(format!("  {:.0}% coverage: max_vocab_size {}", target * 100.0, size));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage_and_oov_rate() {
+        let counts: HashMap<String, usize> =
+            [("a", 6), ("b", 2), ("c", 1), ("d", 1)].iter().map(|&(token, count)| (token.to_string(), count)).collect();
+        let stats = VocabStats::new(&counts, 2, Some(3), 2);
+
+        assert_eq!(stats.corpus_tokens, 10);
+        assert_eq!(stats.below_min_freq, 2);
+        assert_eq!(stats.vocab_tokens, 1);
+        assert!((stats.coverage() - 0.6).abs() < 1e-12);
+        let larger = VocabStats::new(&counts, 2, Some(4), 2);
+        assert!((larger.oov_rate() - 0.2).abs() < 1e-12);
+        // `c` and `d` are below `min_freq`, so a larger vocabulary does not help.
+        assert_eq!(VocabStats::new(&counts, 2, Some(100), 2).coverage(), larger.coverage());
+
+        assert_eq!(stats.size_for_coverage(0.5), Some(3));
+        assert_eq!(stats.size_for_coverage(0.8), Some(4));
+        assert_eq!(stats.size_for_coverage(0.9), None);
+        assert!(stats.summary().contains("90% coverage: max_vocab_size unreachable"));
+    }
+}
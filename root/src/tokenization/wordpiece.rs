@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::configurration::config::UNK_TOKEN;
+
+/// Prefix of pieces that continue a word, e.g. `##able` in `un ##aff ##able`.
+pub const CONTINUATION_PREFIX: &str = "##";
+
+/// Words longer than this many characters map to `[UNK]`, as in BERT.
+pub const MAX_WORD_CHARS: usize = 100;
+
+/// WordPiece tokenizer compatible with BERT-style `vocab.txt` files.
+///
+/// Text is lowercased and split on whitespace and punctuation; every word is then
+/// split into the longest vocabulary pieces from left to right. A word that cannot
+/// be covered completely by vocabulary pieces becomes a single `[UNK]`.
+pub struct WordPieceTokenizer<'a> {
+    vocab: &'a HashMap<String, usize>,
+    continuation_prefix: &'a str,
+    max_word_chars: usize,
+}
+
+impl<'a> WordPieceTokenizer<'a> {
+    pub fn new(vocab: &'a HashMap<String, usize>) -> Self {
+        WordPieceTokenizer { vocab, continuation_prefix: CONTINUATION_PREFIX, max_word_chars: MAX_WORD_CHARS }
+    }
+
+    /// Uses a different continuation prefix and word length limit than BERT's `##` and 100,
+    /// e.g. as configured in an imported tokenizer.
+    pub fn with_options(mut self, continuation_prefix: &'a str, max_word_chars: usize) -> Self {
+        self.continuation_prefix = continuation_prefix;
+        self.max_word_chars = max_word_chars;
+        self
+    }
+
+    /// Reads a plain-text vocabulary with one token per line; a token's id is its line number.
+    pub fn load_vocab(file_path: &str) -> Result<HashMap<String, usize>, std::io::Error> {
+        let data = std::fs::read_to_string(file_path)?;
+        Ok(data
+            .lines()
+            .enumerate()
+            .map(|(id, token)| (token.trim_end().to_string(), id))
+            .collect())
+    }
+
+    /// Lowercases the text and splits it on whitespace, keeping every punctuation
+    /// character as a separate word.
+    pub fn basic_tokenize(text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut current = String::new();
+
+        for c in text.to_lowercase().chars() {
+            if c.is_whitespace() || is_punctuation(c) {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+                if !c.is_whitespace() {
+                    words.push(c.to_string());
+                }
+            } else {
+                current.push(c);
+            }
+        }
+        if !current.is_empty() {
+            words.push(current);
+        }
+        words
+    }
+
+    /// Splits a single word into vocabulary pieces using greedy longest-match-first.
+    ///
+    /// # Returns
+    /// * The pieces, with the continuation prefix on every piece but the first, or `[UNK]` if the word
+    ///   cannot be covered by vocabulary pieces.
+    pub fn word_pieces(&self, word: &str) -> Vec<String> {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() > self.max_word_chars {
+            return vec![UNK_TOKEN.to_string()];
+        }
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let mut end = chars.len();
+            let mut piece = None;
+            while start < end {
+                let substring: String = chars[start..end].iter().collect();
+                let candidate = if start > 0 { format!("{}{}", self.continuation_prefix, substring) } else { substring };
+                if self.vocab.contains_key(&candidate) {
+                    piece = Some(candidate);
+                    break;
+                }
+                end -= 1;
+            }
+
+            match piece {
+                Some(piece) => pieces.push(piece),
+                None => return vec![UNK_TOKEN.to_string()],
+            }
+            start = end;
+        }
+        pieces
+    }
+}
+
+/// BERT treats every non-alphanumeric ASCII symbol and Unicode punctuation as punctuation.
+pub fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || (!c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::temp_path;
+    use crate::tokenization::tokenizer::Tokenizer;
+
+    const TOKENS: [&str; 12] = ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "un", "##aff", "##able", "the", "movie", "##s", ",", "!"];
+
+    fn vocab() -> HashMap<String, usize> {
+        TOKENS
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id))
+            .collect()
+    }
+
+    #[test]
+    fn test_greedy_longest_match() {
+        let vocab = vocab();
+        let tokenizer = WordPieceTokenizer::new(&vocab);
+
+        assert_eq!(tokenizer.word_pieces("unaffable"), vec!["un", "##aff", "##able"]);
+        assert_eq!(tokenizer.word_pieces("movies"), vec!["movie", "##s"]);
+        assert_eq!(tokenizer.word_pieces("unknown"), vec![UNK_TOKEN]);
+    }
+
+    #[test]
+    fn test_basic_tokenize_splits_punctuation() {
+        assert_eq!(
+            WordPieceTokenizer::basic_tokenize("The Movies, unaffable!"),
+            vec!["the", "movies", ",", "unaffable", "!"]
+        );
+    }
+
+    #[test]
+    fn test_load_vocab_uses_line_numbers() {
+        let path = &temp_path("wordpiece_test_vocab.txt");
+        std::fs::write(path, "[PAD]\n[UNK]\nplay\n##ing\n").unwrap();
+        let vocab = WordPieceTokenizer::load_vocab(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(vocab.len(), 4);
+        assert_eq!(vocab["##ing"], 3);
+        assert_eq!(WordPieceTokenizer::new(&vocab).word_pieces("playing"), vec!["play", "##ing"]);
+    }
+
+    #[test]
+    fn test_tokenizer_from_vocab_file() {
+        let path = &temp_path("wordpiece_test_tokenizer_vocab.txt");
+        std::fs::write(path, TOKENS.join("\n")).unwrap();
+        let tokenizer = Tokenizer::from_wordpiece_vocab(path, 8).unwrap();
+        std::fs::write(path, "[PAD]\nplay\n").unwrap();
+        let missing_unk = Tokenizer::from_wordpiece_vocab(path, 8);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(tokenizer.tokenize("The movies, zzz!"), vec![7, 8, 9, 10, 1, 11]);
+        assert_eq!(missing_unk.err().map(|e| e.kind()), Some(std::io::ErrorKind::InvalidData));
+    }
+}
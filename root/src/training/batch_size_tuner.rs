@@ -0,0 +1,235 @@
+use crate::cross_entropy::loss::Loss;
+use crate::data_handler::data_loader::DataLoader;
+use crate::transformer::Transformer;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Training steps timed per probed batch size; the fastest counts, so a single
+/// scheduling hiccup does not end the search early.
+pub const PROBE_REPEATS: usize = 2;
+
+/// Finds the largest batch size whose training step stays within a time and memory
+/// budget. Batch sizes 1, 2, 4, ... are probed with one forward and backward pass each
+/// until a probe exceeds the budget or `max_batch_size` is reached.
+pub struct BatchSizeTuner {
+    /// Longest acceptable forward and backward pass of one batch.
+    pub max_step_time: Duration,
+    /// Largest acceptable memory growth during one step, in bytes; `None` ignores memory.
+    pub max_step_memory: Option<usize>,
+    pub max_batch_size: usize,
+    /// Resets the peak memory before every step, so `step_memory` is the step's peak growth
+    /// rather than its resident growth. Only supported on Linux.
+    pub reset_peak_memory: bool,
+}
+
+/// Measurements of one probed batch size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchSizeProbe {
+    pub batch_size: usize,
+    pub step_time: Duration,
+    /// Peak memory growth during the step in bytes, when the platform reports it.
+    pub step_memory: Option<usize>,
+    pub within_budget: bool,
+}
+
+/// Outcome of `BatchSizeTuner::tune`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchSizeTuning {
+    /// Largest probed batch size within the budget, or 1 when even that exceeds it.
+    pub batch_size: usize,
+    pub probes: Vec<BatchSizeProbe>,
+}
+
+impl BatchSizeTuning {
+    pub fn summary(&self) -> String {
+        let mut lines: Vec<String> = self
+            .probes
+            .iter()
+            .map(|probe| {
+                let memory = probe.step_memory.map_or("n/a".to_string(), |bytes| format!("{:.1} MB", bytes as f64 / 1e6));
+                format!(
+                    "  batch size {:>4}: {:>8.1} ms, {:>10} {}",
+                    probe.batch_size,
+                    probe.step_time.as_secs_f64() * 1000.0,
+                    memory,
+                    if probe.within_budget { "ok" } else { "over budget" }
+                )
+            })
+            .collect();
+        lines.push(format!("Batch size: {}", self.batch_size));
+        lines.join("\n")
+    }
+}
+
+impl BatchSizeTuner {
+    /// Creates a tuner.
+    ///
+    /// # Arguments
+    /// * `max_step_time` - Time budget of one training step.
+    /// * `max_step_memory` - Memory budget of one training step in bytes, or `None`.
+    /// * `max_batch_size` - Largest batch size probed.
+    ///
+    /// # Returns
+    /// A new instance of `BatchSizeTuner`.
+    pub fn new(max_step_time: Duration, max_step_memory: Option<usize>, max_batch_size: usize) -> Self {
+        BatchSizeTuner { max_step_time, max_step_memory, max_batch_size: max_batch_size.max(1), reset_peak_memory: true }
+    }
+
+    /// Whether to reset the process's peak memory before every probed step.
+    pub fn with_peak_memory_reset(mut self, reset_peak_memory: bool) -> Self {
+        self.reset_peak_memory = reset_peak_memory;
+        self
+    }
+
+    /// Probes training steps of `model` on batches of the dataset. Batches larger than
+    /// the dataset are not probed. The model is not updated.
+    ///
+    /// # Arguments
+    /// * `inputs` - Padded token sequences of the training set.
+    /// * `labels` - Their class labels.
+    ///
+    /// # Returns
+    /// An error if the dataset is empty.
+    pub fn tune(&self, model: &Transformer, data_loader: &DataLoader, inputs: &[Vec<usize>], labels: &[usize]) -> Result<BatchSizeTuning, Box<dyn Error>> {
+        if inputs.is_empty() {
+            return Err("Cannot tune the batch size on an empty dataset".into());
+        }
+        let tuner = BatchSizeTuner { max_batch_size: self.max_batch_size.min(inputs.len()), ..*self };
+        Ok(tuner.tune_with(|batch_size| {
+            let (batch_array, mask_array, segments) = data_loader.model_inputs(&inputs[..batch_size]);
+            let batch_labels = &labels[..batch_size];
+
+            let resident = resident_memory();
+            let peak_reset = self.reset_peak_memory && reset_peak_memory();
+            let start = Instant::now();
+            let logits = model.forward_with_segments(&batch_array, Some(&mask_array), Some(&segments));
+            let gradients = Loss::gradients(&logits, batch_labels);
+            model.backward_with_segments(&batch_array, Some(&mask_array), Some(&segments), &gradients);
+            let step_time = start.elapsed();
+            let after = if peak_reset { peak_memory() } else { resident_memory() };
+            let step_memory = resident.zip(after).map(|(before, after)| after.saturating_sub(before));
+            (step_time, step_memory)
+        }))
+    }
+
+    /// Runs the search with a custom `step`, which trains on a batch of the given size
+    /// and returns its duration and memory growth.
+    pub fn tune_with<F>(&self, mut step: F) -> BatchSizeTuning
+    where
+        F: FnMut(usize) -> (Duration, Option<usize>),
+    {
+        let mut probes: Vec<BatchSizeProbe> = Vec::new();
+        let mut batch_size = 1;
+        loop {
+            let (step_time, step_memory) = (0..PROBE_REPEATS)
+                .map(|_| step(batch_size))
+                .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+                .unwrap();
+            let within_budget = step_time <= self.max_step_time
+                && self.max_step_memory.zip(step_memory).is_none_or(|(budget, memory)| memory <= budget);
+            probes.push(BatchSizeProbe { batch_size, step_time, step_memory, within_budget });
+
+            if !within_budget || batch_size >= self.max_batch_size {
+                break;
+            }
+            batch_size = (batch_size * 2).min(self.max_batch_size);
+        }
+
+        let batch_size = probes.iter().filter(|probe| probe.within_budget).map(|probe| probe.batch_size).max().unwrap_or(1);
+        BatchSizeTuning { batch_size, probes }
+    }
+}
+
+/// Reads a `/proc/self/status` field such as `VmRSS`, in bytes.
+#[cfg(target_os = "linux")]
+fn proc_status_bytes(field: &str) -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with(field))?;
+    let kilobytes: usize = line[field.len() + 1..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(target_os = "linux")]
+fn resident_memory() -> Option<usize> {
+    proc_status_bytes("VmRSS")
+}
+
+/// Peak resident memory since the last `reset_peak_memory`.
+#[cfg(target_os = "linux")]
+fn peak_memory() -> Option<usize> {
+    proc_status_bytes("VmHWM")
+}
+
+/// Resets the peak to the current resident memory.
+///
+/// # Returns
+/// Whether the peak was reset; without permission to do so it still covers the whole process.
+#[cfg(target_os = "linux")]
+fn reset_peak_memory() -> bool {
+    std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory() -> Option<usize> {
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_memory() -> Option<usize> {
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reset_peak_memory() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{tiny_config, tiny_vocab};
+    use crate::tokenization::tokenizer::Tokenizer;
+
+    #[test]
+    fn test_keeps_largest_batch_within_budget() {
+        // Steps take 1 ms and 1 MB per example.
+        let step = |batch_size: usize| (Duration::from_millis(batch_size as u64), Some(batch_size * 1_000_000));
+
+        let tuning = BatchSizeTuner::new(Duration::from_millis(10), None, 256).tune_with(step);
+        assert_eq!(tuning.batch_size, 8);
+        let probed: Vec<usize> = tuning.probes.iter().map(|probe| probe.batch_size).collect();
+        assert_eq!(probed, vec![1, 2, 4, 8, 16]);
+
+        let tuning = BatchSizeTuner::new(Duration::from_secs(1), Some(5_000_000), 256).tune_with(step);
+        assert_eq!(tuning.batch_size, 4);
+
+        // The cap is probed even when it is not a power of two.
+        let tuning = BatchSizeTuner::new(Duration::from_secs(1), None, 12).tune_with(step);
+        assert_eq!(tuning.batch_size, 12);
+        assert!(tuning.summary().ends_with("Batch size: 12"));
+
+        let tuning = BatchSizeTuner::new(Duration::ZERO, None, 256).tune_with(step);
+        assert_eq!(tuning.batch_size, 1);
+        assert_eq!(tuning.probes.len(), 1);
+    }
+
+    #[test]
+    fn test_tune_probes_model_steps() {
+        let vocab = tiny_vocab(&["spam"]);
+        let config = tiny_config(2);
+        let model = Transformer::new(config, vocab.clone());
+        let tokenizer = Tokenizer::new(vocab, 4);
+        let data_loader = DataLoader::new(&tokenizer);
+        let inputs = vec![vec![2, 1, 0, 0]; 5];
+        let labels = vec![0, 1, 0, 1, 0];
+
+        let tuning = BatchSizeTuner::new(Duration::from_secs(60), None, 64).tune(&model, &data_loader, &inputs, &labels).unwrap();
+        // Batches are capped at the dataset size.
+        assert_eq!(tuning.batch_size, 5);
+        assert_eq!(tuning.probes.last().unwrap().batch_size, 5);
+
+        let tuner = BatchSizeTuner::new(Duration::from_secs(60), None, 64).with_peak_memory_reset(false);
+        assert_eq!(tuner.tune(&model, &data_loader, &inputs, &labels).unwrap().batch_size, 5);
+        assert!(tuner.tune(&model, &data_loader, &[], &[]).is_err());
+    }
+}
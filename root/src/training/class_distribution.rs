@@ -0,0 +1,74 @@
+use crate::data_handler::label_map::LabelMap;
+
+/// Number of examples per class actually fed to the model, e.g. during one epoch.
+///
+/// Counts are taken from the batches after sampling, so oversampling or other
+/// balancing strategies show up here rather than in the raw dataset statistics.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClassDistribution {
+    counts: Vec<usize>,
+}
+
+impl ClassDistribution {
+    pub fn new() -> Self {
+        ClassDistribution::default()
+    }
+
+    /// Adds the labels of one batch.
+    pub fn record(&mut self, labels: &[usize]) {
+        for &label in labels {
+            if label >= self.counts.len() {
+                self.counts.resize(label + 1, 0);
+            }
+            self.counts[label] += 1;
+        }
+    }
+
+    /// Examples seen per class, indexed by label.
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// Share of the examples seen per class, indexed by label.
+    pub fn proportions(&self) -> Vec<f64> {
+        let total = self.total().max(1) as f64;
+        self.counts.iter().map(|&count| count as f64 / total).collect()
+    }
+
+    /// One-line summary such as `0: 12 (48.0%), 1: 13 (52.0%)`, or `ham: 12 (48.0%), spam: 13 (52.0%)`
+    /// when a label map names the classes.
+    pub fn named_summary(&self, label_map: Option<&LabelMap>) -> String {
+        self.counts
+            .iter()
+            .zip(self.proportions())
+            .enumerate()
+            .map(|(label, (count, share))| {
+                let name = label_map.map_or_else(|| label.to_string(), |label_map| label_map.display_name(label));
+                format!("{}: {} ({:.1}%)", name, count, share * 100.0)
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_counts_and_summary() {
+        let mut distribution = ClassDistribution::new();
+        distribution.record(&[0, 2, 2]);
+        distribution.record(&[2]);
+
+        assert_eq!(distribution.counts(), &[1, 0, 3]);
+        assert_eq!(distribution.proportions(), vec![0.25, 0.0, 0.75]);
+        assert_eq!(distribution.named_summary(None), "0: 1 (25.0%), 1: 0 (0.0%), 2: 3 (75.0%)");
+        let label_map = LabelMap::from_names(&["ham", "spam"]).unwrap();
+        assert_eq!(distribution.named_summary(Some(&label_map)), "ham: 1 (25.0%), spam: 0 (0.0%), 2: 3 (75.0%)");
+    }
+}
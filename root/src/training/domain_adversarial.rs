@@ -0,0 +1,138 @@
+use crate::classification::ClassificationHead;
+use crate::cross_entropy::loss::Loss;
+use crate::model_optimizer::optimizer::{Optimizer, OptimizerType};
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Steepness of the schedule in `DomainAdversary::reversal_strength` (Ganin et al., 2016).
+const SCHEDULE_STEEPNESS: f64 = 10.0;
+
+/// Gradient reversal layer: the identity in the forward pass, multiplying the gradient by
+/// `-strength` in the backward pass.
+///
+/// Placed between the pooled encoder output and a domain classifier, it makes the encoder
+/// ascend the domain loss while the classifier descends it, so the encoder learns features
+/// the classifier cannot tell the domains apart by.
+pub fn reverse_gradient(grad: &Array2<f64>, strength: f64) -> Array2<f64> {
+    grad * -strength
+}
+
+/// Domain classifier branch of domain-adversarial training (DANN).
+///
+/// Every training example belongs to a domain, e.g. the source it was collected from,
+/// read from a metadata field of the dataset. The branch predicts the domain from the
+/// pooled encoder output and sends the reversed gradient back into the encoder (see
+/// `reverse_gradient`), so the encoder learns domain-invariant features while the
+/// classification head learns the task. Only the encoder is kept; the domain classifier is
+/// discarded after training. It is saved with interrupt checkpoints, so a resumed run
+/// continues with the same classifier and optimizer state.
+#[derive(Serialize, Deserialize)]
+pub struct DomainAdversary {
+    /// Metadata field holding every example's domain.
+    pub domain_field: String,
+    /// Maximum reversal strength, reached at the end of training.
+    pub weight: f64,
+    /// Domain names, indexed by domain id; set by `fit_domains`.
+    pub domains: Vec<String>,
+    head: Option<ClassificationHead>,
+    /// Updates the domain classifier; its parameters are stepped as one row.
+    optimizer: Optimizer,
+}
+
+impl DomainAdversary {
+    /// # Arguments
+    /// * `domain_field` - Metadata field of the dataset that names every example's domain.
+    /// * `weight` - Reversal strength at the end of training, relative to the task loss.
+    pub fn new(domain_field: &str, weight: f64) -> Self {
+        DomainAdversary { domain_field: domain_field.to_string(), weight, domains: Vec::new(), head: None, optimizer: Optimizer::new(OptimizerType::Sgd) }
+    }
+
+    /// Collects the domains of a training set and starts a fresh domain classifier over them.
+    /// A classifier restored from a checkpoint over the same domains is kept instead.
+    ///
+    /// # Returns
+    /// * The domain id of every example, or an error for fewer than two domains.
+    pub fn fit_domains(&mut self, domain_values: &[String], d_model: usize) -> Result<Vec<usize>, Box<dyn Error>> {
+        let mut domains = domain_values.to_vec();
+        domains.sort();
+        domains.dedup();
+        if domains.len() < 2 {
+            return Err(format!("Domain-adversarial training needs at least two values of {}, found {:?}", self.domain_field, domains).into());
+        }
+        let ids = domain_values.iter().map(|value| domains.binary_search(value).unwrap()).collect();
+        if self.head.is_none() || self.domains != domains {
+            self.head = Some(ClassificationHead::new(d_model, domains.len()));
+            self.optimizer.reset();
+        }
+        self.domains = domains;
+        Ok(ids)
+    }
+
+    /// Reversal strength after `progress` (0 to 1) of training: `weight * (2 / (1 + e^(-10p)) - 1)`.
+    /// It starts at 0, so the encoder is not pushed around by a domain classifier that has
+    /// not learned anything yet.
+    pub fn reversal_strength(&self, progress: f64) -> f64 {
+        self.weight * (2.0 / (1.0 + (-SCHEDULE_STEEPNESS * progress).exp()) - 1.0)
+    }
+
+    /// Trains the domain classifier on one batch and returns the reversed gradient for the encoder.
+    ///
+    /// # Arguments
+    /// * `pooled` - Pooled encoder output of the batch. Shape: [batch_size, d_model].
+    /// * `domain_ids` - Domain id of every example, from `fit_domains`.
+    /// * `progress` - Fraction of training done, for `reversal_strength`.
+    ///
+    /// # Returns
+    /// * `(domain_loss, correct_domain_predictions, grad_pooled)`, where `grad_pooled` is to be
+    ///   added to the gradient the encoder receives from the task.
+    pub fn step(&mut self, pooled: &Array2<f64>, domain_ids: &[usize], progress: f64) -> (f64, usize, Array2<f64>) {
+        let strength = self.reversal_strength(progress);
+        let head = self.head.as_mut().expect("fit_domains must be called before step");
+        let logits = head.forward(pooled);
+        let loss = Loss::cross_entropy_loss(&logits, domain_ids);
+        let correct = logits
+            .outer_iter()
+            .zip(domain_ids)
+            .filter(|(row, &domain)| row.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map(|(i, _)| i) == Some(domain))
+            .count();
+
+        let (grad_pooled, head_grads) = head.backward(pooled, &Loss::gradients(&logits, domain_ids));
+        let mut params = head.parameters_mut();
+        let mut values = Array2::from_shape_fn((1, params.len()), |(_, i)| *params[i]);
+        let grads = Array2::from_shape_vec((1, head_grads.len()), head_grads).expect("one gradient per parameter");
+        self.optimizer.step(&mut values.view_mut(), &grads.view());
+        for (param, value) in params.iter_mut().zip(values) {
+            **param = value;
+        }
+        (loss, correct, reverse_gradient(&grad_pooled, strength))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_domain_adversary() {
+        let mut adversary = DomainAdversary::new("source", 0.5);
+        assert!(adversary.fit_domains(&["web".to_string(), "web".to_string()], 2).is_err());
+        let values = ["web", "email", "web"].map(String::from);
+        assert_eq!(adversary.fit_domains(&values, 2).unwrap(), vec![1, 0, 1]);
+        assert_eq!(adversary.domains, vec!["email", "web"]);
+
+        assert_eq!(adversary.reversal_strength(0.0), 0.0);
+        assert!((adversary.reversal_strength(1.0) - 0.5).abs() < 1e-4);
+        assert_eq!(reverse_gradient(&array![[1.0, -2.0]], 0.5), array![[-0.5, 1.0]]);
+
+        // The encoder gradient points against the classifier's own input gradient.
+        let pooled = array![[0.3, -0.2], [0.1, 0.4], [0.2, 0.0]];
+        let domain_ids = [1, 0, 1];
+        let head_input_grad = adversary.head.as_ref().unwrap().backward(&pooled, &Loss::gradients(&adversary.head.as_ref().unwrap().forward(&pooled), &domain_ids)).0;
+        let (loss, correct, grad_pooled) = adversary.step(&pooled, &domain_ids, 1.0);
+        assert!(loss > 0.0 && correct <= 3);
+        let strength = adversary.reversal_strength(1.0);
+        assert!((grad_pooled + head_input_grad * strength).iter().all(|d| d.abs() < 1e-12));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::configurration::data_schema::DataSchema;
+use crate::cross_entropy::loss::Loss;
+use crate::data_handler::data_loader::DataLoader;
+use crate::data_handler::label_map::LabelMap;
+use crate::experiment::experiment_run::RunConfig;
+use crate::logging::logger::{LogEvent, LogLevel};
+use crate::tokenization::tokenizer::Tokenizer;
+use crate::transformer::Transformer;
+use std::error::Error;
+
+/// Number of dataset records the dry run loads.
+pub const DRY_RUN_SAMPLE_SIZE: usize = 64;
+/// Number of examples in the dry-run forward/backward batch.
+pub const DRY_RUN_BATCH_SIZE: usize = 2;
+
+/// The config, tokenizer and data schema of the run a dry run exercises, prepared the same
+/// way as for a real run.
+pub struct DryRunSetup {
+    pub config: RunConfig,
+    pub tokenizer: Tokenizer,
+    /// Label map of the run; `None` for runs that predate label maps and use numeric labels.
+    pub label_map: Option<LabelMap>,
+    pub schema: DataSchema,
+}
+
+/// Outcome of every pipeline step exercised by `dry_run`.
+pub struct DryRunReport {
+    /// Step name and either a short detail or the error that stopped the dry run.
+    pub steps: Vec<(String, Result<String, String>)>,
+}
+
+impl DryRunReport {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|(_, outcome)| outcome.is_ok())
+    }
+
+    pub fn print(&self) {
+        let mut report = String::from("Dry run report:");
+        for (step, outcome) in &self.steps {
+            match outcome {
+                Ok(detail) => report.push_str(&format!("\n  [ok]   {}: {}", step, detail)),
+                Err(error) => report.push_str(&format!("\n  [fail] {}: {}", step, error)),
+            }
+        }
+        report.push_str(if self.passed() { "\nDry run passed." } else { "\nDry run failed." });
+        let level = if self.passed() { LogLevel::Info } else { LogLevel::Error };
+        LogEvent::new(level, "dry_run", report).metric("passed", self.passed()).emit();
+    }
+}
+
+/// Exercises the whole training pipeline on a tiny sample without training.
+///
+/// Runs `setup`, the vocabulary, tokenizer and label setup of a real run, validates the
+/// config, loads the first `sample_size` records, constructs the model and runs one forward
+/// and backward pass on a `DRY_RUN_BATCH_SIZE` batch. Stops at the first failing step so
+/// shape, config and data errors surface before a long run starts.
+pub fn dry_run<F>(dataset_path: &str, sample_size: usize, setup: F) -> DryRunReport
+where
+    F: FnOnce() -> Result<DryRunSetup, Box<dyn Error>>,
+{
+    let mut report = DryRunReport { steps: Vec::new() };
+    // Run the steps until one fails; every step records its own outcome.
+    let _ = run_steps(dataset_path, sample_size, setup, &mut report);
+    report
+}
+
+fn record<T>(
+    report: &mut DryRunReport,
+    step: &str,
+    result: Result<(T, String), Box<dyn Error>>,
+) -> Result<T, ()> {
+    match result {
+        Ok((value, detail)) => {
+            report.steps.push((step.to_string(), Ok(detail)));
+            Ok(value)
+        }
+        Err(error) => {
+            report.steps.push((step.to_string(), Err(error.to_string())));
+            Err(())
+        }
+    }
+}
+
+fn run_steps<F>(dataset_path: &str, sample_size: usize, setup: F, report: &mut DryRunReport) -> Result<(), ()>
+where
+    F: FnOnce() -> Result<DryRunSetup, Box<dyn Error>>,
+{
+    let DryRunSetup { config, tokenizer, label_map, schema } = record(report, "setup", setup().map(|setup| {
+        let classes = match &setup.label_map {
+            Some(label_map) => format!("{} classes", label_map.len()),
+            None => "numeric labels".to_string(),
+        };
+        let detail = format!("{} tokens, {}", setup.tokenizer.vocab.len(), classes);
+        (setup, detail)
+    }))?;
+    let config = &config;
+
+    record(report, "config", validate_config(config).map(|detail| ((), detail)))?;
+
+    let records = record(report, "load data", (|| {
+        let data_loader = DataLoader::new(&tokenizer).with_schema(schema);
+        let data_loader = match label_map {
+            Some(label_map) => data_loader.with_label_map(label_map),
+            None => data_loader,
+        };
+        let mut records = data_loader.load_records(dataset_path)?;
+        records.truncate(sample_size);
+        if records.is_empty() {
+            return Err(format!("{} contains no records", dataset_path).into());
+        }
+        let detail = format!("{} records sampled from {}", records.len(), dataset_path);
+        Ok((records, detail))
+    })())?;
+    let texts: Vec<String> = records.iter().map(|r| r.text.clone()).collect();
+
+    let labels = record(report, "labels", (|| {
+        let labels = records
+            .iter()
+            .map(|r| r.label.ok_or_else(|| format!("record {} has no label", r.id)))
+            .collect::<Result<Vec<usize>, String>>()?;
+        if let Some(&label) = labels.iter().find(|&&label| label >= config.model.num_classes) {
+            return Err(format!("label {} is out of range for num_classes = {}", label, config.model.num_classes).into());
+        }
+        Ok((labels, format!("all labels < {}", config.model.num_classes)))
+    })())?;
+
+    let mut model: Transformer = Transformer::new(config.model.clone(), tokenizer.vocab.clone());
+    let num_parameters = model.num_parameters();
+    record(report, "build model", Ok(((), format!("{} parameters", num_parameters))))?;
+
+    let batch_size = DRY_RUN_BATCH_SIZE.min(texts.len());
+    let (batch_array, mask_array) = record(report, "truncation", (|| {
+        let (inputs, truncation) = tokenizer.tokenize_and_pad_batch_with_report(&texts[..batch_size]);
+        let arrays = tokenizer.mask_batch(inputs).to_arrays()?;
+        Ok((arrays, format!("{:?}: {}", tokenizer.truncation, truncation.summary())))
+    })())?;
+    let batch_labels = &labels[..batch_size];
+
+    let logits = record(report, "forward", (|| {
+        let logits = model.forward(&batch_array, Some(&mask_array));
+        if logits.shape() != [batch_size, config.model.num_classes] {
+            return Err(format!("logits shape {:?}, expected [{}, {}]", logits.shape(), batch_size, config.model.num_classes).into());
+        }
+        if logits.iter().any(|value| !value.is_finite()) {
+            return Err("logits contain non-finite values".into());
+        }
+        let detail = format!("logits shape {:?}", logits.shape());
+        Ok((logits, detail))
+    })())?;
+
+    record(report, "backward", (|| {
+        let loss = Loss::cross_entropy_loss(&logits, batch_labels);
+        let grads = model.backward(&batch_array, Some(&mask_array), &Loss::gradients(&logits, batch_labels));
+        if grads.len() != model.parameters_mut().len() {
+            return Err(format!("{} gradients for {} parameters", grads.len(), num_parameters).into());
+        }
+        if !loss.is_finite() || grads.iter().any(|grad| !grad.is_finite()) {
+            return Err("loss or gradients contain non-finite values".into());
+        }
+        Ok(((), format!("loss {:.4}, {} gradients", loss, grads.len())))
+    })())?;
+
+    Ok(())
+}
+
+fn validate_config(config: &RunConfig) -> Result<String, Box<dyn Error>> {
+    let model = &config.model;
+    if model.d_model == 0 || model.ff_dim == 0 || model.num_layers == 0 {
+        return Err("d_model, ff_dim and num_layers must be positive".into());
+    }
+    if model.num_heads == 0 || !model.d_model.is_multiple_of(model.num_heads) {
+        return Err(format!("d_model ({}) must be divisible by num_heads ({})", model.d_model, model.num_heads).into());
+    }
+    if model.num_classes < 2 {
+        return Err(format!("num_classes must be at least 2, got {}", model.num_classes).into());
+    }
+    if config.max_seq_length == 0 || config.batch_size == 0 || config.epochs == 0 {
+        return Err("max_seq_length, batch_size and epochs must be positive".into());
+    }
+    if !(config.learning_rate > 0.0 && config.learning_rate.is_finite()) {
+        return Err(format!("learning_rate must be positive, got {}", config.learning_rate).into());
+    }
+    Ok(format!(
+        "{} layers, d_model {}, {} classes, max_seq_length {}",
+        model.num_layers, model.d_model, model.num_classes, config.max_seq_length
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenization::tokenizer::VocabOptions;
+    use crate::test_utils::fixtures::{temp_path, tiny_config};
+    use crate::data_handler::synthetic::{SyntheticConfig, SyntheticDataset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn config(num_classes: usize) -> RunConfig {
+        let mut config = RunConfig::new(
+            tiny_config(num_classes),
+            1,
+        );
+        config.max_seq_length = 16;
+        config
+    }
+
+    /// Writes a synthetic dataset and returns its path with the texts.
+    fn synthetic_dataset(name: &str) -> (String, Vec<String>) {
+        let dataset_path = temp_path(name);
+        let synthetic_config = SyntheticConfig { num_examples: 8, ..SyntheticConfig::default() };
+        let dataset = SyntheticDataset::generate(&synthetic_config, &mut StdRng::seed_from_u64(0)).unwrap();
+        dataset.save_json(&dataset_path).unwrap();
+        (dataset_path, dataset.texts)
+    }
+
+    fn setup(config: RunConfig, texts: &[String]) -> DryRunSetup {
+        let (vocab, _) = Tokenizer::build_vocab_with_stats(texts, &["[PAD]", "[UNK]"], &VocabOptions::default());
+        let tokenizer = Tokenizer::new(vocab, config.max_seq_length);
+        DryRunSetup { config, tokenizer, label_map: None, schema: DataSchema::default() }
+    }
+
+    #[test]
+    fn test_dry_run_passes_on_valid_pipeline() {
+        let (dataset_path, texts) = synthetic_dataset("dry_run_synthetic_dataset.json");
+        let report = dry_run(&dataset_path, 8, || Ok(setup(config(2), &texts)));
+        std::fs::remove_file(&dataset_path).unwrap();
+        assert!(report.passed(), "{:?}", report.steps);
+        assert_eq!(report.steps.len(), 8);
+    }
+
+    #[test]
+    fn test_dry_run_stops_at_first_failure() {
+        let (dataset_path, texts) = synthetic_dataset("dry_run_failure_dataset.json");
+
+        let report = dry_run(&dataset_path, 8, || Err("no vocabulary".into()));
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(report.steps[0].0, "setup");
+        assert!(report.steps[0].1.is_err());
+
+        let report = dry_run(&temp_path("dry_run_missing_dataset.json"), 8, || Ok(setup(config(2), &texts)));
+        assert!(!report.passed());
+        assert_eq!(report.steps.last().unwrap().0, "load data");
+
+        let report = dry_run(&dataset_path, 8, || Ok(setup(config(1), &texts)));
+        assert_eq!(report.steps.len(), 2);
+        assert!(report.steps[1].1.is_err());
+
+        let mut indivisible = config(2);
+        indivisible.model.num_heads = 3;
+        let report = dry_run(&dataset_path, 8, || Ok(setup(indivisible, &texts)));
+        std::fs::remove_file(&dataset_path).unwrap();
+        assert_eq!(report.steps.len(), 2);
+        assert!(report.steps[1].1.as_ref().unwrap_err().contains("divisible"));
+    }
+}
@@ -1 +1,7 @@
 pub mod trainer;
+pub mod class_distribution;
+pub mod shutdown;
+pub mod dry_run;
+pub mod batch_size_tuner;
+pub mod probe_set;
+pub mod domain_adversarial;
@@ -0,0 +1,183 @@
+use crate::cross_entropy::loss::Loss;
+use crate::data_handler::data_loader::DataLoader;
+use crate::transformer::Transformer;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// A hand-picked example whose prediction is checked after every epoch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProbeExample {
+    pub text: String,
+    pub label: usize,
+    /// Why the example matters, e.g. `negation` or `customer escalation`.
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Prediction for one probe example after an epoch.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProbeResult {
+    pub text: String,
+    pub label: usize,
+    pub predicted_class: usize,
+    /// Probability the model gives to the correct label.
+    pub label_probability: f64,
+    pub correct: bool,
+}
+
+/// Outcome of `ProbeSet::evaluate` for one epoch.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProbeReport {
+    pub results: Vec<ProbeResult>,
+    /// Indices of examples that were correct after the previous evaluation and are wrong now.
+    pub regressions: Vec<usize>,
+    /// Indices of examples that were wrong after the previous evaluation and are correct now.
+    pub fixed: Vec<usize>,
+}
+
+impl ProbeReport {
+    pub fn accuracy(&self) -> f64 {
+        self.results.iter().filter(|result| result.correct).count() as f64 / self.results.len().max(1) as f64
+    }
+
+    /// One line with the accuracy, followed by every regression.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "Probe set: {:.2}% correct, {} regressions, {} fixed",
+            self.accuracy() * 100.0,
+            self.regressions.len(),
+            self.fixed.len()
+        )];
+        for &index in &self.regressions {
+            let result = &self.results[index];
+            lines.push(format!(
+                "  regression: {:?} predicted {} instead of {} (p = {:.3})",
+                result.text, result.predicted_class, result.label, result.label_probability
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// A small fixed set of critical examples evaluated after every training epoch, so
+/// regressions on known tricky inputs show up as soon as they happen instead of being
+/// averaged away in the validation accuracy.
+pub struct ProbeSet {
+    pub examples: Vec<ProbeExample>,
+    /// Padded token ids of the examples.
+    inputs: Vec<Vec<usize>>,
+    /// Which examples were correct after the last evaluation.
+    last_correct: Option<Vec<bool>>,
+}
+
+impl ProbeSet {
+    /// Encodes the examples with the data loader's tokenizer.
+    pub fn new(examples: Vec<ProbeExample>, data_loader: &DataLoader) -> Self {
+        let texts: Vec<String> = examples.iter().map(|example| example.text.clone()).collect();
+        let inputs = data_loader.tokenizer.tokenize_and_pad_batch(&texts);
+        ProbeSet { examples, inputs, last_correct: None }
+    }
+
+    /// Loads a JSON array of `{ "text", "label", "note" }` objects; `note` is optional.
+    pub fn load(file_path: &str, data_loader: &DataLoader) -> Result<Self, Box<dyn Error>> {
+        let examples: Vec<ProbeExample> = serde_json::from_str(&std::fs::read_to_string(file_path)?)?;
+        if examples.is_empty() {
+            return Err(format!("Probe set {} is empty", file_path).into());
+        }
+        Ok(Self::new(examples, data_loader))
+    }
+
+    /// Predicts every example and compares the outcome with the previous evaluation.
+    pub fn evaluate(&mut self, model: &Transformer, data_loader: &DataLoader) -> ProbeReport {
+        let (batch_array, mask_array, segments) = data_loader.model_inputs(&self.inputs);
+        let probabilities = Loss::softmax(&model.forward_with_segments(&batch_array, Some(&mask_array), Some(&segments)));
+
+        let results: Vec<ProbeResult> = self
+            .examples
+            .iter()
+            .zip(probabilities.outer_iter())
+            .map(|(example, row)| {
+                let predicted_class = row
+                    .iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(b.1))
+                    .map(|(index, _)| index)
+                    .unwrap_or(0);
+                ProbeResult {
+                    text: example.text.clone(),
+                    label: example.label,
+                    predicted_class,
+                    label_probability: row.get(example.label).copied().unwrap_or(0.0),
+                    correct: predicted_class == example.label,
+                }
+            })
+            .collect();
+
+        let correct: Vec<bool> = results.iter().map(|result| result.correct).collect();
+        let (mut regressions, mut fixed) = (Vec::new(), Vec::new());
+        if let Some(last_correct) = &self.last_correct {
+            for (index, (&before, &now)) in last_correct.iter().zip(&correct).enumerate() {
+                if before && !now {
+                    regressions.push(index);
+                } else if !before && now {
+                    fixed.push(index);
+                }
+            }
+        }
+        self.last_correct = Some(correct);
+        ProbeReport { results, regressions, fixed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{temp_path, tiny_config, tiny_vocab};
+    use crate::tokenization::tokenizer::Tokenizer;
+
+    fn example(text: &str, label: usize) -> ProbeExample {
+        ProbeExample { text: text.to_string(), label, note: String::new() }
+    }
+
+    #[test]
+    fn test_reports_regressions_between_evaluations() {
+        let vocab = tiny_vocab(&["not", "bad"]);
+        let config = tiny_config(2);
+        let model = Transformer::new(config, vocab.clone());
+        let tokenizer = Tokenizer::new(vocab, 4);
+        let data_loader = DataLoader::new(&tokenizer);
+
+        let mut probes = ProbeSet::new(vec![example("not bad", 0), example("not bad", 1)], &data_loader);
+        let first = probes.evaluate(&model, &data_loader);
+        assert!(first.regressions.is_empty() && first.fixed.is_empty());
+        // The same text with both labels: exactly one of them is predicted correctly.
+        assert_eq!(first.accuracy(), 0.5);
+        let probability_sum: f64 = first.results.iter().map(|result| result.label_probability).sum();
+        assert!((probability_sum - 1.0).abs() < 1e-9);
+
+        // Flipping which label is correct turns one example into a regression and the other into a fix.
+        let was_correct = first.results.iter().position(|result| result.correct).unwrap();
+        probes.last_correct = Some(vec![was_correct == 1, was_correct == 0]);
+        let second = probes.evaluate(&model, &data_loader);
+        assert_eq!(second.regressions, vec![1 - was_correct]);
+        assert_eq!(second.fixed, vec![was_correct]);
+        assert!(second.summary().contains("1 regressions"));
+    }
+
+    #[test]
+    fn test_load_reads_optional_notes() {
+        let tokenizer = Tokenizer::new(tiny_vocab(&[]), 4);
+        let data_loader = DataLoader::new(&tokenizer);
+        let path = &temp_path("probe_set_test.json");
+        std::fs::write(path, r#"[{ "text": "a", "label": 1, "note": "sarcasm" }, { "text": "b", "label": 0 }]"#).unwrap();
+        let probes = ProbeSet::load(path, &data_loader);
+        std::fs::write(path, "[]").unwrap();
+        let empty = ProbeSet::load(path, &data_loader);
+        std::fs::remove_file(path).unwrap();
+
+        let probes = probes.unwrap();
+        assert_eq!(probes.examples[0].note, "sarcasm");
+        assert_eq!(probes.examples[1].note, "");
+        assert!(empty.is_err());
+    }
+}
@@ -0,0 +1,41 @@
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Flag set when SIGINT or SIGTERM is received, checked by the trainer between batches.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownSignal {
+    requested: Arc<AtomicBool>,
+}
+
+impl ShutdownSignal {
+    /// A signal without handlers; `install` registers them.
+    pub fn new() -> Self {
+        ShutdownSignal::default()
+    }
+
+    /// Registers SIGINT and SIGTERM handlers that request a graceful shutdown.
+    /// A second signal while the first is still being handled terminates immediately.
+    pub fn install() -> Result<Self, std::io::Error> {
+        let signal = ShutdownSignal::new();
+        for signal_id in [SIGINT, SIGTERM] {
+            // Registered first so it only fires once the flag has already been set.
+            flag::register_conditional_shutdown(signal_id, 130, Arc::clone(&signal.requested))?;
+            flag::register(signal_id, Arc::clone(&signal.requested))?;
+        }
+        Ok(signal)
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+impl ShutdownSignal {
+    /// Requests a shutdown as a received signal would.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+}
@@ -1,16 +1,199 @@
-use crate::data_handler::data_loader::DataLoader;
+use crate::data_handler::data_loader::{DataLoader, ModelInputs};
 use crate::cross_entropy::loss::Loss;
+use crate::cross_entropy::contrastive_loss::ContrastiveLoss;
 use crate::model_optimizer::optimizer::Optimizer;
 use crate::transformer::Transformer;
-use crate::configurration::config::{BATCH_SIZE, LEARNING_RATE};
+use crate::embedding::embeddings::{EmbeddingDropout, Embeddings};
+use crate::transformer::parallelism::Parallelism;
+use crate::summation::{CompensatedSum, Summation};
+use crate::profiling::profiler;
+use crate::classification::{ClassificationHead, TiedOutputHead};
+use crate::configurration::config::{LEARNING_RATE, MASK_TOKEN, PAD_TOKEN, UNK_TOKEN, CLS_TOKEN, SEP_TOKEN, MLM_MASK_PROBABILITY};
+use crate::data_handler::masking::{TokenMasker, WordDropout};
+use crate::training::class_distribution::ClassDistribution;
+use crate::experiment::experiment_run::ExperimentRun;
+use crate::training::shutdown::ShutdownSignal;
+use crate::training::probe_set::{ProbeReport, ProbeSet};
+use crate::training::domain_adversarial::DomainAdversary;
+use crate::augmentation::Augmenter;
+use crate::logging::logger::LogEvent;
 use ndarray::Array2;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use std::error::Error;
 use std::fs;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Defaults for `Trainer::overfit_single_batch`.
+pub const OVERFIT_BATCH_SIZE: usize = 4;
+pub const OVERFIT_STEPS: usize = 500;
+pub const OVERFIT_LEARNING_RATE: f64 = 0.05;
+pub const OVERFIT_TARGET_LOSS: f64 = 0.05;
+
+/// Settings of the supervised contrastive stage run before `train` (see `pretrain_contrastive`).
+#[derive(Debug, Clone, Copy)]
+pub struct ContrastivePretraining {
+    pub epochs: usize,
+    /// Examples of every class in each batch, so each class has positives.
+    pub min_per_class: usize,
+    pub temperature: f64,
+}
 
 pub struct Trainer<'a> {
     pub model: Transformer,
     pub optimizer: Optimizer,
     pub data_loader: &'a DataLoader<'a>,
     pub epochs: usize,
+    pub ema_decay: Option<f64>,
+    /// First epoch (1-based) averaged into the SWA weights; see `with_swa`.
+    pub swa_start_epoch: Option<usize>,
+    /// Class distribution of the examples seen in every epoch of the last `train` run.
+    pub epoch_class_distributions: Vec<ClassDistribution>,
+    /// When set, epoch checkpoints and metrics are written to the run directory.
+    pub run: Option<ExperimentRun>,
+    /// When set, `train` stops after the current batch once a shutdown is requested.
+    pub shutdown: Option<ShutdownSignal>,
+    /// Whether the last `train` call stopped early because of a shutdown request.
+    pub interrupted: bool,
+    /// Wall-clock budget for `train`; see `with_max_duration`.
+    pub max_duration: Option<Duration>,
+    /// Whether the last `train` call stopped early because `max_duration` ran out.
+    pub budget_exhausted: bool,
+    /// When set, its examples are predicted after every epoch; see `with_probe_set`.
+    pub probe_set: Option<ProbeSet>,
+    /// Probe set results of every epoch of the last `train` run.
+    pub epoch_probe_reports: Vec<ProbeReport>,
+    /// Augmentation stages applied to the training set by `train`; see `with_augmentation`.
+    pub augmenters: Vec<Box<dyn Augmenter + 'a>>,
+    /// Token corruption applied to the training batches of `train`; see `with_word_dropout`.
+    pub word_dropout: Option<WordDropout>,
+    /// Whether `pretrain_mlm` ties its output layer to the token embeddings; see `with_tied_mlm_head`.
+    pub tie_mlm_head: bool,
+    /// Dropout rate on the embedding block output during `train`; see `with_embedding_dropout`.
+    pub embedding_dropout: f64,
+    /// Domain classifier trained adversarially against the encoder by `train`; see `with_domain_adversary`.
+    pub domain_adversary: Option<DomainAdversary>,
+    /// Seed of the random draws of `train` (word dropout, embedding dropout masks); see `with_seed`.
+    pub seed: u64,
+    start_epoch: usize,
+    start_batch: usize,
+    ema_params: Vec<f64>,
+    swa_params: Vec<f64>,
+    swa_count: usize,
+}
+
+/// Progress saved next to an interrupt checkpoint.
+#[derive(Deserialize)]
+pub struct TrainingState {
+    /// Epoch that was in progress when training stopped (0-based).
+    pub epoch: usize,
+    /// Batches of that epoch already applied to the model.
+    pub completed_batches: usize,
+    pub optimizer: Optimizer,
+    /// Trainer seed; `None` in states saved before it was recorded.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Domain classifier of domain-adversarial training, when it was enabled.
+    #[serde(default)]
+    pub domain_adversary: Option<DomainAdversary>,
+    /// EMA shadow parameters, when an EMA was kept (see `Trainer::with_ema`).
+    #[serde(default)]
+    pub ema_params: Option<Vec<f64>>,
+    /// SWA running average and the number of epochs in it, when SWA was enabled (see `Trainer::with_swa`).
+    #[serde(default)]
+    pub swa_params: Option<Vec<f64>>,
+    #[serde(default)]
+    pub swa_count: usize,
+}
+
+fn sibling_path(save_path: &str, tag: &str) -> String {
+    match save_path.strip_suffix(".json") {
+        Some(stem) => format!("{}.{}.json", stem, tag),
+        None => format!("{}.{}", save_path, tag),
+    }
+}
+
+/// Path of the EMA shadow copy saved next to a checkpoint,
+/// e.g. `model.json` -> `model.ema.json`.
+pub fn ema_checkpoint_path(save_path: &str) -> String {
+    sibling_path(save_path, "ema")
+}
+
+/// Path of the SWA weights saved next to a checkpoint,
+/// e.g. `model.json` -> `model.swa.json`.
+pub fn swa_checkpoint_path(save_path: &str) -> String {
+    sibling_path(save_path, "swa")
+}
+
+/// Saves `model` with its parameters temporarily replaced by the `shadow` values (EMA or SWA).
+fn save_with_parameters(model: &mut Transformer, shadow: &mut [f64], path: &str) -> Result<(), std::io::Error> {
+    let swap = |model: &mut Transformer, shadow: &mut [f64]| {
+        for (param, value) in model.parameters_mut().into_iter().zip(shadow.iter_mut()) {
+            std::mem::swap(param, value);
+        }
+    };
+
+    swap(model, shadow);
+    let result = model.save(path);
+    swap(model, shadow);
+    result
+}
+
+/// Path of the lowest-loss epoch checkpoint kept when training has a time budget,
+/// e.g. `model.json` -> `model.best.json`.
+pub fn best_checkpoint_path(save_path: &str) -> String {
+    sibling_path(save_path, "best")
+}
+
+/// Path of the model saved when training is interrupted,
+/// e.g. `model.json` -> `model.interrupted.json`.
+pub fn interrupted_checkpoint_path(save_path: &str) -> String {
+    sibling_path(save_path, "interrupted")
+}
+
+/// Path of the `TrainingState` saved with an interrupt checkpoint,
+/// e.g. `model.json` -> `model.state.json`.
+pub fn training_state_path(save_path: &str) -> String {
+    sibling_path(save_path, "state")
+}
+
+/// Token ids and labels of one training batch.
+type Batch = (Vec<Vec<usize>>, Vec<usize>);
+
+/// Where a batch of `Trainer::train` falls in training.
+struct BatchPosition {
+    epoch: usize,
+    index: usize,
+    num_batches: usize,
+}
+
+/// Running totals of one epoch of `Trainer::train`.
+struct EpochTotals {
+    /// Batches trained in this call, which is fewer than the epoch's when resumed or stopped early.
+    batches: usize,
+    loss: CompensatedSum,
+    correct_predictions: usize,
+    total_samples: usize,
+    class_distribution: ClassDistribution,
+    domain_loss: f64,
+    correct_domains: usize,
+}
+
+impl EpochTotals {
+    fn new(summation: Summation) -> Self {
+        EpochTotals {
+            batches: 0,
+            loss: CompensatedSum::new(summation),
+            correct_predictions: 0,
+            total_samples: 0,
+            class_distribution: ClassDistribution::new(),
+            domain_loss: 0.0,
+            correct_domains: 0,
+        }
+    }
 }
 
 impl<'a> Trainer<'a> {
@@ -26,9 +209,221 @@ impl<'a> Trainer<'a> {
             optimizer,
             data_loader,
             epochs,
+            ema_decay: None,
+            swa_start_epoch: None,
+            epoch_class_distributions: Vec::new(),
+            run: None,
+            shutdown: None,
+            interrupted: false,
+            max_duration: None,
+            budget_exhausted: false,
+            probe_set: None,
+            epoch_probe_reports: Vec::new(),
+            start_epoch: 0,
+            start_batch: 0,
+            ema_params: Vec::new(),
+            swa_params: Vec::new(),
+            swa_count: 0,
+            augmenters: Vec::new(),
+            word_dropout: None,
+            tie_mlm_head: false,
+            embedding_dropout: 0.0,
+            domain_adversary: None,
+            seed: rand::random(),
         }
     }
 
+    /// Keeps an exponential moving average of the parameters during training and
+    /// saves it as a shadow checkpoint at `ema_checkpoint_path(save_path)`.
+    pub fn with_ema(mut self, decay: f64) -> Self {
+        assert!((0.0..1.0).contains(&decay), "EMA decay must be in [0, 1).");
+        self.ema_decay = Some(decay);
+        self
+    }
+
+    /// Stochastic weight averaging: keeps the running mean of the parameters at the end of
+    /// every epoch from `start_epoch` (1-based) on, and saves it at `swa_checkpoint_path(save_path)`.
+    pub fn with_swa(mut self, start_epoch: usize) -> Self {
+        assert!(start_epoch >= 1, "SWA start epoch must be at least 1.");
+        self.swa_start_epoch = Some(start_epoch);
+        self
+    }
+
+
+    /// Runs the forward and backward pass over a batch's sequences on several threads.
+    /// Use `Reduction::Deterministic` for bit-reproducible training with more than one thread.
+    pub fn with_parallelism(mut self, parallelism: Parallelism) -> Self {
+        self.model.parallelism = parallelism;
+        self
+    }
+
+    /// Adds a stage to the augmentation pipeline of `train`, e.g. a `NoiseAugmentation` for
+    /// typos and OCR errors or a `ParaphraseAugmentation` for back-translations. Stages run
+    /// once per `train` call in the order they were added, each over the output of the
+    /// previous one; evaluation and probe sets are never augmented.
+    pub fn with_augmentation<A: Augmenter + 'a>(mut self, augmentation: A) -> Self {
+        self.augmenters.push(Box::new(augmentation));
+        self
+    }
+
+    /// Replaces or drops random tokens of every training batch (see `WordDropout`). Only
+    /// `train` applies it: evaluation, probe sets and inference always see the full input.
+    /// The same corrupted batch goes through the forward and backward pass, so the gradients
+    /// match the loss, and the reported training accuracy is measured on it.
+    pub fn with_word_dropout(mut self, word_dropout: WordDropout) -> Self {
+        self.word_dropout = Some(word_dropout);
+        self
+    }
+
+    /// Drops each value of the embedding block output with probability `rate` during the
+    /// steps of `train`, with a fresh mask per step and sequence. The model only holds the
+    /// mask seed for the duration of a step, so evaluation and inference are unaffected.
+    ///
+    /// # Returns
+    /// * The trainer, or an error if `rate` is not in [0, 1).
+    pub fn with_embedding_dropout(mut self, rate: f64) -> Result<Self, Box<dyn Error>> {
+        if !(0.0..1.0).contains(&rate) {
+            return Err(format!("Embedding dropout rate must be in [0, 1), got {}", rate).into());
+        }
+        self.embedding_dropout = rate;
+        Ok(self)
+    }
+
+    /// Ties the output layer of `pretrain_mlm` to the token embedding matrix: the logits of
+    /// a token are the dot product with its embedding, so the layer only adds biases
+    /// (`vocab_size` parameters instead of `(d_model + 1) * vocab_size`) and its gradient
+    /// updates the embeddings directly. With frozen embeddings only the biases are trained.
+    pub fn with_tied_mlm_head(mut self, tied: bool) -> Self {
+        self.tie_mlm_head = tied;
+        self
+    }
+
+    /// Trains `adversary`'s domain classifier on the pooled encoder output during `train`
+    /// and passes its reversed gradient to the encoder, so the encoder learns features that
+    /// do not tell the training set's domains apart. The training set is loaded with
+    /// `DataLoader::load_texts_with_domains`, so augmentation is not supported.
+    pub fn with_domain_adversary(mut self, adversary: DomainAdversary) -> Self {
+        self.domain_adversary = Some(adversary);
+        self
+    }
+
+    /// Freezes the token embedding matrix, e.g. to fine-tune on pretrained vectors. Frozen
+    /// embeddings drop out of `Transformer::parameters_mut` and the gradient vector, so the
+    /// updates and the EMA only cover the encoder and classification head.
+    pub fn with_frozen_embeddings(mut self, frozen: bool) -> Self {
+        self.model.embeddings.frozen = frozen;
+        self
+    }
+
+    /// Sets how the loss, layer norm statistics and gradients are summed.
+    /// `Summation::Compensated` limits rounding drift over long runs at a small extra cost.
+    pub fn with_summation(mut self, summation: Summation) -> Self {
+        self.model.set_summation(summation);
+        self
+    }
+
+    /// Writes epoch checkpoints to the run's `checkpoints/` directory and logs
+    /// per-epoch metrics to its `metrics.jsonl`.
+    pub fn with_run(mut self, run: ExperimentRun) -> Self {
+        self.run = Some(run);
+        self
+    }
+
+    /// Seeds the random draws of `train`. Every step gets its own generator, derived from the
+    /// seed and the step's position in training, so a run resumed at any epoch or batch
+    /// draws exactly what an uninterrupted run with the same seed would. Without it the
+    /// trainer picks a random seed.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Generator of the random draws of global step `step` (see `with_seed`).
+    fn step_rng(&self, step: usize) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (step as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    /// Skips the first `completed_epochs` epochs, e.g. when resuming from the
+    /// latest checkpoint of a run.
+    pub fn resume_from_epoch(mut self, completed_epochs: usize) -> Self {
+        self.start_epoch = completed_epochs;
+        self
+    }
+
+    /// Stops `train` gracefully when `signal` is triggered: the current batch is
+    /// finished, an interrupt checkpoint is saved and `interrupted` is set.
+    pub fn with_shutdown_signal(mut self, signal: ShutdownSignal) -> Self {
+        self.shutdown = Some(signal);
+        self
+    }
+
+    /// Stops training once `max_duration` of wall-clock time has passed (checked after
+    /// every batch). The lowest-loss epoch checkpoint is kept at `best_checkpoint_path`
+    /// and becomes the final model when the budget runs out.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Predicts the probe set's hand-picked examples after every epoch, printing and
+    /// logging which of them the epoch broke or fixed.
+    pub fn with_probe_set(mut self, probe_set: ProbeSet) -> Self {
+        self.probe_set = Some(probe_set);
+        self
+    }
+
+    /// Continues the EMA and SWA weights saved next to the epoch checkpoint at `checkpoint_path`
+    /// (see `ema_checkpoint_path` and `swa_checkpoint_path`). Call it after `resume_from_epoch`,
+    /// `with_ema`, `with_swa` and `with_frozen_embeddings`; missing files start a fresh average.
+    pub fn resume_shadows_from(mut self, checkpoint_path: &str) -> Result<Self, Box<dyn Error>> {
+        let frozen = self.model.embeddings.frozen;
+        let load_parameters = |path: &str| -> Result<Option<Vec<f64>>, Box<dyn Error>> {
+            if !Path::new(path).exists() {
+                return Ok(None);
+            }
+            let mut model = Transformer::load(path)?;
+            model.embeddings.frozen = frozen;
+            Ok(Some(model.parameters_mut().iter().map(|param| **param).collect()))
+        };
+
+        if self.ema_decay.is_some() {
+            if let Some(ema_params) = load_parameters(&ema_checkpoint_path(checkpoint_path))? {
+                self.ema_params = ema_params;
+            }
+        }
+        if let Some(start) = self.swa_start_epoch {
+            if let Some(swa_params) = load_parameters(&swa_checkpoint_path(checkpoint_path))? {
+                self.swa_params = swa_params;
+                self.swa_count = (self.start_epoch + 1).saturating_sub(start);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Continues an interrupted run: restores the optimizer, the seed, the EMA shadow and the SWA average and
+    /// skips the epochs and batches recorded in the `TrainingState` at `state_path`. The
+    /// model itself is loaded from `interrupted_checkpoint_path` by the caller.
+    pub fn resume_from_state(mut self, state_path: &str) -> Result<Self, Box<dyn Error>> {
+        let state: TrainingState = serde_json::from_str(&fs::read_to_string(state_path)?)?;
+        self.optimizer = state.optimizer;
+        if let Some(seed) = state.seed {
+            self.seed = seed;
+        }
+        self.start_epoch = state.epoch;
+        self.start_batch = state.completed_batches;
+        // Only restored when domain-adversarial training is still enabled.
+        if let (Some(adversary), Some(saved)) = (&mut self.domain_adversary, state.domain_adversary) {
+            *adversary = saved;
+        }
+        if let (Some(_), Some(ema_params)) = (self.ema_decay, state.ema_params) {
+            self.ema_params = ema_params;
+        }
+        if let (Some(_), Some(swa_params)) = (self.swa_start_epoch, state.swa_params) {
+            self.swa_params = swa_params;
+            self.swa_count = state.swa_count;
+        }
+        Ok(self)
+    }
 
     
 // todo: auto specify epochs
@@ -36,60 +431,630 @@ impl<'a> Trainer<'a> {
 
     
     /// Train the model over the specified number of epochs.
-    pub fn train(&mut self, dataset_path: &str, save_path: &str) {
+    ///
+    /// # Returns
+    /// * An error if the data loader's label map has more classes than the model's classification
+    ///   head, if domain-adversarial training is combined with augmentation, or if the
+    ///   dataset or its domains cannot be read.
+    pub fn train(&mut self, dataset_path: &str, save_path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(label_map) = &self.data_loader.label_map {
+            if label_map.len() > self.model.config.num_classes {
+                return Err(format!(
+                    "The label map has {} classes ({}) but the model predicts {}",
+                    label_map.len(),
+                    label_map.names().join(", "),
+                    self.model.config.num_classes
+                )
+                .into());
+            }
+        }
+   
+        if self.domain_adversary.is_some() && !self.augmenters.is_empty() {
+            return Err("Domain-adversarial training does not support augmentation".into());
+        }
    
-        let (inputs, labels) = self.data_loader.load_dataset(dataset_path).unwrap();
-        let batches = self.data_loader.create_batches(inputs, labels);
+        let d_model = self.model.config.d_model;
+        let data_loader = self.data_loader;
+        let (texts, labels, domain_ids) = profiler::time("data_loading", || -> Result<_, Box<dyn Error>> {
+            Ok(match &mut self.domain_adversary {
+                Some(adversary) => {
+                    let (texts, labels, domains) = data_loader.load_texts_with_domains(dataset_path, &adversary.domain_field)?;
+                    let domain_ids = adversary.fit_domains(&domains, d_model)?;
+                    (texts, labels, Some(domain_ids))
+                }
+                None => {
+                    let (texts, labels) = data_loader.load_augmented_texts(dataset_path, &self.augmenters)?;
+                    (texts, labels, None)
+                }
+            })
+        })?;
+        // Sliding windows change the number of examples, so windowed datasets are tokenized
+        // up front. Otherwise the first epoch trains on every batch as soon as the loader's
+        // workers have tokenized it, and keeps the batches for the later epochs.
+        let mut batches = match (&data_loader.sliding_window, &domain_ids) {
+            (Some(_), None) => profiler::time("data_loading", || {
+                let (inputs, labels) = data_loader.labelled_inputs(&texts, labels.clone());
+                data_loader.create_batches(inputs, labels)
+            }),
+            _ => Vec::new(),
+        };
+        let num_batches = if batches.is_empty() { texts.len().div_ceil(data_loader.batch_size) } else { batches.len() };
+        // Batches keep the example order, so domain ids are chunked alike.
+        let batch_domains = |batch_index: usize| {
+            domain_ids.as_ref().map(|ids| &ids[batch_index * data_loader.batch_size..((batch_index + 1) * data_loader.batch_size).min(ids.len())])
+        };
+        // A resumed run continues the shadows restored by `resume_from_state` or `resume_shadows_from`.
+        let resumed = self.start_epoch > 0 || self.start_batch > 0;
+        let num_parameters = self.model.parameters_mut().len();
+        if !resumed || self.ema_params.len() != num_parameters {
+            self.ema_params = self.model.parameters_mut().iter().map(|param| **param).collect();
+        }
+        if !resumed || self.swa_params.len() != num_parameters {
+            self.swa_params.clear();
+            self.swa_count = 0;
+        }
+        self.epoch_class_distributions.clear();
+        self.epoch_probe_reports.clear();
+        self.interrupted = false;
+        self.budget_exhausted = false;
+        let started = Instant::now();
+        let mut best_loss = f64::INFINITY;
+        if self.start_epoch == 0 && self.start_batch == 0 {
+            let _ = fs::remove_file(best_checkpoint_path(save_path));
+        }
 
-        for epoch in 0..self.epochs {
-            println!("Epoch {}/{}", epoch + 1, self.epochs);
+        for epoch in self.start_epoch..self.epochs {
+            LogEvent::info("trainer", format!("Epoch {}/{}", epoch + 1, self.epochs)).step(epoch + 1).emit();
 
-            let mut epoch_loss = 0.0;
-            let mut correct_predictions = 0;
-            let mut total_samples = 0;
+            let mut totals = EpochTotals::new(self.model.summation);
+            let skipped_batches = if epoch == self.start_epoch { self.start_batch } else { 0 };
 
-            for (batch_inputs, batch_labels) in &batches {
-               
-                let batch_array: Array2<f64> = Array2::from_shape_vec(
-                    (batch_inputs.len(), batch_inputs[0].len()),
-                    batch_inputs.iter().flatten().map(|&x| x as f64).collect(),
+            if batches.len() < num_batches {
+                let mut stop_error = None;
+                data_loader.stream_batches(&texts, &labels, |batch_index, batch| {
+                    batches.push(batch);
+                    if batch_index < skipped_batches {
+                        return ControlFlow::Continue(());
+                    }
+                    let position = BatchPosition { epoch, index: batch_index, num_batches };
+                    self.train_batch(&position, &batches[batch_index], batch_domains(batch_index), &mut totals);
+                    self.check_stop(&position, save_path, started).unwrap_or_else(|e| {
+                        stop_error = Some(e);
+                        ControlFlow::Break(())
+                    })
+                });
+                if let Some(e) = stop_error {
+                    return Err(e);
+                }
+            } else {
+                for (batch_index, batch) in batches.iter().enumerate().skip(skipped_batches) {
+                    let position = BatchPosition { epoch, index: batch_index, num_batches };
+                    self.train_batch(&position, batch, batch_domains(batch_index), &mut totals);
+                    if self.check_stop(&position, save_path, started)?.is_break() {
+                        break;
+                    }
+                }
+            }
+            if self.interrupted {
+                return Ok(());
+            }
+
+            let EpochTotals { batches: trained_batches, loss: epoch_loss, correct_predictions, total_samples, class_distribution, domain_loss, correct_domains } = totals;
+            let mean_loss = epoch_loss.value() / trained_batches.max(1) as f64;
+            let epoch_accuracy = correct_predictions as f64 / total_samples.max(1) as f64;
+            LogEvent::info("trainer", format!("Epoch {}: Loss: {:.4}, Accuracy: {:.2}%", epoch + 1, mean_loss, epoch_accuracy * 100.0))
+                .step(epoch + 1)
+                .metric("loss", mean_loss)
+                .metric("accuracy", epoch_accuracy)
+                .emit();
+            if let Some(adversary) = &self.domain_adversary {
+                // Near-chance domain accuracy means the encoder's features are domain-invariant.
+                let mean_domain_loss = domain_loss / trained_batches.max(1) as f64;
+                let domain_accuracy = correct_domains as f64 / total_samples.max(1) as f64;
+                LogEvent::info(
+                    "trainer",
+                    format!(
+                        "Epoch {}: Domain loss: {:.4}, Domain accuracy: {:.2}% over {} domains",
+                        epoch + 1,
+                        mean_domain_loss,
+                        domain_accuracy * 100.0,
+                        adversary.domains.len()
+                    ),
                 )
-                .unwrap();
+                .step(epoch + 1)
+                .metric("domain_loss", mean_domain_loss)
+                .metric("domain_accuracy", domain_accuracy)
+                .emit();
+            }
+            LogEvent::info("trainer", format!("Epoch {} class distribution: {}", epoch + 1, class_distribution.named_summary(self.data_loader.label_map.as_ref())))
+                .step(epoch + 1)
+                .metric("class_counts", class_distribution.counts())
+                .emit();
 
-        
-                let logits = self.model.forward(&batch_array);
+            if let Some(run) = &self.run {
+                let mut record = serde_json::json!({
+                    "stage": "train",
+                    "epoch": epoch + 1,
+                    "loss": mean_loss,
+                    "accuracy": epoch_accuracy,
+                    "class_counts": class_distribution.counts(),
+                });
+                if self.budget_exhausted {
+                    record["completed_batches"] = serde_json::json!(skipped_batches + trained_batches);
+                }
+                run.log_metrics(&record)?;
+            }
+            self.epoch_class_distributions.push(class_distribution);
 
-                let loss = Loss::cross_entropy_loss(&logits, batch_labels);
-                epoch_loss += loss;
+            // The partial epoch is logged above but gets no checkpoint, so a resumed run repeats it.
+            if self.budget_exhausted {
+                LogEvent::info(
+                    "trainer",
+                    format!("Time budget of {:?} exhausted during epoch {}, stopping training.", self.max_duration.unwrap(), epoch + 1),
+                )
+                .step(epoch + 1)
+                .metric("completed_batches", skipped_batches + trained_batches)
+                .emit();
+                break;
+            }
 
-                let gradients = Loss::gradients(&logits, batch_labels);
+            let epoch_save_path = match &self.run {
+                Some(run) => run.checkpoint_path(Some(epoch + 1)),
+                None => format!("{}_epoch_{}.json", save_path, epoch + 1),
+            };
+            self.model.save(&epoch_save_path)?;
+            if self.swa_start_epoch.is_some_and(|start| epoch + 1 >= start) {
+                self.update_swa();
+            }
+            // Saved with every epoch checkpoint so `resume_shadows_from` can continue them.
+            self.save_shadows(&epoch_save_path)?;
 
-              
-                let mut params = self.model.parameters_mut();
-                for (param, grad) in params.iter_mut().zip(gradients.iter()) {
-                    **param -= LEARNING_RATE * grad;
+            if let Some(probe_set) = &mut self.probe_set {
+                let report = probe_set.evaluate(&self.model, self.data_loader);
+                LogEvent::info("trainer", format!("Epoch {} {}", epoch + 1, report.summary()))
+                    .step(epoch + 1)
+                    .metric("probe_accuracy", report.accuracy())
+                    .metric("probe_regressions", &report.regressions)
+                    .emit();
+                if let Some(run) = &self.run {
+                    let record = serde_json::json!({
+                        "stage": "probe",
+                        "epoch": epoch + 1,
+                        "accuracy": report.accuracy(),
+                        "regressions": report.regressions,
+                        "fixed": report.fixed,
+                        "predictions": report.results,
+                    });
+                    run.log_metrics(&record)?;
                 }
+                self.epoch_probe_reports.push(report);
+            }
+
+            if self.max_duration.is_some() && mean_loss < best_loss {
+                best_loss = mean_loss;
+                self.model.save(&best_checkpoint_path(save_path))?;
+            }
+        }
+
+   
+        let best_path = best_checkpoint_path(save_path);
+        if self.budget_exhausted && Path::new(&best_path).exists() {
+            fs::copy(&best_path, save_path)?;
+        } else {
+            self.model.save(save_path)?;
+        }
+        self.save_shadows(save_path)?;
+
+        // A completed run supersedes any earlier interrupt checkpoint.
+        let _ = fs::remove_file(interrupted_checkpoint_path(save_path));
+        let _ = fs::remove_file(training_state_path(save_path));
+        Ok(())
+    }
+
+    /// Saves the model and the training progress (epoch, batch, optimizer state, seed, EMA
+    /// shadow, SWA average) so an interrupted run can be continued with `resume_from_state`.
+    fn save_interrupt_checkpoint(&self, save_path: &str, epoch: usize, completed_batches: usize) -> Result<(), Box<dyn Error>> {
+        self.model.save(&interrupted_checkpoint_path(save_path))?;
+        let state = serde_json::json!({
+            "epoch": epoch,
+            "completed_batches": completed_batches,
+            "optimizer": &self.optimizer,
+            "seed": self.seed,
+            "domain_adversary": &self.domain_adversary,
+            "ema_params": self.ema_decay.map(|_| &self.ema_params),
+            "swa_params": self.swa_start_epoch.map(|_| &self.swa_params),
+            "swa_count": self.swa_count,
+        });
+        fs::write(training_state_path(save_path), state.to_string())?;
+        Ok(())
+    }
+
+    /// Supervised contrastive pretraining of the encoder on pooled embeddings.
+    ///
+    /// Batches come from the stratified sampler so every batch holds at least
+    /// `min_per_class` examples of each class. Run before `train` so that
+    /// cross-entropy fine-tuning starts from class-clustered embeddings.
+    ///
+    /// # Returns
+    /// * An error if the dataset cannot be loaded or `batch_size` cannot hold
+    ///   `min_per_class` examples of every class.
+    pub fn pretrain_contrastive(&mut self, dataset_path: &str, epochs: usize, min_per_class: usize, temperature: f64) -> Result<(), Box<dyn Error>> {
+        let (inputs, labels) = self.data_loader.load_dataset(dataset_path)?;
+
+        for epoch in 0..epochs {
+            let batches = self.data_loader.create_stratified_batches(&inputs, &labels, min_per_class, &mut self.step_rng(epoch))?;
+            let mut epoch_loss = 0.0;
+            let mut class_distribution = ClassDistribution::new();
+
+            for (batch_inputs, batch_labels) in &batches {
+                class_distribution.record(batch_labels);
+                let (batch_array, mask_array, _) = self.batch_arrays(batch_inputs);
+
+                let pooled = self.model.pooled_output(&batch_array, Some(&mask_array));
+                epoch_loss += ContrastiveLoss::supervised_contrastive_loss(&pooled, batch_labels, temperature);
+
+                let grad_pooled = ContrastiveLoss::supervised_contrastive_gradients(&pooled, batch_labels, temperature);
+                let param_grads = self.model.backward_pooled(&batch_array, Some(&mask_array), &grad_pooled);
+                self.apply_gradients(&param_grads);
+            }
+
+            let mean_loss = epoch_loss / batches.len().max(1) as f64;
+            LogEvent::info(
+                "trainer",
+                format!(
+                    "Contrastive epoch {}/{}: Loss: {:.4}, Class distribution: {}",
+                    epoch + 1,
+                    epochs,
+                    mean_loss,
+                    class_distribution.named_summary(self.data_loader.label_map.as_ref())
+                ),
+            )
+            .step(epoch + 1)
+            .metric("contrastive_loss", mean_loss)
+            .emit();
+        }
+        Ok(())
+    }
+
+    /// Sentence-order prediction pretraining on unlabeled text.
+    ///
+    /// Consecutive sentences are encoded as `first [SEP] second` pairs, half of them
+    /// swapped, and a temporary two-class head predicts whether each pair is in its
+    /// original order. Only the encoder is kept; the auxiliary head is discarded.
+    ///
+    /// # Returns
+    /// * The mean loss of every epoch, empty when no text has two sentences.
+    /// * An error if the corpus cannot be loaded.
+    pub fn pretrain_sentence_order(&mut self, corpus_path: &str, epochs: usize) -> Result<Vec<f64>, Box<dyn Error>> {
+        let (encoded, labels) = self.data_loader.load_sentence_order_dataset(corpus_path, &mut self.step_rng(0))?;
+        if labels.is_empty() {
+            LogEvent::info("trainer", "No multi-sentence texts found, skipping sentence-order pretraining.").emit();
+            return Ok(Vec::new());
+        }
+
+        let mut order_head = ClassificationHead::new(self.model.config.d_model, 2);
+        let mut epoch_losses = Vec::with_capacity(epochs);
+
+        for epoch in 0..epochs {
+            let batches = self.data_loader.create_encoded_batches(&encoded, &labels);
+            let mut epoch_loss = 0.0;
+            let mut correct_predictions = 0;
+
+            for (batch, batch_labels) in &batches {
+                let (batch_array, mask_array) = batch.to_arrays()?;
+                // Segment ids tell the two sentences apart when the model has segment embeddings.
+                let segments = batch.token_type_array()?;
 
-            
+                let pooled = self.model.pooled_output_with_segments(&batch_array, Some(&mask_array), Some(&segments));
+                let logits = order_head.forward(&pooled);
+                epoch_loss += Loss::cross_entropy_loss(&logits, batch_labels);
                 correct_predictions += self.compute_correct_predictions(&logits, batch_labels);
-                total_samples += batch_labels.len();
+
+                let grad_logits = Loss::gradients(&logits, batch_labels);
+                let (grad_pooled, head_grads) = order_head.backward(&pooled, &grad_logits);
+                for (param, grad) in order_head.parameters_mut().into_iter().zip(head_grads.iter()) {
+                    *param -= LEARNING_RATE * grad;
+                }
+
+                let param_grads = self.model.backward_pooled_with_segments(&batch_array, Some(&mask_array), Some(&segments), &grad_pooled);
+                self.apply_gradients(&param_grads);
             }
 
-            let epoch_accuracy = correct_predictions as f64 / total_samples as f64;
-            println!(
-                "Epoch {}: Loss: {:.4}, Accuracy: {:.2}%",
-                epoch + 1,
-                epoch_loss / batches.len() as f64,
-                epoch_accuracy * 100.0
-            );
+            let (mean_loss, accuracy) = (epoch_loss / batches.len() as f64, correct_predictions as f64 / labels.len() as f64);
+            LogEvent::info(
+                "trainer",
+                format!("Sentence-order epoch {}/{}: Loss: {:.4}, Accuracy: {:.2}%", epoch + 1, epochs, mean_loss, 100.0 * accuracy),
+            )
+            .step(epoch + 1)
+            .metric("sentence_order_loss", mean_loss)
+            .metric("sentence_order_accuracy", accuracy)
+            .emit();
+            epoch_losses.push(mean_loss);
+        }
+        Ok(epoch_losses)
+    }
 
-         
-            let epoch_save_path = format!("{}_epoch_{}.json", save_path, epoch + 1);
-            self.model.save(&epoch_save_path).expect("Failed to save model");
+    /// Masked language modelling on an unlabeled corpus (domain-adaptive pretraining).
+    ///
+    /// Tokens are corrupted with `TokenMasker` and a temporary output layer over the
+    /// vocabulary predicts the original ids from the encoder output at the selected
+    /// positions. The tokenizer's vocabulary must match the model's and contain `[MASK]`.
+    ///
+    /// # Returns
+    /// * An error when the corpus cannot be read or the vocabulary has no `[MASK]`.
+    pub fn pretrain_mlm(&mut self, corpus_path: &str, epochs: usize) -> Result<(), Box<dyn Error>> {
+        let texts = self.data_loader.load_texts(corpus_path)?;
+        let inputs = self.data_loader.tokenize_texts(&texts);
+        if inputs.is_empty() {
+            LogEvent::info("trainer", "Empty corpus, skipping masked language modelling.").emit();
+            return Ok(());
         }
 
-   
-        self.model.save(save_path).expect("Failed to save final model");
+        let vocab = &self.data_loader.tokenizer.vocab;
+        let mask_id = *vocab.get(MASK_TOKEN).ok_or("Vocabulary must contain [MASK] for MLM pretraining")?;
+        let vocab_size = self.model.embeddings.vocab_size();
+        let masker = TokenMasker::new(mask_id, vocab[PAD_TOKEN], vocab_size, MLM_MASK_PROBABILITY);
+        let mut mlm_head = if self.tie_mlm_head {
+            MlmHead::Tied(TiedOutputHead::new(vocab_size))
+        } else {
+            MlmHead::Untied(ClassificationHead::new(self.model.config.d_model, vocab_size))
+        };
+        LogEvent::info("trainer", format!("MLM output layer: {} parameters{}", mlm_head.num_parameters(), if self.tie_mlm_head { " (tied to the embeddings)" } else { "" })).emit();
+
+        for epoch in 0..epochs {
+            // Seeded like the steps of `train`, so pretraining repeats under the same trainer seed.
+            let mut rng = self.step_rng(epoch);
+            let mut epoch_loss = 0.0;
+            let mut num_batches = 0;
+
+            for batch in inputs.chunks(self.data_loader.batch_size) {
+                let (masked_batch, targets): (Vec<Vec<usize>>, Vec<Vec<Option<usize>>>) =
+                    batch.iter().map(|sequence| masker.mask(sequence, &mut rng)).unzip();
+
+                // Gather the encoder outputs at every selected position of the batch.
+                let mut positions = Vec::new();
+                let mut labels = Vec::new();
+                let mut selected = Vec::new();
+                let (batch_array, mask_array, _) = self.batch_arrays(&masked_batch);
+                for (i, (sequence, sequence_targets)) in masked_batch.iter().zip(targets.iter()).enumerate() {
+                    let encoded = self.model.encode_sequence_masked(sequence, Some(&mask_array.row(i).to_owned()));
+                    for (position, target) in sequence_targets.iter().enumerate() {
+                        if let Some(original) = target {
+                            positions.push((i, position));
+                            labels.push(*original);
+                            selected.extend(encoded.row(position).iter().copied());
+                        }
+                    }
+                }
+                if labels.is_empty() {
+                    continue;
+                }
+
+                let hidden = Array2::from_shape_vec((labels.len(), self.model.config.d_model), selected).unwrap();
+                let logits = mlm_head.forward(&hidden, &self.model.embeddings);
+                epoch_loss += Loss::cross_entropy_loss(&logits, &labels);
+                num_batches += 1;
+
+                let grad_logits = Loss::gradients(&logits, &labels);
+                let (grad_selected, grad_embeddings, head_grads) = mlm_head.backward(&hidden, &grad_logits, &self.model.embeddings);
+                for (param, grad) in mlm_head.parameters_mut().into_iter().zip(head_grads.iter()) {
+                    *param -= LEARNING_RATE * grad;
+                }
+
+                let mut grad_hidden = vec![Array2::zeros((batch_array.ncols(), self.model.config.d_model)); masked_batch.len()];
+                for (&(i, position), grad) in positions.iter().zip(grad_selected.outer_iter()) {
+                    grad_hidden[i].row_mut(position).assign(&grad);
+                }
+                let mut param_grads = self.model.backward_hidden(&batch_array, Some(&mask_array), &grad_hidden);
+                // The tied weights are the embedding matrix, the last parameters of the model.
+                if let (Some(grad_embeddings), false) = (grad_embeddings, self.model.embeddings.frozen) {
+                    let offset = param_grads.len() - self.model.embeddings.num_parameters();
+                    for (grad, tied_grad) in param_grads[offset..].iter_mut().zip(grad_embeddings.iter()) {
+                        *grad += tied_grad;
+                    }
+                }
+                self.apply_gradients(&param_grads);
+            }
+
+            let mean_loss = epoch_loss / num_batches.max(1) as f64;
+            LogEvent::info("trainer", format!("MLM epoch {}/{}: Loss: {:.4}", epoch + 1, epochs, mean_loss))
+                .step(epoch + 1)
+                .metric("mlm_loss", mean_loss)
+                .emit();
+        }
+        Ok(())
+    }
+
+    /// Debugging mode: trains repeatedly on the first batch of the dataset and checks
+    /// that the loss approaches zero. A model that cannot memorise a single batch
+    /// points to a bug in the backward pass or in a new layer.
+    ///
+    /// # Arguments
+    /// * `dataset_path` - Dataset whose first batch (up to `batch_size` examples) is used.
+    /// * `batch_size` - Number of examples to memorise.
+    /// * `steps` - Number of gradient steps.
+    /// * `learning_rate` - SGD step size, typically much larger than `LEARNING_RATE`.
+    /// * `target_loss` - Loss the batch must reach.
+    ///
+    /// # Returns
+    /// * The loss after every step, or an error when `target_loss` was not reached.
+    pub fn overfit_single_batch(
+        &mut self,
+        dataset_path: &str,
+        batch_size: usize,
+        steps: usize,
+        learning_rate: f64,
+        target_loss: f64,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        let (mut inputs, mut labels) = self.data_loader.load_dataset(dataset_path)?;
+        inputs.truncate(batch_size);
+        labels.truncate(batch_size);
+        if inputs.is_empty() {
+            return Err(format!("{} contains no examples", dataset_path).into());
+        }
+        let (batch_array, mask_array, segments) = self.batch_arrays(&inputs);
+
+        let mut losses = Vec::with_capacity(steps);
+        for _ in 0..steps {
+            let logits = self.model.forward_with_segments(&batch_array, Some(&mask_array), Some(&segments));
+            losses.push(Loss::cross_entropy_loss(&logits, &labels));
+
+            let gradients = Loss::gradients(&logits, &labels);
+            let param_grads = self.model.backward_with_segments(&batch_array, Some(&mask_array), Some(&segments), &gradients);
+            self.apply_gradients_with_rate(&param_grads, learning_rate);
+        }
+
+        let logits = self.model.forward_with_segments(&batch_array, Some(&mask_array), Some(&segments));
+        let final_loss = Loss::cross_entropy_loss(&logits, &labels);
+        losses.push(final_loss);
+        LogEvent::info(
+            "trainer",
+            format!("Overfit check: loss {:.4} -> {:.4} after {} steps on {} examples", losses[0], final_loss, steps, labels.len()),
+        )
+        .step(steps)
+        .metric("initial_loss", losses[0])
+        .metric("final_loss", final_loss)
+        .emit();
+
+        if final_loss > target_loss {
+            return Err(format!("loss {:.4} did not reach {:.4} after {} steps", final_loss, target_loss, steps).into());
+        }
+        Ok(losses)
+    }
+
+    /// Runs one training step of `train` on a batch and adds its results to `totals`.
+    fn train_batch(&mut self, position: &BatchPosition, batch: &Batch, batch_domains: Option<&[usize]>, totals: &mut EpochTotals) {
+        let (batch_inputs, batch_labels) = batch;
+        totals.batches += 1;
+        totals.class_distribution.record(batch_labels);
+        let step = position.epoch * position.num_batches + position.index;
+        let mut rng = self.step_rng(step);
+        let step_scope = profiler::scope("step");
+        profiler::record_step();
+
+        let (batch_array, mask_array, segments) = profiler::time("data_loading", || match &self.word_dropout {
+            Some(word_dropout) => self.batch_arrays(&self.corrupt_batch(word_dropout, batch_inputs, &mut rng)),
+            None => self.batch_arrays(batch_inputs),
+        });
+        if self.embedding_dropout > 0.0 {
+            self.model.embedding_dropout = Some(EmbeddingDropout { rate: self.embedding_dropout, seed: rng.gen() });
+        }
+
+        let (logits, grad_pooled_domain) = profiler::time("forward", || match (&mut self.domain_adversary, batch_domains) {
+            (Some(adversary), Some(batch_domains)) => {
+                let pooled = self.model.pooled_output_with_segments(&batch_array, Some(&mask_array), Some(&segments));
+                let progress = step as f64 / (self.epochs * position.num_batches).max(1) as f64;
+                let (loss, correct, grad_pooled) = adversary.step(&pooled, batch_domains, progress);
+                totals.domain_loss += loss;
+                totals.correct_domains += correct;
+                (self.model.classification_head.forward(&pooled), Some(grad_pooled))
+            }
+            _ => (self.model.forward_with_segments(&batch_array, Some(&mask_array), Some(&segments)), None),
+        });
+
+        let (loss, gradients) = profiler::time("loss", || {
+            let loss = Loss::cross_entropy_loss_with(&logits, batch_labels, self.model.summation);
+            (loss, Loss::gradients(&logits, batch_labels))
+        });
+        totals.loss.add(loss);
+
+        let param_grads = profiler::time("backward", || {
+            self.model.backward_with_auxiliary(&batch_array, Some(&mask_array), Some(&segments), &gradients, grad_pooled_domain.as_ref())
+        });
+        self.model.embedding_dropout = None;
+        profiler::time("optimizer", || {
+            self.apply_gradients(&param_grads);
+            self.update_ema();
+        });
+        drop(step_scope);
+
+        totals.correct_predictions += self.compute_correct_predictions(&logits, batch_labels);
+        totals.total_samples += batch_labels.len();
+    }
+
+    /// Checks after a batch of `train` whether a shutdown was requested, saving an interrupt
+    /// checkpoint, or the time budget ran out, and sets `interrupted` or `budget_exhausted`.
+    fn check_stop(&mut self, position: &BatchPosition, save_path: &str, started: Instant) -> Result<ControlFlow<()>, Box<dyn Error>> {
+        if self.shutdown.as_ref().is_some_and(ShutdownSignal::is_requested) {
+            self.save_interrupt_checkpoint(save_path, position.epoch, position.index + 1)
+                .map_err(|e| format!("Failed to save interrupt checkpoint: {}", e))?;
+            LogEvent::info(
+                "trainer",
+                format!(
+                    "Shutdown requested: saved {} after batch {} of epoch {}",
+                    interrupted_checkpoint_path(save_path),
+                    position.index + 1,
+                    position.epoch + 1
+                ),
+            )
+            .step(position.epoch + 1)
+            .metric("completed_batches", position.index + 1)
+            .emit();
+            self.interrupted = true;
+            return Ok(ControlFlow::Break(()));
+        }
+
+        if self.max_duration.is_some_and(|budget| started.elapsed() >= budget) {
+            self.budget_exhausted = true;
+            return Ok(ControlFlow::Break(()));
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Converts a batch of padded sequences into the token, attention-mask and segment-id arrays.
+    fn batch_arrays(&self, batch_inputs: &[Vec<usize>]) -> ModelInputs {
+        self.data_loader.model_inputs(batch_inputs)
+    }
+
+    /// Applies word dropout to every sequence of a batch, never touching special tokens.
+    fn corrupt_batch<R: Rng>(&self, word_dropout: &WordDropout, batch_inputs: &[Vec<usize>], rng: &mut R) -> Vec<Vec<usize>> {
+        let vocab = &self.data_loader.tokenizer.vocab;
+        let pad_id = vocab.get(PAD_TOKEN).copied().unwrap_or(0);
+        let unk_id = vocab.get(UNK_TOKEN).copied().unwrap_or(pad_id);
+        let protected: Vec<usize> = [CLS_TOKEN, SEP_TOKEN, MASK_TOKEN].iter().filter_map(|token| vocab.get(*token).copied()).collect();
+        batch_inputs.iter().map(|sequence| word_dropout.apply(sequence, unk_id, pad_id, &protected, rng)).collect()
+    }
+
+    fn apply_gradients(&mut self, gradients: &[f64]) {
+        self.apply_gradients_with_rate(gradients, LEARNING_RATE);
+    }
+
+    fn apply_gradients_with_rate(&mut self, gradients: &[f64], learning_rate: f64) {
+        for (param, grad) in self.model.parameters_mut().into_iter().zip(gradients.iter()) {
+            *param -= learning_rate * grad;
+        }
+    }
+
+    fn update_ema(&mut self) {
+        if let Some(decay) = self.ema_decay {
+            for (shadow, param) in self.ema_params.iter_mut().zip(self.model.parameters_mut()) {
+                *shadow = decay * *shadow + (1.0 - decay) * *param;
+            }
+        }
+    }
+
+    /// Adds the current parameters to the SWA running mean.
+    fn update_swa(&mut self) {
+        if self.swa_count == 0 {
+            self.swa_params = self.model.parameters_mut().iter().map(|param| **param).collect();
+        } else {
+            let count = (self.swa_count + 1) as f64;
+            for (average, param) in self.swa_params.iter_mut().zip(self.model.parameters_mut()) {
+                *average += (*param - *average) / count;
+            }
+        }
+        self.swa_count += 1;
+    }
+
+    /// Saves the EMA shadow and the SWA average, when kept, next to the checkpoint at `save_path`.
+    fn save_shadows(&mut self, save_path: &str) -> Result<(), std::io::Error> {
+        if self.ema_decay.is_some() {
+            save_with_parameters(&mut self.model, &mut self.ema_params, &ema_checkpoint_path(save_path))?;
+        }
+        if self.swa_count > 0 {
+            save_with_parameters(&mut self.model, &mut self.swa_params, &swa_checkpoint_path(save_path))?;
+        }
+        Ok(())
     }
 
   
@@ -101,7 +1066,7 @@ impl<'a> Trainer<'a> {
                 let predicted_label = logit
                     .iter()
                     .enumerate()
-                    .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                    .max_by(|a, b| a.1.total_cmp(b.1))
                     .map(|(index, _)| index)
                     .unwrap_or(0);
                 predicted_label == label
@@ -110,3 +1075,475 @@ impl<'a> Trainer<'a> {
     }
 }
 
+
+/// Output layer over the vocabulary used by `pretrain_mlm`.
+enum MlmHead {
+    Untied(ClassificationHead),
+    Tied(TiedOutputHead),
+}
+
+impl MlmHead {
+    fn forward(&self, hidden: &Array2<f64>, embeddings: &Embeddings) -> Array2<f64> {
+        match self {
+            MlmHead::Untied(head) => head.forward(hidden),
+            MlmHead::Tied(head) => head.forward(hidden, embeddings),
+        }
+    }
+
+    /// Gradient of `hidden`, of the embedding matrix when tied, and of the head's own parameters.
+    fn backward(&self, hidden: &Array2<f64>, grad_logits: &Array2<f64>, embeddings: &Embeddings) -> (Array2<f64>, Option<Array2<f64>>, Vec<f64>) {
+        match self {
+            MlmHead::Untied(head) => {
+                let (grad_hidden, head_grads) = head.backward(hidden, grad_logits);
+                (grad_hidden, None, head_grads)
+            }
+            MlmHead::Tied(head) => {
+                let (grad_hidden, grad_embeddings, head_grads) = head.backward(hidden, grad_logits, embeddings);
+                (grad_hidden, Some(grad_embeddings), head_grads)
+            }
+        }
+    }
+
+    fn num_parameters(&self) -> usize {
+        match self {
+            MlmHead::Untied(head) => head.num_parameters(),
+            MlmHead::Tied(head) => head.num_parameters(),
+        }
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut f64> {
+        match self {
+            MlmHead::Untied(head) => head.parameters_mut(),
+            MlmHead::Tied(head) => head.parameters_mut(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{synthetic_dataset_path, temp_path, tiny_config, tiny_vocab};
+    use crate::model_optimizer::optimizer::OptimizerType;
+    use crate::tokenization::tokenizer::Tokenizer;
+    use crate::transformer::TransformerConfig;
+    use crate::data_handler::masking::WordDropoutMode;
+    use crate::configurration::data_schema::DataSchema;
+    use crate::data_handler::label_map::LabelMap;
+    use crate::augmentation::noise::{NoiseAugmentation, TextNoise, ALL_NOISE_OPERATIONS};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_shutdown_saves_interrupt_checkpoint() {
+        let dataset_path = &synthetic_dataset_path("shutdown_saves_interrupt_checkpoint_dataset.json");
+        let vocab = tiny_vocab(&[]);
+        let config = tiny_config(2);
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let data_loader = DataLoader::new(&tokenizer);
+
+        let signal = ShutdownSignal::new();
+        signal.request();
+        let mut trainer = Trainer::new(Transformer::new(config, vocab), Optimizer::new(OptimizerType::Sgd), &data_loader, 3)
+            .with_shutdown_signal(signal);
+
+        let save_path = &temp_path("shutdown_test_model.json");
+        trainer.train(dataset_path, save_path).unwrap();
+        let _ = fs::remove_file(dataset_path);
+
+        let state: Result<TrainingState, _> = serde_json::from_str(&fs::read_to_string(training_state_path(save_path)).unwrap());
+        let checkpoint_saved = Path::new(&interrupted_checkpoint_path(save_path)).exists();
+        let final_saved = Path::new(save_path).exists();
+        let _ = fs::remove_file(interrupted_checkpoint_path(save_path));
+        let _ = fs::remove_file(training_state_path(save_path));
+
+        assert!(trainer.interrupted);
+        assert!(checkpoint_saved);
+        assert!(!final_saved);
+        let state = state.unwrap();
+        assert_eq!((state.epoch, state.completed_batches), (0, 1));
+    }
+
+    #[test]
+    fn test_unwritable_checkpoints_fail_training() {
+        let dataset_path = &synthetic_dataset_path("unwritable_checkpoints_fail_training_dataset.json");
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let data_loader = DataLoader::new(&tokenizer);
+        let save_path = "/nonexistent/checkpoints/model.json";
+
+        let mut trainer = Trainer::new(Transformer::new(tiny_config(2), vocab.clone()), Optimizer::new(OptimizerType::Sgd), &data_loader, 1);
+        assert!(trainer.train(dataset_path, save_path).is_err());
+
+        let signal = ShutdownSignal::new();
+        signal.request();
+        let mut interrupted = Trainer::new(Transformer::new(tiny_config(2), vocab), Optimizer::new(OptimizerType::Sgd), &data_loader, 1)
+            .with_shutdown_signal(signal);
+        let error = interrupted.train(dataset_path, save_path).unwrap_err();
+        let _ = fs::remove_file(dataset_path);
+        assert!(error.to_string().contains("interrupt checkpoint"), "{}", error);
+    }
+
+    #[test]
+    fn test_resumed_training_reproduces_random_draws() {
+        let dataset_path = &synthetic_dataset_path("resumed_training_reproduces_random_draws_dataset.json");
+        let vocab = tiny_vocab(&[]);
+        let config = tiny_config(2);
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let data_loader = DataLoader::new(&tokenizer).with_batch_size(4);
+        let initial_path = &temp_path("seeded_resume_test_initial.json");
+        Transformer::<f64>::new(config, vocab).save(initial_path).unwrap();
+        let trainer = |model_path: &str| {
+            Trainer::new(Transformer::load(model_path).unwrap(), Optimizer::new(OptimizerType::Sgd), &data_loader, 1)
+                .with_ema(0.9)
+                .with_embedding_dropout(0.5)
+                .unwrap()
+        };
+
+        let uninterrupted_path = &temp_path("seeded_resume_test_uninterrupted.json");
+        let mut uninterrupted = trainer(initial_path).with_seed(7);
+        uninterrupted.train(dataset_path, uninterrupted_path).unwrap();
+
+        // Interrupted after the first batch, then resumed by a trainer with another seed.
+        let resumed_path = &temp_path("seeded_resume_test_resumed.json");
+        let signal = ShutdownSignal::new();
+        signal.request();
+        trainer(initial_path).with_seed(7).with_shutdown_signal(signal).train(dataset_path, resumed_path).unwrap();
+        let mut resumed = trainer(&interrupted_checkpoint_path(resumed_path))
+            .with_seed(8)
+            .resume_from_state(&training_state_path(resumed_path))
+            .unwrap();
+        assert_eq!(resumed.seed, 7);
+        resumed.train(dataset_path, resumed_path).unwrap();
+
+        let other_seed_path = &temp_path("seeded_resume_test_other_seed.json");
+        let mut other_seed = trainer(initial_path).with_seed(8);
+        other_seed.train(dataset_path, other_seed_path).unwrap();
+        let _ = fs::remove_file(dataset_path);
+
+        let parameters = |trainer: &mut Trainer| trainer.model.parameters_mut().into_iter().map(|p| *p).collect::<Vec<f64>>();
+        let expected = parameters(&mut uninterrupted);
+        let max_difference = |trainer: &mut Trainer| parameters(trainer).iter().zip(&expected).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+        let (resumed_difference, other_seed_difference) = (max_difference(&mut resumed), max_difference(&mut other_seed));
+        // The EMA continues from the shadow saved with the interrupt checkpoint.
+        let ema_difference = resumed.ema_params.iter().zip(&uninterrupted.ema_params).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+        for path in [initial_path, uninterrupted_path, resumed_path, other_seed_path] {
+            let _ = fs::remove_file(path);
+            let _ = fs::remove_file(format!("{}_epoch_1.json", path));
+            let _ = fs::remove_file(ema_checkpoint_path(path));
+            let _ = fs::remove_file(ema_checkpoint_path(&format!("{}_epoch_1.json", path)));
+        }
+        // Up to the JSON round trip of the interrupt checkpoint.
+        assert!(resumed_difference < 1e-12, "resumed run differs by {}", resumed_difference);
+        assert!(ema_difference < 1e-12, "resumed EMA differs by {}", ema_difference);
+        assert!(other_seed_difference > 1e-9);
+    }
+
+    #[test]
+    fn test_swa_averages_epochs_and_resumes_from_epoch_checkpoint() {
+        let dataset_path = &synthetic_dataset_path("swa_averages_epochs_and_resumes_from_epoch_checkpoint_dataset.json");
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let data_loader = DataLoader::new(&tokenizer).with_batch_size(4);
+        let initial_path = &temp_path("swa_test_initial.json");
+        Transformer::<f64>::new(tiny_config(2), vocab).save(initial_path).unwrap();
+        let trainer = |model_path: &str| {
+            Trainer::new(Transformer::load(model_path).unwrap(), Optimizer::new(OptimizerType::Sgd), &data_loader, 3)
+                .with_seed(5)
+                .with_ema(0.9)
+                .with_swa(2)
+        };
+
+        let full_path = &temp_path("swa_test_full.json");
+        let mut full = trainer(initial_path);
+        full.train(dataset_path, full_path).unwrap();
+        let epoch_parameters = |epoch: usize| {
+            let mut model = Transformer::load(&format!("{}_epoch_{}.json", full_path, epoch)).unwrap();
+            model.parameters_mut().into_iter().map(|p| *p).collect::<Vec<f64>>()
+        };
+        let expected_average: Vec<f64> = epoch_parameters(2).iter().zip(epoch_parameters(3)).map(|(a, b)| (a + b) / 2.0).collect();
+        let mut saved_swa = Transformer::load(&swa_checkpoint_path(full_path)).unwrap();
+        let saved_swa: Vec<f64> = saved_swa.parameters_mut().into_iter().map(|p| *p).collect();
+
+        // Resumed from the second epoch's checkpoint, with the shadows saved next to it.
+        let epoch_2_path = format!("{}_epoch_2.json", full_path);
+        let resumed_path = &temp_path("swa_test_resumed.json");
+        let mut resumed = trainer(&epoch_2_path).resume_from_epoch(2).resume_shadows_from(&epoch_2_path).unwrap();
+        assert_eq!(resumed.swa_count, 1);
+        resumed.train(dataset_path, resumed_path).unwrap();
+        let _ = fs::remove_file(dataset_path);
+
+        let max_difference = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+        let average_difference = max_difference(&full.swa_params, &expected_average);
+        let saved_difference = max_difference(&saved_swa, &full.swa_params);
+        let resumed_swa_difference = max_difference(&resumed.swa_params, &full.swa_params);
+        let resumed_ema_difference = max_difference(&resumed.ema_params, &full.ema_params);
+        for path in [initial_path.to_string(), full_path.to_string(), resumed_path.to_string()] {
+            for epoch_path in [path.clone(), format!("{}_epoch_1.json", path), format!("{}_epoch_2.json", path), format!("{}_epoch_3.json", path)] {
+                let _ = fs::remove_file(ema_checkpoint_path(&epoch_path));
+                let _ = fs::remove_file(swa_checkpoint_path(&epoch_path));
+                let _ = fs::remove_file(epoch_path);
+            }
+        }
+
+        assert_eq!((full.swa_count, resumed.swa_count), (2, 2));
+        assert!(average_difference < 1e-12, "SWA differs from the epoch mean by {}", average_difference);
+        assert!(saved_difference < 1e-12);
+        assert!(resumed_swa_difference < 1e-12, "resumed SWA differs by {}", resumed_swa_difference);
+        assert!(resumed_ema_difference < 1e-12, "resumed EMA differs by {}", resumed_ema_difference);
+    }
+
+    #[test]
+    fn test_streamed_batches_train_like_sequential_loading() {
+        let dataset_path = &synthetic_dataset_path("streamed_batches_train_like_sequential_loading_dataset.json");
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let initial_path = &temp_path("streaming_test_initial.json");
+        Transformer::<f64>::new(tiny_config(2), vocab).save(initial_path).unwrap();
+        let train = |workers: usize| {
+            let data_loader = DataLoader::new(&tokenizer).with_batch_size(1).with_workers(workers);
+            let mut trainer = Trainer::new(Transformer::load(initial_path).unwrap(), Optimizer::new(OptimizerType::Sgd), &data_loader, 2).with_seed(3);
+            let save_path = &temp_path(&format!("streaming_test_{}_workers.json", workers));
+            trainer.train(dataset_path, save_path).unwrap();
+            for path in [save_path.to_string(), format!("{}_epoch_1.json", save_path), format!("{}_epoch_2.json", save_path)] {
+                let _ = fs::remove_file(path);
+            }
+            trainer.model.parameters_mut().into_iter().map(|p| *p).collect::<Vec<f64>>()
+        };
+
+        let (sequential, streamed) = (train(1), train(3));
+        let _ = fs::remove_file(initial_path);
+        let _ = fs::remove_file(dataset_path);
+        assert_eq!(sequential, streamed);
+    }
+
+    #[test]
+    fn test_time_budget_stops_training() {
+        let dataset_path = &synthetic_dataset_path("time_budget_stops_training_dataset.json");
+        let vocab = tiny_vocab(&[]);
+        let config = tiny_config(2);
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let data_loader = DataLoader::new(&tokenizer);
+
+        let mut trainer = Trainer::new(Transformer::new(config, vocab), Optimizer::new(OptimizerType::Sgd), &data_loader, 5)
+            .with_max_duration(Duration::ZERO);
+
+        let save_path = &temp_path("time_budget_test_model.json");
+        trainer.train(dataset_path, save_path).unwrap();
+        let _ = fs::remove_file(dataset_path);
+        let final_saved = Path::new(save_path).exists();
+        let epoch_saved = Path::new(&format!("{}_epoch_1.json", save_path)).exists();
+        let _ = fs::remove_file(save_path);
+
+        assert!(trainer.budget_exhausted);
+        // The partial first epoch is still reported, but not checkpointed.
+        assert_eq!(trainer.epoch_class_distributions.len(), 1);
+        assert!(!epoch_saved);
+        assert!(final_saved);
+    }
+
+    #[test]
+    fn test_label_map_wider_than_the_head_is_an_error() {
+        let dataset_path = &synthetic_dataset_path("label_map_wider_than_the_head_is_an_error_dataset.json");
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let data_loader = DataLoader::new(&tokenizer).with_label_map(LabelMap::from_names(&["a", "b", "c"]).unwrap());
+        let mut trainer = Trainer::new(Transformer::new(tiny_config(2), vocab), Optimizer::new(OptimizerType::Sgd), &data_loader, 1);
+
+        let error = trainer.train(dataset_path, &temp_path("label_map_error_model.json")).unwrap_err();
+        let _ = fs::remove_file(dataset_path);
+        assert!(error.to_string().contains("The label map has 3 classes (a, b, c) but the model predicts 2"));
+    }
+
+    #[test]
+    fn test_contrastive_pretraining_needs_room_for_every_class() {
+        let dataset_path = &synthetic_dataset_path("contrastive_pretraining_needs_room_for_every_class_dataset.json");
+        let vocab = tiny_vocab(&[]);
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let data_loader = DataLoader::new(&tokenizer).with_batch_size(4);
+        let mut trainer = Trainer::new(Transformer::new(tiny_config(2), vocab), Optimizer::new(OptimizerType::Sgd), &data_loader, 1).with_seed(0);
+
+        trainer.pretrain_contrastive(dataset_path, 1, 2, 0.1).unwrap();
+        let error = trainer.pretrain_contrastive(dataset_path, 1, 3, 0.1).unwrap_err();
+        let _ = fs::remove_file(dataset_path);
+        assert!(error.to_string().contains("cannot hold"));
+    }
+
+    #[test]
+    fn test_domain_adversarial_training() {
+        let vocab = tiny_vocab(&["refund", "late"]);
+        let config = tiny_config(2);
+        let tokenizer = Tokenizer::new(vocab.clone(), 4);
+        let dataset_path = &temp_path("domain_adversarial_test_dataset.json");
+        fs::write(
+            dataset_path,
+            r#"[{ "text": "refund", "label": 0, "source": "web" }, { "text": "late", "label": 1, "source": "email" },
+                { "text": "late refund", "label": 1, "source": "web" }, { "text": "refund refund", "label": 0, "source": "email" }]"#,
+        )
+        .unwrap();
+
+        // The domain field has to be read as metadata.
+        let data_loader = DataLoader::new(&tokenizer);
+        assert!(data_loader.load_texts_with_domains(dataset_path, "source").is_err());
+        let data_loader = DataLoader::new(&tokenizer).with_schema(DataSchema { metadata_fields: vec!["source".to_string()], ..DataSchema::default() });
+        let (_, labels, domains) = data_loader.load_texts_with_domains(dataset_path, "source").unwrap();
+        assert_eq!((labels, domains), (vec![0, 1, 1, 0], ["web", "email", "web", "email"].map(String::from).to_vec()));
+
+        let encoder_parameters = |model: &mut Transformer| -> Vec<f64> { model.encoder_layers[0].parameters_mut().iter().map(|param| **param).collect() };
+        let mut model = Transformer::new(config, vocab);
+        let encoder_before = encoder_parameters(&mut model);
+        let mut trainer = Trainer::new(model, Optimizer::new(OptimizerType::Sgd), &data_loader, 2).with_domain_adversary(DomainAdversary::new("source", 0.1));
+        let save_path = &temp_path("domain_adversarial_test_model.json");
+        trainer.train(dataset_path, save_path).unwrap();
+        for path in [save_path.to_string(), format!("{}_epoch_1.json", save_path), format!("{}_epoch_2.json", save_path), dataset_path.to_string()] {
+            let _ = fs::remove_file(path);
+        }
+
+        assert_eq!(trainer.domain_adversary.as_ref().unwrap().domains, vec!["email", "web"]);
+        assert_eq!(trainer.epoch_class_distributions.len(), 2);
+        assert_ne!(encoder_parameters(&mut trainer.model), encoder_before);
+    }
+
+    #[test]
+    fn test_resume_restores_domain_adversary() {
+        let vocab = tiny_vocab(&["refund", "late"]);
+        let tokenizer = Tokenizer::new(vocab.clone(), 4);
+        let dataset_path = &temp_path("domain_resume_test_dataset.json");
+        fs::write(dataset_path, r#"[{ "text": "refund", "label": 0, "source": "web" }, { "text": "late", "label": 1, "source": "email" }]"#).unwrap();
+        let data_loader = DataLoader::new(&tokenizer).with_batch_size(1).with_schema(DataSchema { metadata_fields: vec!["source".to_string()], ..DataSchema::default() });
+        let trainer = || {
+            Trainer::new(Transformer::new(tiny_config(2), vocab.clone()), Optimizer::new(OptimizerType::Sgd), &data_loader, 2)
+                .with_domain_adversary(DomainAdversary::new("source", 0.1))
+        };
+
+        let signal = ShutdownSignal::new();
+        signal.request();
+        let mut interrupted = trainer().with_shutdown_signal(signal);
+        let save_path = &temp_path("domain_resume_test_model.json");
+        let result = interrupted.train(dataset_path, save_path);
+        let resumed = trainer().resume_from_state(&training_state_path(save_path));
+        let noise = NoiseAugmentation { noise: TextNoise { char_probability: 0.1, operations: ALL_NOISE_OPERATIONS }, copies: 1, seed: 1 };
+        let augmented = trainer().with_augmentation(noise).train(dataset_path, save_path);
+        for path in [interrupted_checkpoint_path(save_path), training_state_path(save_path), dataset_path.to_string()] {
+            let _ = fs::remove_file(path);
+        }
+
+        result.unwrap();
+        // The classifier comes back as saved (up to JSON float formatting).
+        let saved: Option<DomainAdversary> = serde_json::from_str(&serde_json::to_string(&interrupted.domain_adversary).unwrap()).unwrap();
+        assert!(saved.as_ref().is_some_and(|adversary| adversary.domains == ["email", "web"]));
+        assert_eq!(serde_json::to_string(&resumed.unwrap().domain_adversary).unwrap(), serde_json::to_string(&saved).unwrap());
+        assert!(augmented.unwrap_err().to_string().contains("does not support augmentation"));
+    }
+
+    #[test]
+    fn test_overfit_single_batch() {
+        let vocab = tiny_vocab(&["win", "free", "meeting", "notes"]);
+        let config = TransformerConfig { d_model: 16, ff_dim: 32, ..tiny_config(2) };
+        let tokenizer = Tokenizer::new(vocab.clone(), 6);
+        let data_loader = DataLoader::new(&tokenizer);
+        let dataset_path = &temp_path("overfit_test_dataset.json");
+        fs::write(
+            dataset_path,
+            r#"[{ "text": "win", "label": 1 }, { "text": "meeting notes notes meeting", "label": 0 }]"#,
+        )
+        .unwrap();
+
+        let mut trainer = Trainer::new(Transformer::new(config, vocab), Optimizer::new(OptimizerType::Sgd), &data_loader, 1);
+        let result = trainer.overfit_single_batch(dataset_path, 2, 300, 0.2, OVERFIT_TARGET_LOSS);
+        fs::remove_file(dataset_path).unwrap();
+
+        let losses = result.unwrap();
+        assert!(losses.last().unwrap() < &losses[0]);
+    }
+
+    #[test]
+    fn test_frozen_embeddings_are_not_updated() {
+        let vocab = tiny_vocab(&["win", "notes"]);
+        let config = tiny_config(2);
+        let tokenizer = Tokenizer::new(vocab.clone(), 4);
+        let data_loader = DataLoader::new(&tokenizer);
+        let dataset_path = &temp_path("frozen_embeddings_test_dataset.json");
+        fs::write(dataset_path, r#"[{ "text": "win", "label": 1 }, { "text": "notes notes", "label": 0 }]"#).unwrap();
+
+        let model = Transformer::new(config, vocab);
+        let (embeddings, head_parameters) = (model.embeddings.token_embedding_matrix().clone(), model.classification_head.num_parameters());
+        let mut trainer = Trainer::new(model, Optimizer::new(OptimizerType::Sgd), &data_loader, 1).with_frozen_embeddings(true);
+        let head_before: Vec<f64> = trainer.model.classification_head.parameters_mut().iter().map(|param| **param).collect();
+        // Five steps towards an unreachable loss target; only the updates matter here.
+        let _ = trainer.overfit_single_batch(dataset_path, 2, 5, 0.2, 0.0);
+        fs::remove_file(dataset_path).unwrap();
+
+        assert_eq!(trainer.model.embeddings.token_embedding_matrix(), &embeddings);
+        let head_after: Vec<f64> = trainer.model.classification_head.parameters_mut().iter().map(|param| **param).collect();
+        assert_eq!(head_after.len(), head_parameters);
+        assert_ne!(head_after, head_before);
+    }
+
+    #[test]
+    fn test_word_dropout_keeps_special_tokens() {
+        let vocab = HashMap::from([
+            (PAD_TOKEN.to_string(), 0),
+            (UNK_TOKEN.to_string(), 1),
+            (CLS_TOKEN.to_string(), 2),
+            ("win".to_string(), 3),
+            ("notes".to_string(), 4),
+        ]);
+        let config = tiny_config(2);
+        let tokenizer = Tokenizer::new(vocab.clone(), 4);
+        let data_loader = DataLoader::new(&tokenizer);
+        let word_dropout = WordDropout { probability: 1.0, mode: WordDropoutMode::Unk };
+        let trainer = Trainer::new(Transformer::new(config, vocab), Optimizer::new(OptimizerType::Sgd), &data_loader, 1)
+            .with_word_dropout(word_dropout);
+
+        let mut rng = rand::thread_rng();
+        let corrupted = trainer.corrupt_batch(&word_dropout, &[vec![2, 3, 4, 0]], &mut rng);
+        assert_eq!(corrupted, vec![vec![2, 1, 1, 0]]);
+    }
+
+    #[test]
+    fn test_tied_mlm_head_updates_the_embeddings() {
+        let vocab = HashMap::from([
+            (PAD_TOKEN.to_string(), 0),
+            (UNK_TOKEN.to_string(), 1),
+            (MASK_TOKEN.to_string(), 2),
+            ("win".to_string(), 3),
+            ("notes".to_string(), 4),
+        ]);
+        let config = tiny_config(2);
+        let tokenizer = Tokenizer::new(vocab.clone(), 4);
+        let data_loader = DataLoader::new(&tokenizer);
+        let corpus_path = &temp_path("tied_mlm_head_test_corpus.json");
+        // 80 tokens; with the fixed seed below, some of them are masked.
+        fs::write(corpus_path, format!("[{}]", vec![r#"{ "text": "win notes win notes" }"#; 20].join(","))).unwrap();
+
+        for frozen in [false, true] {
+            let model = Transformer::new(config.clone(), vocab.clone());
+            let embeddings = model.embeddings.token_embedding_matrix().clone();
+            let mut trainer = Trainer::new(model, Optimizer::new(OptimizerType::Sgd), &data_loader, 1)
+                .with_seed(0)
+                .with_tied_mlm_head(true)
+                .with_frozen_embeddings(frozen);
+            trainer.pretrain_mlm(corpus_path, 1).unwrap();
+            assert_eq!(trainer.model.embeddings.token_embedding_matrix() == embeddings, frozen);
+        }
+        fs::remove_file(corpus_path).unwrap();
+    }
+
+    #[test]
+    fn test_sentence_order_pretraining_reduces_loss() {
+        let vocab = tiny_vocab(&[CLS_TOKEN, SEP_TOKEN, "rain", "fell", "roads", "flooded"]);
+        let config = TransformerConfig { bert_embeddings: true, ..tiny_config(2) };
+        let tokenizer = Tokenizer::new(vocab.clone(), 8);
+        let data_loader = DataLoader::new(&tokenizer).with_batch_size(2);
+        let corpus_path = &temp_path("sentence_order_test_corpus.json");
+        fs::write(corpus_path, format!("[{}]", vec![r#"{ "text": "rain fell. roads flooded." }"#; 16].join(","))).unwrap();
+
+        let mut trainer = Trainer::new(Transformer::new(config, vocab), Optimizer::new(OptimizerType::Adam), &data_loader, 1).with_seed(0);
+        let losses = trainer.pretrain_sentence_order(corpus_path, 20).unwrap();
+        let missing_corpus = trainer.pretrain_sentence_order(&temp_path("missing_sentence_order_corpus.json"), 1);
+        fs::remove_file(corpus_path).unwrap();
+
+        assert_eq!(losses.len(), 20);
+        assert!(losses[19] < losses[0], "loss went from {} to {}", losses[0], losses[19]);
+        assert!(missing_corpus.is_err());
+    }
+}
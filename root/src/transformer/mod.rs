@@ -1,4 +1,6 @@
+#[allow(clippy::module_inception)]
 pub mod transformer;
+pub mod parallelism;
 
 pub use transformer::Transformer;
 pub use transformer::TransformerConfig;
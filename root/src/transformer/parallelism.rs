@@ -0,0 +1,168 @@
+use crate::data_handler::cpu_affinity::pin_worker;
+use crate::data_handler::parallel_loader::process_in_workers;
+use crate::summation::{CompensatedVec, Summation};
+use std::ops::Range;
+use std::sync::mpsc::channel;
+use std::thread;
+
+/// Sequences per chunk in deterministic mode. Fixed, so the summation order does not
+/// depend on the number of threads.
+pub const DETERMINISTIC_CHUNK_SIZE: usize = 4;
+
+/// How per-thread gradient sums are combined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reduction {
+    /// Every thread sums an equal share of the batch and the shares are added in the
+    /// order the threads finish. Fastest, but floating-point rounding, and therefore the
+    /// trained weights, can differ from run to run when more than one thread is used.
+    Unordered,
+    /// The batch is split into chunks of `DETERMINISTIC_CHUNK_SIZE` sequences, each chunk is
+    /// summed in sequence order and the chunk sums are added in chunk order. Results are
+    /// bit-identical across runs and thread counts, at the cost of smaller work units.
+    Deterministic,
+}
+
+/// Multi-threaded execution of the per-sequence loops in the forward and backward pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parallelism {
+    pub num_threads: usize,
+    pub reduction: Reduction,
+    /// Cores the worker threads are pinned to, round-robin; empty leaves them to the OS.
+    pub cores: &'static [usize],
+}
+
+impl Default for Parallelism {
+    /// Single-threaded, which is deterministic regardless of the reduction mode.
+    fn default() -> Self {
+        Parallelism { num_threads: 1, reduction: Reduction::Unordered, cores: &[] }
+    }
+}
+
+impl Parallelism {
+    pub fn new(num_threads: usize, reduction: Reduction) -> Self {
+        Parallelism { num_threads: num_threads.max(1), reduction, cores: &[] }
+    }
+
+    /// Pins the worker threads to `cores` (see `cpu_affinity::pin_current_thread`).
+    /// Single-threaded execution runs on the calling thread and is not pinned.
+    pub fn with_cores(mut self, cores: &'static [usize]) -> Self {
+        self.cores = cores;
+        self
+    }
+
+    /// Sums per-sequence gradients over a batch.
+    ///
+    /// # Arguments
+    /// * `num_sequences` - Batch size.
+    /// * `num_parameters` - Length of the gradient vector.
+    /// * `summation` - How the partial sums are added to the total.
+    /// * `partial_sum` - Returns the gradient summed over a range of sequences, adding
+    ///   the sequences in increasing order.
+    ///
+    /// # Returns
+    /// * The gradient summed over the whole batch.
+    pub fn sum_gradients<F>(&self, num_sequences: usize, num_parameters: usize, summation: Summation, partial_sum: F) -> Vec<f64>
+    where
+        F: Fn(Range<usize>) -> Vec<f64> + Sync,
+    {
+        let mut total = CompensatedVec::zeros(num_parameters, summation);
+        match self.reduction {
+            Reduction::Deterministic => {
+                let chunks = chunk_ranges(num_sequences, DETERMINISTIC_CHUNK_SIZE);
+                // Chunk results are consumed in chunk order, whichever thread finishes first.
+                process_in_workers(&chunks, 1, self.num_threads, self.num_threads * 2, self.cores, |chunk| partial_sum(chunk[0].clone()), |_, partial| {
+                    total.add_at(0, &partial)
+                });
+            }
+            Reduction::Unordered if self.num_threads <= 1 => return partial_sum(0..num_sequences),
+            Reduction::Unordered => {
+                let chunk_size = num_sequences.div_ceil(self.num_threads).max(1);
+                let (sender, receiver) = channel();
+                thread::scope(|scope| {
+                    for (worker, range) in chunk_ranges(num_sequences, chunk_size).into_iter().enumerate() {
+                        let (sender, partial_sum, cores) = (sender.clone(), &partial_sum, self.cores);
+                        scope.spawn(move || {
+                            pin_worker(cores, worker);
+                            sender.send(partial_sum(range)).unwrap()
+                        });
+                    }
+                    drop(sender);
+                    for partial in receiver {
+                        total.add_at(0, &partial);
+                    }
+                });
+            }
+        }
+        total.into_values()
+    }
+
+    /// Applies `f` to every index in `0..len` and returns the results in index order.
+    /// The results do not depend on the number of threads.
+    pub fn map<R, F>(&self, len: usize, f: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(usize) -> R + Sync,
+    {
+        if self.num_threads <= 1 {
+            return (0..len).map(f).collect();
+        }
+        let indices: Vec<usize> = (0..len).collect();
+        let chunk_size = len.div_ceil(self.num_threads).max(1);
+        let mut results = Vec::with_capacity(len);
+        process_in_workers(&indices, chunk_size, self.num_threads, self.num_threads, self.cores, |chunk| {
+            chunk.iter().map(|&i| f(i)).collect::<Vec<R>>()
+        }, |_, chunk_results| results.extend(chunk_results));
+        results
+    }
+}
+
+fn chunk_ranges(len: usize, chunk_size: usize) -> Vec<Range<usize>> {
+    (0..len).step_by(chunk_size).map(|start| start..(start + chunk_size).min(len)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Per-sequence "gradients" with very different magnitudes, so the result of a sum
+    /// depends on the order of the additions.
+    fn partial_sum(range: Range<usize>) -> Vec<f64> {
+        let mut grads = vec![0.0; 3];
+        for i in range {
+            let scale = if i % 3 == 0 { 1e16 } else { 1.0 / (i as f64 + 1.0) };
+            grads[0] += scale;
+            grads[1] -= scale * 0.3;
+            grads[2] += (i as f64).sin();
+        }
+        grads
+    }
+
+    #[test]
+    fn test_deterministic_is_identical_across_thread_counts() {
+        let reference = Parallelism::new(1, Reduction::Deterministic).sum_gradients(37, 3, Summation::Naive, partial_sum);
+        for threads in [2, 3, 8] {
+            for _ in 0..5 {
+                let grads = Parallelism::new(threads, Reduction::Deterministic).sum_gradients(37, 3, Summation::Naive, partial_sum);
+                assert_eq!(grads.iter().map(|g| g.to_bits()).collect::<Vec<_>>(), reference.iter().map(|g| g.to_bits()).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_unordered_matches_up_to_rounding() {
+        let sequential = Parallelism::default().sum_gradients(37, 3, Summation::Naive, partial_sum);
+        let parallel = Parallelism::new(4, Reduction::Unordered).sum_gradients(37, 3, Summation::Naive, partial_sum);
+
+        assert_eq!(sequential, partial_sum(0..37));
+        for (a, b) in sequential.iter().zip(parallel.iter()) {
+            assert!((a - b).abs() <= 1e-12 * a.abs().max(1.0), "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_map_keeps_index_order() {
+        let squares = Parallelism::new(3, Reduction::Unordered).map(10, |i| i * i);
+        assert_eq!(squares, (0..10).map(|i| i * i).collect::<Vec<_>>());
+        assert!(Parallelism::new(4, Reduction::Deterministic).map(0, |i| i).is_empty());
+    }
+}
@@ -1,9 +1,20 @@
-use crate::encoder::encoder_layer::EncoderLayer;
+use crate::encoder::EncoderLayer;
 use crate::classification::ClassificationHead;
-use crate::embedding::embeddings::Embeddings;
+use crate::embedding::embeddings::{EmbeddingDropout, Embeddings};
 use std::collections::HashMap;
-use ndarray::{Array2, Axis};
+use ndarray::{Array1, Array2, ArrayView1, Axis};
+use crate::transformer::parallelism::Parallelism;
+use crate::summation::{CompensatedVec, Summation};
+use crate::profiling::profiler;
+use crate::logging::logger::{LogEvent, LogLevel};
+use crate::configurration::config::{POSITIONAL_ENCODING_VARIANT, SCALE_EMBEDDINGS_BY_SQRT_D_MODEL};
+use crate::quantization::calibration::CalibrationRanges;
+use crate::quantization::quantized_feed_forward::QuantizedFeedForward;
+use crate::quantization::quantizer::Granularity;
+use crate::numerics::Float;
+use crate::experiment::dataset_version::DatasetVersion;
 use serde::{Serialize, Deserialize};
+use std::error::Error;
 
 /// Transformer configuration parameters.
 #[derive(Clone, Serialize, Deserialize)]
@@ -14,24 +25,74 @@ pub struct TransformerConfig {
     pub ff_dim: usize,
     pub num_classes: usize, 
     pub epsilon: f64,     
+    /// BERT-style embedding block: learned segment embeddings are added to the token and
+    /// positional embeddings, and the sum is layer-normalized before the first encoder
+    /// layer. Training additionally applies `Trainer::with_embedding_dropout` to it.
+    #[serde(default)]
+    pub bert_embeddings: bool,
+    /// Adds relative-position representations (Shaw et al.) for distances up to this value to
+    /// the self-attention of every encoder layer, for tasks where local order matters more
+    /// than absolute position. The sinusoidal encodings are still added to the embeddings.
+    #[serde(default)]
+    pub relative_positions: Option<usize>,
+    /// Gives the self-attention of every encoder layer learned query, key, value and output
+    /// projections with `num_heads` heads. Without them attention works on the raw layer
+    /// input and has no parameters of its own.
+    #[serde(default)]
+    pub attention_projections: bool,
 }
 
+/// The encoder-classifier, with its weights in any `Float` precision: `Transformer<f32>`
+/// computes and saves its checkpoints in single precision. Token ids, attention masks and
+/// segment ids are passed as the f64 arrays `DataLoader` builds whatever the precision, and
+/// gradients are accumulated in f64 before they are rounded to it.
 #[derive(Serialize, Deserialize)]
-pub struct Transformer {
-    pub encoder_layers: Vec<EncoderLayer>,
-    pub classification_head: ClassificationHead,
-    pub embeddings: Embeddings,
+#[serde(bound = "A: Float")]
+pub struct Transformer<A = f64> {
+    pub encoder_layers: Vec<EncoderLayer<A>>,
+    pub classification_head: ClassificationHead<A>,
+    pub embeddings: Embeddings<A>,
     pub config: TransformerConfig,
+    /// Threads for the per-sequence loops; a runtime setting, not saved with the model.
+    #[serde(skip)]
+    pub parallelism: Parallelism,
+    /// How gradients, layer norm statistics and losses are summed; set with `set_summation`.
+    #[serde(skip)]
+    pub summation: Summation,
+    /// Dropout on the embedding block output, set by the trainer for the duration of a
+    /// training step; `None` (evaluation, inference) keeps every value.
+    #[serde(skip)]
+    pub embedding_dropout: Option<EmbeddingDropout>,
+    /// Version of the dataset the weights were trained on, so a checkpoint copied out of
+    /// its run directory still identifies its data; `None` for untrained models and
+    /// checkpoints saved before it was recorded.
+    #[serde(default)]
+    pub train_dataset: Option<DatasetVersion>,
 }
 
-impl Transformer {
-    /// Creates a new Transformer 
-
+impl<A: Float> Transformer<A> {
+    /// Creates a new Transformer
     pub fn new(config: TransformerConfig, vocab: HashMap<String, usize>) -> Self {
-        let embeddings = Embeddings::new(vocab, config.d_model);
+        let mut embeddings = Embeddings::new(vocab, config.d_model)
+            .with_sqrt_d_model_scaling(SCALE_EMBEDDINGS_BY_SQRT_D_MODEL)
+            .with_positional_variant(POSITIONAL_ENCODING_VARIANT);
+        if config.bert_embeddings {
+            embeddings = embeddings.with_segment_embeddings().with_layer_norm(A::cast(config.epsilon));
+        }
 
         let encoder_layers = (0..config.num_layers)
-            .map(|_| EncoderLayer::new(config.d_model, config.num_heads, config.ff_dim, config.epsilon))
+            .map(|_| {
+                let mut layer = EncoderLayer::new(config.d_model, config.ff_dim, A::cast(config.epsilon));
+                if let Some(max_distance) = config.relative_positions {
+                    // Projected attention is multi-head, and the heads share the tables.
+                    let dim = if config.attention_projections { config.d_model / config.num_heads } else { config.d_model };
+                    layer = layer.with_relative_positions(max_distance, dim);
+                }
+                if config.attention_projections {
+                    layer = layer.with_attention_projections(config.d_model, config.num_heads);
+                }
+                layer
+            })
             .collect();
 
         let classification_head = ClassificationHead::new(config.d_model, config.num_classes);
@@ -41,10 +102,31 @@ impl Transformer {
             classification_head,
             embeddings,
             config,
+            parallelism: Parallelism::default(),
+            summation: Summation::Naive,
+            embedding_dropout: None,
+            train_dataset: None,
+        }
+    }
+
+    /// Switches gradient accumulation and the layer norm statistics of every encoder
+    /// layer to `summation`.
+    pub fn set_summation(&mut self, summation: Summation) {
+        self.summation = summation;
+        for layer in &mut self.encoder_layers {
+            layer.summation = summation;
+        }
+    }
+
+    /// Returns every encoder layer to its full-precision feed-forward network.
+    pub fn dequantize_feed_forward(&mut self) {
+        for layer in &mut self.encoder_layers {
+            layer.quantized_feed_forward = None;
         }
     }
 
     pub fn save(&self, file_path: &str) -> Result<(), std::io::Error> {
+        let _scope = profiler::scope("checkpoint");
         let serialized = serde_json::to_string(self).expect("Failed to serialize model");
         std::fs::write(file_path, serialized)?;
         Ok(())
@@ -52,34 +134,328 @@ impl Transformer {
 
     pub fn load(file_path: &str) -> Result<Self, std::io::Error> {
         let data = std::fs::read_to_string(file_path)?;
-        let model: Transformer = serde_json::from_str(&data).expect("Failed to deserialize model");
+        let model: Transformer<A> = serde_json::from_str(&data).expect("Failed to deserialize model");
         Ok(model)
     }
 
+    /// Runs a single token sequence through the embeddings and encoder stack, with PAD
+    /// positions (0 in `attention_mask`, shape: [seq_len]) excluded from attention. Real tokens
+    /// get the same representations as without padding.
+    /// Returns the contextual token representations. Shape: [seq_len, d_model].
+    pub fn encode_sequence_masked(&self, tokens: &[usize], attention_mask: Option<&Array1<f64>>) -> Array2<A> {
+        self.encode_row(tokens, None, attention_mask, 0)
+    }
+
+    /// Embeds row `row` of a batch with its segment ids, applying the embedding dropout of a
+    /// training step, and runs it through the encoder stack.
+    fn encode_row(&self, tokens: &[usize], segments: Option<&[usize]>, attention_mask: Option<&Array1<f64>>, row: usize) -> Array2<A> {
+        let attention_mask = attention_mask.map(|mask| mask.mapv(A::cast));
+        let mut encoder_output = profiler::time("embeddings", || self.embed_row(tokens, segments, row));
+        for (i, layer) in self.encoder_layers.iter().enumerate() {
+            let _scope = profiler::scope_indexed("encoder_layer", i);
+            encoder_output = layer.forward_masked(&encoder_output, attention_mask.as_ref());
+        }
+        encoder_output
+    }
+
+    fn embed_row(&self, tokens: &[usize], segments: Option<&[usize]>, row: usize) -> Array2<A> {
+        let embedded = self.embeddings.encode_with_segments(tokens, segments);
+        match &self.embedding_dropout {
+            Some(dropout) => dropout.apply(embedded, row),
+            None => embedded,
+        }
+    }
+
+    /// Runs a single unpadded sequence through the model and records the output of
+    /// every stage, named `embeddings`, `encoder.{i}.{stage}`, `pooled` and `logits`.
+    /// Used to compare the model layer by layer against reference tensors.
+    pub fn layer_outputs(&self, tokens: &[usize]) -> Vec<(String, Array2<A>)> {
+        let mut outputs = Vec::new();
+        let mut hidden = self.embeddings.encode(tokens);
+        outputs.push(("embeddings".to_string(), hidden.clone()));
+
+        for (i, layer) in self.encoder_layers.iter().enumerate() {
+            for (stage, output) in layer.forward_stages(&hidden) {
+                if stage == "output" {
+                    hidden = output.clone();
+                }
+                outputs.push((format!("encoder.{}.{}", i, stage), output));
+            }
+        }
+
+        let pooled = hidden.mean_axis(Axis(0)).unwrap().insert_axis(Axis(0));
+        let logits = self.classification_head.forward(&pooled);
+        outputs.push(("pooled".to_string(), pooled));
+        outputs.push(("logits".to_string(), logits));
+        outputs
+    }
+
+    /// Mean-pools the encoder output of every sequence in the batch.
+    /// Each row of `batched_tokens` holds the token ids of one sequence.
+    /// When an attention mask is given (1 for real tokens, 0 for PAD), PAD
+    /// positions are excluded from attention and from the mean.
+    /// Returns one vector per sequence. Shape: [batch_size, d_model].
+    pub fn pooled_output(&self, batched_tokens: &Array2<f64>, attention_mask: Option<&Array2<f64>>) -> Array2<A> {
+        self.pooled_output_with_segments(batched_tokens, attention_mask, None)
+    }
+
+    /// Same as `pooled_output`, with the segment id of every position
+    /// (`EncodedBatch::token_type_array`, shape: [batch_size, seq_len]) for segment embeddings.
+    pub fn pooled_output_with_segments(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        segments: Option<&Array2<f64>>,
+    ) -> Array2<A> {
+        if let Some(mask) = attention_mask {
+            assert_eq!(mask.shape(), batched_tokens.shape(), "Attention mask must match the token batch shape.");
+        }
+        if let Some(segments) = segments {
+            assert_eq!(segments.shape(), batched_tokens.shape(), "Segment ids must match the token batch shape.");
+        }
+
+        let encoded_sequences = self.parallelism.map(batched_tokens.nrows(), |i| {
+            let token_ids: Vec<usize> = batched_tokens.row(i).iter().map(|&t| t as usize).collect();
+            let segment_ids = segments.map(|segments| segment_row(segments, i));
+            self.encode_row(&token_ids, segment_ids.as_deref(), attention_mask.map(|mask| mask.row(i).to_owned()).as_ref(), i)
+        });
+
+        let mut pooled = Array2::zeros((batched_tokens.nrows(), self.config.d_model));
+        for (i, (mut row, encoded)) in pooled.outer_iter_mut().zip(encoded_sequences).enumerate() {
+
+            match attention_mask {
+                Some(mask) => {
+                    let weights = mask.row(i).mapv(A::cast);
+                    let count = weights.sum();
+                    if count > A::zero() {
+                        let weighted_sum = weights.dot(&encoded);
+                        row.assign(&(weighted_sum / count));
+                    }
+                }
+                None => row.assign(&encoded.mean_axis(Axis(0)).unwrap()),
+            }
+        }
+        pooled
+    }
+
     /// Forward pass through the Transformer.
     /// Processes input tokens through embeddings, encoders, and a classification head.
-    pub fn forward(&self, batched_tokens: &Array2<f64>) -> Array2<f64> {
-        println!("Input tokens shape: {:?}", batched_tokens.shape());
-    
-       
-        let mut encoder_output = batched_tokens.clone();
-        for (i, layer) in self.encoder_layers.iter().enumerate() {
-            encoder_output = layer.forward(&encoder_output);
-            println!("Shape after encoder layer {}: {:?}", i + 1, encoder_output.shape());
-        }
-    
-       
-        let batch_size = encoder_output.nrows();
-        let sequence_features = encoder_output.clone();
-    
-        let logits = self.classification_head.forward(&sequence_features);
-        println!("Output logits shape: {:?}", logits.shape());
-    
+    /// `attention_mask` marks real tokens with 1 and PAD positions with 0.
+    pub fn forward(&self, batched_tokens: &Array2<f64>, attention_mask: Option<&Array2<f64>>) -> Array2<A> {
+        self.forward_with_segments(batched_tokens, attention_mask, None)
+    }
+
+    /// Same as `forward`, with the segment id of every position, e.g. for sentence pairs.
+    pub fn forward_with_segments(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        segments: Option<&Array2<f64>>,
+    ) -> Array2<A> {
+        LogEvent::new(LogLevel::Debug, "transformer", format!("Input tokens shape: {:?}", batched_tokens.shape()))
+            .metric("input_shape", batched_tokens.shape())
+            .emit();
+
+        let pooled = self.pooled_output_with_segments(batched_tokens, attention_mask, segments);
+
+        let logits = self.classification_head.forward(&pooled);
+        LogEvent::new(LogLevel::Debug, "transformer", format!("Output logits shape: {:?}", logits.shape()))
+            .metric("logits_shape", logits.shape())
+            .emit();
+
         logits
     }
 
+    /// Backward pass from the gradient of the logits.
+    ///
+    /// # Returns
+    /// Parameter gradients summed over the batch, in the same order as `parameters_mut`
+    /// (encoder layers, classification head, embeddings).
+    pub fn backward(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        grad_logits: &Array2<A>,
+    ) -> Vec<A> {
+        self.backward_with_segments(batched_tokens, attention_mask, None, grad_logits)
+    }
+
+    /// Same as `backward`, for a forward pass with segment ids (`forward_with_segments`).
+    pub fn backward_with_segments(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        segments: Option<&Array2<f64>>,
+        grad_logits: &Array2<A>,
+    ) -> Vec<A> {
+        self.backward_with_auxiliary(batched_tokens, attention_mask, segments, grad_logits, None)
+    }
+
+    /// Same as `backward_with_segments`, adding `grad_pooled_auxiliary` to the gradient the
+    /// classification head passes back to the pooled outputs, e.g. from an objective on the
+    /// sentence embeddings trained jointly with the task (see `DomainAdversary`).
+    pub fn backward_with_auxiliary(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        segments: Option<&Array2<f64>>,
+        grad_logits: &Array2<A>,
+        grad_pooled_auxiliary: Option<&Array2<A>>,
+    ) -> Vec<A> {
+        let pooled = self.pooled_output_with_segments(batched_tokens, attention_mask, segments);
+        let (mut grad_pooled, head_grads) = self.classification_head.backward(&pooled, grad_logits);
+        if let Some(auxiliary) = grad_pooled_auxiliary {
+            grad_pooled += auxiliary;
+        }
+
+        let mut grads = self.backward_pooled_with_segments(batched_tokens, attention_mask, segments, &grad_pooled);
+        let head_offset = self.num_encoder_parameters();
+        grads[head_offset..head_offset + head_grads.len()].copy_from_slice(&head_grads);
+        grads
+    }
+
+    /// Backward pass from the gradient of the pooled outputs, e.g. for objectives
+    /// defined on sentence embeddings. The classification head receives no gradient.
+    ///
+    /// # Returns
+    /// Parameter gradients summed over the batch, in the same order as `parameters_mut`.
+    pub fn backward_pooled(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        grad_pooled: &Array2<A>,
+    ) -> Vec<A> {
+        self.backward_pooled_with_segments(batched_tokens, attention_mask, None, grad_pooled)
+    }
+
+    /// Same as `backward_pooled`, for pooled outputs computed with segment ids.
+    pub fn backward_pooled_with_segments(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        segments: Option<&Array2<f64>>,
+        grad_pooled: &Array2<A>,
+    ) -> Vec<A> {
+        let seq_len = batched_tokens.ncols();
+        let grad_hidden: Vec<Array2<A>> = grad_pooled
+            .outer_iter()
+            .enumerate()
+            .map(|(i, grad)| {
+                // Mean pooling backward: every position receives its pooling weight times the pooled gradient.
+                let pooling_weights = match attention_mask {
+                    Some(mask) => {
+                        let count = mask.row(i).sum();
+                        if count > 0.0 { mask.row(i).mapv(|m| A::cast(m / count)) } else { Array1::zeros(seq_len) }
+                    }
+                    None => Array1::from_elem(seq_len, A::cast(1.0 / seq_len as f64)),
+                };
+                pooling_weights
+                    .insert_axis(Axis(1))
+                    .dot(&grad.insert_axis(Axis(0)))
+            })
+            .collect();
+
+        self.backward_hidden_with_segments(batched_tokens, attention_mask, segments, &grad_hidden)
+    }
+
+    /// Backward pass from the gradient of the final encoder output of every sequence,
+    /// e.g. for token-level objectives such as masked language modelling.
+    ///
+    /// # Arguments
+    /// * `batched_tokens` - Token ids, one sequence per row.
+    /// * `attention_mask` - The mask used in the forward pass, if any (1 for real tokens, 0 for PAD).
+    /// * `grad_hidden` - Gradient of each sequence's encoder output. Shape: [seq_len, d_model] each.
+    ///
+    /// # Returns
+    /// Parameter gradients summed over the batch, in the same order as `parameters_mut`.
+    pub fn backward_hidden(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        grad_hidden: &[Array2<A>],
+    ) -> Vec<A> {
+        self.backward_hidden_with_segments(batched_tokens, attention_mask, None, grad_hidden)
+    }
+
+    fn backward_hidden_with_segments(
+        &self,
+        batched_tokens: &Array2<f64>,
+        attention_mask: Option<&Array2<f64>>,
+        segments: Option<&Array2<f64>>,
+        grad_hidden: &[Array2<A>],
+    ) -> Vec<A> {
+        assert_eq!(batched_tokens.nrows(), grad_hidden.len(), "Expected one hidden-state gradient per sequence.");
+
+        let num_parameters = self.num_parameters();
+
+        self.parallelism.sum_gradients(batched_tokens.nrows(), num_parameters, self.summation, |sequences| {
+            let mut grads = CompensatedVec::zeros(num_parameters, self.summation);
+            for i in sequences {
+                let mask = attention_mask.map(|mask| mask.row(i).to_owned());
+                let segment_ids = segments.map(|segments| segment_row(segments, i));
+                self.accumulate_sequence_gradients(batched_tokens.row(i), segment_ids.as_deref(), mask.as_ref(), i, &grad_hidden[i], &mut grads);
+            }
+            grads.into_values()
+        })
+        .into_iter()
+        .map(A::cast)
+        .collect()
+    }
+
+    /// Adds the encoder and embedding gradients of one sequence to `grads`.
+    fn accumulate_sequence_gradients(
+        &self,
+        tokens: ArrayView1<f64>,
+        segments: Option<&[usize]>,
+        attention_mask: Option<&Array1<f64>>,
+        row: usize,
+        grad_output: &Array2<A>,
+        grads: &mut CompensatedVec,
+    ) {
+        let token_ids: Vec<usize> = tokens.iter().map(|&t| t as usize).collect();
+        let attention_mask = attention_mask.map(|mask| mask.mapv(A::cast));
+        let attention_mask = attention_mask.as_ref();
+
+        let mut layer_inputs = Vec::with_capacity(self.encoder_layers.len());
+        let mut hidden = profiler::time("embeddings", || self.embed_row(&token_ids, segments, row));
+        for (i, layer) in self.encoder_layers.iter().enumerate() {
+            let _scope = profiler::scope_indexed("encoder_layer", i);
+            let output = layer.forward_masked(&hidden, attention_mask);
+            layer_inputs.push(hidden);
+            hidden = output;
+        }
+
+        let mut grad_hidden = grad_output.clone();
+        let mut offset = self.num_encoder_parameters();
+        for (i, (layer, input)) in self.encoder_layers.iter().zip(layer_inputs.iter()).enumerate().rev() {
+            let _scope = profiler::scope_indexed("encoder_layer", i);
+            let (grad_input, layer_grads) = layer.backward_masked(input, attention_mask, &grad_hidden);
+            offset -= layer_grads.len();
+            grads.add_at(offset, &layer_grads);
+            grad_hidden = grad_input;
+        }
+
+        // Embeddings come after the classification head in `parameters_mut`; frozen ones return no gradients.
+        let _scope = profiler::scope("embeddings");
+        if let Some(dropout) = &self.embedding_dropout {
+            grad_hidden = dropout.backward(grad_hidden, row);
+        }
+        let embeddings_offset = self.num_encoder_parameters() + self.classification_head.num_parameters();
+        for (offset, row) in self.embeddings.backward(&token_ids, segments, &grad_hidden) {
+            grads.add_at(embeddings_offset + offset, row.as_slice().expect("gradient rows are contiguous"));
+        }
+    }
+
+    fn num_encoder_parameters(&self) -> usize {
+        self.encoder_layers.iter().map(|layer| layer.num_parameters()).sum()
+    }
+
+    /// Total number of trainable parameters, i.e. the length of `parameters_mut`.
+    pub fn num_parameters(&self) -> usize {
+        self.num_encoder_parameters() + self.classification_head.num_parameters() + self.embeddings.num_parameters()
+    }
 
-    pub fn parameters_mut(&mut self) -> Vec<&mut f64> {
+    pub fn parameters_mut(&mut self) -> Vec<&mut A> {
         let mut params = vec![];
 
         for layer in &mut self.encoder_layers {
@@ -93,3 +469,208 @@ impl Transformer {
     }
 }
 
+impl Transformer {
+    /// Makes the forward pass of every encoder layer use an int8 copy of its feed-forward
+    /// network (see `QuantizedFeedForward`). Calibrate `ranges` on the model before
+    /// quantizing it, since calibration runs the forward pass. Quantization starts from f64 weights.
+    ///
+    /// # Returns
+    /// * An error if `ranges` does not have one entry per encoder layer.
+    pub fn quantize_feed_forward(&mut self, granularity: Granularity, ranges: &CalibrationRanges) -> Result<(), Box<dyn Error>> {
+        if ranges.layers.len() != self.encoder_layers.len() {
+            return Err(format!("Calibrated {} layers but the model has {}", ranges.layers.len(), self.encoder_layers.len()).into());
+        }
+        for (layer, layer_ranges) in self.encoder_layers.iter_mut().zip(&ranges.layers) {
+            layer.quantized_feed_forward = Some(QuantizedFeedForward::new(&layer.feed_forward, granularity, layer_ranges));
+        }
+        Ok(())
+    }
+}
+
+/// Segment ids of row `i` of a segment array.
+fn segment_row(segments: &Array2<f64>, i: usize) -> Vec<usize> {
+    segments.row(i).iter().map(|&segment| segment as usize).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configurration::config::ATTENTION_PROJECTIONS;
+    use crate::test_utils::fixtures::{temp_path, tiny_config, tiny_vocab};
+    use crate::transformer::parallelism::Reduction;
+    use ndarray::array;
+
+    #[test]
+    fn test_masked_mean_pooling_ignores_pad() {
+        let vocab = tiny_vocab(&["hello"]);
+        let config = tiny_config(2);
+        let transformer: Transformer = Transformer::new(config, vocab);
+
+        let tokens = array![[2.0, 2.0, 0.0, 0.0]];
+        let mask = array![[1.0, 1.0, 0.0, 0.0]];
+        let pooled = transformer.pooled_output(&tokens, Some(&mask));
+
+        // PAD is excluded from attention too, so the real tokens are encoded as without padding.
+        let unpadded = transformer.encode_sequence_masked(&[2, 2], None);
+        let expected = unpadded.mean_axis(Axis(0)).unwrap();
+        for (a, b) in pooled.row(0).iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+
+        let encoded = transformer.encode_sequence_masked(&[2, 2, 0, 0], None);
+        let unmasked = transformer.pooled_output(&tokens, None);
+        assert_eq!(unmasked.row(0), encoded.mean_axis(Axis(0)).unwrap());
+    }
+
+    #[test]
+    fn test_masked_backward_matches_unpadded_sequence() {
+        let vocab: HashMap<String, usize> = (0..6).map(|i| (format!("t{}", i), i)).collect();
+        let config = TransformerConfig { num_layers: 2, ..tiny_config(3) };
+        let transformer: Transformer = Transformer::new(config, vocab);
+        let grad_logits = array![[0.3, -0.1, -0.2]];
+
+        let padded = array![[3.0, 1.0, 4.0, 0.0, 0.0]];
+        let mask = array![[1.0, 1.0, 1.0, 0.0, 0.0]];
+        let unpadded = array![[3.0, 1.0, 4.0]];
+
+        let logits = transformer.forward(&padded, Some(&mask));
+        let expected_logits = transformer.forward(&unpadded, None);
+        assert!(logits.iter().zip(expected_logits.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+
+        let grads = transformer.backward(&padded, Some(&mask), &grad_logits);
+        let expected = transformer.backward(&unpadded, None, &grad_logits);
+        assert!(grads.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_default_attention_projections_with_relative_positions() {
+        let vocab: HashMap<String, usize> = (0..6).map(|i| (format!("t{}", i), i)).collect();
+        let config = TransformerConfig { attention_projections: ATTENTION_PROJECTIONS, relative_positions: Some(2), ..tiny_config(3) };
+        let transformer: Transformer = Transformer::new(config, vocab);
+        let layer = &transformer.encoder_layers[0];
+        assert_eq!(layer.projections.as_ref().map(|projections| projections.num_heads), Some(2));
+        // The heads share relative-position tables of the head width.
+        assert_eq!(layer.relative_positions.as_ref().unwrap().key_embeddings.ncols(), 2);
+
+        let grad_logits = array![[0.3, -0.1, -0.2]];
+        let padded = array![[3.0, 1.0, 4.0, 0.0]];
+        let mask = array![[1.0, 1.0, 1.0, 0.0]];
+        let unpadded = array![[3.0, 1.0, 4.0]];
+        let logits = transformer.forward(&padded, Some(&mask));
+        assert!((logits - transformer.forward(&unpadded, None)).iter().all(|d| d.abs() < 1e-12));
+
+        let grads = transformer.backward(&padded, Some(&mask), &grad_logits);
+        assert_eq!(grads.len(), transformer.num_parameters());
+        let expected = transformer.backward(&unpadded, None, &grad_logits);
+        assert!(grads.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_deterministic_parallel_backward_is_bit_identical() {
+        let vocab: HashMap<String, usize> = (0..6).map(|i| (format!("t{}", i), i)).collect();
+        let config = TransformerConfig { num_layers: 2, ..tiny_config(3) };
+        let mut transformer = Transformer::new(config, vocab);
+
+        let tokens = Array2::from_shape_fn((11, 5), |(i, j)| ((i * 7 + j * 3) % 6) as f64);
+        let grad_logits = Array2::from_shape_fn((11, 3), |(i, j)| (i as f64 - j as f64) * 0.01);
+
+        transformer.parallelism = Parallelism::new(1, Reduction::Deterministic);
+        let reference = transformer.backward(&tokens, None, &grad_logits);
+        let reference_logits = transformer.forward(&tokens, None);
+        for threads in [2, 4] {
+            transformer.parallelism = Parallelism::new(threads, Reduction::Deterministic);
+            let grads = transformer.backward(&tokens, None, &grad_logits);
+            assert!(grads.iter().zip(&reference).all(|(a, b)| a.to_bits() == b.to_bits()));
+            assert_eq!(transformer.forward(&tokens, None), reference_logits);
+        }
+
+        transformer.parallelism = Parallelism::default();
+        let sequential = transformer.backward(&tokens, None, &grad_logits);
+        assert!(sequential.iter().zip(&reference).all(|(a, b)| (a - b).abs() < 1e-12));
+
+        transformer.set_summation(Summation::Compensated);
+        assert!(transformer.encoder_layers.iter().all(|layer| layer.summation == Summation::Compensated));
+        let compensated = transformer.backward(&tokens, None, &grad_logits);
+        assert!(compensated.iter().zip(&sequential).all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_bert_embedding_block_with_segments_and_dropout() {
+        let vocab: HashMap<String, usize> = (0..6).map(|i| (format!("t{}", i), i)).collect();
+        let config = TransformerConfig { bert_embeddings: true, ..tiny_config(3) };
+        let mut transformer = Transformer::new(config, vocab);
+        let tokens = array![[3.0, 1.0, 4.0, 2.0], [5.0, 2.0, 0.0, 0.0]];
+        let segments = array![[0.0, 0.0, 1.0, 1.0], [0.0, 0.0, 0.0, 0.0]];
+        let grad_logits = array![[0.3, -0.1, -0.2], [-0.5, 0.2, 0.3]];
+
+        let logits = transformer.forward_with_segments(&tokens, None, Some(&segments));
+        assert_ne!(logits.row(0), transformer.forward(&tokens, None).row(0));
+        assert_eq!(logits.row(1), transformer.forward(&tokens, None).row(1));
+
+        // The backward pass recomputes the forward pass with the same dropout masks, so its
+        // gradients match finite differences of the dropped-out forward pass.
+        transformer.embedding_dropout = Some(EmbeddingDropout { rate: 0.3, seed: 11 });
+        let objective = |model: &Transformer| (model.forward_with_segments(&tokens, None, Some(&segments)) * &grad_logits).sum();
+        let gradients = transformer.backward_with_segments(&tokens, None, Some(&segments), &grad_logits);
+        let epsilon = 1e-6;
+        let last = transformer.num_parameters() - 1;
+        // An encoder weight, the token embedding of `t2` and a segment embedding.
+        for index in [0, 5, 17, last - 8 - 4 * 3, last] {
+            *transformer.parameters_mut()[index] += epsilon;
+            let plus = objective(&transformer);
+            *transformer.parameters_mut()[index] -= 2.0 * epsilon;
+            let minus = objective(&transformer);
+            *transformer.parameters_mut()[index] += epsilon;
+            let numeric = (plus - minus) / (2.0 * epsilon);
+            assert!((numeric - gradients[index]).abs() < 1e-6, "{}: {} vs {}", index, numeric, gradients[index]);
+        }
+    }
+
+    #[test]
+    fn test_single_precision_model_and_checkpoints() {
+        let vocab = tiny_vocab(&["hello", "world"]);
+        let config = TransformerConfig { bert_embeddings: true, relative_positions: Some(2), attention_projections: true, ..tiny_config(2) };
+        let model: Transformer = Transformer::new(config, vocab);
+        let (f64_path, f32_path) = (temp_path("precision_model_f64.json"), temp_path("precision_model_f32.json"));
+        model.save(&f64_path).unwrap();
+
+        // A checkpoint loads at the precision of the model type, and is saved at it.
+        let single = Transformer::<f32>::load(&f64_path).unwrap();
+        single.save(&f32_path).unwrap();
+        let reloaded = Transformer::<f32>::load(&f32_path);
+        let sizes = (std::fs::metadata(&f64_path).unwrap().len(), std::fs::metadata(&f32_path).unwrap().len());
+        std::fs::remove_file(&f64_path).unwrap();
+        std::fs::remove_file(&f32_path).unwrap();
+
+        let tokens = array![[2.0, 3.0, 2.0, 0.0]];
+        let mask = array![[1.0, 1.0, 1.0, 0.0]];
+        let logits = single.forward(&tokens, Some(&mask));
+        assert_eq!(reloaded.unwrap().forward(&tokens, Some(&mask)), logits);
+        assert!(sizes.1 < sizes.0, "f32 checkpoint {} bytes, f64 {} bytes", sizes.1, sizes.0);
+        let expected = model.forward(&tokens, Some(&mask));
+        assert!(logits.iter().zip(expected.iter()).all(|(&a, b)| (a as f64 - b).abs() < 1e-5));
+
+        let grads = single.backward(&tokens, Some(&mask), &array![[0.5f32, -0.5]]);
+        assert_eq!(grads.len(), single.num_parameters());
+        assert!(grads.iter().all(|g| g.is_finite()));
+    }
+
+    #[test]
+    fn test_checkpoint_records_training_dataset() {
+        let path = temp_path("transformer_train_dataset.json");
+        let mut transformer: Transformer = Transformer::new(tiny_config(2), tiny_vocab(&["hello"]));
+        let dataset = DatasetVersion { path: "data/train.json".to_string(), hash: "00000000000000ff".to_string(), bytes: 42 };
+        transformer.train_dataset = Some(dataset.clone());
+        transformer.save(&path).unwrap();
+        let loaded: Transformer = Transformer::load(&path).unwrap();
+        assert_eq!(loaded.train_dataset, Some(dataset));
+
+        // Checkpoints saved before the field existed load without it.
+        let mut value: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        value.as_object_mut().unwrap().remove("train_dataset");
+        std::fs::write(&path, value.to_string()).unwrap();
+        let legacy: Transformer = Transformer::load(&path).unwrap();
+        assert_eq!(legacy.train_dataset, None);
+        std::fs::remove_file(&path).unwrap();
+    }
+}